@@ -0,0 +1,222 @@
+//! The hot paths of the emulator, measured without a rom: the CPU running a synthetic loop, the
+//! GPU rendering the scanlines and the MMU dispatching the accesses.
+//!
+//! Run them with `cargo bench --bench hot_paths`, an argument only runs the benchmarks whose
+//! names contain it, e.g. `cargo bench --bench hot_paths -- gpu`. Every benchmark is repeated for
+//! about a second after a warm up, the best and the median run are printed.
+use std::env;
+use std::hint::black_box;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use NGC224::gameboy::cartridge::load_empty_cartridge;
+use NGC224::gameboy::cpu::IntReg;
+use NGC224::gameboy::gpu::GPU;
+use NGC224::gameboy::joypad::Joypad;
+use NGC224::gameboy::mmu::Mmunit;
+use NGC224::gameboy::sound::apu::{Apu, DEFAULT_SAMPLE_RATE};
+use NGC224::gameboy::timer::Timer;
+use NGC224::gameboy::{IOHandler, Register, Term, CPU};
+
+const WARM_UP: Duration = Duration::from_millis(200);
+const MEASURE: Duration = Duration::from_secs(1);
+
+// The cycles of a frame, 154 lines of 456 dots.
+const FRAME_CYCLES: u32 = 70224;
+
+fn new_mmu(term: Term) -> Mmunit {
+    let intf = Arc::new(IntReg::new());
+    let gpu = GPU::with_term(intf.clone(), term);
+    Mmunit::new(
+        load_empty_cartridge(),
+        Apu::new(DEFAULT_SAMPLE_RATE),
+        gpu,
+        Joypad::new(intf.clone()),
+        Timer::new(intf.clone()),
+        intf,
+    )
+}
+
+/// Run the routine again and again, it returns how many units (instructions, lines or accesses)
+/// it ran. Prints the units per second of the best and the median run.
+fn bench(name: &str, unit: &str, mut routine: impl FnMut() -> u64) {
+    let filter = env::args().skip(1).find(|a| !a.starts_with('-'));
+    if filter.map_or(false, |f| !name.contains(&f)) {
+        return;
+    }
+    let start = Instant::now();
+    while start.elapsed() < WARM_UP {
+        black_box(routine());
+    }
+    let mut rates = vec![];
+    let start = Instant::now();
+    while start.elapsed() < MEASURE {
+        let run = Instant::now();
+        let units = routine();
+        rates.push(units as f64 / run.elapsed().as_secs_f64());
+    }
+    rates.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    println!(
+        "{:<24} {:>8.2} M {}/s (best) {:>8.2} M {}/s (median of {} runs)",
+        name,
+        rates[0] / 1e6,
+        unit,
+        rates[rates.len() / 2] / 1e6,
+        unit,
+        rates.len()
+    );
+}
+
+/// A loop at C000 mixing the ALU, the loads from the work ram, the stack and the branches, like
+/// the inner loop of a game.
+const PROGRAM: [u8; 22] = [
+    0x21, 0x00, 0xd0, // LD HL, 0xd000
+    0x06, 0x40, // LD B, 0x40
+    0x2a, // loop: LD A, (HL+)
+    0x80, // ADD A, B
+    0xcb, 0x37, // SWAP A
+    0xa9, // XOR C
+    0x4f, // LD C, A
+    0xc5, // PUSH BC
+    0xd1, // POP DE
+    0x13, // INC DE
+    0x05, // DEC B
+    0x20, 0xf4, // JR NZ, loop
+    0xc3, 0x00, 0xc0, // JP 0xc000
+    0x00, 0x00,
+];
+
+/// The CPU on the mmu, the peripherals are stepped after every instruction like the emulator.
+fn bench_cpu() {
+    let mut mmu = new_mmu(Term::GB);
+    for (i, b) in PROGRAM.iter().enumerate() {
+        mmu.write_byte(0xc000 + i as u16, *b);
+    }
+    let mut cpu = CPU::new(mmu);
+    cpu.set_reg(Register::new().with_PC(0xc000).with_SP(0xdffe));
+    cpu.disable_ime();
+    bench("cpu/instructions", "instr", || {
+        let count = cpu.get_instruction_count();
+        let mut cycles = 0;
+        while cycles < FRAME_CYCLES {
+            let c = cpu.next();
+            cycles += cpu.get_bus_mut().as_mmu_mut().unwrap().next(c);
+        }
+        cpu.get_instruction_count() - count
+    });
+
+    // The CPU alone on a flat memory, the accesses go through the trait object.
+    let mem = Arc::new(Mutex::new(FlatMemory {
+        data: vec![0x00; 0x10000],
+    }));
+    mem.lock().unwrap().data[0xc000..0xc000 + PROGRAM.len()].copy_from_slice(&PROGRAM);
+    let mut cpu = CPU::new(mem);
+    cpu.set_reg(Register::new().with_PC(0xc000).with_SP(0xdffe));
+    cpu.disable_ime();
+    bench("cpu/instructions-flat", "instr", || {
+        let count = cpu.get_instruction_count();
+        let mut cycles = 0;
+        while cycles < FRAME_CYCLES {
+            cycles += cpu.next();
+        }
+        cpu.get_instruction_count() - count
+    });
+}
+
+struct FlatMemory {
+    data: Vec<u8>,
+}
+
+impl IOHandler for FlatMemory {
+    fn read_byte(&self, a: u16) -> u8 {
+        self.data[a as usize]
+    }
+
+    fn write_byte(&mut self, a: u16, v: u8) {
+        self.data[a as usize] = v;
+    }
+}
+
+/// Fill the tile data with a pattern and the bg map with every tile, then turn on the LCD with
+/// the bg, the window and 10 sprites per line.
+fn setup_gpu(gpu: &mut GPU) {
+    for i in 0..0x1800 {
+        gpu.write_byte(0x8000 + i, (i as u8).wrapping_mul(0x3b) ^ (i >> 4) as u8);
+    }
+    for i in 0..0x800 {
+        gpu.write_byte(0x9800 + i, i as u8);
+    }
+    for i in 0..40 {
+        let y = 16 + (i / 10) * 36;
+        let x = 8 + (i % 10) * 16;
+        gpu.write_byte(0xfe00 + i * 4, y as u8);
+        gpu.write_byte(0xfe00 + i * 4 + 1, x as u8);
+        gpu.write_byte(0xfe00 + i * 4 + 2, i as u8);
+        gpu.write_byte(0xfe00 + i * 4 + 3, if i % 2 == 0 { 0x00 } else { 0x30 });
+    }
+    gpu.write_byte(0xff47, 0xe4);
+    gpu.write_byte(0xff48, 0xd2);
+    gpu.write_byte(0xff49, 0x1e);
+    gpu.write_byte(0xff4a, 0x48);
+    gpu.write_byte(0xff4b, 0x57);
+    // LCD, window at 9C00, the signed tile data, sprites and bg.
+    gpu.write_byte(0xff40, 0xe3);
+}
+
+/// A frame is 144 rendered lines, the rest is the V-Blank.
+fn bench_gpu() {
+    let terms = [
+        ("gpu/scanlines-dmg", Term::GB),
+        ("gpu/scanlines-cgb", Term::GBC),
+    ];
+    for (name, term) in terms.iter() {
+        let intf = Arc::new(IntReg::new());
+        let mut gpu = GPU::with_term(intf, *term);
+        setup_gpu(&mut gpu);
+        bench(name, "line", || {
+            for _ in 0..FRAME_CYCLES / 4 {
+                gpu.next(4);
+            }
+            black_box(gpu.get_data());
+            144
+        });
+    }
+}
+
+/// The reads and the writes spread over the memory map, every access is dispatched by the
+/// address.
+fn bench_mmu() {
+    let mut mmu = new_mmu(Term::GBC);
+    let addrs: Vec<u16> = (0..0x1000_u32)
+        .map(|i| match i % 8 {
+            0 => (i * 7) as u16 & 0x7fff,
+            1 => 0x8000 | (i * 13) as u16 & 0x1fff,
+            2 | 3 => 0xc000 | (i * 11) as u16 & 0x1fff,
+            4 => 0xfe00 | (i * 3) as u16 % 0xa0,
+            5 => 0xff00 | (i * 5) as u16 & 0x4f,
+            _ => 0xff80 | (i * 17) as u16 % 0x7f,
+        })
+        .collect();
+    bench("mmu/reads", "read", || {
+        let mut v = 0_u8;
+        for a in addrs.iter() {
+            v ^= mmu.read_byte(*a);
+        }
+        black_box(v);
+        addrs.len() as u64
+    });
+    // The writes stay out of the rom, a mapper write is a bank switch rather than the dispatch.
+    let writes: Vec<u16> = addrs.iter().copied().filter(|a| *a >= 0x8000).collect();
+    bench("mmu/writes", "write", || {
+        for (i, a) in writes.iter().enumerate() {
+            mmu.write_byte(*a, i as u8);
+        }
+        writes.len() as u64
+    });
+}
+
+fn main() {
+    bench_cpu();
+    bench_gpu();
+    bench_mmu();
+}
@@ -0,0 +1,35 @@
+//! Load the random bytes as the header of a rom, the cartridge factory must return an error
+//! rather than panic. A loaded cartridge is then written and read through the whole bus, so the
+//! mapper of any header is banked around too.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use NGC224::gameboy::cartridge::{load_cartridge_from_bytes, HeaderCheck};
+use NGC224::gameboy::IOHandler;
+
+// The bytes are the header 0100-014F, the rest of the 32KByte rom is zero.
+const HEADER: usize = 0x0100;
+const ROM_SIZE: usize = 0x8000;
+
+fuzz_target!(|data: &[u8]| {
+    let mut rom = vec![0x00; ROM_SIZE];
+    let header = &data[..data.len().min(0x50)];
+    rom[HEADER..HEADER + header.len()].copy_from_slice(header);
+    // The bytes after the header are what the game writes to the mapper.
+    let writes = data.get(0x50..).unwrap_or(&[]);
+    for check in [HeaderCheck::Warn, HeaderCheck::Strict].iter() {
+        let mut cart = match load_cartridge_from_bytes(rom.clone(), *check, None) {
+            Ok(cart) => cart,
+            Err(_) => continue,
+        };
+        for w in writes.chunks_exact(3) {
+            let addr = u16::from_le_bytes([w[0], w[1]]);
+            if addr < 0x8000 || (0xa000..0xc000).contains(&addr) {
+                cart.write_byte(addr, w[2]);
+            }
+            cart.read_byte(addr & 0x7fff);
+            cart.read_byte(0xa000 | addr & 0x1fff);
+        }
+        cart.get_info();
+    }
+});
@@ -0,0 +1,158 @@
+//! Run the random bytes as a program and check the result and the flags of every 8-bit ALU
+//! instruction against a model written apart from the CPU. The first 8 bytes are AF, BC, DE and
+//! HL, the rest is the program at 0x0100, the other memory is zero.
+#![no_main]
+
+use std::sync::{Arc, Mutex};
+
+use libfuzzer_sys::fuzz_target;
+use NGC224::gameboy::IOHandler;
+use NGC224::gameboy::Register;
+use NGC224::gameboy::CPU;
+
+const ZERO: u8 = 0x80;
+const SUBTRACT: u8 = 0x40;
+const HALF_CARRY: u8 = 0x20;
+const CARRY: u8 = 0x10;
+
+// Stop before an endless program or a HALT without an interrupt keeps the fuzzer waiting.
+const MAX_STEPS: usize = 0x1000;
+
+struct FlatMemory {
+    data: Vec<u8>,
+}
+
+impl IOHandler for FlatMemory {
+    fn read_byte(&self, a: u16) -> u8 {
+        self.data[a as usize]
+    }
+
+    fn write_byte(&mut self, a: u16, v: u8) {
+        self.data[a as usize] = v;
+    }
+}
+
+fn flag(set: bool, f: u8) -> u8 {
+    if set { f } else { 0 }
+}
+
+/// Returns A and F after the ALU operation (ADD ADC SUB SBC AND XOR OR CP) of the opcode.
+fn alu(opcode: u8, a: u8, v: u8, f: u8) -> (u8, u8) {
+    let op = (opcode >> 3) & 0x07;
+    let c = match op {
+        1 | 3 => (f & CARRY) >> 4,
+        _ => 0,
+    };
+    let (r, f) = match op {
+        0 | 1 => {
+            let r = u16::from(a) + u16::from(v) + u16::from(c);
+            let h = (a & 0x0f) + (v & 0x0f) + c > 0x0f;
+            (r as u8, flag(h, HALF_CARRY) | flag(r > 0xff, CARRY))
+        }
+        2 | 3 | 7 => {
+            let r = i16::from(a) - i16::from(v) - i16::from(c);
+            let h = (a & 0x0f) < (v & 0x0f) + c;
+            (r as u8, SUBTRACT | flag(h, HALF_CARRY) | flag(r < 0, CARRY))
+        }
+        4 => (a & v, HALF_CARRY),
+        5 => (a ^ v, 0),
+        _ => (a | v, 0),
+    };
+    let f = f | flag(r == 0, ZERO);
+    if op == 7 { (a, f) } else { (r, f) }
+}
+
+/// Returns the register or (HL) of the operand index, B C D E H L (HL) A.
+fn operand(reg: &Register, mem: &FlatMemory, i: u8) -> u8 {
+    match i {
+        0 => reg.get_B(),
+        1 => reg.get_C(),
+        2 => reg.get_D(),
+        3 => reg.get_E(),
+        4 => reg.get_H(),
+        5 => reg.get_L(),
+        6 => mem.read_byte(reg.get_HL()),
+        _ => reg.get_A(),
+    }
+}
+
+/// Returns the expected A, the operand and F if the opcode is modelled.
+fn expect(opcode: u8, reg: &Register, mem: &FlatMemory) -> Option<(u8, u8, u8)> {
+    let (a, f) = (reg.get_A(), reg.get_AF() as u8);
+    match opcode {
+        0x80..=0xbf => {
+            let v = operand(reg, mem, opcode & 0x07);
+            let (a, f) = alu(opcode, a, v, f);
+            Some((a, v, f))
+        }
+        0xc6 | 0xce | 0xd6 | 0xde | 0xe6 | 0xee | 0xf6 | 0xfe => {
+            let v = mem.read_byte(reg.get_PC().wrapping_add(1));
+            let (a, f) = alu(opcode, a, v, f);
+            Some((a, v, f))
+        }
+        // INC r and DEC r, the carry is kept. The result is checked in the operand.
+        _ if opcode & 0xc6 == 0x04 => {
+            let v = operand(reg, mem, (opcode >> 3) & 0x07);
+            let (r, n, h) = if opcode & 0x01 == 0 {
+                let r = v.wrapping_add(1);
+                (r, 0, r & 0x0f == 0)
+            } else {
+                let r = v.wrapping_sub(1);
+                (r, SUBTRACT, r & 0x0f == 0x0f)
+            };
+            let f = flag(r == 0, ZERO) | n | flag(h, HALF_CARRY) | f & CARRY;
+            let a = if (opcode >> 3) & 0x07 == 7 { r } else { a };
+            Some((a, r, f))
+        }
+        _ => None,
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 8 {
+        return;
+    }
+    let mut mem = FlatMemory {
+        data: vec![0x00; 0x10000],
+    };
+    let program = &data[8..data.len().min(8 + 0xff00)];
+    mem.data[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    let mem = Arc::new(Mutex::new(mem));
+    let word = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+    let mut cpu = CPU::new(mem.clone());
+    cpu.set_reg(
+        Register::new()
+            .with_AF(word(0))
+            .with_BC(word(2))
+            .with_DE(word(4))
+            .with_HL(word(6))
+            .with_PC(0x0100)
+            .with_SP(0xfffe),
+    );
+    cpu.disable_ime();
+
+    for _ in 0..MAX_STEPS {
+        let reg = cpu.get_reg_snapshot();
+        let opcode = mem.lock().unwrap().read_byte(reg.get_PC());
+        // The unknown opcodes lock up the CPU.
+        if cpu.get_lock().is_some() || cpu.is_halt() || cpu.is_stop() {
+            return;
+        }
+        let expected = expect(opcode, &reg, &mem.lock().unwrap());
+        let count = cpu.get_instruction_count();
+        let step = cpu.step();
+        // The program may enable an interrupt, its dispatch executes no instruction.
+        let (a, v, f) = match expected {
+            Some(e) if cpu.get_instruction_count() != count => e,
+            _ => continue,
+        };
+        let after = cpu.get_reg_snapshot();
+        let name = &step.mnemonic;
+        assert_eq!(after.get_AF() as u8, f, "{} {:?}", name, reg);
+        assert_eq!(after.get_A(), a, "{} {:?}", name, reg);
+        if opcode & 0xc6 == 0x04 {
+            let r = operand(&after, &mem.lock().unwrap(), (opcode >> 3) & 0x07);
+            assert_eq!(r, v, "{} {:?}", name, reg);
+        }
+    }
+});
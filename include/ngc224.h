@@ -0,0 +1,39 @@
+/* The C interface of the NGC-224 emulator core, see src/ngc224_ffi.rs. */
+#ifndef NGC224_H
+#define NGC224_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define NGC224_SCREEN_W 160
+#define NGC224_SCREEN_H 144
+
+enum ngc224_button {
+    NGC224_RIGHT = 0,
+    NGC224_LEFT = 1,
+    NGC224_UP = 2,
+    NGC224_DOWN = 3,
+    NGC224_A = 4,
+    NGC224_B = 5,
+    NGC224_SELECT = 6,
+    NGC224_START = 7,
+};
+
+typedef struct Ngc224 Ngc224;
+
+Ngc224 *ngc224_create(void);
+void ngc224_destroy(Ngc224 *ngc);
+/* Returns 0, or -1 if the rom can't be loaded. */
+int ngc224_load_rom(Ngc224 *ngc, const uint8_t *data, size_t len);
+void ngc224_step_frame(Ngc224 *ngc);
+/* NGC224_SCREEN_W * NGC224_SCREEN_H RGB pixels, valid until the next call. */
+const uint8_t *ngc224_get_framebuffer(Ngc224 *ngc);
+/* The same pixels in RGBA, valid until the next call. */
+const uint8_t *ngc224_get_frame_rgba(Ngc224 *ngc);
+/* The buffer of len bytes for passing the rom, freed by ngc224_free with the same len. */
+uint8_t *ngc224_alloc(size_t len);
+void ngc224_free(uint8_t *p, size_t len);
+void ngc224_set_button(Ngc224 *ngc, int button, bool pressed);
+
+#endif
@@ -1,10 +1,18 @@
 #![allow(non_snake_case)]
 use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use clap::{App, Arg};
-use fern::colors::{Color, ColoredLevelConfig};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use log::info;
 use NGC224::gameboy::Config;
+use NGC224::gameboy::cartridge::{CartridgeMeta, Checksums};
+use NGC224::gameboy::config::SETTINGS;
+#[cfg(target_os = "linux")]
+use NGC224::gameboy::gamepad::joydev::JoystickBackend;
+use NGC224::gameboy::logging::setup_logger;
+use NGC224::gameboy::movie::Movie;
+use NGC224::gameboy::romcheck::{self, Outcome};
+use NGC224::gameboy::state::Snapshot;
 use NGC224::gameboy::Emulator;
 
 const NAME: &str = env!("CARGO_PKG_NAME");
@@ -12,32 +20,8 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHOR: &str = env!("CARGO_PKG_AUTHORS");
 const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 
-fn setup_logger() -> Result<(), fern::InitError> {
-    let colors = ColoredLevelConfig::new()
-        .info(Color::BrightGreen)
-        .error(Color::BrightRed)
-        .warn(Color::BrightYellow)
-        .debug(Color::BrightMagenta);
-
-    fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "[{}] {} - {:<36}: {}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                colors.color(record.level()),
-                record.target(),
-                message
-            ))
-        })
-        .level(log::LevelFilter::Info)
-        .chain(std::io::stdout())
-        .chain(fern::log_file("output.log")?)
-        .apply()?;
-    Ok(())
-}
-
-fn parse_cmd() -> Result<Config, Box<dyn std::error::Error>> {
-    let matches = App::new(NAME)
+fn parse_cmd() -> ArgMatches<'static> {
+    App::new(NAME)
         .version(VERSION)
         .author(AUTHOR)
         .about(DESCRIPTION)
@@ -45,25 +29,532 @@ fn parse_cmd() -> Result<Config, Box<dyn std::error::Error>> {
             Arg::with_name("path")
                 .short("p")
                 .long("path")
-                .required(true)
-                .help("the rom path")
+                .help("the rom path, show the start screen if it is not given")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("the config file read instead of ~/.config/ngc224/config.toml")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-config")
+                .long("no-config")
+                .help("don't read ~/.config/ngc224/config.toml"),
+        )
+        .arg(
+            Arg::with_name("rom-dir")
+                .short("d")
+                .long("rom-dir")
+                .help("the directory listed by the start screen")
+                .default_value(".")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("perf-overlay")
+                .long("perf-overlay")
+                .help("plot the frame time and emulation speed in the corner"),
+        )
+        .arg(
+            Arg::with_name("vram-viewer")
+                .long("vram-viewer")
+                .help("show the tiles, the BG maps and the sprites in a second window"),
+        )
+        .arg(
+            Arg::with_name("show-fps")
+                .long("show-fps")
+                .help("draw the frames per second above the messages"),
+        )
+        .arg(
+            Arg::with_name("show-stats")
+                .long("show-stats")
+                .help("draw the frames, the speed and the host cpu usage of every second"),
+        )
+        .arg(
+            Arg::with_name("show-io")
+                .long("show-io")
+                .help("draw the LCD, interrupt and timer registers of every frame"),
+        )
+        .arg(
+            Arg::with_name("log-stats")
+                .long("log-stats")
+                .help("log the frames, the speed and the host cpu usage of every second"),
+        )
+        .arg(
+            Arg::with_name("title")
+                .long("title")
+                .value_name("FORMAT")
+                .help("the window title, {title} {fps} and {speed} are filled in every second")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("osd-corner")
+                .long("osd-corner")
+                .help("where the messages like \"State saved\" are drawn")
+                .possible_values(&["top-left", "top-right", "bottom-left", "bottom-right"])
+                .default_value("top-left")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("osd-timeout")
+                .long("osd-timeout")
+                .value_name("SECS")
+                .help("how long a message stays on the screen")
+                .default_value("2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("opposite-direction")
+                .long("opposite-direction")
+                .help("how to report Left+Right or Up+Down held at the same time")
+                .possible_values(&["block", "last-wins", "allow"])
+                .default_value("last-wins")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("renderer")
+                .long("renderer")
+                .help("draw the whole scanline at once, or the pixels with the hardware timing")
+                .possible_values(&["scanline", "fifo"])
+                .default_value("scanline")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("accuracy")
+                .long("accuracy")
+                .help("follow the timing quirks of the hardware the games polling LY depend on")
+                .possible_values(&["fast", "accurate"])
+                .default_value("fast")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("frontend")
+                .long("frontend")
+                .help("open the window with minifb, or with SDL2 if it's built with the sdl2 feature")
+                .possible_values(&["minifb", "sdl2"])
+                .default_value("minifb")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .help("how many times the window is of the screen, F3 switches it")
+                .possible_values(&["1", "2", "4", "8"])
+                .default_value("2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fullscreen")
+                .long("fullscreen")
+                .help("fill the screen with the largest integer scale, F4 toggles it"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .help("scale and smooth the frame for the window, F9 switches it")
+                .possible_values(&["nearest", "2xsai", "hq2x", "scanline", "lcd-grid"])
+                .default_value("nearest")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("green-tint")
+                .long("green-tint")
+                .help("show the colors with the green shades of the DMG screen, F10 toggles it"),
+        )
+        .arg(
+            Arg::with_name("frame-blend")
+                .long("frame-blend")
+                .value_name("PERSISTENCE")
+                .help("mix PERSISTENCE (0.0 to 0.9) of the previous frame in like the LCD ghosting")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .long("palette")
+                .value_name("NAME")
+                .help("the colors of the DMG games, a built in one or one in the palette file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("palette-file")
+                .long("palette-file")
+                .value_name("FILE")
+                .help("load the DMG palettes and the palette of each game from the file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cheats")
+                .long("cheats")
+                .value_name("FILE")
+                .help("apply the Game Genie and GameShark codes in the file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dump-frames")
+                .long("dump-frames")
+                .value_name("DIR")
+                .help("save the frames into the directory as PNG images")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dump-interval")
+                .long("dump-interval")
+                .value_name("N")
+                .help("only save one of every N frames with --dump-frames")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("video-format")
+                .long("video-format")
+                .help("the format of the video recorded with F11")
+                .possible_values(&["gif", "apng"])
+                .default_value("gif")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("keymap-file")
+                .long("keymap-file")
+                .value_name("FILE")
+                .help("load the keyboard mapping from the file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("keymap")
+                .long("keymap")
+                .value_name("BUTTON=KEYS")
+                .help("bind the keys to the button, e.g. a=J,Z, applied after the keymap file")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("autofire")
+                .long("autofire")
+                .value_name("BUTTONS")
+                .help("the buttons which autofire while held, e.g. a,b")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("autofire-rate")
+                .long("autofire-rate")
+                .value_name("HZ")
+                .help("the presses per second of the turbo keys and the autofire buttons")
+                .default_value("10")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("gamepad-deadzone")
+                .long("gamepad-deadzone")
+                .value_name("VALUE")
+                .help("the left stick within VALUE (0.0 to 1.0) doesn't press the direction keys")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fast-forward")
+                .long("fast-forward")
+                .value_name("FACTOR")
+                .help("run FACTOR times of the normal speed while Tab is held")
+                .default_value("4")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sync")
+                .long("sync")
+                .value_name("SOURCE")
+                .help("pace the emulation by video, audio or uncapped")
+                .possible_values(&["video", "audio", "uncapped"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sync-window")
+                .long("sync-window")
+                .help("let the window wait for the frame time instead of the emulator"),
+        )
+        .arg(
+            Arg::with_name("frame-skip")
+                .long("frame-skip")
+                .help("only draw one of every FACTOR frames while fast forwarding"),
+        )
+        .arg(
+            Arg::with_name("save-delay")
+                .long("save-delay")
+                .value_name("SECS")
+                .help("write the battery ram to disk once the game stops saving for SECS seconds")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("random-ram")
+                .long("random-ram")
+                .value_name("SEED")
+                .help("fill the ram with random bytes at power on, a random seed is used if SEED is not given")
+                .min_values(0)
+                .max_values(1),
+        )
+        .arg(
+            Arg::with_name("record-movie")
+                .long("record-movie")
+                .value_name("FILE")
+                .help("record the keys of every frame into the movie file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("play-movie")
+                .long("play-movie")
+                .value_name("FILE")
+                .help("replay the movie file, the keyboard is ignored")
+                .conflicts_with("record-movie")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("audio-dump")
+                .long("audio-dump")
+                .value_name("FILE")
+                .help("write the sound into the wav file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .value_name("FRAMES")
+                .help("run FRAMES frames as fast as possible without the window")
+                .conflicts_with("bench-frames")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .value_name("FILE")
+                .help("log every instruction and the registers into the file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("memory-profile")
+                .long("memory-profile")
+                .value_name("FILE")
+                .help("count the accesses of every memory page and rom bank into the csv file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("memory-heatmap")
+                .long("memory-heatmap")
+                .value_name("FILE")
+                .help("draw the accesses of every memory page into the png file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .value_name("LEVELS")
+                .help("the log level and the ones of the modules, e.g. warn,cpu=debug")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .value_name("FILE")
+                .help("write the log into the file rather than output.log")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-log-file")
+                .long("no-log-file")
+                .help("only write the log to the stdout")
+                .conflicts_with("log-file"),
+        )
+        .arg(
+            Arg::with_name("cpu-history")
+                .long("cpu-history")
+                .value_name("N")
+                .help("keep the last N instructions for the coredump, 0 turns it off")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stack-check")
+                .long("stack-check")
+                .help("warn when the stack leaves the work ram and the high ram"),
+        )
+        .arg(
+            Arg::with_name("cpu-profile")
+                .long("cpu-profile")
+                .help("log the functions and the instructions taking the most cycles at exit"),
+        )
+        .arg(
+            Arg::with_name("recover")
+                .long("recover")
+                .help("pause on a crash of the emulator and save a bug report instead of exiting"),
+        )
+        .arg(
+            Arg::with_name("bootrom")
+                .long("bootrom")
+                .value_name("FILE")
+                .help("run the DMG or CGB boot rom before the game")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("camera-image")
+                .long("camera-image")
+                .value_name("PNG")
+                .help("the image the Pocket Camera sees")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("header-check")
+                .long("header-check")
+                .help("what to do with a rom whose logo or header checksum is wrong")
+                .possible_values(&["warn", "strict"])
+                .default_value("warn")
                 .takes_value(true),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("mbc1")
+                .long("mbc1")
+                .help("how the MBC1 is wired, the multicarts are detected without it")
+                .possible_values(&["normal", "multicart"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .help("the hardware to run on, auto follows the cartridge header")
+                .possible_values(&["auto", "dmg", "cgb"])
+                .default_value("auto")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("data-dir")
+                .long("data-dir")
+                .value_name("DIR")
+                .help("keep the saves, the states, the screenshots and the settings of each game in the directory")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("info")
+                .long("info")
+                .help("print the cartridge header of the rom and exit")
+                .requires("path"),
+        )
+        .arg(
+            Arg::with_name("check-dir")
+                .long("check-dir")
+                .value_name("DIR")
+                .help("check the header and the mapper of every rom in the directory and exit")
+                .conflicts_with("path")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("check-frames")
+                .long("check-frames")
+                .value_name("N")
+                .help("run each rom of --check-dir for N frames without the window")
+                .requires("check-dir")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("debugger")
+                .long("debugger")
+                .help("break into the debugger before the first instruction"),
+        )
+        .arg(
+            Arg::with_name("bench-frames")
+                .long("bench-frames")
+                .value_name("N")
+                .help("run N frames as fast as possible without the window, then print the speed")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("state-info")
+                .about("print the version and contents of a save state file")
+                .arg(Arg::with_name("file").required(true)),
+        )
+        .get_matches()
+}
 
+/// The config file, then the environment, then the flags given on the command line. The
+/// defaults of the flags are not applied, they would override the file.
+fn parse_config(matches: &ArgMatches) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut builder = Config::builder();
     if let Some(path) = matches.value_of("path") {
-        return Ok(Config::new(path.to_string()));
+        builder = builder.rom(path);
+    }
+    builder = match matches.value_of("config") {
+        Some(path) => builder.file(path)?,
+        None if matches.is_present("no-config") => builder,
+        None => builder.default_file()?,
+    };
+    builder = builder.env()?;
+    for key in SETTINGS.iter() {
+        if matches.occurrences_of(key) == 0 {
+            continue;
+        }
+        let values: Vec<&str> = matches.values_of(key).into_iter().flatten().collect();
+        if values.is_empty() {
+            let value = match *key {
+                "random-ram" => {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                    (now.as_nanos() as u64).to_string()
+                }
+                _ => "true".to_string(),
+            };
+            builder = builder.set(key, &value)?;
+        }
+        for value in values {
+            builder = builder.set(key, value)?;
+        }
     }
-    Err("command line parse error".into())
+    Ok(builder.build()?)
+}
+
+fn state_info(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // Show the file as it is, the version before migration is what the user wants to know.
+    let snapshot = Snapshot::from_bytes(&std::fs::read(file)?)?;
+    println!("{}", snapshot.summary());
+    Ok(())
+}
+
+/// Only the header is parsed, the emulator is not started.
+fn rom_info(file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let rom = std::fs::read(file)?;
+    println!("{}", CartridgeMeta::new(&rom)?.summary());
+    println!("{}", Checksums::new(&rom)?.summary());
+    Ok(())
+}
+
+/// The exit code is 1 if any rom is rejected or crashes.
+fn check_dir(dir: &str, matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let frames = matches.value_of("check-frames").unwrap_or("0").parse()?;
+    let config = parse_config(matches)?;
+    let checks = romcheck::check_dir(dir, frames, &config)?;
+    println!("{}", romcheck::format_table(dir, &checks));
+    if checks.iter().any(|c| c.outcome != Outcome::Ok) {
+        process::exit(1);
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    setup_logger()?;
+    let matches = parse_cmd();
+    if let Some(m) = matches.subcommand_matches("state-info") {
+        return state_info(m.value_of("file").unwrap());
+    }
+    if matches.is_present("info") {
+        return rom_info(matches.value_of("path").unwrap());
+    }
+    if let Some(dir) = matches.value_of("check-dir") {
+        return check_dir(dir, &matches);
+    }
+
+    let config = parse_config(&matches)?;
+    setup_logger(&config)?;
     // env_logger::init();
 
     info!("GameBoy Start!!!");
     info!("PID is {}", process::id());
-    let config = parse_cmd()?;
+    if let Some(seed) = config.get_ram_seed() {
+        info!("The ram is randomized with the seed {}", seed);
+    }
     // let config = Config::new("./09-op r,r.gb".to_string());
 
     // lazy_static!{
@@ -73,9 +564,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // }
     // let emulator = Box::leak(Box::new(Emulator::new(config)));
 
-    let mut emulator = Emulator::new(config);
+    let mut emulator = match Emulator::new(config) {
+        Ok(emulator) => emulator,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    #[cfg(target_os = "linux")]
+    emulator.set_gamepad_backend(Box::new(JoystickBackend::new()));
+    if let Some(path) = matches.value_of("record-movie") {
+        emulator.record_movie(path);
+    }
+    if let Some(path) = matches.value_of("play-movie") {
+        emulator.play_movie(Movie::load(path)?);
+    }
+
+    if matches.is_present("debugger") {
+        emulator.attach_debugger();
+    }
+
+    if let Some(frames) = matches.value_of("bench-frames") {
+        let report = emulator.bench(frames.parse()?);
+        println!("{}", report);
+        return Ok(());
+    }
+
+    if let Some(frames) = matches.value_of("headless") {
+        emulator.set_speed_simulation(false);
+        emulator.run_headless(frames.parse()?);
+        return Ok(());
+    }
 
-    emulator.run();
+    emulator.run()?;
 
     Ok(())
 }
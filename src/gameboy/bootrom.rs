@@ -0,0 +1,111 @@
+// The boot rom scrolls the Nintendo logo down, checks the cartridge header, then jumps to 0x0100.
+// It's mapped over the cartridge from the power on until FF50 is written.
+//
+// The DMG boot rom is 256 bytes at 0000-00FF. The CGB one is 2304 bytes at 0000-00FF and
+// 0200-08FF, the cartridge header at 0100-01FF stays visible between them.
+//
+// FF50 - BANK - Boot ROM disable (W)
+//  Writing a non zero value unmaps the boot rom, it can't be mapped again until the power off.
+//
+// See: https://gbdev.io/pandocs/Power_Up_Sequence.html
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::state::{StateError, StateReader, StateWriter, Stateful};
+
+const DMG_SIZE: usize = 0x100;
+const CGB_SIZE: usize = 0x900;
+
+pub struct BootRom {
+    rom: Vec<u8>,
+    mapped: bool,
+}
+
+impl BootRom {
+    /// Returns the error if the size is neither of the DMG nor of the CGB boot rom.
+    pub fn new(rom: Vec<u8>) -> Result<Self, String> {
+        if rom.len() != DMG_SIZE && rom.len() != CGB_SIZE {
+            return Err(format!(
+                "the boot rom is {} bytes, expected {} (DMG) or {} (CGB)",
+                rom.len(),
+                DMG_SIZE,
+                CGB_SIZE
+            ));
+        }
+        Ok(Self { rom, mapped: true })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let rom = fs::read(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        Self::new(rom).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.rom.len() == CGB_SIZE
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    pub fn unmap(&mut self) {
+        self.mapped = false;
+    }
+
+    /// Map it again like the power on.
+    pub fn remap(&mut self) {
+        self.mapped = true;
+    }
+
+    /// Returns the byte of the boot rom, `None` if the cartridge is seen at the address.
+    pub fn get(&self, a: u16) -> Option<u8> {
+        if !self.mapped {
+            return None;
+        }
+        match a as usize {
+            a @ 0x0000..=0x00ff => Some(self.rom[a]),
+            a @ 0x0200..=0x08ff if self.is_cgb() => Some(self.rom[a]),
+            _ => None,
+        }
+    }
+}
+
+/// The rom is loaded from the file again, only the mapping is saved.
+impl Stateful for BootRom {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_bool(self.mapped);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.mapped = r.get_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapping() {
+        assert!(BootRom::new(vec![0x00; 0x200]).is_err());
+
+        let mut dmg = BootRom::new(vec![0x31; DMG_SIZE]).unwrap();
+        assert_eq!(dmg.get(0x0000), Some(0x31));
+        assert_eq!(dmg.get(0x00ff), Some(0x31));
+        assert_eq!(dmg.get(0x0100), None);
+        assert_eq!(dmg.get(0x0200), None);
+        dmg.unmap();
+        assert_eq!(dmg.get(0x0000), None);
+
+        let cgb = BootRom::new(vec![0x31; CGB_SIZE]).unwrap();
+        assert!(cgb.is_cgb());
+        assert_eq!(cgb.get(0x0150), None);
+        assert_eq!(cgb.get(0x0200), Some(0x31));
+        assert_eq!(cgb.get(0x08ff), Some(0x31));
+        assert_eq!(cgb.get(0x0900), None);
+    }
+}
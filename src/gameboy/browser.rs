@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use super::joypad::JoypadKey;
+use super::osd::{draw_text, fill_rect, CHAR_W, LINE_H};
+use super::frontend::Frame;
+use super::{SCREEN_H, SCREEN_W};
+
+const ROM_EXTENSIONS: [&str; 4] = ["gb", "gbc", "gz", "zip"];
+const BACKGROUND: [u8; 3] = [0xff, 0xff, 0xff];
+const FOREGROUND: [u8; 3] = [0x00, 0x00, 0x00];
+
+// The first line is the title, the last line is the help text.
+const VISIBLE_LINES: usize = SCREEN_H / LINE_H - 2;
+const VISIBLE_CHARS: usize = SCREEN_W / CHAR_W - 1;
+
+/// Returns true if the file is named like a rom, or a gzip file or a zip archive of it.
+pub fn is_rom_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| ROM_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// The start screen when emulator is launched without a rom, it lists the roms in a directory
+/// and returns the selected one. It's driven by the joypad keys:
+/// Up/Down move the cursor, Left/Right turn the page, A or Start launch the game.
+pub struct RomBrowser {
+    dir: PathBuf,
+    roms: Vec<PathBuf>,
+    selected: usize,
+}
+
+impl RomBrowser {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let roms = Self::scan(&dir);
+        Self {
+            dir,
+            roms,
+            selected: 0,
+        }
+    }
+
+    /// Find all roms in the directory, sorted by the file name.
+    fn scan(dir: &Path) -> Vec<PathBuf> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Can't read the rom directory {:?}: {}", dir, e);
+                return vec![];
+            }
+        };
+
+        let mut roms: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && is_rom_file(p))
+            .collect();
+        roms.sort();
+        roms
+    }
+
+    pub fn get_roms(&self) -> &[PathBuf] {
+        &self.roms
+    }
+
+    pub fn get_selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Handle a pressed key, returns the rom path if the user launches a game.
+    pub fn press(&mut self, key: JoypadKey) -> Option<PathBuf> {
+        if self.roms.is_empty() {
+            return None;
+        }
+        let last = self.roms.len() - 1;
+        match key {
+            JoypadKey::Up => self.selected = self.selected.saturating_sub(1),
+            JoypadKey::Down => self.selected = (self.selected + 1).min(last),
+            JoypadKey::Left => self.selected = self.selected.saturating_sub(VISIBLE_LINES),
+            JoypadKey::Right => self.selected = (self.selected + VISIBLE_LINES).min(last),
+            JoypadKey::A | JoypadKey::Start => return Some(self.roms[self.selected].clone()),
+            JoypadKey::B | JoypadKey::Select => {}
+        }
+        None
+    }
+
+    /// Render the rom list into a frame.
+    pub fn render(&self) -> Frame {
+        let mut frame = Frame::new(BACKGROUND);
+        draw_text(&mut frame, 1, 0, "SELECT A ROM", FOREGROUND);
+
+        if self.roms.is_empty() {
+            draw_text(&mut frame, 1, LINE_H, "No rom found in", FOREGROUND);
+            let dir = self.dir.to_string_lossy();
+            draw_text(&mut frame, 1, LINE_H * 2, &Self::truncate(&dir), FOREGROUND);
+            return frame;
+        }
+
+        let page_start = self.selected / VISIBLE_LINES * VISIBLE_LINES;
+        let page = self
+            .roms
+            .iter()
+            .enumerate()
+            .skip(page_start)
+            .take(VISIBLE_LINES);
+        for (line, (i, rom)) in page.enumerate() {
+            let y = LINE_H * (line + 1);
+            let name = rom
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let color = if i == self.selected {
+                fill_rect(&mut frame, 0, y, SCREEN_W, LINE_H, FOREGROUND);
+                BACKGROUND
+            } else {
+                FOREGROUND
+            };
+            draw_text(&mut frame, 1, y, &Self::truncate(&name), color);
+        }
+
+        let help = format!("{}/{}  A:START", self.selected + 1, self.roms.len());
+        draw_text(&mut frame, 1, SCREEN_H - LINE_H, &help, FOREGROUND);
+        frame
+    }
+
+    fn truncate(s: &str) -> String {
+        s.chars().take(VISIBLE_CHARS).collect()
+    }
+}
@@ -0,0 +1,266 @@
+// The roms are often distributed compressed, the gzip file or the zip archive holding a single
+// .gb/.gbc file is opened like the rom itself.
+//
+// See: https://www.rfc-editor.org/rfc/rfc1952 (gzip)
+//      https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT (zip)
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use super::error::CartridgeError;
+use crate::gameboy::screenshot::crc32;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZIP_MAGIC: [u8; 4] = *b"PK\x03\x04";
+const ZIP_END_MAGIC: [u8; 4] = *b"PK\x05\x06";
+const ZIP_ENTRY_MAGIC: [u8; 4] = *b"PK\x01\x02";
+// The largest MBC5 rom is 8 MiB, a bigger file is not a rom.
+const MAX_ROM_SIZE: usize = 8 * 1024 * 1024;
+const ROM_EXTENSIONS: [&str; 2] = [".gb", ".gbc"];
+
+fn archive_err(s: impl Into<String>) -> CartridgeError {
+    CartridgeError::Archive(s.into())
+}
+
+fn get_u16(data: &[u8], i: usize) -> Result<u16, CartridgeError> {
+    data.get(i..i + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| archive_err("the archive is truncated"))
+}
+
+fn get_u32(data: &[u8], i: usize) -> Result<u32, CartridgeError> {
+    data.get(i..i + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| archive_err("the archive is truncated"))
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    decompress_to_vec_with_limit(data, MAX_ROM_SIZE)
+        .map_err(|e| archive_err(format!("can't inflate the rom: {:?}", e)))
+}
+
+/// Returns true if the data starts like a gzip file or a zip archive.
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC) || data.starts_with(&ZIP_MAGIC)
+}
+
+/// Returns the rom in the gzip file or the zip archive, any other data is returned as it is.
+pub fn extract_rom(data: Vec<u8>) -> Result<Vec<u8>, CartridgeError> {
+    if data.starts_with(&GZIP_MAGIC) {
+        extract_gzip(&data)
+    } else if data.starts_with(&ZIP_MAGIC) {
+        extract_zip(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+fn extract_gzip(data: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    // The deflate method only.
+    if data.get(2) != Some(&0x08) {
+        return Err(archive_err("unknown gzip compression method"));
+    }
+    let flags = data[3];
+    let mut i = 10;
+    if flags & FEXTRA != 0 {
+        i += 2 + get_u16(data, i)? as usize;
+    }
+    for flag in [FNAME, FCOMMENT].iter() {
+        if flags & flag != 0 {
+            let n = data
+                .get(i..)
+                .and_then(|s| s.iter().position(|b| *b == 0x00))
+                .ok_or_else(|| archive_err("the gzip header is truncated"))?;
+            i += n + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        i += 2;
+    }
+    if data.len() < i + 8 {
+        return Err(archive_err("the gzip file is truncated"));
+    }
+    let rom = inflate(&data[i..data.len() - 8])?;
+    let crc = get_u32(data, data.len() - 8)?;
+    if crc32(&rom) != crc {
+        return Err(archive_err("the gzip crc is wrong"));
+    }
+    Ok(rom)
+}
+
+fn extract_zip(data: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    // The end of central directory record is at the end, after a comment of up to 64 KiB.
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(0x10000 + 22)
+        .find(|i| data[*i..].starts_with(&ZIP_END_MAGIC))
+        .ok_or_else(|| archive_err("the zip central directory is not found"))?;
+    let count = get_u16(data, end + 10)? as usize;
+    let mut i = get_u32(data, end + 16)? as usize;
+
+    let mut roms = vec![];
+    for _ in 0..count {
+        if get_u32(data, i)? != u32::from_le_bytes(ZIP_ENTRY_MAGIC) {
+            return Err(archive_err("the zip central directory is broken"));
+        }
+        let name_len = get_u16(data, i + 28)? as usize;
+        let extra_len = get_u16(data, i + 30)? as usize;
+        let comment_len = get_u16(data, i + 32)? as usize;
+        let name = data
+            .get(i + 46..i + 46 + name_len)
+            .ok_or_else(|| archive_err("the zip central directory is truncated"))?;
+        let name = String::from_utf8_lossy(name).to_lowercase();
+        if ROM_EXTENSIONS.iter().any(|e| name.ends_with(e)) {
+            roms.push((
+                name,
+                get_u16(data, i + 10)?,
+                get_u32(data, i + 16)?,
+                get_u32(data, i + 20)? as usize,
+                get_u32(data, i + 42)? as usize,
+            ));
+        }
+        i += 46 + name_len + extra_len + comment_len;
+    }
+    let (name, method, crc, size, offset) = match roms.len() {
+        0 => return Err(archive_err("no .gb or .gbc file in the zip archive")),
+        1 => roms.pop().unwrap(),
+        n => {
+            let names: Vec<_> = roms.into_iter().map(|r| r.0).collect();
+            return Err(archive_err(format!(
+                "{} roms in the zip archive: {}",
+                n,
+                names.join(", ")
+            )));
+        }
+    };
+
+    // The sizes in the local header may be left zero, so the ones of the central directory
+    // are used.
+    if get_u32(data, offset)? != u32::from_le_bytes(ZIP_MAGIC) {
+        return Err(archive_err(format!("the zip entry {} is broken", name)));
+    }
+    let start =
+        offset + 30 + get_u16(data, offset + 26)? as usize + get_u16(data, offset + 28)? as usize;
+    let compressed = data
+        .get(start..start + size)
+        .ok_or_else(|| archive_err(format!("the zip entry {} is truncated", name)))?;
+    let rom = match method {
+        0 => compressed.to_vec(),
+        8 => inflate(compressed)?,
+        m => {
+            return Err(archive_err(format!(
+                "unknown zip compression method {} of {}",
+                m, name
+            )));
+        }
+    };
+    if crc32(&rom) != crc {
+        return Err(archive_err(format!("the zip crc of {} is wrong", name)));
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniz_oxide::deflate::compress_to_vec;
+
+    fn rom() -> Vec<u8> {
+        (0..0x8000).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        // FNAME is set.
+        let mut gz = vec![0x1f, 0x8b, 0x08, 0x08, 0, 0, 0, 0, 0, 0xff];
+        gz.extend_from_slice(b"game.gb\0");
+        gz.extend(compress_to_vec(data, 6));
+        gz.extend_from_slice(&crc32(data).to_le_bytes());
+        gz.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        gz
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let (mut zip, mut dir) = (vec![], vec![]);
+        for (name, data) in files {
+            let compressed = compress_to_vec(data, 6);
+            let mut header = vec![];
+            header.extend_from_slice(&[0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0, 0, 0, 0]);
+            header.extend_from_slice(&crc32(data).to_le_bytes());
+            header.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&[0x00, 0x00]);
+
+            dir.extend_from_slice(&ZIP_ENTRY_MAGIC);
+            dir.extend_from_slice(&[0x14, 0x00]);
+            dir.extend_from_slice(&header);
+            // The comment, the disk, the attributes and the offset.
+            dir.extend_from_slice(&[0; 10]);
+            dir.extend_from_slice(&(zip.len() as u32).to_le_bytes());
+            dir.extend_from_slice(name.as_bytes());
+
+            zip.extend_from_slice(&ZIP_MAGIC);
+            zip.extend_from_slice(&header);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend(compressed);
+        }
+        let offset = zip.len() as u32;
+        zip.extend_from_slice(&dir);
+        zip.extend_from_slice(&ZIP_END_MAGIC);
+        zip.extend_from_slice(&[0; 4]);
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(dir.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&offset.to_le_bytes());
+        zip.extend_from_slice(&[0x00, 0x00]);
+        zip
+    }
+
+    #[test]
+    fn test_gzip() {
+        let rom = rom();
+        assert_eq!(extract_rom(rom.clone()).unwrap(), rom);
+        let mut gz = gzip(&rom);
+        assert_eq!(extract_rom(gz.clone()).unwrap(), rom);
+        let n = gz.len();
+        gz[n - 8] ^= 0xff;
+        assert!(matches!(extract_rom(gz), Err(CartridgeError::Archive(_))));
+    }
+
+    #[test]
+    fn test_zip() {
+        let rom = rom();
+        let data = zip(&[("readme.txt", b"hello"), ("Game.GB", &rom)]);
+        assert_eq!(extract_rom(data).unwrap(), rom);
+
+        let data = zip(&[("readme.txt", b"hello")]);
+        assert!(extract_rom(data).is_err());
+        let data = zip(&[("a.gb", &rom), ("b.gbc", &rom)]);
+        let err = extract_rom(data).err().unwrap();
+        assert_eq!(err.to_string(), "2 roms in the zip archive: a.gb, b.gbc");
+    }
+
+    #[test]
+    fn test_peek_header() {
+        let mut rom = rom();
+        rom[0x0134..0x0147].copy_from_slice(&[0x00; 0x13]);
+        rom[0x0134..0x0138].copy_from_slice(b"PEEK");
+        rom[0x0147..0x014a].copy_from_slice(&[0x13, 0x01, 0x02]); // MBC3+RAM+BATTERY
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("ngc224-peek-{}.gb", std::process::id()));
+        for data in [rom.clone(), gzip(&rom)].iter() {
+            std::fs::write(&path, data).unwrap();
+            let meta = crate::gameboy::cartridge::peek_header(&path).unwrap();
+            assert_eq!(meta.get_title(), "PEEK");
+            assert_eq!(meta.get_rom_size(), 64 * 1024);
+            assert_eq!(meta.get_ram_size(), 8 * 1024);
+        }
+        std::fs::write(&path, &rom[..0x0100]).unwrap();
+        let err = crate::gameboy::cartridge::peek_header(&path).err();
+        assert!(matches!(err, Some(CartridgeError::TooSmall(0x0100))));
+        let _ = std::fs::remove_file(&path);
+    }
+}
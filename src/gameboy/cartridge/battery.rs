@@ -0,0 +1,285 @@
+use std::fs::File;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{error, info};
+
+use super::meta::CartridgeMeta;
+use super::{load_ram_state, save_ram_state};
+use crate::gameboy::clock;
+use crate::gameboy::state::{StateError, StateReader, StateWriter};
+
+/// How long the ram must stay untouched before it's written to the save file.
+pub const DEFAULT_FLUSH_DELAY: Duration = Duration::from_secs(2);
+
+/// The statistics of the battery backed ram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryStats {
+    /// Bytes written by the game but not in the save file yet.
+    pub dirty_bytes: usize,
+    /// The wall time of the last successful flush, `None` if it's never flushed.
+    pub last_flush: Option<SystemTime>,
+    pub flush_count: u32,
+}
+
+/// The write scheduler of the battery backed ram.
+/// A write only marks the byte dirty, the ram is flushed once the game stops writing for the delay,
+/// so a save routine touching hundreds of bytes ends up in a single file write.
+/// The pending bytes are flushed immediately when the cartridge is dropped.
+#[derive(Debug)]
+pub struct BatterySave {
+    path: PathBuf,
+    delay: Duration,
+    dirty: Vec<bool>,
+    dirty_bytes: usize,
+    last_write: Option<Instant>,
+    last_flush: Option<SystemTime>,
+    flush_count: u32,
+}
+
+impl BatterySave {
+    /// Returns the scheduler of a ram with the size, an empty path disables the persistence.
+    pub fn new(path: impl AsRef<Path>, size: usize) -> Self {
+        Self {
+            path: PathBuf::from(path.as_ref()),
+            delay: DEFAULT_FLUSH_DELAY,
+            dirty: vec![false; size],
+            dirty_bytes: 0,
+            last_write: None,
+            last_flush: None,
+            flush_count: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.path.as_os_str().is_empty()
+    }
+
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    /// Record the game writes the byte at the index of the ram.
+    pub fn mark_dirty(&mut self, i: usize) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Some(dirty) = self.dirty.get_mut(i) {
+            if !*dirty {
+                *dirty = true;
+                self.dirty_bytes += 1;
+            }
+        }
+        self.last_write = clock::now();
+    }
+
+    /// Mark the whole ram dirty, it's replaced by a save state.
+    pub fn mark_all_dirty(&mut self) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.dirty.iter_mut().for_each(|d| *d = true);
+        self.dirty_bytes = self.dirty.len();
+        self.last_write = clock::now();
+    }
+
+    /// Flush the ram if it's dirty and the last write is older than the delay.
+    /// Returns true if the save file is written.
+    pub fn poll(&mut self, ram: &[u8], now: Instant) -> bool {
+        match self.last_write {
+            Some(t) if self.dirty_bytes != 0 && now.saturating_duration_since(t) >= self.delay => {
+                self.flush(ram)
+            }
+            _ => false,
+        }
+    }
+
+    /// Write the ram to the save file if it's dirty. On failure the bytes are kept dirty, so the
+    /// next poll retries. Returns true if the save file is written.
+    pub fn flush(&mut self, ram: &[u8]) -> bool {
+        if !self.is_enabled() || self.dirty_bytes == 0 {
+            return false;
+        }
+        match File::create(&self.path).and_then(|mut f| f.write_all(ram)) {
+            Ok(()) => {
+                info!(
+                    "{} dirty bytes are saved to {:?}",
+                    self.dirty_bytes, self.path
+                );
+                self.dirty.iter_mut().for_each(|d| *d = false);
+                self.dirty_bytes = 0;
+                self.last_flush = clock::wall_time();
+                self.flush_count += 1;
+                true
+            }
+            Err(e) => {
+                error!("Can't write the save file {:?}: {}", self.path, e);
+                false
+            }
+        }
+    }
+
+    pub fn get_stats(&self) -> BatteryStats {
+        BatteryStats {
+            dirty_bytes: self.dirty_bytes,
+            last_flush: self.last_flush,
+            flush_count: self.flush_count,
+        }
+    }
+}
+
+/// The battery backed ram of a mapper. The game writes through it so the writes are scheduled,
+/// and the pending ones are flushed when it's dropped.
+#[derive(Debug)]
+pub struct BatteryRam {
+    data: Vec<u8>,
+    save: BatterySave,
+}
+
+impl BatteryRam {
+    /// Returns the ram kept in the save file, an empty path disables the persistence.
+    pub fn new(data: Vec<u8>, path: impl AsRef<Path>) -> Self {
+        let save = BatterySave::new(path, data.len());
+        Self { data, save }
+    }
+
+    /// Write the byte at the index of the ram, the index past the end is ignored.
+    pub fn write(&mut self, i: usize, v: u8) {
+        if let Some(b) = self.data.get_mut(i) {
+            *b = v;
+            self.save.mark_dirty(i);
+        }
+    }
+
+    /// Returns the ram as the save file holds it, `None` if there is no battery or no ram.
+    pub fn get_save_data(&self, meta: &CartridgeMeta) -> Option<&[u8]> {
+        if meta.get_type().has_battery() && !self.data.is_empty() {
+            Some(&self.data)
+        } else {
+            None
+        }
+    }
+
+    /// Copy the save data to the ram, the rest of the ram is zero.
+    pub fn load_save_data(&mut self, meta: &CartridgeMeta, data: &[u8]) {
+        if !meta.get_type().has_battery() {
+            return;
+        }
+        let n = data.len().min(self.data.len());
+        self.data[..n].copy_from_slice(&data[..n]);
+        self.data[n..].iter_mut().for_each(|b| *b = 0);
+        self.save.mark_all_dirty();
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter, meta: &CartridgeMeta) {
+        save_ram_state(w, meta, &self.data);
+    }
+
+    /// Restore the ram of the snapshot, it replaces the one in the save file.
+    pub fn load_state(
+        &mut self,
+        r: &mut StateReader,
+        meta: &CartridgeMeta,
+    ) -> Result<(), StateError> {
+        load_ram_state(r, meta, &mut self.data)?;
+        self.save.mark_all_dirty();
+        Ok(())
+    }
+
+    /// Flush the ram if the game stopped writing for the delay, returns true if the save file
+    /// is written.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        self.save.poll(&self.data, now)
+    }
+
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.save.set_delay(delay);
+    }
+
+    /// Returns the statistics, `None` without the save file.
+    pub fn get_stats(&self) -> Option<BatteryStats> {
+        if self.save.is_enabled() {
+            Some(self.save.get_stats())
+        } else {
+            None
+        }
+    }
+}
+
+impl Deref for BatteryRam {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for BatteryRam {
+    fn drop(&mut self) {
+        // Don't lose the writes still waiting for the delay.
+        self.save.flush(&self.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn temp_save(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ngc224-{}-{}.sav", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_flush_is_debounced() {
+        let path = temp_save("debounce");
+        let mut battery = BatterySave::new(&path, 4);
+        let ram = [1, 2, 3, 4];
+        battery.mark_dirty(0);
+        battery.mark_dirty(0);
+        battery.mark_dirty(3);
+        assert_eq!(battery.get_stats().dirty_bytes, 2);
+
+        // The game is still writing.
+        assert!(!battery.poll(&ram, Instant::now()));
+        assert!(!path.exists());
+
+        assert!(battery.poll(&ram, Instant::now() + DEFAULT_FLUSH_DELAY));
+        assert_eq!(fs::read(&path).unwrap(), ram);
+        let stats = battery.get_stats();
+        assert_eq!(stats.dirty_bytes, 0);
+        assert_eq!(stats.flush_count, 1);
+        assert!(stats.last_flush.is_some());
+
+        // Nothing new to write.
+        assert!(!battery.poll(&ram, Instant::now() + DEFAULT_FLUSH_DELAY));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_disabled_without_path() {
+        let mut battery = BatterySave::new("", 4);
+        battery.mark_dirty(1);
+        assert!(!battery.flush(&[0; 4]));
+        assert_eq!(battery.get_stats().dirty_bytes, 0);
+    }
+
+    #[test]
+    fn test_ram_is_flushed_on_drop() {
+        let path = temp_save("drop");
+        let mut ram = BatteryRam::new(vec![0; 4], &path);
+        ram.write(2, 0x55);
+        // Past the end of the ram.
+        ram.write(4, 0x66);
+        assert_eq!(ram.get_stats().unwrap().dirty_bytes, 1);
+        assert_eq!(ram[2], 0x55);
+        drop(ram);
+        assert_eq!(fs::read(&path).unwrap(), [0, 0, 0x55, 0]);
+        fs::remove_file(&path).unwrap();
+    }
+}
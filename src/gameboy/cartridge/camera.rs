@@ -0,0 +1,310 @@
+// The image seen by the sensor of the Pocket Camera. The PNG is decoded, turned to grey and
+// scaled down to the 128x112 pixels the camera captures.
+//
+// Only the non interlaced images are decoded, every color type and bit depth is accepted.
+//
+// See: https://www.w3.org/TR/png/
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+
+use crate::gameboy::screenshot::PNG_SIGNATURE;
+
+pub const CAMERA_W: usize = 128;
+pub const CAMERA_H: usize = 112;
+
+// A bigger image is not worth to be scaled down to 128x112.
+const MAX_IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The grey pixels of the sensor, row by row, 0x00 is black and 0xff is white.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraImage {
+    pixels: Vec<u8>,
+}
+
+impl CameraImage {
+    /// Returns the image filled with the grey level.
+    pub fn blank(level: u8) -> Self {
+        Self {
+            pixels: vec![level; CAMERA_W * CAMERA_H],
+        }
+    }
+
+    /// Returns the error if the PNG is broken or interlaced.
+    pub fn from_png(data: &[u8]) -> Result<Self, String> {
+        let (w, h, grey) = decode_png(data)?;
+        Ok(Self {
+            pixels: scale_down(&grey, w, h),
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        Self::from_png(&data).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * CAMERA_W + x]
+    }
+}
+
+fn get_u32(data: &[u8], i: usize) -> Result<u32, String> {
+    data.get(i..i + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "the PNG is truncated".to_string())
+}
+
+/// Returns the width, the height and the grey levels of the image.
+fn decode_png(data: &[u8]) -> Result<(usize, usize, Vec<u8>), String> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return Err("not a PNG image".to_string());
+    }
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut idat = vec![];
+    let mut i = PNG_SIGNATURE.len();
+    while i < data.len() {
+        let len = get_u32(data, i)? as usize;
+        let kind = data.get(i + 4..i + 8).ok_or("the PNG is truncated")?;
+        let body = data.get(i + 8..i + 8 + len).ok_or("the PNG is truncated")?;
+        match kind {
+            b"IHDR" if body.len() == 13 => header = Some(body),
+            b"PLTE" => palette = body,
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        // The CRC is not checked, a damaged image still shows something.
+        i += 12 + len;
+    }
+    let header = header.ok_or("the PNG has no IHDR")?;
+    let w = get_u32(header, 0)? as usize;
+    let h = get_u32(header, 4)? as usize;
+    let (depth, color) = (header[8] as usize, header[9]);
+    if header[12] != 0 {
+        return Err("the interlaced PNG is not supported".to_string());
+    }
+    let channels = match (color, depth) {
+        (0, 1) | (0, 2) | (0, 4) | (0, 8) | (0, 16) => 1,
+        (3, 1) | (3, 2) | (3, 4) | (3, 8) => 1,
+        (2, 8) | (2, 16) => 3,
+        (4, 8) | (4, 16) => 2,
+        (6, 8) | (6, 16) => 4,
+        _ => return Err(format!("bad color type {} of depth {}", color, depth)),
+    };
+    if w == 0 || h == 0 || w * h > MAX_IMAGE_SIZE {
+        return Err(format!("the image size {}x{} is not supported", w, h));
+    }
+
+    let stride = (w * channels * depth).div_ceil(8);
+    let raw = decompress_to_vec_zlib_with_limit(&idat, (stride + 1) * h)
+        .map_err(|e| format!("can't inflate the image: {:?}", e))?;
+    if raw.len() < (stride + 1) * h {
+        return Err("the image data is truncated".to_string());
+    }
+    let bpp = (channels * depth).div_ceil(8).max(1);
+    let mut rows = vec![0; stride * h];
+    for y in 0..h {
+        let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let (prior, row) = rows.split_at_mut(y * stride);
+        let prior = if y == 0 {
+            None
+        } else {
+            Some(&prior[(y - 1) * stride..])
+        };
+        unfilter(line[0], &line[1..], prior, &mut row[..stride], bpp)?;
+    }
+
+    let max = (1usize << depth) - 1;
+    let sample = |row: &[u8], n: usize| -> usize {
+        match depth {
+            16 => row[n * 2] as usize,
+            8 => row[n] as usize,
+            _ => {
+                let bit = n * depth;
+                (row[bit / 8] as usize >> (8 - depth - bit % 8)) & max
+            }
+        }
+    };
+    let mut grey = Vec::with_capacity(w * h);
+    for row in rows.chunks(stride) {
+        for x in 0..w {
+            let level = match color {
+                0 => sample(row, x) * 255 / max.min(255),
+                3 => {
+                    let n = sample(row, x) * 3;
+                    let rgb = palette
+                        .get(n..n + 3)
+                        .ok_or("the palette index is out of range")?;
+                    luma(rgb[0], rgb[1], rgb[2])
+                }
+                2 | 6 => {
+                    let n = x * channels;
+                    luma(
+                        sample(row, n) as u8,
+                        sample(row, n + 1) as u8,
+                        sample(row, n + 2) as u8,
+                    )
+                }
+                _ => sample(row, x * channels),
+            };
+            grey.push(level as u8);
+        }
+    }
+    Ok((w, h, grey))
+}
+
+fn luma(r: u8, g: u8, b: u8) -> usize {
+    (r as usize * 299 + g as usize * 587 + b as usize * 114) / 1000
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undo the filter of the scanline, the row above is `None` for the first one.
+fn unfilter(
+    filter: u8,
+    line: &[u8],
+    prior: Option<&[u8]>,
+    row: &mut [u8],
+    bpp: usize,
+) -> Result<(), String> {
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prior.map_or(0, |p| p[i]);
+        let c = if i >= bpp {
+            prior.map_or(0, |p| p[i - bpp])
+        } else {
+            0
+        };
+        row[i] = match filter {
+            0 => line[i],
+            1 => line[i].wrapping_add(a),
+            2 => line[i].wrapping_add(b),
+            3 => line[i].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+            4 => line[i].wrapping_add(paeth(a, b, c)),
+            n => return Err(format!("bad filter type {}", n)),
+        };
+    }
+    Ok(())
+}
+
+/// Fit the image into the sensor keeping the aspect, the area of every pixel is averaged. The
+/// image is cropped in the center if the aspect differs.
+fn scale_down(grey: &[u8], w: usize, h: usize) -> Vec<u8> {
+    // The size of the crop which has the aspect of the sensor.
+    let (cw, ch) = if w * CAMERA_H > h * CAMERA_W {
+        (h * CAMERA_W / CAMERA_H, h)
+    } else {
+        (w, w * CAMERA_H / CAMERA_W)
+    };
+    let (cw, ch) = (cw.max(1), ch.max(1));
+    let (left, top) = ((w - cw) / 2, (h - ch) / 2);
+
+    let mut pixels = Vec::with_capacity(CAMERA_W * CAMERA_H);
+    for y in 0..CAMERA_H {
+        let (y0, y1) = span(y, CAMERA_H, ch);
+        for x in 0..CAMERA_W {
+            let (x0, x1) = span(x, CAMERA_W, cw);
+            let mut sum = 0;
+            for row in grey[(top + y0) * w..(top + y1) * w].chunks(w) {
+                sum += row[left + x0..left + x1]
+                    .iter()
+                    .map(|v| *v as usize)
+                    .sum::<usize>();
+            }
+            pixels.push((sum / ((y1 - y0) * (x1 - x0))) as u8);
+        }
+    }
+    pixels
+}
+
+/// Returns the source pixels of the `i`th of the `n` pixels, there is at least one.
+fn span(i: usize, n: usize, size: usize) -> (usize, usize) {
+    let start = (i * size / n).min(size - 1);
+    let end = ((i + 1) * size / n).max(start + 1);
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::screenshot::{put_chunk, zlib_stored};
+
+    fn png(w: u32, h: u32, color: u8, depth: u8, raw: &[u8]) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend_from_slice(&w.to_be_bytes());
+        header.extend_from_slice(&h.to_be_bytes());
+        header.extend_from_slice(&[depth, color, 0, 0, 0]);
+        let mut png = PNG_SIGNATURE.to_vec();
+        put_chunk(&mut png, b"IHDR", &header);
+        put_chunk(&mut png, b"IDAT", &zlib_stored(raw));
+        put_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn test_rgb_is_scaled_to_the_sensor() {
+        // 256x224, the left half is white and the right half is black.
+        let mut raw = vec![];
+        for _ in 0..224 {
+            raw.push(0);
+            for x in 0..256 {
+                let v = if x < 128 { 0xff } else { 0x00 };
+                raw.extend_from_slice(&[v, v, v]);
+            }
+        }
+        let image = CameraImage::from_png(&png(256, 224, 2, 8, &raw)).unwrap();
+        assert_eq!(image.get(0, 0), 0xff);
+        assert_eq!(image.get(63, 111), 0xff);
+        assert_eq!(image.get(64, 0), 0x00);
+        assert_eq!(image.get(127, 111), 0x00);
+    }
+
+    #[test]
+    fn test_filters() {
+        // A 2x2 grey image, the Sub filter on the first row and the Up filter on the second one.
+        let raw = [1, 0x10, 0x10, 2, 0x20, 0x30];
+        let (w, h, grey) = decode_png(&png(2, 2, 0, 8, &raw)).unwrap();
+        assert_eq!((w, h), (2, 2));
+        assert_eq!(grey, vec![0x10, 0x20, 0x30, 0x50]);
+    }
+
+    #[test]
+    fn test_low_bit_depth() {
+        // 1 bit grey, 10100000.
+        let (_, _, grey) = decode_png(&png(3, 1, 0, 1, &[0, 0xa0])).unwrap();
+        assert_eq!(grey, vec![0xff, 0x00, 0xff]);
+    }
+
+    #[test]
+    fn test_broken_png() {
+        assert!(CameraImage::from_png(b"GIF89a").is_err());
+        let mut data = png(2, 2, 0, 8, &[0; 6]);
+        data.truncate(20);
+        assert!(CameraImage::from_png(&data).is_err());
+        assert!(CameraImage::from_png(&png(2, 2, 0, 8, &[0; 3])).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use super::meta::{CartridgeType, HEADER_END};
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    /// The rom is shorter than the cartridge header, the field is the rom size.
+    TooSmall(usize),
+    UnknownType(u8),
+    UnsupportedRomSize(u8),
+    UnsupportedRamSize(u8),
+    /// The mapper is recognized, but the emulator doesn't implement it yet.
+    Unimplemented(CartridgeType),
+    /// The logo at 0x0104-0x0133 is not the Nintendo logo.
+    BadLogo,
+    BadHeaderChecksum,
+    /// The gzip file or the zip archive is broken, or doesn't hold exactly one rom.
+    Archive(String),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Io(e) => write!(f, "can't read the rom: {}", e),
+            CartridgeError::TooSmall(n) => write!(
+                f,
+                "the rom is {} bytes, it's too small to contain the header (0x{:x} bytes)",
+                n, HEADER_END
+            ),
+            CartridgeError::UnknownType(t) => write!(f, "unknown cartridge type 0x{:02x}", t),
+            CartridgeError::UnsupportedRomSize(n) => write!(f, "unsupported rom size 0x{:02x}", n),
+            CartridgeError::UnsupportedRamSize(n) => write!(f, "unsupported ram size 0x{:02x}", n),
+            CartridgeError::Unimplemented(t) => {
+                write!(f, "the cartridge type {:?} is not implemented", t)
+            }
+            CartridgeError::BadLogo => write!(f, "the Nintendo logo in the header is wrong"),
+            CartridgeError::BadHeaderChecksum => write!(f, "the header checksum is wrong"),
+            CartridgeError::Archive(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for CartridgeError {}
+
+impl From<io::Error> for CartridgeError {
+    fn from(e: io::Error) -> Self {
+        CartridgeError::Io(e)
+    }
+}
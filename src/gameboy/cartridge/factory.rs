@@ -1,14 +1,21 @@
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use log::info;
+use log::{info, warn};
 
+use super::archive::extract_rom;
+use super::error::CartridgeError;
 use super::meta::CartridgeMeta;
 use super::meta::CartridgeType;
+use super::meta::HeaderCheck;
+use super::meta::Mbc1Wiring;
+use super::r#impl::huc1::HuC1;
 use super::r#impl::mbc1::MBC1;
 use super::r#impl::mbc2::MBC2;
 use super::r#impl::mbc3::MBC3;
+use super::r#impl::mbc5::MBC5;
+use super::r#impl::mmm01::MMM01;
+use super::r#impl::pocket_camera::PocketCamera;
 use super::r#impl::rom_only::RomOnly;
 use super::Cartridge;
 
@@ -18,92 +25,609 @@ pub struct CartridgeFactory {}
 // check rom cartridge type here https://ladecadence.net/trastero/listado%20juegos%20gameboy.html
 impl CartridgeFactory {
     /// Returns the differrent catridge entity according to the type from rom metadata.
-    pub fn new_catridge(path: impl AsRef<Path>) -> Box<dyn Cartridge> {
-        let mut rom = Vec::new();
-        let mut f = File::open(&path).unwrap();
-        f.read_to_end(&mut rom).unwrap();
+    /// The corrupted header is checked before the save files are touched. The rom can be in a
+    /// gzip file or a zip archive, the save files are placed beside the archive then.
+    pub fn new_catridge(
+        path: impl AsRef<Path>,
+        check: HeaderCheck,
+        mbc1: Option<Mbc1Wiring>,
+    ) -> Result<Box<dyn Cartridge>, CartridgeError> {
+        let rom = extract_rom(fs::read(&path)?)?;
+        CartridgeMeta::new(&rom)?.verify(check)?;
+        Self::from_rom_with_wiring(rom, Some(path.as_ref()), mbc1)
+    }
+
+    /// Build the cartridge from the rom data. The save files are placed beside the rom path, the
+    /// battery is not persisted if there is no path.
+    pub fn from_rom(
+        rom: Vec<u8>,
+        path: Option<&Path>,
+    ) -> Result<Box<dyn Cartridge>, CartridgeError> {
+        Self::from_rom_with_wiring(rom, path, None)
+    }
 
-        let meta = CartridgeMeta::new(&rom);
-        let save_path = path.as_ref().to_path_buf().with_extension("sav");
-        let rtc_save_path = path.as_ref().to_path_buf().with_extension("rtc");
+    /// Like `from_rom`, the MBC1 is wired as `mbc1` says or as detected from the rom.
+    pub fn from_rom_with_wiring(
+        mut rom: Vec<u8>,
+        path: Option<&Path>,
+        mbc1: Option<Mbc1Wiring>,
+    ) -> Result<Box<dyn Cartridge>, CartridgeError> {
+        let meta = match Self::find_mmm01_menu(&rom) {
+            Some(meta) => meta,
+            None => CartridgeMeta::new(&rom)?,
+        };
+        let (save_path, rtc_save_path) = match path {
+            Some(path) => (path.with_extension("sav"), path.with_extension("rtc")),
+            None => (PathBuf::new(), PathBuf::new()),
+        };
 
         info!("cartridge metadata is {:?}", meta);
 
+        // A truncated dump reads as open bus beyond the end.
+        if rom.len() < meta.get_rom_size() {
+            warn!(
+                "The rom is {} bytes, but the header declares {} bytes",
+                rom.len(),
+                meta.get_rom_size()
+            );
+            rom.resize(meta.get_rom_size(), 0xff);
+        }
+        let wiring = mbc1.unwrap_or_else(|| Mbc1Wiring::detect(&rom));
+        let is_mbc1 = matches!(
+            meta.get_type(),
+            CartridgeType::ROM_MBC1
+                | CartridgeType::ROM_MBC1_RAM
+                | CartridgeType::ROM_MBC1_RAM_BATT
+        );
+        if is_mbc1 && wiring == Mbc1Wiring::Multicart {
+            info!("The MBC1 is wired as a multicart");
+        }
+
         let cart: Box<dyn Cartridge> = match meta.get_type() {
-            CartridgeType::ROM_ONLY => Box::new(RomOnly::new(meta, rom.to_owned())),
-            CartridgeType::ROM_MBC1 => Box::new(MBC1::new(meta, rom.to_owned(), vec![], "")),
+            CartridgeType::ROM_ONLY => Box::new(RomOnly::new(meta, rom)),
+            CartridgeType::ROM_MBC1 => Box::new(MBC1::new(meta, rom, vec![], "", wiring)),
             CartridgeType::ROM_MBC1_RAM => {
                 let ram = vec![0; meta.get_ram_size()];
-                Box::new(MBC1::new(meta, rom.to_owned(), ram, ""))
+                Box::new(MBC1::new(meta, rom, ram, "", wiring))
             }
             CartridgeType::ROM_MBC1_RAM_BATT => {
-                let ram = match File::open(&save_path) {
-                    Ok(mut ok) => {
-                        let mut ram = Vec::new();
-                        ok.read_to_end(&mut ram).unwrap();
-                        ram
-                    }
-                    Err(_) => vec![0; meta.get_ram_size()],
-                };
-
-                Box::new(MBC1::new(meta, rom.to_owned(), ram, save_path))
-            }
-            CartridgeType::ROM_MBC2 => Box::new(MBC2::new(meta, rom.to_owned(), vec![0; 512], "")),
+                let ram = Self::load_ram(&save_path, meta.get_ram_size());
+                Box::new(MBC1::new(meta, rom, ram, save_path, wiring))
+            }
+            CartridgeType::ROM_MBC2 => Box::new(MBC2::new(meta, rom, vec![0; 512], "")),
             CartridgeType::ROM_MBC2_BATT => {
-                let ram = match File::open(&save_path) {
-                    Ok(mut ok) => {
-                        let mut ram = Vec::new();
-                        ok.read_to_end(&mut ram).unwrap();
-                        ram
-                    }
-                    Err(_) => vec![0; 512],
-                };
-
-                Box::new(MBC2::new(meta, rom.to_owned(), ram, save_path))
-            }
-            CartridgeType::ROM_MBC3_TIMER_BATT => Box::new(MBC3::new(
-                meta,
-                rom.to_owned(),
-                vec![],
-                save_path,
-                rtc_save_path,
-            )),
+                let ram = Self::load_ram(&save_path, 512);
+                Box::new(MBC2::new(meta, rom, ram, save_path))
+            }
+            CartridgeType::ROM_MBC3_TIMER_BATT => {
+                Box::new(MBC3::new(meta, rom, vec![], save_path, rtc_save_path))
+            }
             CartridgeType::ROM_MBC3_TIMER_RAM_BATT => {
-                let ram = match File::open(&save_path) {
-                    Ok(mut ok) => {
-                        let mut ram = Vec::new();
-                        ok.read_to_end(&mut ram).unwrap();
-                        ram
-                    }
-                    Err(_) => vec![0; meta.get_ram_size()],
-                };
-                Box::new(MBC3::new(
-                    meta,
-                    rom.to_owned(),
-                    ram,
-                    save_path,
-                    rtc_save_path,
-                ))
-            }
-            CartridgeType::ROM_MBC3 => Box::new(MBC3::new(meta, rom.to_owned(), vec![], "", "")),
+                let ram = Self::load_ram(&save_path, meta.get_ram_size());
+                Box::new(MBC3::new(meta, rom, ram, save_path, rtc_save_path))
+            }
+            CartridgeType::ROM_MBC3 => Box::new(MBC3::new(meta, rom, vec![], "", "")),
             CartridgeType::ROM_MBC3_RAM => {
                 let ram = vec![0; meta.get_ram_size()];
-                Box::new(MBC3::new(meta, rom.to_owned(), ram, "", ""))
+                Box::new(MBC3::new(meta, rom, ram, "", ""))
             }
             CartridgeType::ROM_MBC3_RAM_BATT => {
-                let ram = match File::open(&save_path) {
-                    Ok(mut ok) => {
-                        let mut ram = Vec::new();
-                        ok.read_to_end(&mut ram).unwrap();
-                        ram
-                    }
-                    Err(_) => vec![0; meta.get_ram_size()],
-                };
-                Box::new(MBC3::new(meta, rom.to_owned(), ram, save_path, ""))
-            }
-            n => panic!("Sorry, this cartridge type: {:?} is not implemented...", n),
+                let ram = Self::load_ram(&save_path, meta.get_ram_size());
+                Box::new(MBC3::new(meta, rom, ram, save_path, ""))
+            }
+            CartridgeType::ROM_MBC5 => Box::new(MBC5::new(meta, rom, vec![], "", false)),
+            CartridgeType::ROM_MBC5_RAM => {
+                let ram = vec![0; meta.get_ram_size()];
+                Box::new(MBC5::new(meta, rom, ram, "", false))
+            }
+            CartridgeType::ROM_MBC5_RAM_BATT => {
+                let ram = Self::load_ram(&save_path, meta.get_ram_size());
+                Box::new(MBC5::new(meta, rom, ram, save_path, false))
+            }
+            CartridgeType::ROM_MBC5_RUMBLE => Box::new(MBC5::new(meta, rom, vec![], "", true)),
+            CartridgeType::ROM_MBC5_RUMBLE_RAM => {
+                let ram = vec![0; meta.get_ram_size()];
+                Box::new(MBC5::new(meta, rom, ram, "", true))
+            }
+            CartridgeType::ROM_MBC5_RUMBLE_RAM_BATT => {
+                let ram = Self::load_ram(&save_path, meta.get_ram_size());
+                Box::new(MBC5::new(meta, rom, ram, save_path, true))
+            }
+            CartridgeType::ROM_MMM01 => Box::new(MMM01::new(meta, rom, vec![], "")),
+            CartridgeType::ROM_MMM01_RAM => {
+                let ram = vec![0; meta.get_ram_size()];
+                Box::new(MMM01::new(meta, rom, ram, ""))
+            }
+            CartridgeType::ROM_MMM01_RAM_BATT => {
+                let ram = Self::load_ram(&save_path, meta.get_ram_size());
+                Box::new(MMM01::new(meta, rom, ram, save_path))
+            }
+            // The camera always has the 128KByte battery backed ram.
+            CartridgeType::ROM_POCKET_CAMERA => {
+                let ram = Self::load_ram(&save_path, meta.get_ram_size());
+                Box::new(PocketCamera::new(meta, rom, ram, save_path))
+            }
+            // The type 0xff is always HuC1+RAM+BATTERY.
+            CartridgeType::ROM_HUC1 => {
+                let ram = Self::load_ram(&save_path, meta.get_ram_size());
+                Box::new(HuC1::new(meta, rom, ram, save_path))
+            }
+            n => return Err(CartridgeError::Unimplemented(n)),
         };
 
-        cart
+        Ok(cart)
+    }
+
+    /// Returns the header of the MMM01 menu in the last 32KByte, the header at the start of the
+    /// rom is the one of the first game.
+    fn find_mmm01_menu(rom: &[u8]) -> Option<CartridgeMeta> {
+        let menu = rom.get(rom.len().checked_sub(0x8000)?..)?;
+        let meta = CartridgeMeta::new(menu).ok()?;
+        let is_mmm01 = matches!(
+            meta.get_type(),
+            CartridgeType::ROM_MMM01
+                | CartridgeType::ROM_MMM01_RAM
+                | CartridgeType::ROM_MMM01_RAM_BATT
+        );
+        if is_mmm01 && meta.is_logo_valid() {
+            Some(meta)
+        } else {
+            None
+        }
+    }
+
+    /// Read the battery backed ram from the save file, the size always matches the cartridge
+    /// even if the file is broken.
+    fn load_ram(save_path: &Path, size: usize) -> Vec<u8> {
+        if save_path.as_os_str().is_empty() {
+            return vec![0; size];
+        }
+        let mut ram = fs::read(save_path).unwrap_or_default();
+        if !ram.is_empty() && ram.len() != size {
+            warn!(
+                "The save file {:?} is {} bytes, expected {} bytes",
+                save_path,
+                ram.len(),
+                size
+            );
+        }
+        ram.resize(size, 0);
+        ram
+    }
+
+    /// Returns a cartridge filled with zero, it stands for the empty slot.
+    pub fn new_empty_catridge() -> Box<dyn Cartridge> {
+        let rom = vec![0x00; 0x8000];
+        Self::from_rom(rom, None).expect("the zero header is a valid rom only cartridge")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::cartridge::{
+        CameraImage, CartridgeType, MapperState, HEADER_END, NINTENDO_LOGO,
+    };
+    use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+
+    /// A rom only header, the rest of the rom is omitted.
+    fn header() -> Vec<u8> {
+        vec![0x00; HEADER_END]
+    }
+
+    #[test]
+    fn test_truncated_rom() {
+        for n in 0..HEADER_END {
+            assert!(matches!(
+                CartridgeFactory::from_rom(vec![0x00; n], None),
+                Err(CartridgeError::TooSmall(size)) if size == n
+            ));
+        }
+
+        let mut rom = header();
+        rom[0x0147] = 0x01; // MBC1
+        rom[0x0148] = 0x05; // 1MB
+        let cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        assert_eq!(cart.read_byte(0x3fff), 0xff);
+        assert_eq!(cart.read_byte(0x7fff), 0xff);
+    }
+
+    #[test]
+    fn test_garbage_header_never_panics() {
+        // xorshift, the sequence is fixed so a failure is reproducible.
+        let mut seed: u32 = 0x2545_f491;
+        let mut rand = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+
+        for _ in 0..1000 {
+            let len = HEADER_END - 8 + (rand() as usize % 0x100);
+            let mut rom: Vec<u8> = (0..len).map(|_| rand() as u8).collect();
+            // Pick a known type and size in most cases, the mapper is constructed then.
+            if rand() % 4 != 0 && len > 0x0149 {
+                let types = [
+                    0x00, 0x01, 0x02, 0x03, 0x05, 0x06, 0x0f, 0x11, 0x13, 0x19, 0x1b, 0x1e,
+                ];
+                rom[0x0147] = types[rand() as usize % types.len()];
+                rom[0x0148] = (rand() % 4) as u8;
+                rom[0x0149] = (rand() % 6) as u8;
+            }
+            if let Ok(cart) = CartridgeFactory::from_rom(rom, None) {
+                for addr in (0x0000..0x8000).step_by(0x80).chain(0xa000..0xc000) {
+                    cart.read_byte(addr);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_info_reports_mapper_state() {
+        let mut rom = header();
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom[0x0147] = 0x12; // MBC3+RAM
+        rom[0x0148] = 0x02; // 128KB
+        rom[0x0149] = 0x03; // 32KB
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        cart.write_byte(0x0000, 0x0a);
+        cart.write_byte(0x2000, 0x05);
+        cart.write_byte(0x4000, 0x02);
+
+        let info = cart.get_info();
+        assert_eq!(info.title, "TEST");
+        assert_eq!(info.rom_size, 128 * 1024);
+        assert!(!info.has_battery);
+        assert_eq!(
+            info.mapper,
+            MapperState {
+                rom_bank: 5,
+                ram_bank: 2,
+                ram_enabled: true,
+                rtc_latched: Some(false),
+            }
+        );
+    }
+
+    #[test]
+    fn test_mbc5_banks_are_mirrored() {
+        let mut rom = vec![0x00; 0x10000];
+        rom[0x0147] = 0x1a; // MBC5+RAM
+        rom[0x0148] = 0x01; // 64KB, 4 banks
+        rom[0x0149] = 0x03; // 32KB, 4 banks
+        for bank in 0..4 {
+            rom[bank * 0x4000 + 0x3000] = bank as u8;
+        }
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        cart.write_byte(0x2000, 0x06);
+        assert_eq!(cart.read_byte(0x7000), 2);
+        cart.write_byte(0x3000, 0x01);
+        assert_eq!(cart.read_byte(0x7000), 2);
+
+        cart.write_byte(0x0000, 0x0a);
+        cart.write_byte(0x4000, 0x01);
+        cart.write_byte(0xa000, 0x55);
+        cart.write_byte(0x4000, 0x05);
+        assert_eq!(cart.read_byte(0xa000), 0x55);
+    }
+
+    fn temp_rom_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ngc224-{}-{}.gb", name, std::process::id()));
+        let _ = fs::remove_file(path.with_extension("sav"));
+        let _ = fs::remove_file(path.with_extension("rtc"));
+        path
+    }
+
+    #[test]
+    fn test_battery_ram_survives_reload() {
+        // MBC1+RAM+BATTERY, MBC2+BATTERY, MBC3+RAM+BATTERY and MBC3+TIMER+RAM+BATTERY.
+        for (i, t) in [0x03, 0x06, 0x13, 0x10].iter().enumerate() {
+            let path = temp_rom_path(&format!("reload-{}", i));
+            let mut rom = header();
+            rom[0x0147] = *t;
+            rom[0x0149] = 0x02; // 8KB
+
+            let mut cart = CartridgeFactory::from_rom(rom.clone(), Some(&path)).unwrap();
+            cart.write_byte(0x0000, 0x0a);
+            cart.write_byte(0xa010, 0x05);
+            drop(cart);
+            assert!(path.with_extension("sav").exists(), "type {:02x}", t);
+
+            let mut cart = CartridgeFactory::from_rom(rom, Some(&path)).unwrap();
+            cart.write_byte(0x0000, 0x0a);
+            assert_eq!(cart.read_byte(0xa010) & 0x0f, 0x05, "type {:02x}", t);
+            drop(cart);
+            let _ = fs::remove_file(path.with_extension("sav"));
+            let _ = fs::remove_file(path.with_extension("rtc"));
+        }
+    }
+
+    #[test]
+    fn test_rtc_is_saved_beside_the_ram() {
+        let path = temp_rom_path("rtc");
+        let mut rom = header();
+        rom[0x0147] = 0x10; // MBC3+TIMER+RAM+BATTERY
+        rom[0x0149] = 0x02;
+
+        // A broken rtc file restarts the clock instead of failing the load.
+        fs::write(path.with_extension("rtc"), [0x01, 0x02, 0x03]).unwrap();
+        drop(CartridgeFactory::from_rom(rom.clone(), Some(&path)).unwrap());
+        let saved = fs::read(path.with_extension("rtc")).unwrap();
+        assert_eq!(saved.len(), 17);
+
+        drop(CartridgeFactory::from_rom(rom, Some(&path)).unwrap());
+        assert_eq!(fs::read(path.with_extension("rtc")).unwrap(), saved);
+        let _ = fs::remove_file(path.with_extension("rtc"));
+    }
+
+    #[test]
+    fn test_halted_rtc_survives_reload() {
+        let path = temp_rom_path("rtc-halt");
+        let mut rom = header();
+        rom[0x0147] = 0x10; // MBC3+TIMER+RAM+BATTERY
+        rom[0x0149] = 0x02;
+
+        let mut cart = CartridgeFactory::from_rom(rom.clone(), Some(&path)).unwrap();
+        cart.write_byte(0x0000, 0x0a);
+        for (reg, v) in [(0x0c, 0xc1), (0x0a, 0x17), (0x0b, 0x2a)].iter() {
+            cart.write_byte(0x4000, *reg);
+            cart.write_byte(0xa000, *v);
+        }
+        drop(cart);
+
+        let mut cart = CartridgeFactory::from_rom(rom, Some(&path)).unwrap();
+        cart.write_byte(0x0000, 0x0a);
+        cart.write_byte(0x6000, 0x00);
+        cart.write_byte(0x6000, 0x01);
+        for (reg, v) in [(0x0c, 0xc1), (0x0a, 0x17), (0x0b, 0x2a)].iter() {
+            cart.write_byte(0x4000, *reg);
+            assert_eq!(cart.read_byte(0xa000), *v, "register {:02x}", reg);
+        }
+        drop(cart);
+        let _ = fs::remove_file(path.with_extension("sav"));
+        let _ = fs::remove_file(path.with_extension("rtc"));
+    }
+
+    #[test]
+    fn test_save_ram_without_files() {
+        // MBC1+RAM+BATTERY, MBC2+BATTERY, MBC3+TIMER+RAM+BATTERY, MBC5+RAM+BATTERY, the Pocket
+        // Camera and HuC1.
+        for t in [0x03, 0x06, 0x10, 0x1b, 0xfc, 0xff].iter() {
+            let mut rom = header();
+            rom[0x0147] = *t;
+            rom[0x0149] = 0x02; // 8KB
+            let mut cart = CartridgeFactory::from_rom(rom.clone(), None).unwrap();
+            cart.write_byte(0x0000, 0x0a);
+            cart.write_byte(0xa010, 0x05);
+            let save = cart.save_ram().expect("the ram is battery backed").to_vec();
+            assert_eq!(save[0x10] & 0x0f, 0x05, "type {:02x}", t);
+
+            let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+            cart.load_ram(&save[..0x20]);
+            cart.write_byte(0x0000, 0x0a);
+            assert_eq!(cart.read_byte(0xa010) & 0x0f, 0x05, "type {:02x}", t);
+            assert_eq!(cart.save_ram().unwrap().len(), save.len());
+            assert_eq!(cart.rtc_state().is_some(), *t == 0x10);
+        }
+
+        // The ram without a battery is lost at the power off.
+        let mut rom = header();
+        rom[0x0147] = 0x02; // MBC1+RAM
+        rom[0x0149] = 0x02;
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        assert!(cart.save_ram().is_none());
+        cart.load_ram(&[0x42; 0x10]);
+        cart.write_byte(0x0000, 0x0a);
+        assert_eq!(cart.read_byte(0xa000), 0x00);
+    }
+
+    #[test]
+    fn test_state_of_another_game_is_refused() {
+        let mut rom = header();
+        rom[0x0134..0x0138].copy_from_slice(b"GAME");
+        rom[0x0147] = 0x02; // MBC1+RAM
+        rom[0x0149] = 0x02;
+        let mut cart = CartridgeFactory::from_rom(rom.clone(), None).unwrap();
+        cart.write_byte(0x0000, 0x0a);
+        cart.write_byte(0xa000, 0x42);
+        let mut w = StateWriter::new();
+        cart.save_state(&mut w);
+        let data = w.into_bytes();
+
+        let mut restored = CartridgeFactory::from_rom(rom.clone(), None).unwrap();
+        restored.load_state(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(restored.read_byte(0xa000), 0x42);
+
+        rom[0x0134..0x0138].copy_from_slice(b"MISC");
+        let mut other = CartridgeFactory::from_rom(rom, None).unwrap();
+        assert!(matches!(
+            other.load_state(&mut StateReader::new(&data)),
+            Err(StateError::WrongCartridge)
+        ));
+    }
+
+    #[test]
+    fn test_pocket_camera_capture() {
+        let mut rom = header();
+        rom[0x0147] = 0xfc; // POCKET CAMERA
+        rom[0x0148] = 0x05; // 1MB
+        rom[0x0149] = 0x04; // 128KB
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        cart.set_camera_image(CameraImage::blank(0xff));
+        cart.write_byte(0x4000, 0x10);
+        // Full exposure, and every threshold of the matrix at 80h.
+        cart.write_byte(0xa002, 0x10);
+        cart.write_byte(0xa003, 0x00);
+        for reg in 0xa006..0xa036 {
+            cart.write_byte(reg, 0x80);
+        }
+        cart.write_byte(0xa000, 0x01);
+        assert_eq!(cart.read_byte(0xa000) & 0x01, 0x00);
+        assert_eq!(cart.read_byte(0xa010), 0x00);
+
+        // The white image is the color 0, the inverted one is the color 3.
+        cart.write_byte(0x4000, 0x00);
+        assert_eq!(cart.read_byte(0xa100), 0x00);
+        cart.write_byte(0x4000, 0x10);
+        cart.write_byte(0xa004, 0x08);
+        cart.write_byte(0xa000, 0x01);
+        cart.write_byte(0x4000, 0x00);
+        for addr in 0xa100..0xaf00 {
+            assert_eq!(cart.read_byte(addr), 0xff, "{:04x}", addr);
+        }
+        assert_eq!(cart.read_byte(0xaf00), 0x00);
+    }
+
+    #[test]
+    fn test_mbc5_banks() {
+        let mut rom = header();
+        rom[0x0147] = 0x1a; // MBC5+RAM
+        rom[0x0148] = 0x08; // 8MB
+        rom[0x0149] = 0x04; // 128KB
+        rom.resize(0x80_0000, 0x00);
+        for bank in 0..512 {
+            rom[bank * 0x4000 + 1] = bank as u8;
+            rom[bank * 0x4000 + 2] = (bank >> 8) as u8;
+        }
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        assert_eq!(cart.get_mapper_state().rom_bank, 1);
+
+        // The 9th bit is written separately, and the bank 0 is not remapped.
+        cart.write_byte(0x2000, 0x23);
+        cart.write_byte(0x3000, 0x01);
+        assert_eq!(cart.read_byte(0x4001), 0x23);
+        assert_eq!(cart.read_byte(0x4002), 0x01);
+        cart.write_byte(0x2000, 0x00);
+        cart.write_byte(0x3000, 0x00);
+        assert_eq!(cart.get_mapper_state().rom_bank, 0);
+
+        cart.write_byte(0x0000, 0x0a);
+        for bank in 0..16 {
+            cart.write_byte(0x4000, bank);
+            cart.write_byte(0xa000, bank + 0x80);
+        }
+        for bank in 0..16 {
+            cart.write_byte(0x4000, bank);
+            assert_eq!(cart.read_byte(0xa000), bank + 0x80);
+        }
+    }
+
+    fn mbc1_rom(size: u8, banks: usize) -> Vec<u8> {
+        let mut rom = header();
+        rom[0x0147] = 0x01; // MBC1
+        rom[0x0148] = size;
+        rom.resize(banks * 0x4000, 0x00);
+        for bank in 0..banks {
+            rom[bank * 0x4000 + 1] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_large_mbc1_banks() {
+        let rom = mbc1_rom(0x06, 128); // 2MB
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        cart.write_byte(0x2000, 0x05);
+        cart.write_byte(0x4000, 0x02);
+        assert_eq!(cart.read_byte(0x4001), 0x45);
+        assert_eq!(cart.read_byte(0x0001), 0x00);
+        // The RAM Banking Mode keeps the upper bits on 4000-7FFF and maps them into 0000-3FFF.
+        cart.write_byte(0x6000, 0x01);
+        assert_eq!(cart.read_byte(0x4001), 0x45);
+        assert_eq!(cart.read_byte(0x0001), 0x40);
+        assert_eq!(cart.get_mapper_state().rom_bank, 0x45);
+        // The bank 0x20 is remapped to 0x21.
+        cart.write_byte(0x2000, 0x00);
+        cart.write_byte(0x4000, 0x01);
+        assert_eq!(cart.read_byte(0x4001), 0x21);
+
+        // The bank beyond a 256KB rom wraps around.
+        let rom = mbc1_rom(0x03, 16);
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        cart.write_byte(0x2000, 0x13);
+        assert_eq!(cart.read_byte(0x4001), 0x03);
+    }
+
+    #[test]
+    fn test_mbc1_multicart() {
+        let mut rom = mbc1_rom(0x05, 64); // 1MB
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        assert_eq!(Mbc1Wiring::detect(&rom), Mbc1Wiring::Normal);
+        rom[0x40104..0x40134].copy_from_slice(&NINTENDO_LOGO);
+        assert_eq!(Mbc1Wiring::detect(&rom), Mbc1Wiring::Multicart);
+
+        let mut cart = CartridgeFactory::from_rom(rom.clone(), None).unwrap();
+        cart.write_byte(0x2000, 0x13);
+        cart.write_byte(0x4000, 0x02);
+        assert_eq!(cart.read_byte(0x4001), 0x23);
+        // The game at the bank 0x20 starts from its own bank 0.
+        cart.write_byte(0x6000, 0x01);
+        assert_eq!(cart.read_byte(0x0001), 0x20);
+        cart.write_byte(0x2000, 0x10);
+        assert_eq!(cart.read_byte(0x4001), 0x20);
+
+        let mut cart =
+            CartridgeFactory::from_rom_with_wiring(rom, None, Some(Mbc1Wiring::Normal)).unwrap();
+        cart.write_byte(0x2000, 0x13);
+        cart.write_byte(0x4000, 0x01);
+        assert_eq!(cart.read_byte(0x4001), 0x33);
+    }
+
+    #[test]
+    fn test_huc1() {
+        let mut rom = mbc1_rom(0x05, 64); // 1MB
+        rom[0x0147] = 0xff; // HuC1+RAM+BATTERY
+        rom[0x0149] = 0x03; // 32KB
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        cart.write_byte(0x2000, 0x00);
+        assert_eq!(cart.read_byte(0x4001), 0x01);
+        cart.write_byte(0x2000, 0x3f);
+        assert_eq!(cart.read_byte(0x4001), 0x3f);
+
+        // The RAM is mapped without enabling it.
+        cart.write_byte(0x4000, 0x02);
+        cart.write_byte(0xa000, 0x42);
+        assert_eq!(cart.read_byte(0xa000), 0x42);
+        // The infrared port sees no light.
+        cart.write_byte(0x0000, 0x0e);
+        assert_eq!(cart.read_byte(0xa000), 0xc0);
+        cart.write_byte(0xa000, 0x01);
+        cart.write_byte(0x0000, 0x00);
+        assert_eq!(cart.read_byte(0xa000), 0x42);
+        assert_eq!(cart.get_mapper_state().ram_bank, 2);
+    }
+
+    #[test]
+    fn test_mmm01_maps_the_game() {
+        // 4 games of 128KB, the menu is in the last 32KB.
+        let mut rom = mbc1_rom(0x04, 32); // 512KB
+        let menu = rom.len() - 0x8000;
+        rom[menu + 0x0104..menu + 0x0134].copy_from_slice(&NINTENDO_LOGO);
+        rom[menu + 0x0147] = 0x0d; // MMM01+RAM+BATTERY
+        rom[menu + 0x0148] = 0x04;
+        rom[menu + 0x0149] = 0x03; // 32KB
+        let mut cart = CartridgeFactory::from_rom(rom, None).unwrap();
+        assert!(matches!(
+            cart.get_meta().get_type(),
+            CartridgeType::ROM_MMM01_RAM_BATT
+        ));
+        assert_eq!(cart.read_byte(0x0001), 0x1e);
+        assert_eq!(cart.read_byte(0x4001), 0x1f);
+
+        // The menu picks the third game at the bank 0x10, the game switches 3 bits of the bank.
+        cart.write_byte(0x2000, 0x10);
+        cart.write_byte(0x6000, 0x30);
+        cart.write_byte(0x0000, 0x40);
+        assert_eq!(cart.read_byte(0x0001), 0x10);
+        assert_eq!(cart.read_byte(0x4001), 0x11);
+        cart.write_byte(0x2000, 0x05);
+        assert_eq!(cart.read_byte(0x4001), 0x15);
+        // The upper bits are locked.
+        cart.write_byte(0x2000, 0x1f);
+        assert_eq!(cart.read_byte(0x4001), 0x17);
+        cart.write_byte(0x6000, 0x00);
+        cart.write_byte(0x2000, 0x0f);
+        assert_eq!(cart.read_byte(0x4001), 0x17);
     }
 }
@@ -0,0 +1,167 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::BatteryRam;
+use super::BatteryStats;
+use super::Cartridge;
+use super::CartridgeMeta;
+use super::IOHandler;
+use super::MapperState;
+use super::MemoryBank;
+use super::{StateError, StateReader, StateWriter, Stateful};
+
+/// The Hudson HuC1 banks like the MBC1 without the banking modes, up to 1MByte ROM (64 banks) and
+/// 32KByte RAM (4 banks). The RAM area can be switched to the infrared port instead, the port is
+/// a stub which never sees the light of another Game Boy.
+#[derive(Debug)]
+pub struct HuC1 {
+    meta: CartridgeMeta,
+    rom: Vec<u8>,
+    ram: BatteryRam,
+    rom_bank: usize,
+    ram_bank: usize,
+    // A000-BFFF is the infrared port rather than the RAM.
+    ir_mode: bool,
+    // The LED of the infrared port, it's lit by writing 1 in the IR mode.
+    ir_led: bool,
+}
+
+impl HuC1 {
+    /// Returns a new HuC1 chip.
+    pub fn new(meta: CartridgeMeta, rom: Vec<u8>, ram: Vec<u8>, sav: impl AsRef<Path>) -> Self {
+        let ram = BatteryRam::new(ram, sav);
+        Self {
+            meta,
+            rom,
+            ram,
+            rom_bank: 1,
+            ram_bank: 0,
+            ir_mode: false,
+            ir_led: false,
+        }
+    }
+
+    fn get_ram_index(&self, addr: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        Some((self.ram_bank * 0x2000 + addr as usize - 0xa000) % self.ram.len())
+    }
+}
+
+impl MemoryBank for HuC1 {
+    fn get_rom_bank_num(&self) -> usize {
+        self.rom_bank
+    }
+
+    fn get_ram_bank_num(&self) -> usize {
+        self.ram_bank
+    }
+
+    fn read_via_rom_bank(&self, addr: u16) -> u8 {
+        let bank_addr = 0x4000 * self.get_rom_bank_num() + (addr as usize - 0x4000);
+        self.rom[bank_addr % self.rom.len()]
+    }
+
+    /// The infrared port reads C0 while no light is received, C1 would be the light.
+    fn read_via_ram_bank(&self, addr: u16) -> u8 {
+        if self.ir_mode {
+            return 0xc0;
+        }
+        self.get_ram_index(addr).map_or(0xff, |i| self.ram[i])
+    }
+
+    fn write_via_ram_bank(&mut self, addr: u16, value: u8) {
+        if self.ir_mode {
+            self.ir_led = value & 0x01 != 0;
+            return;
+        }
+        if let Some(i) = self.get_ram_index(addr) {
+            self.ram.write(i, value);
+        }
+    }
+}
+
+impl IOHandler for HuC1 {
+    fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => self.rom[addr as usize],
+            0x4000..=0x7fff => self.read_via_rom_bank(addr),
+            0xa000..=0xbfff => self.read_via_ram_bank(addr),
+            _ => 0x00,
+        }
+    }
+
+    /// ### 0000-1FFF - RAM/IR Select (Write Only)
+    /// Writing 0Eh maps the infrared port at A000-BFFF, any other value maps the RAM. The RAM
+    /// doesn't need to be enabled.
+    ///
+    /// ### 2000-3FFF - ROM Bank Number (Write Only)
+    /// The 6 bits ROM bank number, 00h is translated to 01h like the MBC1.
+    ///
+    /// ### 4000-5FFF - RAM Bank Number (Write Only)
+    /// Selects the RAM bank 00-03h.
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1fff => self.ir_mode = value == 0x0e,
+            0x2000..=0x3fff => self.rom_bank = usize::from(value & 0x3f).max(1),
+            0x4000..=0x5fff => self.ram_bank = usize::from(value & 0x03),
+            0xa000..=0xbfff => self.write_via_ram_bank(addr, value),
+            _ => {}
+        }
+    }
+}
+
+impl Stateful for HuC1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ram.save_state(w, &self.meta);
+        w.put_u8(self.rom_bank as u8);
+        w.put_u8(self.ram_bank as u8);
+        w.put_bool(self.ir_mode);
+        w.put_bool(self.ir_led);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ram.load_state(r, &self.meta)?;
+        self.rom_bank = usize::from(r.get_u8()? & 0x3f).max(1);
+        self.ram_bank = usize::from(r.get_u8()? & 0x03);
+        self.ir_mode = r.get_bool()?;
+        self.ir_led = r.get_bool()?;
+        Ok(())
+    }
+}
+
+impl Cartridge for HuC1 {
+    fn get_meta(&self) -> CartridgeMeta {
+        self.meta.clone()
+    }
+
+    fn get_mapper_state(&self) -> MapperState {
+        MapperState {
+            rom_bank: self.get_rom_bank_num(),
+            ram_bank: self.get_ram_bank_num(),
+            ram_enabled: !self.ir_mode,
+            rtc_latched: None,
+        }
+    }
+
+    fn poll_battery(&mut self, now: Instant) {
+        self.ram.poll(now);
+    }
+
+    fn set_battery_delay(&mut self, delay: Duration) {
+        self.ram.set_delay(delay);
+    }
+
+    fn get_battery_stats(&self) -> Option<BatteryStats> {
+        self.ram.get_stats()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.ram.get_save_data(&self.meta)
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram.load_save_data(&self.meta, data);
+    }
+}
@@ -1,20 +1,22 @@
-use std::fs::File;
-use std::io::Write;
-use std::path::{Path, PathBuf};
-
-use log::info;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 use super::BankMode;
+use super::BatteryRam;
+use super::BatteryStats;
 use super::Cartridge;
 use super::CartridgeMeta;
 use super::IOHandler;
+use super::MapperState;
+use super::Mbc1Wiring;
 use super::MemoryBank;
+use super::{StateError, StateReader, StateWriter, Stateful};
 
 #[derive(Debug)]
 pub struct MBC1 {
     meta: CartridgeMeta,
     rom: Vec<u8>,
-    ram: Vec<u8>,
+    ram: BatteryRam,
     bank_mode: BankMode, // MBC1 has two different maximum memory modes: 16Mbit ROM/8KByte RAM or 4Mbit ROM/32KByte RAM.
 
     // Bank Mode   RAM Bank Bits   ROM Bank Bits
@@ -29,14 +31,25 @@ pub struct MBC1 {
 
     //    ROM Bank Number = ROM Bank Bits
     //    RAM Bank Number = RAM Bank Bits
+    //
+    // The rom larger than 512KB takes the RAM Bank Bits in both modes, the RAM Banking Mode also
+    // maps them into 0000-3FFF then. The bank beyond the rom wraps around like the chip ignores
+    // the upper address lines.
     bank_reg: u8,
     ram_enabled: bool,
-    sav_path: PathBuf,
+    wiring: Mbc1Wiring,
 }
 
 impl MBC1 {
     /// Returns a new MBC1 chip.
-    pub fn new(meta: CartridgeMeta, rom: Vec<u8>, ram: Vec<u8>, sav: impl AsRef<Path>) -> Self {
+    pub fn new(
+        meta: CartridgeMeta,
+        rom: Vec<u8>,
+        ram: Vec<u8>,
+        sav: impl AsRef<Path>,
+        wiring: Mbc1Wiring,
+    ) -> Self {
+        let ram = BatteryRam::new(ram, sav);
         Self {
             meta,
             rom,
@@ -44,18 +57,45 @@ impl MBC1 {
             bank_mode: BankMode::Rom,
             bank_reg: 0x01,
             ram_enabled: false,
-            sav_path: PathBuf::from(sav.as_ref()),
+            wiring,
+        }
+    }
+
+    /// Returns the bank number made of the RAM Bank Bits above the given ROM Bank Bits.
+    fn get_bank_num(&self, lower: u8) -> usize {
+        let upper = (self.bank_reg & 0b0110_0000) >> 5;
+        let n = match self.wiring {
+            Mbc1Wiring::Normal => upper << 5 | lower,
+            Mbc1Wiring::Multicart => upper << 4 | (lower & 0b0000_1111),
+        };
+        n as usize
+    }
+
+    /// Returns the rom offset of the address in the bank, the missing address lines wrap it.
+    fn get_rom_addr(&self, bank: usize, addr: u16) -> usize {
+        (0x4000 * bank + (addr as usize & 0x3fff)) % self.rom.len()
+    }
+
+    /// Returns the rom bank mapped at 0000-3FFF.
+    fn get_rom0_bank_num(&self) -> usize {
+        match self.bank_mode {
+            BankMode::Rom => 0,
+            BankMode::Ram => self.get_bank_num(0),
         }
     }
+
+    fn get_ram_addr(&self, addr: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let bank_addr = 0x2000 * self.get_ram_bank_num() + (addr as usize - 0xa000);
+        Some(bank_addr % self.ram.len())
+    }
 }
 
 impl MemoryBank for MBC1 {
     fn get_rom_bank_num(&self) -> usize {
-        let n = match self.bank_mode {
-            BankMode::Rom => self.bank_reg & 0b0111_1111,
-            BankMode::Ram => self.bank_reg & 0b0001_1111,
-        };
-        n as usize
+        self.get_bank_num(self.bank_reg & 0b0001_1111)
     }
 
     fn get_ram_bank_num(&self) -> usize {
@@ -67,35 +107,16 @@ impl MemoryBank for MBC1 {
     }
 
     fn read_via_rom_bank(&self, addr: u16) -> u8 {
-        let bank_addr = 0x4000 * self.get_rom_bank_num() + (addr as usize - 0x4000);
-        if (bank_addr as usize) < self.rom.len() {
-            self.rom[bank_addr as usize]
-        } else {
-            0x00
-        }
+        self.rom[self.get_rom_addr(self.get_rom_bank_num(), addr)]
     }
 
     fn read_via_ram_bank(&self, addr: u16) -> u8 {
-        if !self.ram_enabled {
-            return 0x00;
-        }
-
-        let bank_addr = 0x2000 * self.get_ram_bank_num() + (addr as usize - 0xa000);
-        if (bank_addr as usize) < self.ram.len() {
-            self.ram[bank_addr as usize]
-        } else {
-            0x00
-        }
+        self.get_ram_addr(addr).map_or(0x00, |i| self.ram[i])
     }
 
     fn write_via_ram_bank(&mut self, addr: u16, value: u8) {
-        if !self.ram_enabled {
-            return;
-        }
-
-        let bank_addr = 0x2000 * self.get_ram_bank_num() + (addr as usize - 0xa000);
-        if (bank_addr as usize) < self.ram.len() {
-            self.ram[bank_addr as usize] = value
+        if let Some(i) = self.get_ram_addr(addr) {
+            self.ram.write(i, value);
         }
     }
 }
@@ -116,7 +137,7 @@ impl IOHandler for MBC1 {
     /// Available RAM sizes are: 2KByte (at A000-A7FF), 8KByte (at A000-BFFF), and 32KByte (in form of four 8K banks at A000-BFFF).
     fn read_byte(&self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x3fff => self.rom[addr as usize],
+            0x0000..=0x3fff => self.rom[self.get_rom_addr(self.get_rom0_bank_num(), addr)],
             0x4000..=0x7fff => self.read_via_rom_bank(addr),
             0xa000..=0xbfff => self.read_via_ram_bank(addr),
             _ => 0x00,
@@ -169,10 +190,10 @@ impl IOHandler for MBC1 {
                 let n = value & 0b0011;
                 self.bank_reg = self.bank_reg & 0b1001_1111 | (n << 5);
             }
-            0x6000..=0x7fff => match value {
+            // Only the bit 0 is connected.
+            0x6000..=0x7fff => match value & 0x01 {
                 0x00 => self.bank_mode = BankMode::Rom,
-                0x01 => self.bank_mode = BankMode::Ram,
-                _ => panic!("Invalid value: {}", value),
+                _ => self.bank_mode = BankMode::Ram,
             },
             0xa000..=0xbfff => {
                 self.write_via_ram_bank(addr, value);
@@ -182,20 +203,58 @@ impl IOHandler for MBC1 {
     }
 }
 
+impl Stateful for MBC1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ram.save_state(w, &self.meta);
+        w.put_bool(matches!(self.bank_mode, BankMode::Ram));
+        w.put_u8(self.bank_reg);
+        w.put_bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ram.load_state(r, &self.meta)?;
+        self.bank_mode = if r.get_bool()? {
+            BankMode::Ram
+        } else {
+            BankMode::Rom
+        };
+        self.bank_reg = r.get_u8()?;
+        self.ram_enabled = r.get_bool()?;
+        Ok(())
+    }
+}
+
 impl Cartridge for MBC1 {
     fn get_meta(&self) -> CartridgeMeta {
         self.meta.clone()
     }
-}
 
-impl Drop for MBC1 {
-    fn drop(&mut self) {
-        if self.sav_path.to_str().unwrap().is_empty() {
-            return;
+    fn get_mapper_state(&self) -> MapperState {
+        MapperState {
+            rom_bank: self.get_rom_bank_num(),
+            ram_bank: self.get_ram_bank_num(),
+            ram_enabled: self.ram_enabled,
+            rtc_latched: None,
         }
-        File::create(self.sav_path.clone())
-            .and_then(|mut f| f.write_all(&self.ram))
-            .unwrap();
-        info!("save success when drop the cartridge object.")
+    }
+
+    fn poll_battery(&mut self, now: Instant) {
+        self.ram.poll(now);
+    }
+
+    fn set_battery_delay(&mut self, delay: Duration) {
+        self.ram.set_delay(delay);
+    }
+
+    fn get_battery_stats(&self) -> Option<BatteryStats> {
+        self.ram.get_stats()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.ram.get_save_data(&self.meta)
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram.load_save_data(&self.meta, data);
     }
 }
@@ -1,34 +1,34 @@
-use std::fs::File;
-use std::io::Write;
-use std::path::{Path, PathBuf};
-
-use log::info;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
+use super::BatteryRam;
+use super::BatteryStats;
 use super::Cartridge;
 use super::CartridgeMeta;
 use super::IOHandler;
+use super::MapperState;
 use super::MemoryBank;
+use super::{StateError, StateReader, StateWriter, Stateful};
 
 #[derive(Debug)]
 pub struct MBC2 {
     pub meta: CartridgeMeta,
     rom: Vec<u8>,
-    ram: Vec<u8>,
+    ram: BatteryRam,
     rom_bank: usize,
     ram_enabled: bool,
-    sav_path: PathBuf,
 }
 
 impl MBC2 {
     /// Returns a new MBC2 chip.
     pub fn new(meta: CartridgeMeta, rom: Vec<u8>, ram: Vec<u8>, sav: impl AsRef<Path>) -> Self {
+        let ram = BatteryRam::new(ram, sav);
         Self {
             meta,
             rom,
             ram,
             rom_bank: 1,
             ram_enabled: false,
-            sav_path: PathBuf::from(sav.as_ref()),
         }
     }
 }
@@ -53,7 +53,7 @@ impl MemoryBank for MBC2 {
 
     fn read_via_ram_bank(&self, addr: u16) -> u8 {
         // It's has no ram bank
-        if !self.ram_enabled {
+        if self.ram_enabled {
             self.ram[(addr - 0xa000) as usize]
         } else {
             0x00
@@ -65,10 +65,7 @@ impl MemoryBank for MBC2 {
             return;
         }
 
-        let bank_addr = addr as usize - 0xa000;
-        if (bank_addr as usize) < self.ram.len() {
-            self.ram[bank_addr as usize] = value
-        }
+        self.ram.write(addr as usize - 0xa000, value);
     }
 }
 
@@ -136,20 +133,53 @@ impl IOHandler for MBC2 {
     }
 }
 
+impl Stateful for MBC2 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ram.save_state(w, &self.meta);
+        w.put_u8(self.rom_bank as u8);
+        w.put_bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ram.load_state(r, &self.meta)?;
+        self.rom_bank = usize::from(r.get_u8()?);
+        self.ram_enabled = r.get_bool()?;
+        Ok(())
+    }
+}
+
 impl Cartridge for MBC2 {
     fn get_meta(&self) -> CartridgeMeta {
         self.meta.clone()
     }
-}
 
-impl Drop for MBC2 {
-    fn drop(&mut self) {
-        if self.sav_path.to_str().unwrap().is_empty() {
-            return;
+    fn get_mapper_state(&self) -> MapperState {
+        // The built-in ram has no bank.
+        MapperState {
+            rom_bank: self.get_rom_bank_num(),
+            ram_bank: 0,
+            ram_enabled: self.ram_enabled,
+            rtc_latched: None,
         }
-        File::create(self.sav_path.clone())
-            .and_then(|mut f| f.write_all(&self.ram))
-            .unwrap();
-        info!("save success when drop the cartridge object.")
+    }
+
+    fn poll_battery(&mut self, now: Instant) {
+        self.ram.poll(now);
+    }
+
+    fn set_battery_delay(&mut self, delay: Duration) {
+        self.ram.set_delay(delay);
+    }
+
+    fn get_battery_stats(&self) -> Option<BatteryStats> {
+        self.ram.get_stats()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.ram.get_save_data(&self.meta)
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram.load_save_data(&self.meta, data);
     }
 }
@@ -1,26 +1,26 @@
-use std::fs::File;
-use std::io::Write;
-use std::path::{Path, PathBuf};
-
-use log::error;
-use log::info;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
+use super::BatteryRam;
+use super::BatteryStats;
 use super::Cartridge;
 use super::CartridgeMeta;
 use super::IOHandler;
+use super::MapperState;
 use super::MemoryBank;
 use super::RealTimeClock;
+use super::RtcState;
+use super::{StateError, StateReader, StateWriter, Stateful};
 
 #[derive(Debug)]
 pub struct MBC3 {
     meta: CartridgeMeta,
     rom: Vec<u8>,
-    ram: Vec<u8>,
+    ram: BatteryRam,
     rtc: RealTimeClock,
     rom_bank: usize,
     ram_bank: usize,
     ram_enabled: bool,
-    sav_path: PathBuf,
 }
 
 impl MBC3 {
@@ -32,6 +32,7 @@ impl MBC3 {
         save_path: impl AsRef<Path>,
         rtc_save_path: impl AsRef<Path>,
     ) -> Self {
+        let ram = BatteryRam::new(ram, save_path);
         MBC3 {
             meta,
             rom,
@@ -40,7 +41,6 @@ impl MBC3 {
             rom_bank: 1,
             ram_bank: 0,
             ram_enabled: false,
-            sav_path: PathBuf::from(save_path.as_ref()),
         }
     }
 }
@@ -64,15 +64,16 @@ impl MemoryBank for MBC3 {
     }
 
     fn read_via_ram_bank(&self, addr: u16) -> u8 {
-        if self.ram_enabled {
-            if self.get_ram_bank_num() <= 0x03 {
-                let i = self.get_ram_bank_num() * 0x2000 + addr as usize - 0xa000;
-                self.ram[i]
-            } else {
-                self.rtc.get(self.ram_bank as u16)
+        if !self.ram_enabled {
+            return 0x00;
+        }
+        match self.ram_bank {
+            0x00..=0x03 => {
+                let i = self.ram_bank * 0x2000 + addr as usize - 0xa000;
+                self.ram.get(i).copied().unwrap_or(0x00)
             }
-        } else {
-            0x00
+            0x08..=0x0c => self.rtc.get(self.ram_bank as u16),
+            _ => 0x00,
         }
     }
 
@@ -81,9 +82,13 @@ impl MemoryBank for MBC3 {
             return;
         }
 
-        let bank_addr = addr as usize - 0xa000;
-        if (bank_addr as usize) < self.ram.len() {
-            self.ram[bank_addr as usize] = value
+        match self.ram_bank {
+            0x00..=0x03 => {
+                let i = self.ram_bank * 0x2000 + addr as usize - 0xa000;
+                self.ram.write(i, value);
+            }
+            0x08..=0x0c => self.rtc.set(self.ram_bank as u16, value),
+            _ => {}
         }
     }
 }
@@ -136,16 +141,7 @@ impl IOHandler for MBC3 {
     ///
     fn write_byte(&mut self, addr: u16, value: u8) {
         match addr {
-            0xa000..=0xbfff => {
-                if self.ram_enabled {
-                    if self.ram_bank <= 0x03 {
-                        let i = self.ram_bank * 0x2000 + addr as usize - 0xa000;
-                        self.ram[i] = value;
-                    } else {
-                        self.rtc.set(self.ram_bank as u16, value)
-                    }
-                }
-            }
+            0xa000..=0xbfff => self.write_via_ram_bank(addr, value),
             0x0000..=0x1fff => {
                 self.ram_enabled = value & 0x0f == 0x0a;
             }
@@ -162,37 +158,69 @@ impl IOHandler for MBC3 {
                 // https://github.com/mvdnes/rboy/blob/a1729c729c504f48c9ec47a5c3f35d16c56a5ee3/src/mbc/mbc3.rs#L151
                 self.ram_bank = (value & 0x0f) as usize;
             }
-            0x6000..=0x7fff => match value {
-                0 => self.rtc.unlock(),
-                1 => {
-                    if !self.rtc.is_locked() {
-                        self.rtc.tick();
-                    };
-                    self.rtc.lock();
-                }
-                _ => {
-                    error! {"Only support 0|1 to tick, but get the value {}", value}
-                }
-            },
+            0x6000..=0x7fff => self.rtc.write_latch(value),
             _ => {}
         }
     }
 }
 
+impl Stateful for MBC3 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ram.save_state(w, &self.meta);
+        w.put_u8(self.rom_bank as u8);
+        w.put_u8(self.ram_bank as u8);
+        w.put_bool(self.ram_enabled);
+        self.rtc.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ram.load_state(r, &self.meta)?;
+        self.rom_bank = usize::from(r.get_u8()?);
+        self.ram_bank = usize::from(r.get_u8()?);
+        self.ram_enabled = r.get_bool()?;
+        self.rtc.load_state(r)?;
+        Ok(())
+    }
+}
+
 impl Cartridge for MBC3 {
     fn get_meta(&self) -> CartridgeMeta {
         self.meta.clone()
     }
-}
 
-impl Drop for MBC3 {
-    fn drop(&mut self) {
-        if self.sav_path.to_str().unwrap().is_empty() {
-            return;
+    fn get_mapper_state(&self) -> MapperState {
+        MapperState {
+            rom_bank: self.get_rom_bank_num(),
+            ram_bank: self.get_ram_bank_num(),
+            ram_enabled: self.ram_enabled,
+            rtc_latched: Some(self.rtc.is_latched()),
+        }
+    }
+
+    fn poll_battery(&mut self, now: Instant) {
+        // Keep the clock beside the save, it's not lost if the emulator is killed.
+        if self.ram.poll(now) {
+            self.rtc.save();
         }
-        File::create(self.sav_path.clone())
-            .and_then(|mut f| f.write_all(&self.ram))
-            .unwrap();
-        info!("save success when drop the cartridge object.")
+    }
+
+    fn set_battery_delay(&mut self, delay: Duration) {
+        self.ram.set_delay(delay);
+    }
+
+    fn get_battery_stats(&self) -> Option<BatteryStats> {
+        self.ram.get_stats()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.ram.get_save_data(&self.meta)
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram.load_save_data(&self.meta, data);
+    }
+
+    fn rtc_state(&self) -> Option<RtcState> {
+        Some(self.rtc.get_state())
     }
 }
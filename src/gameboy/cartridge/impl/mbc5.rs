@@ -0,0 +1,216 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::BatteryRam;
+use super::BatteryStats;
+use super::Cartridge;
+use super::CartridgeMeta;
+use super::IOHandler;
+use super::MapperState;
+use super::MemoryBank;
+use super::{StateError, StateReader, StateWriter, Stateful};
+
+/// The MBC5 is the first MBC which works in the GBC double speed mode, it addresses up to 8MByte ROM
+/// (512 banks) and 128KByte RAM (16 banks).
+#[derive(Debug)]
+pub struct MBC5 {
+    meta: CartridgeMeta,
+    rom: Vec<u8>,
+    ram: BatteryRam,
+    // The 9 bits ROM bank number, the bank 0 is selectable.
+    rom_bank: usize,
+    ram_bank: usize,
+    ram_enabled: bool,
+    // The bit 3 of the RAM bank number drives the rumble motor instead.
+    has_rumble: bool,
+    rumble: bool,
+    // The games pulse the motor within a frame to set its strength, a pulse isn't missed.
+    rumble_ran: bool,
+}
+
+impl MBC5 {
+    /// Returns a new MBC5 chip.
+    pub fn new(
+        meta: CartridgeMeta,
+        rom: Vec<u8>,
+        ram: Vec<u8>,
+        sav: impl AsRef<Path>,
+        has_rumble: bool,
+    ) -> Self {
+        let ram = BatteryRam::new(ram, sav);
+        Self {
+            meta,
+            rom,
+            ram,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            has_rumble,
+            rumble: false,
+            rumble_ran: false,
+        }
+    }
+
+    /// The banks past the end of the chips mirror the ones below, the unconnected lines of the
+    /// bank number are ignored.
+    fn get_rom_index(&self, addr: u16) -> usize {
+        let count = (self.rom.len() / 0x4000).max(1);
+        0x4000 * (self.rom_bank & (count - 1)) + (addr as usize - 0x4000)
+    }
+
+    fn get_ram_index(&self, addr: u16) -> usize {
+        let count = (self.ram.len() / 0x2000).max(1);
+        (self.ram_bank & (count - 1)) * 0x2000 + addr as usize - 0xa000
+    }
+}
+
+impl MemoryBank for MBC5 {
+    fn get_rom_bank_num(&self) -> usize {
+        self.rom_bank
+    }
+
+    fn get_ram_bank_num(&self) -> usize {
+        self.ram_bank
+    }
+
+    fn read_via_rom_bank(&self, addr: u16) -> u8 {
+        self.rom
+            .get(self.get_rom_index(addr))
+            .copied()
+            .unwrap_or(0xff)
+    }
+
+    fn read_via_ram_bank(&self, addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xff;
+        }
+        self.ram
+            .get(self.get_ram_index(addr))
+            .copied()
+            .unwrap_or(0xff)
+    }
+
+    fn write_via_ram_bank(&mut self, addr: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        self.ram.write(self.get_ram_index(addr), value);
+    }
+}
+
+impl IOHandler for MBC5 {
+    /// ### 0000-3FFF - ROM Bank 00 (Read Only)
+    /// Same as for MBC1.
+    ///
+    /// ### 4000-7FFF - ROM Bank 00-1FF (Read Only)
+    /// Same as for MBC1, except that accessing up to bank 1FFh is supported now. Also, bank 0 is actually bank 0.
+    ///
+    /// ### A000-BFFF - RAM Bank 00-0F, if any (Read/Write)
+    /// Same as for MBC1, except RAM sizes are 8KiB, 32KiB and 128KiB.
+    fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => self.rom[addr as usize],
+            0x4000..=0x7fff => self.read_via_rom_bank(addr),
+            0xa000..=0xbfff => self.read_via_ram_bank(addr),
+            _ => 0x00,
+        }
+    }
+
+    /// ### 0000-1FFF - RAM Enable (Write Only)
+    /// Mostly the same as for MBC1. Writing $0A will enable reading and writing to external RAM.
+    /// Writing $00 will disable it.
+    ///
+    /// ### 2000-2FFF - 8 least significant bits of ROM bank number (Write Only)
+    /// The 8 least significant bits of the ROM bank number go here. Writing 0 will indeed give bank 0 on MBC5,
+    /// unlike other MBCs.
+    ///
+    /// ### 3000-3FFF - 9th bit of ROM bank number (Write Only)
+    /// The 9th bit of the ROM bank number goes here.
+    ///
+    /// ### 4000-5FFF - RAM bank number (Write Only)
+    /// Writing a value in range for $00-$0F maps the corresponding external RAM Bank (if any) into memory at A000-BFFF.
+    /// On the rumble carts the bit 3 turns the motor on and off instead.
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1fff => {
+                self.ram_enabled = value == 0x0a;
+            }
+            0x2000..=0x2fff => {
+                self.rom_bank = (self.rom_bank & 0x100) | usize::from(value);
+            }
+            0x3000..=0x3fff => {
+                self.rom_bank = (self.rom_bank & 0x0ff) | (usize::from(value & 0x01) << 8);
+            }
+            0x4000..=0x5fff => {
+                let mask = if self.has_rumble { 0x07 } else { 0x0f };
+                self.ram_bank = usize::from(value & mask);
+                if self.has_rumble {
+                    self.rumble = value & 0x08 != 0;
+                    self.rumble_ran |= self.rumble;
+                }
+            }
+            0xa000..=0xbfff => self.write_via_ram_bank(addr, value),
+            _ => {}
+        }
+    }
+}
+
+impl Stateful for MBC5 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ram.save_state(w, &self.meta);
+        w.put_u16(self.rom_bank as u16);
+        w.put_u8(self.ram_bank as u8);
+        w.put_bool(self.ram_enabled);
+    }
+
+    /// The motor isn't saved, it stops until the game writes the bank number again.
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ram.load_state(r, &self.meta)?;
+        self.rom_bank = usize::from(r.get_u16()? & 0x01ff);
+        self.ram_bank = usize::from(r.get_u8()? & 0x0f);
+        self.ram_enabled = r.get_bool()?;
+        self.rumble = false;
+        Ok(())
+    }
+}
+
+impl Cartridge for MBC5 {
+    fn get_meta(&self) -> CartridgeMeta {
+        self.meta.clone()
+    }
+
+    fn get_mapper_state(&self) -> MapperState {
+        MapperState {
+            rom_bank: self.get_rom_bank_num(),
+            ram_bank: self.get_ram_bank_num(),
+            ram_enabled: self.ram_enabled,
+            rtc_latched: None,
+        }
+    }
+
+    fn poll_battery(&mut self, now: Instant) {
+        self.ram.poll(now);
+    }
+
+    fn set_battery_delay(&mut self, delay: Duration) {
+        self.ram.set_delay(delay);
+    }
+
+    fn get_battery_stats(&self) -> Option<BatteryStats> {
+        self.ram.get_stats()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.ram.get_save_data(&self.meta)
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram.load_save_data(&self.meta, data);
+    }
+
+    fn take_rumble(&mut self) -> bool {
+        let ran = self.rumble_ran || self.rumble;
+        self.rumble_ran = false;
+        ran
+    }
+}
@@ -0,0 +1,221 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::BatteryRam;
+use super::BatteryStats;
+use super::Cartridge;
+use super::CartridgeMeta;
+use super::IOHandler;
+use super::MapperState;
+use super::MemoryBank;
+use super::{StateError, StateReader, StateWriter, Stateful};
+
+/// The MMM01 is the mapper of the multicarts, the menu is in the last 32KByte of the ROM and
+/// boots first. The menu writes the upper bits of the banks of the chosen game and how many of
+/// the lower bits the game may switch, then it maps the game which runs like on a MBC1 inside
+/// its own part of the ROM and the RAM. The mapping is locked until the power off.
+///
+/// The MBC1 banking mode and the multiplex bit of 6000-7FFF are not emulated.
+/// See: https://gbdev.io/pandocs/MMM01.html
+#[derive(Debug)]
+pub struct MMM01 {
+    meta: CartridgeMeta,
+    rom: Vec<u8>,
+    ram: BatteryRam,
+    // The game is mapped, the menu can't write the upper bits and the masks anymore.
+    mapped: bool,
+    // The 9 bits ROM bank number: bit 7-8 high, bit 5-6 mid and bit 0-4 low.
+    rom_bank: usize,
+    // The bits of the ROM bank low the game switches, the others are fixed by the menu.
+    rom_bank_mask: usize,
+    // The 4 bits RAM bank number, the game switches the bits of the mask.
+    ram_bank: usize,
+    ram_bank_mask: usize,
+    ram_enabled: bool,
+}
+
+impl MMM01 {
+    /// Returns a new MMM01 chip.
+    pub fn new(meta: CartridgeMeta, rom: Vec<u8>, ram: Vec<u8>, sav: impl AsRef<Path>) -> Self {
+        let ram = BatteryRam::new(ram, sav);
+        Self {
+            meta,
+            rom,
+            ram,
+            mapped: false,
+            rom_bank: 0x00,
+            rom_bank_mask: 0x1f,
+            ram_bank: 0x00,
+            ram_bank_mask: 0x03,
+            ram_enabled: false,
+        }
+    }
+
+    /// Returns the bank mapped at 0000-3FFF, the menu is in the last 2 banks before the mapping.
+    fn get_rom0_bank_num(&self) -> usize {
+        if self.mapped {
+            self.rom_bank & !self.rom_bank_mask
+        } else {
+            0x1fe
+        }
+    }
+
+    fn get_rom_addr(&self, bank: usize, addr: u16) -> usize {
+        (0x4000 * bank + (addr as usize & 0x3fff)) % self.rom.len()
+    }
+
+    fn get_ram_index(&self, addr: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        Some((self.ram_bank * 0x2000 + addr as usize - 0xa000) % self.ram.len())
+    }
+}
+
+impl MemoryBank for MMM01 {
+    /// The game's bank 0 is translated to 1 like the MBC1.
+    fn get_rom_bank_num(&self) -> usize {
+        if !self.mapped {
+            0x1ff
+        } else if self.rom_bank & self.rom_bank_mask == 0 {
+            self.rom_bank | 0x01
+        } else {
+            self.rom_bank
+        }
+    }
+
+    fn get_ram_bank_num(&self) -> usize {
+        self.ram_bank
+    }
+
+    fn read_via_rom_bank(&self, addr: u16) -> u8 {
+        self.rom[self.get_rom_addr(self.get_rom_bank_num(), addr)]
+    }
+
+    fn read_via_ram_bank(&self, addr: u16) -> u8 {
+        self.get_ram_index(addr).map_or(0xff, |i| self.ram[i])
+    }
+
+    fn write_via_ram_bank(&mut self, addr: u16, value: u8) {
+        if let Some(i) = self.get_ram_index(addr) {
+            self.ram.write(i, value);
+        }
+    }
+}
+
+impl IOHandler for MMM01 {
+    fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => self.rom[self.get_rom_addr(self.get_rom0_bank_num(), addr)],
+            0x4000..=0x7fff => self.read_via_rom_bank(addr),
+            0xa000..=0xbfff => self.read_via_ram_bank(addr),
+            _ => 0x00,
+        }
+    }
+
+    /// The bits written only before the mapping are marked with *.
+    ///
+    /// ### 0000-1FFF - RAM Enable (Write Only)
+    /// Bit 0-3 enables the RAM with 0Ah, bit 4-5* fix the bits of the RAM bank number and bit 6*
+    /// maps the game.
+    ///
+    /// ### 2000-3FFF - ROM Bank Number (Write Only)
+    /// Bit 0-4 is the ROM bank low, bit 5-6* is the ROM bank mid.
+    ///
+    /// ### 4000-5FFF - RAM Bank Number (Write Only)
+    /// Bit 0-1 is the RAM bank low, bit 2-3* is the RAM bank high and bit 4-5* is the ROM bank high.
+    ///
+    /// ### 6000-7FFF - Mode Select (Write Only)
+    /// Bit 2-5* fix the bit 1-4 of the ROM bank low.
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        let v = usize::from(value);
+        match addr {
+            0x0000..=0x1fff => {
+                self.ram_enabled = value & 0x0f == 0x0a;
+                if !self.mapped {
+                    self.ram_bank_mask = 0x03 & !(v >> 4);
+                    self.mapped = value & 0x40 != 0;
+                }
+            }
+            0x2000..=0x3fff => {
+                let mask = if self.mapped {
+                    self.rom_bank_mask
+                } else {
+                    0x7f
+                };
+                self.rom_bank = self.rom_bank & !mask | v & mask;
+            }
+            0x4000..=0x5fff => {
+                let mask = self.ram_bank_mask;
+                self.ram_bank = self.ram_bank & !mask | v & mask;
+                if !self.mapped {
+                    self.ram_bank = self.ram_bank & 0x03 | v & 0x0c;
+                    self.rom_bank = self.rom_bank & 0x7f | (v & 0x30) << 3;
+                }
+            }
+            0x6000..=0x7fff if !self.mapped => {
+                self.rom_bank_mask = 0x1f & !((v & 0x3c) >> 1);
+            }
+            0xa000..=0xbfff => self.write_via_ram_bank(addr, value),
+            _ => {}
+        }
+    }
+}
+
+impl Stateful for MMM01 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ram.save_state(w, &self.meta);
+        w.put_bool(self.mapped);
+        w.put_u16(self.rom_bank as u16);
+        w.put_u8(self.rom_bank_mask as u8);
+        w.put_u8(self.ram_bank as u8);
+        w.put_u8(self.ram_bank_mask as u8);
+        w.put_bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ram.load_state(r, &self.meta)?;
+        self.mapped = r.get_bool()?;
+        self.rom_bank = usize::from(r.get_u16()? & 0x01ff);
+        self.rom_bank_mask = usize::from(r.get_u8()? & 0x1f);
+        self.ram_bank = usize::from(r.get_u8()? & 0x0f);
+        self.ram_bank_mask = usize::from(r.get_u8()? & 0x03);
+        self.ram_enabled = r.get_bool()?;
+        Ok(())
+    }
+}
+
+impl Cartridge for MMM01 {
+    fn get_meta(&self) -> CartridgeMeta {
+        self.meta.clone()
+    }
+
+    fn get_mapper_state(&self) -> MapperState {
+        MapperState {
+            rom_bank: self.get_rom_bank_num() % (self.rom.len() / 0x4000).max(1),
+            ram_bank: self.get_ram_bank_num(),
+            ram_enabled: self.ram_enabled,
+            rtc_latched: None,
+        }
+    }
+
+    fn poll_battery(&mut self, now: Instant) {
+        self.ram.poll(now);
+    }
+
+    fn set_battery_delay(&mut self, delay: Duration) {
+        self.ram.set_delay(delay);
+    }
+
+    fn get_battery_stats(&self) -> Option<BatteryStats> {
+        self.ram.get_stats()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.ram.get_save_data(&self.meta)
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram.load_save_data(&self.meta, data);
+    }
+}
@@ -1,13 +1,25 @@
 use super::bank::BankMode;
 use super::bank::MemoryBank;
+use super::battery::BatteryRam;
+use super::battery::BatteryStats;
+use super::camera::CameraImage;
 use super::meta::CartridgeMeta;
+use super::meta::Mbc1Wiring;
 use super::rtc::RealTimeClock;
+use super::rtc::RtcState;
 use super::Cartridge;
+use super::MapperState;
 use super::IOHandler;
+use super::{load_ram_state, save_ram_state};
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
 
+pub mod huc1;
 pub mod mbc1;
 pub mod mbc2;
 pub mod mbc3;
+pub mod mbc5;
+pub mod mmm01;
+pub mod pocket_camera;
 pub mod rom_only;
 
 pub use mbc1::MBC1;
@@ -0,0 +1,238 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::BatteryRam;
+use super::BatteryStats;
+use super::CameraImage;
+use super::Cartridge;
+use super::CartridgeMeta;
+use super::IOHandler;
+use super::MapperState;
+use super::MemoryBank;
+use super::{StateError, StateReader, StateWriter, Stateful};
+use crate::gameboy::cartridge::camera::{CAMERA_H, CAMERA_W};
+
+// A000 is the capture control, A001-A035 set up the sensor and the dithering.
+const REGS_LEN: usize = 0x36;
+const REG_CONTROL: usize = 0x00;
+const REG_EXPOSURE: usize = 0x02;
+const REG_INVERT: usize = 0x04;
+const REG_MATRIX: usize = 0x06;
+// The captured image is written in the tiles at A100 of the RAM bank 0.
+const IMAGE_ADDR: usize = 0x0100;
+
+/// The Pocket Camera (Game Boy Camera) banks 1MByte ROM (64 banks) and 128KByte RAM (16 banks).
+/// The RAM bank 10h maps the registers of the sensor, a capture turns the image from the user
+/// into the dithered tiles the camera roll is made of. The capture is finished at once, the
+/// busy bit is clear the next time it's read.
+#[derive(Debug)]
+pub struct PocketCamera {
+    meta: CartridgeMeta,
+    rom: Vec<u8>,
+    ram: BatteryRam,
+    rom_bank: usize,
+    ram_bank: usize,
+    ram_enable: bool,
+    regs: [u8; REGS_LEN],
+    image: CameraImage,
+}
+
+impl PocketCamera {
+    /// Returns a new Pocket Camera, it sees the grey image until `set_camera_image`.
+    pub fn new(meta: CartridgeMeta, rom: Vec<u8>, ram: Vec<u8>, sav: impl AsRef<Path>) -> Self {
+        let ram = BatteryRam::new(ram, sav);
+        Self {
+            meta,
+            rom,
+            ram,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enable: false,
+            regs: [0x00; REGS_LEN],
+            image: CameraImage::blank(0x80),
+        }
+    }
+
+    fn is_regs_mapped(&self) -> bool {
+        self.ram_bank & 0x10 != 0
+    }
+
+    fn get_ram_index(&self, addr: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        Some((self.ram_bank * 0x2000 + addr as usize - 0xa000) % self.ram.len())
+    }
+
+    /// Returns the color 0-3 of the pixel, the exposure scales the light and the thresholds of
+    /// the 4x4 matrix dither it.
+    fn get_color(&self, x: usize, y: usize) -> u8 {
+        let exposure =
+            u32::from(self.regs[REG_EXPOSURE]) << 8 | u32::from(self.regs[REG_EXPOSURE + 1]);
+        let mut light = (u32::from(self.image.get(x, y)) * exposure / 0x1000).min(0xff) as u8;
+        if self.regs[REG_INVERT] & 0x08 != 0 {
+            light = !light;
+        }
+        let m = REG_MATRIX + ((y & 3) * 4 + (x & 3)) * 3;
+        match &self.regs[m..m + 3] {
+            [t, _, _] if light < *t => 3,
+            [_, t, _] if light < *t => 2,
+            [_, _, t] if light < *t => 1,
+            _ => 0,
+        }
+    }
+
+    /// Write the captured image into the RAM bank 0, 16x14 tiles of 2 bits per pixel.
+    fn capture(&mut self) {
+        if self.ram.len() < IMAGE_ADDR + CAMERA_W * CAMERA_H / 4 {
+            return;
+        }
+        for y in 0..CAMERA_H {
+            for x in (0..CAMERA_W).step_by(8) {
+                let (mut lo, mut hi) = (0u8, 0u8);
+                for i in 0..8 {
+                    let color = self.get_color(x + i, y);
+                    lo |= (color & 0x01) << (7 - i);
+                    hi |= (color >> 1) << (7 - i);
+                }
+                let tile = (y / 8) * (CAMERA_W / 8) + x / 8;
+                let i = IMAGE_ADDR + tile * 16 + (y % 8) * 2;
+                self.ram.write(i, lo);
+                self.ram.write(i + 1, hi);
+            }
+        }
+    }
+}
+
+impl MemoryBank for PocketCamera {
+    fn get_rom_bank_num(&self) -> usize {
+        self.rom_bank
+    }
+
+    fn get_ram_bank_num(&self) -> usize {
+        self.ram_bank
+    }
+
+    fn read_via_rom_bank(&self, addr: u16) -> u8 {
+        let bank_addr = 0x4000 * self.get_rom_bank_num() + (addr as usize - 0x4000);
+        self.rom[bank_addr % self.rom.len()]
+    }
+
+    /// Only A000 of the registers can be read, the capture is never busy. The RAM is readable
+    /// even if it's not enabled.
+    fn read_via_ram_bank(&self, addr: u16) -> u8 {
+        if self.is_regs_mapped() {
+            return match (addr as usize - 0xa000) % 0x80 {
+                REG_CONTROL => self.regs[REG_CONTROL] & 0x06,
+                _ => 0x00,
+            };
+        }
+        self.get_ram_index(addr).map_or(0xff, |i| self.ram[i])
+    }
+
+    fn write_via_ram_bank(&mut self, addr: u16, value: u8) {
+        if self.is_regs_mapped() {
+            let reg = (addr as usize - 0xa000) % 0x80;
+            if reg < REGS_LEN {
+                self.regs[reg] = value;
+            }
+            if reg == REG_CONTROL && value & 0x01 != 0 {
+                self.capture();
+            }
+            return;
+        }
+        if !self.ram_enable {
+            return;
+        }
+        if let Some(i) = self.get_ram_index(addr) {
+            self.ram.write(i, value);
+        }
+    }
+}
+
+impl IOHandler for PocketCamera {
+    fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3fff => self.rom[addr as usize],
+            0x4000..=0x7fff => self.read_via_rom_bank(addr),
+            0xa000..=0xbfff => self.read_via_ram_bank(addr),
+            _ => 0x00,
+        }
+    }
+
+    /// ### 0000-1FFF - RAM Enable (Write Only)
+    /// 0Ah enables the writes to the RAM, the registers are always writable.
+    ///
+    /// ### 2000-3FFF - ROM Bank Number (Write Only)
+    /// The 6 bits ROM bank number, the bank 00h can be mapped at 4000-7FFF.
+    ///
+    /// ### 4000-5FFF - RAM Bank Number (Write Only)
+    /// Selects the RAM bank 00-0Fh, the bit 4 maps the camera registers instead.
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1fff => self.ram_enable = value & 0x0f == 0x0a,
+            0x2000..=0x3fff => self.rom_bank = usize::from(value & 0x3f),
+            0x4000..=0x5fff => self.ram_bank = usize::from(value & 0x1f),
+            0xa000..=0xbfff => self.write_via_ram_bank(addr, value),
+            _ => {}
+        }
+    }
+}
+
+impl Stateful for PocketCamera {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.ram.save_state(w, &self.meta);
+        w.put_u8(self.rom_bank as u8);
+        w.put_u8(self.ram_bank as u8);
+        w.put_bool(self.ram_enable);
+        w.put_bytes(&self.regs);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ram.load_state(r, &self.meta)?;
+        self.rom_bank = usize::from(r.get_u8()? & 0x3f);
+        self.ram_bank = usize::from(r.get_u8()? & 0x1f);
+        self.ram_enable = r.get_bool()?;
+        r.fill(&mut self.regs)?;
+        Ok(())
+    }
+}
+
+impl Cartridge for PocketCamera {
+    fn get_meta(&self) -> CartridgeMeta {
+        self.meta.clone()
+    }
+
+    fn get_mapper_state(&self) -> MapperState {
+        MapperState {
+            rom_bank: self.get_rom_bank_num(),
+            ram_bank: self.get_ram_bank_num(),
+            ram_enabled: self.ram_enable,
+            rtc_latched: None,
+        }
+    }
+
+    fn set_camera_image(&mut self, image: CameraImage) {
+        self.image = image;
+    }
+
+    fn poll_battery(&mut self, now: Instant) {
+        self.ram.poll(now);
+    }
+
+    fn set_battery_delay(&mut self, delay: Duration) {
+        self.ram.set_delay(delay);
+    }
+
+    fn get_battery_stats(&self) -> Option<BatteryStats> {
+        self.ram.get_stats()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.ram.get_save_data(&self.meta)
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram.load_save_data(&self.meta, data);
+    }
+}
@@ -1,10 +1,13 @@
+#[cfg(feature = "native")]
 use backtrace::Backtrace;
 use log::error;
-use log::info;
 
 use super::Cartridge;
 use super::CartridgeMeta;
 use super::IOHandler;
+use super::MapperState;
+use super::{load_ram_state, save_ram_state};
+use super::{StateError, StateReader, StateWriter, Stateful};
 
 /// Small games of not more than 32KBytes ROM do not require a MBC chip for ROM banking.
 /// The ROM is directly mapped to memory at 0000-7FFFh.
@@ -25,13 +28,26 @@ impl RomOnly {
 impl IOHandler for RomOnly {
     /// Read a byte from address.
     fn read_byte(&self, a: u16) -> u8 {
-        self.rom[a as usize]
+        // There is no ram at A000-BFFF, it reads as open bus.
+        self.rom.get(a as usize).copied().unwrap_or(0xff)
     }
 
     /// Write a byte to address.
     fn write_byte(&mut self, _: u16, _: u8) {
-        let bt = Backtrace::new();
-        error!("Rom-Only cartridge is not writable {:?}", bt);
+        #[cfg(feature = "native")]
+        error!("Rom-Only cartridge is not writable {:?}", Backtrace::new());
+        #[cfg(not(feature = "native"))]
+        error!("Rom-Only cartridge is not writable");
+    }
+}
+
+impl Stateful for RomOnly {
+    fn save_state(&self, w: &mut StateWriter) {
+        save_ram_state(w, &self.meta, &[]);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        load_ram_state(r, &self.meta, &mut [])
     }
 }
 
@@ -39,10 +55,13 @@ impl Cartridge for RomOnly {
     fn get_meta(&self) -> CartridgeMeta {
         self.meta.clone()
     }
-}
 
-impl Drop for RomOnly {
-    fn drop(&mut self) {
-        info!("save success when drop the cartridge object.")
+    fn get_mapper_state(&self) -> MapperState {
+        MapperState {
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            rtc_latched: None,
+        }
     }
 }
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+use super::meta::{CartridgeMeta, CartridgePlatform, CartridgeRegion, CartridgeType};
+
+/// The live registers of the memory bank controller.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MapperState {
+    /// The rom bank mapped at 4000-7FFF.
+    pub rom_bank: usize,
+    /// The ram bank mapped at A000-BFFF, MBC3 uses 08-0C for the clock registers.
+    pub ram_bank: usize,
+    pub ram_enabled: bool,
+    /// Whether the clock registers are latched, `None` if there is no real time clock.
+    pub rtc_latched: Option<bool>,
+}
+
+/// The cartridge header and the mapper state. It's the supported surface for the frontends and
+/// the tools to inspect the inserted cartridge, instead of downcasting `Box<dyn Cartridge>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CartridgeInfo {
+    pub title: String,
+    pub r#type: CartridgeType,
+    pub platform: CartridgePlatform,
+    pub region: CartridgeRegion,
+    pub licensee: String,
+    pub support_sgb: bool,
+    /// The rom and ram size in byte.
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub has_battery: bool,
+    pub mapper: MapperState,
+}
+
+impl CartridgeInfo {
+    pub fn new(meta: &CartridgeMeta, mapper: MapperState, has_battery: bool) -> Self {
+        Self {
+            title: meta.get_title(),
+            r#type: meta.get_type(),
+            platform: meta.get_platform(),
+            region: meta.get_region(),
+            licensee: meta.get_licensee(),
+            support_sgb: meta.support_sgb(),
+            rom_size: meta.get_rom_size(),
+            ram_size: meta.get_ram_size(),
+            has_battery,
+            mapper,
+        }
+    }
+}
@@ -3,8 +3,14 @@
 use std::array::IntoIter;
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::str::FromStr;
 use std::string::ToString;
 
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::error::CartridgeError;
+
 // See
 // - https://gbdev.gg8.se/wiki/articles/The_Cartridge_Header
 // - http://gameboy.mongenel.com/dmg/asmmemmap.html
@@ -116,9 +122,80 @@ lazy_static! {
     };
 }
 
+/// The cartridge header is located at 0x0100-0x014f.
+pub const HEADER_END: usize = 0x0150;
+
+/// The logo at 0x0104-0x0133, the boot rom locks up if it doesn't match.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e, 0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+/// What to do with a rom whose logo or header checksum is wrong, the real hardware refuses it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeaderCheck {
+    /// Log a warning and boot it anyway, many homebrew roms and hacks get the header wrong.
+    Warn,
+    /// Refuse to boot it.
+    Strict,
+}
+
+impl FromStr for HeaderCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "strict" => Ok(Self::Strict),
+            _ => Err(format!("unknown header check mode {}", s)),
+        }
+    }
+}
+
+/// How the MBC1 is wired to the rom. The multicarts (MBC1M) pack 4 games of 256KB into 1MB, their
+/// two bit register is the bit 4-5 of the rom bank rather than 5-6, and the bit 4 of the first
+/// register is not connected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mbc1Wiring {
+    Normal,
+    Multicart,
+}
+
+impl Mbc1Wiring {
+    /// Guess the wiring from the rom, a multicart has the header of the second game at the bank
+    /// 0x10 with the logo.
+    pub fn detect(rom: &[u8]) -> Self {
+        const GAME_SIZE: usize = 0x40000;
+        match rom.get(GAME_SIZE + 0x0104..GAME_SIZE + 0x0134) {
+            Some(logo) if rom.len() == 4 * GAME_SIZE && logo == NINTENDO_LOGO => Self::Multicart,
+            _ => Self::Normal,
+        }
+    }
+}
+
+impl FromStr for Mbc1Wiring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Self::Normal),
+            "multicart" => Ok(Self::Multicart),
+            _ => Err(format!("unknown MBC1 wiring {}", s)),
+        }
+    }
+}
+
+/// Returns the complement checksum of 0x0134-0x014C, the boot rom compares it with 0x014D.
+fn header_checksum(data: &[u8]) -> u8 {
+    data[0x0134..=0x014c]
+        .iter()
+        .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1))
+}
+
 /// Catrtridge Type, see this link https://gbdev.gg8.se/wiki/articles/The_Cartridge_Header.
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum CartridgeType {
     ROM_ONLY,
     ROM_MBC1,
@@ -149,6 +226,26 @@ pub enum CartridgeType {
     ROM_HUC1,
 }
 
+impl CartridgeType {
+    /// Returns true if the ram or the clock is kept by a battery while the power is off.
+    pub fn has_battery(self) -> bool {
+        matches!(
+            self,
+            Self::ROM_MBC1_RAM_BATT
+                | Self::ROM_MBC2_BATT
+                | Self::ROM_MMM01_RAM_BATT
+                | Self::ROM_MBC3_TIMER_BATT
+                | Self::ROM_MBC3_TIMER_RAM_BATT
+                | Self::ROM_MBC3_RAM_BATT
+                | Self::ROM_MBC5_RAM_BATT
+                | Self::ROM_MBC5_RUMBLE_RAM_BATT
+                | Self::ROM_MBC7_BATT
+                | Self::ROM_POCKET_CAMERA
+                | Self::ROM_HUC1
+        )
+    }
+}
+
 // #[derive(Debug, Copy, Clone)]
 // pub enum CartridgeFeature {
 //     WithRAM,
@@ -158,14 +255,14 @@ pub enum CartridgeType {
 
 /// Catrtridge Region, see this link https://gbdev.gg8.se/wiki/articles/The_Cartridge_Header.
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum CartridgeRegion {
     JP,
     NON_JP,
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum CartridgePlatform {
     // Game Boy Color, works on old gameboys also
     GBC,
@@ -188,6 +285,8 @@ pub struct CartridgeMeta {
     support_sgb: bool,
     licensee: String,
     platform: CartridgePlatform,
+    logo_valid: bool,
+    header_checksum_valid: bool,
 }
 
 impl CartridgeMeta {
@@ -210,9 +309,9 @@ impl CartridgeMeta {
     /// 12h  MBC3+RAM                 FEh  HuC3
     /// 13h  MBC3+RAM+BATTERY         FFh  HuC1+RAM+BATTERY
     /// ```
-    fn parse_type(data: &Vec<u8>) -> CartridgeType {
+    fn parse_type(data: &[u8]) -> Result<CartridgeType, CartridgeError> {
         let t = data[0x0147];
-        match t {
+        let r#type = match t {
             0x00 => CartridgeType::ROM_ONLY,
             0x01 => CartridgeType::ROM_MBC1,
             0x02 => CartridgeType::ROM_MBC1_RAM,
@@ -240,8 +339,9 @@ impl CartridgeMeta {
             0xfd => CartridgeType::ROM_BANDAI_TAMA5,
             0xfe => CartridgeType::ROM_HUC3,
             0xff => CartridgeType::ROM_HUC1,
-            _ => panic!("invalie cartridge type {}", t),
-        }
+            _ => return Err(CartridgeError::UnknownType(t)),
+        };
+        Ok(r#type)
     }
 
     /// Parse the rom size field from the cartridge header.
@@ -259,9 +359,9 @@ impl CartridgeMeta {
     /// 0x53 - 1.2MByte (80 banks)
     /// 0x54 - 1.5MByte (96 banks)
     /// ```
-    fn parse_rom_size(data: &Vec<u8>) -> usize {
+    fn parse_rom_size(data: &[u8]) -> Result<usize, CartridgeError> {
         let bank = 16384;
-        match data[0x0148] {
+        let size = match data[0x0148] {
             0x00 => bank * 2,
             0x01 => bank * 4,
             0x02 => bank * 8,
@@ -274,8 +374,9 @@ impl CartridgeMeta {
             0x52 => bank * 72,
             0x53 => bank * 80,
             0x54 => bank * 96,
-            n => panic!("Unsupported rom size: 0x{:02x}", n),
-        }
+            n => return Err(CartridgeError::UnsupportedRomSize(n)),
+        };
+        Ok(size)
     }
 
     /// Parse the ram size field from the cartridge header.
@@ -287,16 +388,17 @@ impl CartridgeMeta {
     /// 04h - 128 KBytes (16 banks of 8KBytes each)
     /// 05h - 64 KBytes (8 banks of 8KBytes each)
     /// ```
-    fn parse_ram_size(data: &Vec<u8>) -> usize {
-        match data[0x0149] {
+    fn parse_ram_size(data: &[u8]) -> Result<usize, CartridgeError> {
+        let size = match data[0x0149] {
             0x00 => 0,
             0x01 => 1024 * 2,
             0x02 => 1024 * 8,
             0x03 => 1024 * 32,
             0x04 => 1024 * 128,
             0x05 => 1024 * 64,
-            n => panic!("Unsupported ram size: 0x{:02x}", n),
-        }
+            n => return Err(CartridgeError::UnsupportedRamSize(n)),
+        };
+        Ok(size)
     }
 
     /// Parse the title field from the cartridge header.
@@ -306,7 +408,7 @@ impl CartridgeMeta {
     /// When inventing the CGB, Nintendo has reduced the length of this area to 15 characters,
     /// and some months later they had the fantastic idea to reduce it to 11 characters only.
     /// The new meaning of the ex-title bytes is described below.
-    fn parse_title(data: &Vec<u8>) -> String {
+    fn parse_title(data: &[u8]) -> String {
         let mut name = String::new();
         let lower = 0x0134;
         let upper = 0x0143;
@@ -341,7 +443,7 @@ impl CartridgeMeta {
     /// 03h = Game supports SGB functions
     /// ```
     /// The SGB disables its SGB functions if this byte is set to another value than 03h.
-    fn parse_platform(data: &Vec<u8>) -> CartridgePlatform {
+    fn parse_platform(data: &[u8]) -> CartridgePlatform {
         if data[0x0146] == 0x03 {
             return CartridgePlatform::SGB;
         } else if data[0x0143] == 0x80 {
@@ -357,7 +459,7 @@ impl CartridgeMeta {
     /// Specifies if this version of the game is supposed to be sold in Japan, or anywhere else. Only two values are defined.
     /// 00h - Japanese
     /// 01h - Non-Japanese
-    fn parse_region(data: &Vec<u8>) -> CartridgeRegion {
+    fn parse_region(data: &[u8]) -> CartridgeRegion {
         if data[0x014A] == 0 {
             CartridgeRegion::JP
         } else {
@@ -366,7 +468,7 @@ impl CartridgeMeta {
     }
 
     /// Parse the SGB Flag.
-    fn parse_sgb_flag(data: &Vec<u8>) -> bool {
+    fn parse_sgb_flag(data: &[u8]) -> bool {
         data[0x0146] != 0
     }
 
@@ -375,7 +477,7 @@ impl CartridgeMeta {
     /// Specifies a two character ASCII licensee code, indicating the company or publisher of the game.
     /// These two bytes are used in newer games only (games that have been released after the SGB has been invented).
     /// Older games are using the header entry at 014B instead.
-    fn parse_licensee(data: &Vec<u8>) -> String {
+    fn parse_licensee(data: &[u8]) -> String {
         // See the Python Code: https://github.com/garbear/pyrominfo/blob/9c3b94482c2eed335858535633c22bfa71e14c45/pyrominfo/gameboy.py
         // # 0144-0145 - New Licenseee Code, two character ASCII licenseee code
         // # 014B - Old Licenseee Code in range 00-FF, value of 33h signals New Licensee Code is used instead
@@ -441,21 +543,55 @@ impl CartridgeMeta {
     pub fn get_platform(&self) -> CartridgePlatform {
         self.platform
     }
+
+    /// Returns whether the logo matches `NINTENDO_LOGO`.
+    pub fn is_logo_valid(&self) -> bool {
+        self.logo_valid
+    }
+
+    /// Returns whether the header checksum at 0x014D is right.
+    pub fn is_header_checksum_valid(&self) -> bool {
+        self.header_checksum_valid
+    }
+
+    /// Returns the error if the header is corrupted and the check is strict, or logs it.
+    pub fn verify(&self, check: HeaderCheck) -> Result<(), CartridgeError> {
+        let err = if !self.logo_valid {
+            CartridgeError::BadLogo
+        } else if !self.header_checksum_valid {
+            CartridgeError::BadHeaderChecksum
+        } else {
+            return Ok(());
+        };
+        match check {
+            HeaderCheck::Warn => {
+                warn!("The rom {} may be corrupted: {}", self.title, err);
+                Ok(())
+            }
+            HeaderCheck::Strict => Err(err),
+        }
+    }
 }
 
 impl CartridgeMeta {
     /// Parse the cartridge header and return the meta info struct.
-    pub fn new(data: &Vec<u8>) -> Self {
+    /// The data must contain the whole header, the rest of the rom is not required.
+    pub fn new(data: &[u8]) -> Result<Self, CartridgeError> {
+        if data.len() < HEADER_END {
+            return Err(CartridgeError::TooSmall(data.len()));
+        }
         let title = Self::parse_title(data);
-        let rom_size = Self::parse_rom_size(data);
-        let ram_size = Self::parse_ram_size(data);
-        let r#type = Self::parse_type(data);
+        let rom_size = Self::parse_rom_size(data)?;
+        let ram_size = Self::parse_ram_size(data)?;
+        let r#type = Self::parse_type(data)?;
         let region = Self::parse_region(data);
         let support_sgb = Self::parse_sgb_flag(data);
         let licensee = Self::parse_licensee(data);
         let platform = Self::parse_platform(data);
+        let logo_valid = data[0x0104..0x0134] == NINTENDO_LOGO;
+        let header_checksum_valid = header_checksum(data) == data[0x014d];
 
-        Self {
+        Ok(Self {
             title,
             rom_size,
             ram_size,
@@ -464,6 +600,161 @@ impl CartridgeMeta {
             support_sgb,
             licensee,
             platform,
+            logo_valid,
+            header_checksum_valid,
+        })
+    }
+}
+
+impl CartridgeMeta {
+    /// Returns a human readable summary of the header, used by the `--info` flag.
+    pub fn summary(&self) -> String {
+        let cgb = match self.platform {
+            CartridgePlatform::GBC => "supported",
+            CartridgePlatform::GBC_ONLY => "required",
+            _ => "no",
+        };
+        [
+            format!("title: {}", self.title),
+            format!("type: {:?}", self.r#type),
+            format!("rom size: {} KB", self.rom_size / 1024),
+            format!("ram size: {} KB", self.ram_size / 1024),
+            format!("licensee: {}", self.licensee),
+            format!("region: {:?}", self.region),
+            format!("cgb: {}", cgb),
+            format!("sgb: {}", if self.support_sgb { "yes" } else { "no" }),
+            format!("logo: {}", if self.logo_valid { "ok" } else { "mismatch" }),
+        ]
+        .join("\n")
+    }
+}
+
+/// The checksums kept in the header, and the ones computed from the rom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Checksums {
+    pub header: u8,
+    pub computed_header: u8,
+    pub global: u16,
+    pub computed_global: u16,
+}
+
+impl Checksums {
+    /// The header checksum covers 0x0134-0x014C, the global checksum is the sum of all the rom
+    /// bytes except its own two bytes. The data must contain the whole header.
+    pub fn new(data: &[u8]) -> Result<Self, CartridgeError> {
+        if data.len() < HEADER_END {
+            return Err(CartridgeError::TooSmall(data.len()));
         }
+        let computed_header = header_checksum(data);
+        let computed_global = data
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 0x014e && *i != 0x014f)
+            .fold(0u16, |x, (_, b)| x.wrapping_add(u16::from(*b)));
+        Ok(Self {
+            header: data[0x014d],
+            computed_header,
+            global: u16::from_be_bytes([data[0x014e], data[0x014f]]),
+            computed_global,
+        })
+    }
+
+    /// The boot rom locks up if the header checksum is wrong.
+    pub fn is_header_valid(&self) -> bool {
+        self.header == self.computed_header
+    }
+
+    /// The global checksum is not checked by the hardware.
+    pub fn is_global_valid(&self) -> bool {
+        self.global == self.computed_global
+    }
+
+    pub fn summary(&self) -> String {
+        let header = if self.is_header_valid() {
+            "ok".to_string()
+        } else {
+            format!("mismatch, computed {:02x}", self.computed_header)
+        };
+        let global = if self.is_global_valid() {
+            "ok".to_string()
+        } else {
+            format!("mismatch, computed {:04x}", self.computed_global)
+        };
+        format!(
+            "header checksum: {:02x} {}\nglobal checksum: {:04x} {}",
+            self.header, header, self.global, global
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0134..0x0139].copy_from_slice(b"TETRA");
+        let checksums = Checksums::new(&rom).unwrap();
+        // 0 - sum(bytes) - 25
+        let sum = b"TETRA".iter().map(|b| u32::from(*b)).sum::<u32>() + 25;
+        assert_eq!(checksums.computed_header, (0x100 - sum % 0x100) as u8);
+        assert!(!checksums.is_header_valid());
+
+        rom[0x014d] = checksums.computed_header;
+        let sum = rom.iter().map(|b| u16::from(*b)).fold(0, u16::wrapping_add);
+        rom[0x014e..0x0150].copy_from_slice(&sum.to_be_bytes());
+        let checksums = Checksums::new(&rom).unwrap();
+        assert!(checksums.is_header_valid());
+        assert!(checksums.is_global_valid());
+        assert_eq!(
+            checksums.summary(),
+            format!(
+                "header checksum: {:02x} ok\nglobal checksum: {:04x} ok",
+                rom[0x014d], sum
+            )
+        );
+        assert!(Checksums::new(&rom[..0x0100]).is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0134..0x0139].copy_from_slice(b"TETRA");
+        rom[0x0143] = 0x80;
+        rom[0x0147] = 0x03;
+        rom[0x0148] = 0x01;
+        rom[0x0149] = 0x02;
+        let summary = CartridgeMeta::new(&rom).unwrap().summary();
+        assert!(summary.starts_with("title: TETRA\ntype: ROM_MBC1_RAM_BATT\nrom size: 64 KB"));
+        assert!(summary.contains("ram size: 8 KB"));
+        assert!(summary.contains("cgb: supported\nsgb: no"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut rom = vec![0x00; 0x8000];
+        let meta = CartridgeMeta::new(&rom).unwrap();
+        assert!(!meta.is_logo_valid());
+        assert!(meta.verify(HeaderCheck::Warn).is_ok());
+        assert!(matches!(
+            meta.verify(HeaderCheck::Strict),
+            Err(CartridgeError::BadLogo)
+        ));
+
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        let meta = CartridgeMeta::new(&rom).unwrap();
+        assert!(meta.is_logo_valid());
+        assert!(!meta.is_header_checksum_valid());
+        assert!(matches!(
+            meta.verify(HeaderCheck::Strict),
+            Err(CartridgeError::BadHeaderChecksum)
+        ));
+
+        rom[0x014d] = header_checksum(&rom);
+        let meta = CartridgeMeta::new(&rom).unwrap();
+        assert!(meta.is_header_checksum_valid());
+        assert!(meta.verify(HeaderCheck::Strict).is_ok());
+        assert!(meta.summary().ends_with("logo: ok"));
     }
 }
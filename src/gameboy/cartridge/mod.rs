@@ -1,25 +1,161 @@
+mod archive;
 mod bank;
+mod battery;
+mod camera;
+mod error;
 mod factory;
 mod r#impl;
+mod info;
 mod rtc;
 
 mod meta;
 
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
+pub use battery::{BatteryStats, DEFAULT_FLUSH_DELAY};
+pub use camera::CameraImage;
+pub use error::CartridgeError;
+pub use info::{CartridgeInfo, MapperState};
 use factory::CartridgeFactory;
 use log::info;
 pub use meta::*;
+pub use rtc::RtcState;
 
 use crate::gameboy::mmu::IOHandler;
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
 
-pub trait Cartridge: IOHandler + Send + Drop {
+/// The bank registers and the ram are saved into the snapshot, the rom is not.
+/// A cartridge with a save file flushes the pending writes when it's dropped.
+pub trait Cartridge: IOHandler + Stateful + Send {
     fn get_meta(&self) -> meta::CartridgeMeta;
+
+    /// Returns the live registers of the memory bank controller.
+    fn get_mapper_state(&self) -> MapperState;
+
+    /// Returns the header and the mapper state.
+    fn get_info(&self) -> CartridgeInfo {
+        let has_battery = self.get_battery_stats().is_some();
+        CartridgeInfo::new(&self.get_meta(), self.get_mapper_state(), has_battery)
+    }
+
+    /// Flush the battery backed ram if the game stopped writing long enough.
+    fn poll_battery(&mut self, _now: Instant) {}
+
+    /// Set how long the battery backed ram must stay untouched before it's flushed.
+    fn set_battery_delay(&mut self, _delay: Duration) {}
+
+    /// Returns the statistics of the battery backed ram, `None` if there is no battery.
+    fn get_battery_stats(&self) -> Option<BatteryStats> {
+        None
+    }
+
+    /// Show the image to the sensor of the Pocket Camera, the other cartridges ignore it.
+    fn set_camera_image(&mut self, _image: CameraImage) {}
+
+    /// Returns true if the rumble motor ran since the last call, the carts without a motor
+    /// never rumble.
+    fn take_rumble(&mut self) -> bool {
+        false
+    }
+
+    /// Returns the battery backed ram as the save file holds it, `None` if there is no battery or
+    /// no ram. A frontend without the files keeps the saves with it.
+    fn save_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Replace the battery backed ram with the save data, it's cut or padded with zero to the
+    /// size of the ram. The data is written to the save file at the next flush.
+    fn load_ram(&mut self, _data: &[u8]) {}
+
+    /// Returns the counter of the real time clock, `None` if there is no clock.
+    fn rtc_state(&self) -> Option<RtcState> {
+        None
+    }
+}
+
+/// Save the title and the ram, every mapper starts its snapshot section with them.
+fn save_ram_state(w: &mut StateWriter, meta: &CartridgeMeta, ram: &[u8]) {
+    let title = meta.get_title();
+    w.put_u8(title.len() as u8);
+    w.put_bytes(title.as_bytes());
+    w.put_u32(ram.len() as u32);
+    w.put_bytes(ram);
+}
+
+/// Restore the ram saved by `save_ram_state`, a snapshot of another game is refused.
+fn load_ram_state(
+    r: &mut StateReader,
+    meta: &CartridgeMeta,
+    ram: &mut [u8],
+) -> Result<(), StateError> {
+    let n = r.get_u8()? as usize;
+    let title = r.get_bytes(n)?;
+    if title != meta.get_title().as_bytes() || r.get_u32()? as usize != ram.len() {
+        return Err(StateError::WrongCartridge);
+    }
+    r.fill(ram)
 }
 
 // https://github.com/StarlitGhost/GBOxide
 
-pub fn load_cartridge_from_file(file_path: impl AsRef<Path>) -> Box<dyn Cartridge> {
+/// Returns the error if the rom can't be read, or its header is broken or not supported. The
+/// MBC1 wiring is detected from the rom without `mbc1`.
+pub fn load_cartridge_from_file(
+    file_path: impl AsRef<Path>,
+    check: HeaderCheck,
+    mbc1: Option<Mbc1Wiring>,
+) -> Result<Box<dyn Cartridge>, CartridgeError> {
     info!("Loading cartridge from {:?}", file_path.as_ref().to_str());
-    CartridgeFactory::new_catridge(file_path)
+    CartridgeFactory::new_catridge(&file_path, check, mbc1)
+}
+
+/// Returns the header of the rom file without reading the rest of the rom, a gzip file or a zip
+/// archive is extracted as a whole. The header of a MMM01 is the one of its first game.
+pub fn peek_header(file_path: impl AsRef<Path>) -> Result<CartridgeMeta, CartridgeError> {
+    let mut header = Vec::with_capacity(HEADER_END);
+    File::open(&file_path)?
+        .take(HEADER_END as u64)
+        .read_to_end(&mut header)?;
+    if archive::is_archive(&header) {
+        return CartridgeMeta::new(&read_rom(file_path)?);
+    }
+    CartridgeMeta::new(&header)
+}
+
+/// Returns the rom of the file, the file may be a gzip file or a zip archive of the rom.
+pub fn read_rom(file_path: impl AsRef<Path>) -> Result<Vec<u8>, CartridgeError> {
+    archive::extract_rom(std::fs::read(file_path)?)
+}
+
+/// Like `load_cartridge_from_file` with the rom read already, the save files are the save path
+/// with the `.sav` and `.rtc` extensions.
+pub fn load_cartridge_from_rom(
+    rom: Vec<u8>,
+    save_path: &Path,
+    check: HeaderCheck,
+    mbc1: Option<Mbc1Wiring>,
+) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    CartridgeMeta::new(&rom)?.verify(check)?;
+    CartridgeFactory::from_rom_with_wiring(rom, Some(save_path), mbc1)
+}
+
+/// Returns the error if the header of the rom data is broken or not supported, the battery is
+/// not persisted without a file. The data may be a gzip file or a zip archive of the rom.
+pub fn load_cartridge_from_bytes(
+    data: Vec<u8>,
+    check: HeaderCheck,
+    mbc1: Option<Mbc1Wiring>,
+) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    let rom = archive::extract_rom(data)?;
+    CartridgeMeta::new(&rom)?.verify(check)?;
+    CartridgeFactory::from_rom_with_wiring(rom, None, mbc1)
+}
+
+/// Returns a cartridge which is used when no game is inserted.
+pub fn load_empty_cartridge() -> Box<dyn Cartridge> {
+    CartridgeFactory::new_empty_catridge()
 }
@@ -3,6 +3,40 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::gameboy::clock;
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+
+const DAY: u64 = 24 * 3600;
+// The day counter has 9 bits, it overflows after 512 days.
+const DAYS_OVERFLOW: u64 = 512 * DAY;
+
+const HALT: u8 = 0x40;
+const CARRY: u8 = 0x80;
+
+/// Returns the wall time in seconds, 0 without a clock.
+fn now() -> u64 {
+    clock::wall_time()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The running counter of the clock, not the registers latched by the game.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RtcState {
+    /// The day counter, 0-511.
+    pub days: u16,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub halted: bool,
+    /// The day counter overflowed.
+    pub carry: bool,
+}
+
 /// The Clock Counter Registers
 ///  08h  RTC S   Seconds   0-59 (0-3Bh)
 ///  09h  RTC M   Minutes   0-59 (0-3Bh)
@@ -12,6 +46,19 @@ use std::time::SystemTime;
 ///        Bit 0  Most significant bit of Day Counter (Bit 8)
 ///        Bit 6  Halt (0=Active, 1=Stop Timer)
 ///        Bit 7  Day Counter Carry Bit (1=Counter Overflow)
+///
+/// The game reads the registers latched by writing 00h then 01h to 6000-7FFF, the counter keeps
+/// running behind them. A write goes to both of the counter and the latched register.
+///
+/// The counter is kept in the `.rtc` file beside the save, so the time of the game goes on while
+/// the emulator is closed:
+/// ```ignore
+/// Offset  Size  Description
+/// 0       8     The wall time in seconds when the counter was zero, big endian
+/// 8       8     The counter in seconds if it's halted, big endian
+/// 16      1     Bit 6 halt, bit 7 day counter carry
+/// ```
+/// The file of an older release only has the first 8 bytes.
 #[derive(Debug)]
 pub struct RealTimeClock {
     s: u8,
@@ -19,76 +66,155 @@ pub struct RealTimeClock {
     h: u8,
     dl: u8,
     dh: u8,
+    // The wall time when the counter was zero, it's moved when the counter is written.
     zero: u64,
+    // The counter stopped by the halt flag.
+    halted: Option<u64>,
+    carry: bool,
     sav_path: PathBuf,
-    is_locked: bool,
+    // The last value written to 6000-7FFF.
+    latch: u8,
 }
 
 impl RealTimeClock {
     pub fn new(sav_path: impl AsRef<Path>) -> Self {
-        let zero = match std::fs::read(sav_path.as_ref()) {
-            Ok(ok) => {
-                let mut b: [u8; 8] = Default::default();
-                b.copy_from_slice(&ok);
-                u64::from_be_bytes(b)
-            }
-            Err(_) => SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        };
-        Self {
-            zero,
+        let mut rtc = Self {
+            zero: now(),
+            halted: None,
+            carry: false,
             s: 0,
             m: 0,
             h: 0,
             dl: 0,
             dh: 0,
             sav_path: sav_path.as_ref().to_path_buf(),
-            is_locked: false,
+            latch: 0xff,
+        };
+        rtc.load();
+        rtc
+    }
+
+    /// Read the counter, a missing or broken file starts the clock from now.
+    fn load(&mut self) {
+        if self.sav_path.as_os_str().is_empty() {
+            return;
+        }
+        let data = match std::fs::read(&self.sav_path) {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        if data.len() != 8 && data.len() != 17 {
+            warn!(
+                "The rtc file {:?} is {} bytes, expected 8 or 17 bytes",
+                self.sav_path,
+                data.len()
+            );
+            return;
+        }
+        let u64_at = |i: usize| {
+            let mut b = [0x00; 8];
+            b.copy_from_slice(&data[i..i + 8]);
+            u64::from_be_bytes(b)
+        };
+        self.zero = u64_at(0);
+        if data.len() == 17 {
+            if data[16] & HALT != 0 {
+                self.halted = Some(u64_at(8));
+            }
+            self.carry = data[16] & CARRY != 0;
         }
     }
 
-    #[inline]
-    pub fn lock(&mut self) {
-        self.is_locked = true;
+    /// Write the counter beside the save file. Returns true if it's written.
+    pub fn save(&self) -> bool {
+        if self.sav_path.as_os_str().is_empty() {
+            return false;
+        }
+        let mut data = self.zero.to_be_bytes().to_vec();
+        data.extend_from_slice(&self.halted.unwrap_or(0).to_be_bytes());
+        data.push(self.flags());
+        match File::create(&self.sav_path).and_then(|mut f| f.write_all(&data)) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Can't write the rtc file {:?}: {}", self.sav_path, e);
+                false
+            }
+        }
     }
 
-    #[inline]
-    pub fn unlock(&mut self) {
-        self.is_locked = false;
+    fn flags(&self) -> u8 {
+        let mut flags = 0x00;
+        if self.halted.is_some() {
+            flags |= HALT;
+        }
+        if self.carry {
+            flags |= CARRY;
+        }
+        flags
     }
 
-    #[inline]
-    pub fn is_locked(&self) -> bool {
-        self.is_locked
-    }
-
-    pub fn tick(&mut self) {
-        let d = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            - self.zero;
-
-        self.s = (d % 60) as u8;
-        self.m = (d / 60 % 60) as u8;
-        self.h = (d / 3600 % 24) as u8;
-        let days = (d / 3600 / 24) as u16;
-        self.dl = (days % 256) as u8;
-        match days {
-            0x0000..=0x00ff => {}
-            0x0100..=0x01ff => {
-                self.dh |= 0x01;
-            }
-            _ => {
-                self.dh |= 0x01;
-                self.dh |= 0x80;
-            }
+    /// Returns the counter in seconds, the overflow of the day counter sets the carry.
+    fn counter(&mut self) -> u64 {
+        let secs = match self.halted {
+            Some(secs) => secs,
+            None => now().saturating_sub(self.zero),
+        };
+        if secs >= DAYS_OVERFLOW {
+            self.carry = true;
+            self.set_counter(secs % DAYS_OVERFLOW);
+        }
+        secs % DAYS_OVERFLOW
+    }
+
+    /// Returns the counter as the game would latch it now.
+    pub fn get_state(&self) -> RtcState {
+        let secs = match self.halted {
+            Some(secs) => secs,
+            None => now().saturating_sub(self.zero),
+        };
+        let carry = self.carry || secs >= DAYS_OVERFLOW;
+        let secs = secs % DAYS_OVERFLOW;
+        RtcState {
+            days: (secs / DAY) as u16,
+            hours: (secs / 3600 % 24) as u8,
+            minutes: (secs / 60 % 60) as u8,
+            seconds: (secs % 60) as u8,
+            halted: self.halted.is_some(),
+            carry,
+        }
+    }
+
+    fn set_counter(&mut self, secs: u64) {
+        match self.halted {
+            Some(_) => self.halted = Some(secs),
+            None => self.zero = now().saturating_sub(secs),
         }
     }
 
-    #[allow(dead_code)]
+    /// Handle the write to 6000-7FFF, the counter is latched when 00h then 01h are written.
+    pub fn write_latch(&mut self, v: u8) {
+        if self.latch == 0x00 && v == 0x01 {
+            self.latch_counter();
+        }
+        self.latch = v;
+    }
+
+    /// Returns true if 01h is the last value written to 6000-7FFF.
+    #[inline]
+    pub fn is_latched(&self) -> bool {
+        self.latch == 0x01
+    }
+
+    fn latch_counter(&mut self) {
+        let secs = self.counter();
+        let days = secs / DAY;
+        self.s = (secs % 60) as u8;
+        self.m = (secs / 60 % 60) as u8;
+        self.h = (secs / 3600 % 24) as u8;
+        self.dl = days as u8;
+        self.dh = (days >> 8) as u8 & 0x01 | self.flags();
+    }
+
     pub fn get(&self, a: u16) -> u8 {
         match a {
             0x08 => self.s,
@@ -100,26 +226,150 @@ impl RealTimeClock {
         }
     }
 
-    #[allow(dead_code)]
     pub fn set(&mut self, a: u16, v: u8) {
+        let secs = self.counter();
+        let (mut s, mut m, mut h) = (secs % 60, secs / 60 % 60, secs / 3600 % 24);
+        let mut days = secs / DAY;
         match a {
-            0x08 => self.s = v,
-            0x09 => self.m = v,
-            0x0a => self.h = v,
-            0x0b => self.dl = v,
-            0x0c => self.dh = v,
+            0x08 => {
+                self.s = v & 0x3f;
+                s = u64::from(self.s);
+            }
+            0x09 => {
+                self.m = v & 0x3f;
+                m = u64::from(self.m);
+            }
+            0x0a => {
+                self.h = v & 0x1f;
+                h = u64::from(self.h);
+            }
+            0x0b => {
+                self.dl = v;
+                days = days & 0x100 | u64::from(v);
+            }
+            0x0c => {
+                self.dh = v & (0x01 | HALT | CARRY);
+                days = days & 0xff | u64::from(v & 0x01) << 8;
+                self.carry = v & CARRY != 0;
+            }
             _ => panic!("Invalid item"),
         }
+        let secs = days * DAY + h * 3600 + m * 60 + s;
+        if a == 0x0c {
+            self.halted = if v & HALT != 0 { Some(secs) } else { None };
+        }
+        self.set_counter(secs);
+    }
+}
+
+/// The latched registers are saved, the counter itself is kept in the rtc file and keeps running
+/// on the wall time.
+impl Stateful for RealTimeClock {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_bytes(&[self.s, self.m, self.h, self.dl, self.dh]);
+        w.put_bool(self.is_latched());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.s = r.get_u8()?;
+        self.m = r.get_u8()?;
+        self.h = r.get_u8()?;
+        self.dl = r.get_u8()?;
+        self.dh = r.get_u8()?;
+        self.latch = if r.get_bool()? { 0x01 } else { 0x00 };
+        Ok(())
     }
 }
 
 impl Drop for RealTimeClock {
     fn drop(&mut self) {
-        if self.sav_path.to_str().unwrap().is_empty() {
-            return;
-        }
-        File::create(self.sav_path.clone())
-            .and_then(|mut f| f.write_all(&self.zero.to_be_bytes()))
-            .unwrap()
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latched(rtc: &mut RealTimeClock) -> [u8; 5] {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        [0x08, 0x09, 0x0a, 0x0b, 0x0c].map(|a| rtc.get(a))
+    }
+
+    #[test]
+    fn test_latch() {
+        let mut rtc = RealTimeClock::new("");
+        rtc.zero -= 2 * DAY + 3 * 3600 + 4 * 60 + 5;
+        // 01h alone doesn't latch.
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.get(0x08), 0x00);
+        assert!(rtc.is_latched());
+
+        let [_, m, h, dl, dh] = latched(&mut rtc);
+        assert_eq!([m, h, dl, dh], [4, 3, 2, 0]);
+        // The latched registers don't move with the counter.
+        rtc.zero -= 60;
+        assert_eq!(rtc.get(0x09), 4);
+        rtc.write_latch(0x00);
+        assert!(!rtc.is_latched());
+    }
+
+    #[test]
+    fn test_halt_and_write() {
+        let mut rtc = RealTimeClock::new("");
+        rtc.set(0x0c, HALT | 0x01);
+        rtc.set(0x08, 30);
+        rtc.set(0x09, 20);
+        rtc.set(0x0a, 10);
+        rtc.set(0x0b, 0x05);
+        assert_eq!(rtc.get(0x0b), 0x05);
+        assert_eq!(latched(&mut rtc), [30, 20, 10, 0x05, HALT | 0x01]);
+
+        // The halted counter doesn't follow the wall time.
+        rtc.zero = 0;
+        assert_eq!(latched(&mut rtc), [30, 20, 10, 0x05, HALT | 0x01]);
+
+        // It goes on from there after the halt flag is cleared.
+        rtc.set(0x0c, 0x01);
+        assert_eq!(rtc.halted, None);
+        assert_eq!(latched(&mut rtc)[1..], [20, 10, 0x05, 0x01]);
+    }
+
+    #[test]
+    fn test_day_carry() {
+        let mut rtc = RealTimeClock::new("");
+        rtc.zero -= DAYS_OVERFLOW + DAY + 10;
+        let [_, _, _, dl, dh] = latched(&mut rtc);
+        assert_eq!([dl, dh], [1, CARRY]);
+        // The carry stays until it's cleared by the game.
+        assert_eq!(latched(&mut rtc)[4], CARRY);
+        rtc.set(0x0c, 0x00);
+        assert_eq!(latched(&mut rtc)[3..], [1, 0x00]);
+    }
+
+    #[test]
+    fn test_state() {
+        let mut rtc = RealTimeClock::new("");
+        rtc.set(0x0c, HALT);
+        rtc.set(0x0b, 0x2c);
+        rtc.set(0x0a, 10);
+        rtc.set(0x09, 20);
+        rtc.set(0x08, 30);
+        let state = rtc.get_state();
+        assert_eq!(
+            state,
+            RtcState {
+                days: 0x2c,
+                hours: 10,
+                minutes: 20,
+                seconds: 30,
+                halted: true,
+                carry: false,
+            }
+        );
+        rtc.set(0x0c, HALT | CARRY | 0x01);
+        assert_eq!(rtc.get_state().days, 0x12c);
+        assert!(rtc.get_state().carry);
     }
 }
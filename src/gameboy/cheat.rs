@@ -0,0 +1,296 @@
+//! The cheat codes. A Game Genie code patches the byte read from the rom, a GameShark code
+//! writes the byte into the ram at every VBlank.
+//!
+//! The Game Genie code `ABC-DEF-GHI` replaces the byte at `FCDE ^ 0xf000` with `AB`, only if the
+//! rom has `GI` rotated right by 2 and XORed with 0xba there, `H` is unused. The short form
+//! `ABC-DEF` always replaces it.
+//!
+//! The GameShark code `TTVVLLHH` writes `VV` to `HHLL`. The type `TT` is 01 for the memory seen by
+//! the cpu, 90-97 picks the CGB wram bank of D000-DFFF.
+//!
+//! The cheat file has one code per line, the text after the code is its name:
+//! ```ignore
+//! # Super Mario Land
+//! 01990AC0 infinite lives
+//! 00A-17B-C49
+//! ```
+use std::fs;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatKind {
+    GameGenie {
+        addr: u16,
+        value: u8,
+        // The byte the rom must have, the patch applies to every bank without it.
+        compare: Option<u8>,
+    },
+    GameShark {
+        // The wram bank, `None` writes through the memory map.
+        bank: Option<usize>,
+        addr: u16,
+        value: u8,
+    },
+}
+
+fn parse_hex(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 16).map_err(|_| format!("invalid cheat code {}", s))
+}
+
+impl FromStr for CheatKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits: String = s.chars().filter(|c| *c != '-').collect();
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid cheat code {}", s));
+        }
+        match digits.len() {
+            6 | 9 => {
+                let value = parse_hex(&digits[0..2])? as u8;
+                let addr = parse_hex(&digits[2..6])? as u16;
+                let addr = addr.rotate_right(4) ^ 0xf000;
+                if addr >= 0x8000 {
+                    return Err(format!("the Game Genie code {} is out of the rom", s));
+                }
+                let compare = if digits.len() == 9 {
+                    let old = parse_hex(&format!("{}{}", &digits[6..7], &digits[8..9]))? as u8;
+                    Some(old.rotate_right(2) ^ 0xba)
+                } else {
+                    None
+                };
+                Ok(Self::GameGenie {
+                    addr,
+                    value,
+                    compare,
+                })
+            }
+            8 => {
+                let bank = match parse_hex(&digits[0..2])? {
+                    0x00 | 0x01 => None,
+                    t @ 0x90..=0x97 => Some((t as usize & 0x07).max(1)),
+                    t => return Err(format!("unsupported GameShark type {:02x}", t)),
+                };
+                let value = parse_hex(&digits[2..4])? as u8;
+                let addr = (parse_hex(&digits[4..8])? as u16).swap_bytes();
+                if bank.is_some() && !(0xd000..=0xdfff).contains(&addr) {
+                    return Err(format!("the GameShark code {} is out of the wram bank", s));
+                }
+                Ok(Self::GameShark { bank, addr, value })
+            }
+            _ => Err(format!(
+                "the cheat code {} should be ABC-DEF(-GHI) or TTVVLLHH",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    // The code as it's written, the cheats are found by it.
+    code: String,
+    name: String,
+    kind: CheatKind,
+    enabled: bool,
+}
+
+impl Cheat {
+    pub fn new(code: &str, name: &str) -> Result<Self, String> {
+        Ok(Self {
+            code: code.to_string(),
+            name: name.to_string(),
+            kind: code.parse()?,
+            enabled: true,
+        })
+    }
+
+    pub fn get_code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_kind(&self) -> CheatKind {
+        self.kind
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the cheat file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let s = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        s.parse()
+    }
+
+    pub fn get_cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Add the code, the same code is replaced.
+    pub fn add(&mut self, code: &str, name: &str) -> Result<(), String> {
+        let cheat = Cheat::new(code, name)?;
+        self.remove(code);
+        self.cheats.push(cheat);
+        Ok(())
+    }
+
+    /// Returns false if the code is not added.
+    pub fn remove(&mut self, code: &str) -> bool {
+        let len = self.cheats.len();
+        self.cheats.retain(|c| c.code != code);
+        self.cheats.len() != len
+    }
+
+    /// Returns false if the code is not added.
+    pub fn set_enabled(&mut self, code: &str, enabled: bool) -> bool {
+        match self.cheats.iter_mut().find(|c| c.code == code) {
+            Some(c) => {
+                c.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Switch the code on or off, returns whether it's on now.
+    pub fn toggle(&mut self, code: &str) -> Option<bool> {
+        let cheat = self.cheats.iter_mut().find(|c| c.code == code)?;
+        cheat.enabled = !cheat.enabled;
+        Some(cheat.enabled)
+    }
+
+    /// Returns the byte read from the rom with the Game Genie codes applied.
+    pub fn patch_rom(&self, a: u16, v: u8) -> u8 {
+        for cheat in self.cheats.iter().filter(|c| c.enabled) {
+            if let CheatKind::GameGenie {
+                addr,
+                value,
+                compare,
+            } = cheat.kind
+            {
+                if addr == a && compare.unwrap_or(v) == v {
+                    return value;
+                }
+            }
+        }
+        v
+    }
+
+    /// Returns the writes of the GameShark codes, as the bank, the address and the value.
+    pub fn get_ram_writes(&self) -> Vec<(Option<usize>, u16, u8)> {
+        self.cheats
+            .iter()
+            .filter(|c| c.enabled)
+            .filter_map(|c| match c.kind {
+                CheatKind::GameShark { bank, addr, value } => Some((bank, addr, value)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Parse the cheat file.
+impl FromStr for Cheats {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cheats = Self::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let code = parts.next().unwrap();
+            let name = parts.next().unwrap_or("").trim();
+            cheats
+                .add(code, name)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(cheats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_game_genie() {
+        assert_eq!(
+            "00A-17B-C49".parse(),
+            Ok(CheatKind::GameGenie {
+                addr: 0x4a17,
+                value: 0x00,
+                compare: Some(0xc9_u8.rotate_right(2) ^ 0xba),
+            })
+        );
+        assert_eq!(
+            "3EA-20F".parse(),
+            Ok(CheatKind::GameGenie {
+                addr: 0x0a20,
+                value: 0x3e,
+                compare: None,
+            })
+        );
+        // F ^ 0xf = 8 is beyond the rom.
+        assert!("00A-177-C49".parse::<CheatKind>().is_err());
+        assert!("00A-17B-C".parse::<CheatKind>().is_err());
+    }
+
+    #[test]
+    fn test_parse_gameshark() {
+        assert_eq!(
+            "01990AC0".parse(),
+            Ok(CheatKind::GameShark {
+                bank: None,
+                addr: 0xc00a,
+                value: 0x99,
+            })
+        );
+        assert_eq!(
+            "9001FFD0".parse(),
+            Ok(CheatKind::GameShark {
+                bank: Some(1),
+                addr: 0xd0ff,
+                value: 0x01,
+            })
+        );
+        assert!("02990AC0".parse::<CheatKind>().is_err());
+        assert!("91990AC0".parse::<CheatKind>().is_err());
+    }
+
+    #[test]
+    fn test_cheats() {
+        let mut cheats: Cheats = "# lives\n01990AC0 infinite lives\n\n3EA-20F\n"
+            .parse()
+            .unwrap();
+        assert_eq!(cheats.get_cheats()[0].get_name(), "infinite lives");
+        assert_eq!(cheats.patch_rom(0x0a20, 0x12), 0x3e);
+        assert_eq!(cheats.patch_rom(0x0a21, 0x12), 0x12);
+        assert_eq!(cheats.get_ram_writes(), vec![(None, 0xc00a, 0x99)]);
+
+        assert_eq!(cheats.toggle("3EA-20F"), Some(false));
+        assert_eq!(cheats.patch_rom(0x0a20, 0x12), 0x12);
+        assert!(cheats.remove("01990AC0"));
+        assert!(!cheats.remove("01990AC0"));
+        assert!(cheats.get_ram_writes().is_empty());
+        assert!("bad".parse::<Cheats>().unwrap_err().starts_with("line 1:"));
+    }
+}
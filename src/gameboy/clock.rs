@@ -0,0 +1,21 @@
+//! The clocks of the host. std has no clock on wasm32, `Instant::now` and `SystemTime::now`
+//! panic there, so the build without the `native` feature runs without the time: the frames
+//! aren't paced, the statistics and the messages are off, the save ram is written when the
+//! cartridge is dropped and the RTC doesn't tick.
+use std::time::{Instant, SystemTime};
+
+/// Returns the monotonic time, `None` without the `native` feature.
+pub fn now() -> Option<Instant> {
+    #[cfg(feature = "native")]
+    return Some(Instant::now());
+    #[cfg(not(feature = "native"))]
+    None
+}
+
+/// Returns the wall time, `None` without the `native` feature.
+pub fn wall_time() -> Option<SystemTime> {
+    #[cfg(feature = "native")]
+    return Some(SystemTime::now());
+    #[cfg(not(feature = "native"))]
+    None
+}
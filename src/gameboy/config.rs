@@ -2,31 +2,1064 @@
 // use serde_json::Result;
 // use winit::event::VirtualKeyCode;
 
-// TODO: custom key mapping
-// #[derive(Serialize, Deserialize)]
-// pub struct Keyboard {
-//     right: VirtualKeyCode,
-//     up: VirtualKeyCode,
-//     left: VirtualKeyCode,
-//     down: VirtualKeyCode,
-//     a: VirtualKeyCode,
-//     b: VirtualKeyCode,
-//     select: VirtualKeyCode,
-//     start: VirtualKeyCode,
-// }
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::cartridge::{HeaderCheck, Mbc1Wiring, DEFAULT_FLUSH_DELAY};
+use super::cheat::Cheats;
+use super::frontend::Backend;
+use super::gamepad::DEFAULT_DEADZONE;
+use super::graphics::filter::Filter;
+use super::graphics::gpu::Renderer;
+use super::graphics::palette::PaletteSet;
+use super::history::DEFAULT_HISTORY_SIZE;
+use super::joypad::{JoypadKey, OppositeDirection, DEFAULT_AUTOFIRE_RATE};
+#[cfg(feature = "native")]
+use super::keymap::Keymap;
+use super::logging::{LogLevels, DEFAULT_LOG_FILE};
+use super::osd::message::{Corner, DEFAULT_TIMEOUT};
+use super::pacing::SyncMode;
+use super::recorder::VideoFormat;
+use super::spec::{Accuracy, Mode};
+use super::stats::DEFAULT_TITLE_FORMAT;
+
+/// The settings of the config file and the environment, they're named like the command line
+/// flags. They're applied in this order, e.g. the keymap file before the bindings.
+pub const SETTINGS: [&str; 53] = [
+    "rom-dir",
+    "perf-overlay",
+    "vram-viewer",
+    "show-fps",
+    "show-stats",
+    "show-io",
+    "log-stats",
+    "title",
+    "osd-corner",
+    "osd-timeout",
+    "opposite-direction",
+    "renderer",
+    "accuracy",
+    "frontend",
+    "scale",
+    "fullscreen",
+    "filter",
+    "green-tint",
+    "frame-blend",
+    "palette-file",
+    "palette",
+    "cheats",
+    "dump-frames",
+    "dump-interval",
+    "video-format",
+    "keymap-file",
+    "keymap",
+    "autofire",
+    "autofire-rate",
+    "gamepad-deadzone",
+    "fast-forward",
+    "sync",
+    "sync-window",
+    "frame-skip",
+    "save-delay",
+    "random-ram",
+    "audio-dump",
+    "trace",
+    "memory-profile",
+    "memory-heatmap",
+    "log-level",
+    "log-file",
+    "no-log-file",
+    "cpu-history",
+    "stack-check",
+    "cpu-profile",
+    "recover",
+    "bootrom",
+    "camera-image",
+    "header-check",
+    "mbc1",
+    "mode",
+    "data-dir",
+];
+
+/// The environment variables are the settings in upper case with this prefix, e.g.
+/// `NGC224_SCALE=4` or `NGC224_FRAME_BLEND=0.5`.
+pub const ENV_PREFIX: &str = "NGC224_";
 
 // #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone)]
 pub struct Config {
-    file_path: String,
+    file_path: Option<String>,
+    // The directory listed by the start screen when no rom is given.
+    rom_dir: String,
+    // The log level of the emulator and its modules, and the file the log is written into.
+    log_levels: LogLevels,
+    log_file: Option<String>,
+    // Plot the frame time graph in the corner.
+    perf_overlay: bool,
+    // Open the VRAM viewer next to the game window.
+    vram_viewer: bool,
+    // Where the messages like "State saved" are drawn, and how long they stay.
+    osd_corner: Corner,
+    osd_timeout: Duration,
+    // Draw the frames per second above the messages.
+    show_fps: bool,
+    // Draw the statistics of every second above the messages, and log them.
+    show_stats: bool,
+    log_stats: bool,
+    // The window title, it's updated with the statistics every second.
+    title_format: String,
+    // Draw the I/O registers of every frame above the messages.
+    show_io: bool,
+    // How to report Left+Right or Up+Down held at the same time.
+    opposite_direction: OppositeDirection,
+    // How long the battery ram must stay untouched before it's written to the save file.
+    save_delay: Duration,
+    // Fill the ram with random bytes from the seed at power on, `None` zeroes it.
+    ram_seed: Option<u64>,
+    // Write the audio into the wav file instead of playing it.
+    audio_dump: Option<String>,
+    // Save every `frame_dump_interval` frames into the directory as PNG images.
+    frame_dump: Option<String>,
+    frame_dump_interval: u32,
+    // The format of the video recorded with the hotkey.
+    video_format: VideoFormat,
+    // Log every executed instruction into the file.
+    trace: Option<String>,
+    // Count the accesses of every memory page and rom bank, they're saved into the CSV and the
+    // heatmap image at exit.
+    memory_profile: Option<String>,
+    memory_heatmap: Option<String>,
+    // How many executed instructions are kept for the coredump, 0 keeps none.
+    cpu_history: usize,
+    // Warn when the stack leaves the work ram and the high ram.
+    stack_check: bool,
+    // Count the cycles of every instruction and function, the hot ones are logged at exit.
+    cpu_profile: bool,
+    // Pause the window on a panic of the emulator instead of exiting, a bug report is saved.
+    panic_recovery: bool,
+    // What the speed simulation sleeps by to keep the real hardware speed.
+    sync_mode: SyncMode,
+    // Let the window wait for the frame time instead of the emulator.
+    window_sync: bool,
+    #[cfg(feature = "native")]
+    keymap: Keymap,
+    // The presses per second of the turbo keys and the autofire buttons.
+    autofire_rate: u32,
+    // The buttons which autofire while held.
+    autofire: Vec<JoypadKey>,
+    // The left stick within the deadzone doesn't press any direction key.
+    gamepad_deadzone: f32,
+    // How many times of the normal speed to run while fast forwarding.
+    fast_forward_factor: u32,
+    // Only draw the last frame of every `fast_forward_factor` frames while fast forwarding.
+    frame_skip: bool,
+    // The pixel FIFO draws the mid-line effects, the scanline renderer is faster.
+    renderer: Renderer,
+    // Keep the timing quirks of the hardware, the fast timing is enough for most games.
+    accuracy: Accuracy,
+    // The library of the windows, SDL2 needs the `sdl2` feature.
+    frontend: Backend,
+    // How many times the window is of the screen, 1, 2, 4 or 8.
+    scale: u32,
+    // The borderless window of the largest integer scale fitting the host screen.
+    fullscreen: bool,
+    // How the frame is scaled and decorated for the window.
+    filter: Filter,
+    // Show the colors with the 4 green shades of the DMG screen.
+    green_tint: bool,
+    // How much of the previous frame is mixed into the current one, like the ghosting of the LCD.
+    frame_blend: f32,
+    // The DMG palettes and the palette of each game.
+    palettes: PaletteSet,
+    // The palette of the games without one in `palettes`.
+    palette: String,
+    // The cheat codes applied from the power on.
+    cheats: Cheats,
+    // Whether to boot the rom with a wrong logo or header checksum.
+    header_check: HeaderCheck,
+    // How the MBC1 is wired, `None` detects the multicarts from the rom.
+    mbc1_wiring: Option<Mbc1Wiring>,
+    // Run the boot rom from the power on, `None` starts from the post boot state.
+    boot_rom: Option<String>,
+    // The PNG seen by the Pocket Camera, it sees a grey image without it.
+    camera_image: Option<String>,
+    // The hardware to run on, `Mode::Auto` follows the cartridge header.
+    mode: Mode,
+    // The directory of the saves, the states, the screenshots and the settings of each game,
+    // `None` keeps them beside the rom.
+    data_dir: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            file_path: None,
+            rom_dir: ".".to_string(),
+            log_levels: LogLevels::default(),
+            log_file: Some(DEFAULT_LOG_FILE.to_string()),
+            perf_overlay: false,
+            vram_viewer: false,
+            osd_corner: Corner::TopLeft,
+            osd_timeout: DEFAULT_TIMEOUT,
+            show_fps: false,
+            show_stats: false,
+            log_stats: false,
+            title_format: DEFAULT_TITLE_FORMAT.to_string(),
+            show_io: false,
+            opposite_direction: OppositeDirection::LastWins,
+            save_delay: DEFAULT_FLUSH_DELAY,
+            ram_seed: None,
+            audio_dump: None,
+            frame_dump: None,
+            frame_dump_interval: 1,
+            video_format: VideoFormat::Gif,
+            trace: None,
+            memory_profile: None,
+            memory_heatmap: None,
+            cpu_history: DEFAULT_HISTORY_SIZE,
+            stack_check: false,
+            cpu_profile: false,
+            panic_recovery: false,
+            sync_mode: SyncMode::Video,
+            window_sync: false,
+            #[cfg(feature = "native")]
+            keymap: Keymap::default(),
+            autofire_rate: DEFAULT_AUTOFIRE_RATE,
+            autofire: vec![],
+            gamepad_deadzone: DEFAULT_DEADZONE,
+            fast_forward_factor: 4,
+            frame_skip: false,
+            renderer: Renderer::Scanline,
+            accuracy: Accuracy::Fast,
+            frontend: Backend::Minifb,
+            scale: 2,
+            fullscreen: false,
+            filter: Filter::Nearest,
+            green_tint: false,
+            frame_blend: 0.0,
+            palettes: PaletteSet::default(),
+            palette: "gray".to_string(),
+            cheats: Cheats::new(),
+            header_check: HeaderCheck::Warn,
+            mbc1_wiring: None,
+            boot_rom: None,
+            camera_image: None,
+            mode: Mode::Auto,
+            data_dir: None,
+        }
+    }
 }
 
 impl Config {
     pub fn new(file_path: String) -> Self {
-        Self { file_path }
+        Self {
+            file_path: Some(file_path),
+            ..Self::default()
+        }
+    }
+
+    pub fn get_file_path(&self) -> Option<&str> {
+        self.file_path.as_deref()
+    }
+
+    pub fn set_file_path(&mut self, file_path: String) {
+        self.file_path = Some(file_path);
+    }
+
+    pub fn get_rom_dir(&self) -> &str {
+        &self.rom_dir
+    }
+
+    pub fn set_rom_dir(&mut self, rom_dir: String) {
+        self.rom_dir = rom_dir;
+    }
+
+    pub fn get_log_levels(&self) -> &LogLevels {
+        &self.log_levels
+    }
+
+    pub fn set_log_levels(&mut self, levels: LogLevels) {
+        self.log_levels = levels;
+    }
+
+    pub fn get_log_file(&self) -> Option<&str> {
+        self.log_file.as_deref()
+    }
+
+    /// Write the log into the file along with the stdout, `None` only writes to the stdout.
+    pub fn set_log_file(&mut self, path: Option<String>) {
+        self.log_file = path;
+    }
+
+    pub fn is_perf_overlay_enabled(&self) -> bool {
+        self.perf_overlay
+    }
+
+    pub fn set_perf_overlay(&mut self, enabled: bool) {
+        self.perf_overlay = enabled;
+    }
+
+    pub fn is_vram_viewer_enabled(&self) -> bool {
+        self.vram_viewer
+    }
+
+    pub fn set_vram_viewer(&mut self, enabled: bool) {
+        self.vram_viewer = enabled;
+    }
+
+    pub fn get_osd_corner(&self) -> Corner {
+        self.osd_corner
+    }
+
+    pub fn set_osd_corner(&mut self, corner: Corner) {
+        self.osd_corner = corner;
+    }
+
+    pub fn get_osd_timeout(&self) -> Duration {
+        self.osd_timeout
+    }
+
+    pub fn set_osd_timeout(&mut self, timeout: Duration) {
+        self.osd_timeout = timeout;
+    }
+
+    pub fn is_fps_shown(&self) -> bool {
+        self.show_fps
+    }
+
+    pub fn set_show_fps(&mut self, enabled: bool) {
+        self.show_fps = enabled;
+    }
+
+    pub fn is_stats_shown(&self) -> bool {
+        self.show_stats
+    }
+
+    pub fn set_show_stats(&mut self, enabled: bool) {
+        self.show_stats = enabled;
+    }
+
+    pub fn is_io_shown(&self) -> bool {
+        self.show_io
+    }
+
+    pub fn set_show_io(&mut self, enabled: bool) {
+        self.show_io = enabled;
+    }
+
+    pub fn is_stats_logged(&self) -> bool {
+        self.log_stats
+    }
+
+    pub fn set_log_stats(&mut self, enabled: bool) {
+        self.log_stats = enabled;
+    }
+
+    pub fn get_title_format(&self) -> &str {
+        &self.title_format
+    }
+
+    pub fn set_title_format(&mut self, format: String) {
+        self.title_format = format;
+    }
+
+    pub fn get_opposite_direction(&self) -> OppositeDirection {
+        self.opposite_direction
+    }
+
+    pub fn set_opposite_direction(&mut self, mode: OppositeDirection) {
+        self.opposite_direction = mode;
+    }
+
+    pub fn get_save_delay(&self) -> Duration {
+        self.save_delay
+    }
+
+    pub fn set_save_delay(&mut self, delay: Duration) {
+        self.save_delay = delay;
+    }
+
+    pub fn get_ram_seed(&self) -> Option<u64> {
+        self.ram_seed
+    }
+
+    pub fn set_ram_seed(&mut self, seed: Option<u64>) {
+        self.ram_seed = seed;
+    }
+
+    pub fn get_audio_dump(&self) -> Option<&str> {
+        self.audio_dump.as_deref()
+    }
+
+    pub fn set_audio_dump(&mut self, path: String) {
+        self.audio_dump = Some(path);
+    }
+
+    pub fn get_frame_dump(&self) -> Option<&str> {
+        self.frame_dump.as_deref()
+    }
+
+    pub fn set_frame_dump(&mut self, dir: String) {
+        self.frame_dump = Some(dir);
+    }
+
+    pub fn get_frame_dump_interval(&self) -> u32 {
+        self.frame_dump_interval
+    }
+
+    pub fn set_frame_dump_interval(&mut self, interval: u32) {
+        self.frame_dump_interval = interval.max(1);
+    }
+
+    pub fn get_video_format(&self) -> VideoFormat {
+        self.video_format
+    }
+
+    pub fn set_video_format(&mut self, format: VideoFormat) {
+        self.video_format = format;
+    }
+
+    pub fn get_trace(&self) -> Option<&str> {
+        self.trace.as_deref()
+    }
+
+    pub fn set_trace(&mut self, path: String) {
+        self.trace = Some(path);
+    }
+
+    pub fn get_memory_profile(&self) -> Option<&str> {
+        self.memory_profile.as_deref()
+    }
+
+    pub fn set_memory_profile(&mut self, path: String) {
+        self.memory_profile = Some(path);
+    }
+
+    pub fn get_memory_heatmap(&self) -> Option<&str> {
+        self.memory_heatmap.as_deref()
+    }
+
+    pub fn set_memory_heatmap(&mut self, path: String) {
+        self.memory_heatmap = Some(path);
+    }
+
+    pub fn is_memory_profiled(&self) -> bool {
+        self.memory_profile.is_some() || self.memory_heatmap.is_some()
+    }
+
+    pub fn get_cpu_history_size(&self) -> usize {
+        self.cpu_history
+    }
+
+    pub fn set_cpu_history_size(&mut self, size: usize) {
+        self.cpu_history = size;
+    }
+
+    pub fn is_stack_check_enabled(&self) -> bool {
+        self.stack_check
+    }
+
+    pub fn set_stack_check(&mut self, enabled: bool) {
+        self.stack_check = enabled;
+    }
+
+    pub fn is_cpu_profile_enabled(&self) -> bool {
+        self.cpu_profile
+    }
+
+    pub fn set_cpu_profile(&mut self, enabled: bool) {
+        self.cpu_profile = enabled;
+    }
+
+    pub fn is_panic_recovery_enabled(&self) -> bool {
+        self.panic_recovery
+    }
+
+    pub fn set_panic_recovery(&mut self, enabled: bool) {
+        self.panic_recovery = enabled;
+    }
+
+    pub fn is_speed_simulation_enabled(&self) -> bool {
+        self.sync_mode != SyncMode::Uncapped
+    }
+
+    /// Turning it on again goes back to the video sync.
+    pub fn set_speed_simulation(&mut self, enabled: bool) {
+        if !enabled {
+            self.sync_mode = SyncMode::Uncapped;
+        } else if self.sync_mode == SyncMode::Uncapped {
+            self.sync_mode = SyncMode::Video;
+        }
+    }
+
+    pub fn get_sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
+
+    pub fn set_sync_mode(&mut self, mode: SyncMode) {
+        self.sync_mode = mode;
+    }
+
+    pub fn is_window_synced(&self) -> bool {
+        self.window_sync
+    }
+
+    pub fn set_window_sync(&mut self, enabled: bool) {
+        self.window_sync = enabled;
+    }
+
+    #[cfg(feature = "native")]
+    pub fn get_keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    #[cfg(feature = "native")]
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn get_autofire_rate(&self) -> u32 {
+        self.autofire_rate
+    }
+
+    pub fn set_autofire_rate(&mut self, rate: u32) {
+        self.autofire_rate = rate;
+    }
+
+    pub fn get_autofire(&self) -> &[JoypadKey] {
+        &self.autofire
+    }
+
+    pub fn set_autofire(&mut self, buttons: Vec<JoypadKey>) {
+        self.autofire = buttons;
+    }
+
+    pub fn get_gamepad_deadzone(&self) -> f32 {
+        self.gamepad_deadzone
+    }
+
+    pub fn set_gamepad_deadzone(&mut self, deadzone: f32) {
+        self.gamepad_deadzone = deadzone;
+    }
+
+    pub fn get_fast_forward_factor(&self) -> u32 {
+        self.fast_forward_factor
+    }
+
+    pub fn set_fast_forward_factor(&mut self, factor: u32) {
+        self.fast_forward_factor = factor.max(1);
+    }
+
+    pub fn is_frame_skip_enabled(&self) -> bool {
+        self.frame_skip
+    }
+
+    pub fn set_frame_skip(&mut self, enabled: bool) {
+        self.frame_skip = enabled;
+    }
+
+    pub fn get_renderer(&self) -> Renderer {
+        self.renderer
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    pub fn get_accuracy(&self) -> Accuracy {
+        self.accuracy
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.accuracy = accuracy;
+    }
+
+    pub fn get_frontend(&self) -> Backend {
+        self.frontend
+    }
+
+    pub fn set_frontend(&mut self, frontend: Backend) {
+        self.frontend = frontend;
+    }
+
+    pub fn get_scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Returns the error if the window can't be scaled by it, the scale is kept then.
+    pub fn set_scale(&mut self, scale: u32) -> Result<(), String> {
+        if ![1, 2, 4, 8].contains(&scale) {
+            return Err(format!("the scale {} should be 1, 2, 4 or 8", scale));
+        }
+        self.scale = scale;
+        Ok(())
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    pub fn set_fullscreen(&mut self, enabled: bool) {
+        self.fullscreen = enabled;
+    }
+
+    pub fn get_filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    pub fn is_green_tint_enabled(&self) -> bool {
+        self.green_tint
+    }
+
+    pub fn set_green_tint(&mut self, enabled: bool) {
+        self.green_tint = enabled;
+    }
+
+    pub fn get_frame_blend(&self) -> f32 {
+        self.frame_blend
+    }
+
+    pub fn set_frame_blend(&mut self, persistence: f32) {
+        self.frame_blend = persistence;
+    }
+
+    pub fn get_palettes(&self) -> &PaletteSet {
+        &self.palettes
+    }
+
+    pub fn set_palettes(&mut self, palettes: PaletteSet) {
+        self.palettes = palettes;
+    }
+
+    pub fn get_palette(&self) -> &str {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, name: String) {
+        self.palette = name;
+    }
+
+    pub fn get_cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+    }
+
+    pub fn get_header_check(&self) -> HeaderCheck {
+        self.header_check
+    }
+
+    pub fn set_header_check(&mut self, check: HeaderCheck) {
+        self.header_check = check;
+    }
+
+    pub fn get_mbc1_wiring(&self) -> Option<Mbc1Wiring> {
+        self.mbc1_wiring
+    }
+
+    pub fn set_mbc1_wiring(&mut self, wiring: Option<Mbc1Wiring>) {
+        self.mbc1_wiring = wiring;
+    }
+
+    pub fn get_boot_rom(&self) -> Option<&str> {
+        self.boot_rom.as_deref()
+    }
+
+    pub fn set_boot_rom(&mut self, path: String) {
+        self.boot_rom = Some(path);
+    }
+
+    pub fn get_camera_image(&self) -> Option<&str> {
+        self.camera_image.as_deref()
+    }
+
+    pub fn set_camera_image(&mut self, path: String) {
+        self.camera_image = Some(path);
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn get_data_dir(&self) -> Option<&str> {
+        self.data_dir.as_deref()
+    }
+
+    pub fn set_data_dir(&mut self, dir: Option<String>) {
+        self.data_dir = dir;
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("the {} {:?} is invalid: {}", key, value, e))
+}
+
+fn parse_secs(key: &str, value: &str) -> Result<Duration, String> {
+    let secs: f64 = parse(key, value)?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(format!("the {} {} should be 0 or more seconds", key, secs));
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+impl Config {
+    /// Returns the builder layering the config file, the environment and the settings.
+    pub fn builder() -> ConfigBuilder {
+        Self::default().into_builder()
+    }
+
+    /// Returns the builder layering the settings over this config, e.g. the settings of a game.
+    pub fn into_builder(self) -> ConfigBuilder {
+        ConfigBuilder { config: self }
+    }
+
+    /// Apply one of `SETTINGS` from its text, like the value of the command line flag. The
+    /// switches take `true` or `false`, `keymap` adds a binding like `a=J,Z`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let path = || value.to_string();
+        match key {
+            "rom-dir" => self.rom_dir = path(),
+            "perf-overlay" => self.perf_overlay = parse(key, value)?,
+            "vram-viewer" => self.vram_viewer = parse(key, value)?,
+            "show-fps" => self.show_fps = parse(key, value)?,
+            "show-stats" => self.show_stats = parse(key, value)?,
+            "show-io" => self.show_io = parse(key, value)?,
+            "log-stats" => self.log_stats = parse(key, value)?,
+            "title" => self.title_format = path(),
+            "osd-corner" => self.osd_corner = parse(key, value)?,
+            "osd-timeout" => self.osd_timeout = parse_secs(key, value)?,
+            "opposite-direction" => self.opposite_direction = parse(key, value)?,
+            "renderer" => self.renderer = parse(key, value)?,
+            "accuracy" => self.accuracy = parse(key, value)?,
+            "frontend" => self.frontend = parse(key, value)?,
+            "scale" => self.set_scale(parse(key, value)?)?,
+            "fullscreen" => self.fullscreen = parse(key, value)?,
+            "filter" => self.filter = parse(key, value)?,
+            "green-tint" => self.green_tint = parse(key, value)?,
+            "frame-blend" => {
+                let blend = parse(key, value)?;
+                if !(0.0..=0.9).contains(&blend) {
+                    return Err(format!("the frame blend {} should be 0.0 to 0.9", blend));
+                }
+                self.frame_blend = blend;
+            }
+            "palette-file" => self.palettes = PaletteSet::load(value)?,
+            "palette" => self.palette = path(),
+            "cheats" => self.cheats = Cheats::load(value)?,
+            "dump-frames" => self.frame_dump = Some(path()),
+            "dump-interval" => self.frame_dump_interval = parse(key, value)?,
+            "video-format" => self.video_format = parse(key, value)?,
+            #[cfg(feature = "native")]
+            "keymap-file" => self.keymap = Keymap::load(value)?,
+            #[cfg(feature = "native")]
+            "keymap" => self.keymap.apply(value)?,
+            "autofire" => {
+                self.autofire = value
+                    .split(',')
+                    .map(|b| parse(key, b.trim()))
+                    .collect::<Result<_, _>>()?
+            }
+            "autofire-rate" => {
+                let rate = parse(key, value)?;
+                if !(1..=30).contains(&rate) {
+                    return Err(format!("the autofire rate {} should be 1 to 30", rate));
+                }
+                self.autofire_rate = rate;
+            }
+            "gamepad-deadzone" => self.gamepad_deadzone = parse(key, value)?,
+            "fast-forward" => self.set_fast_forward_factor(parse(key, value)?),
+            "sync" => self.sync_mode = parse(key, value)?,
+            "sync-window" => self.window_sync = parse(key, value)?,
+            "frame-skip" => self.frame_skip = parse(key, value)?,
+            "save-delay" => self.save_delay = parse_secs(key, value)?,
+            "random-ram" => self.ram_seed = Some(parse(key, value)?),
+            "audio-dump" => self.audio_dump = Some(path()),
+            "trace" => self.trace = Some(path()),
+            "memory-profile" => self.memory_profile = Some(path()),
+            "memory-heatmap" => self.memory_heatmap = Some(path()),
+            "log-level" => self.log_levels = parse(key, value)?,
+            "log-file" => self.log_file = Some(path()),
+            "no-log-file" => {
+                if parse(key, value)? {
+                    self.log_file = None;
+                }
+            }
+            "cpu-history" => self.cpu_history = parse(key, value)?,
+            "stack-check" => self.stack_check = parse(key, value)?,
+            "cpu-profile" => self.cpu_profile = parse(key, value)?,
+            "recover" => self.panic_recovery = parse(key, value)?,
+            "bootrom" => self.boot_rom = Some(path()),
+            "camera-image" => self.camera_image = Some(path()),
+            "header-check" => self.header_check = parse(key, value)?,
+            "mbc1" => self.mbc1_wiring = Some(parse(key, value)?),
+            "mode" => self.mode = parse(key, value)?,
+            "data-dir" => self.data_dir = Some(path()),
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Layers the settings over the defaults. The usual order is the config file, then the
+/// environment, then the settings of the caller like the command line flags, a later one wins.
+///
+/// ```ignore
+/// let config = Config::builder()
+///     .default_file()?
+///     .env()?
+///     .set("scale", "4")?
+///     .rom("game.gb")
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Returns `$XDG_CONFIG_HOME/ngc224/config.toml`, or `~/.config/ngc224/config.toml`.
+    pub fn get_default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("ngc224").join("config.toml"))
+    }
+
+    /// Apply the config file of the default path, it's skipped if there's none.
+    pub fn default_file(self) -> Result<Self, String> {
+        match Self::get_default_path() {
+            Some(path) if path.exists() => self.file(path),
+            _ => Ok(self),
+        }
+    }
+
+    /// Apply the config file.
+    pub fn file(self, path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let s = fs::read_to_string(path).map_err(|e| e.to_string());
+        s.and_then(|s| self.toml(&s))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Apply the settings of the config file content. It's a subset of TOML, a setting a line
+    /// like `scale = 4` or `palette = "green"`. The sections like `[video]` only group them,
+    /// every line of `[keymap]` binds a button like `a = ["J", "Z"]`.
+    pub fn toml(mut self, s: &str) -> Result<Self, String> {
+        let mut section = String::new();
+        for (i, line) in s.lines().enumerate() {
+            let err = |e: String| format!("line {}: {}", i + 1, e);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                section = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| err(format!("the section {} is not closed", line)))?
+                    .trim()
+                    .to_string();
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap().trim().replace('_', "-");
+            let value = parts
+                .next()
+                .ok_or_else(|| err(format!("expect <key> = <value>, found {}", line)))?;
+            let value = parse_value(value.trim()).map_err(err)?;
+            if section == "keymap" {
+                self.config.set("keymap", &format!("{}={}", key, value))
+            } else {
+                self.config.set(&key, &value)
+            }
+            .map_err(err)?;
+        }
+        Ok(self)
+    }
+
+    /// Apply the `NGC224_` environment variables of the process.
+    pub fn env(self) -> Result<Self, String> {
+        self.vars(env::vars())
+    }
+
+    /// Apply the variables named like the environment, the others are ignored.
+    pub fn vars(
+        mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, String> {
+        let vars: Vec<(String, String)> = vars.into_iter().collect();
+        // In the order of the settings rather than the environment.
+        for key in SETTINGS.iter() {
+            let name = format!("{}{}", ENV_PREFIX, key.to_uppercase().replace('-', "_"));
+            if let Some((_, value)) = vars.iter().find(|(n, _)| *n == name) {
+                self.config
+                    .set(key, value)
+                    .map_err(|e| format!("{}: {}", name, e))?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Apply one of `SETTINGS`, see `Config::set`.
+    pub fn set(mut self, key: &str, value: &str) -> Result<Self, String> {
+        self.config.set(key, value)?;
+        Ok(self)
+    }
+
+    pub fn rom(mut self, path: &str) -> Self {
+        self.config.set_file_path(path.to_string());
+        self
+    }
+
+    /// Returns the config, the palette must be a built in one or one of the palette file.
+    pub fn build(self) -> Result<Config, String> {
+        let name = self.config.get_palette();
+        if self.config.get_palettes().get(name).is_none() {
+            return Err(format!("unknown palette {}", name));
+        }
+        Ok(self.config)
+    }
+}
+
+/// Cut the comment off the line, a `#` within the quotes is kept.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Returns the text of the value: a string in quotes, a list joined by commas, or a number or
+/// boolean as it is.
+fn parse_value(value: &str) -> Result<String, String> {
+    if let Some(list) = value.strip_prefix('[') {
+        let list = list
+            .strip_suffix(']')
+            .ok_or_else(|| format!("the list {} is not closed", value))?;
+        let items = list
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(parse_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(items.join(","));
+    }
+    match value.strip_prefix('"') {
+        Some(s) => s
+            .strip_suffix('"')
+            .map(str::to_string)
+            .ok_or_else(|| format!("the string {} is not closed", value)),
+        None if value.is_empty() => Err("the value is empty".to_string()),
+        None => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml() {
+        let config = Config::builder()
+            .toml(
+                r#"
+# The defaults of the window.
+[video]
+scale = 4
+filter = "hq2x"   # smooth
+title = "{title} #1"
+
+[debug]
+cpu_history = 0
+stack-check = true
+"#,
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(config.get_scale(), 4);
+        assert_eq!(config.get_filter(), Filter::Hq2x);
+        assert_eq!(config.get_title_format(), "{title} #1");
+        assert_eq!(config.get_cpu_history_size(), 0);
+        assert!(config.is_stack_check_enabled());
+
+        let err = Config::builder().toml("\nscale = 3").unwrap_err();
+        assert_eq!(err, "line 2: the scale 3 should be 1, 2, 4 or 8");
+        let err = Config::builder().toml("scal = 4").unwrap_err();
+        assert_eq!(err, "line 1: unknown setting scal");
+        assert!(Config::builder().toml("title = \"open").is_err());
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_toml_keymap() {
+        use minifb::Key;
+
+        let config = Config::builder()
+            .toml("[keymap]\na = [\"Z\", \"X\"]\nturbo-b = \"C\"")
+            .unwrap()
+            .build()
+            .unwrap();
+        let keymap = config.get_keymap();
+        assert_eq!(keymap.get_keys(&JoypadKey::A), vec![Key::Z, Key::X]);
+        assert_eq!(keymap.get_turbo_keys(&JoypadKey::B), vec![Key::C]);
+    }
+
+    #[test]
+    fn test_layers() {
+        let vars = vec![
+            ("NGC224_SCALE".to_string(), "8".to_string()),
+            ("NGC224_GREEN_TINT".to_string(), "true".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ];
+        let config = Config::builder()
+            .toml("scale = 4\nfilter = \"scanline\"\npalette = \"green\"")
+            .unwrap()
+            .vars(vars)
+            .unwrap()
+            .set("green-tint", "false")
+            .unwrap()
+            .build()
+            .unwrap();
+        // The environment wins over the file, the settings over both.
+        assert_eq!(config.get_scale(), 8);
+        assert_eq!(config.get_filter(), Filter::Scanline);
+        assert!(!config.is_green_tint_enabled());
+
+        let err = Config::builder().set("palette", "sepia").unwrap().build();
+        assert_eq!(err.unwrap_err(), "unknown palette sepia");
     }
 
-    pub fn get_file_path(&self) -> &str {
-        &self.file_path
+    #[test]
+    fn test_scale_is_checked() {
+        let mut config = Config::default();
+        assert!(config.set_scale(4).is_ok());
+        assert!(config.set_scale(3).is_err());
+        assert_eq!(config.get_scale(), 4);
+        assert!(config.set("scale", "0").is_err());
     }
 }
@@ -1,22 +1,60 @@
 use std::cell::RefCell;
-use std::rc::Rc;
-use std::thread;
-use std::time;
-
-use super::super::get_global_term;
-use super::super::mmu::IOHandler;
-use super::opcode_set::OP_CODE_SET;
-use super::register::Flag;
+use std::fmt;
+
+use log::{error, warn};
+
+use super::super::Term;
+use super::super::mmu::{DataBus, IOHandler};
+use super::opcode_set::{CB_CODE_SET, OP_CODE_SET};
+use super::register::{Flag, IntFlag};
 use super::register::Register;
-use crate::gameboy::debug::insert_cpu_record;
-use crate::gameboy::debug::CPUDebugInfo;
-use crate::gameboy::spec::{STEP_CYCLES, STEP_TIME};
+use crate::gameboy::disasm;
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+
+/// A memory access of the CPU, in the order it's done.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryAccess {
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+/// The unknown opcode which locked up the CPU, the real CPU hangs until the power is cycled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuLock {
+    /// The address of the opcode.
+    pub pc: u16,
+    pub opcode: u8,
+}
+
+impl fmt::Display for CpuLock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (opcode, pc) = (self.opcode, self.pc);
+        write!(f, "the unknown opcode {:02x} at {:04x}", opcode, pc)
+    }
+}
+
+/// What `CPU::step` did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepResult {
+    /// The PC before the step.
+    pub pc: u16,
+    pub opcode: u8,
+    /// The disassembled instruction, or `INT`, `HALT`, `STOP` and `LOCKED` if no instruction is
+    /// executed.
+    pub mnemonic: String,
+    pub cycles: u32,
+    /// The reads and the writes, the fetch of the opcode and its operands included. A word is
+    /// accessed as the low byte then the high byte.
+    pub memory_accesses: Vec<MemoryAccess>,
+}
 
 pub struct CPU {
     pub reg: Register,
     // flag: FlagRegister,
     is_halt: bool,
-    data_bus: Rc<RefCell<dyn IOHandler>>,
+    // The CPU owns the memory, the emulator reaches the mmu through it.
+    data_bus: DataBus,
 
     /// The IME flag is used to disable all interrupts,
     /// overriding any enabled bits in the IE Register.
@@ -35,32 +73,66 @@ pub struct CPU {
     /// 0 - Disable all Interrupts
     /// 1 - Enable all Interrupts that are enabled in IE Register (FFFF)
     ime_flag: bool,
-
-    step_cycles: u32,
-    step_zero: time::Instant,
-    step_flip: bool,
-    speed_simulation: bool,
+    // EI is executed, IME will be set after the next instruction.
+    ime_pending: bool,
+    // STOP is executed, the CPU sleeps until a selected joypad line goes low.
+    is_stop: bool,
+    // HALT is executed with IME off and an interrupt pending, the next opcode byte is read twice.
+    halt_bug: bool,
+    // An unknown opcode is executed, nothing runs or wakes the CPU again.
+    lock: Option<CpuLock>,
+
+    // The count of executed instructions, interrupt dispatches and halted steps excluded.
+    instructions: u64,
+
+    // The memory accesses are recorded while `step` runs.
+    recording: bool,
+    accesses: RefCell<Vec<MemoryAccess>>,
+
+    // Warn when the stack leaves the work ram and the high ram, it's likely a bug of the game.
+    stack_check: bool,
+    // The stack is out of them, it's warned once until it's back.
+    stack_astray: bool,
+    stack_warnings: u32,
 }
 
 impl CPU {
-    pub fn new(data_bus: Rc<RefCell<dyn IOHandler>>, speed_simulation: bool) -> Self {
+    /// Returns the CPU of the DMG.
+    pub fn new(data_bus: impl Into<DataBus>) -> Self {
+        Self::with_term(data_bus, Term::GB)
+    }
+
+    /// Returns the CPU with the registers the boot rom of the hardware leaves behind.
+    pub fn with_term(data_bus: impl Into<DataBus>, term: Term) -> Self {
         let mut reg = Register::new();
-        let term = get_global_term();
         reg.init(term);
 
         Self {
             reg,
             is_halt: false,
-            data_bus,
+            data_bus: data_bus.into(),
             ime_flag: true,
-
-            step_cycles: 0,
-            step_zero: time::Instant::now(),
-            step_flip: false,
-            speed_simulation,
+            ime_pending: false,
+            is_stop: false,
+            halt_bug: false,
+            lock: None,
+            instructions: 0,
+            recording: false,
+            accesses: RefCell::new(vec![]),
+            stack_check: false,
+            stack_astray: false,
+            stack_warnings: 0,
         }
     }
 
+    pub fn get_bus(&self) -> &DataBus {
+        &self.data_bus
+    }
+
+    pub fn get_bus_mut(&mut self) -> &mut DataBus {
+        &mut self.data_bus
+    }
+
     pub fn get_reg_snapshot(&self) -> Register {
         self.reg.clone()
     }
@@ -75,11 +147,64 @@ impl CPU {
 
     pub fn disable_ime(&mut self) {
         self.ime_flag = false;
+        self.ime_pending = false;
+    }
+
+    /// Enable the IME after the next instruction, it's the behavior of EI.
+    pub fn enable_ime_delayed(&mut self) {
+        self.ime_pending = true;
+    }
+
+    pub fn is_ime_pending(&self) -> bool {
+        self.ime_pending
     }
 
     pub fn is_halt(&self) -> bool {
         self.is_halt
     }
+
+    pub fn is_stop(&self) -> bool {
+        self.is_stop
+    }
+
+    /// Returns the unknown opcode which locked up the CPU, if any.
+    pub fn get_lock(&self) -> Option<CpuLock> {
+        self.lock
+    }
+
+    pub fn get_instruction_count(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn set_stack_check(&mut self, enabled: bool) {
+        self.stack_check = enabled;
+        self.stack_astray = false;
+    }
+
+    /// Returns how many times the stack left the work ram and the high ram.
+    pub fn get_stack_warnings(&self) -> u32 {
+        self.stack_warnings
+    }
+
+    /// The stack is checked on every push and pop. It's at C000-DFFF or FF80-FFFE, E000 is the
+    /// top of an empty stack at the end of the work ram.
+    fn check_stack(&mut self) {
+        if !self.stack_check {
+            return;
+        }
+        let sp = self.reg.get_SP();
+        let astray = !matches!(sp, 0xc000..=0xe000 | 0xff80..=0xfffe);
+        if astray && !self.stack_astray {
+            self.stack_warnings += 1;
+            warn!(
+                "The stack moved to 0x{:04x}, out of the work ram and the high ram, PC is 0x{:04x}",
+                sp,
+                self.reg.get_PC()
+            );
+        }
+        self.stack_astray = astray;
+    }
+
     fn imm(&mut self) -> u8 {
         let v = self.read_byte_from_memory(self.reg.get_PC());
         self.reg.incr_PC();
@@ -108,6 +233,47 @@ impl CPU {
     }
 }
 
+impl Stateful for CPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u16(self.reg.get_AF());
+        w.put_u16(self.reg.get_BC());
+        w.put_u16(self.reg.get_DE());
+        w.put_u16(self.reg.get_HL());
+        w.put_u16(self.reg.get_SP());
+        w.put_u16(self.reg.get_PC());
+        w.put_bool(self.ime_flag);
+        w.put_bool(self.ime_pending);
+        w.put_bool(self.is_halt);
+        w.put_bool(self.is_stop);
+        w.put_bool(self.halt_bug);
+        w.put_bool(self.lock.is_some());
+        let lock = self.lock.unwrap_or(CpuLock { pc: 0, opcode: 0 });
+        w.put_u16(lock.pc);
+        w.put_u8(lock.opcode);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.reg.set_AF(r.get_u16()?);
+        self.reg.set_BC(r.get_u16()?);
+        self.reg.set_DE(r.get_u16()?);
+        self.reg.set_HL(r.get_u16()?);
+        self.reg.set_SP(r.get_u16()?);
+        self.reg.set_PC(r.get_u16()?);
+        self.ime_flag = r.get_bool()?;
+        self.ime_pending = r.get_bool()?;
+        self.is_halt = r.get_bool()?;
+        self.is_stop = r.get_bool()?;
+        self.halt_bug = r.get_bool()?;
+        let locked = r.get_bool()?;
+        let lock = CpuLock {
+            pc: r.get_u16()?,
+            opcode: r.get_u8()?,
+        };
+        self.lock = if locked { Some(lock) } else { None };
+        Ok(())
+    }
+}
+
 #[allow(non_snake_case)]
 impl CPU {
     /*
@@ -134,109 +300,168 @@ impl CPU {
     // 	}
     // }
 
+    // IF and IE are read straight from the bus, checking the interrupts is not an access of the
+    // instruction.
+    fn is_interrupt_pending(&self) -> bool {
+        let intf = self.data_bus.read_byte(0xff0f);
+        let inte = self.data_bus.read_byte(0xffff);
+        intf & inte & 0x1f != 0
+    }
+
     fn hi(&mut self) -> u32 {
-        if !self.is_halt && !self.is_ime_enabled() {
+        if !self.is_ime_enabled() {
             return 0;
         }
-        let intf = self.read_byte_from_memory(0xff0f);
-        let inte = self.read_byte_from_memory(0xffff);
-        let ii = intf & inte;
+        let intf = self.data_bus.read_byte(0xff0f);
+        let inte = self.data_bus.read_byte(0xffff);
+        // Only the lower 5 bits are interrupt sources, the rest of IF/IE are unused.
+        let ii = intf & inte & 0x1f;
         if ii == 0x00 {
             return 0;
         }
-        self.is_halt = false;
-        if !self.is_ime_enabled() {
-            return 0;
-        }
         self.disable_ime();
 
-        // Consumer an interrupter, the rest is written back to the register
-        let n = ii.trailing_zeros();
-        let intf = intf & !(1 << n);
-        self.write_byte_to_memory(0xff0f, intf);
+        // The dispatch takes 5 M-cycles: 2 waits, the 2 bytes of PC pushed and the jump. The
+        // interrupt is chosen after the upper byte is pushed, so pushing it into IE (SP is 0x0000)
+        // can cancel the interrupt, then PC is set to 0x0000 and IF is left as it is.
+        // See: https://github.com/Gekkio/mooneye-test-suite/blob/main/acceptance/interrupts/ie_push.s
+        let pc = self.reg.get_PC();
+        let sp = self.reg.get_SP().wrapping_sub(1);
+        self.write_byte_to_memory(sp, (pc >> 8) as u8);
+        let ii = self.data_bus.read_byte(0xff0f) & self.data_bus.read_byte(0xffff);
+        let sp = sp.wrapping_sub(1);
+        self.write_byte_to_memory(sp, pc as u8);
+        self.reg.set_SP(sp);
+        self.check_stack();
+        let flag = match IntFlag::highest(ii) {
+            Some(flag) => flag,
+            None => {
+                self.reg.set_PC(0x0000);
+                return 5;
+            }
+        };
 
-        self._stack_push(self.reg.get_PC());
+        // Consumer an interrupter, the rest is left in the register
+        self.data_bus.ack_interrupt(flag);
         // Set the PC to correspond interrupt process program:
         // V-Blank: 0x40
         // LCD: 0x48
         // TIMER: 0x50
         // JOYPAD: 0x60
         // Serial: 0x58
-        self.reg.set_PC(0x0040 | ((n as u16) << 3));
-        4
+        self.reg.set_PC(flag.get_vector());
+        5
     }
 
-    pub fn _next(&mut self) -> u32 {
+    pub fn next(&mut self) -> u32 {
+        if self.lock.is_some() {
+            return 4;
+        }
+        if self.is_stop {
+            // P10-P13 are low if a selected key is pressed.
+            if self.data_bus.read_byte(0xff00) & 0x0f == 0x0f {
+                return 4;
+            }
+            self.is_stop = false;
+        }
+        // The halted CPU polls IF & IE every M-cycle. It wakes up in the cycle after an interrupt
+        // is requested, then dispatches it, or goes on without servicing it if IME is off.
+        if self.is_halt {
+            if self.is_interrupt_pending() {
+                self.is_halt = false;
+            }
+            return 4;
+        }
         let cycles = {
             let c = self.hi();
             if c != 0 {
                 c * 4
-            } else if self.is_halt {
-                4
             } else {
-                self.execute_opcode()
+                let pending = self.ime_pending;
+                let c = self.execute_opcode();
+                // DI between EI and here cancels the pending flag.
+                if pending && self.ime_pending {
+                    self.ime_pending = false;
+                    self.ime_flag = true;
+                }
+                c
             }
         };
         cycles
     }
 
-    fn down_frequency(&mut self) {
-        self.step_flip = true;
-        self.step_cycles -= STEP_CYCLES;
-        let now = time::Instant::now();
-        let d = now.duration_since(self.step_zero);
-        let s = u64::from(STEP_TIME.saturating_sub(d.as_millis() as u32));
-        thread::sleep(time::Duration::from_millis(s));
-        self.step_zero = self
-            .step_zero
-            .checked_add(time::Duration::from_millis(u64::from(STEP_TIME)))
-            .unwrap();
-
-        if now.checked_duration_since(self.step_zero).is_some() {
-            self.step_zero = now;
-        }
-    }
-
-    pub fn next(&mut self) -> u32 {
-        if self.speed_simulation {
-            if self.step_cycles > STEP_CYCLES {
-                self.down_frequency();
-            }
-            let cycles = self._next();
-            self.step_cycles += cycles;
-            cycles
-        } else {
-            self._next()
+    /// Run `next` and report the memory it accessed, for the debuggers and the tests comparing
+    /// against another core. A halted or stopped CPU, or one dispatching an interrupt, executes
+    /// no instruction, then the opcode is the one at PC.
+    pub fn step(&mut self) -> StepResult {
+        let pc = self.reg.get_PC();
+        let bytes: Vec<u8> = (0..3)
+            .map(|i| self.data_bus.read_byte(pc.wrapping_add(i)))
+            .collect();
+        let (stop, count) = (self.is_stop, self.instructions);
+        self.recording = true;
+        let cycles = self.next();
+        self.recording = false;
+        let memory_accesses = self.accesses.take();
+        let mnemonic = if self.instructions != count {
+            disasm::decode(&bytes, pc).text
+        } else if self.lock.is_some() {
+            "LOCKED".to_string()
+        } else if !memory_accesses.is_empty() {
+            "INT".to_string()
+        } else if stop {
+            "STOP".to_string()
+        } else {
+            "HALT".to_string()
+        };
+        StepResult {
+            pc,
+            opcode: bytes[0],
+            mnemonic,
+            cycles,
+            memory_accesses,
         }
     }
 
-    pub fn flip(&mut self) -> bool {
-        let r = self.step_flip;
-        if r {
-            self.step_flip = false;
+    fn record(&self, addr: u16, value: u8, write: bool) {
+        if self.recording {
+            let access = MemoryAccess { addr, value, write };
+            self.accesses.borrow_mut().push(access);
         }
-        r
     }
 
     pub fn get_current_opcode(&self) -> u8 {
         self.imm_freeze()
     }
     pub fn execute_opcode(&mut self) -> u32 {
-        let opcode = self.imm();
+        let pc = self.reg.get_PC();
+        // The PC fails to increase after the HALT bug.
+        let opcode = if self.halt_bug {
+            self.halt_bug = false;
+            self.imm_freeze()
+        } else {
+            self.imm()
+        };
 
-        // TODO: 时钟周期这里有问题
         // if opcode != 0xCB {
         //     println!("cpu opcode is {:?}", opcode);
         //     println!("cpu reg is {:?}", format!("{:?}", self.reg).to_lowercase());
         // }
-        if opcode != 0xcb {
-            insert_cpu_record(CPUDebugInfo::new(self.reg.clone(), opcode, false));
+        let op = match &OP_CODE_SET[opcode as usize] {
+            Some(op) => op,
+            None => {
+                self.lock = Some(CpuLock { pc, opcode });
+                error!("The CPU is locked up by {}", self.lock.unwrap());
+                return 4;
+            }
+        };
+        self.instructions += 1;
+        if opcode == 0xcb {
+            let cb_opcode = self.data_bus.read_byte(self.reg.get_PC());
+            op.ex(self);
+            return CB_CODE_SET[cb_opcode as usize].get_clock();
         }
-
-        OP_CODE_SET
-            .get(&opcode)
-            .expect(&format!("unknown opcode is {}", opcode))
-            .ex(self)
+        op.ex(self)
     }
 
     pub fn get_opcode(&self) {}
@@ -244,56 +469,62 @@ impl CPU {
     pub fn exexute_forever(&mut self) {}
 
     pub fn read_byte_from_memory(&self, addr: u16) -> u8 {
-        let data = self.data_bus.borrow().read_byte(addr);
+        let data = self.data_bus.read_byte(addr);
         // println!("fuck read byte {}:{:02x}", addr, data);
+        self.record(addr, data, false);
         data
     }
 
     pub fn read_word_from_memory(&self, addr: u16) -> u16 {
-        let data = self.data_bus.borrow().read_word(addr);
+        let data = self.data_bus.read_word(addr);
         // println!("!!!! read byte {}:{:02x}", addr, data);
+        self.record(addr, data as u8, false);
+        self.record(addr.wrapping_add(1), (data >> 8) as u8, false);
         data
     }
 
     pub fn write_byte_to_memory(&mut self, addr: u16, data: u8) {
-        self.data_bus.borrow_mut().write_byte(addr, data);
+        self.data_bus.write_byte(addr, data);
+        self.record(addr, data, true);
     }
 
     pub fn write_word_to_memory(&mut self, addr: u16, data: u16) {
-        self.data_bus.borrow_mut().write_word(addr, data);
+        self.data_bus.write_word(addr, data);
+        self.record(addr, data as u8, true);
+        self.record(addr.wrapping_add(1), (data >> 8) as u8, true);
     }
 }
 
 // all opcodes here
 #[allow(non_snake_case)]
 impl CPU {
-    pub fn op_0x00(&mut self) -> u32 {
+    pub fn op_0x00(&mut self) -> bool {
         // just nop here
-        0
+        false
     }
 
     /// Read a word from the memory which PC pointed and assign to BC register.
-    pub fn op_0x01(&mut self) -> u32 {
+    pub fn op_0x01(&mut self) -> bool {
         let v = self.imm_word();
         self.reg.set_BC(v);
-        0
+        false
     }
 
     /// Write the value in A register to the memory which BC pointed.
-    pub fn op_0x02(&mut self) -> u32 {
+    pub fn op_0x02(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_BC(), self.reg.get_A());
-        0
+        false
     }
 
     /// Incr the value in BC register.
-    pub fn op_0x03(&mut self) -> u32 {
+    pub fn op_0x03(&mut self) -> bool {
         let v = self.reg.get_BC();
         self.reg.set_BC(v.wrapping_add(1));
-        0
+        false
     }
 
     // TODO why
-    pub fn op_0x04(&mut self) -> u32 {
+    pub fn op_0x04(&mut self) -> bool {
         let v = self.reg.get_B();
         let new_v = v.wrapping_add(1);
         self.reg.set_B(new_v);
@@ -311,10 +542,10 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry);
         }
 
-        0
+        false
     }
 
-    pub fn op_0x05(&mut self) -> u32 {
+    pub fn op_0x05(&mut self) -> bool {
         let v = self.reg.get_B();
         let new_v = v.wrapping_sub(1);
         self.reg.set_B(new_v);
@@ -331,18 +562,18 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x06(&mut self) -> u32 {
+    pub fn op_0x06(&mut self) -> bool {
         let v = self.imm();
         self.reg.set_B(v);
 
-        0
+        false
     }
 
     // 	OP:0x07 RLCA
-    pub fn op_0x07(&mut self) -> u32 {
+    pub fn op_0x07(&mut self) -> bool {
         let v = self.reg.get_A();
 
         // TODO 这个逻辑貌似有问题
@@ -366,16 +597,16 @@ impl CPU {
         // core.CPU.Flags.HalfCarry = false
         // core.CPU.Flags.Carry = origin > 0x7F
 
-        0
+        false
     }
 
-    pub fn op_0x08(&mut self) -> u32 {
+    pub fn op_0x08(&mut self) -> bool {
         let addr = self.imm_word();
         self.write_word_to_memory(addr, self.reg.get_SP());
-        0
+        false
     }
 
-    pub fn op_0x09(&mut self) -> u32 {
+    pub fn op_0x09(&mut self) -> bool {
         let old_hl = self.reg.get_HL();
         let old_bc = self.reg.get_BC();
 
@@ -395,22 +626,22 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry)
         }
 
-        0
+        false
     }
 
-    pub fn op_0x0A(&mut self) -> u32 {
+    pub fn op_0x0A(&mut self) -> bool {
         self.reg
             .set_A(self.read_byte_from_memory(self.reg.get_BC()));
-        0
+        false
     }
 
-    pub fn op_0x0B(&mut self) -> u32 {
+    pub fn op_0x0B(&mut self) -> bool {
         let v = self.reg.get_BC();
         self.reg.set_BC(v.wrapping_sub(1));
-        0
+        false
     }
 
-    pub fn op_0x0C(&mut self) -> u32 {
+    pub fn op_0x0C(&mut self) -> bool {
         let v = self.reg.get_C();
         let new_v = v.wrapping_add(1);
         self.reg.set_C(new_v);
@@ -428,10 +659,10 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry);
         }
 
-        0
+        false
     }
 
-    pub fn op_0x0D(&mut self) -> u32 {
+    pub fn op_0x0D(&mut self) -> bool {
         let v = self.reg.get_C();
         let new_v = v.wrapping_sub(1);
         self.reg.set_C(new_v);
@@ -448,18 +679,18 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x0E(&mut self) -> u32 {
+    pub fn op_0x0E(&mut self) -> bool {
         let v = self.imm();
         self.reg.set_C(v);
 
-        0
+        false
     }
 
     // 	OP:0x0F RRCA
-    pub fn op_0x0F(&mut self) -> u32 {
+    pub fn op_0x0F(&mut self) -> bool {
         let v = self.reg.get_A();
         if v & 0x01 == 0x01 {
             self.reg.set_A(v >> 1 | 0x80);
@@ -473,32 +704,40 @@ impl CPU {
         self.reg.unset_flag(Flag::Zero);
         self.reg.unset_flag(Flag::Sub);
 
-        0
+        false
     }
 
-    pub fn op_0x10(&mut self) -> u32 {
-        // TODO: Stop op code
-        0
+    pub fn op_0x10(&mut self) -> bool {
+        // STOP is followed by a padding byte.
+        self.imm();
+        // DIV is reset.
+        self.write_byte_to_memory(0xff04, 0x00);
+        // The speed switch prepared through KEY1 is done instead of stopping the CPU.
+        let mmu = self.data_bus.as_mmu_mut();
+        if !mmu.is_some_and(|mmu| mmu.switch_speed()) {
+            self.is_stop = true;
+        }
+        false
     }
 
-    pub fn op_0x11(&mut self) -> u32 {
+    pub fn op_0x11(&mut self) -> bool {
         let v = self.imm_word();
         self.reg.set_DE(v);
-        0
+        false
     }
 
-    pub fn op_0x12(&mut self) -> u32 {
+    pub fn op_0x12(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_DE(), self.reg.get_A());
-        0
+        false
     }
 
-    pub fn op_0x13(&mut self) -> u32 {
+    pub fn op_0x13(&mut self) -> bool {
         let v = self.reg.get_DE();
         self.reg.set_DE(v.wrapping_add(1));
-        0
+        false
     }
 
-    pub fn op_0x14(&mut self) -> u32 {
+    pub fn op_0x14(&mut self) -> bool {
         let v = self.reg.get_D();
         let new_v = v.wrapping_add(1);
         self.reg.set_D(new_v);
@@ -516,10 +755,10 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry);
         }
 
-        0
+        false
     }
 
-    pub fn op_0x15(&mut self) -> u32 {
+    pub fn op_0x15(&mut self) -> bool {
         let v = self.reg.get_D();
         let new_v = v.wrapping_sub(1);
         self.reg.set_D(new_v);
@@ -536,17 +775,17 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x16(&mut self) -> u32 {
+    pub fn op_0x16(&mut self) -> bool {
         let v = self.imm();
         self.reg.set_D(v);
 
-        0
+        false
     }
 
-    pub fn op_0x17(&mut self) -> u32 {
+    pub fn op_0x17(&mut self) -> bool {
         let is_carry_flag_set = self.reg.is_flag_set(Flag::Carry);
         let mut carry = 0;
         if is_carry_flag_set {
@@ -566,10 +805,10 @@ impl CPU {
 
         self.reg.set_A((v << 1 & 0xFF) | carry);
 
-        0
+        false
     }
 
-    pub fn op_0x18(&mut self) -> u32 {
+    pub fn op_0x18(&mut self) -> bool {
         // address := int32(core.CPU.Registers.PC) + int32(int8(core.getParameter8()))
         // core.CPU.Registers.PC = uint16(address)
 
@@ -578,10 +817,10 @@ impl CPU {
         //let address = (self.reg.get_PC() as i32 + v as i32) as u16;
         //self.reg.set_PC(address);
         self.alu_jr(v);
-        0
+        false
     }
 
-    pub fn op_0x19(&mut self) -> u32 {
+    pub fn op_0x19(&mut self) -> bool {
         let old_hl = self.reg.get_HL();
         let old_de = self.reg.get_DE();
 
@@ -601,22 +840,22 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry)
         }
 
-        0
+        false
     }
 
-    pub fn op_0x1A(&mut self) -> u32 {
+    pub fn op_0x1A(&mut self) -> bool {
         self.reg
             .set_A(self.read_byte_from_memory(self.reg.get_DE()));
-        0
+        false
     }
 
-    pub fn op_0x1B(&mut self) -> u32 {
+    pub fn op_0x1B(&mut self) -> bool {
         let v = self.reg.get_DE();
         self.reg.set_DE(v.wrapping_sub(1));
-        0
+        false
     }
 
-    pub fn op_0x1C(&mut self) -> u32 {
+    pub fn op_0x1C(&mut self) -> bool {
         let v = self.reg.get_E();
         let new_v = v.wrapping_add(1);
         self.reg.set_E(new_v);
@@ -633,10 +872,10 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x1D(&mut self) -> u32 {
+    pub fn op_0x1D(&mut self) -> bool {
         let v = self.reg.get_E();
         let new_v = v.wrapping_sub(1);
         self.reg.set_E(new_v);
@@ -653,16 +892,16 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x1E(&mut self) -> u32 {
+    pub fn op_0x1E(&mut self) -> bool {
         let v = self.imm();
         self.reg.set_E(v);
-        0
+        false
     }
 
-    pub fn op_0x1F(&mut self) -> u32 {
+    pub fn op_0x1F(&mut self) -> bool {
         let v = self.reg.get_A();
         let is_carry_flag_set = self.reg.is_flag_set(Flag::Carry);
         let mut carry = 0;
@@ -680,7 +919,7 @@ impl CPU {
             self.reg.unset_flag(Flag::Carry);
         }
 
-        0
+        false
     }
     fn alu_jr(&mut self, n: u8) {
         let n = n as i8;
@@ -688,50 +927,77 @@ impl CPU {
             .set_PC(((u32::from(self.reg.get_PC()) as i32) + i32::from(n)) as u16);
     }
 
-    // // JR IF
-    // fn alu_jr(&mut self, n: u8) {
-    //     let n = n as i8;
-    //     self.reg.set_PC(((u32::from(self.reg.get_PC()) as i32) + i32::from(n)) as u16);
-    // }
+    // The conditional jumps, calls and returns. The condition is the flag being set or cleared,
+    // i.e. Z, NZ, C and NC. The operand is fetched whether the branch is taken or not, the
+    // handlers return if it's taken and the cycles of both ways are in `OP_CODE_SET`.
 
-    pub fn op_0x20(&mut self) -> u32 {
+    fn _check_cc(&self, flag: Flag, set: bool) -> bool {
+        self.reg.is_flag_set(flag) == set
+    }
+
+    fn _jr_cc(&mut self, flag: Flag, set: bool) -> bool {
         let v = self.imm();
-        // let n = self.imm();
-        if !self.reg.is_flag_set(Flag::Zero) {
-            // let address = (self.reg.get_PC() as i32 + v as i32) as u16;
-            // 这里必须先 u32 然后 i32 为什么
-            // let address = ((u32::from(self.reg.get_PC()) as i32) + i32::from(v as i8)) as u16;
-            // self.reg.set_PC(address);
-            //
+        let taken = self._check_cc(flag, set);
+        if taken {
             self.alu_jr(v);
-            return 4;
         }
+        taken
+    }
+
+    fn _jp_cc(&mut self, flag: Flag, set: bool) -> bool {
+        let addr = self.imm_word();
+        let taken = self._check_cc(flag, set);
+        if taken {
+            self.reg.set_PC(addr);
+        }
+        taken
+    }
+
+    fn _call_cc(&mut self, flag: Flag, set: bool) -> bool {
+        let addr = self.imm_word();
+        let taken = self._check_cc(flag, set);
+        if taken {
+            self._stack_push(self.reg.get_PC());
+            self.reg.set_PC(addr);
+        }
+        taken
+    }
+
+    fn _ret_cc(&mut self, flag: Flag, set: bool) -> bool {
+        let taken = self._check_cc(flag, set);
+        if taken {
+            let addr = self._stack_pop();
+            self.reg.set_PC(addr);
+        }
+        taken
+    }
 
-        0
+    pub fn op_0x20(&mut self) -> bool {
+        self._jr_cc(Flag::Zero, false)
     }
 
-    pub fn op_0x21(&mut self) -> u32 {
+    pub fn op_0x21(&mut self) -> bool {
         let v = self.imm_word();
         self.reg.set_HL(v);
-        0
+        false
     }
 
-    pub fn op_0x22(&mut self) -> u32 {
+    pub fn op_0x22(&mut self) -> bool {
         let addr = self.reg.get_HL();
         let v = self.reg.get_A();
 
         self.write_byte_to_memory(addr, v);
         self.reg.incr_HL();
 
-        0
+        false
     }
 
-    pub fn op_0x23(&mut self) -> u32 {
+    pub fn op_0x23(&mut self) -> bool {
         self.reg.incr_HL();
-        0
+        false
     }
 
-    pub fn op_0x24(&mut self) -> u32 {
+    pub fn op_0x24(&mut self) -> bool {
         let v = self.reg.get_H();
         let new_v = v.wrapping_add(1);
         self.reg.set_H(new_v);
@@ -749,10 +1015,10 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry);
         }
 
-        0
+        false
     }
 
-    pub fn op_0x25(&mut self) -> u32 {
+    pub fn op_0x25(&mut self) -> bool {
         let v = self.reg.get_H();
         let new_v = v.wrapping_sub(1);
         self.reg.set_H(new_v);
@@ -769,19 +1035,19 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x26(&mut self) -> u32 {
+    pub fn op_0x26(&mut self) -> bool {
         // https://users.rust-lang.org/t/mutable-borrows-in-nested-function-calls/28028/2
         // https://rustc-dev-guide.rust-lang.org/borrow_check/two_phase_borrows.html
 
         let v = self.imm();
         self.reg.set_H(v);
-        0
+        false
     }
 
-    pub fn op_0x27(&mut self) -> u32 {
+    pub fn op_0x27(&mut self) -> bool {
         let mut v = self.reg.get_A();
         let mut adjust = if self.reg.is_flag_set(Flag::Carry) {
             0x60
@@ -818,25 +1084,14 @@ impl CPU {
         }
         self.reg.set_A(v);
 
-        0
+        false
     }
 
-    pub fn op_0x28(&mut self) -> u32 {
-        let v = self.imm();
-
-        if self.reg.is_flag_set(Flag::Zero) {
-            // let n = n as i8;
-            // self.reg.pc = ((u32::from(self.reg.pc) as i32) + i32::from(n)) as u16;
-
-            // let address = (self.reg.get_PC() as i32 + i32::from(v)) as u16;
-            // self.reg.set_PC(address);
-            self.alu_jr(v);
-            return 4;
-        }
-        0
+    pub fn op_0x28(&mut self) -> bool {
+        self._jr_cc(Flag::Zero, true)
     }
 
-    pub fn op_0x29(&mut self) -> u32 {
+    pub fn op_0x29(&mut self) -> bool {
         let v = self.reg.get_HL();
 
         let new_v = v.wrapping_add(v);
@@ -855,24 +1110,24 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry)
         }
 
-        0
+        false
     }
 
-    pub fn op_0x2A(&mut self) -> u32 {
+    pub fn op_0x2A(&mut self) -> bool {
         let addr = self.reg.get_HL();
         let data = self.read_byte_from_memory(addr);
         self.reg.set_A(data);
         self.reg.incr_HL();
-        0
+        false
     }
 
-    pub fn op_0x2B(&mut self) -> u32 {
+    pub fn op_0x2B(&mut self) -> bool {
         let v = self.reg.get_HL();
         self.reg.set_HL(v.wrapping_sub(1));
-        0
+        false
     }
 
-    pub fn op_0x2C(&mut self) -> u32 {
+    pub fn op_0x2C(&mut self) -> bool {
         let v = self.reg.get_L();
         let new_v = v.wrapping_add(1);
         self.reg.set_L(new_v);
@@ -889,10 +1144,10 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x2D(&mut self) -> u32 {
+    pub fn op_0x2D(&mut self) -> bool {
         let v = self.reg.get_L();
         let new_v = v.wrapping_sub(1);
         self.reg.set_L(new_v);
@@ -909,17 +1164,17 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x2E(&mut self) -> u32 {
+    pub fn op_0x2E(&mut self) -> bool {
         let v = self.imm();
         self.reg.set_L(v);
-        0
+        false
     }
 
     // 	OP:0x2F CPL
-    pub fn op_0x2F(&mut self) -> u32 {
+    pub fn op_0x2F(&mut self) -> bool {
         // core.CPU.Registers.A = 0XFF ^ core.CPU.Registers.A
         // core.CPU.Flags.Sub = true
         // core.CPU.Flags.HalfCarry = true
@@ -928,43 +1183,34 @@ impl CPU {
         self.reg.set_flag(Flag::Sub);
         self.reg.set_flag(Flag::HalfCarry);
 
-        0
+        false
     }
 
-    pub fn op_0x30(&mut self) -> u32 {
-        let v = self.imm(); // as i8;
-        if !self.reg.is_flag_set(Flag::Carry) {
-            // let address = (self.reg.get_PC() as i32 + v as i32) as u16;
-            // self.reg.set_PC(address);
-
-            self.alu_jr(v);
-            return 4;
-        }
-
-        0
+    pub fn op_0x30(&mut self) -> bool {
+        self._jr_cc(Flag::Carry, false)
     }
 
-    pub fn op_0x31(&mut self) -> u32 {
+    pub fn op_0x31(&mut self) -> bool {
         let v = self.imm_word();
         self.reg.set_SP(v);
-        0
+        false
     }
 
-    pub fn op_0x32(&mut self) -> u32 {
+    pub fn op_0x32(&mut self) -> bool {
         let addr = self.reg.get_HL();
         let data = self.reg.get_A();
         self.write_byte_to_memory(addr, data);
-        self.reg.set_HL(addr - 1);
-        0
+        self.reg.set_HL(addr.wrapping_sub(1));
+        false
     }
 
-    pub fn op_0x33(&mut self) -> u32 {
+    pub fn op_0x33(&mut self) -> bool {
         let v = self.reg.get_SP();
         self.reg.set_SP(v.wrapping_add(1));
-        0
+        false
     }
 
-    pub fn op_0x34(&mut self) -> u32 {
+    pub fn op_0x34(&mut self) -> bool {
         let addr = self.reg.get_HL();
         let v = self.read_byte_from_memory(addr);
         let new_v = v.wrapping_add(1);
@@ -982,10 +1228,10 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x35(&mut self) -> u32 {
+    pub fn op_0x35(&mut self) -> bool {
         let addr = self.reg.get_HL();
         let v = self.read_byte_from_memory(addr);
         let new_v = v.wrapping_sub(1);
@@ -1004,35 +1250,28 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry);
         }
 
-        0
+        false
     }
 
-    pub fn op_0x36(&mut self) -> u32 {
+    pub fn op_0x36(&mut self) -> bool {
         let addr = self.reg.get_HL();
         let v = self.imm();
         self.write_byte_to_memory(addr, v);
-        0
+        false
     }
 
-    pub fn op_0x37(&mut self) -> u32 {
+    pub fn op_0x37(&mut self) -> bool {
         self.reg.set_flag(Flag::Carry);
         self.reg.unset_flag(Flag::HalfCarry);
         self.reg.unset_flag(Flag::Sub);
-        0
+        false
     }
 
-    pub fn op_0x38(&mut self) -> u32 {
-        let v = self.imm(); // as i8;
-        if self.reg.is_flag_set(Flag::Carry) {
-            // let address = (self.reg.get_PC() as i32 + v as i32) as u16;
-            // self.reg.set_PC(address);
-            self.alu_jr(v);
-            return 4;
-        }
-        0
+    pub fn op_0x38(&mut self) -> bool {
+        self._jr_cc(Flag::Carry, true)
     }
 
-    pub fn op_0x39(&mut self) -> u32 {
+    pub fn op_0x39(&mut self) -> bool {
         let v = self.reg.get_SP();
         let a = self.reg.get_HL();
 
@@ -1052,24 +1291,24 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry)
         }
 
-        0
+        false
     }
 
-    pub fn op_0x3A(&mut self) -> u32 {
+    pub fn op_0x3A(&mut self) -> bool {
         let addr = self.reg.get_HL();
         let value = self.read_byte_from_memory(addr);
         self.reg.set_A(value);
         self.reg.set_HL(addr.wrapping_sub(1));
-        0
+        false
     }
 
-    pub fn op_0x3B(&mut self) -> u32 {
+    pub fn op_0x3B(&mut self) -> bool {
         let v = self.reg.get_SP();
         self.reg.set_SP(v.wrapping_sub(1));
-        0
+        false
     }
 
-    pub fn op_0x3C(&mut self) -> u32 {
+    pub fn op_0x3C(&mut self) -> bool {
         let v = self.reg.get_A();
         let new_v = v.wrapping_add(1);
         self.reg.set_A(new_v);
@@ -1087,10 +1326,10 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry);
         }
 
-        0
+        false
     }
 
-    pub fn op_0x3D(&mut self) -> u32 {
+    pub fn op_0x3D(&mut self) -> bool {
         let v = self.reg.get_A();
         let new_v = v.wrapping_sub(1);
         self.reg.set_A(new_v);
@@ -1107,406 +1346,414 @@ impl CPU {
         } else {
             self.reg.unset_flag(Flag::HalfCarry);
         }
-        0
+        false
     }
 
-    pub fn op_0x3E(&mut self) -> u32 {
+    pub fn op_0x3E(&mut self) -> bool {
         let v = self.imm();
         self.reg.set_A(v);
-        0
+        false
     }
 
-    pub fn op_0x3F(&mut self) -> u32 {
+    pub fn op_0x3F(&mut self) -> bool {
         self.reg.reverse_flag(Flag::Carry);
         self.reg.unset_flag(Flag::HalfCarry);
         self.reg.unset_flag(Flag::Sub);
-        0
+        false
     }
 
-    pub fn op_0x40(&mut self) -> u32 {
+    pub fn op_0x40(&mut self) -> bool {
         self.reg.set_B(self.reg.get_B());
-        0
+        false
     }
 
-    pub fn op_0x41(&mut self) -> u32 {
+    pub fn op_0x41(&mut self) -> bool {
         self.reg.set_B(self.reg.get_C());
-        0
+        false
     }
 
-    pub fn op_0x42(&mut self) -> u32 {
+    pub fn op_0x42(&mut self) -> bool {
         self.reg.set_B(self.reg.get_D());
-        0
+        false
     }
 
-    pub fn op_0x43(&mut self) -> u32 {
+    pub fn op_0x43(&mut self) -> bool {
         self.reg.set_B(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x44(&mut self) -> u32 {
+    pub fn op_0x44(&mut self) -> bool {
         self.reg.set_B(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x45(&mut self) -> u32 {
+    pub fn op_0x45(&mut self) -> bool {
         self.reg.set_B(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x46(&mut self) -> u32 {
+    pub fn op_0x46(&mut self) -> bool {
         self.reg
             .set_B(self.read_byte_from_memory(self.reg.get_HL()));
-        0
+        false
     }
 
-    pub fn op_0x47(&mut self) -> u32 {
+    pub fn op_0x47(&mut self) -> bool {
         self.reg.set_B(self.reg.get_A());
 
-        0
+        false
     }
 
-    pub fn op_0x48(&mut self) -> u32 {
+    pub fn op_0x48(&mut self) -> bool {
         self.reg.set_C(self.reg.get_B());
 
-        0
+        false
     }
 
-    pub fn op_0x49(&mut self) -> u32 {
+    pub fn op_0x49(&mut self) -> bool {
         self.reg.set_C(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x4A(&mut self) -> u32 {
+    pub fn op_0x4A(&mut self) -> bool {
         self.reg.set_C(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x4B(&mut self) -> u32 {
+    pub fn op_0x4B(&mut self) -> bool {
         self.reg.set_C(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x4C(&mut self) -> u32 {
+    pub fn op_0x4C(&mut self) -> bool {
         self.reg.set_C(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x4D(&mut self) -> u32 {
+    pub fn op_0x4D(&mut self) -> bool {
         self.reg.set_C(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x4E(&mut self) -> u32 {
+    pub fn op_0x4E(&mut self) -> bool {
         self.reg
             .set_C(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x4F(&mut self) -> u32 {
+    pub fn op_0x4F(&mut self) -> bool {
         self.reg.set_C(self.reg.get_A());
 
-        0
+        false
     }
 
-    pub fn op_0x50(&mut self) -> u32 {
+    pub fn op_0x50(&mut self) -> bool {
         self.reg.set_D(self.reg.get_B());
 
-        0
+        false
     }
 
-    pub fn op_0x51(&mut self) -> u32 {
+    pub fn op_0x51(&mut self) -> bool {
         self.reg.set_D(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x52(&mut self) -> u32 {
+    pub fn op_0x52(&mut self) -> bool {
         self.reg.set_D(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x53(&mut self) -> u32 {
+    pub fn op_0x53(&mut self) -> bool {
         self.reg.set_D(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x54(&mut self) -> u32 {
+    pub fn op_0x54(&mut self) -> bool {
         self.reg.set_D(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x55(&mut self) -> u32 {
+    pub fn op_0x55(&mut self) -> bool {
         self.reg.set_D(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x56(&mut self) -> u32 {
+    pub fn op_0x56(&mut self) -> bool {
         self.reg
             .set_D(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x57(&mut self) -> u32 {
+    pub fn op_0x57(&mut self) -> bool {
         self.reg.set_D(self.reg.get_A());
 
-        0
+        false
     }
 
-    pub fn op_0x58(&mut self) -> u32 {
+    pub fn op_0x58(&mut self) -> bool {
         self.reg.set_E(self.reg.get_B());
 
-        0
+        false
     }
 
-    pub fn op_0x59(&mut self) -> u32 {
+    pub fn op_0x59(&mut self) -> bool {
         self.reg.set_E(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x5A(&mut self) -> u32 {
+    pub fn op_0x5A(&mut self) -> bool {
         self.reg.set_E(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x5B(&mut self) -> u32 {
+    pub fn op_0x5B(&mut self) -> bool {
         self.reg.set_E(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x5C(&mut self) -> u32 {
+    pub fn op_0x5C(&mut self) -> bool {
         self.reg.set_E(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x5D(&mut self) -> u32 {
+    pub fn op_0x5D(&mut self) -> bool {
         self.reg.set_E(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x5E(&mut self) -> u32 {
+    pub fn op_0x5E(&mut self) -> bool {
         self.reg
             .set_E(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x5F(&mut self) -> u32 {
+    pub fn op_0x5F(&mut self) -> bool {
         self.reg.set_E(self.reg.get_A());
 
-        0
+        false
     }
 
-    pub fn op_0x60(&mut self) -> u32 {
+    pub fn op_0x60(&mut self) -> bool {
         self.reg.set_H(self.reg.get_B());
 
-        0
+        false
     }
 
-    pub fn op_0x61(&mut self) -> u32 {
+    pub fn op_0x61(&mut self) -> bool {
         self.reg.set_H(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x62(&mut self) -> u32 {
+    pub fn op_0x62(&mut self) -> bool {
         self.reg.set_H(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x63(&mut self) -> u32 {
+    pub fn op_0x63(&mut self) -> bool {
         self.reg.set_H(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x64(&mut self) -> u32 {
+    pub fn op_0x64(&mut self) -> bool {
         self.reg.set_H(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x65(&mut self) -> u32 {
+    pub fn op_0x65(&mut self) -> bool {
         self.reg.set_H(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x66(&mut self) -> u32 {
+    pub fn op_0x66(&mut self) -> bool {
         self.reg
             .set_H(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x67(&mut self) -> u32 {
+    pub fn op_0x67(&mut self) -> bool {
         self.reg.set_H(self.reg.get_A());
 
-        0
+        false
     }
 
-    pub fn op_0x68(&mut self) -> u32 {
+    pub fn op_0x68(&mut self) -> bool {
         self.reg.set_L(self.reg.get_B());
 
-        0
+        false
     }
 
-    pub fn op_0x69(&mut self) -> u32 {
+    pub fn op_0x69(&mut self) -> bool {
         self.reg.set_L(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x6A(&mut self) -> u32 {
+    pub fn op_0x6A(&mut self) -> bool {
         self.reg.set_L(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x6B(&mut self) -> u32 {
+    pub fn op_0x6B(&mut self) -> bool {
         self.reg.set_L(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x6C(&mut self) -> u32 {
+    pub fn op_0x6C(&mut self) -> bool {
         self.reg.set_L(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x6D(&mut self) -> u32 {
+    pub fn op_0x6D(&mut self) -> bool {
         self.reg.set_L(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x6E(&mut self) -> u32 {
+    pub fn op_0x6E(&mut self) -> bool {
         self.reg
             .set_L(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x6F(&mut self) -> u32 {
+    pub fn op_0x6F(&mut self) -> bool {
         self.reg.set_L(self.reg.get_A());
 
-        0
+        false
     }
 
-    pub fn op_0x70(&mut self) -> u32 {
+    pub fn op_0x70(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_HL(), self.reg.get_B());
-        0
+        false
     }
 
-    pub fn op_0x71(&mut self) -> u32 {
+    pub fn op_0x71(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_HL(), self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x72(&mut self) -> u32 {
+    pub fn op_0x72(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_HL(), self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x73(&mut self) -> u32 {
+    pub fn op_0x73(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_HL(), self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x74(&mut self) -> u32 {
+    pub fn op_0x74(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_HL(), self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x75(&mut self) -> u32 {
+    pub fn op_0x75(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_HL(), self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x76(&mut self) -> u32 {
-        self.is_halt = true;
+    pub fn op_0x76(&mut self) -> bool {
+        // With IME off, HALT returns at once if an interrupt is pending and the bug happens.
+        // Right after EI the bug makes the interrupt return to the HALT, so it's executed again.
+        if self.ime_flag || !self.is_interrupt_pending() {
+            self.is_halt = true;
+        } else if self.ime_pending {
+            self.reg.set_PC(self.reg.get_PC().wrapping_sub(1));
+        } else {
+            self.halt_bug = true;
+        }
         // info!("halt opcode!!");
-        0
+        false
     }
 
-    pub fn op_0x77(&mut self) -> u32 {
+    pub fn op_0x77(&mut self) -> bool {
         self.write_byte_to_memory(self.reg.get_HL(), self.reg.get_A());
 
-        0
+        false
     }
 
-    pub fn op_0x78(&mut self) -> u32 {
+    pub fn op_0x78(&mut self) -> bool {
         self.reg.set_A(self.reg.get_B());
 
-        0
+        false
     }
 
-    pub fn op_0x79(&mut self) -> u32 {
+    pub fn op_0x79(&mut self) -> bool {
         self.reg.set_A(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x7A(&mut self) -> u32 {
+    pub fn op_0x7A(&mut self) -> bool {
         self.reg.set_A(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x7B(&mut self) -> u32 {
+    pub fn op_0x7B(&mut self) -> bool {
         self.reg.set_A(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x7C(&mut self) -> u32 {
+    pub fn op_0x7C(&mut self) -> bool {
         self.reg.set_A(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x7D(&mut self) -> u32 {
+    pub fn op_0x7D(&mut self) -> bool {
         self.reg.set_A(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x7E(&mut self) -> u32 {
+    pub fn op_0x7E(&mut self) -> bool {
         self.reg
             .set_A(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x7F(&mut self) -> u32 {
+    pub fn op_0x7F(&mut self) -> bool {
         self.reg.set_A(self.reg.get_A());
 
-        0
+        false
     }
 
     fn _op_add(&mut self, v: u8) {
@@ -1533,51 +1780,51 @@ impl CPU {
             self.reg.unset_flag(Flag::Carry);
         }
     }
-    pub fn op_0x80(&mut self) -> u32 {
+    pub fn op_0x80(&mut self) -> bool {
         self._op_add(self.reg.get_B());
-        0
+        false
     }
 
-    pub fn op_0x81(&mut self) -> u32 {
+    pub fn op_0x81(&mut self) -> bool {
         self._op_add(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x82(&mut self) -> u32 {
+    pub fn op_0x82(&mut self) -> bool {
         self._op_add(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x83(&mut self) -> u32 {
+    pub fn op_0x83(&mut self) -> bool {
         self._op_add(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x84(&mut self) -> u32 {
+    pub fn op_0x84(&mut self) -> bool {
         self._op_add(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x85(&mut self) -> u32 {
+    pub fn op_0x85(&mut self) -> bool {
         self._op_add(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x86(&mut self) -> u32 {
+    pub fn op_0x86(&mut self) -> bool {
         self._op_add(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x87(&mut self) -> u32 {
+    pub fn op_0x87(&mut self) -> bool {
         self._op_add(self.reg.get_A());
 
-        0
+        false
     }
 
     // ADC
@@ -1613,51 +1860,51 @@ impl CPU {
         }
     }
 
-    pub fn op_0x88(&mut self) -> u32 {
+    pub fn op_0x88(&mut self) -> bool {
         self._op_adc(self.reg.get_B());
-        0
+        false
     }
 
-    pub fn op_0x89(&mut self) -> u32 {
+    pub fn op_0x89(&mut self) -> bool {
         self._op_adc(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x8A(&mut self) -> u32 {
+    pub fn op_0x8A(&mut self) -> bool {
         self._op_adc(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x8B(&mut self) -> u32 {
+    pub fn op_0x8B(&mut self) -> bool {
         self._op_adc(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x8C(&mut self) -> u32 {
+    pub fn op_0x8C(&mut self) -> bool {
         self._op_adc(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x8D(&mut self) -> u32 {
+    pub fn op_0x8D(&mut self) -> bool {
         self._op_adc(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x8E(&mut self) -> u32 {
+    pub fn op_0x8E(&mut self) -> bool {
         self._op_adc(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x8F(&mut self) -> u32 {
+    pub fn op_0x8F(&mut self) -> bool {
         self._op_adc(self.reg.get_A());
 
-        0
+        false
     }
 
     fn _op_sub(&mut self, v: u8) {
@@ -1685,51 +1932,51 @@ impl CPU {
             self.reg.unset_flag(Flag::HalfCarry);
         }
     }
-    pub fn op_0x90(&mut self) -> u32 {
+    pub fn op_0x90(&mut self) -> bool {
         self._op_sub(self.reg.get_B());
-        0
+        false
     }
 
-    pub fn op_0x91(&mut self) -> u32 {
+    pub fn op_0x91(&mut self) -> bool {
         self._op_sub(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x92(&mut self) -> u32 {
+    pub fn op_0x92(&mut self) -> bool {
         self._op_sub(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x93(&mut self) -> u32 {
+    pub fn op_0x93(&mut self) -> bool {
         self._op_sub(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x94(&mut self) -> u32 {
+    pub fn op_0x94(&mut self) -> bool {
         self._op_sub(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x95(&mut self) -> u32 {
+    pub fn op_0x95(&mut self) -> bool {
         self._op_sub(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x96(&mut self) -> u32 {
+    pub fn op_0x96(&mut self) -> bool {
         self._op_sub(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x97(&mut self) -> u32 {
+    pub fn op_0x97(&mut self) -> bool {
         self._op_sub(self.reg.get_A());
 
-        0
+        false
     }
 
     fn _op_sbc(&mut self, v: u8) {
@@ -1763,51 +2010,51 @@ impl CPU {
         }
     }
 
-    pub fn op_0x98(&mut self) -> u32 {
+    pub fn op_0x98(&mut self) -> bool {
         self._op_sbc(self.reg.get_B());
-        0
+        false
     }
 
-    pub fn op_0x99(&mut self) -> u32 {
+    pub fn op_0x99(&mut self) -> bool {
         self._op_sbc(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0x9A(&mut self) -> u32 {
+    pub fn op_0x9A(&mut self) -> bool {
         self._op_sbc(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0x9B(&mut self) -> u32 {
+    pub fn op_0x9B(&mut self) -> bool {
         self._op_sbc(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0x9C(&mut self) -> u32 {
+    pub fn op_0x9C(&mut self) -> bool {
         self._op_sbc(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0x9D(&mut self) -> u32 {
+    pub fn op_0x9D(&mut self) -> bool {
         self._op_sbc(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0x9E(&mut self) -> u32 {
+    pub fn op_0x9E(&mut self) -> bool {
         self._op_sbc(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0x9F(&mut self) -> u32 {
+    pub fn op_0x9F(&mut self) -> bool {
         self._op_sbc(self.reg.get_A());
 
-        0
+        false
     }
 
     fn _op_and(&mut self, v: u8) {
@@ -1826,51 +2073,51 @@ impl CPU {
         }
     }
 
-    pub fn op_0xA0(&mut self) -> u32 {
+    pub fn op_0xA0(&mut self) -> bool {
         self._op_and(self.reg.get_B());
-        0
+        false
     }
 
-    pub fn op_0xA1(&mut self) -> u32 {
+    pub fn op_0xA1(&mut self) -> bool {
         self._op_and(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0xA2(&mut self) -> u32 {
+    pub fn op_0xA2(&mut self) -> bool {
         self._op_and(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0xA3(&mut self) -> u32 {
+    pub fn op_0xA3(&mut self) -> bool {
         self._op_and(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0xA4(&mut self) -> u32 {
+    pub fn op_0xA4(&mut self) -> bool {
         self._op_and(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0xA5(&mut self) -> u32 {
+    pub fn op_0xA5(&mut self) -> bool {
         self._op_and(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0xA6(&mut self) -> u32 {
+    pub fn op_0xA6(&mut self) -> bool {
         self._op_and(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0xA7(&mut self) -> u32 {
+    pub fn op_0xA7(&mut self) -> bool {
         self._op_and(self.reg.get_A());
 
-        0
+        false
     }
 
     fn _op_xor(&mut self, v: u8) {
@@ -1889,52 +2136,52 @@ impl CPU {
         self.reg.unset_flag(Flag::Carry);
     }
 
-    pub fn op_0xA8(&mut self) -> u32 {
+    pub fn op_0xA8(&mut self) -> bool {
         self._op_xor(self.reg.get_B());
 
-        0
+        false
     }
 
-    pub fn op_0xA9(&mut self) -> u32 {
+    pub fn op_0xA9(&mut self) -> bool {
         self._op_xor(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0xAA(&mut self) -> u32 {
+    pub fn op_0xAA(&mut self) -> bool {
         self._op_xor(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0xAB(&mut self) -> u32 {
+    pub fn op_0xAB(&mut self) -> bool {
         self._op_xor(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0xAC(&mut self) -> u32 {
+    pub fn op_0xAC(&mut self) -> bool {
         self._op_xor(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0xAD(&mut self) -> u32 {
+    pub fn op_0xAD(&mut self) -> bool {
         self._op_xor(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0xAE(&mut self) -> u32 {
+    pub fn op_0xAE(&mut self) -> bool {
         self._op_xor(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0xAF(&mut self) -> u32 {
+    pub fn op_0xAF(&mut self) -> bool {
         self._op_xor(self.reg.get_A());
 
-        0
+        false
     }
 
     fn _op_or(&mut self, v: u8) {
@@ -1953,52 +2200,52 @@ impl CPU {
         self.reg.unset_flag(Flag::Carry);
     }
 
-    pub fn op_0xB0(&mut self) -> u32 {
+    pub fn op_0xB0(&mut self) -> bool {
         self._op_or(self.reg.get_B());
 
-        0
+        false
     }
 
-    pub fn op_0xB1(&mut self) -> u32 {
+    pub fn op_0xB1(&mut self) -> bool {
         self._op_or(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0xB2(&mut self) -> u32 {
+    pub fn op_0xB2(&mut self) -> bool {
         self._op_or(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0xB3(&mut self) -> u32 {
+    pub fn op_0xB3(&mut self) -> bool {
         self._op_or(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0xB4(&mut self) -> u32 {
+    pub fn op_0xB4(&mut self) -> bool {
         self._op_or(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0xB5(&mut self) -> u32 {
+    pub fn op_0xB5(&mut self) -> bool {
         self._op_or(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0xB6(&mut self) -> u32 {
+    pub fn op_0xB6(&mut self) -> bool {
         self._op_or(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0xB7(&mut self) -> u32 {
+    pub fn op_0xB7(&mut self) -> bool {
         self._op_or(self.reg.get_A());
 
-        0
+        false
     }
 
     fn _op_compare(&mut self, v: u8) {
@@ -2025,207 +2272,165 @@ impl CPU {
         }
     }
 
-    pub fn op_0xB8(&mut self) -> u32 {
+    pub fn op_0xB8(&mut self) -> bool {
         self._op_compare(self.reg.get_B());
-        0
+        false
     }
 
-    pub fn op_0xB9(&mut self) -> u32 {
+    pub fn op_0xB9(&mut self) -> bool {
         self._op_compare(self.reg.get_C());
 
-        0
+        false
     }
 
-    pub fn op_0xBA(&mut self) -> u32 {
+    pub fn op_0xBA(&mut self) -> bool {
         self._op_compare(self.reg.get_D());
 
-        0
+        false
     }
 
-    pub fn op_0xBB(&mut self) -> u32 {
+    pub fn op_0xBB(&mut self) -> bool {
         self._op_compare(self.reg.get_E());
 
-        0
+        false
     }
 
-    pub fn op_0xBC(&mut self) -> u32 {
+    pub fn op_0xBC(&mut self) -> bool {
         self._op_compare(self.reg.get_H());
 
-        0
+        false
     }
 
-    pub fn op_0xBD(&mut self) -> u32 {
+    pub fn op_0xBD(&mut self) -> bool {
         self._op_compare(self.reg.get_L());
 
-        0
+        false
     }
 
-    pub fn op_0xBE(&mut self) -> u32 {
+    pub fn op_0xBE(&mut self) -> bool {
         self._op_compare(self.read_byte_from_memory(self.reg.get_HL()));
 
-        0
+        false
     }
 
-    pub fn op_0xBF(&mut self) -> u32 {
+    pub fn op_0xBF(&mut self) -> bool {
         self._op_compare(self.reg.get_A());
 
-        0
+        false
     }
 
     fn _stack_pop(&mut self) -> u16 {
         let addr = self.reg.get_SP();
         let data = self.read_word_from_memory(addr);
-        self.reg.set_SP(addr + 2);
+        self.reg.set_SP(addr.wrapping_add(2));
+        self.check_stack();
         data
     }
 
-    pub fn op_0xC0(&mut self) -> u32 {
-        if !self.reg.is_flag_set(Flag::Zero) {
-            let v = self._stack_pop();
-            self.reg.set_PC(v);
-            return 12;
-        }
-        0
+    pub fn op_0xC0(&mut self) -> bool {
+        self._ret_cc(Flag::Zero, false)
     }
 
-    pub fn op_0xC1(&mut self) -> u32 {
+    pub fn op_0xC1(&mut self) -> bool {
         let v = self._stack_pop();
         self.reg.set_BC(v);
-        0
+        false
     }
 
-    pub fn op_0xC2(&mut self) -> u32 {
-        let addr = self.imm_word();
-        if !self.reg.is_flag_set(Flag::Zero) {
-            self.reg.set_PC(addr);
-            return 4;
-        }
-        0
+    pub fn op_0xC2(&mut self) -> bool {
+        self._jp_cc(Flag::Zero, false)
     }
 
-    pub fn op_0xC3(&mut self) -> u32 {
+    pub fn op_0xC3(&mut self) -> bool {
         let v = self.imm_word();
         self.reg.set_PC(v);
-        0
+        false
     }
 
     fn _stack_push(&mut self, data: u16) {
         let sp = self.reg.get_SP();
-        let new_sp = sp - 2;
+        let new_sp = sp.wrapping_sub(2);
         self.reg.set_SP(new_sp);
         self.write_word_to_memory(new_sp, data);
+        self.check_stack();
     }
 
-    pub fn op_0xC4(&mut self) -> u32 {
-        let v = self.imm_word();
-        if !self.reg.is_flag_set(Flag::Zero) {
-            self._stack_push(self.reg.get_PC());
-            self.reg.set_PC(v);
-            return 14;
-        }
-        0
+    pub fn op_0xC4(&mut self) -> bool {
+        self._call_cc(Flag::Zero, false)
     }
 
-    pub fn op_0xC5(&mut self) -> u32 {
+    pub fn op_0xC5(&mut self) -> bool {
         self._stack_push(self.reg.get_BC());
-        0
+        false
     }
 
-    pub fn op_0xC6(&mut self) -> u32 {
+    pub fn op_0xC6(&mut self) -> bool {
         let v = self.imm();
         self._op_add(v);
 
-        0
+        false
     }
 
-    pub fn op_0xC7(&mut self) -> u32 {
+    pub fn op_0xC7(&mut self) -> bool {
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(0x0000);
-        0
+        false
     }
 
-    pub fn op_0xC8(&mut self) -> u32 {
-        if self.reg.is_flag_set(Flag::Zero) {
-            let v = self._stack_pop();
-            self.reg.set_PC(v);
-            return 12;
-        }
-        0
+    pub fn op_0xC8(&mut self) -> bool {
+        self._ret_cc(Flag::Zero, true)
     }
 
-    pub fn op_0xC9(&mut self) -> u32 {
+    pub fn op_0xC9(&mut self) -> bool {
         let v = self._stack_pop();
         self.reg.set_PC(v);
-        0
+        false
     }
 
-    pub fn op_0xCA(&mut self) -> u32 {
-        let addr = self.imm_word();
-        if self.reg.is_flag_set(Flag::Zero) {
-            self.reg.set_PC(addr);
-            return 4;
-        }
-
-        0
+    pub fn op_0xCA(&mut self) -> bool {
+        self._jp_cc(Flag::Zero, true)
     }
 
-    pub fn op_0xCC(&mut self) -> u32 {
-        let v = self.imm_word();
-        if self.reg.is_flag_set(Flag::Zero) {
-            self._stack_push(self.reg.get_PC());
-            self.reg.set_PC(v);
-            return 12;
-        }
-
-        0
+    pub fn op_0xCC(&mut self) -> bool {
+        self._call_cc(Flag::Zero, true)
     }
 
-    pub fn op_0xCD(&mut self) -> u32 {
+    pub fn op_0xCD(&mut self) -> bool {
         let v = self.imm_word();
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(v);
 
-        0
+        false
     }
 
-    pub fn op_0xCE(&mut self) -> u32 {
+    pub fn op_0xCE(&mut self) -> bool {
         let v = self.imm();
         self._op_adc(v);
 
-        0
+        false
     }
 
-    pub fn op_0xCF(&mut self) -> u32 {
+    pub fn op_0xCF(&mut self) -> bool {
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(0x0008);
-        0
+        false
     }
 
-    pub fn op_0xD0(&mut self) -> u32 {
-        if !self.reg.is_flag_set(Flag::Carry) {
-            let v = self._stack_pop();
-            self.reg.set_PC(v);
-            return 12;
-        }
-        0
+    pub fn op_0xD0(&mut self) -> bool {
+        self._ret_cc(Flag::Carry, false)
     }
 
-    pub fn op_0xD1(&mut self) -> u32 {
+    pub fn op_0xD1(&mut self) -> bool {
         // self.reg.set_DE(self._stack_pop());
 
         let v = self._stack_pop();
         self.reg.set_DE(v);
 
-        0
+        false
     }
 
-    pub fn op_0xD2(&mut self) -> u32 {
-        let addr = self.imm_word();
-        if !self.reg.is_flag_set(Flag::Carry) {
-            self.reg.set_PC(addr);
-            return 4;
-        }
-        0
+    pub fn op_0xD2(&mut self) -> bool {
+        self._jp_cc(Flag::Carry, false)
     }
 
     // pub fn op_0xD3(&mut self) -> u32 {
@@ -2233,60 +2438,44 @@ impl CPU {
     //     0
     // }
 
-    pub fn op_0xD4(&mut self) -> u32 {
-        let v = self.imm_word();
-        if !self.reg.is_flag_set(Flag::Carry) {
-            self._stack_push(self.reg.get_PC());
-            self.reg.set_PC(v);
-            return 14;
-        }
-        0
+    pub fn op_0xD4(&mut self) -> bool {
+        self._call_cc(Flag::Carry, false)
     }
 
-    pub fn op_0xD5(&mut self) -> u32 {
+    pub fn op_0xD5(&mut self) -> bool {
         self._stack_push(self.reg.get_DE());
 
-        0
+        false
     }
 
-    pub fn op_0xD6(&mut self) -> u32 {
+    pub fn op_0xD6(&mut self) -> bool {
         let v = self.imm();
         self._op_sub(v);
-        0
+        false
     }
 
-    pub fn op_0xD7(&mut self) -> u32 {
+    pub fn op_0xD7(&mut self) -> bool {
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(0x0010);
-        0
+        false
     }
 
-    pub fn op_0xD8(&mut self) -> u32 {
-        if self.reg.is_flag_set(Flag::Carry) {
-            let v = self._stack_pop();
-            self.reg.set_PC(v);
-            return 12;
-        }
-        0
+    pub fn op_0xD8(&mut self) -> bool {
+        self._ret_cc(Flag::Carry, true)
     }
 
-    pub fn op_0xD9(&mut self) -> u32 {
+    pub fn op_0xD9(&mut self) -> bool {
         let v = self._stack_pop();
         self.reg.set_PC(v);
         self.enable_ime();
-        0
+        false
     }
 
-    pub fn op_0xDA(&mut self) -> u32 {
-        let addr = self.imm_word();
-        if self.reg.is_flag_set(Flag::Carry) {
-            self.reg.set_PC(addr);
-            return 4;
-        }
-        0
+    pub fn op_0xDA(&mut self) -> bool {
+        self._jp_cc(Flag::Carry, true)
     }
 
-    pub fn op_0xDB(&mut self) -> u32 {
+    pub fn op_0xDB(&mut self) -> bool {
         // address := core.getParameter16()
         // if core.CPU.Flags.Carry {
         //     core.StackPush(core.CPU.Registers.PC)
@@ -2299,20 +2488,14 @@ impl CPU {
         if self.reg.is_flag_set(Flag::Carry) {
             self._stack_push(self.reg.get_PC());
             self.reg.set_PC(addr);
-            return 12;
+            return true;
         }
 
-        0
+        false
     }
 
-    pub fn op_0xDC(&mut self) -> u32 {
-        let v = self.imm_word();
-        if self.reg.is_flag_set(Flag::Carry) {
-            self._stack_push(self.reg.get_PC());
-            self.reg.set_PC(v);
-            return 14;
-        }
-        0
+    pub fn op_0xDC(&mut self) -> bool {
+        self._call_cc(Flag::Carry, true)
     }
 
     // pub fn op_0xDd(&mut self) -> u32 {
@@ -2320,19 +2503,19 @@ impl CPU {
     //     0
     // }
 
-    pub fn op_0xDE(&mut self) -> u32 {
+    pub fn op_0xDE(&mut self) -> bool {
         let v = self.imm();
         self._op_sbc(v);
-        0
+        false
     }
 
-    pub fn op_0xDF(&mut self) -> u32 {
+    pub fn op_0xDF(&mut self) -> bool {
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(0x18);
-        0
+        false
     }
 
-    pub fn op_0xE0(&mut self) -> u32 {
+    pub fn op_0xE0(&mut self) -> bool {
         // NOTE
         let addr = 0xFF00 | u16::from(self.imm());
         let data = self.reg.get_A();
@@ -2342,20 +2525,20 @@ impl CPU {
         // self.mem.borrow_mut().set(a, self.reg.a);
 
         // core.WriteMemory(0xFF00+uint16(core.getParameter8()), core.CPU.Registers.A)
-        0
+        false
     }
 
-    pub fn op_0xE1(&mut self) -> u32 {
+    pub fn op_0xE1(&mut self) -> bool {
         let v = self._stack_pop();
         self.reg.set_HL(v);
-        0
+        false
     }
 
-    pub fn op_0xE2(&mut self) -> u32 {
+    pub fn op_0xE2(&mut self) -> bool {
         let addr = 0xFF00 | u16::from(self.reg.get_C());
         let data = self.reg.get_A();
         self.write_byte_to_memory(addr, data);
-        0
+        false
     }
 
     // pub fn op_0xe3(&mut self) -> u32 {
@@ -2366,24 +2549,24 @@ impl CPU {
     //     0
     // }
 
-    pub fn op_0xE5(&mut self) -> u32 {
+    pub fn op_0xE5(&mut self) -> bool {
         self._stack_push(self.reg.get_HL());
-        0
+        false
     }
 
-    pub fn op_0xE6(&mut self) -> u32 {
+    pub fn op_0xE6(&mut self) -> bool {
         let v = self.imm();
         self._op_and(v);
-        0
+        false
     }
 
-    pub fn op_0xE7(&mut self) -> u32 {
+    pub fn op_0xE7(&mut self) -> bool {
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(0x0020);
-        0
+        false
     }
 
-    pub fn op_0xE8(&mut self) -> u32 {
+    pub fn op_0xE8(&mut self) -> bool {
         // origin1 := core.CPU.Registers.SP
         // origin2 := int8(core.getParameter8())
         // res := uint16(int32(core.CPU.Registers.SP) + int32(origin2))
@@ -2416,18 +2599,18 @@ impl CPU {
             self.reg.unset_flag(Flag::Carry);
         }
 
-        0
+        false
     }
 
-    pub fn op_0xE9(&mut self) -> u32 {
+    pub fn op_0xE9(&mut self) -> bool {
         self.reg.set_PC(self.reg.get_HL());
-        0
+        false
     }
 
-    pub fn op_0xEA(&mut self) -> u32 {
+    pub fn op_0xEA(&mut self) -> bool {
         let addr = self.imm_word();
         self.write_byte_to_memory(addr, self.reg.get_A());
-        0
+        false
     }
 
     // pub fn op_0xeb(&mut self) -> u32 {
@@ -2443,19 +2626,19 @@ impl CPU {
     //     0
     // }
 
-    pub fn op_0xEE(&mut self) -> u32 {
+    pub fn op_0xEE(&mut self) -> bool {
         let v = self.imm();
         self._op_xor(v);
-        0
+        false
     }
 
-    pub fn op_0xEF(&mut self) -> u32 {
+    pub fn op_0xEF(&mut self) -> bool {
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(0x0028);
-        0
+        false
     }
 
-    pub fn op_0xF0(&mut self) -> u32 {
+    pub fn op_0xF0(&mut self) -> bool {
         let addr = 0xFF00 | u16::from(self.imm());
         // println!(
         //     "!!!!OXF0 addr is {} => {}",
@@ -2464,50 +2647,50 @@ impl CPU {
         // );
         self.reg.set_A(self.read_byte_from_memory(addr));
 
-        0
+        false
     }
 
-    pub fn op_0xF1(&mut self) -> u32 {
+    pub fn op_0xF1(&mut self) -> bool {
         let v = self._stack_pop();
         // 这个地方需要注意
         self.reg.set_AF(v);
-        0
+        false
     }
 
-    pub fn op_0xF2(&mut self) -> u32 {
+    pub fn op_0xF2(&mut self) -> bool {
         let addr = 0xFF00 + u16::from(self.reg.get_C());
         self.reg.set_A(self.read_byte_from_memory(addr));
-        0
+        false
     }
 
-    pub fn op_0xF3(&mut self) -> u32 {
+    pub fn op_0xF3(&mut self) -> bool {
         self.disable_ime();
-        0
+        false
     }
 
     // pub fn op_0xf4(&mut self) -> u32 {
     //     0
     // }
 
-    pub fn op_0xF5(&mut self) -> u32 {
+    pub fn op_0xF5(&mut self) -> bool {
         self._stack_push(self.reg.get_AF());
-        0
+        false
     }
 
-    pub fn op_0xF6(&mut self) -> u32 {
+    pub fn op_0xF6(&mut self) -> bool {
         let v = self.imm();
 
         self._op_or(v);
-        0
+        false
     }
 
-    pub fn op_0xF7(&mut self) -> u32 {
+    pub fn op_0xF7(&mut self) -> bool {
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(0x0030);
-        0
+        false
     }
 
-    pub fn op_0xF8(&mut self) -> u32 {
+    pub fn op_0xF8(&mut self) -> bool {
         let v1 = self.reg.get_SP();
         // NOTICE TODO:
         // u8 to i8 then to i32
@@ -2534,23 +2717,23 @@ impl CPU {
             self.reg.unset_flag(Flag::Carry);
         }
 
-        0
+        false
     }
 
-    pub fn op_0xF9(&mut self) -> u32 {
+    pub fn op_0xF9(&mut self) -> bool {
         self.reg.set_SP(self.reg.get_HL());
-        0
+        false
     }
 
-    pub fn op_0xFA(&mut self) -> u32 {
+    pub fn op_0xFA(&mut self) -> bool {
         let addr = self.imm_word();
         self.reg.set_A(self.read_byte_from_memory(addr));
-        0
+        false
     }
 
-    pub fn op_0xFB(&mut self) -> u32 {
-        self.enable_ime();
-        0
+    pub fn op_0xFB(&mut self) -> bool {
+        self.enable_ime_delayed();
+        false
     }
 
     // pub fn op_0xfc(&mut self) -> u32 {
@@ -2562,24 +2745,24 @@ impl CPU {
     //     0
     // }
 
-    pub fn op_0xFE(&mut self) -> u32 {
+    pub fn op_0xFE(&mut self) -> bool {
         let v = self.imm();
         self._op_compare(v);
 
-        0
+        false
     }
 
-    pub fn op_0xFF(&mut self) -> u32 {
+    pub fn op_0xFF(&mut self) -> bool {
         self._stack_push(self.reg.get_PC());
         self.reg.set_PC(0x0038);
-        0
+        false
     }
 }
 
 // Extend OpCodes
 
 #[allow(non_snake_case)]
-impl<'a> CPU {
+impl CPU {
     fn alu_rlc(&mut self, a: u8) -> u8 {
         let c = (a & 0x80) >> 7 == 0x01;
         let r = (a << 1) | u8::from(c);
@@ -2770,23 +2953,36 @@ impl<'a> CPU {
         a | (1 << b)
     }
 
-    fn get_setter(&'a mut self, i: u8) -> Box<dyn FnMut(u8) + 'a> {
+    fn get_cb_operand(&self, i: u8) -> u8 {
         match i {
-            0 => Box::new(move |v: u8| self.reg.set_B(v)),
-            1 => Box::new(move |v: u8| self.reg.set_C(v)),
-            2 => Box::new(move |v: u8| self.reg.set_D(v)),
-            3 => Box::new(move |v: u8| self.reg.set_E(v)),
-            4 => Box::new(move |v: u8| self.reg.set_H(v)),
-            5 => Box::new(move |v: u8| self.reg.set_L(v)),
-            6 => Box::new(move |v: u8| self.write_byte_to_memory(self.reg.get_HL(), v)),
-
-            7 => Box::new(move |v: u8| self.reg.set_A(v)),
+            0 => self.reg.get_B(),
+            1 => self.reg.get_C(),
+            2 => self.reg.get_D(),
+            3 => self.reg.get_E(),
+            4 => self.reg.get_H(),
+            5 => self.reg.get_L(),
+            6 => self.read_byte_from_memory(self.reg.get_HL()),
+            7 => self.reg.get_A(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_cb_operand(&mut self, i: u8, v: u8) {
+        match i {
+            0 => self.reg.set_B(v),
+            1 => self.reg.set_C(v),
+            2 => self.reg.set_D(v),
+            3 => self.reg.set_E(v),
+            4 => self.reg.set_H(v),
+            5 => self.reg.set_L(v),
+            6 => self.write_byte_to_memory(self.reg.get_HL(), v),
+            7 => self.reg.set_A(v),
             _ => unreachable!(),
         }
     }
 
     // 	OP:0xCB PREFIX CB
-    pub fn op_0xCB(&mut self) -> u32 {
+    pub fn op_0xCB(&mut self) -> bool {
         // nextIns := core.getParameter8()
         // if core.cbMap[nextIns] != nil {
         //     core.cbMap[nextIns]()
@@ -2801,213 +2997,35 @@ impl<'a> CPU {
         // println!("fuck cb opcode is {}", next_op);
         self.reg.incr_PC();
 
-        insert_cpu_record(CPUDebugInfo::new(self.reg.clone(), next_op, true));
-
-        #[allow(unused_assignments)] // it will be orverwirte
-        let mut v = 0;
-
-        let row = next_op / 8;
-        let col = next_op % 8;
-
-        {
-            let getters: Vec<Box<dyn Fn() -> u8>> = vec![
-                Box::new(|| self.reg.get_B()),
-                Box::new(|| self.reg.get_C()),
-                Box::new(|| self.reg.get_D()),
-                Box::new(|| self.reg.get_E()),
-                Box::new(|| self.reg.get_H()),
-                Box::new(|| self.reg.get_L()),
-                Box::new(|| self.read_byte_from_memory(self.reg.get_HL())),
-                Box::new(|| self.reg.get_A()),
-            ];
-            v = getters[col as usize]();
-        }
-
-        // let mut setter: Box<dyn FnMut(u8)> = match col {
-        //     0 => Box::new(|v: u8| self.reg.set_B(v)),
-        //     1 => Box::new(|v: u8| self.reg.set_C(v)),
-        //     2 => Box::new(|v: u8| self.reg.set_D(v)),
-        //     3 => Box::new(|v: u8| self.reg.set_E(v)),
-        //     4 => Box::new(|v: u8| self.reg.set_H(v)),
-        //     5 => Box::new(|v: u8| self.reg.set_L(v)),
-        //     6 => Box::new(|v: u8| self.write_byte_to_memory(self.reg.get_HL(), v)),
-
-        //     7 => Box::new(|v: u8| self.reg.set_A(v)),
-        //     _ => unreachable!(),
-        // };
-
-        match row {
-            0x00 => {
-                let v = self.alu_rlc(v);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x01 => {
-                let v = self.alu_rrc(v);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x02 => {
-                let v = self.alu_rl(v);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x03 => {
-                let v = self.alu_rr(v);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x04 => {
-                let v = self.alu_sla(v);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-
-            0x05 => {
-                let v = self.alu_sra(v);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x06 => {
-                let v = self.alu_swap(v);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x07 => {
-                let v = self.alu_srl(v);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x08 => {
-                self.alu_bit(v, 0);
-            }
-            0x09 => {
-                self.alu_bit(v, 1);
-            }
-            0x0A => {
-                self.alu_bit(v, 2);
-            }
-            0x0B => {
-                self.alu_bit(v, 3);
-            }
-            0x0C => {
-                self.alu_bit(v, 4);
-            }
-            0x0D => {
-                self.alu_bit(v, 5);
-            }
-            0x0E => {
-                self.alu_bit(v, 6);
-            }
-            0x0F => {
-                self.alu_bit(v, 7);
-            }
-            0x10 => {
-                let v = self.alu_res(v, 0);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x11 => {
-                let v = self.alu_res(v, 1);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x12 => {
-                let v = self.alu_res(v, 2);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x13 => {
-                let v = self.alu_res(v, 3);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x14 => {
-                let v = self.alu_res(v, 4);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x15 => {
-                let v = self.alu_res(v, 5);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x16 => {
-                let v = self.alu_res(v, 6);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x17 => {
-                let v = self.alu_res(v, 7);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x18 => {
-                let v = self.alu_set(v, 0);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x19 => {
-                let v = self.alu_set(v, 1);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x1A => {
-                let v = self.alu_set(v, 2);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x1B => {
-                let v = self.alu_set(v, 3);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-
-            0x1C => {
-                let v = self.alu_set(v, 4);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x1D => {
-                let v = self.alu_set(v, 5);
-                let mut setter = self.get_setter(col);
-                setter(v);
+        CB_CODE_SET[next_op as usize].ex(self);
+        false
+    }
+
+    /// The CB prefixed instruction, it's instantiated for each opcode of `CB_CODE_SET` so the
+    /// operation and the register are resolved at compile time.
+    pub fn op_cb<const OP: u8>(&mut self) -> bool {
+        let row = OP >> 3;
+        let col = OP & 0x07;
+        let bit = row & 0x07;
+        let v = self.get_cb_operand(col);
+        let r = match row {
+            0x00 => self.alu_rlc(v),
+            0x01 => self.alu_rrc(v),
+            0x02 => self.alu_rl(v),
+            0x03 => self.alu_rr(v),
+            0x04 => self.alu_sla(v),
+            0x05 => self.alu_sra(v),
+            0x06 => self.alu_swap(v),
+            0x07 => self.alu_srl(v),
+            // BIT doesn't write the operand back.
+            0x08..=0x0f => {
+                self.alu_bit(v, bit);
+                return false;
             }
-            0x1E => {
-                let v = self.alu_set(v, 6);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            0x1F => {
-                let v = self.alu_set(v, 7);
-                let mut setter = self.get_setter(col);
-                setter(v);
-            }
-            _ => {
-                unreachable!("{:#02x}", row);
-            }
-        }
-
-        let ex_op_cycles = vec![
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 0
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 1
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 2
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 3
-            2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2, 2, 2, 2, 3, 2, // 4
-            2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2, 2, 2, 2, 3, 2, // 5
-            2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2, 2, 2, 2, 3, 2, // 6
-            2, 2, 2, 2, 2, 2, 3, 2, 2, 2, 2, 2, 2, 2, 3, 2, // 7
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 8
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // 9
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // A
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // B
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // C
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // D
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // E
-            2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 2, 2, 2, 2, 4, 2, // F
-        ]; //0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
-
-        // TODO
-        return ex_op_cycles[next_op as usize] * 2;
+            0x10..=0x17 => self.alu_res(v, bit),
+            _ => self.alu_set(v, bit),
+        };
+        self.set_cb_operand(col, r);
+        false
     }
 }
@@ -1,3 +1,7 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::{Deserialize, Serialize};
+
 use super::super::Term;
 
 /// # The CPU Registers.
@@ -46,7 +50,7 @@ use super::super::Term;
 ///
 /// # Reference:
 /// - [CPU Registers and Flags](https://gbdev.gg8.se/wiki/articles/CPU_Registers_and_Flags)
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[allow(non_snake_case)]
 pub struct Register {
     A: u8,
@@ -68,33 +72,6 @@ impl Register {
         Self::default()
     }
 
-    // #[cfg(test)]
-    pub fn new_from_debug_string(s: &str) -> Self {
-        let mut reg = Self::new();
-        // a trick way, but work for now.
-        let s = &s[11..s.len() - 2];
-        for field in s.split(", ") {
-            let tmp: Vec<&str> = field.split(": ").collect();
-            let name = tmp[0].to_uppercase();
-            let value: u16 = tmp[1].parse().unwrap();
-            match &name as &str {
-                "A" => reg.A = value as u8,
-                "B" => reg.B = value as u8,
-                "C" => reg.C = value as u8,
-                "D" => reg.D = value as u8,
-                "E" => reg.E = value as u8,
-                "F" => reg.F = value as u8,
-                "H" => reg.H = value as u8,
-                "L" => reg.L = value as u8,
-                "PC" => reg.PC = value,
-                "SP" => reg.SP = value,
-                _ => panic!("{} is unknown field", name),
-            }
-        }
-
-        reg
-    }
-
     /// Init the register, fill the default value.
     /// ```ignore
     /// name value(hex)
@@ -138,6 +115,40 @@ impl Register {
     }
 }
 
+// All Builder Methods, e.g. `Register::new().with_AF(0x01b0).with_PC(0x0100)`.
+#[allow(non_snake_case)]
+impl Register {
+    pub fn with_AF(mut self, v: u16) -> Self {
+        self.set_AF(v);
+        self
+    }
+
+    pub fn with_BC(mut self, v: u16) -> Self {
+        self.set_BC(v);
+        self
+    }
+
+    pub fn with_DE(mut self, v: u16) -> Self {
+        self.set_DE(v);
+        self
+    }
+
+    pub fn with_HL(mut self, v: u16) -> Self {
+        self.set_HL(v);
+        self
+    }
+
+    pub fn with_PC(mut self, v: u16) -> Self {
+        self.set_PC(v);
+        self
+    }
+
+    pub fn with_SP(mut self, v: u16) -> Self {
+        self.set_SP(v);
+        self
+    }
+}
+
 // All Getter Methods.
 #[allow(non_snake_case)]
 impl Register {
@@ -198,7 +209,7 @@ impl Register {
     /// Incr the value of `PC` register.
     #[inline]
     pub fn incr_PC(&mut self) {
-        self.PC += 1;
+        self.PC = self.PC.wrapping_add(1);
     }
 
     /// Returns the value of 16bit `AF` register.
@@ -385,7 +396,7 @@ impl Flag {
 // Bit 3: Serial   Interrupt Request (INT 58h)  (1=Request)
 // Bit 4: Joypad   Interrupt Request (INT 60h)  (1=Request)
 #[rustfmt::skip]
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum IntFlag {
     VBlank  = 0b0000,
     LCDStat = 0b0001,
@@ -394,16 +405,65 @@ pub enum IntFlag {
     Joypad  = 0b0100,
 }
 
+// By the priority, the lower bit first.
+const INT_FLAGS: [IntFlag; 5] = [
+    IntFlag::VBlank,
+    IntFlag::LCDStat,
+    IntFlag::Timer,
+    IntFlag::Serial,
+    IntFlag::Joypad,
+];
+
+impl IntFlag {
+    /// Returns the bit of the flag in IF and IE.
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Returns the address of the interrupt handler.
+    pub fn get_vector(self) -> u16 {
+        0x0040 | (self as u16) << 3
+    }
+
+    /// Returns the request of the highest priority in the bits of IF, if any.
+    pub fn highest(bits: u8) -> Option<Self> {
+        INT_FLAGS.iter().copied().find(|f| bits & f.mask() != 0)
+    }
+}
+
+/// The IF register, only its lower 5 bits exist. The GPU, the timer, the joypad and the serial
+/// port request the interrupts by themselves, so the register is shared by them and the mmu. It's
+/// atomic rather than a `RefCell` to keep the machine `Send`, the machine runs on one thread at a
+/// time so the relaxed order is enough.
+#[derive(Debug, Default)]
 pub struct IntReg {
-    pub data: u8,
+    data: AtomicU8,
 }
 
 impl IntReg {
     pub fn new() -> Self {
-        Self { data: 0x00 }
+        Self::default()
+    }
+
+    pub fn req(&self, flag: IntFlag) {
+        self.data.fetch_or(flag.mask(), Ordering::Relaxed);
+    }
+
+    /// Clear the request, the CPU acknowledges it when the interrupt is dispatched.
+    pub fn ack(&self, flag: IntFlag) {
+        self.data.fetch_and(!flag.mask(), Ordering::Relaxed);
+    }
+
+    pub fn is_requested(&self, flag: IntFlag) -> bool {
+        self.data.load(Ordering::Relaxed) & flag.mask() != 0
+    }
+
+    /// Returns IF as the CPU reads it, the upper 3 bits always read as 1.
+    pub fn get(&self) -> u8 {
+        self.data.load(Ordering::Relaxed) | 0xe0
     }
 
-    pub fn req(&mut self, flag: IntFlag) {
-        self.data |= 1 << flag as u8;
+    pub fn set(&self, v: u8) {
+        self.data.store(v & 0x1f, Ordering::Relaxed);
     }
 }
@@ -1,118 +1,852 @@
-use std::cell::RefCell;
-use std::collections::VecDeque;
-use std::fs::File;
-use std::io::LineWriter;
-use std::io::Write;
-use std::path::Path;
-use std::rc::Rc;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
 
-use log::error;
+#[cfg(feature = "native")]
 use rustyline::Editor;
 
 use super::cpu::Register;
 use super::cpu::CPU;
 use super::graphics::gpu::GPU;
+use super::history::{CpuHistory, COREDUMP_PATH};
+use super::mmu::{IOHandler, Mmunit};
 
-const RECORE_LIMIT: usize = 512;
+/// A debugger command editing the graphics memory, it goes through the GPU write path.
+#[derive(Debug, PartialEq)]
+pub enum Poke {
+    /// `sprite 5 x=80 y=40 tile=0x12 attr=0x20`, the omitted fields are kept.
+    /// x and y are the screen position, OAM stores them plus 8 and 16.
+    Sprite {
+        index: u8,
+        x: Option<u8>,
+        y: Option<u8>,
+        tile: Option<u8>,
+        attr: Option<u8>,
+    },
+    /// `tilepoke 0x12 row 3 0b00111100 0b00111100`, replace a line of the tile at 8000+tile*16
+    /// with the low and the high bit plane.
+    Tile { tile: u8, row: u8, lo: u8, hi: u8 },
+}
 
-#[derive(Debug, Clone)]
-pub struct CPUDebugInfo {
-    reg: Register,
-    opcode: u8,
-    is_ext_opcode: bool,
+/// Parse a number in decimal, hex with `0x` or binary with `0b`.
+fn parse_u16(s: &str) -> Result<u16, String> {
+    let r = if let Some(hex) = s.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        u16::from_str_radix(bin, 2)
+    } else {
+        s.parse()
+    };
+    r.map_err(|_| format!("invalid number {:?}", s))
 }
 
-impl CPUDebugInfo {
-    pub fn new(reg: Register, opcode: u8, is_ext_opcode: bool) -> Self {
-        Self {
-            reg,
-            opcode,
-            is_ext_opcode,
+fn parse_u8(s: &str) -> Result<u8, String> {
+    u8::try_from(parse_u16(s)?).map_err(|_| format!("invalid number {:?}", s))
+}
+
+impl Poke {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let args: Vec<&str> = line.split_ascii_whitespace().collect();
+        match args.as_slice() {
+            ["sprite", index, fields @ ..] => {
+                let index = parse_u8(index)?;
+                if index >= 40 {
+                    return Err(format!("sprite index {} is out of 0-39", index));
+                }
+                let (mut x, mut y, mut tile, mut attr) = (None, None, None, None);
+                for field in fields {
+                    let (k, v) = field
+                        .split_once('=')
+                        .ok_or_else(|| format!("expect key=value, get {:?}", field))?;
+                    let v = parse_u8(v)?;
+                    match k {
+                        "x" => x = Some(v.checked_add(8).ok_or("x is out of range")?),
+                        "y" => y = Some(v.checked_add(16).ok_or("y is out of range")?),
+                        "tile" => tile = Some(v),
+                        "attr" => attr = Some(v),
+                        _ => return Err(format!("unknown sprite field {:?}", k)),
+                    }
+                }
+                Ok(Poke::Sprite {
+                    index,
+                    x,
+                    y,
+                    tile,
+                    attr,
+                })
+            }
+            ["tilepoke", tile, "row", row, lo, hi] => {
+                let row = parse_u8(row)?;
+                if row >= 8 {
+                    return Err(format!("row {} is out of 0-7", row));
+                }
+                Ok(Poke::Tile {
+                    tile: parse_u8(tile)?,
+                    row,
+                    lo: parse_u8(lo)?,
+                    hi: parse_u8(hi)?,
+                })
+            }
+            _ => Err(format!("can't parse {:?}", line)),
+        }
+    }
+
+    /// Returns the memory writes of the command.
+    pub fn get_writes(&self) -> Vec<(u16, u8)> {
+        match *self {
+            Poke::Sprite {
+                index,
+                x,
+                y,
+                tile,
+                attr,
+            } => {
+                let base = 0xfe00 + u16::from(index) * 4;
+                [y, x, tile, attr]
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, v)| v.map(|v| (base + i as u16, v)))
+                    .collect()
+            }
+            Poke::Tile { tile, row, lo, hi } => {
+                let addr = 0x8000 + u16::from(tile) * 16 + u16::from(row) * 2;
+                vec![(addr, lo), (addr + 1, hi)]
+            }
+        }
+    }
+
+    /// Write the memory and render the frame again.
+    pub fn apply(&self, gpu: &mut GPU) {
+        for (a, v) in self.get_writes() {
+            gpu.write_byte(a, v);
+        }
+        gpu.rerender();
+    }
+}
+
+/// The condition of a breakpoint, `if a == 0x12` or `if hl != 0xc000`.
+#[derive(Debug, PartialEq)]
+pub struct Condition {
+    reg: String,
+    value: u16,
+    equal: bool,
+}
+
+impl Condition {
+    fn get_reg(&self, reg: &Register) -> u16 {
+        match self.reg.as_str() {
+            "a" => u16::from(reg.get_A()),
+            "f" => reg.get_AF() & 0xff,
+            "b" => u16::from(reg.get_B()),
+            "c" => u16::from(reg.get_C()),
+            "d" => u16::from(reg.get_D()),
+            "e" => u16::from(reg.get_E()),
+            "h" => u16::from(reg.get_H()),
+            "l" => u16::from(reg.get_L()),
+            "af" => reg.get_AF(),
+            "bc" => reg.get_BC(),
+            "de" => reg.get_DE(),
+            "hl" => reg.get_HL(),
+            _ => reg.get_SP(),
+        }
+    }
+
+    pub fn is_met(&self, reg: &Register) -> bool {
+        (self.get_reg(reg) == self.value) == self.equal
+    }
+}
+
+/// A debugger breakpoint, it's hit before the instruction at the address is executed.
+#[derive(Debug, PartialEq)]
+pub struct Breakpoint {
+    addr: u16,
+    cond: Option<Condition>,
+}
+
+impl Breakpoint {
+    /// Parse `break <addr> [if <reg> ==|!= <value>]`.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let args: Vec<&str> = line.split_ascii_whitespace().collect();
+        let (addr, cond) = match args.as_slice() {
+            ["break", addr] => (addr, None),
+            ["break", addr, "if", reg, op, value] => {
+                let reg = reg.to_ascii_lowercase();
+                let regs = [
+                    "a", "f", "b", "c", "d", "e", "h", "l", "af", "bc", "de", "hl", "sp",
+                ];
+                if !regs.contains(&reg.as_str()) {
+                    return Err(format!("unknown register {:?}", reg));
+                }
+                let equal = match *op {
+                    "==" => true,
+                    "!=" => false,
+                    _ => return Err(format!("expect == or !=, get {:?}", op)),
+                };
+                let value = parse_u16(value)?;
+                if reg.len() == 1 && value > 0xff {
+                    return Err(format!("{} is an 8 bits register", reg));
+                }
+                (addr, Some(Condition { reg, value, equal }))
+            }
+            _ => return Err(format!("can't parse {:?}", line)),
+        };
+        Ok(Self {
+            addr: parse_u16(addr)?,
+            cond,
+        })
+    }
+
+    pub fn is_hit(&self, reg: &Register) -> bool {
+        reg.get_PC() == self.addr && self.cond.as_ref().is_none_or(|c| c.is_met(reg))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04x}", self.addr)?;
+        if let Some(c) = &self.cond {
+            let op = if c.equal { "==" } else { "!=" };
+            write!(f, " if {} {} 0x{:x}", c.reg, op, c.value)?;
         }
+        Ok(())
+    }
+}
+
+/// Format the memory like `c000: 00 01 02 ...`, 16 bytes a line.
+pub fn dump_memory(mem: &dyn IOHandler, addr: u16, len: u16) -> Vec<String> {
+    let end = u32::from(addr) + u32::from(len);
+    (u32::from(addr)..end.min(0x10000))
+        .step_by(16)
+        .map(|start| {
+            let bytes: Vec<String> = (start..(start + 16).min(end.min(0x10000)))
+                .map(|a| format!("{:02x}", mem.read_byte(a as u16)))
+                .collect();
+            format!("{:04x}: {}", start, bytes.join(" "))
+        })
+        .collect()
+}
+
+/// The I/O registers shown by `io` and `--show-io`, in the order they're dumped.
+pub const IO_REGISTERS: [(&str, u16); 14] = [
+    ("LCDC", 0xff40),
+    ("STAT", 0xff41),
+    ("SCY", 0xff42),
+    ("SCX", 0xff43),
+    ("LY", 0xff44),
+    ("LYC", 0xff45),
+    ("BGP", 0xff47),
+    ("OBP0", 0xff48),
+    ("OBP1", 0xff49),
+    ("IE", 0xffff),
+    ("IF", 0xff0f),
+    ("TIMA", 0xff05),
+    ("TMA", 0xff06),
+    ("TAC", 0xff07),
+];
+// The registers in a line of the dump, it fits in the width of the OSD.
+const IO_REGISTERS_PER_LINE: usize = 3;
+
+/// Format the I/O registers like `LCDC 91 STAT 85 SCY 00`, 3 registers a line.
+pub fn dump_io_registers(mem: &dyn IOHandler) -> Vec<String> {
+    IO_REGISTERS
+        .chunks(IO_REGISTERS_PER_LINE)
+        .map(|regs| {
+            let regs: Vec<String> = regs
+                .iter()
+                .map(|(name, a)| format!("{} {:02X}", name, mem.read_byte(*a)))
+                .collect();
+            regs.join(" ")
+        })
+        .collect()
+}
+
+/// A debugger command writing an I/O register, `set LCDC 0x91` or `set 0xff43 8`. The register is
+/// one of `IO_REGISTERS` or any address in FF00-FFFF, the write goes through the mmu like the cpu
+/// does.
+#[derive(Debug, PartialEq)]
+pub struct RegisterPoke {
+    pub addr: u16,
+    pub value: u8,
+}
+
+impl RegisterPoke {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        match line.split_ascii_whitespace().collect::<Vec<_>>().as_slice() {
+            ["set", reg, value] => {
+                let addr = match IO_REGISTERS
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(reg))
+                {
+                    Some((_, a)) => *a,
+                    None => match parse_u16(reg) {
+                        Ok(a) if a >= 0xff00 => a,
+                        _ => return Err(format!("unknown register {:?}", reg)),
+                    },
+                };
+                Ok(Self {
+                    addr,
+                    value: parse_u8(value)?,
+                })
+            }
+            _ => Err(format!("can't parse {:?}", line)),
+        }
+    }
+
+    pub fn apply(&self, mem: &mut dyn IOHandler) {
+        mem.write_byte(self.addr, self.value);
+    }
+}
+
+/// Which accesses a watchpoint breaks on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+/// A debugger watchpoint, it breaks after the instruction touching the memory in the range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    start: u16,
+    end: u16,
+    kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, kind: WatchKind) -> Self {
+        Self { start, end, kind }
+    }
+
+    /// Parse `watch <addr>[-<end>] [r|w|rw]`, it watches the writes by default.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let args: Vec<&str> = line.split_ascii_whitespace().collect();
+        let (range, kind) = match args.as_slice() {
+            ["watch", range] => (range, WatchKind::Write),
+            ["watch", range, kind] => {
+                let kind = match *kind {
+                    "r" => WatchKind::Read,
+                    "w" => WatchKind::Write,
+                    "rw" => WatchKind::Access,
+                    _ => return Err(format!("expect r, w or rw, get {:?}", kind)),
+                };
+                (range, kind)
+            }
+            _ => return Err(format!("can't parse {:?}", line)),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_u16(start)?, parse_u16(end)?),
+            None => (parse_u16(range)?, parse_u16(range)?),
+        };
+        if start > end {
+            return Err(format!("the range {:?} is empty", range));
+        }
+        Ok(Self::new(start, end, kind))
+    }
+
+    pub fn is_hit(&self, a: u16, write: bool) -> bool {
+        let kind = match self.kind {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+        };
+        kind && (self.start..=self.end).contains(&a)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04x}", self.start)?;
+        if self.end != self.start {
+            write!(f, "-0x{:04x}", self.end)?;
+        }
+        let kind = match self.kind {
+            WatchKind::Read => "r",
+            WatchKind::Write => "w",
+            WatchKind::Access => "rw",
+        };
+        write!(f, " {}", kind)
+    }
+}
+
+/// The access which hit a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub index: usize,
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.write {
+            write!(f, "write 0x{:02x} to 0x{:04x}", self.value, self.addr)
+        } else {
+            write!(f, "read 0x{:02x} from 0x{:04x}", self.value, self.addr)
+        }
+    }
+}
+
+/// The watchpoints checked by the mmu on every access, the first hit is kept until the debugger
+/// takes it.
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+    hit: Cell<Option<WatchHit>>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_list(&self) -> &[Watchpoint] {
+        &self.list
+    }
+
+    pub fn add(&mut self, w: Watchpoint) {
+        self.list.push(w);
+    }
+
+    pub fn remove(&mut self, i: usize) -> Option<Watchpoint> {
+        if i < self.list.len() {
+            Some(self.list.remove(i))
+        } else {
+            None
+        }
+    }
+
+    /// Check the access, it's cheap without any watchpoint.
+    pub fn check(&self, a: u16, v: u8, write: bool) {
+        if self.list.is_empty() || self.is_hit() {
+            return;
+        }
+        if let Some(index) = self.list.iter().position(|w| w.is_hit(a, write)) {
+            self.hit.set(Some(WatchHit {
+                index,
+                addr: a,
+                value: v,
+                write,
+            }));
+        }
+    }
+
+    pub fn is_hit(&self) -> bool {
+        self.hit.get().is_some()
+    }
+
+    pub fn take_hit(&self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+}
+
+/// How the candidates of the RAM search are narrowed, against the values of the last search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchFilter {
+    Equal(u8),
+    NotEqual(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl SearchFilter {
+    /// Parse `search ==|!= <value>` or `search changed|unchanged|increased|decreased`.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let args: Vec<&str> = line.split_ascii_whitespace().collect();
+        match args.as_slice() {
+            ["search", "==", v] => Ok(Self::Equal(parse_u8(v)?)),
+            ["search", "!=", v] => Ok(Self::NotEqual(parse_u8(v)?)),
+            ["search", "changed"] => Ok(Self::Changed),
+            ["search", "unchanged"] => Ok(Self::Unchanged),
+            ["search", "increased"] => Ok(Self::Increased),
+            ["search", "decreased"] => Ok(Self::Decreased),
+            _ => Err(format!("can't parse {:?}", line)),
+        }
+    }
+
+    fn is_match(self, last: u8, now: u8) -> bool {
+        match self {
+            Self::Equal(v) => now == v,
+            Self::NotEqual(v) => now != v,
+            Self::Changed => now != last,
+            Self::Unchanged => now == last,
+            Self::Increased => now > last,
+            Self::Decreased => now < last,
+        }
+    }
+}
+
+/// The work ram and the high ram, where the games keep their state.
+pub const SEARCH_RANGES: [(u16, u16); 2] = [(0xc000, 0xdfff), (0xff80, 0xfffe)];
+
+/// Find the address of a value by narrowing the candidates down search by search, e.g. search
+/// for the lives, lose one and search for the decreased ones.
+#[derive(Debug, Clone)]
+pub struct RamSearch {
+    // The addresses left and their values at the last search.
+    candidates: Vec<(u16, u8)>,
+}
+
+impl RamSearch {
+    /// Start with every address in the ranges.
+    pub fn new(mem: &dyn IOHandler, ranges: &[(u16, u16)]) -> Self {
+        let candidates = ranges
+            .iter()
+            .flat_map(|&(start, end)| start..=end)
+            .map(|a| (a, mem.read_byte(a)))
+            .collect();
+        Self { candidates }
+    }
+
+    pub fn get_candidates(&self) -> &[(u16, u8)] {
+        &self.candidates
+    }
+
+    /// Keep the addresses matching the filter, returns how many are left.
+    pub fn filter(&mut self, mem: &dyn IOHandler, filter: SearchFilter) -> usize {
+        self.candidates = self
+            .candidates
+            .iter()
+            .map(|&(a, last)| (a, last, mem.read_byte(a)))
+            .filter(|&(_, last, now)| filter.is_match(last, now))
+            .map(|(a, _, now)| (a, now))
+            .collect();
+        self.candidates.len()
     }
 }
 
-lazy_static! {
-    static ref CPU_RECORD: Mutex<VecDeque<CPUDebugInfo>> = Mutex::new(VecDeque::new());
+/// The address of the code, the rom at 4000-7FFF is told apart by the bank mapped there. The
+/// code in the ram is in the bank 0.
+pub type CodeAddress = (usize, u16);
+
+/// The code out of any call runs from the entry point of the cartridge.
+const MAIN: CodeAddress = (0, 0x0100);
+
+/// Attributes the emulated cycles to the instructions and to the functions running them, for
+/// finding the hot loops of a rom. A function is entered by a `CALL`, a `RST` or an interrupt,
+/// and left when the stack is back above its return address.
+#[derive(Debug, Clone, Default)]
+pub struct CycleProfiler {
+    instructions: HashMap<CodeAddress, u64>,
+    functions: HashMap<CodeAddress, u64>,
+    // The entries of the functions called and the SP right after the call.
+    calls: Vec<(CodeAddress, u16)>,
+    total: u64,
 }
 
-pub fn insert_cpu_record(record: CPUDebugInfo) {
-    let data = CPU_RECORD.lock();
-    if data.is_err() {
-        error!("insert the cpu debug info failed {:?}, skip", data.err());
-        return;
+impl CycleProfiler {
+    pub fn new() -> Self {
+        Self::default()
     }
-    let mut q = data.unwrap();
 
-    if q.len() >= RECORE_LIMIT {
-        q.pop_front();
+    /// Count the cycles of the instruction at `pc`, `sp` is the SP before it. The cycles go to
+    /// the function at the top of the calls, or to the entry point out of any call.
+    pub fn record(&mut self, pc: CodeAddress, sp: u16, cycles: u32) {
+        while matches!(self.calls.last(), Some((_, s)) if *s < sp) {
+            self.calls.pop();
+        }
+        let function = self.calls.last().map_or(MAIN, |(entry, _)| *entry);
+        *self.instructions.entry(pc).or_default() += u64::from(cycles);
+        *self.functions.entry(function).or_default() += u64::from(cycles);
+        self.total += u64::from(cycles);
+    }
+
+    /// Enter the function at `entry`, `sp` is the SP after the return address is pushed.
+    pub fn call(&mut self, entry: CodeAddress, sp: u16) {
+        self.calls.push((entry, sp));
+    }
+
+    /// Returns the cycles counted in all.
+    pub fn get_total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the busiest instructions and their cycles, the busiest first.
+    pub fn top_instructions(&self, n: usize) -> Vec<(CodeAddress, u64)> {
+        Self::top(&self.instructions, n)
+    }
+
+    /// Returns the busiest functions and the cycles spent in them, their callees excluded.
+    pub fn top_functions(&self, n: usize) -> Vec<(CodeAddress, u64)> {
+        Self::top(&self.functions, n)
+    }
+
+    fn top(counts: &HashMap<CodeAddress, u64>, n: usize) -> Vec<(CodeAddress, u64)> {
+        let mut top: Vec<_> = counts.iter().map(|(a, c)| (*a, *c)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        top.truncate(n);
+        top
+    }
+
+    /// Returns the lines of the report, `02:4a3c  1234567 cycles  12.3%`.
+    pub fn report(&self, n: usize) -> Vec<String> {
+        let line = |((bank, a), c): (CodeAddress, u64)| {
+            let percent = c as f64 * 100.0 / self.total.max(1) as f64;
+            format!("{:02x}:{:04x} {:>10} cycles {:>5.1}%", bank, a, c, percent)
+        };
+        let mut lines = vec!["The hot functions:".to_string()];
+        lines.extend(self.top_functions(n).into_iter().map(line));
+        lines.push("The hot instructions:".to_string());
+        lines.extend(self.top_instructions(n).into_iter().map(line));
+        lines
     }
-    q.push_back(record);
 }
 
-pub fn dump_cpu_record(file_path: impl AsRef<Path>) {
-    let f = File::create(file_path).unwrap();
-    let mut f = LineWriter::new(f);
-    let data = CPU_RECORD.lock().unwrap();
-    for line in data.iter() {
-        f.write(format!("{:?}\n", line).as_bytes())
-            .expect("write file failed");
+#[cfg(feature = "native")]
+type LineEditor = Editor<()>;
+
+/// Reads the plain lines of stdin without the native line editor.
+#[cfg(not(feature = "native"))]
+struct LineEditor;
+
+#[cfg(not(feature = "native"))]
+impl LineEditor {
+    fn new() -> Self {
+        Self
+    }
+
+    fn readline(&mut self, prompt: &str) -> std::io::Result<String> {
+        use std::io::Write;
+
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    fn add_history_entry(&mut self, _: &str) -> bool {
+        false
     }
-    f.flush().expect("flush file failed");
 }
 
 pub struct Inspector {
-    rl: Editor<()>,
+    rl: LineEditor,
     flag: Arc<AtomicBool>,
+    breakpoints: Vec<Breakpoint>,
+    search: Option<RamSearch>,
 }
 
 impl Inspector {
     pub fn new() -> Self {
         Self {
-            rl: Editor::new(),
+            rl: LineEditor::new(),
             flag: Arc::new(AtomicBool::new(false)),
+            breakpoints: vec![],
+            search: None,
         }
     }
 
+    /// Break before the next instruction, as SIGUSR1 does.
+    pub fn attach(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    /// Break on SIGUSR1, there's no signal without the native build.
     pub fn start_monitor(&self) {
+        #[cfg(feature = "native")]
         signal_hook::flag::register(signal_hook::consts::SIGUSR1, Arc::clone(&self.flag)).unwrap();
     }
 
-    pub fn should_enter_trap(&self) -> bool {
+    /// It's called before every instruction, a breakpoint, a watchpoint hit by the last
+    /// instruction or the attached debugger traps.
+    pub fn should_enter_trap(&self, reg: &Register, watchpoints: &Watchpoints) -> bool {
         self.flag.load(Ordering::Relaxed)
+            || watchpoints.is_hit()
+            || self.breakpoints.iter().any(|b| b.is_hit(reg))
+    }
+
+    /// Print the candidates left, only the first ones if there are many.
+    fn print_candidates(search: &RamSearch) {
+        let candidates = search.get_candidates();
+        for (a, v) in candidates.iter().take(16) {
+            println!("{:04x}: {:02x}", a, v);
+        }
+        if candidates.len() > 16 {
+            println!("... {} addresses", candidates.len());
+        }
+    }
+
+    fn run_search(&mut self, line: &str, mem: &dyn IOHandler) {
+        match line.split_ascii_whitespace().nth(1) {
+            Some("reset") | None => {
+                let search = RamSearch::new(mem, &SEARCH_RANGES);
+                println!("{} addresses", search.get_candidates().len());
+                self.search = Some(search);
+            }
+            Some("list") => match &self.search {
+                Some(search) => Self::print_candidates(search),
+                None => println!("no search, start one with search reset"),
+            },
+            Some(_) => match (SearchFilter::parse(line), self.search.as_mut()) {
+                (Ok(filter), Some(search)) => {
+                    search.filter(mem, filter);
+                    Self::print_candidates(search);
+                }
+                (Ok(_), None) => println!("no search, start one with search reset"),
+                (Err(e), _) => println!("{}", e),
+            },
+        }
+    }
+
+    /// Prompt for the commands until the emulator resumes, the cpu must run on the mmu.
+    pub fn break_here(&mut self, cpu: &mut CPU, history: Option<&CpuHistory>) {
+        if let Some(i) = self.breakpoints.iter().position(|b| b.is_hit(&cpu.reg)) {
+            println!("hit breakpoint {}: {}", i, self.breakpoints[i]);
+        }
+        if let Some(lock) = cpu.get_lock() {
+            println!("the CPU is locked up by {}", lock);
+        }
+        let reg = cpu.get_reg_snapshot();
+        let ime = cpu.is_ime_enabled();
+        let opcode = cpu.get_current_opcode();
+        let mmu = match cpu.get_bus_mut().as_mmu_mut() {
+            Some(mmu) => mmu,
+            None => return,
+        };
+        if let Some(hit) = mmu.watchpoints.take_hit() {
+            let w = &mmu.watchpoints.get_list()[hit.index];
+            println!("hit watchpoint {}: {}, {}", hit.index, w, hit);
+        }
+        self.prompt(&reg, ime, opcode, mmu, history);
+        // The reads of the debugger don't count.
+        mmu.watchpoints.take_hit();
     }
 
-    pub fn break_here(&mut self, cpu: &CPU, _: Rc<RefCell<GPU>>) {
+    fn prompt(
+        &mut self,
+        reg: &Register,
+        ime: bool,
+        opcode: u8,
+        mmu: &mut Mmunit,
+        history: Option<&CpuHistory>,
+    ) {
         loop {
             let readline = self.rl.readline(">>> ");
             match readline {
                 Ok(line) if line.starts_with("help") => {
-                    println!("help here, todo");
+                    println!("step | continue | regs | mem <addr> <len> | var cpu|opcode");
+                    println!("break <addr> [if <reg> ==|!= <value>] | breaks | delete <n>");
+                    println!("watch <addr>[-<end>] [r|w|rw] | watches | unwatch <n>");
+                    println!("search reset|list | search ==|!= <value>");
+                    println!("search changed|unchanged|increased|decreased");
+                    println!("sprite <0-39> [x=] [y=] [tile=] [attr=]");
+                    println!("tilepoke <tile> row <0-7> <low byte> <high byte>");
+                    println!("io | set <register> <value>");
+                    println!("dump [<file>.json|<file>.csv]");
                 }
-                Ok(line) if line.starts_with("next") => {
+                Ok(line) if line.starts_with("next") || line.starts_with("step") => {
                     self.rl.add_history_entry(line.as_str());
+                    self.flag.store(true, Ordering::Relaxed);
                     break;
                 }
-                Ok(line) if line.starts_with("detach") => {
+                // Run until a breakpoint is hit.
+                Ok(line) if line.starts_with("detach") || line.starts_with("continue") => {
                     self.rl.add_history_entry(line.as_str());
                     self.flag.store(false, Ordering::Relaxed);
                     break;
                 }
+                Ok(line) if line.starts_with("breaks") => {
+                    self.rl.add_history_entry(line.as_str());
+                    for (i, b) in self.breakpoints.iter().enumerate() {
+                        println!("{}: {}", i, b);
+                    }
+                }
+                Ok(line) if line.starts_with("break") => {
+                    self.rl.add_history_entry(line.as_str());
+                    match Breakpoint::parse(&line) {
+                        Ok(b) => {
+                            println!("breakpoint {}: {}", self.breakpoints.len(), b);
+                            self.breakpoints.push(b);
+                        }
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                Ok(line) if line.starts_with("delete") => {
+                    self.rl.add_history_entry(line.as_str());
+                    match line
+                        .split_ascii_whitespace()
+                        .nth(1)
+                        .map(str::parse::<usize>)
+                    {
+                        Some(Ok(i)) if i < self.breakpoints.len() => {
+                            self.breakpoints.remove(i);
+                        }
+                        _ => println!("expect the index of a breakpoint"),
+                    }
+                }
+                Ok(line) if line.starts_with("watches") => {
+                    self.rl.add_history_entry(line.as_str());
+                    for (i, w) in mmu.watchpoints.get_list().iter().enumerate() {
+                        println!("{}: {}", i, w);
+                    }
+                }
+                Ok(line) if line.starts_with("watch") => {
+                    self.rl.add_history_entry(line.as_str());
+                    match Watchpoint::parse(&line) {
+                        Ok(w) => {
+                            println!("watchpoint {}: {}", mmu.watchpoints.get_list().len(), w);
+                            mmu.watchpoints.add(w);
+                        }
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                Ok(line) if line.starts_with("unwatch") => {
+                    self.rl.add_history_entry(line.as_str());
+                    match line
+                        .split_ascii_whitespace()
+                        .nth(1)
+                        .map(str::parse::<usize>)
+                    {
+                        Some(Ok(i)) if i < mmu.watchpoints.get_list().len() => {
+                            mmu.watchpoints.remove(i);
+                        }
+                        _ => println!("expect the index of a watchpoint"),
+                    }
+                }
+                Ok(line) if line.starts_with("search") => {
+                    self.rl.add_history_entry(line.as_str());
+                    self.run_search(&line, mmu);
+                }
+                Ok(line) if line.starts_with("regs") => {
+                    self.rl.add_history_entry(line.as_str());
+                    println!("{:?} ime={}", reg, ime);
+                }
+                Ok(line) if line.starts_with("mem") => {
+                    self.rl.add_history_entry(line.as_str());
+                    let args: Vec<&str> = line.split_ascii_whitespace().collect();
+                    match args.as_slice() {
+                        ["mem", addr, len] => match (parse_u16(addr), parse_u16(len)) {
+                            (Ok(addr), Ok(len)) => {
+                                for l in dump_memory(mmu, addr, len) {
+                                    println!("{}", l);
+                                }
+                            }
+                            (Err(e), _) | (_, Err(e)) => println!("{}", e),
+                        },
+                        _ => println!("expect mem <addr> <len>"),
+                    }
+                }
                 Ok(line) if line.starts_with("var") => {
                     if let Some(obj) = line.split_ascii_whitespace().nth(1) {
                         self.rl.add_history_entry(line.as_str());
                         match obj {
                             "cpu" => {
-                                println!("cpu register is {:?}", cpu.get_reg_snapshot())
+                                println!("cpu register is {:?}", reg)
                             }
                             "gpu" => {
                                 // TODO:
                             }
                             "opcode" => {
-                                println!("next opcode is {:0x}", cpu.get_current_opcode())
+                                println!("next opcode is {:0x}", opcode)
                             }
                             _ => {
                                 println!("unknown object")
@@ -122,9 +856,44 @@ impl Inspector {
                     }
                     println!("var command parse failed");
                 }
+                Ok(line) if line.starts_with("sprite") || line.starts_with("tilepoke") => {
+                    self.rl.add_history_entry(line.as_str());
+                    // The frame is shown once the emulator resumes.
+                    match Poke::parse(&line) {
+                        Ok(poke) => poke.apply(&mut mmu.gpu.borrow_mut()),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                Ok(line) if line.starts_with("io") => {
+                    self.rl.add_history_entry(line.as_str());
+                    for l in dump_io_registers(mmu) {
+                        println!("{}", l);
+                    }
+                }
+                Ok(line) if line.starts_with("set") => {
+                    self.rl.add_history_entry(line.as_str());
+                    match RegisterPoke::parse(&line) {
+                        Ok(poke) => {
+                            poke.apply(mmu);
+                            // The scroll and the palettes show up in the frame right away.
+                            mmu.gpu.borrow_mut().rerender();
+                        }
+                        Err(e) => println!("{}", e),
+                    }
+                }
                 Ok(line) if line.starts_with("dump") => {
                     self.rl.add_history_entry(line.as_str());
-                    dump_cpu_record(Path::new("./coredump"));
+                    let path = line
+                        .split_ascii_whitespace()
+                        .nth(1)
+                        .unwrap_or(COREDUMP_PATH);
+                    match history {
+                        Some(history) => match history.save(path) {
+                            Ok(()) => println!("{} instructions saved to {}", history.len(), path),
+                            Err(e) => println!("can't save {}: {}", path, e),
+                        },
+                        None => println!("the cpu history is off"),
+                    }
                 }
                 Ok(line) => {
                     println!("unknown command {}", line);
@@ -137,3 +906,178 @@ impl Inspector {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_poke() {
+        let poke = Poke::parse("sprite 5 x=80 y=40 tile=0x12").unwrap();
+        assert_eq!(
+            poke.get_writes(),
+            vec![(0xfe14, 56), (0xfe15, 88), (0xfe16, 0x12)]
+        );
+
+        let poke = Poke::parse("tilepoke 0x12 row 3 0b00111100 0b00111100").unwrap();
+        assert_eq!(poke.get_writes(), vec![(0x8126, 0x3c), (0x8127, 0x3c)]);
+
+        assert!(Poke::parse("sprite 40 x=1").is_err());
+        assert!(Poke::parse("sprite 0 x=250").is_err());
+        assert!(Poke::parse("sprite 0 z=1").is_err());
+        assert!(Poke::parse("tilepoke 1 row 8 0 0").is_err());
+        assert!(Poke::parse("tilepoke 1 3 0 0").is_err());
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut reg = Register::new();
+        reg.set_PC(0x0150);
+        reg.set_A(0x12);
+        reg.set_HL(0xc000);
+
+        assert!(Breakpoint::parse("break 0x150").unwrap().is_hit(&reg));
+        assert!(!Breakpoint::parse("break 0x151").unwrap().is_hit(&reg));
+        assert!(
+            Breakpoint::parse("break 336 if a == 0x12")
+                .unwrap()
+                .is_hit(&reg)
+        );
+        assert!(
+            !Breakpoint::parse("break 0x150 if hl != 0xc000")
+                .unwrap()
+                .is_hit(&reg)
+        );
+        assert_eq!(
+            Breakpoint::parse("break 0x150 if HL == 0xc000")
+                .unwrap()
+                .to_string(),
+            "0x0150 if hl == 0xc000"
+        );
+
+        assert!(Breakpoint::parse("break zz").is_err());
+        assert!(Breakpoint::parse("break 0x150 if x == 1").is_err());
+        assert!(Breakpoint::parse("break 0x150 if a == 0x100").is_err());
+        assert!(Breakpoint::parse("break 0x150 if a > 1").is_err());
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let w = Watchpoint::parse("watch 0xc000-0xc0ff").unwrap();
+        assert!(w.is_hit(0xc010, true));
+        assert!(!w.is_hit(0xc010, false));
+        assert!(!w.is_hit(0xc100, true));
+        assert_eq!(w.to_string(), "0xc000-0xc0ff w");
+        let w = Watchpoint::parse("watch 0xff80 rw").unwrap();
+        assert!(w.is_hit(0xff80, false) && w.is_hit(0xff80, true));
+        assert_eq!(w.to_string(), "0xff80 rw");
+
+        assert!(Watchpoint::parse("watch 0xc100-0xc000").is_err());
+        assert!(Watchpoint::parse("watch 0xc000 x").is_err());
+
+        let mut watchpoints = Watchpoints::new();
+        watchpoints.check(0xc000, 0x01, true);
+        assert!(!watchpoints.is_hit());
+        watchpoints.add(Watchpoint::parse("watch 0xc001 r").unwrap());
+        watchpoints.add(Watchpoint::parse("watch 0xc000").unwrap());
+        watchpoints.check(0xc001, 0x02, true);
+        assert!(!watchpoints.is_hit());
+        watchpoints.check(0xc000, 0x03, true);
+        // The first hit is kept.
+        watchpoints.check(0xc001, 0x04, false);
+        let hit = watchpoints.take_hit().unwrap();
+        assert_eq!(hit.index, 1);
+        assert_eq!(hit.to_string(), "write 0x03 to 0xc000");
+        assert!(!watchpoints.is_hit());
+    }
+
+    struct Ram(RefCell<Vec<u8>>);
+
+    impl IOHandler for Ram {
+        fn read_byte(&self, a: u16) -> u8 {
+            self.0.borrow()[a as usize]
+        }
+
+        fn write_byte(&mut self, a: u16, v: u8) {
+            self.0.get_mut()[a as usize] = v;
+        }
+    }
+
+    #[test]
+    fn test_ram_search() {
+        let mut ram = Ram(RefCell::new(vec![0x00; 0x10000]));
+        ram.write_byte(0xc010, 3);
+        ram.write_byte(0xd020, 3);
+        ram.write_byte(0xff90, 3);
+        let mut search = RamSearch::new(&ram, &SEARCH_RANGES);
+        assert_eq!(search.get_candidates().len(), 0x2000 + 0x7f);
+        assert_eq!(search.filter(&ram, SearchFilter::Equal(3)), 3);
+
+        // A life is lost.
+        ram.write_byte(0xc010, 2);
+        ram.write_byte(0xff90, 4);
+        let filter = SearchFilter::parse("search decreased").unwrap();
+        assert_eq!(search.filter(&ram, filter), 1);
+        assert_eq!(search.get_candidates(), [(0xc010, 2)]);
+        assert_eq!(search.filter(&ram, SearchFilter::Unchanged), 1);
+        assert_eq!(search.filter(&ram, SearchFilter::Changed), 0);
+
+        assert_eq!(
+            SearchFilter::parse("search != 0x10"),
+            Ok(SearchFilter::NotEqual(0x10))
+        );
+        assert!(SearchFilter::parse("search == 0x100").is_err());
+        assert!(SearchFilter::parse("search bigger").is_err());
+    }
+
+    #[test]
+    fn test_cycle_profiler() {
+        let mut profiler = CycleProfiler::new();
+        // The main loop at 0150 calls 0200, which calls 4000 of the bank 2.
+        profiler.record((0, 0x0150), 0xfffe, 4);
+        profiler.record((0, 0x0151), 0xfffe, 24);
+        profiler.call((0, 0x0200), 0xfffc);
+        profiler.record((0, 0x0200), 0xfffc, 8);
+        profiler.record((0, 0x0201), 0xfffc, 24);
+        profiler.call((2, 0x4000), 0xfffa);
+        profiler.record((2, 0x4000), 0xfffa, 100);
+        // The RETs are counted in the functions they return from.
+        profiler.record((2, 0x4001), 0xfffa, 16);
+        profiler.record((0, 0x0204), 0xfffc, 16);
+        profiler.record((0, 0x0154), 0xfffe, 4);
+        assert_eq!(profiler.get_total(), 196);
+        assert_eq!(
+            profiler.top_functions(3),
+            vec![((2, 0x4000), 116), ((0, 0x0200), 48), ((0, 0x0100), 32)]
+        );
+        assert_eq!(profiler.top_instructions(1), vec![((2, 0x4000), 100)]);
+        assert_eq!(profiler.report(1)[1], "02:4000        116 cycles  59.2%");
+    }
+
+    #[test]
+    fn test_io_registers() {
+        let mut ram = Ram(RefCell::new(vec![0x00; 0x10000]));
+        ram.write_byte(0xff40, 0x91);
+        ram.write_byte(0xffff, 0x1f);
+        let lines = dump_io_registers(&ram);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "LCDC 91 STAT 00 SCY 00");
+        assert_eq!(lines[3], "IE 1F IF 00 TIMA 00");
+        assert_eq!(lines[4], "TMA 00 TAC 00");
+
+        RegisterPoke::parse("set scx 8").unwrap().apply(&mut ram);
+        assert_eq!(ram.read_byte(0xff43), 8);
+        assert_eq!(
+            RegisterPoke::parse("set 0xff4b 0x07"),
+            Ok(RegisterPoke {
+                addr: 0xff4b,
+                value: 0x07
+            })
+        );
+        assert!(RegisterPoke::parse("set 0xc000 1").is_err());
+        assert!(RegisterPoke::parse("set LCDC 0x100").is_err());
+        assert!(RegisterPoke::parse("set LCDC").is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+/// Returns the feature behind the IO register if it's not emulated yet, the read of such
+/// register returns 0x00 and the write is dropped.
+pub fn get_stub_feature(a: u16) -> Option<&'static str> {
+    match a {
+        0xff01..=0xff02 => Some("serial"),
+        0xff51..=0xff55 => Some("hdma"),
+        0xff56 => Some("infrared"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StubAccess {
+    pub reads: u64,
+    pub writes: u64,
+    // The PC of the instruction which touched the register at first.
+    pub first_pc: u16,
+}
+
+/// Counts the accesses to the stub IO registers, so it's clear which missing features a game
+/// actually needs.
+#[derive(Debug, Default)]
+pub struct IoDiagnostics {
+    accesses: BTreeMap<u16, StubAccess>,
+    // The PC of the executing instruction.
+    pc: u16,
+}
+
+impl IoDiagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn record(&mut self, a: u16, is_write: bool) {
+        let pc = self.pc;
+        let access = self.accesses.entry(a).or_insert(StubAccess {
+            reads: 0,
+            writes: 0,
+            first_pc: pc,
+        });
+        if is_write {
+            access.writes += 1;
+        } else {
+            access.reads += 1;
+        }
+    }
+
+    pub fn get(&self, a: u16) -> Option<&StubAccess> {
+        self.accesses.get(&a)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accesses.is_empty()
+    }
+
+    /// Returns one line per register, the most used one comes first.
+    pub fn summary(&self) -> Vec<String> {
+        let mut accesses: Vec<(&u16, &StubAccess)> = self.accesses.iter().collect();
+        accesses.sort_by_key(|(_, s)| std::cmp::Reverse(s.reads + s.writes));
+        accesses
+            .into_iter()
+            .map(|(a, s)| {
+                format!(
+                    "{:04x} {:<18} reads {:>8} writes {:>8} first pc {:04x}",
+                    a,
+                    get_stub_feature(*a).unwrap_or("unknown"),
+                    s.reads,
+                    s.writes,
+                    s.first_pc
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_sorted_by_count() {
+        let mut d = IoDiagnostics::new();
+        d.set_pc(0x0150);
+        d.record(0xff02, true);
+        d.set_pc(0x0200);
+        d.record(0xff01, false);
+        d.record(0xff01, true);
+        d.record(0xff02, false);
+        d.record(0xff02, false);
+
+        assert_eq!(
+            d.get(0xff02),
+            Some(&StubAccess {
+                reads: 2,
+                writes: 1,
+                first_pc: 0x0150
+            })
+        );
+        let summary = d.summary();
+        assert_eq!(summary.len(), 2);
+        assert!(summary[0].starts_with("ff02 serial"));
+        assert!(summary[1].ends_with("first pc 0200"));
+    }
+}
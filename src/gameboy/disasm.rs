@@ -0,0 +1,224 @@
+//! The disassembler, it reuses the opcode names of `OP_CODE_SET` and `CB_CODE_SET` and decodes
+//! their operands.
+//!
+//! The operand placeholders in the names are:
+//! ```ignore
+//! d8   immediate byte          LD B,0x12
+//! a8   high page offset byte   LDH (0x44),A    
+//! r8   signed byte             JR NZ,0x0150 (the jump target) / ADD SP,-0x02
+//! d16  immediate word          LD HL,0xc000
+//! a16  address word            CALL 0x0200
+//! ```
+use std::fmt;
+
+use super::cpu::opcode_set::{CB_CODE_SET, OP_CODE_SET};
+use super::cpu::Register;
+use super::mmu::IOHandler;
+
+/// A decoded instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub pc: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}: {}", self.pc, self.text)
+    }
+}
+
+/// Returns the count of operand bytes and the name of the base opcode.
+fn lookup(opcode: u8) -> Option<(usize, String)> {
+    let name = match opcode {
+        0xcb => return Some((1, String::new())),
+        // STOP is followed by a padding byte.
+        0x10 => return Some((1, "STOP".to_string())),
+        _ => OP_CODE_SET[opcode as usize].as_ref()?.get_name(),
+    };
+    let n = if name.contains("d16") || name.contains("a16") {
+        2
+    } else if name.contains("d8") || name.contains("a8") || name.contains("r8") {
+        1
+    } else {
+        0
+    };
+    Some((n, name))
+}
+
+fn signed(v: u8) -> String {
+    let v = v as i8;
+    if v < 0 {
+        format!("-0x{:02x}", -i16::from(v))
+    } else {
+        format!("0x{:02x}", v)
+    }
+}
+
+/// Decode the instruction at the beginning of the bytes, which are located at `pc`.
+/// An unknown opcode or a truncated instruction is decoded as a `DB` of the first byte.
+pub fn decode(bytes: &[u8], pc: u16) -> Instruction {
+    let db = |b: u8| Instruction {
+        pc,
+        bytes: vec![b],
+        text: format!("DB 0x{:02x}", b),
+    };
+    let opcode = match bytes.first() {
+        Some(b) => *b,
+        None => {
+            return Instruction {
+                pc,
+                bytes: vec![],
+                text: String::new(),
+            };
+        }
+    };
+    let (n, name) = match lookup(opcode) {
+        Some(v) if bytes.len() > v.0 => v,
+        _ => return db(opcode),
+    };
+    let bytes = bytes[..=n].to_vec();
+    let text = if opcode == 0xcb {
+        CB_CODE_SET[bytes[1] as usize].get_name()
+    } else if n == 2 {
+        let word = format!("0x{:02x}{:02x}", bytes[2], bytes[1]);
+        name.replace("d16", &word).replace("a16", &word)
+    } else if name.contains("r8") && name.starts_with("JR") {
+        let target = pc.wrapping_add(2).wrapping_add(bytes[1] as i8 as u16);
+        name.replace("r8", &format!("0x{:04x}", target))
+    } else if name.contains("SP+r8") {
+        let v = signed(bytes[1]);
+        let v = if v.starts_with('-') {
+            v
+        } else {
+            format!("+{}", v)
+        };
+        name.replace("+r8", &v)
+    } else if n == 1 {
+        let v = if name.contains("r8") {
+            signed(bytes[1])
+        } else {
+            format!("0x{:02x}", bytes[1])
+        };
+        name.replace("d8", &v).replace("a8", &v).replace("r8", &v)
+    } else {
+        name
+    };
+    Instruction { pc, bytes, text }
+}
+
+/// Disassemble the bytes located at `base_pc` one instruction after another.
+pub fn disassemble(bytes: &[u8], base_pc: u16) -> Vec<Instruction> {
+    let mut r = vec![];
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let ins = decode(&bytes[offset..], base_pc.wrapping_add(offset as u16));
+        offset += ins.len();
+        r.push(ins);
+    }
+    r
+}
+
+/// Decode the instruction at the address of the memory.
+pub fn decode_at(mem: &dyn IOHandler, pc: u16) -> Instruction {
+    let bytes: Vec<u8> = (0..3).map(|i| mem.read_byte(pc.wrapping_add(i))).collect();
+    decode(&bytes, pc)
+}
+
+/// A line of the `--trace` output, the instruction at PC and the registers before it runs.
+pub fn trace_line(mem: &dyn IOHandler, reg: &Register) -> String {
+    let ins = decode_at(mem, reg.get_PC());
+    format!(
+        "{:04x}: {:<16} A={:02x} F={:02x} B={:02x} C={:02x} D={:02x} E={:02x} H={:02x} L={:02x} SP={:04x}",
+        ins.pc,
+        ins.text,
+        reg.get_A(),
+        reg.get_AF() as u8,
+        reg.get_B(),
+        reg.get_C(),
+        reg.get_D(),
+        reg.get_E(),
+        reg.get_H(),
+        reg.get_L(),
+        reg.get_SP()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(bytes: &[u8], pc: u16) -> Vec<String> {
+        disassemble(bytes, pc).into_iter().map(|i| i.text).collect()
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let bytes = [
+            0x00, 0x3e, 0x12, 0x21, 0x00, 0xc0, 0xe0, 0x44, 0x20, 0xfe, 0xcb, 0x7c, 0xcb, 0x37,
+            0xe8, 0xfe, 0xf8, 0x02, 0x10, 0x00, 0xd3,
+        ];
+        assert_eq!(
+            texts(&bytes, 0x0150),
+            vec![
+                "NOP",
+                "LD A,0x12",
+                "LD HL,0xc000",
+                "LDH (0x44),A",
+                "JR NZ,0x0158",
+                "BIT 7,H",
+                "SWAP A",
+                "ADD SP,-0x02",
+                "LD HL,SP+0x02",
+                "STOP",
+                "DB 0xd3",
+            ]
+        );
+        assert_eq!(disassemble(&bytes, 0x0150)[2].pc, 0x0153);
+    }
+
+    #[test]
+    fn test_truncated_instruction() {
+        assert_eq!(texts(&[0xcd, 0x00], 0), vec!["DB 0xcd", "NOP"]);
+        assert!(disassemble(&[], 0).is_empty());
+    }
+
+    struct Ram(Vec<u8>);
+
+    impl IOHandler for Ram {
+        fn read_byte(&self, a: u16) -> u8 {
+            self.0[a as usize]
+        }
+
+        fn write_byte(&mut self, a: u16, v: u8) {
+            self.0[a as usize] = v;
+        }
+    }
+
+    #[test]
+    fn test_trace_line() {
+        let mut ram = Ram(vec![0x00; 0x10000]);
+        ram.write_byte(0x0100, 0xc3);
+        ram.write_byte(0x0101, 0x50);
+        ram.write_byte(0x0102, 0x01);
+        let mut reg = Register::new();
+        reg.set_PC(0x0100);
+        reg.set_AF(0x01b0);
+        reg.set_SP(0xfffe);
+        assert_eq!(
+            trace_line(&ram, &reg),
+            "0100: JP 0x0150        A=01 F=b0 B=00 C=00 D=00 E=00 H=00 L=00 SP=fffe"
+        );
+    }
+}
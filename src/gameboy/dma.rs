@@ -0,0 +1,235 @@
+//! The DMA transfers.
+//!
+//! The OAM DMA, FF46 starts copying 160 bytes from `XX00` to the OAM at one byte per M-cycle.
+//! The transfer starts one M-cycle after the write. While it runs, the CPU only reaches the IO
+//! registers and the high ram: the OAM reads 0xff, and the bus the DMA reads from gives the
+//! byte being copied instead of the one at the address.
+//! See: https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+//!
+//! The CGB VRAM DMA, FF55 copies blocks of 0x10 bytes from the ROM or the work ram to the VRAM.
+//! The general purpose DMA copies all the blocks at once, the HBlank DMA copies a block in each
+//! HBlank. The CPU is stopped while a block is copied.
+//! See: https://gbdev.io/pandocs/CGB_Registers.html#lcd-vram-dma-transfers
+use std::ops::Range;
+
+use super::state::{StateError, StateReader, StateWriter, Stateful};
+
+// The bytes of the OAM.
+pub const OAM_DMA_LEN: u16 = 0xa0;
+
+/// The memory buses of the DMG, the DMA blocks the one it reads from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bus {
+    // The cartridge and the work ram.
+    External,
+    Video,
+    // The OAM, the IO registers and the high ram.
+    Internal,
+}
+
+impl Bus {
+    pub fn of(a: u16) -> Self {
+        match a {
+            0x8000..=0x9fff => Self::Video,
+            0xfe00..=0xffff => Self::Internal,
+            _ => Self::External,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OamDma {
+    // FF46 - DMA - DMA Transfer and Start Address (R/W)
+    reg: u8,
+    // The transfer is running and blocks the memory.
+    active: bool,
+    // A transfer is requested and waits for `delay` cycles, the previous one keeps blocking.
+    starting: bool,
+    delay: u8,
+    // The bytes copied.
+    index: u16,
+    // The cycles not enough for a byte.
+    cycles: u8,
+}
+
+impl OamDma {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> u8 {
+        self.reg
+    }
+
+    /// Request a transfer from `v << 8`, a running one is restarted.
+    pub fn set(&mut self, v: u8) {
+        self.reg = v;
+        self.starting = true;
+        self.delay = 4;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// The address of the byte to copy, the pages above 0xdf read the echo ram.
+    pub fn get_source(&self, offset: u16) -> u16 {
+        let src = (u16::from(self.reg) << 8) + offset;
+        if src >= 0xe000 { src - 0x2000 } else { src }
+    }
+
+    /// The address the DMA reads at this moment.
+    pub fn get_current(&self) -> u16 {
+        self.get_source(self.index.min(OAM_DMA_LEN - 1))
+    }
+
+    /// Step the transfer by the CPU cycles, returns the offsets of the bytes to copy.
+    pub fn next(&mut self, cycles: u32) -> Range<u16> {
+        let mut cycles = cycles;
+        if self.starting {
+            if cycles < u32::from(self.delay) {
+                self.delay -= cycles as u8;
+                return 0..0;
+            }
+            cycles -= u32::from(self.delay);
+            self.starting = false;
+            self.active = true;
+            self.index = 0;
+            self.cycles = 0;
+        }
+        if !self.active {
+            return 0..0;
+        }
+        let cycles = cycles + u32::from(self.cycles);
+        let start = self.index;
+        let end = (u32::from(start) + cycles / 4).min(u32::from(OAM_DMA_LEN)) as u16;
+        self.cycles = (cycles % 4) as u8;
+        self.index = end;
+        if end == OAM_DMA_LEN {
+            self.active = false;
+        }
+        start..end
+    }
+}
+
+impl Stateful for OamDma {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u8(self.reg);
+        w.put_bool(self.active);
+        w.put_bool(self.starting);
+        w.put_u8(self.delay);
+        w.put_u16(self.index);
+        w.put_u8(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.reg = r.get_u8()?;
+        self.active = r.get_bool()?;
+        self.starting = r.get_bool()?;
+        self.delay = r.get_u8()?;
+        self.index = r.get_u16()?;
+        self.cycles = r.get_u8()?;
+        Ok(())
+    }
+}
+
+// The bytes of a VRAM DMA block.
+pub const HDMA_BLOCK_LEN: u16 = 0x10;
+
+// The normal speed cycles to copy a block, the CPU is stopped meanwhile.
+pub const HDMA_BLOCK_CYCLES: u32 = 32;
+
+#[derive(Debug)]
+pub struct Hdma {
+    // FF51 - HDMA1, FF52 - HDMA2 - the source, the lower 4 bits are ignored.
+    src: u16,
+    // FF53 - HDMA3, FF54 - HDMA4 - the offset of the destination in the VRAM, the upper 3 bits
+    // and the lower 4 bits are ignored.
+    dst: u16,
+    // The HBlank DMA is running.
+    active: bool,
+    // FF55 - HDMA5 - the remaining blocks minus 1, 0x7f when the transfer is done.
+    remain: u8,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self {
+            src: 0x0000,
+            dst: 0x0000,
+            active: false,
+            remain: 0x7f,
+        }
+    }
+}
+
+impl Hdma {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The source and the destination can't be read back.
+    pub fn get(&self, a: u16) -> u8 {
+        match a {
+            0xff55 if self.active => self.remain,
+            0xff55 => 0x80 | self.remain,
+            _ => 0xff,
+        }
+    }
+
+    /// Write the registers, returns the count of the blocks to copy at once. The general purpose
+    /// DMA copies them all, the HBlank DMA waits for the HBlank, a write with the bit 7 reset
+    /// cancels it.
+    pub fn set(&mut self, a: u16, v: u8) -> u16 {
+        match a {
+            0xff51 => self.src = (u16::from(v) << 8) | (self.src & 0x00ff),
+            0xff52 => self.src = (self.src & 0xff00) | u16::from(v & 0xf0),
+            0xff53 => self.dst = (u16::from(v & 0x1f) << 8) | (self.dst & 0x00ff),
+            0xff54 => self.dst = (self.dst & 0xff00) | u16::from(v & 0xf0),
+            0xff55 if self.active && v & 0x80 == 0x00 => self.active = false,
+            0xff55 => {
+                self.remain = v & 0x7f;
+                if v & 0x80 != 0x00 {
+                    self.active = true;
+                } else {
+                    return u16::from(self.remain) + 1;
+                }
+            }
+            _ => {}
+        }
+        0
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Take the next block, returns the source and the destination offset in the VRAM.
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.src, self.dst);
+        self.src = self.src.wrapping_add(HDMA_BLOCK_LEN);
+        self.dst = self.dst.wrapping_add(HDMA_BLOCK_LEN) & 0x1ff0;
+        if self.remain == 0x00 {
+            self.active = false;
+        }
+        self.remain = self.remain.wrapping_sub(1) & 0x7f;
+        block
+    }
+}
+
+impl Stateful for Hdma {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u16(self.src);
+        w.put_u16(self.dst);
+        w.put_bool(self.active);
+        w.put_u8(self.remain);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.src = r.get_u16()?;
+        self.dst = r.get_u16()?;
+        self.active = r.get_bool()?;
+        self.remain = r.get_u8()?;
+        Ok(())
+    }
+}
@@ -1,84 +1,1294 @@
 use std::cell::RefCell;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::mem;
 use std::panic;
-use std::path::Path;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+#[cfg(feature = "native")]
+use std::thread;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "native")]
 use backtrace::Backtrace;
-use log::info;
-use minifb;
+#[cfg(feature = "native")]
+use chrono::Local;
+use log::{error, info};
+#[cfg(feature = "native")]
+use minifb::Key;
 
+use super::bootrom::BootRom;
+use super::browser::RomBrowser;
 use super::cartridge::load_cartridge_from_file;
-use super::cartridge::CartridgePlatform;
+use super::cartridge::load_cartridge_from_bytes;
+use super::cartridge::load_cartridge_from_rom;
+use super::cartridge::load_empty_cartridge;
+use super::cartridge::BatteryStats;
+use super::cartridge::CameraImage;
+use super::cartridge::CartridgeInfo;
+use super::cartridge::Cartridge;
+use super::cartridge::read_rom;
+use super::clock;
 use super::config::Config;
+use super::cpu::CpuLock;
 use super::cpu::IntReg;
 use super::cpu::CPU;
-use super::debug::dump_cpu_record;
-use super::debug::Inspector;
+use super::cpu::Register;
+#[cfg(feature = "native")]
+use super::debug::dump_io_registers;
+use super::debug::{CodeAddress, CycleProfiler, Inspector};
+use super::disasm::trace_line;
+use super::error::Error;
+use super::frontend::{Frame, InputSource, RumbleSink, VideoSink};
+#[cfg(feature = "native")]
+use super::frontend::{open_frontend, Audio, Frontend, Window};
+use super::gamepad::{GamepadBackend, Gamepads};
+#[cfg(feature = "native")]
+use super::graphics::filter::PostProcess;
 use super::graphics::gpu::GPU;
+use super::history::{CpuHistory, CpuRecord, COREDUMP_PATH};
+#[cfg(feature = "native")]
+use super::graphics::gpu::{VIEWER_H, VIEWER_W};
+use super::joypad::Autofire;
 use super::joypad::Joypad;
 use super::joypad::JoypadKey;
-use super::mmu::Mmunit;
-use super::set_global_term;
+use super::joypad::KEYS;
+use super::mmu::{IOHandler, Mmunit};
+use super::movie::Movie;
+use super::osd::message::MessageOverlay;
+#[cfg(feature = "native")]
+use super::osd::perf::PerfOverlay;
+use super::pacing::{FramePacer, SyncMode};
+use super::profiler::MemoryProfiler;
+use super::recorder::{Recorder, VideoFormat};
+use super::screenshot::{save_png, FrameDump};
+use super::script::{Hooks, ScriptApi};
+use super::sound::apu::DEFAULT_SAMPLE_RATE;
+#[cfg(feature = "native")]
+use super::sound::AudioQueue;
+use super::sound::{Apu, AudioSink, WavWriter};
+use super::state::{Snapshot, StateError};
+use super::state::{APU_SECTION, BOOT_ROM_SECTION, CARTRIDGE_SECTION, CPU_SECTION, GPU_SECTION};
+use super::state::{JOYPAD_SECTION, MMU_SECTION, SERIAL_SECTION, TIMER_SECTION};
+use super::stats::{format_title, FrameStats, StatsCollector};
+use super::storage::Storage;
 use super::timer::Timer;
-use super::Term;
+use super::FRAME_CYCLES;
+#[cfg(feature = "native")]
+use super::STEP_TIME;
 use super::{SCREEN_H, SCREEN_W};
 
+// The cartridge, the config with the settings of the game and the name of the game.
+type OpenedRom = (Box<dyn Cartridge>, Config, Option<String>);
+
+/// The result of `Emulator::bench`.
+#[derive(Debug)]
+pub struct BenchReport {
+    pub frames: u32,
+    pub cycles: u64,
+    pub instructions: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    pub fn get_frames_per_sec(&self) -> f64 {
+        f64::from(self.frames) / self.elapsed.as_secs_f64()
+    }
+
+    pub fn get_instructions_per_sec(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} frames in {:.3}s, {:.1} frames/sec, {:.0} instructions/sec",
+            self.frames,
+            self.elapsed.as_secs_f64(),
+            self.get_frames_per_sec(),
+            self.get_instructions_per_sec()
+        )
+    }
+}
+
+/// The commands sent to the emulator from another thread, e.g. the UI thread of a frontend
+/// running the emulator on its own thread. They're run at the start of the next frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Hold the key down along with the input source, until it's released.
+    Press(JoypadKey),
+    Release(JoypadKey),
+    Pause,
+    Resume,
+    SaveState(PathBuf),
+    LoadState(PathBuf),
+    /// See `Emulator::frame_step`.
+    FrameStep,
+    /// See `Emulator::set_slow_motion`.
+    SlowMotion(u32),
+}
+
+/// The input movie attached to the running game.
+enum MovieSession {
+    /// The keys are captured every frame, the movie is written to the path when the window closes.
+    Recording(Movie, PathBuf),
+    /// The keys are fed from the movie, the next frame is at the index. The keyboard is ignored.
+    Playing(Movie, usize),
+}
+
+// The bit of the key in the layout of `Joypad::get_keys`.
+fn key_bit(key: &JoypadKey) -> u8 {
+    1 << KEYS.iter().position(|k| k == key).unwrap()
+}
+
+fn mmu_of(cpu: &CPU) -> &Mmunit {
+    cpu.get_bus()
+        .as_mmu()
+        .expect("the emulator runs on the mmu")
+}
+
+fn mmu_of_mut(cpu: &mut CPU) -> &mut Mmunit {
+    cpu.get_bus_mut()
+        .as_mmu_mut()
+        .expect("the emulator runs on the mmu")
+}
+
 pub struct Emulator {
     config: Config,
+    // The config without the settings of the game, the next rom starts from it.
+    defaults: Config,
+    // The name of the game in the data directory.
+    game: Option<String>,
     cpu: CPU,
-    gpu: Rc<RefCell<GPU>>,
-    pub mmu: Rc<RefCell<Mmunit>>,
     inspector: Inspector,
+    // The start screen, it's only available when no rom is given.
+    browser: Option<RomBrowser>,
+    movie: Option<MovieSession>,
+    // The turbo keys and the autofire buttons, it's applied before the keys reach the joypad.
+    autofire: Autofire,
+    // The samples of every frame are written here, they're dropped if it's not set.
+    audio: Option<Box<dyn AudioSink>>,
+    // Every instruction is logged here before it's executed.
+    trace: Option<Box<dyn Write + Send>>,
+    // The last executed instructions for the coredump, `None` records nothing.
+    history: Option<CpuHistory>,
+    // The cycles of the instructions and the functions, `None` counts nothing.
+    cpu_profile: Option<CycleProfiler>,
+    // The frontend plugged by the library user, the window doesn't use them.
+    video: Option<Box<dyn VideoSink>>,
+    input: Option<Box<dyn InputSource + Send>>,
+    // The rumble motor of the cartridge goes here, the OSD shows it without a sink.
+    rumble: Option<Box<dyn RumbleSink>>,
+    rumbling: bool,
+    // Every Nth frame is saved here, for comparing the runs.
+    frame_dump: Option<FrameDump>,
+    // The video being recorded.
+    recorder: Option<Recorder>,
+    // The controllers read by the window along with the keyboard.
+    gamepads: Option<Gamepads>,
+    // The messages drawn over the frame of the window.
+    osd: MessageOverlay,
+    // `None` without a clock.
+    stats: Option<StatsCollector>,
+    // Sleeps after every frame to keep the real hardware speed.
+    pacer: FramePacer,
+    paused: bool,
+    // The frames left to run while paused, one is added by every frame step.
+    frame_steps: u32,
+    // The last finished frame.
+    frame: Frame,
+    // The callbacks of the scripts.
+    hooks: Hooks,
+    // The commands from the other threads, and the keys they hold in the layout of
+    // `Joypad::get_keys`.
+    commands: Option<Receiver<Command>>,
+    remote_keys: u8,
 }
 
 impl Emulator {
-    pub fn new(config: Config) -> Self {
-        let path = Path::new(config.get_file_path());
-        let cart = load_cartridge_from_file(path);
-        let term = match cart.get_meta().get_platform() {
-            CartridgePlatform::GBC => Term::GBC,
-            CartridgePlatform::GBC_ONLY => Term::GBC,
-            CartridgePlatform::SGB => Term::SGB,
-            _ => Term::GB,
+    /// Returns the error if the rom of the config can't be loaded.
+    pub fn new(defaults: Config) -> Result<Self, Error> {
+        // Without a rom, boot into the start screen and let the user pick one.
+        let (cart, config, game, browser) = match defaults.get_file_path() {
+            Some(path) => {
+                let (cart, config, game) = Self::open_rom(Path::new(path), &defaults)?;
+                (cart, config, game, None)
+            }
+            None => (
+                load_empty_cartridge(),
+                defaults.clone(),
+                None,
+                Some(RomBrowser::new(defaults.get_rom_dir())),
+            ),
         };
+        let cpu = Self::build(cart, &config)?;
+        let audio = config.get_audio_dump().and_then(|path| {
+            match WavWriter::create(path, DEFAULT_SAMPLE_RATE) {
+                Ok(w) => Some(Box::new(w) as Box<dyn AudioSink>),
+                Err(e) => {
+                    error!("Can't create the audio dump {}: {}", path, e);
+                    None
+                }
+            }
+        });
+        let trace = config
+            .get_trace()
+            .and_then(|path| match File::create(path) {
+                Ok(f) => Some(Box::new(BufWriter::new(f)) as Box<dyn Write + Send>),
+                Err(e) => {
+                    error!("Can't create the trace file {}: {}", path, e);
+                    None
+                }
+            });
+        let frame_dump = config.get_frame_dump().and_then(|dir| {
+            match FrameDump::create(dir, config.get_frame_dump_interval()) {
+                Ok(d) => Some(d),
+                Err(e) => {
+                    error!("Can't create the frame dump directory {}: {}", dir, e);
+                    None
+                }
+            }
+        });
 
-        set_global_term(term);
+        let mut osd = MessageOverlay::new(config.get_osd_corner(), config.get_osd_timeout());
+        osd.set_fps_enabled(config.is_fps_shown());
+        let pacer = FramePacer::new(Self::get_pacer_mode(&config));
+        let history = Self::new_history(config.get_cpu_history_size());
+        let cpu_profile = config.is_cpu_profile_enabled().then(CycleProfiler::new);
+        let mut autofire = Autofire::new(config.get_autofire_rate());
+        for button in config.get_autofire() {
+            autofire.set_button(button, true);
+        }
 
-        let intf = Rc::new(RefCell::new(IntReg::new()));
+        Ok(Self {
+            config,
+            defaults,
+            game,
+            cpu,
+            inspector: Inspector::new(),
+            browser,
+            movie: None,
+            autofire,
+            audio,
+            trace,
+            history,
+            cpu_profile,
+            video: None,
+            input: None,
+            rumble: None,
+            rumbling: false,
+            frame_dump,
+            recorder: None,
+            gamepads: None,
+            osd,
+            stats: clock::now().map(StatsCollector::new),
+            pacer,
+            paused: false,
+            frame_steps: 0,
+            frame: Frame::default(),
+            hooks: Hooks::new(),
+            commands: None,
+            remote_keys: 0x00,
+        })
+    }
 
-        let gpu = Rc::new(RefCell::new(GPU::new(intf.clone())));
-        let joypad = Joypad::new(intf.clone());
+    /// The mmu is owned by the cpu, so the cpu reaches the memory without any borrow check.
+    pub fn get_mmu(&self) -> &Mmunit {
+        mmu_of(&self.cpu)
+    }
+
+    pub fn get_mmu_mut(&mut self) -> &mut Mmunit {
+        mmu_of_mut(&mut self.cpu)
+    }
+
+    /// Build the whole machine around the cartridge.
+    fn build(mut cart: Box<dyn Cartridge>, config: &Config) -> Result<CPU, Error> {
+        let boot_rom = config.get_boot_rom().map(BootRom::load).transpose()?;
+        if let Some(path) = config.get_camera_image() {
+            cart.set_camera_image(CameraImage::load(path)?);
+        }
+        Ok(Self::build_with(cart, config, boot_rom))
+    }
+
+    fn build_with(mut cart: Box<dyn Cartridge>, config: &Config, boot_rom: Option<BootRom>) -> CPU {
+        let term = config.get_mode().get_term(cart.get_meta().get_platform());
+        let palette = config
+            .get_palettes()
+            .find(&cart.get_meta().get_title(), config.get_palette());
+        cart.set_battery_delay(config.get_save_delay());
+
+        let intf = Arc::new(IntReg::new());
+
+        let mut gpu = GPU::with_term(intf.clone(), term);
+        gpu.set_renderer(config.get_renderer());
+        gpu.set_accuracy(config.get_accuracy());
+        gpu.set_dmg_palette(palette);
+        let mut joypad = Joypad::new(intf.clone());
+        joypad.set_opposite_direction(config.get_opposite_direction());
         let timer = Timer::new(intf.clone());
 
-        let mmu = Rc::new(RefCell::new(Mmunit::new(
+        let mut mmu = Mmunit::new(
             cart,
-            gpu.clone(),
+            Apu::new(DEFAULT_SAMPLE_RATE),
+            gpu,
             joypad,
             timer,
             intf.clone(),
-        )));
-        let cpu = CPU::new(mmu.clone(), true);
+        );
+        mmu.init_ram(config.get_ram_seed());
+        mmu.cheats = config.get_cheats().clone();
+        let has_boot_rom = boot_rom.is_some();
+        if let Some(boot_rom) = boot_rom {
+            mmu.set_boot_rom(boot_rom);
+        }
+        if config.is_memory_profiled() {
+            mmu.profiler = Some(RefCell::new(MemoryProfiler::new()));
+        }
+        let mut cpu = CPU::with_term(mmu, term);
+        cpu.set_stack_check(config.is_stack_check_enabled());
+        // The boot rom starts from the cleared registers and sets them up by itself.
+        if has_boot_rom {
+            cpu.set_reg(Register::new());
+        }
         info! {"Emulator new {:?}", cpu.get_reg_snapshot()};
 
-        Self {
-            config,
-            cpu,
-            gpu,
-            mmu,
-            inspector: Inspector::new(),
+        cpu
+    }
+
+    /// Reset the game like the power is cycled. The cpu, the memory, the GPU, the timer and the
+    /// sound start over, the cartridge, its ram, the cheats and the memory profile are kept.
+    pub fn reset(&mut self) {
+        let mmu = self.get_mmu_mut();
+        let cart = mem::replace(&mut mmu.cartridge, load_empty_cartridge());
+        let cheats = mem::take(&mut mmu.cheats);
+        let profiler = mmu.profiler.take();
+        let boot_rom = mmu.boot_rom.take().map(|mut b| {
+            b.remap();
+            b
+        });
+        self.cpu = Self::build_with(cart, &self.config, boot_rom);
+        self.get_mmu_mut().cheats = cheats;
+        self.get_mmu_mut().profiler = profiler;
+        self.get_mmu_mut().write_log = self.hooks.new_write_log();
+        self.pacer.reset();
+    }
+
+    /// Stop running the frames in the window, it still shows the last frame and takes the
+    /// hotkeys. `step_frame` is not affected.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.frame_steps = 0;
+        // Don't run fast to catch up the paused time.
+        self.pacer.reset();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Run exactly one more frame in the window or `serve_commands` and stay paused, the running
+    /// game is paused instead.
+    pub fn frame_step(&mut self) {
+        if self.paused {
+            self.frame_steps += 1;
+        } else {
+            self.pause();
+        }
+    }
+
+    // Returns true if a frame is due while paused.
+    fn take_frame_step(&mut self) -> bool {
+        let due = self.frame_steps > 0;
+        self.frame_steps = self.frame_steps.saturating_sub(1);
+        due
+    }
+
+    /// Hot-swap the cartridge, the machine is rebuilt and the game starts from the beginning.
+    /// The old cartridge is dropped here, so its save file is written back. The running game is
+    /// kept if the rom can't be loaded.
+    pub fn load_rom(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let (cart, mut config, game) = Self::open_rom(path.as_ref(), &self.defaults)?;
+        self.cpu = Self::build(cart, &config)?;
+        self.get_mmu_mut().write_log = self.hooks.new_write_log();
+        self.browser = None;
+        let path = path.as_ref().to_string_lossy().to_string();
+        self.defaults.set_file_path(path.clone());
+        config.set_file_path(path);
+        self.config = config;
+        self.game = game;
+        Ok(())
+    }
+
+    /// Returns the cartridge of the rom and the config with the settings of the game over it.
+    /// With the data directory, the save files are kept there and named by the game.
+    fn open_rom(path: &Path, defaults: &Config) -> Result<OpenedRom, Error> {
+        let storage = match defaults.get_data_dir() {
+            Some(dir) => Storage::new(dir),
+            None => {
+                let check = defaults.get_header_check();
+                let cart = load_cartridge_from_file(path, check, defaults.get_mbc1_wiring())?;
+                return Ok((cart, defaults.clone(), None));
+            }
+        };
+        let rom = read_rom(path)?;
+        let game = Storage::get_game_key(&rom)?;
+        let settings = storage.get_settings_path(&game);
+        let config = if settings.exists() {
+            info!("Apply the settings of the game {:?}", settings);
+            let builder = defaults.clone().into_builder();
+            builder
+                .file(&settings)
+                .and_then(|b| b.build())
+                .map_err(Error::Config)?
+        } else {
+            defaults.clone()
+        };
+        storage.create()?;
+        let cart = load_cartridge_from_rom(
+            rom,
+            &storage.get_save_path(&game),
+            config.get_header_check(),
+            config.get_mbc1_wiring(),
+        )?;
+        Ok((cart, config, Some(game)))
+    }
+
+    /// Returns the data directory and the name of the game in it, if the config has one.
+    #[cfg(feature = "native")]
+    fn get_storage(&self) -> Option<(Storage, &str)> {
+        let dir = self.config.get_data_dir()?;
+        Some((Storage::new(dir), self.game.as_deref()?))
+    }
+
+    /// Hot-swap the cartridge with the rom in memory, like `load_rom` but without the save files.
+    pub fn load_rom_bytes(&mut self, rom: Vec<u8>) -> Result<(), Error> {
+        let cart = load_cartridge_from_bytes(
+            rom,
+            self.config.get_header_check(),
+            self.config.get_mbc1_wiring(),
+        )?;
+        self.cpu = Self::build(cart, &self.config)?;
+        self.get_mmu_mut().write_log = self.hooks.new_write_log();
+        self.browser = None;
+        self.game = None;
+        Ok(())
+    }
+
+    /// Add the Game Genie or GameShark code, it's applied from now on.
+    pub fn add_cheat(&mut self, code: &str, name: &str) -> Result<(), String> {
+        self.get_mmu_mut().cheats.add(code, name)
+    }
+
+    /// Returns false if the code is not added.
+    pub fn remove_cheat(&mut self, code: &str) -> bool {
+        self.get_mmu_mut().cheats.remove(code)
+    }
+
+    /// Switch the code on or off, returns whether it's on now.
+    pub fn toggle_cheat(&mut self, code: &str) -> Option<bool> {
+        self.get_mmu_mut().cheats.toggle(code)
+    }
+
+    /// Replace the audio output, the samples are passed to it at the end of every frame.
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio = Some(sink);
+    }
+
+    fn flush_audio(&mut self) {
+        let samples = self.get_mmu_mut().apu.drain_samples();
+        if let Some(audio) = self.audio.as_mut() {
+            audio.write(&samples);
+        }
+    }
+
+    /// Every finished frame is passed to the sink.
+    pub fn set_video_sink(&mut self, sink: Box<dyn VideoSink>) {
+        self.video = Some(sink);
+    }
+
+    /// The keys are polled from the source at the end of every frame, unless a movie is playing.
+    /// It must be `Send` to move the emulator to another thread, unlike the keyboard of the window.
+    pub fn set_input_source(&mut self, input: Box<dyn InputSource + Send>) {
+        self.input = Some(input);
+    }
+
+    /// The rumble motor starts and stops the sink, e.g. to forward it to the gamepad.
+    pub fn set_rumble_sink(&mut self, sink: Box<dyn RumbleSink>) {
+        self.rumble = Some(sink);
+        self.osd.set_indicator(None);
+    }
+
+    /// Call the hook after every frame, e.g. to read the timer of the game for an auto-splitter.
+    pub fn on_frame(&mut self, hook: impl FnMut(&mut ScriptApi) + Send + 'static) {
+        self.hooks.on_frame(Box::new(hook));
+    }
+
+    /// Call the hook before the instruction at PC is executed.
+    pub fn on_pc(&mut self, pc: u16, hook: impl FnMut(&mut ScriptApi) + Send + 'static) {
+        self.hooks.on_pc(pc, Box::new(hook));
+    }
+
+    /// Call the hook with the address and the value after the instruction writing to
+    /// `start..=end`.
+    pub fn on_write(
+        &mut self,
+        start: u16,
+        end: u16,
+        hook: impl FnMut(&mut ScriptApi, u16, u8) + Send + 'static,
+    ) {
+        self.hooks.on_write(start, end, Box::new(hook));
+        self.get_mmu_mut().write_log = self.hooks.new_write_log();
+    }
+
+    /// Returns true if the rumble motor ran in the last frame.
+    pub fn is_rumbling(&self) -> bool {
+        self.rumbling
+    }
+
+    fn update_rumble(&mut self) {
+        let rumbling = self.get_mmu_mut().cartridge.take_rumble();
+        if rumbling == self.rumbling {
+            return;
+        }
+        self.rumbling = rumbling;
+        match self.rumble.as_mut() {
+            Some(rumble) => rumble.set_rumble(rumbling),
+            None => self.osd.set_indicator(rumbling.then_some("RUMBLE")),
+        }
+    }
+
+    /// Read the controllers from the backend along with the keyboard of the window.
+    pub fn set_gamepad_backend(&mut self, backend: Box<dyn GamepadBackend>) {
+        self.gamepads = Some(Gamepads::new(backend, self.config.get_gamepad_deadzone()));
+    }
+
+    /// Sleep to keep the real hardware speed, it's enabled by default. Turn it off to run the
+    /// frames as fast as possible.
+    pub fn set_speed_simulation(&mut self, enabled: bool) {
+        self.config.set_speed_simulation(enabled);
+        self.defaults.set_speed_simulation(enabled);
+        self.pacer.set_mode(Self::get_pacer_mode(&self.config));
+    }
+
+    /// Pace the frames by the video, by the audio sink or not at all. The audio sync needs a
+    /// sink reporting its queue, e.g. `AudioQueue`, it falls back to the video without one.
+    pub fn set_sync_mode(&mut self, mode: SyncMode) {
+        self.config.set_sync_mode(mode);
+        self.defaults.set_sync_mode(mode);
+        self.pacer.set_mode(Self::get_pacer_mode(&self.config));
+    }
+
+    fn get_pacer_mode(config: &Config) -> SyncMode {
+        match config.get_sync_mode() {
+            // The window paces itself if it's synced.
+            SyncMode::Video if config.is_window_synced() => SyncMode::Uncapped,
+            mode => mode,
+        }
+    }
+
+    /// Run `Config::get_fast_forward_factor` times of the normal speed, it only matters while
+    /// the speed simulation is enabled.
+    pub fn set_fast_forward(&mut self, enabled: bool) {
+        let factor = if enabled {
+            self.config.get_fast_forward_factor()
+        } else {
+            1
+        };
+        self.pacer.set_speed_factor(factor);
+    }
+
+    pub fn is_fast_forward(&self) -> bool {
+        self.pacer.get_speed_factor() > 1
+    }
+
+    /// Run at the percent of the normal speed, 1 to 100, for watching a glitch or a fast
+    /// sequence. 100 runs at the normal speed again.
+    pub fn set_slow_motion(&mut self, percent: u32) {
+        self.pacer.set_slow_motion(percent);
+    }
+
+    pub fn get_slow_motion(&self) -> u32 {
+        self.pacer.get_slow_motion()
+    }
+
+    /// Log the instructions into the writer from now on, a line is like
+    /// `0150: LD A,0x12  A=01 F=b0 ...` with the registers before the instruction.
+    pub fn set_trace(&mut self, w: Box<dyn Write + Send>) {
+        self.trace = Some(w);
+    }
+
+    fn new_history(size: usize) -> Option<CpuHistory> {
+        if size > 0 {
+            Some(CpuHistory::new(size))
+        } else {
+            None
+        }
+    }
+
+    /// Keep the last executed instructions from now on, 0 turns the history off.
+    pub fn set_cpu_history_size(&mut self, size: usize) {
+        self.history = Self::new_history(size);
+    }
+
+    /// Returns the unknown opcode which locked up the CPU, only the reset runs the game again.
+    pub fn get_cpu_lock(&self) -> Option<CpuLock> {
+        self.cpu.get_lock()
+    }
+
+    /// Returns the last executed instructions, `None` if the history is off.
+    pub fn get_cpu_history(&self) -> Option<&CpuHistory> {
+        self.history.as_ref()
+    }
+
+    /// Enter the debugger before the first instruction, type `help` in it for the commands.
+    pub fn attach_debugger(&mut self) {
+        self.inspector.attach();
+    }
+
+    /// Record the keys of every frame from now on, the movie is saved when the window closes.
+    /// It's expected to start right after the power on, the ram seed is taken from the config.
+    pub fn record_movie(&mut self, path: impl AsRef<Path>) {
+        let movie = Movie::new(self.config.get_ram_seed());
+        self.movie = Some(MovieSession::Recording(movie, path.as_ref().to_path_buf()));
+    }
+
+    /// Replay the movie from the power on, the ram is initialized with the seed in the movie.
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.get_mmu_mut().init_ram(movie.get_ram_seed());
+        self.movie = Some(MovieSession::Playing(movie, 0));
+    }
+
+    /// Feed the keys of the next frame, they only change at the frame boundary, so the replay of
+    /// a movie is exact. The input is ignored while a movie is playing.
+    fn next_input_frame(&mut self, input: Option<&mut dyn InputSource>) {
+        let joypad = &mut mmu_of_mut(&mut self.cpu).joypad;
+        let playing = matches!(self.movie, Some(MovieSession::Playing(..)));
+        if !playing && (input.is_some() || self.commands.is_some()) {
+            let (mut keys, mut turbo) = (self.remote_keys, 0x00);
+            if let Some(input) = input {
+                for (i, key) in KEYS.iter().enumerate() {
+                    if input.is_held(key) {
+                        keys |= 1 << i;
+                    }
+                    if input.is_turbo_held(key) {
+                        turbo |= 1 << i;
+                    }
+                }
+            }
+            joypad.set_keys(self.autofire.apply(keys, turbo));
+        }
+        match self.movie.as_mut() {
+            Some(MovieSession::Recording(movie, _)) => {
+                movie.push(joypad.get_keys());
+            }
+            Some(MovieSession::Playing(movie, i)) => {
+                if let Some(keys) = movie.get_frame(*i) {
+                    joypad.set_keys(keys);
+                    *i += 1;
+                } else if *i == movie.len() {
+                    info!("The movie ends after {} frames", movie.len());
+                    joypad.set_keys(0x00);
+                    *i += 1;
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Switch the turbo keys and the autofire buttons on or off, returns whether they're on now.
+    /// The turbo keys press their button like the plain keys while it's off.
+    pub fn toggle_autofire(&mut self) -> bool {
+        self.autofire.set_enabled(!self.autofire.is_enabled());
+        self.autofire.is_enabled()
+    }
+
+    /// Make the button autofire while it's held, or stop it.
+    pub fn set_autofire(&mut self, button: &JoypadKey, enabled: bool) {
+        self.autofire.set_button(button, enabled);
+    }
+
+    /// The presses per second of the turbo keys and the autofire buttons.
+    pub fn set_autofire_rate(&mut self, rate: u32) {
+        self.autofire.set_rate(rate);
+    }
+
+    /// Save the recording movie.
+    fn finish_movie(&mut self) {
+        if let Some(MovieSession::Recording(movie, path)) = self.movie.take() {
+            match movie.save(&path) {
+                Ok(()) => info!("{} frames are recorded to {:?}", movie.len(), path),
+                Err(e) => error!("Can't save the movie {:?}: {}", path, e),
+            }
+        }
+    }
+
+    /// Run until the GPU finishes a frame, or a frame of cycles passes while the LCD is off.
+    /// The frame is passed to the video sink and the keys of the next frame are polled.
+    fn run_frame(&mut self, input: Option<&mut dyn InputSource>) {
+        let mut cycles = 0;
+        loop {
+            cycles += self.next();
+            let gpu = self.get_mmu().gpu.borrow();
+            if gpu.should_updated() || (!gpu.is_lcd_enabled() && cycles >= FRAME_CYCLES) {
+                break;
+            }
+        }
+        let gpu = self.get_mmu_mut().gpu.get_mut();
+        gpu.reset_updated();
+        let frame = gpu.get_frame();
+        self.frame.clone_from(&frame);
+        self.hooks.run_frame(&mut self.cpu);
+        if let Some(now) = clock::now() {
+            self.get_mmu_mut().cartridge.poll_battery(now);
+            self.record_stats(u64::from(cycles), now);
+        }
+        self.update_rumble();
+        if let Some(video) = self.video.as_mut() {
+            video.present(&self.frame);
+        }
+        if let Some(dump) = self.frame_dump.as_mut() {
+            dump.present(&self.frame);
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.present(&self.frame);
+        }
+        self.flush_audio();
+        self.next_input_frame(input);
+        // The cycles are in the normal speed, so the double speed takes the same time.
+        let sample_rate = self.get_mmu().apu.get_sample_rate();
+        let queued = self
+            .audio
+            .as_ref()
+            .and_then(|a| a.get_queued())
+            .map(|n| Duration::from_secs_f64(n as f64 / f64::from(sample_rate)));
+        self.pacer.wait(cycles, queued);
+    }
+
+    fn record_stats(&mut self, cycles: u64, now: Instant) {
+        let slept = self.pacer.get_sleep_time();
+        let late_frames = self.pacer.get_late_frames();
+        let stats = self.stats.as_mut();
+        if let Some(stats) = stats.and_then(|s| s.frame(cycles, slept, late_frames, now)) {
+            if self.config.is_stats_logged() {
+                info!("{}", stats);
+            }
+            if self.config.is_stats_shown() {
+                self.osd.set_status(stats.get_lines());
+            }
+        }
+    }
+
+    /// Returns the frames, the cycles and the host time of the last second, `None` in the
+    /// first second.
+    pub fn get_stats(&self) -> Option<FrameStats> {
+        self.stats.as_ref().and_then(|s| s.get_last())
+    }
+
+    /// Returns the last finished frame.
+    pub fn get_frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Returns the last finished frame in RGBA, row by row.
+    pub fn frame(&self) -> &[u8] {
+        self.frame.as_rgba()
+    }
+
+    /// Save the last finished frame as a PNG image.
+    pub fn screenshot(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        save_png(path, &self.frame)
+    }
+
+    /// Start recording the frames into the video file, the running recording is finished first.
+    pub fn start_recording(
+        &mut self,
+        path: impl AsRef<Path>,
+        format: VideoFormat,
+    ) -> io::Result<()> {
+        self.stop_recording()?;
+        self.recorder = Some(Recorder::create(path, format)?);
+        Ok(())
+    }
+
+    /// Finish the video file, it does nothing if no recording is running.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// The file of the screenshot and the video hotkeys, it's beside the rom or in the data
+    /// directory, and named by the time.
+    #[cfg(feature = "native")]
+    fn get_capture_path(&self, extension: &str) -> PathBuf {
+        let name = format!("{}.{}", Local::now().format("%Y%m%d-%H%M%S"), extension);
+        if let Some((storage, game)) = self.get_storage() {
+            return storage.get_capture_path(game, &name);
+        }
+        match self.config.get_file_path() {
+            Some(rom) => {
+                let rom = Path::new(rom);
+                let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+                rom.with_file_name(format!("{}-{}", stem, name))
+            }
+            None => PathBuf::from(name),
+        }
+    }
+
+    /// Show the message over the frame of the window until the timeout of the config.
+    pub fn show_message(&mut self, text: &str) {
+        if let Some(now) = clock::now() {
+            self.osd.show(text, now);
+        }
+    }
+
+    /// Save the screenshot of the F12 hotkey.
+    #[cfg(feature = "native")]
+    fn quick_screenshot(&mut self) {
+        let path = self.get_capture_path("png");
+        match self.screenshot(&path) {
+            Ok(()) => {
+                info!("The screenshot is saved to {:?}", path);
+                self.show_message("Screenshot saved");
+            }
+            Err(e) => {
+                error!("Can't save the screenshot to {:?}: {}", path, e);
+                self.show_message("Can't save the screenshot");
+            }
         }
     }
 
+    /// Start or stop the recording of the F11 hotkey.
+    #[cfg(feature = "native")]
+    fn toggle_recording(&mut self) {
+        if self.is_recording() {
+            match self.stop_recording() {
+                Ok(()) => {
+                    info!("The recording is finished");
+                    self.show_message("Recording finished");
+                }
+                Err(e) => {
+                    error!("Can't finish the recording: {}", e);
+                    self.show_message("Can't finish the recording");
+                }
+            }
+            return;
+        }
+        let format = self.config.get_video_format();
+        let path = self.get_capture_path(format.get_extension());
+        match self.start_recording(&path, format) {
+            Ok(()) => {
+                info!("Recording to {:?}", path);
+                self.show_message("Recording");
+            }
+            Err(e) => {
+                error!("Can't record to {:?}: {}", path, e);
+                self.show_message("Can't record");
+            }
+        }
+    }
+
+    /// Returns the sender of the commands, the sender of an earlier call stops working. The
+    /// emulator must be moved to the thread running it, see `serve_commands`.
+    pub fn command_sender(&mut self) -> Sender<Command> {
+        let (sender, receiver) = mpsc::channel();
+        self.commands = Some(receiver);
+        sender
+    }
+
+    fn run_command(&mut self, command: Command) {
+        match command {
+            Command::Press(key) => self.remote_keys |= key_bit(&key),
+            Command::Release(key) => self.remote_keys &= !key_bit(&key),
+            Command::Pause => self.pause(),
+            Command::Resume => self.resume(),
+            Command::FrameStep => self.frame_step(),
+            Command::SlowMotion(percent) => self.set_slow_motion(percent),
+            Command::SaveState(path) => match self.save_state(&path) {
+                Ok(()) => {
+                    info!("The state is saved to {:?}", path);
+                    self.show_message("State saved");
+                }
+                Err(e) => {
+                    error!("Can't save the state to {:?}: {}", path, e);
+                    self.show_message("Can't save the state");
+                }
+            },
+            Command::LoadState(path) => match self.load_state(&path) {
+                Ok(()) => {
+                    info!("The state is loaded from {:?}", path);
+                    self.show_message("State loaded");
+                }
+                Err(e) => {
+                    error!("Can't load the state from {:?}: {}", path, e);
+                    self.show_message("Can't load the state");
+                }
+            },
+        }
+    }
+
+    /// Run the pending commands, returns false once every sender is dropped.
+    fn run_commands(&mut self) -> bool {
+        loop {
+            let command = match self.commands.as_ref().map(|c| c.try_recv()) {
+                Some(Ok(command)) => command,
+                Some(Err(TryRecvError::Disconnected)) => return false,
+                Some(Err(TryRecvError::Empty)) | None => return true,
+            };
+            self.run_command(command);
+        }
+    }
+
+    /// Run the frames on this thread until every sender of `command_sender` is dropped, the
+    /// paused emulator waits for the next command. The movie and the recording are saved at the
+    /// end like `run_headless`.
+    pub fn serve_commands(&mut self) {
+        self.run_guarded(|emulator| {
+            loop {
+                let open = emulator.run_commands();
+                // The frame steps sent before the senders are dropped still run.
+                if emulator.paused && emulator.take_frame_step() {
+                    emulator.step_frame();
+                } else if !open {
+                    break;
+                } else if !emulator.paused {
+                    emulator.step_frame();
+                } else {
+                    match emulator.commands.as_ref().map(|c| c.recv()) {
+                        Some(Ok(command)) => emulator.run_command(command),
+                        _ => break,
+                    }
+                }
+            }
+        });
+        self.finish_session();
+    }
+
+    /// Run a frame with the plugged video sink and input source, it doesn't need any window.
+    /// The pending commands are run first, the pause doesn't stop it.
+    pub fn step_frame(&mut self) -> &Frame {
+        self.run_commands();
+        let mut input = self.input.take();
+        self.run_frame(input.as_mut().map(|i| i.as_mut() as &mut dyn InputSource));
+        self.input = input;
+        &self.frame
+    }
+
+    /// Run the frames without the window, then save the movie like the window is closed.
+    pub fn run_headless(&mut self, frames: u32) {
+        self.run_guarded(|emulator| {
+            for _ in 0..frames {
+                emulator.step_frame();
+            }
+        });
+        self.finish_session();
+    }
+
+    fn finish_session(&mut self) {
+        self.finish_movie();
+        if let Err(e) = self.stop_recording() {
+            error!("Can't finish the recording: {}", e);
+        }
+        self.report_stub_io();
+        self.save_memory_profile();
+        self.report_cpu_profile();
+    }
+
+    /// Take a snapshot of the running machine.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        let mmu = self.get_mmu();
+        snapshot.put(CPU_SECTION, &self.cpu);
+        snapshot.put(MMU_SECTION, mmu);
+        snapshot.put(GPU_SECTION, &*mmu.gpu.borrow());
+        snapshot.put(TIMER_SECTION, &mmu.timer);
+        snapshot.put(JOYPAD_SECTION, &mmu.joypad);
+        snapshot.put(CARTRIDGE_SECTION, &*mmu.cartridge);
+        snapshot.put(APU_SECTION, &mmu.apu);
+        snapshot.put(SERIAL_SECTION, &mmu.serial);
+        if let Some(boot_rom) = &mmu.boot_rom {
+            snapshot.put(BOOT_ROM_SECTION, boot_rom);
+        }
+        snapshot
+    }
+
+    /// Restore the machine from a snapshot, it must be migrated to the current version.
+    /// The cartridge is checked first, a snapshot of another game changes nothing.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), StateError> {
+        snapshot.restore_optional(CARTRIDGE_SECTION, &mut *self.get_mmu_mut().cartridge)?;
+        snapshot.restore(CPU_SECTION, &mut self.cpu)?;
+        let mmu = mmu_of_mut(&mut self.cpu);
+        snapshot.restore(MMU_SECTION, mmu)?;
+        snapshot.restore_optional(GPU_SECTION, mmu.gpu.get_mut())?;
+        snapshot.restore_optional(TIMER_SECTION, &mut mmu.timer)?;
+        snapshot.restore_optional(JOYPAD_SECTION, &mut mmu.joypad)?;
+        snapshot.restore_optional(APU_SECTION, &mut mmu.apu)?;
+        snapshot.restore_optional(SERIAL_SECTION, &mut mmu.serial)?;
+        // The snapshot taken without the boot rom is past the boot.
+        if let Some(boot_rom) = mmu.boot_rom.as_mut() {
+            if !snapshot.restore_optional(BOOT_ROM_SECTION, boot_rom)? {
+                boot_rom.unmap();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save_state(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        self.snapshot().save(path)
+    }
+
+    /// Load a snapshot file, the snapshot written by an older release is upgraded first.
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        let snapshot = Snapshot::load(path)?;
+        self.restore(&snapshot)
+    }
+
+    /// The snapshot file of the F5/F7 hotkeys, it's beside the rom or in the data directory.
+    #[cfg(feature = "native")]
+    fn get_state_path(&self) -> Option<PathBuf> {
+        if let Some((storage, game)) = self.get_storage() {
+            return Some(storage.get_state_path(game));
+        }
+        self.config
+            .get_file_path()
+            .map(|path| Path::new(path).with_extension("ngcs"))
+    }
+
+    #[cfg(feature = "native")]
+    fn quick_save(&mut self) {
+        if let Some(path) = self.get_state_path() {
+            self.run_command(Command::SaveState(path));
+        }
+    }
+
+    #[cfg(feature = "native")]
+    fn quick_load(&mut self) {
+        if let Some(path) = self.get_state_path() {
+            self.run_command(Command::LoadState(path));
+        }
+    }
+
+    /// Returns the header and the live mapper state of the inserted cartridge.
+    pub fn cartridge_info(&self) -> CartridgeInfo {
+        self.get_mmu().cartridge.get_info()
+    }
+
+    /// Returns the statistics of the battery backed ram, `None` if the cartridge has no battery.
+    pub fn get_battery_stats(&self) -> Option<BatteryStats> {
+        self.get_mmu().cartridge.get_battery_stats()
+    }
+
+    /// Run the frames as fast as possible, without the window, the input and the speed
+    /// simulation. A frame is `FRAME_CYCLES` cycles, so the figure doesn't depend on the LCD.
+    pub fn bench(&mut self, frames: u32) -> BenchReport {
+        let target = u64::from(frames) * u64::from(FRAME_CYCLES);
+        let instructions = self.cpu.get_instruction_count();
+        let mut cycles = 0;
+
+        let start = Instant::now();
+        while cycles < target {
+            cycles += u64::from(self.step());
+        }
+        BenchReport {
+            frames,
+            cycles,
+            instructions: self.cpu.get_instruction_count() - instructions,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Run the cpu for the cycles at least, without the frontend, the frames are not presented.
+    /// Returns the cycles run in the normal speed.
+    pub fn run_cycles(&mut self, cycles: u64) -> u64 {
+        let mut run = 0;
+        while run < cycles {
+            run += u64::from(self.next());
+        }
+        run
+    }
+
+    /// Returns the bytes sent through the serial port since the power on.
+    pub fn get_serial_output(&self) -> Vec<u8> {
+        self.get_mmu().serial.get_output().to_vec()
+    }
+
     fn next(&mut self) -> u32 {
-        if self.inspector.should_enter_trap() {
+        let watchpoints = &self.get_mmu().watchpoints;
+        if self.inspector.should_enter_trap(&self.cpu.reg, watchpoints) {
             // println!("{:?}", self.cpu.reg);
-            self.inspector.break_here(&self.cpu, self.gpu.clone());
+            let history = self.history.as_ref();
+            self.inspector.break_here(&mut self.cpu, history);
+        }
+        if !self.hooks.is_empty() && !self.cpu.is_halt() && !self.cpu.is_stop() {
+            self.hooks.run_pc(&mut self.cpu);
+        }
+        let pc = self.cpu.reg.get_PC();
+        self.get_mmu().diagnostics.borrow_mut().set_pc(pc);
+        if let Some(w) = self.trace.as_mut() {
+            if !self.cpu.is_halt() && !self.cpu.is_stop() {
+                let mmu = mmu_of(&self.cpu);
+                mmu.pause_profiler(true);
+                let line = trace_line(mmu, &self.cpu.reg);
+                mmu.pause_profiler(false);
+                // The trace reads the instruction, it's not a hit of the watchpoints.
+                mmu.watchpoints.take_hit();
+                if let Err(e) = writeln!(w, "{}", line) {
+                    error!("Can't write the trace: {}", e);
+                    self.trace = None;
+                }
+            }
+        }
+        if self.history.is_none() {
+            return self.step();
         }
+
+        let record = self.get_cpu_record();
+        let instructions = self.cpu.get_instruction_count();
+        let cycles = self.step();
+        let history = self.history.as_mut().unwrap();
+        // The halted steps and the interrupt dispatch don't execute the instruction.
+        if self.cpu.get_instruction_count() == instructions {
+            history.skip(cycles);
+        } else {
+            history.push(CpuRecord { cycles, ..record });
+        }
+        cycles
+    }
+
+    /// Returns the record of the instruction at PC before it runs, its cycles are not known yet.
+    fn get_cpu_record(&self) -> CpuRecord {
+        let mmu = mmu_of(&self.cpu);
+        let pc = self.cpu.reg.get_PC();
+        mmu.pause_profiler(true);
+        let opcode = mmu.read_byte(pc);
+        let cb_opcode = if opcode == 0xcb {
+            Some(mmu.read_byte(pc.wrapping_add(1)))
+        } else {
+            None
+        };
+        mmu.pause_profiler(false);
+        // The history reads the instruction, it's not a hit of the watchpoints.
+        mmu.watchpoints.take_hit();
+        let mapper = mmu.cartridge.get_mapper_state();
+        CpuRecord {
+            cycle: 0,
+            cycles: 0,
+            reg: self.cpu.get_reg_snapshot(),
+            opcode,
+            cb_opcode,
+            rom_bank: mapper.rom_bank,
+            ram_bank: mapper.ram_bank,
+            wram_bank: mmu.get_wram_bank(),
+        }
+    }
+
+    /// Execute an instruction and step the other components with it, returns the cycles in the
+    /// normal speed.
+    fn step(&mut self) -> u32 {
+        // Peeking at the opcode is not an access of the game.
+        self.get_mmu().pause_profiler(true);
+        let opcode = self.cpu.get_current_opcode();
+        self.get_mmu().pause_profiler(false);
+        let before = self.cpu_profile.as_ref().map(|_| {
+            let pc = self.get_code_address(self.cpu.reg.get_PC());
+            (pc, self.cpu.reg.get_SP(), self.cpu.get_instruction_count())
+        });
         let cycles = self.cpu.next();
-        self.mmu.borrow_mut().next(cycles);
+        let cycles = self.get_mmu_mut().next(cycles);
+        if let Some((pc, sp, instructions)) = before {
+            // The call pushed the return address, an interrupt is dispatched without any
+            // instruction.
+            let is_rst = opcode & 0xc7 == 0xc7;
+            let is_call = is_rst || matches!(opcode, 0xc4 | 0xcc | 0xcd | 0xd4 | 0xdc);
+            let dispatched = self.cpu.get_instruction_count() == instructions;
+            let called = (is_call || dispatched) && self.cpu.reg.get_SP() == sp.wrapping_sub(2);
+            let entry = self.get_code_address(self.cpu.reg.get_PC());
+            let profile = self.cpu_profile.as_mut().unwrap();
+            profile.record(pc, sp, cycles);
+            if called {
+                profile.call(entry, sp.wrapping_sub(2));
+            }
+        }
+        if !self.hooks.is_empty() {
+            self.hooks.run_write(&mut self.cpu);
+        }
         cycles
     }
 
+    /// Returns the bank and the address of the code for the profiler.
+    fn get_code_address(&self, a: u16) -> CodeAddress {
+        match a {
+            0x4000..=0x7fff => (self.get_mmu().cartridge.get_mapper_state().rom_bank, a),
+            _ => (0, a),
+        }
+    }
+
+    /// Returns the cycles counted by the profiler, if it's on.
+    pub fn get_cpu_profile(&self) -> Option<&CycleProfiler> {
+        self.cpu_profile.as_ref()
+    }
+
+    /// Log the functions and the instructions taking the most cycles.
+    fn report_cpu_profile(&self) {
+        if let Some(profile) = self.cpu_profile.as_ref() {
+            info!("The profile of {} cycles:", profile.get_total());
+            for line in profile.report(20) {
+                info!("  {}", line);
+            }
+        }
+    }
+
+    /// Returns the counts of the memory accesses, if the memory is profiled.
+    pub fn get_memory_profile(&self) -> Option<MemoryProfiler> {
+        self.get_mmu().profiler.as_ref().map(|p| p.borrow().clone())
+    }
+
+    /// Save the memory profile into the CSV and the heatmap of the config.
+    fn save_memory_profile(&self) {
+        let profiler = match self.get_memory_profile() {
+            Some(profiler) => profiler,
+            None => return,
+        };
+        if let Some(path) = self.config.get_memory_profile() {
+            match fs::write(path, profiler.to_csv()) {
+                Ok(()) => info!("The memory profile is saved into {}", path),
+                Err(e) => error!("Can't save the memory profile into {}: {}", path, e),
+            }
+        }
+        if let Some(path) = self.config.get_memory_heatmap() {
+            match save_png(path, &profiler.render_heatmap()) {
+                Ok(()) => info!("The memory heatmap is saved into {}", path),
+                Err(e) => error!("Can't save the memory heatmap into {}: {}", path, e),
+            }
+        }
+    }
+
+    /// Log the accesses to the IO registers which are not emulated yet.
+    fn report_stub_io(&self) {
+        let mmu = self.get_mmu();
+        let diagnostics = mmu.diagnostics.borrow();
+        if diagnostics.is_empty() {
+            return;
+        }
+        info!("The game accessed these unimplemented IO registers:");
+        for line in diagnostics.summary() {
+            info!("  {}", line);
+        }
+    }
+
     // fn _run(mut self) -> ! {
     //     let event_loop = EventLoop::new();
     //     let mut input = WinitInputHelper::new();
@@ -226,52 +1436,177 @@ impl Emulator {
     //     });
     // }
 
-    fn _run(&mut self) {
-        let c_scale = 2;
-        let mut option = minifb::WindowOptions::default();
-        option.resize = true;
-        option.scale = match c_scale {
-            1 => minifb::Scale::X1,
-            2 => minifb::Scale::X2,
-            4 => minifb::Scale::X4,
-            8 => minifb::Scale::X8,
-            _ => panic!("Supported scale: 1, 2, 4 or 8"),
-        };
-        let mut window = minifb::Window::new(
-            format!("Gameboy - {}", "RED").as_str(),
-            SCREEN_W,
-            SCREEN_H,
-            option,
+    /// Open the game window with the scale of the config.
+    #[cfg(feature = "native")]
+    fn open_window(&self, frontend: &mut dyn Frontend) -> Result<Box<dyn Window>, Error> {
+        frontend.open_window(
+            &self.get_window_title(),
+            self.config.get_scale(),
+            self.config.is_fullscreen(),
         )
-        .unwrap();
-        let mut window_buffer = vec![0x00; SCREEN_W * SCREEN_H];
-        window
-            .update_with_buffer(window_buffer.as_slice(), SCREEN_W, SCREEN_H)
-            .unwrap();
+    }
 
+    /// Play the sound with the device of the frontend, unless it goes to the dump or the sink
+    /// plugged already.
+    #[cfg(feature = "native")]
+    fn open_audio(&mut self, frontend: &mut dyn Frontend) -> Option<Box<dyn Audio>> {
+        if self.audio.is_some() {
+            return None;
+        }
+        let sample_rate = self.get_mmu().apu.get_sample_rate();
+        // A quarter of a second, the oldest samples are dropped beyond it while fast forwarding.
+        let queue = AudioQueue::new(sample_rate as usize / 4);
+        match frontend.open_audio(queue.clone(), sample_rate) {
+            Ok(Some(device)) => {
+                self.set_audio_sink(Box::new(queue));
+                Some(device)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                error!("Can't open the sound device: {}", e);
+                None
+            }
+        }
+    }
+
+    #[cfg(feature = "native")]
+    fn _run(&mut self) -> Result<(), Error> {
+        let mut frontend = open_frontend(self.config.get_frontend())?;
+        let mut window = self.open_window(&mut *frontend)?;
+        let mut audio = self.open_audio(&mut *frontend);
+        let mut audio_paused = false;
+        let mut post = PostProcess::new(
+            self.config.get_filter(),
+            self.config.is_green_tint_enabled(),
+        );
+        post.set_persistence(self.config.get_frame_blend());
+        window.present(&vec![0x00; SCREEN_W * SCREEN_H], SCREEN_W, SCREEN_H);
+
+        let mut viewer = if self.config.is_vram_viewer_enabled() {
+            match frontend.open_viewer("VRAM", VIEWER_W, VIEWER_H) {
+                Ok(viewer) => Some(viewer),
+                Err(e) => {
+                    error!("Can't open the VRAM viewer: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut perf = if self.config.is_perf_overlay_enabled() {
+            Some(PerfOverlay::new())
+        } else {
+            None
+        };
+        let mut title_stats = None;
+        // The game is paused by a panic, the reset resumes it.
+        let mut crashed = false;
+        let mut locked = false;
+
+        let keymap = self.config.get_keymap().clone();
+        let mut gamepads = self.gamepads.take();
+        let mut skipped_frames = 0;
+        let window_sync =
+            self.config.is_window_synced() && self.config.get_sync_mode() == SyncMode::Video;
+        if window_sync {
+            window.limit_update_rate(Some(self.pacer.get_frame_time(FRAME_CYCLES)));
+        }
         loop {
             if !window.is_open() {
                 break;
             }
+            if audio_paused != self.paused {
+                audio_paused = self.paused;
+                if let Some(audio) = audio.as_mut() {
+                    audio.set_paused(audio_paused);
+                }
+            }
 
-            self.next();
-
-            if self.mmu.borrow().gpu.borrow().should_updated() {
-                self.mmu.borrow_mut().gpu.borrow_mut().reset_updated();
-                // println!("{:?}", self.mmu.borrow().gpu.data);
-                let mut i: usize = 0;
-
-                for l in self.mmu.borrow().gpu.borrow().get_data().iter() {
-                    for w in l.iter() {
-                        let b = u32::from(w[0]) << 16;
-                        let g = u32::from(w[1]) << 8;
-                        let r = u32::from(w[2]);
-                        let a = 0xff00_0000;
+            if self.browser.is_some() {
+                self.next_browser_frame(&mut *window, &mut post);
+                continue;
+            }
 
-                        window_buffer[i] = a | b | g | r;
+            // Hold Tab to fast forward.
+            let fast_forward = window.is_key_down(Key::Tab);
+            if fast_forward != self.is_fast_forward() {
+                self.set_fast_forward(fast_forward);
+                if window_sync {
+                    window.limit_update_rate(Some(self.pacer.get_frame_time(FRAME_CYCLES)));
+                }
+                self.show_message(if fast_forward {
+                    "Fast-forward ON"
+                } else {
+                    "Fast-forward OFF"
+                });
+            }
+            let skip = !self.paused
+                && self.is_fast_forward()
+                && self.config.is_frame_skip_enabled()
+                && skipped_frames + 1 < self.config.get_fast_forward_factor();
+            self.get_mmu().gpu.borrow_mut().set_skip_rendering(skip);
 
-                        i += 1;
+            self.run_commands();
+            // Press Backslash to pause, then again to run a frame, it repeats while held.
+            if !crashed && window.is_key_pressed(Key::Backslash, true) {
+                self.frame_step();
+                if self.frame_steps == 0 {
+                    self.show_message("Paused");
+                }
+            }
+            let mut keyboard = keymap.with_window(&*window);
+            if self.paused && !self.take_frame_step() {
+                thread::sleep(Duration::from_millis(u64::from(STEP_TIME)));
+            } else {
+                let frame = panic::AssertUnwindSafe(|| match gamepads.as_mut() {
+                    Some(pads) => self.run_frame(Some(&mut (keyboard, pads))),
+                    None => self.run_frame(Some(&mut keyboard)),
+                });
+                let result = panic::catch_unwind(frame);
+                if let Err(e) = result {
+                    if !self.config.is_panic_recovery_enabled() {
+                        panic::resume_unwind(e);
                     }
+                    self.recover(e.as_ref());
+                    crashed = true;
+                }
+            }
+            if locked != self.cpu.get_lock().is_some() {
+                locked = !locked;
+                if locked {
+                    self.show_message("CPU locked, F2 resets");
+                }
+            }
+
+            if skip {
+                skipped_frames += 1;
+                // Still poll the keyboard events.
+                window.update();
+            } else {
+                skipped_frames = 0;
+                let mut frame = self.frame.clone();
+                let now = Instant::now();
+                if let Some(perf) = perf.as_mut() {
+                    perf.tick(now);
+                    perf.draw(&mut frame);
+                }
+                if self.config.is_io_shown() {
+                    self.osd.set_panel(dump_io_registers(self.get_mmu()));
+                }
+                self.osd.tick(now);
+                self.osd.draw(&mut frame, now);
+                Self::present_window(&mut *window, &mut post, &frame);
+                if self.get_stats() != title_stats {
+                    title_stats = self.get_stats();
+                    window.set_title(&self.get_window_title());
+                }
+                // The viewer is closed by itself, the game goes on.
+                if viewer.as_ref().is_some_and(|v| !v.is_open()) {
+                    viewer = None;
+                }
+                if let Some(viewer) = viewer.as_mut() {
+                    self.present_vram(&mut **viewer);
                 }
 
                 // let start = SystemTime::now();
@@ -279,39 +1614,183 @@ impl Emulator {
                 //     .duration_since(UNIX_EPOCH)
                 //     .expect("Time went backwards");
                 // println!("{:?}", since_the_epoch);
-                window
-                    .update_with_buffer(window_buffer.as_slice(), SCREEN_W, SCREEN_H)
-                    .unwrap();
             }
 
-            if !self.cpu.flip() {
-                continue;
+            if window.is_key_pressed(Key::F1, false) {
+                if self.paused {
+                    crashed = false;
+                    self.resume();
+                    self.show_message("Resumed");
+                } else {
+                    self.pause();
+                    self.show_message("Paused");
+                }
             }
-
-            let keys = vec![
-                (minifb::Key::D, JoypadKey::Right),
-                (minifb::Key::W, JoypadKey::Up),
-                (minifb::Key::A, JoypadKey::Left),
-                (minifb::Key::S, JoypadKey::Down),
-                (minifb::Key::J, JoypadKey::A),
-                (minifb::Key::K, JoypadKey::B),
-                (minifb::Key::N, JoypadKey::Select),
-                (minifb::Key::M, JoypadKey::Start),
-            ];
-            for (rk, vk) in &keys {
-                if window.is_key_down(*rk) {
-                    self.mmu.borrow_mut().joypad.keydown(vk.clone());
-                    // It's so important
-                    break;
+            if window.is_key_pressed(Key::F2, false) {
+                self.reset();
+                if crashed {
+                    crashed = false;
+                    self.resume();
+                }
+                info!("Reset");
+                self.show_message("Reset");
+            }
+            if window.is_key_pressed(Key::F5, false) {
+                self.quick_save();
+            }
+            if window.is_key_pressed(Key::F7, false) {
+                self.quick_load();
+            }
+            if window.is_key_pressed(Key::F12, false) {
+                self.quick_screenshot();
+            }
+            if window.is_key_pressed(Key::F11, false) {
+                self.toggle_recording();
+            }
+            if window.is_key_pressed(Key::F6, false) {
+                let mut gpu = self.get_mmu().gpu.borrow_mut();
+                let palette = self
+                    .config
+                    .get_palettes()
+                    .next(gpu.get_dmg_palette().get_name());
+                info!("The palette is {}", palette.get_name());
+                let text = format!("Palette {}", palette.get_name());
+                gpu.set_dmg_palette(palette);
+                drop(gpu);
+                self.show_message(&text);
+            }
+            if window.is_key_pressed(Key::F8, false) {
+                let on = self.toggle_autofire();
+                info!("The autofire is {}", if on { "on" } else { "off" });
+                self.show_message(if on { "Autofire ON" } else { "Autofire OFF" });
+            }
+            let scale = window.is_key_pressed(Key::F3, false);
+            let fullscreen = window.is_key_pressed(Key::F4, false);
+            if scale || fullscreen {
+                if scale {
+                    // 1x, 2x, 4x, 8x and 1x again.
+                    let next = match self.config.get_scale() {
+                        8 => 1,
+                        n => n * 2,
+                    };
+                    // The next scale is always one of the supported.
+                    let _ = self.config.set_scale(next);
+                    let _ = self.defaults.set_scale(next);
+                    self.show_message(&format!("Scale {}x", self.config.get_scale()));
                 } else {
-                    self.mmu.borrow_mut().joypad.keyup(vk.clone());
+                    let fullscreen = !self.config.is_fullscreen();
+                    self.config.set_fullscreen(fullscreen);
+                    self.defaults.set_fullscreen(fullscreen);
+                    self.show_message(if self.config.is_fullscreen() {
+                        "Fullscreen ON"
+                    } else {
+                        "Fullscreen OFF"
+                    });
+                }
+                // The scale of a minifb window is fixed, the window is opened again.
+                window = match self.open_window(&mut *frontend) {
+                    Ok(window) => window,
+                    Err(e) => {
+                        self.gamepads = gamepads;
+                        return Err(e);
+                    }
+                };
+                if window_sync {
+                    window.limit_update_rate(Some(self.pacer.get_frame_time(FRAME_CYCLES)));
+                }
+            }
+            if window.is_key_pressed(Key::F9, false) {
+                post.set_filter(post.get_filter().next());
+                info!("The filter is {:?}", post.get_filter());
+                self.show_message(&format!("Filter {:?}", post.get_filter()));
+            }
+            if window.is_key_pressed(Key::Slash, false) {
+                // 100%, 50%, 25% and 100% again.
+                let next = match self.get_slow_motion() {
+                    100 => 50,
+                    50 => 25,
+                    _ => 100,
+                };
+                self.set_slow_motion(next);
+                if window_sync {
+                    window.limit_update_rate(Some(self.pacer.get_frame_time(FRAME_CYCLES)));
+                }
+                info!("The speed is {}%", next);
+                self.show_message(&format!("Speed {}%", next));
+            }
+            if window.is_key_pressed(Key::F10, false) {
+                post.set_green_tint(!post.is_green_tint_enabled());
+                self.show_message(if post.is_green_tint_enabled() {
+                    "Green tint ON"
+                } else {
+                    "Green tint OFF"
+                });
+            }
+        }
+        self.gamepads = gamepads;
+        Ok(())
+    }
+
+    /// Returns the window title of the config filled with the statistics of the last second.
+    pub fn get_window_title(&self) -> String {
+        let title = self.get_mmu().cartridge.get_meta().get_title();
+        let title = if title.is_empty() { "Gameboy" } else { &title };
+        format_title(
+            self.config.get_title_format(),
+            title,
+            self.get_stats().as_ref(),
+        )
+    }
+
+    /// Filter the frame and show it in the window.
+    #[cfg(feature = "native")]
+    fn present_window(window: &mut dyn Window, post: &mut PostProcess, frame: &Frame) {
+        let (w, h) = post.get_size();
+        window.present(post.apply(frame), w, h);
+    }
+
+    /// Show the VRAM of the last frame in the viewer window.
+    #[cfg(feature = "native")]
+    fn present_vram(&self, viewer: &mut dyn Window) {
+        let buffer: Vec<u32> = self
+            .get_mmu()
+            .gpu
+            .borrow()
+            .render_vram()
+            .iter()
+            .map(|p| (u32::from(p[0]) << 16) | (u32::from(p[1]) << 8) | u32::from(p[2]))
+            .collect();
+        viewer.present(&buffer, VIEWER_W, VIEWER_H);
+    }
+
+    /// Draw the start screen and handle the input, launch the game once the user selects one.
+    #[cfg(feature = "native")]
+    fn next_browser_frame(&mut self, window: &mut dyn Window, post: &mut PostProcess) {
+        let browser = self.browser.as_mut().unwrap();
+        let mut selected = None;
+        for (rk, vk) in self.config.get_keymap().get_bindings() {
+            if window.is_key_pressed(*rk, true) {
+                selected = browser.press(vk.clone());
+                break;
+            }
+        }
+
+        Self::present_window(window, post, &browser.render());
+
+        match selected {
+            Some(path) => {
+                info!("Launch the rom {:?} from the start screen", path);
+                if let Err(e) = self.load_rom(&path) {
+                    error!("Can't launch the rom {:?}: {}", path, e);
                 }
             }
+            None => thread::sleep(Duration::from_millis(u64::from(STEP_TIME))),
         }
     }
 
     fn set_panic_hook() {
         panic::set_hook(Box::new(|panic_info| {
+            #[cfg(feature = "native")]
             let bt = Backtrace::new();
             let mut msg = vec![];
             if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
@@ -329,14 +1808,75 @@ impl Emulator {
                 msg.push("but can't get location information...".to_string());
             }
             println!("{}", msg.join(" "));
+            #[cfg(feature = "native")]
             println!("The full backtrace is {:?}", bt);
-            dump_cpu_record(Path::new("./coredump")); // TODO: file name
         }));
     }
 
-    pub fn run(&mut self) {
-        self.inspector.start_monitor();
+    /// Run the loop with the panic hook, the cpu history is saved into the coredump if it
+    /// panics.
+    fn run_guarded<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
         Self::set_panic_hook();
-        self._run();
+        match panic::catch_unwind(panic::AssertUnwindSafe(|| f(self))) {
+            Ok(v) => v,
+            Err(e) => {
+                if let Some(history) = self.history.as_ref() {
+                    match history.save(COREDUMP_PATH) {
+                        Ok(()) => println!("The last instructions are saved to {}", COREDUMP_PATH),
+                        Err(e) => println!("Can't save the coredump {}: {}", COREDUMP_PATH, e),
+                    }
+                }
+                panic::resume_unwind(e);
+            }
+        }
+    }
+
+    /// Save the bug report of the panic into the directory: the reason, the cartridge header,
+    /// the last instructions, the last frame and the snapshot of the machine.
+    pub fn save_bug_report(&self, dir: impl AsRef<Path>, reason: &str) -> Result<(), StateError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("panic.txt"), format!("{}\n", reason))?;
+        fs::write(
+            dir.join("cartridge.txt"),
+            format!("{:#?}\n", self.cartridge_info()),
+        )?;
+        if let Some(history) = self.history.as_ref() {
+            history.save(dir.join("history.csv"))?;
+        }
+        save_png(dir.join("frame.png"), &self.frame)?;
+        // The machine may be broken in the middle of the instruction, the snapshot is the last.
+        self.save_state(dir.join("state.ngcs"))
+    }
+
+    /// Pause the game after the panic and save the bug report beside the rom, the player
+    /// continues or resets.
+    #[cfg(feature = "native")]
+    fn recover(&mut self, e: &(dyn std::any::Any + Send)) {
+        let reason = match e.downcast_ref::<&str>() {
+            Some(s) => s.to_string(),
+            None => e.downcast_ref::<String>().cloned().unwrap_or_default(),
+        };
+        self.pause();
+        let dir = self.get_capture_path("crash");
+        let saved = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            self.save_bug_report(&dir, &reason)
+        }));
+        match saved {
+            Ok(Ok(())) => info!("The bug report is saved into {:?}", dir),
+            Ok(Err(e)) => error!("Can't save the bug report into {:?}: {}", dir, e),
+            Err(_) => error!("Can't save the bug report into {:?}", dir),
+        }
+        self.show_message("Crashed, F1 continues, F2 resets");
+    }
+
+    /// Open the window and run until it's closed, returns the error if the window can't be
+    /// opened.
+    #[cfg(feature = "native")]
+    pub fn run(&mut self) -> Result<(), Error> {
+        self.inspector.start_monitor();
+        let result = self.run_guarded(Self::_run);
+        self.finish_session();
+        result
     }
 }
@@ -0,0 +1,57 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use super::cartridge::CartridgeError;
+use super::state::StateError;
+
+/// The error of the emulator, it wraps the error of the part which failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The rom can't be read or the cartridge is not supported.
+    Cartridge(CartridgeError),
+    State(StateError),
+    Io(io::Error),
+    /// The settings of the game are invalid.
+    Config(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Cartridge(e) => write!(f, "can't load the cartridge: {}", e),
+            Error::State(e) => write!(f, "can't load the snapshot: {}", e),
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Config(e) => write!(f, "invalid settings: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Cartridge(e) => Some(e),
+            Error::State(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Config(_) => None,
+        }
+    }
+}
+
+impl From<CartridgeError> for Error {
+    fn from(e: CartridgeError) -> Self {
+        Error::Cartridge(e)
+    }
+}
+
+impl From<StateError> for Error {
+    fn from(e: StateError) -> Self {
+        Error::State(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,278 @@
+//! The interfaces between the emulator and the frontend driving it. The window of the binary is
+//! one frontend, a library user plugs its own through `Emulator::set_video_sink` and
+//! `Emulator::set_input_source`, then calls `Emulator::step_frame`.
+//!
+//! The binary opens its windows and its sound device through a `Frontend`, minifb by default or
+//! SDL2 with the `sdl2` feature, picked by `--frontend`.
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "native")]
+use std::time::Duration;
+
+#[cfg(feature = "native")]
+use minifb::Key;
+
+#[cfg(feature = "native")]
+use super::error::Error;
+use super::joypad::JoypadKey;
+#[cfg(feature = "native")]
+use super::sound::AudioQueue;
+use super::{SCREEN_H, SCREEN_W};
+
+#[cfg(feature = "native")]
+mod minifb_window;
+#[cfg(all(feature = "native", feature = "sdl2"))]
+mod sdl_window;
+
+#[cfg(feature = "native")]
+pub use minifb_window::MinifbFrontend;
+#[cfg(all(feature = "native", feature = "sdl2"))]
+pub use sdl_window::SdlFrontend;
+
+/// The pixels of a frame in RGBA, row by row, 4 bytes each. The alpha is always FF, so the
+/// buffer is passed as it is to the canvas, the texture or the window.
+#[derive(PartialEq)]
+pub struct Frame {
+    data: Vec<u8>,
+}
+
+impl Frame {
+    /// Returns the frame filled with the color.
+    pub fn new(color: [u8; 3]) -> Self {
+        let mut frame = Self {
+            data: vec![0xff; SCREEN_W * SCREEN_H * 4],
+        };
+        frame.fill(color);
+        frame
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * SCREEN_W + x) * 4;
+        [self.data[i], self.data[i + 1], self.data[i + 2]]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        let i = (y * SCREEN_W + x) * 4;
+        self.data[i..i + 3].copy_from_slice(&color);
+    }
+
+    pub fn fill(&mut self, color: [u8; 3]) {
+        for p in self.data.chunks_exact_mut(4) {
+            p[..3].copy_from_slice(&color);
+        }
+    }
+
+    /// Returns the RGB pixels row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = [u8; 3]> + '_ {
+        self.data.chunks_exact(4).map(|p| [p[0], p[1], p[2]])
+    }
+
+    /// Returns the `SCREEN_W * SCREEN_H * 4` bytes of the frame.
+    pub fn as_rgba(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// The finished frames are copied into the same buffer.
+impl Clone for Frame {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.data.clone_from(&source.data);
+    }
+}
+
+/// The frame starts white like the LCD.
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new([0xff; 3])
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Frame({}x{})", SCREEN_W, SCREEN_H)
+    }
+}
+
+/// Where the frames go, it's called once the GPU finishes a frame.
+pub trait VideoSink: Send {
+    fn present(&mut self, frame: &Frame);
+}
+
+/// Where the rumble goes, e.g. the motor of a gamepad. It's called when the motor of the
+/// cartridge starts or stops, checked at the end of every frame.
+pub trait RumbleSink: Send {
+    fn set_rumble(&mut self, enabled: bool);
+}
+
+/// Where the keys come from, it's polled at the end of every frame.
+pub trait InputSource {
+    /// Returns true if the key is held down.
+    fn is_held(&mut self, key: &JoypadKey) -> bool;
+
+    /// Returns true if the turbo key of the button is held down, the button autofires then.
+    fn is_turbo_held(&mut self, _key: &JoypadKey) -> bool {
+        false
+    }
+}
+
+impl<T: InputSource + ?Sized> InputSource for &mut T {
+    fn is_held(&mut self, key: &JoypadKey) -> bool {
+        (**self).is_held(key)
+    }
+
+    fn is_turbo_held(&mut self, key: &JoypadKey) -> bool {
+        (**self).is_turbo_held(key)
+    }
+}
+
+/// The key is held if either source holds it, e.g. the keyboard and the gamepad.
+impl<A: InputSource, B: InputSource> InputSource for (A, B) {
+    fn is_held(&mut self, key: &JoypadKey) -> bool {
+        // Both are polled, so neither misses its events.
+        let a = self.0.is_held(key);
+        let b = self.1.is_held(key);
+        a || b
+    }
+
+    fn is_turbo_held(&mut self, key: &JoypadKey) -> bool {
+        let a = self.0.is_turbo_held(key);
+        let b = self.1.is_turbo_held(key);
+        a || b
+    }
+}
+
+/// The library of the windows of the binary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Minifb,
+    /// Only in the build with the `sdl2` feature.
+    Sdl2,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "minifb" => Ok(Self::Minifb),
+            "sdl2" => Ok(Self::Sdl2),
+            _ => Err(format!("unknown frontend {}", s)),
+        }
+    }
+}
+
+/// The keyboard of a window. The keys are the ones of `minifb::Key` whichever library reads
+/// them, so the keymap is the same for every frontend.
+#[cfg(feature = "native")]
+pub trait Input {
+    /// Returns true if the key is held down.
+    fn is_key_down(&self, key: Key) -> bool;
+
+    /// Returns true if the key went down before the last update, with `repeat` the repeats of
+    /// the held key count too.
+    fn is_key_pressed(&self, key: Key, repeat: bool) -> bool;
+}
+
+/// A window of the binary, the game screen or the VRAM viewer.
+#[cfg(feature = "native")]
+pub trait Window: Input {
+    /// Returns false once the user closes the window.
+    fn is_open(&self) -> bool;
+
+    /// Show the pixels of `0RGB`, stretched to the window keeping the aspect. The events are
+    /// polled as well.
+    fn present(&mut self, buffer: &[u32], w: usize, h: usize);
+
+    /// Poll the events without drawing.
+    fn update(&mut self);
+
+    fn set_title(&mut self, title: &str);
+
+    /// `present` and `update` wait until the time since the last one passes, `None` doesn't wait.
+    fn limit_update_rate(&mut self, time: Option<Duration>);
+}
+
+/// The sound device of a frontend, it plays the samples of its `AudioQueue` until it's dropped.
+#[cfg(feature = "native")]
+pub trait Audio {
+    /// The paused device plays nothing and leaves the queue as it is.
+    fn set_paused(&mut self, paused: bool);
+}
+
+/// Opens the windows and the sound device of a library.
+#[cfg(feature = "native")]
+pub trait Frontend {
+    /// Open the window of the game screen, 1, 2, 4 or 8 times of it. The fullscreen one is
+    /// borderless and of the largest integer scale fitting the host screen.
+    fn open_window(
+        &mut self,
+        title: &str,
+        scale: u32,
+        fullscreen: bool,
+    ) -> Result<Box<dyn Window>, Error>;
+
+    /// Open a plain window of the size, e.g. the VRAM viewer.
+    fn open_viewer(&mut self, title: &str, w: usize, h: usize) -> Result<Box<dyn Window>, Error>;
+
+    /// Start playing the stereo samples of the queue at the rate, `None` if the library has no
+    /// sound.
+    fn open_audio(
+        &mut self,
+        _queue: AudioQueue,
+        _sample_rate: u32,
+    ) -> Result<Option<Box<dyn Audio>>, Error> {
+        Ok(None)
+    }
+}
+
+/// Returns the frontend of the backend, the error if it's not built in.
+#[cfg(feature = "native")]
+pub fn open_frontend(backend: Backend) -> Result<Box<dyn Frontend>, Error> {
+    match backend {
+        Backend::Minifb => Ok(Box::new(MinifbFrontend)),
+        #[cfg(feature = "sdl2")]
+        Backend::Sdl2 => Ok(Box::new(SdlFrontend::new()?)),
+        #[cfg(not(feature = "sdl2"))]
+        Backend::Sdl2 => Err(Error::Config(
+            "the sdl2 frontend is not built, build with `--features sdl2`".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgba() {
+        let mut frame = Frame::new([0x00; 3]);
+        frame.set_pixel(1, 0, [0x01, 0x02, 0x03]);
+        let rgba = frame.as_rgba();
+        assert_eq!(rgba.len(), SCREEN_W * SCREEN_H * 4);
+        assert_eq!(rgba[..8], [0x00, 0x00, 0x00, 0xff, 0x01, 0x02, 0x03, 0xff]);
+        assert_eq!(frame.get_pixel(1, 0), [0x01, 0x02, 0x03]);
+        assert_eq!(frame.pixels().nth(1), Some([0x01, 0x02, 0x03]));
+    }
+
+    #[test]
+    fn test_backend() {
+        assert_eq!("minifb".parse(), Ok(Backend::Minifb));
+        assert_eq!("sdl2".parse(), Ok(Backend::Sdl2));
+        assert!("gtk".parse::<Backend>().is_err());
+    }
+
+    #[cfg(all(feature = "native", not(feature = "sdl2")))]
+    #[test]
+    fn test_sdl2_is_not_built() {
+        assert!(matches!(
+            open_frontend(Backend::Sdl2),
+            Err(Error::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+//! The windows of minifb, the default frontend. It has no sound, and the scale of a window is
+//! fixed once it's opened.
+use std::io;
+use std::time::Duration;
+
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, WindowOptions};
+
+use super::{Frontend, Input, Window};
+use crate::gameboy::error::Error;
+use crate::gameboy::{SCREEN_H, SCREEN_W};
+
+pub struct MinifbFrontend;
+
+impl MinifbFrontend {
+    fn open(
+        title: &str,
+        w: usize,
+        h: usize,
+        option: WindowOptions,
+    ) -> Result<Box<dyn Window>, Error> {
+        let window = minifb::Window::new(title, w, h, option)
+            .map_err(|e| Error::Io(io::Error::other(e.to_string())))?;
+        Ok(Box::new(MinifbWindow(window)))
+    }
+}
+
+impl Frontend for MinifbFrontend {
+    fn open_window(
+        &mut self,
+        title: &str,
+        scale: u32,
+        fullscreen: bool,
+    ) -> Result<Box<dyn Window>, Error> {
+        let mut option = WindowOptions::default();
+        if fullscreen {
+            option.borderless = true;
+            option.title = false;
+            option.topmost = true;
+            option.scale = Scale::FitScreen;
+        } else {
+            option.resize = true;
+            option.scale = match scale {
+                1 => Scale::X1,
+                2 => Scale::X2,
+                4 => Scale::X4,
+                8 => Scale::X8,
+                n => {
+                    let e = format!("the scale {} should be 1, 2, 4 or 8", n);
+                    return Err(Error::Config(e));
+                }
+            };
+        }
+        option.scale_mode = ScaleMode::AspectRatioStretch;
+        Self::open(title, SCREEN_W, SCREEN_H, option)
+    }
+
+    fn open_viewer(&mut self, title: &str, w: usize, h: usize) -> Result<Box<dyn Window>, Error> {
+        Self::open(title, w, h, WindowOptions::default())
+    }
+}
+
+struct MinifbWindow(minifb::Window);
+
+impl Input for MinifbWindow {
+    fn is_key_down(&self, key: Key) -> bool {
+        self.0.is_key_down(key)
+    }
+
+    fn is_key_pressed(&self, key: Key, repeat: bool) -> bool {
+        let repeat = if repeat {
+            KeyRepeat::Yes
+        } else {
+            KeyRepeat::No
+        };
+        self.0.is_key_pressed(key, repeat)
+    }
+}
+
+impl Window for MinifbWindow {
+    fn is_open(&self) -> bool {
+        self.0.is_open()
+    }
+
+    fn present(&mut self, buffer: &[u32], w: usize, h: usize) {
+        self.0.update_with_buffer(buffer, w, h).unwrap();
+    }
+
+    fn update(&mut self) {
+        self.0.update();
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.0.set_title(title);
+    }
+
+    fn limit_update_rate(&mut self, time: Option<Duration>) {
+        self.0.limit_update_rate(time);
+    }
+}
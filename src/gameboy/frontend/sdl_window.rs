@@ -0,0 +1,361 @@
+//! The windows and the sound of SDL2, built with the `sdl2` feature. SDL has one event pump for
+//! all the windows, so the events are kept for the window they belong to until it polls them.
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::error;
+use minifb::Key;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Scancode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use sdl2::{EventPump, Sdl, VideoSubsystem};
+
+use super::{Audio, Frontend, Input, Window};
+use crate::gameboy::error::Error;
+use crate::gameboy::sound::AudioQueue;
+use crate::gameboy::{SCREEN_H, SCREEN_W};
+
+#[rustfmt::skip]
+const SCANCODES: &[(Key, Scancode)] = &[
+    (Key::Key0, Scancode::Num0), (Key::Key1, Scancode::Num1), (Key::Key2, Scancode::Num2),
+    (Key::Key3, Scancode::Num3), (Key::Key4, Scancode::Num4), (Key::Key5, Scancode::Num5),
+    (Key::Key6, Scancode::Num6), (Key::Key7, Scancode::Num7), (Key::Key8, Scancode::Num8),
+    (Key::Key9, Scancode::Num9),
+    (Key::A, Scancode::A), (Key::B, Scancode::B), (Key::C, Scancode::C), (Key::D, Scancode::D),
+    (Key::E, Scancode::E), (Key::F, Scancode::F), (Key::G, Scancode::G), (Key::H, Scancode::H),
+    (Key::I, Scancode::I), (Key::J, Scancode::J), (Key::K, Scancode::K), (Key::L, Scancode::L),
+    (Key::M, Scancode::M), (Key::N, Scancode::N), (Key::O, Scancode::O), (Key::P, Scancode::P),
+    (Key::Q, Scancode::Q), (Key::R, Scancode::R), (Key::S, Scancode::S), (Key::T, Scancode::T),
+    (Key::U, Scancode::U), (Key::V, Scancode::V), (Key::W, Scancode::W), (Key::X, Scancode::X),
+    (Key::Y, Scancode::Y), (Key::Z, Scancode::Z),
+    (Key::F1, Scancode::F1), (Key::F2, Scancode::F2), (Key::F3, Scancode::F3),
+    (Key::F4, Scancode::F4), (Key::F5, Scancode::F5), (Key::F6, Scancode::F6),
+    (Key::F7, Scancode::F7), (Key::F8, Scancode::F8), (Key::F9, Scancode::F9),
+    (Key::F10, Scancode::F10), (Key::F11, Scancode::F11), (Key::F12, Scancode::F12),
+    (Key::Down, Scancode::Down), (Key::Left, Scancode::Left), (Key::Right, Scancode::Right),
+    (Key::Up, Scancode::Up),
+    (Key::Apostrophe, Scancode::Apostrophe), (Key::Backquote, Scancode::Grave),
+    (Key::Backslash, Scancode::Backslash), (Key::Comma, Scancode::Comma),
+    (Key::Equal, Scancode::Equals), (Key::LeftBracket, Scancode::LeftBracket),
+    (Key::Minus, Scancode::Minus), (Key::Period, Scancode::Period),
+    (Key::RightBracket, Scancode::RightBracket), (Key::Semicolon, Scancode::Semicolon),
+    (Key::Slash, Scancode::Slash),
+    (Key::Backspace, Scancode::Backspace), (Key::Delete, Scancode::Delete),
+    (Key::End, Scancode::End), (Key::Enter, Scancode::Return), (Key::Escape, Scancode::Escape),
+    (Key::Home, Scancode::Home), (Key::Insert, Scancode::Insert),
+    (Key::PageDown, Scancode::PageDown), (Key::PageUp, Scancode::PageUp),
+    (Key::Space, Scancode::Space), (Key::Tab, Scancode::Tab),
+    (Key::LeftShift, Scancode::LShift), (Key::RightShift, Scancode::RShift),
+    (Key::LeftCtrl, Scancode::LCtrl), (Key::RightCtrl, Scancode::RCtrl),
+    (Key::LeftAlt, Scancode::LAlt), (Key::RightAlt, Scancode::RAlt),
+    (Key::NumPad0, Scancode::Kp0), (Key::NumPad1, Scancode::Kp1), (Key::NumPad2, Scancode::Kp2),
+    (Key::NumPad3, Scancode::Kp3), (Key::NumPad4, Scancode::Kp4), (Key::NumPad5, Scancode::Kp5),
+    (Key::NumPad6, Scancode::Kp6), (Key::NumPad7, Scancode::Kp7), (Key::NumPad8, Scancode::Kp8),
+    (Key::NumPad9, Scancode::Kp9),
+];
+
+fn to_scancode(key: Key) -> Option<Scancode> {
+    SCANCODES.iter().find(|(k, _)| *k == key).map(|(_, sc)| *sc)
+}
+
+fn sdl_error(e: impl ToString) -> Error {
+    Error::Io(io::Error::other(e.to_string()))
+}
+
+/// Returns the streaming texture of the buffers of the size.
+fn new_texture(
+    creator: &TextureCreator<WindowContext>,
+    w: usize,
+    h: usize,
+) -> Result<Texture, String> {
+    creator
+        .create_texture_streaming(PixelFormatEnum::RGB888, w as u32, h as u32)
+        .map_err(|e| e.to_string())
+}
+
+/// The events polled for the windows.
+struct Events {
+    pump: EventPump,
+    // The keys pressed in each window and not taken by it yet, with the repeat flag.
+    pressed: HashMap<u32, Vec<(Scancode, bool)>>,
+    closed: HashSet<u32>,
+    quit: bool,
+}
+
+impl Events {
+    fn poll(&mut self) {
+        for event in self.pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => self.quit = true,
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    self.closed.insert(window_id);
+                }
+                Event::KeyDown {
+                    window_id,
+                    scancode: Some(scancode),
+                    repeat,
+                    ..
+                } => self
+                    .pressed
+                    .entry(window_id)
+                    .or_default()
+                    .push((scancode, repeat)),
+                _ => {}
+            }
+        }
+    }
+}
+
+pub struct SdlFrontend {
+    // SDL is shut down once it's dropped, after every window.
+    sdl: Sdl,
+    video: VideoSubsystem,
+    events: Rc<RefCell<Events>>,
+}
+
+impl SdlFrontend {
+    pub fn new() -> Result<Self, Error> {
+        let sdl = sdl2::init().map_err(sdl_error)?;
+        let video = sdl.video().map_err(sdl_error)?;
+        let pump = sdl.event_pump().map_err(sdl_error)?;
+        Ok(Self {
+            sdl,
+            video,
+            events: Rc::new(RefCell::new(Events {
+                pump,
+                pressed: HashMap::new(),
+                closed: HashSet::new(),
+                quit: false,
+            })),
+        })
+    }
+
+    /// Open the window of the buffers of `size`.
+    fn open(
+        &mut self,
+        window: sdl2::video::Window,
+        size: (usize, usize),
+        integer_scale: bool,
+    ) -> Result<Box<dyn Window>, Error> {
+        let id = window.id();
+        let mut canvas = window.into_canvas().build().map_err(sdl_error)?;
+        // The pixels stay square in the fullscreen window.
+        canvas.set_integer_scale(integer_scale).map_err(sdl_error)?;
+        canvas
+            .set_logical_size(size.0 as u32, size.1 as u32)
+            .map_err(sdl_error)?;
+        let creator = canvas.texture_creator();
+        let texture = new_texture(&creator, size.0, size.1).map_err(sdl_error)?;
+        Ok(Box::new(SdlWindow {
+            id,
+            canvas,
+            creator,
+            texture,
+            size,
+            bytes: vec![],
+            events: self.events.clone(),
+            pressed: vec![],
+            rate: None,
+            last_update: Instant::now(),
+        }))
+    }
+}
+
+impl Frontend for SdlFrontend {
+    fn open_window(
+        &mut self,
+        title: &str,
+        scale: u32,
+        fullscreen: bool,
+    ) -> Result<Box<dyn Window>, Error> {
+        let (w, h) = (SCREEN_W as u32 * scale, SCREEN_H as u32 * scale);
+        let mut builder = self.video.window(title, w, h);
+        builder.position_centered();
+        if fullscreen {
+            builder.fullscreen_desktop();
+        } else {
+            builder.resizable();
+        }
+        let window = builder.build().map_err(sdl_error)?;
+        self.open(window, (SCREEN_W, SCREEN_H), fullscreen)
+    }
+
+    fn open_viewer(&mut self, title: &str, w: usize, h: usize) -> Result<Box<dyn Window>, Error> {
+        let window = self
+            .video
+            .window(title, w as u32, h as u32)
+            .build()
+            .map_err(sdl_error)?;
+        self.open(window, (w, h), false)
+    }
+
+    fn open_audio(
+        &mut self,
+        queue: AudioQueue,
+        sample_rate: u32,
+    ) -> Result<Option<Box<dyn Audio>>, Error> {
+        let audio = self.sdl.audio().map_err(sdl_error)?;
+        let desired = AudioSpecDesired {
+            freq: Some(sample_rate as i32),
+            channels: Some(2),
+            samples: None,
+        };
+        // SDL converts the samples if the device plays another format.
+        let device = audio
+            .open_playback(None, &desired, |_| Playback {
+                queue,
+                buffer: vec![],
+            })
+            .map_err(sdl_error)?;
+        device.resume();
+        Ok(Some(Box::new(SdlAudio(device))))
+    }
+}
+
+/// The callback of the device, it runs on the audio thread of SDL.
+struct Playback {
+    queue: AudioQueue,
+    buffer: Vec<[f32; 2]>,
+}
+
+impl AudioCallback for Playback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.buffer.resize(out.len() / 2, [0.0; 2]);
+        self.queue.pop(&mut self.buffer);
+        for (o, sample) in out.chunks_exact_mut(2).zip(&self.buffer) {
+            o.copy_from_slice(sample);
+        }
+    }
+}
+
+struct SdlAudio(AudioDevice<Playback>);
+
+impl Audio for SdlAudio {
+    fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.0.pause();
+        } else {
+            self.0.resume();
+        }
+    }
+}
+
+struct SdlWindow {
+    id: u32,
+    canvas: WindowCanvas,
+    creator: TextureCreator<WindowContext>,
+    // The texture of the buffers of `size`, it's made again only if the size changes. It's
+    // freed with the canvas.
+    texture: Texture,
+    // The size of the last buffer, the canvas keeps its aspect.
+    size: (usize, usize),
+    // The buffer in the bytes of the texture.
+    bytes: Vec<u8>,
+    events: Rc<RefCell<Events>>,
+    // The keys pressed before the last update.
+    pressed: Vec<(Scancode, bool)>,
+    rate: Option<Duration>,
+    last_update: Instant,
+}
+
+impl SdlWindow {
+    fn draw(&mut self, buffer: &[u32], w: usize, h: usize) -> Result<(), String> {
+        if self.size != (w, h) {
+            let texture = new_texture(&self.creator, w, h)?;
+            // The old texture is of this canvas and nothing draws it anymore.
+            unsafe { mem::replace(&mut self.texture, texture).destroy() };
+            self.canvas
+                .set_logical_size(w as u32, h as u32)
+                .map_err(|e| e.to_string())?;
+            self.size = (w, h);
+        }
+        self.bytes.clear();
+        self.bytes
+            .extend(buffer.iter().flat_map(|p| p.to_ne_bytes()));
+        self.texture
+            .update(None, &self.bytes, w * 4)
+            .map_err(|e| e.to_string())?;
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, None)?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn wait(&mut self) {
+        if let Some(rate) = self.rate {
+            let elapsed = self.last_update.elapsed();
+            if elapsed < rate {
+                thread::sleep(rate - elapsed);
+            }
+        }
+        self.last_update = Instant::now();
+    }
+}
+
+impl Input for SdlWindow {
+    fn is_key_down(&self, key: Key) -> bool {
+        let events = self.events.borrow();
+        to_scancode(key).is_some_and(|sc| events.pump.keyboard_state().is_scancode_pressed(sc))
+    }
+
+    fn is_key_pressed(&self, key: Key, repeat: bool) -> bool {
+        to_scancode(key).is_some_and(|sc| {
+            self.pressed
+                .iter()
+                .any(|&(s, repeated)| s == sc && (repeat || !repeated))
+        })
+    }
+}
+
+impl Window for SdlWindow {
+    fn is_open(&self) -> bool {
+        let events = self.events.borrow();
+        !events.quit && !events.closed.contains(&self.id)
+    }
+
+    fn present(&mut self, buffer: &[u32], w: usize, h: usize) {
+        if let Err(e) = self.draw(buffer, w, h) {
+            error!("Can't draw the window: {}", e);
+        }
+        self.update();
+    }
+
+    fn update(&mut self) {
+        self.wait();
+        let mut events = self.events.borrow_mut();
+        events.poll();
+        self.pressed = events.pressed.remove(&self.id).unwrap_or_default();
+    }
+
+    fn set_title(&mut self, title: &str) {
+        if let Err(e) = self.canvas.window_mut().set_title(title) {
+            error!("Can't set the window title: {}", e);
+        }
+    }
+
+    fn limit_update_rate(&mut self, time: Option<Duration>) {
+        self.rate = time;
+    }
+}
+
+impl Drop for SdlWindow {
+    fn drop(&mut self) {
+        let mut events = self.events.borrow_mut();
+        events.pressed.remove(&self.id);
+        events.closed.remove(&self.id);
+    }
+}
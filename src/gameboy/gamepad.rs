@@ -0,0 +1,232 @@
+//! The controller input. The events come from a `GamepadBackend`, which wraps the controller
+//! library of the platform (e.g. gilrs). The binary reads the Linux joystick devices with
+//! `joydev::JoystickBackend`.
+//!
+//! The D-pad and the left stick both drive the direction keys, the stick is ignored inside the
+//! deadzone. Controllers may be plugged and unplugged at any time, the keys of all the connected
+//! ones are merged.
+use std::collections::HashMap;
+
+use log::info;
+
+use super::frontend::InputSource;
+use super::joypad::JoypadKey;
+
+#[cfg(target_os = "linux")]
+pub mod joydev;
+
+pub const DEFAULT_DEADZONE: f32 = 0.5;
+
+/// The controller buttons, named after their position like the Xbox layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    // The bottom face button, A on Xbox.
+    South,
+    // The right face button, B on Xbox.
+    East,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub fn to_joypad_key(self) -> JoypadKey {
+        match self {
+            Self::DPadUp => JoypadKey::Up,
+            Self::DPadDown => JoypadKey::Down,
+            Self::DPadLeft => JoypadKey::Left,
+            Self::DPadRight => JoypadKey::Right,
+            Self::South => JoypadKey::A,
+            Self::East => JoypadKey::B,
+            Self::Select => JoypadKey::Select,
+            Self::Start => JoypadKey::Start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    // -1.0 is left and 1.0 is right.
+    LeftStickX,
+    // -1.0 is down and 1.0 is up.
+    LeftStickY,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GamepadEvent {
+    Connected(usize),
+    Disconnected(usize),
+    ButtonPressed(usize, Button),
+    ButtonReleased(usize, Button),
+    AxisChanged(usize, Axis, f32),
+}
+
+/// The source of the controller events, each id is a controller.
+pub trait GamepadBackend: Send {
+    /// Returns the next pending event, `None` if there's no more.
+    fn poll(&mut self) -> Option<GamepadEvent>;
+}
+
+#[derive(Debug, Default)]
+struct Pad {
+    buttons: Vec<Button>,
+    x: f32,
+    y: f32,
+}
+
+impl Pad {
+    fn is_held(&self, key: &JoypadKey, deadzone: f32) -> bool {
+        let stick = match key {
+            JoypadKey::Right => self.x > deadzone,
+            JoypadKey::Left => self.x < -deadzone,
+            JoypadKey::Up => self.y > deadzone,
+            JoypadKey::Down => self.y < -deadzone,
+            _ => false,
+        };
+        stick || self.buttons.iter().any(|b| b.to_joypad_key() == *key)
+    }
+}
+
+pub struct Gamepads {
+    backend: Box<dyn GamepadBackend>,
+    pads: HashMap<usize, Pad>,
+    deadzone: f32,
+}
+
+impl Gamepads {
+    pub fn new(backend: Box<dyn GamepadBackend>, deadzone: f32) -> Self {
+        Self {
+            backend,
+            pads: HashMap::new(),
+            deadzone,
+        }
+    }
+
+    pub fn get_deadzone(&self) -> f32 {
+        self.deadzone
+    }
+
+    pub fn set_deadzone(&mut self, deadzone: f32) {
+        self.deadzone = deadzone;
+    }
+
+    /// Returns the count of the connected controllers.
+    pub fn len(&self) -> usize {
+        self.pads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pads.is_empty()
+    }
+
+    /// Handle the pending events of the backend.
+    pub fn update(&mut self) {
+        while let Some(event) = self.backend.poll() {
+            match event {
+                GamepadEvent::Connected(id) => {
+                    info!("The gamepad {} is connected", id);
+                    self.pads.insert(id, Pad::default());
+                }
+                GamepadEvent::Disconnected(id) => {
+                    info!("The gamepad {} is disconnected", id);
+                    // Its keys are released with it.
+                    self.pads.remove(&id);
+                }
+                // The backend may not report the controllers connected before it starts.
+                GamepadEvent::ButtonPressed(id, b) => {
+                    let pad = self.pads.entry(id).or_default();
+                    if !pad.buttons.contains(&b) {
+                        pad.buttons.push(b);
+                    }
+                }
+                GamepadEvent::ButtonReleased(id, b) => {
+                    if let Some(pad) = self.pads.get_mut(&id) {
+                        pad.buttons.retain(|v| *v != b);
+                    }
+                }
+                GamepadEvent::AxisChanged(id, axis, v) => {
+                    let pad = self.pads.entry(id).or_default();
+                    match axis {
+                        Axis::LeftStickX => pad.x = v,
+                        Axis::LeftStickY => pad.y = v,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The events are handled before the keys are read.
+impl InputSource for Gamepads {
+    fn is_held(&mut self, key: &JoypadKey) -> bool {
+        self.update();
+        self.pads.values().any(|p| p.is_held(key, self.deadzone))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<VecDeque<GamepadEvent>>>);
+
+    impl Events {
+        fn push(&self, e: GamepadEvent) {
+            self.0.lock().unwrap().push_back(e);
+        }
+    }
+
+    impl GamepadBackend for Events {
+        fn poll(&mut self) -> Option<GamepadEvent> {
+            self.0.lock().unwrap().pop_front()
+        }
+    }
+
+    #[test]
+    fn test_buttons() {
+        let events = Events::default();
+        let mut pads = Gamepads::new(Box::new(events.clone()), DEFAULT_DEADZONE);
+        events.push(GamepadEvent::Connected(0));
+        events.push(GamepadEvent::ButtonPressed(0, Button::South));
+        events.push(GamepadEvent::ButtonPressed(0, Button::DPadLeft));
+        assert!(pads.is_held(&JoypadKey::A));
+        assert!(pads.is_held(&JoypadKey::Left));
+        assert!(!pads.is_held(&JoypadKey::B));
+        events.push(GamepadEvent::ButtonReleased(0, Button::South));
+        assert!(!pads.is_held(&JoypadKey::A));
+    }
+
+    #[test]
+    fn test_stick_deadzone() {
+        let events = Events::default();
+        let mut pads = Gamepads::new(Box::new(events.clone()), 0.3);
+        events.push(GamepadEvent::AxisChanged(0, Axis::LeftStickX, 0.2));
+        events.push(GamepadEvent::AxisChanged(0, Axis::LeftStickY, -0.9));
+        assert!(!pads.is_held(&JoypadKey::Right));
+        assert!(!pads.is_held(&JoypadKey::Left));
+        assert!(pads.is_held(&JoypadKey::Down));
+        events.push(GamepadEvent::AxisChanged(0, Axis::LeftStickX, 0.4));
+        assert!(pads.is_held(&JoypadKey::Right));
+    }
+
+    #[test]
+    fn test_hot_plug() {
+        let events = Events::default();
+        let mut pads = Gamepads::new(Box::new(events.clone()), DEFAULT_DEADZONE);
+        events.push(GamepadEvent::Connected(0));
+        events.push(GamepadEvent::Connected(1));
+        events.push(GamepadEvent::ButtonPressed(1, Button::Start));
+        assert!(pads.is_held(&JoypadKey::Start));
+        assert_eq!(pads.len(), 2);
+        events.push(GamepadEvent::Disconnected(1));
+        assert!(!pads.is_held(&JoypadKey::Start));
+        assert_eq!(pads.len(), 1);
+    }
+}
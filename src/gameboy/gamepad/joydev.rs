@@ -0,0 +1,281 @@
+//! The controllers of the Linux joystick API, `/dev/input/js0` to `js3`. The devices are read
+//! without blocking and opened again every second, so the controllers can be plugged at any
+//! time. The buttons and the axes follow the xpad (Xbox) layout, the D-pad is the hat axes 6/7.
+//!
+//! See: https://www.kernel.org/doc/html/latest/input/joydev/joystick-api.html
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use super::{Axis, Button, GamepadBackend, GamepadEvent};
+
+const MAX_PADS: usize = 4;
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+// fcntl.h, the reads return WouldBlock instead of waiting for the next event.
+const O_NONBLOCK: i32 = 0o4000;
+
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+// The synthetic events of the state when the device is opened.
+const JS_EVENT_INIT: u8 = 0x80;
+
+const AXIS_LEFT_X: u8 = 0;
+const AXIS_LEFT_Y: u8 = 1;
+const AXIS_HAT_X: u8 = 6;
+const AXIS_HAT_Y: u8 = 7;
+
+/// The direction of the hat axis, -1, 0 or 1 on each.
+#[derive(Debug, Default)]
+struct Hat {
+    x: i8,
+    y: i8,
+}
+
+struct Joystick {
+    file: File,
+    hat: Hat,
+}
+
+pub struct JoystickBackend {
+    dir: PathBuf,
+    pads: Vec<Option<Joystick>>,
+    events: VecDeque<GamepadEvent>,
+    last_scan: Option<Instant>,
+}
+
+impl Default for JoystickBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JoystickBackend {
+    pub fn new() -> Self {
+        Self::with_dir("/dev/input")
+    }
+
+    /// Returns the backend reading the `js*` devices in the directory.
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            pads: (0..MAX_PADS).map(|_| None).collect(),
+            events: VecDeque::new(),
+            last_scan: None,
+        }
+    }
+
+    /// Open the devices which are not opened yet.
+    fn scan(&mut self, now: Instant) {
+        if self.last_scan.is_some_and(|t| now - t < SCAN_INTERVAL) {
+            return;
+        }
+        self.last_scan = Some(now);
+        for (id, pad) in self.pads.iter_mut().enumerate() {
+            if pad.is_some() {
+                continue;
+            }
+            let path = self.dir.join(format!("js{}", id));
+            let opened = OpenOptions::new()
+                .read(true)
+                .custom_flags(O_NONBLOCK)
+                .open(path);
+            if let Ok(file) = opened {
+                *pad = Some(Joystick {
+                    file,
+                    hat: Hat::default(),
+                });
+                self.events.push_back(GamepadEvent::Connected(id));
+            }
+        }
+    }
+
+    /// Read the pending events of every device.
+    fn read(&mut self) {
+        let mut data = [0u8; 8];
+        for (id, pad) in self.pads.iter_mut().enumerate() {
+            while let Some(js) = pad.as_mut() {
+                match js.file.read_exact(&mut data) {
+                    Ok(()) => to_events(id, &data, &mut js.hat, &mut self.events),
+                    // No event is pending, the device only fails the read when it's unplugged.
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(_) => {
+                        *pad = None;
+                        self.events.push_back(GamepadEvent::Disconnected(id));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GamepadBackend for JoystickBackend {
+    fn poll(&mut self) -> Option<GamepadEvent> {
+        if self.events.is_empty() {
+            self.scan(Instant::now());
+            self.read();
+        }
+        self.events.pop_front()
+    }
+}
+
+fn to_button(number: u8) -> Option<Button> {
+    match number {
+        0 => Some(Button::South),
+        1 => Some(Button::East),
+        6 => Some(Button::Select),
+        7 => Some(Button::Start),
+        _ => None,
+    }
+}
+
+/// Press or release the D-pad buttons of the hat axis.
+fn to_hat_events(
+    id: usize,
+    old: i8,
+    new: i8,
+    buttons: (Button, Button),
+    events: &mut VecDeque<GamepadEvent>,
+) {
+    if old == new {
+        return;
+    }
+    let button = |v: i8| if v < 0 { buttons.0 } else { buttons.1 };
+    if old != 0 {
+        events.push_back(GamepadEvent::ButtonReleased(id, button(old)));
+    }
+    if new != 0 {
+        events.push_back(GamepadEvent::ButtonPressed(id, button(new)));
+    }
+}
+
+/// Turn the `js_event` into the events of the controller: the time (u32), the value (i16), the
+/// type and the number of the button or the axis.
+fn to_events(id: usize, data: &[u8; 8], hat: &mut Hat, events: &mut VecDeque<GamepadEvent>) {
+    let value = i16::from_le_bytes([data[4], data[5]]);
+    let (kind, number) = (data[6] & !JS_EVENT_INIT, data[7]);
+    match kind {
+        JS_EVENT_BUTTON => {
+            if let Some(b) = to_button(number) {
+                events.push_back(if value != 0 {
+                    GamepadEvent::ButtonPressed(id, b)
+                } else {
+                    GamepadEvent::ButtonReleased(id, b)
+                });
+            }
+        }
+        JS_EVENT_AXIS => {
+            let v = f32::from(value) / 32767.0;
+            match number {
+                AXIS_LEFT_X => {
+                    events.push_back(GamepadEvent::AxisChanged(id, Axis::LeftStickX, v));
+                }
+                // The joystick API is positive downward.
+                AXIS_LEFT_Y => {
+                    events.push_back(GamepadEvent::AxisChanged(id, Axis::LeftStickY, -v));
+                }
+                AXIS_HAT_X => {
+                    let x = value.signum() as i8;
+                    let buttons = (Button::DPadLeft, Button::DPadRight);
+                    to_hat_events(id, hat.x, x, buttons, events);
+                    hat.x = x;
+                }
+                AXIS_HAT_Y => {
+                    let y = value.signum() as i8;
+                    let buttons = (Button::DPadUp, Button::DPadDown);
+                    to_hat_events(id, hat.y, y, buttons, events);
+                    hat.y = y;
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn js_event(value: i16, kind: u8, number: u8) -> [u8; 8] {
+        let v = value.to_le_bytes();
+        [0, 0, 0, 0, v[0], v[1], kind, number]
+    }
+
+    fn to_all_events(id: usize, data: &[[u8; 8]]) -> Vec<GamepadEvent> {
+        let (mut hat, mut events) = (Hat::default(), VecDeque::new());
+        for d in data {
+            to_events(id, d, &mut hat, &mut events);
+        }
+        Vec::from(events)
+    }
+
+    #[test]
+    fn test_js_events() {
+        let events = to_all_events(
+            1,
+            &[
+                js_event(1, JS_EVENT_BUTTON | JS_EVENT_INIT, 7),
+                js_event(0, JS_EVENT_BUTTON, 0),
+                js_event(-32767, JS_EVENT_AXIS, 1),
+                // The Y button is not a key.
+                js_event(1, JS_EVENT_BUTTON, 3),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![
+                GamepadEvent::ButtonPressed(1, Button::Start),
+                GamepadEvent::ButtonReleased(1, Button::South),
+                GamepadEvent::AxisChanged(1, Axis::LeftStickY, 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hat_is_the_dpad() {
+        let events = to_all_events(
+            0,
+            &[
+                js_event(-32767, JS_EVENT_AXIS, 6),
+                js_event(32767, JS_EVENT_AXIS, 6),
+                js_event(0, JS_EVENT_AXIS, 6),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![
+                GamepadEvent::ButtonPressed(0, Button::DPadLeft),
+                GamepadEvent::ButtonReleased(0, Button::DPadLeft),
+                GamepadEvent::ButtonPressed(0, Button::DPadRight),
+                GamepadEvent::ButtonReleased(0, Button::DPadRight),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_device_is_opened() {
+        let dir = std::env::temp_dir().join(format!("ngc224-joydev-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut data = js_event(1, JS_EVENT_BUTTON, 1).to_vec();
+        data.extend_from_slice(&js_event(0, JS_EVENT_BUTTON, 1));
+        std::fs::write(dir.join("js2"), data).unwrap();
+
+        let mut backend = JoystickBackend::with_dir(&dir);
+        assert_eq!(backend.poll(), Some(GamepadEvent::Connected(2)));
+        assert_eq!(
+            backend.poll(),
+            Some(GamepadEvent::ButtonPressed(2, Button::East))
+        );
+        assert_eq!(
+            backend.poll(),
+            Some(GamepadEvent::ButtonReleased(2, Button::East))
+        );
+        // The end of the file is not an event.
+        assert_eq!(backend.poll(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,449 @@
+//! The post-processing between the GPU frame and the window buffer. A filter scales the frame
+//! up and smooths or decorates the pixels, the green tint maps the colors to the 4 shades of the
+//! DMG screen before it.
+//!
+//! The frame blending mixes the previous frames into the current one like the slow pixels of the
+//! DMG LCD, some games flicker the sprites every other frame for the transparency and count on it.
+//!
+//! The window shows 2x of the screen, the 2x filters therefore draw one pixel per window pixel,
+//! `nearest` keeps the frame as is and leaves the stretching to the window.
+use std::str::FromStr;
+
+use crate::gameboy::frontend::Frame;
+use crate::gameboy::{SCREEN_H, SCREEN_W};
+
+// The DMG screen from the darkest shade to the lightest.
+const DMG_GREEN: [u32; 4] = [0x0f_380f, 0x30_6230, 0x8b_ac0f, 0x9b_bc0f];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    // Kreed's 2xSaI, it smooths the edges of the sprites.
+    Sai2x,
+    Hq2x,
+    // Every second line is darker like a CRT.
+    Scanline,
+    // The gaps between the pixels of the LCD are darker.
+    LcdGrid,
+}
+
+// The order the hotkey goes through.
+const FILTERS: [Filter; 5] = [
+    Filter::Nearest,
+    Filter::Sai2x,
+    Filter::Hq2x,
+    Filter::Scanline,
+    Filter::LcdGrid,
+];
+
+impl Filter {
+    /// How many times the filter scales the frame up.
+    pub fn get_scale(self) -> usize {
+        match self {
+            Self::Nearest => 1,
+            _ => 2,
+        }
+    }
+
+    /// Returns the filter after this one, the last is followed by the first.
+    pub fn next(self) -> Self {
+        let i = FILTERS.iter().position(|f| *f == self).unwrap();
+        FILTERS[(i + 1) % FILTERS.len()]
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Self::Nearest),
+            "2xsai" => Ok(Self::Sai2x),
+            "hq2x" => Ok(Self::Hq2x),
+            "scanline" => Ok(Self::Scanline),
+            "lcd-grid" => Ok(Self::LcdGrid),
+            _ => Err(format!("unknown filter {}", s)),
+        }
+    }
+}
+
+/// The pixels of the frame in the 0RGB format of the window.
+struct Pixels {
+    data: Vec<u32>,
+}
+
+impl Pixels {
+    /// The pixel at the position, the one outside of the screen repeats the edge.
+    fn get(&self, x: isize, y: isize) -> u32 {
+        let x = x.max(0).min(SCREEN_W as isize - 1) as usize;
+        let y = y.max(0).min(SCREEN_H as isize - 1) as usize;
+        self.data[y * SCREEN_W + x]
+    }
+}
+
+/// Mix the colors by the weights, per channel.
+fn mix(colors: &[(u32, u32)]) -> u32 {
+    let total: u32 = colors.iter().map(|(_, w)| w).sum();
+    let mut out = 0;
+    for shift in [0, 8, 16].iter() {
+        let sum: u32 = colors.iter().map(|(c, w)| (c >> shift & 0xff) * w).sum();
+        out |= (sum / total) << shift;
+    }
+    out
+}
+
+fn interp(a: u32, b: u32) -> u32 {
+    mix(&[(a, 1), (b, 1)])
+}
+
+/// Darken the color to `n / 4` of it.
+fn dim(c: u32, n: u32) -> u32 {
+    mix(&[(c, n), (0x00_0000, 4 - n)])
+}
+
+fn to_yuv(c: u32) -> (i32, i32, i32) {
+    let (r, g, b) = (
+        (c >> 16 & 0xff) as i32,
+        (c >> 8 & 0xff) as i32,
+        (c & 0xff) as i32,
+    );
+    (
+        (r * 299 + g * 587 + b * 114) / 1000,
+        (-r * 169 - g * 331 + b * 500) / 1000 + 128,
+        (r * 500 - g * 419 - b * 81) / 1000 + 128,
+    )
+}
+
+/// The colors are distinct with the thresholds of the original hqx.
+fn is_distinct(a: u32, b: u32) -> bool {
+    let (ya, ua, va) = to_yuv(a);
+    let (yb, ub, vb) = to_yuv(b);
+    (ya - yb).abs() > 0x30 || (ua - ub).abs() > 0x07 || (va - vb).abs() > 0x06
+}
+
+/// Convert the frame to the window pixels, optionally with the DMG green shades.
+fn to_pixels(frame: &Frame, green_tint: bool) -> Pixels {
+    let mut data = Vec::with_capacity(SCREEN_W * SCREEN_H);
+    for p in frame.as_rgba().chunks_exact(4) {
+        let c = (u32::from(p[0]) << 16) | (u32::from(p[1]) << 8) | u32::from(p[2]);
+        data.push(if green_tint {
+            let (y, _, _) = to_yuv(c);
+            DMG_GREEN[y as usize * 4 / 256]
+        } else {
+            c
+        });
+    }
+    Pixels { data }
+}
+
+/// Mix the previous pixels by the persistence into the current ones, they are kept for the next
+/// frame. The previous pixels hold what they mixed before, so an old frame fades out over a few.
+fn blend(pixels: &mut Pixels, previous: &mut Vec<u32>, persistence: f32) {
+    if previous.len() == pixels.data.len() {
+        let w = (persistence * 256.0) as u32;
+        for (c, p) in pixels.data.iter_mut().zip(previous.iter()) {
+            *c = mix(&[(*c, 256 - w), (*p, w)]);
+        }
+    }
+    previous.clear();
+    previous.extend_from_slice(&pixels.data);
+}
+
+/// Returns the 2x2 block of the pixel at (x, y), from left to right and top to bottom.
+fn sai_2x(p: &Pixels, x: isize, y: isize) -> [u32; 4] {
+    // I E F J
+    // G A B K
+    // H C D L
+    // M N O P
+    let at = |dx, dy| p.get(x + dx, y + dy);
+    let (i, e, f, j) = (at(-1, -1), at(0, -1), at(1, -1), at(2, -1));
+    let (g, a, b, k) = (at(-1, 0), at(0, 0), at(1, 0), at(2, 0));
+    let (h, c, d, l) = (at(-1, 1), at(0, 1), at(1, 1), at(2, 1));
+    let (m, n, o) = (at(-1, 2), at(0, 2), at(1, 2));
+
+    // The votes of the pixels around for the A-D or the B-C diagonal.
+    let result = |a: u32, b: u32, c: u32, d: u32, sign: i32| -> i32 {
+        let (mut x, mut y) = (0, 0);
+        if a == c {
+            x += 1;
+        } else if b == c {
+            y += 1;
+        }
+        if a == d {
+            x += 1;
+        } else if b == d {
+            y += 1;
+        }
+        sign * (i32::from(x <= 1) - i32::from(y <= 1))
+    };
+
+    let (right, below, corner);
+    if a == d && b != c {
+        right = if (a == e && b == l) || (a == c && a == f && b != e && b == j) {
+            a
+        } else {
+            interp(a, b)
+        };
+        below = if (a == g && c == o) || (a == b && a == h && g != c && c == m) {
+            a
+        } else {
+            interp(a, c)
+        };
+        corner = a;
+    } else if b == c && a != d {
+        right = if (b == f && a == h) || (b == e && b == d && a != f && a == i) {
+            b
+        } else {
+            interp(a, b)
+        };
+        below = if (c == h && a == f) || (c == g && c == d && a != h && a == i) {
+            c
+        } else {
+            interp(a, c)
+        };
+        corner = b;
+    } else if a == d && b == c {
+        if a == b {
+            right = a;
+            below = a;
+            corner = a;
+        } else {
+            right = interp(a, b);
+            below = interp(a, c);
+            let votes = result(a, b, g, e, 1)
+                + result(b, a, k, f, -1)
+                + result(b, a, h, n, -1)
+                + result(a, b, l, o, 1);
+            corner = match votes {
+                v if v > 0 => a,
+                v if v < 0 => b,
+                _ => mix(&[(a, 1), (b, 1), (c, 1), (d, 1)]),
+            };
+        }
+    } else {
+        corner = mix(&[(a, 1), (b, 1), (c, 1), (d, 1)]);
+        right = if a == c && a == f && b != e && b == j {
+            a
+        } else if b == e && b == d && a != f && a == i {
+            b
+        } else {
+            interp(a, b)
+        };
+        below = if a == b && a == h && g != c && c == m {
+            a
+        } else if c == g && c == d && a != h && a == i {
+            c
+        } else {
+            interp(a, c)
+        };
+    }
+    [a, right, below, corner]
+}
+
+/// Returns the 2x2 block of the pixel at (x, y). It's a reduced HQ2x, each corner looks at its
+/// two sides and the diagonal instead of the 256 patterns of the 3x3 neighbours.
+fn hq_2x(p: &Pixels, x: isize, y: isize) -> [u32; 4] {
+    let w = p.get(x, y);
+    let mut block = [w; 4];
+    for (k, (dx, dy)) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter().enumerate() {
+        let side_x = p.get(x + dx, y);
+        let side_y = p.get(x, y + dy);
+        let diagonal = p.get(x + dx, y + dy);
+        block[k] = if !is_distinct(side_x, side_y) && is_distinct(w, side_x) {
+            // An edge crosses the corner, blend with the other side of it.
+            mix(&[(w, 2), (side_x, 1), (side_y, 1)])
+        } else if is_distinct(w, diagonal) {
+            mix(&[(w, 3), (diagonal, 1)])
+        } else {
+            w
+        };
+    }
+    block
+}
+
+/// The post-processing stage, it keeps the buffer passed to the window.
+pub struct PostProcess {
+    filter: Filter,
+    green_tint: bool,
+    // How much of the previous frame stays, 0.0 turns the blending off.
+    persistence: f32,
+    previous: Vec<u32>,
+    buffer: Vec<u32>,
+}
+
+impl PostProcess {
+    pub fn new(filter: Filter, green_tint: bool) -> Self {
+        Self {
+            filter,
+            green_tint,
+            persistence: 0.0,
+            previous: vec![],
+            buffer: vec![],
+        }
+    }
+
+    pub fn get_filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    pub fn is_green_tint_enabled(&self) -> bool {
+        self.green_tint
+    }
+
+    pub fn set_green_tint(&mut self, enabled: bool) {
+        self.green_tint = enabled;
+    }
+
+    pub fn get_persistence(&self) -> f32 {
+        self.persistence
+    }
+
+    /// Set how much of the previous frame is mixed into the current one, from 0.0 to 1.0.
+    pub fn set_persistence(&mut self, persistence: f32) {
+        self.persistence = persistence.clamp(0.0, 1.0);
+        self.previous.clear();
+    }
+
+    /// Returns the width and the height of the output.
+    pub fn get_size(&self) -> (usize, usize) {
+        let scale = self.filter.get_scale();
+        (SCREEN_W * scale, SCREEN_H * scale)
+    }
+
+    /// Filter the frame, returns the pixels in the 0RGB format line by line.
+    pub fn apply(&mut self, frame: &Frame) -> &[u32] {
+        let mut pixels = to_pixels(frame, self.green_tint);
+        if self.persistence > 0.0 {
+            blend(&mut pixels, &mut self.previous, self.persistence);
+        }
+        if self.filter == Filter::Nearest {
+            self.buffer = pixels.data;
+            return &self.buffer;
+        }
+        let (w, h) = self.get_size();
+        self.buffer.resize(w * h, 0);
+        for y in 0..SCREEN_H {
+            for x in 0..SCREEN_W {
+                let c = pixels.data[y * SCREEN_W + x];
+                let block = match self.filter {
+                    Filter::Sai2x => sai_2x(&pixels, x as isize, y as isize),
+                    Filter::Hq2x => hq_2x(&pixels, x as isize, y as isize),
+                    Filter::Scanline => [c, c, dim(c, 2), dim(c, 2)],
+                    Filter::LcdGrid => [c, dim(c, 3), dim(c, 3), dim(c, 3)],
+                    Filter::Nearest => unreachable!(),
+                };
+                let i = y * 2 * w + x * 2;
+                self.buffer[i] = block[0];
+                self.buffer[i + 1] = block[1];
+                self.buffer[i + w] = block[2];
+                self.buffer[i + w + 1] = block[3];
+            }
+        }
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_frame(color: [u8; 3]) -> Frame {
+        Frame::new(color)
+    }
+
+    #[test]
+    fn test_filter_size() {
+        let frame = new_frame([0x12, 0x34, 0x56]);
+        let mut post = PostProcess::new(Filter::Nearest, false);
+        assert_eq!(post.apply(&frame).len(), SCREEN_W * SCREEN_H);
+        assert_eq!(post.apply(&frame)[0], 0x12_3456);
+        for filter in FILTERS[1..].iter() {
+            post.set_filter(*filter);
+            assert_eq!(post.get_size(), (SCREEN_W * 2, SCREEN_H * 2));
+            assert_eq!(post.apply(&frame).len(), SCREEN_W * SCREEN_H * 4);
+        }
+    }
+
+    #[test]
+    fn test_smoothing_keeps_flat_color() {
+        let frame = new_frame([0x80, 0x40, 0x20]);
+        for filter in [Filter::Sai2x, Filter::Hq2x].iter() {
+            let mut post = PostProcess::new(*filter, false);
+            assert!(post.apply(&frame).iter().all(|c| *c == 0x80_4020));
+        }
+    }
+
+    #[test]
+    fn test_smoothing_blends_edges() {
+        // A white diagonal on black.
+        let mut frame = new_frame([0x00; 3]);
+        for i in 0..SCREEN_H {
+            frame.set_pixel(i, i, [0xff; 3]);
+        }
+        for filter in [Filter::Sai2x, Filter::Hq2x].iter() {
+            let mut post = PostProcess::new(*filter, false);
+            let w = SCREEN_W * 2;
+            let out = post.apply(&frame);
+            // The pixel on the right of the diagonal pixel is between white and black.
+            let right = out[10 * 2 * w + 10 * 2 + 1];
+            assert!(right != 0x00_0000 && right != 0xff_ffff, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_scanline_and_grid() {
+        let frame = new_frame([0xff; 3]);
+        let w = SCREEN_W * 2;
+        let mut post = PostProcess::new(Filter::Scanline, false);
+        let out = post.apply(&frame);
+        assert_eq!((out[0], out[1], out[w]), (0xff_ffff, 0xff_ffff, 0x7f_7f7f));
+        post.set_filter(Filter::LcdGrid);
+        let out = post.apply(&frame);
+        assert_eq!(
+            (out[0], out[1], out[w + 1]),
+            (0xff_ffff, 0xbf_bfbf, 0xbf_bfbf)
+        );
+    }
+
+    #[test]
+    fn test_green_tint() {
+        let mut post = PostProcess::new(Filter::Nearest, true);
+        assert_eq!(post.apply(&new_frame([0xff; 3]))[0], DMG_GREEN[3]);
+        assert_eq!(post.apply(&new_frame([0x00; 3]))[0], DMG_GREEN[0]);
+        assert_eq!(post.apply(&new_frame([0x60; 3]))[0], DMG_GREEN[1]);
+    }
+
+    #[test]
+    fn test_frame_blending() {
+        let (white, black) = (new_frame([0xff; 3]), new_frame([0x00; 3]));
+        let mut post = PostProcess::new(Filter::Nearest, false);
+        post.apply(&white);
+        assert_eq!(post.apply(&black)[0], 0x00_0000);
+
+        post.set_persistence(0.5);
+        assert_eq!(post.apply(&white)[0], 0xff_ffff);
+        assert_eq!(post.apply(&black)[0], 0x7f_7f7f);
+        // The white fades out over the frames.
+        assert_eq!(post.apply(&black)[0], 0x3f_3f3f);
+        // A sprite shown every other frame is half transparent.
+        let mut shown = (0, 0);
+        for _ in 0..4 {
+            shown = (post.apply(&white)[0], post.apply(&black)[0]);
+        }
+        assert_eq!(shown, (0xa9_a9a9, 0x54_5454));
+        post.set_persistence(2.0);
+        assert_eq!(post.get_persistence(), 1.0);
+    }
+
+    #[test]
+    fn test_next_filter() {
+        assert_eq!(Filter::Nearest.next(), Filter::Sai2x);
+        assert_eq!(Filter::LcdGrid.next(), Filter::Nearest);
+        assert_eq!("lcd-grid".parse::<Filter>(), Ok(Filter::LcdGrid));
+        assert!("xbr".parse::<Filter>().is_err());
+    }
+}
@@ -1,5 +1,4 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use super::cpu::IntFlag as Flag;
 use super::cpu::IntReg;
@@ -7,12 +6,36 @@ use super::lcd::LCDControllerRegister;
 use super::lcd::LCDMode;
 use super::lcd::LCDStatusRegister;
 use super::mmu::IOHandler;
-use super::tile::{Attr, GBColor, Palette, TileLine};
-use crate::gameboy::{SCREEN_H, SCREEN_W};
+use super::palette::DmgPalette;
+use super::tile::{Attr, GBColor, Palette, PaletteRam, PixelIndex};
+use crate::gameboy::frontend::Frame;
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+use crate::gameboy::{Accuracy, Term, SCREEN_H, SCREEN_W};
+
+mod fifo;
+mod prohibited;
+mod tile_cache;
+mod viewer;
+
+use self::fifo::PixelFifo;
+use self::tile_cache::TileCache;
+pub use self::fifo::Renderer;
+pub use self::viewer::{VIEWER_H, VIEWER_W};
+
+// The hardware draws at most 10 sprites on a scanline.
+const MAX_SPRITES_PER_LINE: usize = 10;
+// The dots of the line 153 LY reads as 153, it's 0 for the rest of the line and the line 0.
+const LY_153_DOTS: u32 = 4;
+// The dots of the line 144 before the mode 1 and the VBlank interrupt.
+const VBLANK_DELAY_DOTS: u32 = 4;
 
 pub struct GPU {
     updated: bool,
-    data: [[[u8; 3]; SCREEN_W]; SCREEN_H],
+    // The frame being drawn, it's copied into `frame` once finished.
+    data: Frame,
+    frame: Arc<Frame>,
+    // The color number and palette of each pixel in `data`.
+    indices: [[PixelIndex; SCREEN_W]; SCREEN_H],
 
     lcdc: LCDControllerRegister,
     stat: LCDStatusRegister,
@@ -46,9 +69,17 @@ pub struct GPU {
     /// two bits aren't used because sprite data 00 is transparent.
     obj_palette1: u8,
 
-    /// Ram
+    /// The CGB background and sprite palette memory, FF68-FF69 and FF6A-FF6B.
+    /// They can't be accessed while the LCD controller is reading them in mode 3.
+    bg_palette_ram: PaletteRam,
+    obj_palette_ram: PaletteRam,
+
+    /// Ram, the bank 1 is only selectable in CGB mode through FF4F - VBK.
+    /// The bank 1 holds the CGB BG map attributes at the same address of the tile numbers.
     ram: [u8; 0x4000],
     ram_bank: usize,
+    // The tile data of the ram decoded, it's updated by every write.
+    tiles: TileCache,
     // VRAM Sprite Attribute Table (OAM)
     // Gameboy video controller can display up to 40 sprites either in 8x8 or in 8x16 pixels. Because of a limitation of
     // hardware, only ten sprites can be displayed per scan line. Sprite patterns have the same format as BG tiles, but
@@ -79,6 +110,8 @@ pub struct GPU {
     // Bit3   Tile VRAM-Bank  **CGB Mode Only**     (0=Bank 0, 1=Bank 1)
     // Bit2-0 Palette number  **CGB Mode Only**     (OBP0-7)
     oam: [u8; 0xa0],
+    // The sprites on LY selected by the OAM scan, in the drawing priority.
+    line_sprites: Vec<usize>,
 
     prio: [(bool, usize); SCREEN_W],
     // The LCD controller operates on a 222 Hz = 4.194 MHz dot clock. An entire frame is 154 scanlines, 70224 dots, or
@@ -86,14 +119,38 @@ pub struct GPU {
     // Scanlines 144 through 153 are mode 1.
     cycles: u32,
 
-    intf: Rc<RefCell<IntReg>>,
+    intf: Arc<IntReg>,
+    // The colors, the VRAM bank and the BG map attributes are used with Term::GBC.
+    term: Term,
+    // The scanlines are not drawn, the frames are skipped while fast forwarding.
+    skip_rendering: bool,
+    // A HBlank is entered since the last check, the CGB HDMA copies a block in each.
+    hblank: bool,
+    // A VBlank is entered since the last check, the GameShark codes are written in each.
+    vblank: bool,
+    // The window has its own line counter, it only moves on the lines the window is drawn. So
+    // the window hidden for some lines goes on from the row it stops at.
+    window_line: u8,
+    renderer: Renderer,
+    // The quirks of the line boundaries are kept with `Accuracy::Accurate`.
+    accuracy: Accuracy,
+    fifo: PixelFifo,
+    // The colors of the DMG shades.
+    dmg_palette: DmgPalette,
 }
 
 impl GPU {
-    pub fn new(intf: Rc<RefCell<IntReg>>) -> Self {
+    /// Returns the GPU of the DMG.
+    pub fn new(intf: Arc<IntReg>) -> Self {
+        Self::with_term(intf, Term::GB)
+    }
+
+    pub fn with_term(intf: Arc<IntReg>, term: Term) -> Self {
         Self {
             updated: false,
-            data: [[[0xff; 3]; SCREEN_W]; SCREEN_H], // white
+            data: Frame::default(), // white
+            frame: Arc::default(),
+            indices: [[PixelIndex::default(); SCREEN_W]; SCREEN_H],
             lcdc: LCDControllerRegister::new(),
             stat: LCDStatusRegister::new(),
             scroll_y: 0x00,
@@ -105,15 +162,77 @@ impl GPU {
             bg_palette: 0x00,
             obj_palette0: 0x00,
             obj_palette1: 0x01,
+            bg_palette_ram: PaletteRam::new(),
+            obj_palette_ram: PaletteRam::new(),
             ram: [0x00; 0x4000],
             ram_bank: 0x00,
+            tiles: TileCache::new(),
             oam: [0x00; 0xa0],
+            line_sprites: vec![],
             prio: [(true, 0); SCREEN_W],
             cycles: 0,
             intf,
+            term,
+            skip_rendering: false,
+            hblank: false,
+            vblank: false,
+            window_line: 0,
+            renderer: Renderer::Scanline,
+            accuracy: Accuracy::Fast,
+            fifo: PixelFifo::new(),
+            dmg_palette: DmgPalette::default(),
         }
     }
 
+    /// Stop drawing the scanlines, the last drawn frame stays in the buffer.
+    pub fn set_skip_rendering(&mut self, skip: bool) {
+        self.skip_rendering = skip;
+    }
+
+    pub fn get_renderer(&self) -> Renderer {
+        self.renderer
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    pub fn get_accuracy(&self) -> Accuracy {
+        self.accuracy
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.accuracy = accuracy;
+    }
+
+    pub fn get_dmg_palette(&self) -> &DmgPalette {
+        &self.dmg_palette
+    }
+
+    /// Replace the colors of the DMG shades, the pixels drawn from now on use them.
+    pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+        self.dmg_palette = palette;
+    }
+
+    pub fn is_lcd_enabled(&self) -> bool {
+        self.lcdc.is_lcd_enabled()
+    }
+
+    /// Returns true if the GPU renders in CGB mode.
+    pub fn is_cgb(&self) -> bool {
+        self.term == Term::GBC
+    }
+
+    /// Returns true if a HBlank is entered since the last call.
+    pub fn take_hblank(&mut self) -> bool {
+        std::mem::take(&mut self.hblank)
+    }
+
+    /// Returns true if a VBlank is entered since the last call.
+    pub fn take_vblank(&mut self) -> bool {
+        std::mem::take(&mut self.vblank)
+    }
+
     pub fn reset_updated(&mut self) {
         self.updated = false;
     }
@@ -124,11 +243,33 @@ impl GPU {
 
     /// Clear the screen content, Set all White.
     fn clear_screen(&mut self) {
-        self.data = [[[0xff; 3]; SCREEN_W]; SCREEN_H];
+        self.data.fill([0xff; 3]);
+        self.indices = [[PixelIndex::default(); SCREEN_W]; SCREEN_H];
+    }
+
+    /// Returns the frame being drawn, it's finished once `should_updated` is true.
+    pub fn get_data(&self) -> &Frame {
+        &self.data
+    }
+
+    /// Returns the last finished frame, the handle is not touched while the next one is drawn.
+    pub fn get_frame(&self) -> Arc<Frame> {
+        self.frame.clone()
+    }
+
+    // The buffer is reused unless a consumer still holds the previous frame.
+    fn finish_frame(&mut self) {
+        self.updated = true;
+        match Arc::get_mut(&mut self.frame) {
+            Some(frame) => frame.clone_from(&self.data),
+            None => self.frame = Arc::new(self.data.clone()),
+        }
     }
 
-    pub fn get_data(&self) -> [[[u8; 3]; SCREEN_W]; SCREEN_H] {
-        self.data
+    /// Returns the color number and palette of every pixel in the frame, the tests could assert on
+    /// them without caring about the shade of the palette.
+    pub fn debug_frame_indices(&self) -> &[[PixelIndex; SCREEN_W]; SCREEN_H] {
+        &self.indices
     }
 
     /// Read byte from the GPU ram.
@@ -136,6 +277,11 @@ impl GPU {
         self.ram[addr as usize - 0x8000]
     }
 
+    /// Read byte from the GPU ram bank, whichever bank is mapped by VBK.
+    fn read_byte_from_bank(&self, bank: usize, addr: u16) -> u8 {
+        self.ram[bank * 0x2000 + addr as usize - 0x8000]
+    }
+
     /// Get the GB Color.
     fn get_color(&self, palette: Palette, i: usize) -> GBColor {
         let mut v = self.bg_palette;
@@ -165,10 +311,60 @@ impl GPU {
         }
     }
 
+    /// Returns the color of the color number, the `cgb_palette` 0-7 picks the color palette in CGB
+    /// mode and it's ignored otherwise.
+    fn get_rgb(&self, palette: Palette, cgb_palette: u8, color_num: u8) -> [u8; 3] {
+        if !self.is_cgb() {
+            self.dmg_palette
+                .get_rgb(self.get_color(palette, color_num as usize))
+        } else if palette == Palette::BG {
+            self.bg_palette_ram.get_rgb(cgb_palette, color_num)
+        } else {
+            self.obj_palette_ram.get_rgb(cgb_palette, color_num)
+        }
+    }
+
     /// Render the pixel in current scanline.
-    fn render_pixel(&mut self, x: usize, c: GBColor) {
-        let c = c as u8;
-        self.data[self.ly as usize][x] = [c, c, c];
+    fn render_pixel(&mut self, x: usize, palette: Palette, cgb_palette: u8, color_num: u8) {
+        let rgb = self.get_rgb(palette, cgb_palette, color_num);
+        self.data.set_pixel(x, self.ly as usize, rgb);
+        self.indices[self.ly as usize][x] = PixelIndex { color_num, palette };
+    }
+
+    /// Returns true if the palette memory is used by the pixel transfer, it reads 0xff and ignores
+    /// the write then.
+    fn is_palette_ram_locked(&self) -> bool {
+        self.lcdc.is_lcd_enabled() && self.stat.get_mode() == LCDMode::VRAM
+    }
+
+    /// Render the scanline at LY.
+    /// In CGB mode the LCDC bit 0 doesn't hide the bg, it only drops the bg priority.
+    fn render_line(&mut self) {
+        if self.lcdc.bg_display() || self.is_cgb() {
+            self.render_bg();
+        }
+        if self.lcdc.is_sprite_enabled() {
+            self.render_sprite();
+        }
+    }
+
+    /// Render the whole frame again from the current VRAM, OAM and registers, the changes made in
+    /// the middle of the frame are not replayed. It lets the debugger show a poke right away.
+    pub fn rerender(&mut self) {
+        let (ly, window_line) = (self.ly, self.window_line);
+        self.window_line = 0;
+        for line in 0..SCREEN_H {
+            self.ly = line as u8;
+            self.scan_oam();
+            self.render_line();
+            if self.is_window_visible() {
+                self.window_line = self.window_line.wrapping_add(1);
+            }
+        }
+        self.ly = ly;
+        self.window_line = window_line;
+        self.scan_oam();
+        self.finish_frame();
     }
 
     /// Call this method every enter new LCD mode!
@@ -178,29 +374,42 @@ impl GPU {
         match self.stat.get_mode() {
             LCDMode::HBlank => {
                 if self.stat.is_m0_interrupt_enabled() {
-                    self.intf.borrow_mut().req(Flag::LCDStat);
-                }
-                // Render scanline
-                if self.lcdc.bg_display() {
-                    self.render_bg();
+                    self.intf.req(Flag::LCDStat);
                 }
-                if self.lcdc.is_sprite_enabled() {
-                    self.render_sprite();
+                let window_drawn = match self.renderer {
+                    Renderer::Scanline => {
+                        if !self.skip_rendering {
+                            self.render_line();
+                        }
+                        self.is_window_visible()
+                    }
+                    Renderer::Fifo => self.fifo.is_window_drawn(),
+                };
+                if window_drawn {
+                    self.window_line = self.window_line.wrapping_add(1);
                 }
+                self.hblank = true;
             }
             LCDMode::VBlank => {
-                self.updated = true;
-                self.intf.borrow_mut().req(Flag::VBlank);
+                self.window_line = 0;
+                self.finish_frame();
+                self.vblank = true;
+                self.intf.req(Flag::VBlank);
                 if self.stat.is_m1_interrupt_enabled() {
-                    self.intf.borrow_mut().req(Flag::LCDStat);
+                    self.intf.req(Flag::LCDStat);
                 }
             }
             LCDMode::OAM => {
                 if self.stat.is_m2_interrupt_enabled() {
-                    self.intf.borrow_mut().req(Flag::LCDStat);
+                    self.intf.req(Flag::LCDStat);
+                }
+                self.scan_oam();
+            }
+            LCDMode::VRAM => {
+                if self.renderer == Renderer::Fifo {
+                    self.start_fifo();
                 }
             }
-            LCDMode::VRAM => {} // do nothing!
         }
     }
 
@@ -215,11 +424,16 @@ impl GPU {
     // Mode 3  _33____33____33____33____33____33__________________3___
     // Mode 0  ___000___000___000___000___000___000________________000
     // Mode 1  ____________________________________11111111111111_____
+    //
+    // The accurate timing follows the hardware at the line boundaries: LY reads as 0 for most of
+    // the line 153 and LYC=0 matches there, the mode 1 starts a few dots into the line 144, and
+    // the mode 2 STAT interrupt is requested at the start of the line 144 as well.
     pub fn next(&mut self, cycles: u32) {
         if !self.lcdc.is_lcd_enabled() {
             return;
         }
 
+        let quirks = self.accuracy == Accuracy::Accurate;
         let mut remaining_cycles = cycles;
 
         while remaining_cycles > 0 {
@@ -228,21 +442,35 @@ impl GPU {
             } else {
                 remaining_cycles
             };
+            let start = self.cycles;
             self.cycles += current_cycles;
             remaining_cycles -= current_cycles;
 
             // Full line takes 114 ticks
-            if self.cycles >= 456 {
+            let new_line = self.cycles >= 456;
+            if new_line {
                 self.cycles -= 456;
                 self.ly = (self.ly + 1) % 154;
-                if self.stat.is_ly_interrupt_enabled() && self.ly == self.lc {
-                    self.intf.borrow_mut().req(Flag::LCDStat);
+                // The line 0 is compared during the line 153 already.
+                let compared = quirks && self.ly == 0;
+                if self.stat.is_ly_interrupt_enabled() && self.ly == self.lc && !compared {
+                    self.intf.req(Flag::LCDStat);
                 }
-                // This is a VBlank line
-                if self.ly >= 144 && self.stat.get_mode() != LCDMode::VBlank {
-                    self.change_mode(LCDMode::VBlank);
+                if quirks && self.ly == 144 && self.stat.is_m2_interrupt_enabled() {
+                    self.intf.req(Flag::LCDStat);
                 }
             }
+            // LY reads as 0 from this step on, so LYC=0 matches.
+            let zeroed = self.cycles >= LY_153_DOTS && (new_line || start < LY_153_DOTS);
+            let lyc_zero = self.stat.is_ly_interrupt_enabled() && self.lc == 0;
+            if quirks && self.ly == 153 && zeroed && lyc_zero {
+                self.intf.req(Flag::LCDStat);
+            }
+            // This is a VBlank line
+            let delayed = quirks && self.ly == 144 && self.cycles < VBLANK_DELAY_DOTS;
+            if self.ly >= 144 && self.stat.get_mode() != LCDMode::VBlank && !delayed {
+                self.change_mode(LCDMode::VBlank);
+            }
 
             // This is a normal line
             if self.ly < 144 {
@@ -250,6 +478,20 @@ impl GPU {
                     if self.stat.get_mode() != LCDMode::OAM {
                         self.change_mode(LCDMode::OAM);
                     }
+                } else if self.renderer == Renderer::Fifo {
+                    // The mode 3 lasts until the pixels of the line are out.
+                    let dots = if self.stat.get_mode() == LCDMode::OAM {
+                        self.change_mode(LCDMode::VRAM);
+                        self.cycles - 80
+                    } else {
+                        current_cycles
+                    };
+                    if self.stat.get_mode() == LCDMode::VRAM {
+                        self.run_fifo(dots);
+                        if self.fifo.is_done() {
+                            self.change_mode(LCDMode::HBlank);
+                        }
+                    }
                 } else if self.cycles <= (80 + 172) {
                     // 252 cycles
                     if self.stat.get_mode() != LCDMode::VRAM {
@@ -265,6 +507,16 @@ impl GPU {
         }
     }
 
+    /// Returns LY as the CPU reads it.
+    fn get_ly(&self) -> u8 {
+        let quirks = self.accuracy == Accuracy::Accurate;
+        if quirks && self.ly == 153 && self.cycles >= LY_153_DOTS {
+            0
+        } else {
+            self.ly
+        }
+    }
+
     /// Returns true if we should render window instead of the bg.
     fn using_window(&self) -> bool {
         if self.lcdc.is_window_enabled() {
@@ -278,36 +530,28 @@ impl GPU {
         false
     }
 
-    /// Get the windwo poistion, the result'x axios will minus 7.
-    fn get_window_topleft_position(&self) -> (u8, u8) {
-        (self.window_x.wrapping_sub(7), self.window_y)
+    /// Returns true if the window is drawn on the current scanline.
+    fn is_window_visible(&self) -> bool {
+        self.using_window() && self.window_x <= 166
+    }
+
+    /// Returns true if the pixel of the current scanline is in the window, it starts at WX - 7.
+    fn is_window_pixel(&self, x: u8) -> bool {
+        self.using_window() && u16::from(x) + 7 >= u16::from(self.window_x)
     }
 
     /// Get the tile position.
     fn get_tile_position(&self, line_offset: u8) -> (u8, u8) {
-        let (window_x, window_y) = self.get_window_topleft_position();
-
-        // yPos is used to calculate which of 32 vertical tiles the
-        // current scanline is drawing
-        let pos_y = if self.using_window() {
-            // self.ly + self.scroll_y - (self.scroll_y + self.window_y)
-            // 位于 window 中的偏移
-            self.ly.wrapping_sub(window_y)
+        if self.is_window_pixel(line_offset) {
+            // 位于 window 中的偏移, the row comes from the window line counter.
+            (line_offset + 7 - self.window_x, self.window_line)
         } else {
-            // TODO
-            // self.scroll_y.wrapping_add(self.ly)
             // 位于 bg 的偏移
-            self.ly.wrapping_add(self.scroll_y)
-        };
-
-        let mut pos_x = self.scroll_x.wrapping_add(line_offset);
-        if self.using_window() {
-            if line_offset >= window_x {
-                pos_x = line_offset - window_x;
-            }
+            (
+                self.scroll_x.wrapping_add(line_offset),
+                self.ly.wrapping_add(self.scroll_y),
+            )
         }
-
-        (pos_x, pos_y)
     }
 
     /// Find the tile data address.
@@ -338,16 +582,44 @@ impl GPU {
         tile_data_addr
     }
 
-    /// Render bg or the window.
-    fn render_bg(&mut self) {
-        let (window_x, _) = self.get_window_topleft_position();
+    /// Returns the color numbers of the bg or the window tile at the row of the tile, with the
+    /// CGB attributes.
+    fn fetch_bg_tile_line(&self, base_addr: u16, row: u16, col: u16, y: u8) -> ([u8; 8], u8) {
+        // lookup up the tile_data num and return the actual address of tile data.
+        let tile_data_addr = self.find_tile_data_addr(base_addr, row, col);
+
+        // The CGB attributes of the tile are at the same address in the bank 1.
+        let attr = if self.is_cgb() {
+            self.read_byte_from_bank(1, base_addr + row * 32 + col)
+        } else {
+            0x00
+        };
+        let tile_attr = Attr::from(attr);
+
+        // find the correct vertical line we're on of the
+        // tile to get the tile data
+        // from in memory
+        let line_in_tile = if tile_attr.has_yflip() { 7 - y } else { y };
+
+        // each line takes up two bytes of memory
+        let bank = tile_attr.get_bank();
+        let addr = tile_data_addr + u16::from(line_in_tile * 2);
+        (*self.tiles.get_row(bank, addr), attr)
+    }
 
+    /// Render bg or the window. The 8 pixels of a tile share its tile map entry, attributes and
+    /// tile row, so they're only looked up when the scanline enters the next tile.
+    fn render_bg(&mut self) {
         // 口袋妖怪红，尼多朗会先跳出来
         // let bg_base = if using_window {
         //     self.lcdc.window_tilemap_addr()
         // } else {
         //     self.lcdc.bg_tilemap_addr()
         // };
+        let bg_display = self.lcdc.bg_display();
+        // The tile map address and the row of the last fetched tile, the bg and the window may
+        // show different rows of the same entry.
+        let mut fetched: Option<(u16, u8, [u8; 8], u8)> = None;
 
         for pixel in 0..SCREEN_W {
             let pixel = pixel as u8;
@@ -359,35 +631,58 @@ impl GPU {
             // 一个 tile 8 * 8 个像素
             let tile_row = u16::from(pox_y / 8);
             let tile_col = u16::from(pos_x / 8);
+            let y = pox_y % 8;
 
             // Background memory base addr.
-            let bg_base_addr = if self.using_window() && pixel >= window_x {
+            let bg_base_addr = if self.is_window_pixel(pixel) {
                 self.lcdc.get_window_tilemap_addr()
             } else {
                 self.lcdc.get_bg_tilemap_addr()
             };
+            let tile_map_addr = bg_base_addr + tile_row * 32 + tile_col;
+            let (tile_line, attr) = match fetched {
+                Some((a, row, line, attr)) if a == tile_map_addr && row == y => (line, attr),
+                _ => self.fetch_bg_tile_line(bg_base_addr, tile_row, tile_col, y),
+            };
+            fetched = Some((tile_map_addr, y, tile_line, attr));
+            let tile_attr = Attr::from(attr);
 
-            // lookup up the tile_data num and return the actual address of tile data.
-            let tile_data_addr = self.find_tile_data_addr(bg_base_addr, tile_row, tile_col);
-
-            // find the correct vertical line we're on of the
-            // tile to get the tile data
-            // from in memory
-            let line_in_tile = pox_y % 8;
-
-            // each line takes up two bytes of memory
-            let data_1 = self.read_byte_from_ram(tile_data_addr + u16::from(line_in_tile * 2));
-            let data_2 = self.read_byte_from_ram(tile_data_addr + u16::from(line_in_tile * 2) + 1);
-            // tile_y_data = [data_1, data_2];
-            let tile_line = TileLine::new([data_1, data_2]);
+            let color_bit = if tile_attr.has_xflip() {
+                7 - pos_x % 8
+            } else {
+                pos_x % 8
+            };
+            let color_num = tile_line[color_bit as usize];
 
-            let color_bit = pos_x % 8;
-            let color_num = tile_line.get_color_num(color_bit);
+            // The sprites are always above the bg if the CGB bg priority is off.
+            self.prio[pixel as usize] = if bg_display {
+                (tile_attr.get_priority(), color_num as usize)
+            } else {
+                (false, 0)
+            };
+            let cgb_palette = tile_attr.get_cgb_palette();
+            self.render_pixel(pixel as usize, Palette::BG, cgb_palette, color_num);
+        }
+    }
 
-            self.prio[pixel as usize] = (false, color_num as usize);
-            let color = self.get_color(Palette::BG, color_num as usize);
-            self.render_pixel(pixel as usize, color);
+    /// The OAM scan of the mode 2, it selects the first 10 sprites on LY in the OAM order, the
+    /// sprites out of the screen horizontally count too. The DMG draws the sprite with the
+    /// smaller X above the others, then the one earlier in the OAM. The CGB only follows the OAM.
+    fn scan_oam(&mut self) {
+        let (_, sprite_y_size) = self.lcdc.get_sprite_size();
+        let line = u16::from(self.ly) + 16;
+        let mut sprites: Vec<usize> = (0..40)
+            .filter(|i| {
+                let pos_y = u16::from(self.oam[i * 4]);
+                line >= pos_y && line < pos_y + u16::from(sprite_y_size)
+            })
+            .take(MAX_SPRITES_PER_LINE)
+            .collect();
+        if !self.is_cgb() {
+            // The sort is stable, so the OAM order is kept for the same X.
+            sprites.sort_by_key(|i| self.oam[i * 4 + 1]);
         }
+        self.line_sprites = sprites;
     }
 
     /// Gameboy video controller can display up to 40 sprites either in 8x8 or in 8x16 pixels. Because of a limitation
@@ -419,73 +714,69 @@ impl GPU {
     ///     Bit4   Palette number  **Non CGB Mode Only** (0=OBP0, 1=OBP1)
     ///     Bit3   Tile VRAM-Bank  **CGB Mode Only**     (0=Bank 0, 1=Bank 1)
     ///     Bit2-0 Palette number  **CGB Mode Only**     (OBP0-7)
-    fn render_sprite(&mut self) {
+    /// Read the row of the sprite on LY, returns it with the attributes.
+    fn fetch_sprite_line(&self, i: usize) -> ([u8; 8], u8) {
         // Sprite tile size 8x8 or 8x16(2 stacked vertically).
         let (_, sprite_y_size) = self.lcdc.get_sprite_size();
-        for i in 0..40 {
-            //  sprite occupies 4 bytes in the sprite attributes table
-            let index = (i as u16) * 4;
-            let sprite_addr = 0xfe00 + index;
+        //  sprite occupies 4 bytes in the sprite attributes table
+        let sprite_addr = 0xfe00 + (i as u16) * 4;
+
+        // 0: Sprite Y Position: Position of the sprite on the Y axis of the viewing display minus 16
+        let pos_y = self.read_byte(sprite_addr).wrapping_sub(16);
+        // In 8x16 mode, the lower bit of the tile number is ignored.
+        let tile_number = if sprite_y_size == 16 {
+            self.read_byte(sprite_addr + 2) & 0xfe
+        } else {
+            self.read_byte(sprite_addr + 2)
+        };
+        let attr = self.read_byte(sprite_addr + 3);
+        let tile_attr = Attr::from(attr);
 
-            // 0: Sprite Y Position: Position of the sprite on the Y axis of the viewing display minus 16
-            // 1: Sprite X Position: Position of the sprite on the X axis of the viewing display minus 8
-            let pos_y = self.read_byte(sprite_addr).wrapping_sub(16);
-            let pox_x = self.read_byte(sprite_addr + 1).wrapping_sub(8);
-            let tile_number = self.read_byte(sprite_addr + 2);
-            let tile_attr = Attr::from(self.read_byte(sprite_addr + 3));
-
-            // if !(self.ly > pos_y && self.ly < pos_y.wrapping_add(sprite_y_size)) {
-            //     continue;
-            // }
-
-            // & if self.lcdc.get_sprite_size() == 16 {
-            //     0xfe
-            // } else {
-            //     0xff
-            // };
-
-            if pos_y <= 0xff - sprite_y_size + 1 {
-                if self.ly < pos_y || self.ly > pos_y + sprite_y_size - 1 {
-                    continue;
-                }
-            } else {
-                if self.ly > pos_y.wrapping_add(sprite_y_size) - 1 {
-                    continue;
-                }
-            }
+        let line_in_tile = if tile_attr.has_yflip() {
+            sprite_y_size - 1 - self.ly.wrapping_sub(pos_y)
+        } else {
+            self.ly.wrapping_sub(pos_y)
+        };
+
+        let tile_data_addr = 0x8000u16 + u16::from(tile_number) * 16 + u16::from(line_in_tile) * 2;
 
+        let bank = if self.is_cgb() {
+            tile_attr.get_bank()
+        } else {
+            0
+        };
+        (*self.tiles.get_row(bank, tile_data_addr), attr)
+    }
+
+    fn render_sprite(&mut self) {
+        // The pixels of a sprite with a higher priority, a sprite behind the bg still hides the
+        // lower ones.
+        let mut drawn = [false; SCREEN_W];
+        for i in self.line_sprites.clone() {
+            // 1: Sprite X Position: Position of the sprite on the X axis of the viewing display minus 8
+            let pox_x = self.read_byte(0xfe00 + (i as u16) * 4 + 1).wrapping_sub(8);
             if pox_x >= (SCREEN_W as u8) && pox_x <= (0xff - 7) {
                 continue;
             }
-
-            let line_in_tile = if tile_attr.has_yflip() {
-                sprite_y_size - 1 - self.ly.wrapping_sub(pos_y)
-            } else {
-                self.ly.wrapping_sub(pos_y)
-            };
-
-            let tile_data_addr =
-                0x8000u16 + u16::from(tile_number) * 16 + u16::from(line_in_tile) * 2;
-
-            let data_1 = self.read_byte_from_ram(tile_data_addr);
-            let data_2 = self.read_byte_from_ram(tile_data_addr + 1);
-
-            let tile_line = TileLine::new([data_1, data_2]);
+            let (tile_line, attr) = self.fetch_sprite_line(i);
+            let tile_attr = Attr::from(attr);
 
             // its easier to read in from right to left as pixel 0 is
             // bit 7 in the colour data, pixel 1 is bit 6 etc...
             for x in 0..8 {
-                if pox_x.wrapping_add(x) >= (SCREEN_W as u8) {
+                let pixel_x = pox_x.wrapping_add(x) as usize;
+                if pixel_x >= SCREEN_W {
                     continue;
                 }
                 let tile_x = if tile_attr.has_xflip() { 7 - x } else { x };
-                let color_num = tile_line.get_color_num(tile_x);
-                if color_num == 0 {
+                let color_num = tile_line[tile_x as usize];
+                if color_num == 0 || drawn[pixel_x] {
                     continue;
                 }
+                drawn[pixel_x] = true;
 
                 // Confirm the priority of background and sprite.
-                let prio = self.prio[pox_x.wrapping_add(x) as usize];
+                let prio = self.prio[pixel_x];
                 let skip = if prio.0 {
                     prio.1 != 0
                 } else {
@@ -495,12 +786,74 @@ impl GPU {
                     continue;
                 }
 
-                let palette = tile_attr.get_palette();
+                // The CGB sprites are recorded as OBP0, their color palette is in bit 2-0.
+                let palette = if self.is_cgb() {
+                    Palette::OBP0
+                } else {
+                    tile_attr.get_palette()
+                };
+                let cgb_palette = tile_attr.get_cgb_palette();
+                self.render_pixel(pixel_x, palette, cgb_palette, color_num);
+            }
+        }
+    }
+}
+
+/// The frame buffer is not saved, it's rendered again from the restored VRAM.
+impl Stateful for GPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u8(self.lcdc.get_value());
+        w.put_u8(self.stat.get_value());
+        w.put_u8(self.scroll_y);
+        w.put_u8(self.scroll_x);
+        w.put_u8(self.window_y);
+        w.put_u8(self.window_x);
+        w.put_u8(self.ly);
+        w.put_u8(self.lc);
+        w.put_u8(self.bg_palette);
+        w.put_u8(self.obj_palette0);
+        w.put_u8(self.obj_palette1);
+        self.bg_palette_ram.save_state(w);
+        self.obj_palette_ram.save_state(w);
+        w.put_u8(self.ram_bank as u8);
+        w.put_bytes(&self.ram);
+        w.put_bytes(&self.oam);
+        w.put_u32(self.cycles);
+        w.put_u8(self.window_line);
+    }
 
-                let color = self.get_color(palette, color_num as usize);
-                self.render_pixel(pox_x.wrapping_add(x) as usize, color);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.lcdc.set_value(r.get_u8()?);
+        self.stat.set_value(r.get_u8()?);
+        self.scroll_y = r.get_u8()?;
+        self.scroll_x = r.get_u8()?;
+        self.window_y = r.get_u8()?;
+        self.window_x = r.get_u8()?;
+        self.ly = r.get_u8()?;
+        self.lc = r.get_u8()?;
+        self.bg_palette = r.get_u8()?;
+        self.obj_palette0 = r.get_u8()?;
+        self.obj_palette1 = r.get_u8()?;
+        self.bg_palette_ram.load_state(r)?;
+        self.obj_palette_ram.load_state(r)?;
+        self.ram_bank = usize::from(r.get_u8()? & 0x01);
+        r.fill(&mut self.ram)?;
+        self.tiles.rebuild(&self.ram);
+        r.fill(&mut self.oam)?;
+        self.cycles = r.get_u32()?;
+        self.window_line = r.get_u8()?;
+        if self.lcdc.is_lcd_enabled() {
+            self.rerender();
+            // The FIFO is not saved, the line is drawn again.
+            if self.renderer == Renderer::Fifo && self.stat.get_mode() == LCDMode::VRAM {
+                self.start_fifo();
+                self.run_fifo(self.cycles.saturating_sub(80));
             }
+        } else {
+            self.clear_screen();
+            self.finish_frame();
         }
+        Ok(())
     }
 }
 
@@ -513,13 +866,22 @@ impl IOHandler for GPU {
             0xff41 => self.stat.get_value(),
             0xff42 => self.scroll_y,
             0xff43 => self.scroll_x,
-            0xff44 => self.ly,
+            0xff44 => self.get_ly(),
             0xff45 => self.lc,
             0xff47 => self.bg_palette,
             0xff48 => self.obj_palette0,
             0xff49 => self.obj_palette1,
             0xff4a => self.window_y,
             0xff4b => self.window_x,
+            // FF4F - VBK - CGB Mode Only - VRAM Bank, bit 7-1 read as 1.
+            0xff4f if self.is_cgb() => 0xfe | self.ram_bank as u8,
+            0xff4f => 0xff,
+            0xff68 => self.bg_palette_ram.get_spec(),
+            0xff69 if self.is_palette_ram_locked() => 0xff,
+            0xff69 => self.bg_palette_ram.get_data(),
+            0xff6a => self.obj_palette_ram.get_spec(),
+            0xff6b if self.is_palette_ram_locked() => 0xff,
+            0xff6b => self.obj_palette_ram.get_data(),
             _ => unreachable!(
                 "GPU should not handle the {:0x} address read operation",
                 addr
@@ -529,16 +891,21 @@ impl IOHandler for GPU {
 
     fn write_byte(&mut self, addr: u16, val: u8) {
         match addr {
-            0x8000..=0x9fff => self.ram[self.ram_bank * 0x2000 + addr as usize - 0x8000] = val,
+            0x8000..=0x9fff => {
+                let i = self.ram_bank * 0x2000 + addr as usize - 0x8000;
+                self.ram[i] = val;
+                self.tiles.update(&self.ram, i);
+            }
             0xfe00..=0xfe9f => self.oam[addr as usize - 0xfe00] = val,
             0xff40 => {
                 self.lcdc.set_value(val);
                 if !self.lcdc.is_lcd_enabled() {
                     self.cycles = 0;
                     self.ly = 0;
+                    self.window_line = 0;
                     self.stat.set_mode(LCDMode::HBlank);
                     self.clear_screen();
-                    self.updated = true;
+                    self.finish_frame();
                 }
             }
             0xff41 => {
@@ -548,7 +915,7 @@ impl IOHandler for GPU {
                     self.stat.disable_ly_interrupt();
                 }
 
-                if 0x20 != 0x00 {
+                if val & 0x20 != 0x00 {
                     self.stat.enable_m2_interrupt();
                 } else {
                     self.stat.disable_m2_interrupt();
@@ -573,6 +940,18 @@ impl IOHandler for GPU {
             0xff49 => self.obj_palette1 = val,
             0xff4a => self.window_y = val,
             0xff4b => self.window_x = val,
+            0xff4f if self.is_cgb() => self.ram_bank = usize::from(val & 0x01),
+            0xff4f => {}
+            0xff68 => self.bg_palette_ram.set_spec(val),
+            0xff69 => {
+                let locked = self.is_palette_ram_locked();
+                self.bg_palette_ram.set_data(val, locked);
+            }
+            0xff6a => self.obj_palette_ram.set_spec(val),
+            0xff6b => {
+                let locked = self.is_palette_ram_locked();
+                self.obj_palette_ram.set_data(val, locked);
+            }
             _ => panic!(
                 "GPU should not handle the {:0x} address write operation, value is {:0x}",
                 addr, val
@@ -0,0 +1,321 @@
+//! The pixel FIFO renderer, it draws the scanline dot by dot in the mode 3 like the hardware, so
+//! the SCX, WX, palette and LCDC writes in the middle of a line show up from that pixel on.
+//!
+//! The background fetcher reads a tile row in 6 dots (tile number, data low, data high) and
+//! pushes 8 pixels once the FIFO is empty, one pixel is shifted out every dot. The first fetch of
+//! the line is thrown away and SCX % 8 pixels are dropped, so the mode 3 takes 172 dots at least
+//! and ends when the 160th pixel is out. The sprites are fetched at once when their X is reached,
+//! their penalty dots are not emulated.
+//! See: https://gbdev.io/pandocs/pixel_fifo.html
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use super::GPU;
+use crate::gameboy::graphics::tile::{Attr, Palette, TileLine};
+use crate::gameboy::SCREEN_W;
+
+/// How the GPU draws the scanlines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Renderer {
+    /// Draw the whole line at the HBlank, it's fast.
+    Scanline,
+    /// Draw the pixels with the timing of the hardware.
+    Fifo,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanline" => Ok(Self::Scanline),
+            "fifo" => Ok(Self::Fifo),
+            _ => Err(format!("unknown renderer {}", s)),
+        }
+    }
+}
+
+// The dots of the first fetch of the line which is thrown away.
+const FIRST_FETCH_DOTS: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BgPixel {
+    color_num: u8,
+    // The CGB BG map attributes.
+    attr: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ObjPixel {
+    color_num: u8,
+    attr: u8,
+    // The OAM index, the lower one is above on the CGB.
+    index: usize,
+}
+
+pub struct PixelFifo {
+    bg: VecDeque<BgPixel>,
+    // The sprite pixels are aligned with the pixels to shift out.
+    obj: VecDeque<ObjPixel>,
+    step: Step,
+    // The dots spent in the step, each step but the push takes 2.
+    dots: u8,
+    // The tile column of the next fetch, counted from SCX or the left of the window.
+    fetch_x: u8,
+    tile_addr: u16,
+    tile_attr: u8,
+    data: [u8; 2],
+    // The pixels shifted out.
+    lx: u8,
+    // The pixels to drop before the first one is shown.
+    discard: u8,
+    stall: u8,
+    in_window: bool,
+    window_drawn: bool,
+    // The sprites of `line_sprites` fetched on this line, by bit.
+    fetched: u16,
+    done: bool,
+}
+
+impl PixelFifo {
+    pub fn new() -> Self {
+        Self {
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+            step: Step::Tile,
+            dots: 0,
+            fetch_x: 0,
+            tile_addr: 0,
+            tile_attr: 0,
+            data: [0x00; 2],
+            lx: 0,
+            discard: 0,
+            stall: 0,
+            in_window: false,
+            window_drawn: false,
+            fetched: 0,
+            done: true,
+        }
+    }
+
+    /// Start the mode 3 of a line, SCX % 8 is latched here.
+    fn start(&mut self, scroll_x: u8) {
+        self.bg.clear();
+        self.obj.clear();
+        self.step = Step::Tile;
+        self.dots = 0;
+        self.fetch_x = 0;
+        self.lx = 0;
+        self.discard = scroll_x % 8;
+        self.stall = FIRST_FETCH_DOTS;
+        self.in_window = false;
+        self.window_drawn = false;
+        self.fetched = 0;
+        self.done = false;
+    }
+
+    /// Returns true if the window is drawn on the line.
+    pub fn is_window_drawn(&self) -> bool {
+        self.window_drawn
+    }
+
+    /// Returns true if the 160 pixels of the line are out.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+impl Default for PixelFifo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GPU {
+    pub(super) fn start_fifo(&mut self) {
+        self.fifo.start(self.scroll_x);
+    }
+
+    /// Run the dots of the mode 3.
+    pub(super) fn run_fifo(&mut self, dots: u32) {
+        for _ in 0..dots {
+            if self.fifo.is_done() {
+                return;
+            }
+            self.fifo_dot();
+        }
+    }
+
+    fn fifo_dot(&mut self) {
+        if self.fifo.stall > 0 {
+            self.fifo.stall -= 1;
+            return;
+        }
+        // The window starts at the pixel WX - 7, the fetcher restarts from its left.
+        if !self.fifo.in_window && self.is_window_pixel(self.fifo.lx) {
+            if self.fifo.lx == 0 && self.window_x < 7 {
+                self.fifo.discard = 7 - self.window_x;
+            }
+            self.fifo.in_window = true;
+            self.fifo.window_drawn = true;
+            self.fifo.bg.clear();
+            self.fifo.step = Step::Tile;
+            self.fifo.dots = 0;
+            self.fifo.fetch_x = 0;
+        }
+        self.fetcher_dot();
+
+        if self.fifo.bg.is_empty() {
+            return;
+        }
+        if self.fifo.discard > 0 {
+            self.fifo.bg.pop_front();
+            self.fifo.discard -= 1;
+            return;
+        }
+        self.fetch_sprites();
+        let bg = self.fifo.bg.pop_front().unwrap();
+        let obj = self.fifo.obj.pop_front().unwrap_or_default();
+        if !self.skip_rendering {
+            self.mix_pixel(bg, obj);
+        }
+        self.fifo.lx += 1;
+        if usize::from(self.fifo.lx) == SCREEN_W {
+            self.fifo.done = true;
+        }
+    }
+
+    fn fetcher_dot(&mut self) {
+        if self.fifo.step != Step::Push {
+            self.fifo.dots += 1;
+            if self.fifo.dots < 2 {
+                return;
+            }
+            self.fifo.dots = 0;
+        }
+        // The row in the 256x256 map and the tile column.
+        let (line, col, base_addr) = if self.fifo.in_window {
+            let base_addr = self.lcdc.get_window_tilemap_addr();
+            (self.window_line, self.fifo.fetch_x & 0x1f, base_addr)
+        } else {
+            let line = self.ly.wrapping_add(self.scroll_y);
+            let col = (self.scroll_x / 8).wrapping_add(self.fifo.fetch_x) & 0x1f;
+            (line, col, self.lcdc.get_bg_tilemap_addr())
+        };
+        let (row, col) = (u16::from(line / 8), u16::from(col));
+        match self.fifo.step {
+            Step::Tile => {
+                self.fifo.tile_addr = self.find_tile_data_addr(base_addr, row, col);
+                self.fifo.tile_attr = if self.is_cgb() {
+                    self.read_byte_from_bank(1, base_addr + row * 32 + col)
+                } else {
+                    0x00
+                };
+                self.fifo.step = Step::DataLow;
+            }
+            Step::DataLow | Step::DataHigh => {
+                let attr = Attr::from(self.fifo.tile_attr);
+                let line_in_tile = if attr.has_yflip() {
+                    7 - line % 8
+                } else {
+                    line % 8
+                };
+                let addr = self.fifo.tile_addr + u16::from(line_in_tile) * 2;
+                if self.fifo.step == Step::DataLow {
+                    self.fifo.data[0] = self.read_byte_from_bank(attr.get_bank(), addr);
+                    self.fifo.step = Step::DataHigh;
+                } else {
+                    self.fifo.data[1] = self.read_byte_from_bank(attr.get_bank(), addr + 1);
+                    self.fifo.step = Step::Push;
+                }
+            }
+            Step::Push => {
+                if !self.fifo.bg.is_empty() {
+                    return;
+                }
+                let attr = Attr::from(self.fifo.tile_attr);
+                let tile_line = TileLine::new(self.fifo.data);
+                for x in 0..8 {
+                    let bit = if attr.has_xflip() { 7 - x } else { x };
+                    self.fifo.bg.push_back(BgPixel {
+                        color_num: tile_line.get_color_num(bit),
+                        attr: self.fifo.tile_attr,
+                    });
+                }
+                self.fifo.fetch_x = self.fifo.fetch_x.wrapping_add(1);
+                self.fifo.step = Step::Tile;
+            }
+        }
+    }
+
+    /// Merge the sprites starting at this pixel into the sprite FIFO. A pixel already there is
+    /// kept unless it's transparent, or the new sprite is earlier in the OAM on the CGB.
+    fn fetch_sprites(&mut self) {
+        for (k, i) in self.line_sprites.clone().into_iter().enumerate() {
+            if self.fifo.fetched & (1 << k) != 0 {
+                continue;
+            }
+            let pos_x = i16::from(self.oam[i * 4 + 1]) - 8;
+            if pos_x.max(0) != i16::from(self.fifo.lx) {
+                continue;
+            }
+            self.fifo.fetched |= 1 << k;
+            // The part on the left of the screen is dropped.
+            let skip = (i16::from(self.fifo.lx) - pos_x) as u8;
+            let (tile_line, attr) = self.fetch_sprite_line(i);
+            let xflip = Attr::from(attr).has_xflip();
+            let cgb = self.is_cgb();
+            while self.fifo.obj.len() < 8 {
+                self.fifo.obj.push_back(ObjPixel::default());
+            }
+            for x in skip..8 {
+                let color_num = tile_line[usize::from(if xflip { 7 - x } else { x })];
+                let slot = &mut self.fifo.obj[usize::from(x - skip)];
+                if color_num != 0 && (slot.color_num == 0 || (cgb && i < slot.index)) {
+                    *slot = ObjPixel {
+                        color_num,
+                        attr,
+                        index: i,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Mix the bg and the sprite pixel with the same rules as the scanline renderer.
+    fn mix_pixel(&mut self, bg: BgPixel, obj: ObjPixel) {
+        let x = usize::from(self.fifo.lx);
+        let bg_attr = Attr::from(bg.attr);
+        // The DMG bg is blank without LCDC bit 0, the CGB one only drops the priority.
+        let bg_color = if self.lcdc.bg_display() || self.is_cgb() {
+            bg.color_num
+        } else {
+            0
+        };
+        let (bg_priority, bg_opaque) = if self.lcdc.bg_display() {
+            (bg_attr.get_priority(), bg_color != 0)
+        } else {
+            (false, false)
+        };
+        let obj_attr = Attr::from(obj.attr);
+        let obj_hidden = bg_opaque && (bg_priority || obj_attr.get_priority());
+        if obj.color_num != 0 && self.lcdc.is_sprite_enabled() && !obj_hidden {
+            let palette = if self.is_cgb() {
+                Palette::OBP0
+            } else {
+                obj_attr.get_palette()
+            };
+            self.render_pixel(x, palette, obj_attr.get_cgb_palette(), obj.color_num);
+        } else {
+            self.render_pixel(x, Palette::BG, bg_attr.get_cgb_palette(), bg_color);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! The prohibited area FEA0-FEFF after the OAM. It reads FF while the LCD controller holds the
+//! OAM in the mode 2 and 3, and otherwise it depends on the hardware:
+//!
+//! - the DMG reads 00, but any access in the mode 2 corrupts the OAM row being scanned,
+//! - the CGB (revision E) reads the high nibble of the low address byte twice, e.g. FEAx is AA.
+//!
+//! The writes are ignored, apart from the corruption.
+//! See: https://gbdev.io/pandocs/Memory_Map.html#fea0feff-range
+//!      https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+use super::GPU;
+use crate::gameboy::graphics::lcd::LCDMode;
+
+// The OAM scan reads a row of 8 bytes, 2 sprites, every 4 dots.
+const ROW_SIZE: usize = 8;
+const ROWS: usize = 0xa0 / ROW_SIZE;
+
+impl GPU {
+    /// Returns true if the LCD controller holds the OAM, the cpu can't reach it.
+    pub fn is_oam_locked(&self) -> bool {
+        self.lcdc.is_lcd_enabled() && matches!(self.stat.get_mode(), LCDMode::OAM | LCDMode::VRAM)
+    }
+
+    pub fn read_prohibited(&mut self, addr: u16) -> u8 {
+        if self.is_oam_locked() {
+            self.corrupt_oam(|a, b, c| b | (a & c));
+            0xff
+        } else if self.is_cgb() {
+            let nibble = (addr as u8 >> 4) & 0x0f;
+            nibble << 4 | nibble
+        } else {
+            0x00
+        }
+    }
+
+    pub fn write_prohibited(&mut self, _addr: u16) {
+        if self.is_oam_locked() {
+            self.corrupt_oam(|a, b, c| ((a ^ c) & (b ^ c)) ^ c);
+        }
+    }
+
+    /// Corrupt the row the DMG scans in the mode 2: its first word is made of the words `a` of
+    /// the row, `b` and `c` of the row before by the glitch, and the rest is the row before. The
+    /// first row is never corrupted.
+    fn corrupt_oam(&mut self, glitch: impl Fn(u16, u16, u16) -> u16) {
+        if self.is_cgb() || self.stat.get_mode() != LCDMode::OAM {
+            return;
+        }
+        let row = self.cycles as usize / 4;
+        if row == 0 || row >= ROWS {
+            return;
+        }
+        let (cur, prev) = (row * ROW_SIZE, (row - 1) * ROW_SIZE);
+        let word = |oam: &[u8], i: usize| u16::from_le_bytes([oam[i], oam[i + 1]]);
+        let v = glitch(
+            word(&self.oam, cur),
+            word(&self.oam, prev),
+            word(&self.oam, prev + 4),
+        );
+        self.oam[cur..cur + 2].copy_from_slice(&v.to_le_bytes());
+        self.oam.copy_within(prev + 2..prev + ROW_SIZE, cur + 2);
+    }
+}
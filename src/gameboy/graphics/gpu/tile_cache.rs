@@ -0,0 +1,49 @@
+//! The tile data decoded into the color numbers. The scanline renderer reads a tile row for
+//! every 8 pixels of the bg, the window and the sprites, while the games rarely write the tile
+//! data, so a row is decoded once when its VRAM bytes are written rather than on every read.
+use crate::gameboy::graphics::tile::TileLine;
+
+// The tile data is 0x8000-0x97FF in each bank, 384 tiles of 8 rows.
+const TILE_DATA_SIZE: usize = 0x1800;
+const ROWS_PER_BANK: usize = TILE_DATA_SIZE / 2;
+
+pub struct TileCache {
+    // The color numbers of the tile rows, the bank 1 follows the bank 0.
+    rows: Vec<[u8; 8]>,
+}
+
+impl TileCache {
+    pub fn new() -> Self {
+        Self {
+            rows: vec![[0; 8]; 2 * ROWS_PER_BANK],
+        }
+    }
+
+    /// Decode the row holding the byte at the index of the VRAM, the BG maps are ignored.
+    pub fn update(&mut self, ram: &[u8], i: usize) {
+        let (bank, offset) = (i / 0x2000, i % 0x2000);
+        if offset >= TILE_DATA_SIZE {
+            return;
+        }
+        let start = i & !0x01;
+        let line = TileLine::new([ram[start], ram[start + 1]]);
+        let row = &mut self.rows[bank * ROWS_PER_BANK + offset / 2];
+        for (bit, color_num) in row.iter_mut().enumerate() {
+            *color_num = line.get_color_num(bit as u8);
+        }
+    }
+
+    /// Decode every row again, e.g. after the VRAM is restored.
+    pub fn rebuild(&mut self, ram: &[u8]) {
+        for bank in 0..2 {
+            for offset in (0..TILE_DATA_SIZE).step_by(2) {
+                self.update(ram, bank * 0x2000 + offset);
+            }
+        }
+    }
+
+    /// Returns the color numbers of the row at the address 8000-97FF, from left to right.
+    pub fn get_row(&self, bank: usize, addr: u16) -> &[u8; 8] {
+        &self.rows[bank * ROWS_PER_BANK + (addr as usize - 0x8000) / 2]
+    }
+}
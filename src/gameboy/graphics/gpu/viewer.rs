@@ -0,0 +1,163 @@
+//! The VRAM viewer for debugging the homebrew, it draws what the GPU holds rather than what is on
+//! the screen:
+//!
+//! - the 384 tiles of the tile data at the top left, the CGB bank 1 is next to the bank 0,
+//! - the 40 sprites of the OAM below them, in the OAM order,
+//! - the BG maps at 0x9800 and 0x9c00 on the right, the screen of SCX/SCY is outlined in red and
+//!   the window in blue on the maps the LCDC selects.
+//!
+//! The tiles use the BG palette 0, the maps and the sprites use their own palettes.
+use super::GPU;
+use crate::gameboy::graphics::tile::{Attr, Palette};
+use crate::gameboy::{SCREEN_H, SCREEN_W};
+
+pub const VIEWER_W: usize = MAP_X[1] + MAP_SIZE;
+pub const VIEWER_H: usize = MAP_SIZE;
+
+// 16 tiles in a row of a bank, 24 rows.
+const TILESET_W: usize = 16 * 8;
+const TILESET_H: usize = 24 * 8;
+// The sprites are in 2 rows of 20, each one in a 12x20 cell.
+const OAM_Y: usize = TILESET_H + 8;
+const SPRITES_PER_ROW: usize = 20;
+const CELL_W: usize = 12;
+const CELL_H: usize = 20;
+const MAP_SIZE: usize = 256;
+const MAP_X: [usize; 2] = [2 * TILESET_W + 8, 2 * TILESET_W + 16 + MAP_SIZE];
+
+const BACKDROP: [u8; 3] = [0x40, 0x40, 0x40];
+const SCREEN_RECT: [u8; 3] = [0xff, 0x00, 0x00];
+const WINDOW_RECT: [u8; 3] = [0x00, 0x80, 0xff];
+
+impl GPU {
+    /// Returns the pixels of the VRAM viewer, `VIEWER_W` x `VIEWER_H` row by row.
+    pub fn render_vram(&self) -> Vec<[u8; 3]> {
+        let mut view = vec![BACKDROP; VIEWER_W * VIEWER_H];
+        self.render_tileset(&mut view);
+        self.render_oam(&mut view);
+        for (i, base_addr) in [0x9800, 0x9c00].iter().enumerate() {
+            self.render_map(&mut view, MAP_X[i], *base_addr);
+        }
+        view
+    }
+
+    /// Returns the color number of the pixel in the tile at the address.
+    fn get_tile_pixel(&self, bank: usize, tile_addr: u16, x: u8, y: u8) -> u8 {
+        self.tiles.get_row(bank, tile_addr + u16::from(y) * 2)[x as usize]
+    }
+
+    fn render_tileset(&self, view: &mut [[u8; 3]]) {
+        let banks = if self.is_cgb() { 2 } else { 1 };
+        for bank in 0..banks {
+            for y in 0..TILESET_H {
+                for x in 0..TILESET_W {
+                    let tile = (y / 8 * 16 + x / 8) as u16;
+                    let color_num =
+                        self.get_tile_pixel(bank, 0x8000 + tile * 16, x as u8 % 8, y as u8 % 8);
+                    view[y * VIEWER_W + bank * TILESET_W + x] =
+                        self.get_rgb(Palette::BG, 0, color_num);
+                }
+            }
+        }
+    }
+
+    /// Draw the sprites with the size of the LCDC, the transparent pixels are left as the backdrop.
+    fn render_oam(&self, view: &mut [[u8; 3]]) {
+        let (_, sprite_y_size) = self.lcdc.get_sprite_size();
+        for i in 0..40 {
+            let tile_number = if sprite_y_size == 16 {
+                self.oam[i * 4 + 2] & 0xfe
+            } else {
+                self.oam[i * 4 + 2]
+            };
+            let attr = Attr::from(self.oam[i * 4 + 3]);
+            let (palette, bank) = if self.is_cgb() {
+                (Palette::OBP0, attr.get_bank())
+            } else {
+                (attr.get_palette(), 0)
+            };
+            let left = i % SPRITES_PER_ROW * CELL_W + 2;
+            let top = OAM_Y + i / SPRITES_PER_ROW * CELL_H + 2;
+            for y in 0..sprite_y_size {
+                for x in 0..8 {
+                    let tile_x = if attr.has_xflip() { 7 - x } else { x };
+                    let tile_y = if attr.has_yflip() {
+                        sprite_y_size - 1 - y
+                    } else {
+                        y
+                    };
+                    let tile_addr = 0x8000 + u16::from(tile_number) * 16;
+                    let color_num = self.get_tile_pixel(bank, tile_addr, tile_x, tile_y);
+                    if color_num != 0 {
+                        view[(top + y as usize) * VIEWER_W + left + x as usize] =
+                            self.get_rgb(palette, attr.get_cgb_palette(), color_num);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draw the BG map with the tile data and the attributes the LCDC and the VRAM select now.
+    fn render_map(&self, view: &mut [[u8; 3]], left: usize, base_addr: u16) {
+        for y in 0..MAP_SIZE {
+            for x in 0..MAP_SIZE {
+                let (row, col) = ((y / 8) as u16, (x / 8) as u16);
+                let tile_addr = self.find_tile_data_addr(base_addr, row, col);
+                let attr = if self.is_cgb() {
+                    Attr::from(self.read_byte_from_bank(1, base_addr + row * 32 + col))
+                } else {
+                    Attr::from(0x00)
+                };
+                let (mut tile_x, mut tile_y) = (x as u8 % 8, y as u8 % 8);
+                if attr.has_xflip() {
+                    tile_x = 7 - tile_x;
+                }
+                if attr.has_yflip() {
+                    tile_y = 7 - tile_y;
+                }
+                let color_num = self.get_tile_pixel(attr.get_bank(), tile_addr, tile_x, tile_y);
+                view[y * VIEWER_W + left + x] =
+                    self.get_rgb(Palette::BG, attr.get_cgb_palette(), color_num);
+            }
+        }
+
+        if base_addr == self.lcdc.get_bg_tilemap_addr() {
+            let (x, y) = (usize::from(self.scroll_x), usize::from(self.scroll_y));
+            draw_map_rect(view, left, x, y, SCREEN_W, SCREEN_H, SCREEN_RECT);
+        }
+        // The window starts at the top left of its map, WX below 7 cuts its left columns.
+        let (wx, wy) = (usize::from(self.window_x), usize::from(self.window_y));
+        if base_addr == self.lcdc.get_window_tilemap_addr()
+            && self.lcdc.is_window_enabled()
+            && wx <= 166
+            && wy < SCREEN_H
+        {
+            let (x, w) = (7usize.saturating_sub(wx), SCREEN_W - wx.saturating_sub(7));
+            draw_map_rect(view, left, x, 0, w, SCREEN_H - wy, WINDOW_RECT);
+        }
+    }
+}
+
+/// Outline the rectangle on the map at the left of the view, it wraps around the map border like
+/// the scrolling does.
+fn draw_map_rect(
+    view: &mut [[u8; 3]],
+    left: usize,
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+    color: [u8; 3],
+) {
+    let mut put = |px: usize, py: usize| {
+        view[(py % MAP_SIZE) * VIEWER_W + left + px % MAP_SIZE] = color;
+    };
+    for i in 0..w {
+        put(x + i, y);
+        put(x + i, y + h - 1);
+    }
+    for i in 0..h {
+        put(x, y + i);
+        put(x + w - 1, y + i);
+    }
+}
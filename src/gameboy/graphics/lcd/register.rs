@@ -56,6 +56,11 @@ impl LCDStatusRegister {
         self.mode
     }
 
+    /// Set the raw value of register, the mode bits included.
+    pub fn set_value(&mut self, v: u8) {
+        self.mode = v;
+    }
+
     /// Get the mode.
     pub fn get_mode(&self) -> LCDMode {
         LCDMode::from(self.mode & 0b11)
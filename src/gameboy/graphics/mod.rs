@@ -1,5 +1,7 @@
+pub mod filter;
 pub mod gpu;
 pub mod lcd;
+pub mod palette;
 pub mod tile;
 
 use super::cpu;
@@ -0,0 +1,257 @@
+//! The colors of the 4 DMG shades. A few palettes are built in, more are defined in the palette
+//! file along with the palette to use for each game. The file is a small subset of TOML like the
+//! keymap file, the colors go from the lightest shade to the darkest and the games are matched by
+//! the title in the rom header:
+//! ```ignore
+//! [palettes]
+//! sepia = ["#f8e8c8", "#c8a878", "#806040", "#302010"]
+//! [games]
+//! "TETRIS" = "green"
+//! ```
+use std::fs;
+use std::str::FromStr;
+
+use super::tile::GBColor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DmgPalette {
+    name: String,
+    // The RGB of the shades from white to black.
+    colors: [[u8; 3]; 4],
+}
+
+impl DmgPalette {
+    pub fn new(name: &str, colors: [[u8; 3]; 4]) -> Self {
+        Self {
+            name: name.to_string(),
+            colors,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_rgb(&self, color: GBColor) -> [u8; 3] {
+        match color {
+            GBColor::White => self.colors[0],
+            GBColor::Light => self.colors[1],
+            GBColor::Dark => self.colors[2],
+            GBColor::Black => self.colors[3],
+        }
+    }
+}
+
+/// The gray shades of `GBColor`, it's the default.
+impl Default for DmgPalette {
+    fn default() -> Self {
+        Self::new("gray", [[0xff; 3], [0xc0; 3], [0x60; 3], [0x00; 3]])
+    }
+}
+
+fn rgb(v: u32) -> [u8; 3] {
+    [(v >> 16) as u8, (v >> 8) as u8, v as u8]
+}
+
+fn from_hex(name: &str, colors: [u32; 4]) -> DmgPalette {
+    let [a, b, c, d] = colors;
+    DmgPalette::new(name, [rgb(a), rgb(b), rgb(c), rgb(d)])
+}
+
+/// The palettes offered without a palette file, the first is the default.
+fn builtin_palettes() -> Vec<DmgPalette> {
+    vec![
+        DmgPalette::default(),
+        // The green LCD of the DMG.
+        from_hex("green", [0x9b_bc0f, 0x8b_ac0f, 0x30_6230, 0x0f_380f]),
+        from_hex("pocket", [0xc4_cfa1, 0x8b_956d, 0x4d_533c, 0x1f_1f1f]),
+        // The Super Game Boy presets.
+        from_hex("sgb-1a", [0xf8_e8c8, 0xd8_9048, 0xa8_2820, 0x30_1850]),
+        from_hex("sgb-2a", [0xf0_c8a0, 0xc0_8848, 0x28_7800, 0x00_0000]),
+    ]
+}
+
+/// Parse the color of the form `#rrggbb`.
+pub fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    let hex = s
+        .strip_prefix('#')
+        .filter(|h| h.len() == 6)
+        .ok_or_else(|| format!("the color {} should be #rrggbb", s))?;
+    let v = u32::from_str_radix(hex, 16).map_err(|_| format!("invalid color {}", s))?;
+    Ok(rgb(v))
+}
+
+/// Drop the comment of the line, the `#` in the quotes is a color.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteSet {
+    palettes: Vec<DmgPalette>,
+    // The title of the game and the name of its palette.
+    games: Vec<(String, String)>,
+}
+
+impl Default for PaletteSet {
+    fn default() -> Self {
+        Self {
+            palettes: builtin_palettes(),
+            games: vec![],
+        }
+    }
+}
+
+impl PaletteSet {
+    /// Load the palette file, the built in palettes are kept.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let s = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        s.parse()
+    }
+
+    pub fn get_palettes(&self) -> &[DmgPalette] {
+        &self.palettes
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DmgPalette> {
+        self.palettes.iter().find(|p| p.name == name)
+    }
+
+    /// Add the palette, the one with the same name is replaced.
+    pub fn add(&mut self, palette: DmgPalette) {
+        match self.palettes.iter_mut().find(|p| p.name == palette.name) {
+            Some(p) => *p = palette,
+            None => self.palettes.push(palette),
+        }
+    }
+
+    pub fn set_game(&mut self, title: &str, name: &str) {
+        self.games.retain(|(t, _)| t != title);
+        self.games.push((title.to_string(), name.to_string()));
+    }
+
+    /// Returns the palette of the game, or the named one if the game has none.
+    pub fn find(&self, title: &str, name: &str) -> DmgPalette {
+        let name = self
+            .games
+            .iter()
+            .find(|(t, _)| t == title)
+            .map_or(name, |(_, n)| n.as_str());
+        self.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Returns the palette after the named one, the last is followed by the first.
+    pub fn next(&self, name: &str) -> DmgPalette {
+        let i = self.palettes.iter().position(|p| p.name == name);
+        let i = i.map_or(0, |i| (i + 1) % self.palettes.len());
+        self.palettes[i].clone()
+    }
+}
+
+/// Parse the palette file.
+impl FromStr for PaletteSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = Self::default();
+        let mut section = "";
+        for (i, line) in s.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            let err = |e: String| format!("line {}: {}", i + 1, e);
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') && !line.contains('=') {
+                section = match line {
+                    "[palettes]" => "palettes",
+                    "[games]" => "games",
+                    _ => return Err(err(format!("unknown section {}", line))),
+                };
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap().trim().trim_matches('"');
+            let value = parts
+                .next()
+                .ok_or_else(|| err(format!("expect <name> = <value>, found {}", line)))?
+                .trim();
+            match section {
+                "palettes" => {
+                    let list = value
+                        .strip_prefix('[')
+                        .and_then(|v| v.strip_suffix(']'))
+                        .ok_or_else(|| err(format!("the palette {} should be a list", key)))?;
+                    let colors = list
+                        .split(',')
+                        .map(|c| parse_color(c.trim().trim_matches('"')))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(err)?;
+                    if colors.len() != 4 {
+                        return Err(err(format!("the palette {} needs 4 colors", key)));
+                    }
+                    set.add(DmgPalette::new(
+                        key,
+                        [colors[0], colors[1], colors[2], colors[3]],
+                    ));
+                }
+                "games" => set.set_game(key, value.trim_matches('"')),
+                _ => return Err(err(format!("{} is not in a section", key))),
+            }
+        }
+        for (title, name) in set.games.iter() {
+            if set.get(name).is_none() {
+                return Err(format!("the palette {} of {} is not defined", name, title));
+            }
+        }
+        Ok(set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#9bbc0f"), Ok([0x9b, 0xbc, 0x0f]));
+        assert!(parse_color("9bbc0f").is_err());
+        assert!(parse_color("#9bbc0").is_err());
+        assert!(parse_color("#9bbcxx").is_err());
+    }
+
+    #[test]
+    fn test_parse_file() {
+        // The `#` of the colors is quoted, only the one after them starts a comment.
+        let set: PaletteSet = "[palettes] # mine\nsepia = [\"#f8e8c8\", \"#c8a878\", \"#806040\", \"#302010\"]\n\n[games]\n\"TETRIS\" = \"green\"\nZELDA = \"sepia\"\n"
+            .parse()
+            .unwrap();
+        let sepia = set.get("sepia").unwrap();
+        assert_eq!(sepia.get_rgb(GBColor::Dark), [0x80, 0x60, 0x40]);
+        assert_eq!(set.find("TETRIS", "gray").get_name(), "green");
+        assert_eq!(set.find("ZELDA", "gray").get_name(), "sepia");
+        assert_eq!(set.find("KIRBY", "pocket").get_name(), "pocket");
+        assert_eq!(set.find("KIRBY", "nothing"), DmgPalette::default());
+
+        let undefined = "[games]\nTETRIS = \"nothing\"\n";
+        assert!(undefined.parse::<PaletteSet>().is_err());
+        let err = "[palettes]\nbad = [\"#ffffff\"]\n"
+            .parse::<PaletteSet>()
+            .unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+
+    #[test]
+    fn test_next_palette() {
+        let set = PaletteSet::default();
+        assert_eq!(set.next("gray").get_name(), "green");
+        assert_eq!(set.next("sgb-2a").get_name(), "gray");
+    }
+}
@@ -1,7 +1,10 @@
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+
 /// Each tile is stored in memory as 16 bytes.
 /// A tile is 8x8 pixels and that in memory each line of the tile requires two bytes to represent,
 /// hence the 16 bytes per tile.
 /// Each tile is sized 8x8 pixels and has a color depth of 4 colors/gray shades.
+#[derive(Clone, Copy)]
 pub struct TileLine {
     data: [u8; 2],
 }
@@ -39,7 +42,7 @@ pub enum GBColor {
     Black = 0x00,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Palette {
     OBP0 = 0,
     OBP1 = 1,
@@ -48,6 +51,101 @@ pub enum Palette {
     BG = 0xff,
 }
 
+/// The logical color of a rendered pixel, it doesn't depend on the palette register value.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PixelIndex {
+    /// The color number 0-3 in the tile data.
+    pub color_num: u8,
+    /// The palette which maps the color number to the shade.
+    pub palette: Palette,
+}
+
+impl Default for PixelIndex {
+    fn default() -> Self {
+        Self {
+            color_num: 0,
+            palette: Palette::BG,
+        }
+    }
+}
+
+/// The CGB palette memory, 8 palettes of 4 colors, each color is 2 bytes little endian RGB555.
+/// It's accessed through a pair of registers, BCPS/BCPD for the background and OCPS/OCPD for the sprites.
+///
+/// The specification register:
+/// Bit7   Auto Increment  (0=Disabled, 1=Increment after Writing)
+/// Bit5-0 Index (00-3F)
+pub struct PaletteRam {
+    index: u8,
+    auto_increment: bool,
+    data: [u8; 64],
+}
+
+impl PaletteRam {
+    pub fn new() -> Self {
+        Self {
+            index: 0,
+            auto_increment: false,
+            data: [0xff; 64],
+        }
+    }
+
+    /// Bit 6 is unused and always reads as 1.
+    pub fn get_spec(&self) -> u8 {
+        let inc = if self.auto_increment { 0x80 } else { 0x00 };
+        inc | 0x40 | self.index
+    }
+
+    pub fn set_spec(&mut self, v: u8) {
+        self.auto_increment = v & 0x80 != 0;
+        self.index = v & 0x3f;
+    }
+
+    pub fn get_data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    /// Write the byte at the index, the write is dropped if the palette memory is locked.
+    /// The index is incremented in both cases.
+    pub fn set_data(&mut self, v: u8, locked: bool) {
+        if !locked {
+            self.data[self.index as usize] = v;
+        }
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3f;
+        }
+    }
+
+    /// Get the color of the palette 0-7, the 5 bits channels are scaled to 8 bits.
+    pub fn get_rgb(&self, palette: u8, color_num: u8) -> [u8; 3] {
+        let i = (palette as usize & 0x07) * 8 + color_num as usize * 2;
+        let c = u16::from(self.data[i]) | (u16::from(self.data[i + 1]) << 8);
+        let scale = |v: u16| {
+            let v = (v & 0x1f) as u8;
+            (v << 3) | (v >> 2)
+        };
+        [scale(c), scale(c >> 5), scale(c >> 10)]
+    }
+}
+
+impl Stateful for PaletteRam {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u8(self.get_spec());
+        w.put_bytes(&self.data);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.set_spec(r.get_u8()?);
+        r.fill(&mut self.data)
+    }
+}
+
+impl Default for PaletteRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Bit7   OBJ-to-BG Priority (0=OBJ Above BG, 1=OBJ Behind BG color 1-3)
 ///     (Used for both BG and Window. BG color 0 is always behind OBJ)
 /// Bit6   Y flip          (0=Normal, 1=Vertically mirrored)
@@ -55,12 +153,16 @@ pub enum Palette {
 /// Bit4   Palette number  **Non CGB Mode Only** (0=OBP0, 1=OBP1)
 /// Bit3   Tile VRAM-Bank  **CGB Mode Only**     (0=Bank 0, 1=Bank 1)
 /// Bit2-0 Palette number  **CGB Mode Only**     (OBP0-7)
+///
+/// The CGB BG map attributes in the VRAM bank 1 share the layout, except the bit 4 is unused and
+/// the bit 7 puts the tile above the sprites.
 pub struct Attr {
     priority: bool,
     yflip: bool,
     xflip: bool,
     palette: Palette,
-    // TODO
+    bank: usize,
+    cgb_palette: u8,
 }
 
 impl Attr {
@@ -76,7 +178,15 @@ impl Attr {
     }
 
     pub fn get_palette(&self) -> Palette {
-        self.palette.clone()
+        self.palette
+    }
+
+    pub fn get_bank(&self) -> usize {
+        self.bank
+    }
+
+    pub fn get_cgb_palette(&self) -> u8 {
+        self.cgb_palette
     }
 }
 
@@ -86,11 +196,13 @@ impl From<u8> for Attr {
             priority: u & (1 << 7) != 0,
             yflip: u & (1 << 6) != 0,
             xflip: u & (1 << 5) != 0,
-            palette: if u & (1 << 4) == 1 {
+            palette: if u & (1 << 4) != 0 {
                 Palette::OBP1
             } else {
                 Palette::OBP0
             },
+            bank: usize::from(u & (1 << 3) != 0),
+            cgb_palette: u & 0x07,
         }
     }
 }
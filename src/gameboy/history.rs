@@ -0,0 +1,223 @@
+//! The last instructions executed by the cpu, kept in a ring buffer for the post-mortem. The
+//! emulator records them only when the history is on, and saves them into `coredump.json` if it
+//! panics. A library user exports them with `CpuHistory::to_json` or `to_csv`.
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::cpu::Register;
+
+/// The instructions kept by default.
+pub const DEFAULT_HISTORY_SIZE: usize = 512;
+/// Where the history is saved when the emulator panics.
+pub const COREDUMP_PATH: &str = "./coredump.json";
+
+const CSV_HEADER: &str =
+    "cycle,cycles,pc,opcode,cb_opcode,a,f,b,c,d,e,h,l,sp,rom_bank,ram_bank,wram_bank";
+
+/// An executed instruction with the registers and the banks before it runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpuRecord {
+    /// The cycles since the history is started, at the start of the instruction.
+    pub cycle: u64,
+    /// The cycles the instruction takes in the normal speed.
+    pub cycles: u32,
+    pub reg: Register,
+    pub opcode: u8,
+    /// The second byte of a CB prefixed instruction.
+    pub cb_opcode: Option<u8>,
+    /// The banks mapped at 4000-7FFF, A000-BFFF and D000-DFFF.
+    pub rom_bank: usize,
+    pub ram_bank: usize,
+    pub wram_bank: usize,
+}
+
+impl CpuRecord {
+    fn get_fields(&self) -> [(&'static str, u64); 16] {
+        let reg = &self.reg;
+        [
+            ("cycle", self.cycle),
+            ("cycles", u64::from(self.cycles)),
+            ("pc", u64::from(reg.get_PC())),
+            ("opcode", u64::from(self.opcode)),
+            ("a", u64::from(reg.get_A())),
+            ("f", u64::from(reg.get_AF() as u8)),
+            ("b", u64::from(reg.get_B())),
+            ("c", u64::from(reg.get_C())),
+            ("d", u64::from(reg.get_D())),
+            ("e", u64::from(reg.get_E())),
+            ("h", u64::from(reg.get_H())),
+            ("l", u64::from(reg.get_L())),
+            ("sp", u64::from(reg.get_SP())),
+            ("rom_bank", self.rom_bank as u64),
+            ("ram_bank", self.ram_bank as u64),
+            ("wram_bank", self.wram_bank as u64),
+        ]
+    }
+
+    /// Returns the record as a JSON object, the numbers are in decimal.
+    pub fn to_json(&self) -> String {
+        let mut fields: Vec<String> = self
+            .get_fields()
+            .iter()
+            .map(|(k, v)| format!("\"{}\":{}", k, v))
+            .collect();
+        let cb_opcode = self
+            .cb_opcode
+            .map_or("null".to_string(), |op| op.to_string());
+        fields.insert(4, format!("\"cb_opcode\":{}", cb_opcode));
+        format!("{{{}}}", fields.join(","))
+    }
+
+    /// Returns the record as a line of the CSV with `CSV_HEADER`, no CB opcode is empty.
+    pub fn to_csv(&self) -> String {
+        let mut fields: Vec<String> = self
+            .get_fields()
+            .iter()
+            .map(|(_, v)| v.to_string())
+            .collect();
+        fields.insert(4, self.cb_opcode.map_or(String::new(), |op| op.to_string()));
+        fields.join(",")
+    }
+}
+
+/// The ring buffer of the last executed instructions, the oldest one is dropped for a new one.
+#[derive(Debug, Clone)]
+pub struct CpuHistory {
+    records: VecDeque<CpuRecord>,
+    capacity: usize,
+    // The cycles since the history is started.
+    cycle: u64,
+}
+
+impl CpuHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+            cycle: 0,
+        }
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the records from the oldest.
+    pub fn iter(&self) -> impl Iterator<Item = &CpuRecord> {
+        self.records.iter()
+    }
+
+    /// Record the instruction, its cycle is set from the cycles recorded before.
+    pub fn push(&mut self, mut record: CpuRecord) {
+        record.cycle = self.cycle;
+        self.cycle += u64::from(record.cycles);
+        if self.records.len() >= self.capacity {
+            self.records.pop_front();
+        }
+        if self.capacity > 0 {
+            self.records.push_back(record);
+        }
+    }
+
+    /// Count the cycles without an instruction, e.g. the halted steps and the interrupt dispatch.
+    pub fn skip(&mut self, cycles: u32) {
+        self.cycle += u64::from(cycles);
+    }
+
+    /// Returns the records as a JSON array from the oldest, one object a line.
+    pub fn to_json(&self) -> String {
+        let records: Vec<String> = self.iter().map(|r| format!("  {}", r.to_json())).collect();
+        format!("[\n{}\n]\n", records.join(",\n"))
+    }
+
+    /// Returns the records as a CSV with the header from the oldest.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for r in self.iter() {
+            csv.push_str(&r.to_csv());
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Write the records into the file, the CSV if its extension is `csv` and the JSON otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let data = match path.extension() {
+            Some(ext) if ext == "csv" => self.to_csv(),
+            _ => self.to_json(),
+        };
+        fs::write(path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(pc: u16, cycles: u32) -> CpuRecord {
+        let mut reg = Register::new();
+        reg.set_PC(pc);
+        reg.set_A(0x12);
+        CpuRecord {
+            cycle: 0,
+            cycles,
+            reg,
+            opcode: 0x00,
+            cb_opcode: None,
+            rom_bank: 1,
+            ram_bank: 0,
+            wram_bank: 1,
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut history = CpuHistory::new(2);
+        history.push(record(0x0100, 4));
+        history.skip(20);
+        history.push(record(0x0101, 8));
+        history.push(record(0x0103, 4));
+        assert_eq!(history.len(), 2);
+        let records: Vec<(u16, u64)> = history.iter().map(|r| (r.reg.get_PC(), r.cycle)).collect();
+        assert_eq!(records, vec![(0x0101, 24), (0x0103, 32)]);
+
+        let mut history = CpuHistory::new(0);
+        history.push(record(0x0100, 4));
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn test_export() {
+        let mut history = CpuHistory::new(4);
+        history.push(record(0x0100, 4));
+        let mut cb = record(0x0101, 8);
+        cb.opcode = 0xcb;
+        cb.cb_opcode = Some(0x37);
+        history.push(cb);
+
+        let csv = history.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "0,4,256,0,,18,0,0,0,0,0,0,0,0,1,0,1");
+        assert_eq!(lines[2], "4,8,257,203,55,18,0,0,0,0,0,0,0,0,1,0,1");
+
+        let json = history.to_json();
+        assert!(json.starts_with("[\n  {\"cycle\":0,\"cycles\":4,\"pc\":256,\"opcode\":0,"));
+        assert!(json.contains("\"cb_opcode\":null,\"a\":18"));
+        assert!(json.contains("\"opcode\":203,\"cb_opcode\":55,"));
+        assert!(json.ends_with("\"wram_bank\":1}\n]\n"));
+    }
+}
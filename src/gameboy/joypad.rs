@@ -1,9 +1,10 @@
-use std::cell::RefCell;
 use std::convert::From;
-use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use super::cpu::IntFlag;
 use super::cpu::IntReg;
+use super::state::{StateError, StateReader, StateWriter, Stateful};
 use super::IOHandler;
 
 const SELECT_FUNC_KEY_MASK: u8 = 0b0010_0000;
@@ -68,76 +69,347 @@ impl From<JoypadKey> for JoypadKeyMask {
     }
 }
 
+impl FromStr for JoypadKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "right" => Ok(Self::Right),
+            "left" => Ok(Self::Left),
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            "a" => Ok(Self::A),
+            "b" => Ok(Self::B),
+            "select" => Ok(Self::Select),
+            "start" => Ok(Self::Start),
+            _ => Err(format!("unknown joypad key {}", s)),
+        }
+    }
+}
+
+/// What to do when both keys of an opposite pair (Left+Right, Up+Down) are held, the real
+/// hardware can't report them at the same time and some games break if it happens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OppositeDirection {
+    /// Neither key is reported until one of them is released.
+    Block,
+    /// The key pressed later wins, the earlier one comes back after it is released.
+    LastWins,
+    /// Report both as they are.
+    Allow,
+}
+
+impl FromStr for OppositeDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "last-wins" => Ok(Self::LastWins),
+            "allow" => Ok(Self::Allow),
+            _ => Err(format!("unknown opposite direction mode {}", s)),
+        }
+    }
+}
+
+/// All the keys, in the bit order of `Joypad::get_keys`.
+pub const KEYS: [JoypadKey; 8] = [
+    JoypadKey::Right,
+    JoypadKey::Left,
+    JoypadKey::Up,
+    JoypadKey::Down,
+    JoypadKey::A,
+    JoypadKey::B,
+    JoypadKey::Select,
+    JoypadKey::Start,
+];
+
+fn key_index(key: &JoypadKey) -> usize {
+    KEYS.iter().position(|k| k == key).unwrap()
+}
+
+/// Returns the index of the opposite direction.
+fn opposite_index(i: usize) -> Option<usize> {
+    match i {
+        0..=3 => Some(i ^ 1),
+        _ => None,
+    }
+}
+
+/// The input sanitization layer, every input source goes through it before reaching the joypad.
+/// It tracks the physically held keys and resolves what the game is allowed to see, so a key
+/// repeat from the frontend and an impossible opposite pair never reach the register.
+pub struct InputSanitizer {
+    mode: OppositeDirection,
+    // The press order of each held key, 0 means released.
+    held: [u64; 8],
+    pressed: [bool; 8],
+    order: u64,
+}
+
+impl InputSanitizer {
+    pub fn new(mode: OppositeDirection) -> Self {
+        Self {
+            mode,
+            held: [0; 8],
+            pressed: [false; 8],
+            order: 0,
+        }
+    }
+
+    pub fn get_mode(&self) -> OppositeDirection {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: OppositeDirection) {
+        self.mode = mode;
+    }
+
+    /// Returns whether the key is pressed from the view of the game.
+    pub fn is_pressed(&self, key: &JoypadKey) -> bool {
+        self.pressed[key_index(key)]
+    }
+
+    /// Returns the keys which change their state, `true` means pressed.
+    pub fn keydown(&mut self, key: &JoypadKey) -> Vec<(JoypadKey, bool)> {
+        let i = key_index(key);
+        if self.held[i] != 0 {
+            // Key repeat.
+            return vec![];
+        }
+        self.order += 1;
+        self.held[i] = self.order;
+        self.resolve()
+    }
+
+    pub fn keyup(&mut self, key: &JoypadKey) -> Vec<(JoypadKey, bool)> {
+        let i = key_index(key);
+        if self.held[i] == 0 {
+            return vec![];
+        }
+        self.held[i] = 0;
+        self.resolve()
+    }
+
+    fn resolve(&mut self) -> Vec<(JoypadKey, bool)> {
+        let mut changes = vec![];
+        for (i, key) in KEYS.iter().enumerate() {
+            let mut pressed = self.held[i] != 0;
+            if let Some(j) = opposite_index(i) {
+                if pressed && self.held[j] != 0 {
+                    pressed = match self.mode {
+                        OppositeDirection::Block => false,
+                        OppositeDirection::LastWins => self.held[i] > self.held[j],
+                        OppositeDirection::Allow => true,
+                    };
+                }
+            }
+            if pressed != self.pressed[i] {
+                self.pressed[i] = pressed;
+                changes.push((key.clone(), pressed));
+            }
+        }
+        changes
+    }
+}
+
+/// The presses per second of the autofire by default.
+pub const DEFAULT_AUTOFIRE_RATE: u32 = 10;
+
+/// The autofire, a held turbo key or a held button set to autofire alternates between pressed
+/// and released every few frames. It starts from pressed, so a short tap is never lost.
+#[derive(Debug, Clone)]
+pub struct Autofire {
+    // The frames of each half of the period.
+    half_period: u32,
+    enabled: bool,
+    // The buttons which autofire while held, in the layout of `Joypad::get_keys`.
+    buttons: u8,
+    // The frames each key has been autofiring.
+    frames: [u32; 8],
+}
+
+impl Autofire {
+    pub fn new(rate: u32) -> Self {
+        let mut autofire = Self {
+            half_period: 1,
+            enabled: true,
+            buttons: 0x00,
+            frames: [0; 8],
+        };
+        autofire.set_rate(rate);
+        autofire
+    }
+
+    /// Returns the presses per second at 60 frames per second.
+    pub fn get_rate(&self) -> u32 {
+        30 / self.half_period
+    }
+
+    /// The rate is rounded to a whole count of frames, 30 is the fastest.
+    pub fn set_rate(&mut self, rate: u32) {
+        self.half_period = (30 / rate.max(1)).max(1);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The turbo keys become the plain buttons while it's disabled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_button_enabled(&self, key: &JoypadKey) -> bool {
+        self.buttons & (1 << key_index(key)) != 0
+    }
+
+    /// Make the button autofire while it's held.
+    pub fn set_button(&mut self, key: &JoypadKey, enabled: bool) {
+        let mask = 1 << key_index(key);
+        if enabled {
+            self.buttons |= mask;
+        } else {
+            self.buttons &= !mask;
+        }
+    }
+
+    /// Returns the keys of the next frame from the held keys and the held turbo keys, both are
+    /// in the layout of `Joypad::get_keys`.
+    pub fn apply(&mut self, keys: u8, turbo: u8) -> u8 {
+        if !self.enabled {
+            self.frames = [0; 8];
+            return keys | turbo;
+        }
+        let auto = turbo | (keys & self.buttons);
+        let mut out = keys & !auto;
+        for (i, frames) in self.frames.iter_mut().enumerate() {
+            if auto & (1 << i) == 0 {
+                *frames = 0;
+                continue;
+            }
+            if (*frames / self.half_period) & 0x01 == 0 {
+                out |= 1 << i;
+            }
+            *frames += 1;
+        }
+        out
+    }
+}
+
 pub struct Joypad {
-    intf: Rc<RefCell<IntReg>>,
-    reg: u8,
+    intf: Arc<IntReg>,
+    // Bit 0-3 of the direction keys and the button keys, 0 means pressed.
+    direction: u8,
+    button: u8,
     // The cpu tell us what should be select, direction key or func key.
     select_mask: u8,
+    sanitizer: InputSanitizer,
 }
 
 impl Joypad {
-    pub fn new(intf: Rc<RefCell<IntReg>>) -> Self {
+    pub fn new(intf: Arc<IntReg>) -> Self {
         Self {
             intf,
-            reg: 0xff,
+            direction: 0x0f,
+            button: 0x0f,
             select_mask: 0xff,
+            sanitizer: InputSanitizer::new(OppositeDirection::LastWins),
         }
     }
+
+    pub fn set_opposite_direction(&mut self, mode: OppositeDirection) {
+        self.sanitizer.set_mode(mode);
+    }
 }
 
 impl Joypad {
+    /// Press the key, it's shared by all the input sources.
     pub fn keydown(&mut self, key: JoypadKey) {
-        let keys: [JoypadKey; 4] = [
-            JoypadKey::Right,
-            JoypadKey::Left,
-            JoypadKey::Up,
-            JoypadKey::Down,
-        ];
-        if keys.contains(&key) {
-            self.reg &= !SELECT_DIRECTION_KEY_MASK;
-        } else {
-            self.reg &= !SELECT_FUNC_KEY_MASK;
+        for (key, pressed) in self.sanitizer.keydown(&key) {
+            self.apply(key, pressed);
         }
-
-        self.reg &= !((JoypadKeyMask::from(key.clone())).bits());
-        self.intf.borrow_mut().req(IntFlag::Joypad);
     }
 
     pub fn keyup(&mut self, key: JoypadKey) {
-        let keys: [JoypadKey; 4] = [
-            JoypadKey::Right,
-            JoypadKey::Left,
-            JoypadKey::Up,
-            JoypadKey::Down,
-        ];
-        if keys.contains(&key) {
-            self.reg |= SELECT_DIRECTION_KEY_MASK;
+        for (key, pressed) in self.sanitizer.keyup(&key) {
+            self.apply(key, pressed);
+        }
+    }
+
+    /// Returns the keys the game sees, bit 0-7 are Right, Left, Up, Down, A, B, Select, Start.
+    pub fn get_keys(&self) -> u8 {
+        (!self.direction & 0x0f) | ((!self.button & 0x0f) << 4)
+    }
+
+    /// Press the keys in the mask and release the others, the layout is `get_keys`.
+    pub fn set_keys(&mut self, keys: u8) {
+        for (i, key) in KEYS.iter().enumerate() {
+            if keys & (1 << i) != 0 {
+                self.keydown(key.clone());
+            } else {
+                self.keyup(key.clone());
+            }
+        }
+    }
+
+    /// The input lines P10-P13 of the selected keys, 0 means low.
+    fn get_lines(&self) -> u8 {
+        self.read_byte(0xff00) & 0x0f
+    }
+
+    /// The interrupt is requested when any input line goes from high to low. It happens when a
+    /// selected key is pressed, or when a held key is selected.
+    fn check_falling_edge(&mut self, lines: u8) {
+        if lines & !self.get_lines() != 0x00 {
+            self.intf.req(IntFlag::Joypad);
+        }
+    }
+
+    fn apply(&mut self, key: JoypadKey, pressed: bool) {
+        let lines = self.get_lines();
+        let is_direction = key_index(&key) < 4;
+        let mask = JoypadKeyMask::from(key).bits();
+        let reg = if is_direction {
+            &mut self.direction
         } else {
-            self.reg |= SELECT_FUNC_KEY_MASK;
+            &mut self.button
+        };
+        if pressed {
+            *reg &= !mask;
+        } else {
+            *reg |= mask;
         }
+        self.check_falling_edge(lines);
+    }
+}
 
-        self.reg |= (JoypadKeyMask::from(key)).bits();
+/// The keys held on the host are not saved, only the matrix the game sees.
+impl Stateful for Joypad {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u8(self.direction);
+        w.put_u8(self.button);
+        w.put_u8(self.select_mask);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.direction = r.get_u8()? & 0x0f;
+        self.button = r.get_u8()? & 0x0f;
+        self.select_mask = r.get_u8()?;
+        Ok(())
     }
 }
 
 impl IOHandler for Joypad {
     fn read_byte(&self, _: u16) -> u8 {
+        let mut v = 0xc0 | (self.select_mask & 0x30) | 0x0f;
         if (self.select_mask & SELECT_DIRECTION_KEY_MASK) == 0 {
-            if self.reg & SELECT_DIRECTION_KEY_MASK == 0 {
-                return self.reg;
-            } else {
-                return 0xff;
-            }
+            v &= 0xf0 | self.direction;
         }
         if (self.select_mask & SELECT_FUNC_KEY_MASK) == 0 {
-            if self.reg & SELECT_FUNC_KEY_MASK == 0 {
-                return self.reg;
-            } else {
-                return 0xff;
-            }
+            v &= 0xf0 | self.button;
         }
-
-        0xff
+        v
     }
 
     // Reference: http://www.codeslinger.co.uk/pages/projects/gameboy/joypad.html
@@ -155,6 +427,133 @@ impl IOHandler for Joypad {
     fn write_byte(&mut self, _: u16, v: u8) {
         // 0b0010_0000 (32)
         // 0b0001_0000 (16)
+        let lines = self.get_lines();
         self.select_mask = v;
+        self.check_falling_edge(lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_repeat_is_suppressed() {
+        let mut s = InputSanitizer::new(OppositeDirection::Allow);
+        assert_eq!(s.keydown(&JoypadKey::A), vec![(JoypadKey::A, true)]);
+        assert!(s.keydown(&JoypadKey::A).is_empty());
+        assert_eq!(s.keyup(&JoypadKey::A), vec![(JoypadKey::A, false)]);
+        assert!(s.keyup(&JoypadKey::A).is_empty());
+    }
+
+    #[test]
+    fn test_opposite_direction_modes() {
+        let mut s = InputSanitizer::new(OppositeDirection::Block);
+        s.keydown(&JoypadKey::Left);
+        assert_eq!(s.keydown(&JoypadKey::Right), vec![(JoypadKey::Left, false)]);
+        assert_eq!(s.keyup(&JoypadKey::Left), vec![(JoypadKey::Right, true)]);
+
+        let mut s = InputSanitizer::new(OppositeDirection::LastWins);
+        s.keydown(&JoypadKey::Up);
+        s.keydown(&JoypadKey::Down);
+        assert!(!s.is_pressed(&JoypadKey::Up));
+        assert!(s.is_pressed(&JoypadKey::Down));
+        // Left/Right are independent of Up/Down.
+        s.keydown(&JoypadKey::Left);
+        assert!(s.is_pressed(&JoypadKey::Left));
+        assert_eq!(
+            s.keyup(&JoypadKey::Down),
+            vec![(JoypadKey::Up, true), (JoypadKey::Down, false)]
+        );
+
+        let mut s = InputSanitizer::new(OppositeDirection::Allow);
+        s.keydown(&JoypadKey::Up);
+        s.keydown(&JoypadKey::Down);
+        assert!(s.is_pressed(&JoypadKey::Up) && s.is_pressed(&JoypadKey::Down));
+    }
+
+    #[test]
+    fn test_autofire() {
+        let a = 1 << key_index(&JoypadKey::A);
+        let b = 1 << key_index(&JoypadKey::B);
+        let up = 1 << key_index(&JoypadKey::Up);
+        let mut autofire = Autofire::new(15);
+        assert_eq!(autofire.get_rate(), 15);
+        // The turbo A is pressed for 2 frames and released for 2 frames, Up stays held.
+        let frames: Vec<u8> = (0..6).map(|_| autofire.apply(up, a)).collect();
+        assert_eq!(frames, vec![up | a, up | a, up, up, up | a, up | a]);
+        // It starts from pressed again after it's released.
+        assert_eq!(autofire.apply(0x00, 0x00), 0x00);
+        assert_eq!(autofire.apply(0x00, a), a);
+
+        autofire.set_button(&JoypadKey::B, true);
+        assert!(autofire.is_button_enabled(&JoypadKey::B));
+        let frames: Vec<u8> = (0..4).map(|_| autofire.apply(b, 0x00)).collect();
+        assert_eq!(frames, vec![b, b, 0x00, 0x00]);
+
+        autofire.set_enabled(false);
+        assert_eq!(autofire.apply(b, a), a | b);
+        assert_eq!(autofire.apply(b, a), a | b);
+
+        autofire.set_rate(100);
+        assert_eq!(autofire.get_rate(), 30);
+    }
+
+    #[test]
+    fn test_direction_and_button_share_bits() {
+        let intf = Arc::new(IntReg::new());
+        let mut joypad = Joypad::new(intf);
+        joypad.keydown(JoypadKey::Right);
+        joypad.keydown(JoypadKey::B);
+
+        joypad.write_byte(0xff00, SELECT_FUNC_KEY_MASK);
+        assert_eq!(joypad.read_byte(0xff00) & 0x0f, 0b1110);
+        joypad.write_byte(0xff00, SELECT_DIRECTION_KEY_MASK);
+        assert_eq!(joypad.read_byte(0xff00) & 0x0f, 0b1101);
+
+        joypad.keyup(JoypadKey::B);
+        assert_eq!(joypad.read_byte(0xff00) & 0x0f, 0b1111);
+    }
+
+    #[test]
+    fn test_interrupt_on_falling_edge() {
+        let intf = Arc::new(IntReg::new());
+        let mut joypad = Joypad::new(intf.clone());
+        let requested = |intf: &Arc<IntReg>| {
+            let v = intf.is_requested(IntFlag::Joypad);
+            intf.ack(IntFlag::Joypad);
+            v
+        };
+        // No line is selected.
+        joypad.keydown(JoypadKey::A);
+        assert!(!requested(&intf));
+        // Selecting the buttons pulls the line of A low.
+        joypad.write_byte(0xff00, SELECT_DIRECTION_KEY_MASK);
+        assert!(requested(&intf));
+        // The directions are not selected.
+        joypad.keydown(JoypadKey::Left);
+        assert!(!requested(&intf));
+        joypad.keydown(JoypadKey::B);
+        assert!(requested(&intf));
+        joypad.keyup(JoypadKey::B);
+        assert!(!requested(&intf));
+        // Both the directions and the buttons are held at the same time.
+        joypad.write_byte(0xff00, 0x00);
+        assert!(requested(&intf));
+        assert_eq!(joypad.read_byte(0xff00) & 0x0f, 0b1100);
+    }
+
+    #[test]
+    fn test_keys_roundtrip() {
+        let intf = Arc::new(IntReg::new());
+        let mut joypad = Joypad::new(intf);
+        joypad.keydown(JoypadKey::Left);
+        joypad.keydown(JoypadKey::Start);
+        assert_eq!(joypad.get_keys(), 0b1000_0010);
+
+        joypad.set_keys(0b0001_0100);
+        assert_eq!(joypad.get_keys(), 0b0001_0100);
+        assert!(joypad.sanitizer.is_pressed(&JoypadKey::A));
+        assert!(!joypad.sanitizer.is_pressed(&JoypadKey::Start));
     }
 }
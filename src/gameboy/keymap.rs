@@ -0,0 +1,281 @@
+//! The keyboard mapping of the joypad, a button may be bound to several keys.
+//!
+//! The keymap file is a small subset of TOML, one button per line, the key names are the ones of
+//! `minifb::Key` and are case insensitive:
+//! ```ignore
+//! # comment
+//! [keymap]
+//! a = ["J", "Z"]
+//! start = "Enter"
+//! turbo-a = "U"
+//! ```
+//! The `turbo-` buttons autofire while their keys are held. The `--keymap` flag takes the same
+//! binding in the form of `a=J,Z`.
+use std::fs;
+use std::str::FromStr;
+
+use minifb::Key;
+
+use super::frontend::{InputSource, Window};
+use super::joypad::{JoypadKey, KEYS};
+
+#[rustfmt::skip]
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("0", Key::Key0), ("1", Key::Key1), ("2", Key::Key2), ("3", Key::Key3), ("4", Key::Key4),
+    ("5", Key::Key5), ("6", Key::Key6), ("7", Key::Key7), ("8", Key::Key8), ("9", Key::Key9),
+    ("A", Key::A), ("B", Key::B), ("C", Key::C), ("D", Key::D), ("E", Key::E), ("F", Key::F),
+    ("G", Key::G), ("H", Key::H), ("I", Key::I), ("J", Key::J), ("K", Key::K), ("L", Key::L),
+    ("M", Key::M), ("N", Key::N), ("O", Key::O), ("P", Key::P), ("Q", Key::Q), ("R", Key::R),
+    ("S", Key::S), ("T", Key::T), ("U", Key::U), ("V", Key::V), ("W", Key::W), ("X", Key::X),
+    ("Y", Key::Y), ("Z", Key::Z),
+    ("F1", Key::F1), ("F2", Key::F2), ("F3", Key::F3), ("F4", Key::F4), ("F5", Key::F5),
+    ("F6", Key::F6), ("F7", Key::F7), ("F8", Key::F8), ("F9", Key::F9), ("F10", Key::F10),
+    ("F11", Key::F11), ("F12", Key::F12),
+    ("Down", Key::Down), ("Left", Key::Left), ("Right", Key::Right), ("Up", Key::Up),
+    ("Apostrophe", Key::Apostrophe), ("Backquote", Key::Backquote), ("Backslash", Key::Backslash),
+    ("Comma", Key::Comma), ("Equal", Key::Equal), ("LeftBracket", Key::LeftBracket),
+    ("Minus", Key::Minus), ("Period", Key::Period), ("RightBracket", Key::RightBracket),
+    ("Semicolon", Key::Semicolon), ("Slash", Key::Slash),
+    ("Backspace", Key::Backspace), ("Delete", Key::Delete), ("End", Key::End),
+    ("Enter", Key::Enter), ("Escape", Key::Escape), ("Home", Key::Home), ("Insert", Key::Insert),
+    ("PageDown", Key::PageDown), ("PageUp", Key::PageUp), ("Space", Key::Space), ("Tab", Key::Tab),
+    ("LeftShift", Key::LeftShift), ("RightShift", Key::RightShift),
+    ("LeftCtrl", Key::LeftCtrl), ("RightCtrl", Key::RightCtrl),
+    ("LeftAlt", Key::LeftAlt), ("RightAlt", Key::RightAlt),
+    ("NumPad0", Key::NumPad0), ("NumPad1", Key::NumPad1), ("NumPad2", Key::NumPad2),
+    ("NumPad3", Key::NumPad3), ("NumPad4", Key::NumPad4), ("NumPad5", Key::NumPad5),
+    ("NumPad6", Key::NumPad6), ("NumPad7", Key::NumPad7), ("NumPad8", Key::NumPad8),
+    ("NumPad9", Key::NumPad9),
+];
+
+/// Parse the name of a `minifb::Key`.
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    KEY_NAMES
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, k)| *k)
+        .ok_or_else(|| format!("unknown keyboard key {}", name))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: Vec<(Key, JoypadKey)>,
+    // The keys of the buttons which autofire while held.
+    turbo: Vec<(Key, JoypadKey)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (Key::D, JoypadKey::Right),
+                (Key::W, JoypadKey::Up),
+                (Key::A, JoypadKey::Left),
+                (Key::S, JoypadKey::Down),
+                (Key::J, JoypadKey::A),
+                (Key::K, JoypadKey::B),
+                (Key::N, JoypadKey::Select),
+                (Key::M, JoypadKey::Start),
+            ],
+            turbo: vec![(Key::U, JoypadKey::A), (Key::I, JoypadKey::B)],
+        }
+    }
+}
+
+impl Keymap {
+    /// Load the keymap file, the buttons not in the file keep the default keys.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let s = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        s.parse()
+    }
+
+    pub fn get_bindings(&self) -> &[(Key, JoypadKey)] {
+        &self.bindings
+    }
+
+    /// Returns the keys bound to the button.
+    pub fn get_keys(&self, button: &JoypadKey) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|(_, b)| b == button)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    /// Returns the turbo keys of the button.
+    pub fn get_turbo_keys(&self, button: &JoypadKey) -> Vec<Key> {
+        self.turbo
+            .iter()
+            .filter(|(_, b)| b == button)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    /// Replace the keys of the button, a key is bound to one button only so it's taken from
+    /// the others.
+    pub fn bind(&mut self, button: JoypadKey, keys: &[Key]) {
+        self.turbo.retain(|(k, _)| !keys.contains(k));
+        Self::replace(&mut self.bindings, button, keys);
+    }
+
+    /// Replace the turbo keys of the button, like `bind`.
+    pub fn bind_turbo(&mut self, button: JoypadKey, keys: &[Key]) {
+        self.bindings.retain(|(k, _)| !keys.contains(k));
+        Self::replace(&mut self.turbo, button, keys);
+    }
+
+    fn replace(bindings: &mut Vec<(Key, JoypadKey)>, button: JoypadKey, keys: &[Key]) {
+        bindings.retain(|(k, b)| *b != button && !keys.contains(k));
+        for k in keys {
+            bindings.push((*k, button.clone()));
+        }
+        // Keep the order of `KEYS`, so the key of the same button is found in the same order.
+        bindings.sort_by_key(|(_, b)| KEYS.iter().position(|k| k == b));
+    }
+
+    /// Bind the keys to the button named in the keymap, e.g. `a` or `turbo-a`.
+    fn bind_name(&mut self, name: &str, keys: &[Key]) -> Result<(), String> {
+        let lower = name.to_lowercase();
+        match lower.strip_prefix("turbo-") {
+            Some(button) => self.bind_turbo(button.parse()?, keys),
+            None => self.bind(lower.parse()?, keys),
+        }
+        Ok(())
+    }
+
+    /// Apply a binding of the form `a=J,Z`.
+    pub fn apply(&mut self, binding: &str) -> Result<(), String> {
+        let mut parts = binding.splitn(2, '=');
+        let button = parts.next().unwrap_or("").trim();
+        let keys = parts.next().ok_or_else(|| {
+            format!(
+                "the binding {} should be <button>=<key>[,<key>...]",
+                binding
+            )
+        })?;
+        let keys = keys
+            .split(',')
+            .map(|k| parse_key(k.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.bind_name(button, &keys)
+    }
+
+    /// Returns the window adapter reading the keys with this keymap.
+    pub fn with_window<'a>(&'a self, window: &'a dyn Window) -> Keyboard<'a> {
+        Keyboard {
+            keymap: self,
+            window,
+        }
+    }
+}
+
+/// Parse the keymap file.
+impl FromStr for Keymap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut keymap = Self::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() || line == "[keymap]" {
+                continue;
+            }
+            let err = |e: String| format!("line {}: {}", i + 1, e);
+            let mut parts = line.splitn(2, '=');
+            let button = parts.next().unwrap().trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| err(format!("expect <button> = <keys>, found {}", line)))?
+                .trim();
+            let value = match value.strip_prefix('[') {
+                Some(v) => v
+                    .strip_suffix(']')
+                    .ok_or_else(|| err(format!("the list {} is not closed", value)))?,
+                None => value,
+            };
+            let keys = value
+                .split(',')
+                .map(|k| k.trim().trim_matches('"'))
+                .filter(|k| !k.is_empty())
+                .map(parse_key)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(err)?;
+            keymap.bind_name(button, &keys).map_err(err)?;
+        }
+        Ok(keymap)
+    }
+}
+
+/// The window reads the keyboard with the keymap.
+pub struct Keyboard<'a> {
+    keymap: &'a Keymap,
+    window: &'a dyn Window,
+}
+
+impl InputSource for Keyboard<'_> {
+    fn is_held(&mut self, key: &JoypadKey) -> bool {
+        self.keymap
+            .bindings
+            .iter()
+            .any(|(rk, vk)| vk == key && self.window.is_key_down(*rk))
+    }
+
+    fn is_turbo_held(&mut self, key: &JoypadKey) -> bool {
+        self.keymap
+            .turbo
+            .iter()
+            .any(|(rk, vk)| vk == key && self.window.is_key_down(*rk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("j"), Ok(Key::J));
+        assert_eq!(parse_key("enter"), Ok(Key::Enter));
+        assert_eq!(parse_key("7"), Ok(Key::Key7));
+        assert!(parse_key("Joystick").is_err());
+    }
+
+    #[test]
+    fn test_apply_binding() {
+        let mut keymap = Keymap::default();
+        keymap.apply("a=Z, Space").unwrap();
+        assert_eq!(keymap.get_keys(&JoypadKey::A), vec![Key::Z, Key::Space]);
+        // The key is moved from the button bound before.
+        keymap.apply("start=J").unwrap();
+        assert_eq!(keymap.get_keys(&JoypadKey::Start), vec![Key::J]);
+        keymap.apply("b=W").unwrap();
+        assert!(keymap.get_keys(&JoypadKey::Up).is_empty());
+        assert_eq!(keymap.get_keys(&JoypadKey::B), vec![Key::W]);
+
+        keymap.apply("turbo-a=Z").unwrap();
+        assert_eq!(keymap.get_turbo_keys(&JoypadKey::A), vec![Key::Z]);
+        assert_eq!(keymap.get_keys(&JoypadKey::A), vec![Key::Space]);
+        keymap.apply("select=Z").unwrap();
+        assert!(keymap.get_turbo_keys(&JoypadKey::A).is_empty());
+
+        assert!(keymap.apply("a").is_err());
+        assert!(keymap.apply("turbo=X").is_err());
+        assert!(keymap.apply("a=X,Joystick").is_err());
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let keymap: Keymap = "# arrows\n[keymap]\nup = [\"Up\", \"W\"]\nRight = \"Right\"\n\nstart = [\"Enter\"] # more\n"
+            .parse()
+            .unwrap();
+        assert_eq!(keymap.get_keys(&JoypadKey::Up), vec![Key::Up, Key::W]);
+        assert_eq!(keymap.get_keys(&JoypadKey::Right), vec![Key::Right]);
+        assert_eq!(keymap.get_keys(&JoypadKey::Start), vec![Key::Enter]);
+        assert_eq!(keymap.get_keys(&JoypadKey::A), vec![Key::J]);
+        assert_eq!(keymap.get_turbo_keys(&JoypadKey::B), vec![Key::I]);
+
+        let err = "a = J\nb = [\"K\"\n".parse::<Keymap>().unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+        assert!("select\n".parse::<Keymap>().is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! The logger of the emulator, it writes to the stdout and optionally into a file. The level is
+//! set for the whole emulator and overridden for the modules, e.g. `warn,cpu=debug` only shows
+//! the warnings and the errors, but every debug message of the cpu.
+//!
+//! The logger itself is in the `native` feature, the build without it only parses the levels of
+//! the config and leaves the logger to the host.
+use std::str::FromStr;
+
+#[cfg(feature = "native")]
+use fern::colors::{Color, ColoredLevelConfig};
+use log::LevelFilter;
+
+#[cfg(feature = "native")]
+use super::config::Config;
+
+/// The default file the log is written into.
+pub const DEFAULT_LOG_FILE: &str = "output.log";
+
+/// The modules which have their own log level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogModule {
+    Cpu,
+    Gpu,
+    Mmu,
+    Cartridge,
+}
+
+impl LogModule {
+    /// Returns the log target of the module, the submodules are included.
+    pub fn get_target(self) -> &'static str {
+        match self {
+            Self::Cpu => concat!(env!("CARGO_PKG_NAME"), "::gameboy::cpu"),
+            Self::Gpu => concat!(env!("CARGO_PKG_NAME"), "::gameboy::graphics"),
+            Self::Mmu => concat!(env!("CARGO_PKG_NAME"), "::gameboy::mmu"),
+            Self::Cartridge => concat!(env!("CARGO_PKG_NAME"), "::gameboy::cartridge"),
+        }
+    }
+}
+
+impl FromStr for LogModule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Self::Cpu),
+            "gpu" => Ok(Self::Gpu),
+            "mmu" => Ok(Self::Mmu),
+            "cartridge" => Ok(Self::Cartridge),
+            _ => Err(format!("unknown log module {}", s)),
+        }
+    }
+}
+
+/// The log level of the emulator and the ones of the modules, parsed from `info,cpu=debug`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLevels {
+    default: LevelFilter,
+    modules: Vec<(LogModule, LevelFilter)>,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Info,
+            modules: vec![],
+        }
+    }
+}
+
+impl LogLevels {
+    pub fn get_default(&self) -> LevelFilter {
+        self.default
+    }
+
+    pub fn set_default(&mut self, level: LevelFilter) {
+        self.default = level;
+    }
+
+    pub fn get_modules(&self) -> &[(LogModule, LevelFilter)] {
+        &self.modules
+    }
+
+    /// Override the level of the module, the last one set wins.
+    pub fn set_module(&mut self, module: LogModule, level: LevelFilter) {
+        self.modules.retain(|(m, _)| *m != module);
+        self.modules.push((module, level));
+    }
+
+    /// Returns the level of the log target.
+    pub fn get_level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(m, _)| {
+                let prefix = m.get_target();
+                target == prefix
+                    || target.starts_with(prefix) && target[prefix.len()..].starts_with("::")
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
+
+impl FromStr for LogLevels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_level =
+            |v: &str| LevelFilter::from_str(v).map_err(|_| format!("unknown log level {}", v));
+        let mut levels = Self::default();
+        for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            match item.split_once('=') {
+                Some((module, level)) => levels.set_module(module.parse()?, parse_level(level)?),
+                None => levels.set_default(parse_level(item)?),
+            }
+        }
+        Ok(levels)
+    }
+}
+
+/// Install the logger with the levels and the log file of the config. It can only be installed
+/// once in a process.
+#[cfg(feature = "native")]
+pub fn setup_logger(config: &Config) -> Result<(), fern::InitError> {
+    let colors = ColoredLevelConfig::new()
+        .info(Color::BrightGreen)
+        .error(Color::BrightRed)
+        .warn(Color::BrightYellow)
+        .debug(Color::BrightMagenta);
+
+    let levels = config.get_log_levels();
+    let mut dispatch = fern::Dispatch::new()
+        .format(move |out, message, record| {
+            out.finish(format_args!(
+                "[{}] {} - {:<36}: {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                colors.color(record.level()),
+                record.target(),
+                message
+            ))
+        })
+        .level(levels.get_default());
+    for (module, level) in levels.get_modules() {
+        dispatch = dispatch.level_for(module.get_target(), *level);
+    }
+    dispatch = dispatch.chain(std::io::stdout());
+    if let Some(path) = config.get_log_file() {
+        dispatch = dispatch.chain(fern::log_file(path)?);
+    }
+    dispatch.apply()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_levels() {
+        let levels: LogLevels = "warn, cpu=debug,gpu=off".parse().unwrap();
+        assert_eq!(levels.get_default(), LevelFilter::Warn);
+        let cpu = levels.get_level("NGC224::gameboy::cpu::cpu");
+        assert_eq!(cpu, LevelFilter::Debug);
+        let gpu = levels.get_level("NGC224::gameboy::graphics");
+        assert_eq!(gpu, LevelFilter::Off);
+        assert_eq!(levels.get_level("NGC224::gameboy::cpus"), LevelFilter::Warn);
+        assert_eq!(levels.get_level("NGC224::gameboy::mmu"), LevelFilter::Warn);
+
+        let levels: LogLevels = "cpu=info,cpu=TRACE".parse().unwrap();
+        assert_eq!(levels.get_default(), LevelFilter::Info);
+        assert_eq!(levels.get_modules(), [(LogModule::Cpu, LevelFilter::Trace)]);
+
+        assert!("loud".parse::<LogLevels>().is_err());
+        assert!("apu=debug".parse::<LogLevels>().is_err());
+    }
+}
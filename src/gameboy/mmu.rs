@@ -1,11 +1,22 @@
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
+use super::bootrom::BootRom;
 use super::cartridge::Cartridge;
-use super::cpu::IntReg;
+use super::cheat::Cheats;
+use super::debug::Watchpoints;
+use super::script::WriteLog;
+use super::cpu::{IntFlag, IntReg};
+use super::diagnostics::IoDiagnostics;
+use super::profiler::MemoryProfiler;
+use super::dma::{Bus, Hdma, OamDma, HDMA_BLOCK_CYCLES, HDMA_BLOCK_LEN};
 use super::graphics::gpu::GPU;
 use super::joypad::Joypad;
+use super::serial::Serial;
+use super::sound::Apu;
+use super::state::{StateError, StateReader, StateWriter, Stateful};
 use super::timer::Timer;
+use super::util::splitmix64;
 // use std::fmt::Debug;
 
 pub trait IOHandler {
@@ -17,13 +28,117 @@ pub trait IOHandler {
 
     /// Read a double byte.
     fn read_word(&self, a: u16) -> u16 {
-        u16::from(self.read_byte(a)) | (u16::from(self.read_byte(a + 1)) << 8)
+        u16::from(self.read_byte(a)) | (u16::from(self.read_byte(a.wrapping_add(1))) << 8)
     }
 
     /// Write a double byte.
     fn write_word(&mut self, a: u16, v: u16) {
         self.write_byte(a, (v & 0xFF) as u8);
-        self.write_byte(a + 1, (v >> 8) as u8)
+        self.write_byte(a.wrapping_add(1), (v >> 8) as u8)
+    }
+
+    /// Clear the request in IF, like the CPU does when it dispatches the interrupt.
+    fn ack_interrupt(&mut self, flag: IntFlag) {
+        let intf = self.read_byte(0xff0f);
+        self.write_byte(0xff0f, intf & !flag.mask());
+    }
+}
+
+/// The shared memory of the tests and the tools, it's locked on every access.
+impl<T: IOHandler + ?Sized> IOHandler for Arc<Mutex<T>> {
+    fn read_byte(&self, a: u16) -> u8 {
+        self.lock().unwrap().read_byte(a)
+    }
+
+    fn write_byte(&mut self, a: u16, v: u8) {
+        self.lock().unwrap().write_byte(a, v)
+    }
+
+    fn read_word(&self, a: u16) -> u16 {
+        self.lock().unwrap().read_word(a)
+    }
+
+    fn write_word(&mut self, a: u16, v: u16) {
+        self.lock().unwrap().write_word(a, v)
+    }
+
+    fn ack_interrupt(&mut self, flag: IntFlag) {
+        self.lock().unwrap().ack_interrupt(flag)
+    }
+}
+
+/// The bus owned by the CPU. The mmu is called directly, so the access to the memory of the
+/// emulator costs no borrow check and no virtual call, any other memory goes through the trait.
+/// The bus is `Send`, so the CPU can be moved to another thread.
+pub enum DataBus {
+    Mmu(Box<Mmunit>),
+    Other(Box<dyn IOHandler + Send>),
+}
+
+impl DataBus {
+    pub fn as_mmu(&self) -> Option<&Mmunit> {
+        match self {
+            DataBus::Mmu(mmu) => Some(mmu),
+            DataBus::Other(_) => None,
+        }
+    }
+
+    pub fn as_mmu_mut(&mut self) -> Option<&mut Mmunit> {
+        match self {
+            DataBus::Mmu(mmu) => Some(mmu),
+            DataBus::Other(_) => None,
+        }
+    }
+}
+
+impl From<Mmunit> for DataBus {
+    fn from(mmu: Mmunit) -> Self {
+        DataBus::Mmu(Box::new(mmu))
+    }
+}
+
+impl<T: IOHandler + Send + ?Sized + 'static> From<Arc<Mutex<T>>> for DataBus {
+    fn from(mem: Arc<Mutex<T>>) -> Self {
+        DataBus::Other(Box::new(mem))
+    }
+}
+
+impl IOHandler for DataBus {
+    #[inline]
+    fn read_byte(&self, a: u16) -> u8 {
+        match self {
+            DataBus::Mmu(mmu) => mmu.read_byte(a),
+            DataBus::Other(mem) => mem.read_byte(a),
+        }
+    }
+
+    #[inline]
+    fn write_byte(&mut self, a: u16, v: u8) {
+        match self {
+            DataBus::Mmu(mmu) => mmu.write_byte(a, v),
+            DataBus::Other(mem) => mem.write_byte(a, v),
+        }
+    }
+
+    fn read_word(&self, a: u16) -> u16 {
+        match self {
+            DataBus::Mmu(mmu) => mmu.read_word(a),
+            DataBus::Other(mem) => mem.read_word(a),
+        }
+    }
+
+    fn write_word(&mut self, a: u16, v: u16) {
+        match self {
+            DataBus::Mmu(mmu) => mmu.write_word(a, v),
+            DataBus::Other(mem) => mem.write_word(a, v),
+        }
+    }
+
+    fn ack_interrupt(&mut self, flag: IntFlag) {
+        match self {
+            DataBus::Mmu(mmu) => mmu.ack_interrupt(flag),
+            DataBus::Other(mem) => mem.ack_interrupt(flag),
+        }
     }
 }
 
@@ -44,39 +159,74 @@ pub trait IOHandler {
 ///
 pub struct Mmunit {
     pub cartridge: Box<dyn Cartridge>,
-    // TODO: apu
-    pub gpu: Rc<RefCell<GPU>>,
+    pub apu: Apu,
+    pub gpu: RefCell<GPU>,
     pub joypad: Joypad,
     pub timer: Timer,
+    pub serial: Serial,
+    pub cheats: Cheats,
+    // Mapped over the cartridge until FF50 is written, `None` starts from the post boot state.
+    pub boot_rom: Option<BootRom>,
     // Interrupts Enable Register (IE)
     inte: u8,
-    intf: Rc<RefCell<IntReg>>,
+    intf: Arc<IntReg>,
     // High ram
     hram: [u8; 0x7f],
     // Work ram
     wram: [u8; 0x8000],
     // CGB wram bank mapping 0xFF70
     wram_bank: usize,
+    // FF4D - KEY1 - CGB Mode Only - Prepare Speed Switch
+    // Bit 7: Current Speed     (0=Normal, 1=Double) (Read Only)
+    // Bit 0: Prepare Speed Switch (0=No, 1=Prepare) (Read/Write)
+    double_speed: bool,
+    speed_switch: bool,
+    dma: OamDma,
+    hdma: Hdma,
+    // The CPU cycles the CPU is stopped for by the VRAM DMA.
+    hdma_stall: u32,
+    // The accesses to the IO registers which are not emulated yet.
+    pub diagnostics: RefCell<IoDiagnostics>,
+    // The debugger breaks once the memory in any of them is touched.
+    pub watchpoints: Watchpoints,
+    // The writes to the ranges of the script hooks.
+    pub write_log: WriteLog,
+    // Counts the accesses of every page and rom bank, `None` counts nothing.
+    pub profiler: Option<RefCell<MemoryProfiler>>,
 }
 
 impl Mmunit {
     pub fn new(
         cart: Box<dyn Cartridge>,
-        gpu: Rc<RefCell<GPU>>,
+        apu: Apu,
+        gpu: GPU,
         joypad: Joypad,
         timer: Timer,
-        intf: Rc<RefCell<IntReg>>,
+        intf: Arc<IntReg>,
     ) -> Self {
         let mut r = Self {
             cartridge: cart,
-            gpu,
+            apu,
+            gpu: RefCell::new(gpu),
             joypad,
             timer,
+            serial: Serial::new(intf.clone()),
+            cheats: Cheats::new(),
+            boot_rom: None,
             intf,
             inte: 0x00,
             hram: [0x00; 0x7f],
             wram: [0x00; 0x8000],
             wram_bank: 0x01,
+            double_speed: false,
+            speed_switch: false,
+            dma: OamDma::new(),
+            hdma: Hdma::new(),
+            hdma_stall: 0,
+            diagnostics: RefCell::new(IoDiagnostics::new()),
+            watchpoints: Watchpoints::new(),
+            write_log: WriteLog::new(),
+            profiler: None,
         };
         r.set_initial();
         r
@@ -150,45 +300,267 @@ impl Mmunit {
 }
 
 impl Mmunit {
+    /// Fill the work ram and the high ram. The power on content is random on the hardware, a game
+    /// relying on it only replays with the same seed. `None` zeroes the ram.
+    pub fn init_ram(&mut self, seed: Option<u64>) {
+        match seed {
+            Some(mut state) => {
+                for b in self.wram.iter_mut().chain(self.hram.iter_mut()) {
+                    *b = splitmix64(&mut state) as u8;
+                }
+            }
+            None => {
+                self.wram = [0x00; 0x8000];
+                self.hram = [0x00; 0x7f];
+            }
+        }
+    }
+
+    /// Map the boot rom, the power on starts from it instead of the post boot state. The LCD is off
+    /// at the power on, the boot rom turns it on.
+    pub fn set_boot_rom(&mut self, boot_rom: BootRom) {
+        self.boot_rom = Some(boot_rom);
+        self.write_byte(0xff40, 0x00);
+    }
+
+    /// Returns the CGB work ram bank mapped at D000-DFFF, it's 1 on the DMG.
+    pub fn get_wram_bank(&self) -> usize {
+        self.wram_bank
+    }
+
+    pub fn is_boot_rom_mapped(&self) -> bool {
+        self.boot_rom.as_ref().is_some_and(BootRom::is_mapped)
+    }
+
+    fn stub_read(&self, a: u16) -> u8 {
+        self.diagnostics.borrow_mut().record(a, false);
+        0x00
+    }
+
+    fn stub_write(&mut self, a: u16) {
+        self.diagnostics.get_mut().record(a, true);
+    }
+
+    /// The CGB registers follow the mode of the GPU.
+    fn is_cgb(&self) -> bool {
+        self.gpu.borrow().is_cgb()
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// Switch the CPU speed if it's prepared through KEY1, the CPU calls it on STOP.
+    /// Returns true if the speed is switched.
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch {
+            return false;
+        }
+        self.speed_switch = false;
+        self.double_speed = !self.double_speed;
+        true
+    }
+
+    /// Write the bytes of the GameShark codes, the game reads them back as its own.
+    fn write_cheats(&mut self) {
+        for (bank, addr, value) in self.cheats.get_ram_writes() {
+            match bank {
+                Some(bank) => self.wram[addr as usize - 0xd000 + 0x1000 * bank] = value,
+                None => self.write_byte(addr, value),
+            }
+        }
+    }
+
+    /// Copy the blocks of the VRAM DMA, the CPU is stopped meanwhile.
+    fn copy_hdma_blocks(&mut self, n: u16) {
+        for _ in 0..n {
+            let (src, dst) = self.hdma.next_block();
+            for i in 0..HDMA_BLOCK_LEN {
+                let b = self.read_raw(src.wrapping_add(i));
+                self.gpu.borrow_mut().write_byte(0x8000 | (dst + i), b);
+            }
+            self.hdma_stall += HDMA_BLOCK_CYCLES << self.double_speed as u32;
+        }
+    }
+
+    /// Step the components by the CPU cycles. The timer follows the CPU clock, while the GPU and
+    /// the APU keep the normal speed, so they get half of the cycles in double speed mode.
+    /// The cycles the CPU is stopped by the VRAM DMA are stepped here too.
+    /// Returns the cycles in the normal speed.
     pub fn next(&mut self, cycles: u32) -> u32 {
+        let mut normal_cycles = self.step(cycles);
+        while self.hdma_stall != 0 {
+            let stall = std::mem::take(&mut self.hdma_stall);
+            normal_cycles += self.step(stall);
+        }
+        normal_cycles
+    }
+
+    fn step(&mut self, cycles: u32) -> u32 {
+        let normal_cycles = if self.double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
         self.timer.next(cycles);
-        self.gpu.borrow_mut().next(cycles);
-        cycles
+        self.serial.next(cycles);
+        // The DMA follows the CPU clock too.
+        for i in self.dma.next(cycles) {
+            let b = self.read_raw(self.dma.get_source(i));
+            self.gpu.borrow_mut().write_byte(0xfe00 + i, b);
+        }
+        self.apu.next(normal_cycles);
+        self.gpu.borrow_mut().next(normal_cycles);
+        let hblank = self.gpu.borrow_mut().take_hblank();
+        if hblank && self.hdma.is_active() {
+            self.copy_hdma_blocks(1);
+        }
+        let vblank = self.gpu.borrow_mut().take_vblank();
+        if vblank {
+            self.write_cheats();
+        }
+        normal_cycles
     }
 }
 
-impl IOHandler for Mmunit {
-    fn read_byte(&self, a: u16) -> u8 {
+/// Only the memory owned by the mmu is saved here, the components keep their own sections.
+impl Stateful for Mmunit {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u8(self.inte);
+        w.put_u8(self.intf.get() & 0x1f);
+        w.put_u8(self.wram_bank as u8);
+        w.put_bytes(&self.hram);
+        w.put_bytes(&self.wram);
+        w.put_bool(self.double_speed);
+        w.put_bool(self.speed_switch);
+        self.dma.save_state(w);
+        self.hdma.save_state(w);
+        w.put_u32(self.hdma_stall);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.inte = r.get_u8()?;
+        self.intf.set(r.get_u8()?);
+        self.wram_bank = r.get_u8()? as usize;
+        r.fill(&mut self.hram)?;
+        r.fill(&mut self.wram)?;
+        self.double_speed = r.get_bool()?;
+        self.speed_switch = r.get_bool()?;
+        self.dma.load_state(r)?;
+        self.hdma.load_state(r)?;
+        self.hdma_stall = r.get_u32()?;
+        Ok(())
+    }
+}
+
+impl Mmunit {
+    pub fn is_dma_active(&self) -> bool {
+        self.dma.is_active()
+    }
+
+    /// The CPU can't reach the OAM and the bus used by the running DMA, it reads the byte being
+    /// copied from the bus instead. Returns `None` if the address is reachable.
+    fn dma_conflict(&self, a: u16) -> Option<u8> {
+        if !self.dma.is_active() {
+            return None;
+        }
+        match a {
+            0xfe00..=0xfeff => Some(0xff),
+            _ if Bus::of(a) == Bus::Internal => None,
+            _ if Bus::of(a) == Bus::of(self.dma.get_current()) => {
+                Some(self.read_raw(self.dma.get_current()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the memory without the DMA blocking, the DMA itself reads through it.
+    fn read_raw(&self, a: u16) -> u8 {
+        if let Some(v) = self.boot_rom.as_ref().and_then(|b| b.get(a)) {
+            return v;
+        }
         match a {
             0x0000..=0x7fff => self.cartridge.read_byte(a),
             0x8000..=0x9fff => self.gpu.borrow().read_byte(a),
             0xa000..=0xbfff => self.cartridge.read_byte(a),
             0xc000..=0xcfff => self.wram[a as usize - 0xc000],
             0xd000..=0xdfff => self.wram[a as usize - 0xd000 + 0x1000 * self.wram_bank],
-            0xe000..=0xefff => self.wram[a as usize - 0xe000],
-            0xf000..=0xfdff => self.wram[a as usize - 0xf000 + 0x1000 * self.wram_bank],
+            // The echo ram mirrors C000-DDFF with the bank selected.
+            0xe000..=0xfdff => self.read_raw(a - 0x2000),
             0xfe00..=0xfe9f => self.gpu.borrow().read_byte(a),
-            0xfea0..=0xfeff => 0x00,
+            0xfea0..=0xfeff => self.gpu.borrow_mut().read_prohibited(a),
             0xff00 => self.joypad.read_byte(a),
-            0xff01..=0xff02 => 0x00, // TODO: serial
+            0xff01..=0xff02 => self.serial.get(a),
             0xff04..=0xff07 => self.timer.get(a),
-            0xff0f => self.intf.borrow().data,
-            0xff10..=0xff3f => 0x00, // TODO: APU
-            0xff4d => 0x00,          // FF4D - KEY1 - CGB Mode Only - Prepare Speed Switch
+            0xff0f => self.intf.get(),
+            0xff10..=0xff3f => self.apu.read_byte(a),
+            0xff4d if self.is_cgb() => {
+                ((self.double_speed as u8) << 7) | 0x7e | self.speed_switch as u8
+            }
+            0xff4d => 0xff,
             0xff40..=0xff45 | 0xff47..=0xff4b | 0xff4f => self.gpu.borrow().read_byte(a),
-            0xff51..=0xff55 => 0x00, // HDMA CGB
+            0xff46 => self.dma.get(),
+            0xff50 => 0xff,
+            0xff51..=0xff55 if self.is_cgb() => self.hdma.get(a),
+            0xff51..=0xff55 => 0xff,
+            0xff56 => self.stub_read(a), // FF56 - RP - CGB Mode Only - Infrared Communications Port
             0xff68..=0xff6b => self.gpu.borrow().read_byte(a),
-            0xff70 => self.wram_bank as u8,
+            // The unused bits of SVBK read as 1.
+            0xff70 if self.is_cgb() => self.wram_bank as u8 | 0xf8,
+            0xff70 => 0xff,
             0xff80..=0xfffe => self.hram[a as usize - 0xff80],
             0xffff => self.inte,
-            _ => 0x00,
+            // Nothing drives the data bus at the unmapped registers.
+            _ => 0xff,
+        }
+    }
+}
+
+impl Mmunit {
+    /// Count the access for the profiler, the switchable rom at 4000-7FFF is counted by the bank
+    /// mapped there.
+    fn profile(&self, a: u16, write: bool) {
+        if let Some(profiler) = self.profiler.as_ref() {
+            let bank = match a {
+                0x0000..=0x3fff => Some(0),
+                0x4000..=0x7fff => Some(self.cartridge.get_mapper_state().rom_bank),
+                _ => None,
+            };
+            profiler.borrow_mut().record(a, write, bank);
         }
     }
 
+    /// Pause the profiler around the reads of the debugger.
+    pub fn pause_profiler(&self, paused: bool) {
+        if let Some(profiler) = self.profiler.as_ref() {
+            profiler.borrow_mut().set_paused(paused);
+        }
+    }
+}
+
+impl IOHandler for Mmunit {
+    fn read_byte(&self, a: u16) -> u8 {
+        let v = self.dma_conflict(a).unwrap_or_else(|| self.read_raw(a));
+        // The Game Genie sits between the cartridge and the cpu.
+        let v = match a {
+            0x0000..=0x7fff if !self.is_boot_rom_mapped() => self.cheats.patch_rom(a, v),
+            _ => v,
+        };
+        self.watchpoints.check(a, v, false);
+        self.profile(a, false);
+        v
+    }
+
     fn write_byte(&mut self, a: u16, v: u8) {
         // if a == 65348 {
         //     debug!("mmu write byte hook 65348 => {}", v);
         // }
+        self.watchpoints.check(a, v, true);
+        self.profile(a, true);
+        if self.dma_conflict(a).is_some() {
+            return;
+        }
+        self.write_log.check(a, v);
         match a {
             0x0000..=0x7fff => self.cartridge.write_byte(a, v),
             0x8000..=0x9fff => {
@@ -200,27 +572,35 @@ impl IOHandler for Mmunit {
             0xe000..=0xefff => self.wram[a as usize - 0xe000] = v,
             0xf000..=0xfdff => self.wram[a as usize - 0xf000 + 0x1000 * self.wram_bank] = v,
             0xfe00..=0xfe9f => self.gpu.borrow_mut().write_byte(a, v),
-            0xfea0..=0xfeff => {}
+            0xfea0..=0xfeff => self.gpu.borrow_mut().write_prohibited(a),
             0xff00 => self.joypad.write_byte(a, v),
-            0xff01..=0xff02 => {} // TODO: serial
+            0xff01..=0xff02 => self.serial.set(a, v),
             0xff04..=0xff07 => self.timer.set(a, v),
-            0xff10..=0xff3f => {} // TODO: apu
-            0xff46 => {
-                // DMA
-                // http://www.codeslinger.co.uk/pages/projects/gameboy/dma.html
-                // See: http://gbdev.gg8.se/wiki/articles/Video_Display#FF46_-_DMA_-_DMA_Transfer_and_Start_Address_.28R.2FW.29
-                let base_addr = u16::from(v) << 8;
-                for i in 0..0xa0 {
-                    let b = self.read_byte(base_addr + i);
-                    self.write_byte(0xfe00 + i, b);
+            0xff10..=0xff3f => self.apu.write_byte(a, v),
+            // See: http://gbdev.gg8.se/wiki/articles/Video_Display#FF46_-_DMA_-_DMA_Transfer_and_Start_Address_.28R.2FW.29
+            0xff46 => self.dma.set(v),
+            0xff4d if self.is_cgb() => self.speed_switch = v & 0x01 != 0,
+            0xff4d => {}
+            0xff40..=0xff45 | 0xff47..=0xff4b | 0xff4f => self.gpu.borrow_mut().write_byte(a, v),
+            0xff50 if v != 0x00 => {
+                if let Some(boot_rom) = self.boot_rom.as_mut() {
+                    boot_rom.unmap();
                 }
             }
-            0xff4d => {} // FF4D - KEY1 - CGB Mode Only - Prepare Speed Switch
-            0xff40..=0xff45 | 0xff47..=0xff4b | 0xff4f => self.gpu.borrow_mut().write_byte(a, v),
-            0xff51..=0xff55 => {} //
+            0xff50 => {}
+            0xff51..=0xff55 if self.is_cgb() => {
+                let n = self.hdma.set(a, v);
+                self.copy_hdma_blocks(n);
+                // Without the LCD there's no HBlank, the first block is copied at once.
+                if a == 0xff55 && self.hdma.is_active() && !self.gpu.borrow().is_lcd_enabled() {
+                    self.copy_hdma_blocks(1);
+                }
+            }
+            0xff51..=0xff55 => {}
+            0xff56 => self.stub_write(a), // FF56 - RP - CGB Mode Only - Infrared Communications Port
             0xff68..=0xff6b => self.gpu.borrow_mut().write_byte(a, v),
-            0xff0f => self.intf.borrow_mut().data = v,
-            0xff70 => {
+            0xff0f => self.intf.set(v),
+            0xff70 if self.is_cgb() => {
                 // In CGB Mode 32 KBytes internal RAM are available.
                 // This memory is divided into 8 banks of 4 KBytes each.
                 // Bank 0 is always available in memory at C000-CFFF,
@@ -231,9 +611,15 @@ impl IOHandler for Mmunit {
                     n => n as usize,
                 };
             }
+            0xff70 => {}
             0xff80..=0xfffe => self.hram[a as usize - 0xff80] = v,
             0xffff => self.inte = v,
             _ => {}
         }
     }
+
+    // The dispatch clears IF without a write, the watchpoints and the write hooks miss it.
+    fn ack_interrupt(&mut self, flag: IntFlag) {
+        self.intf.ack(flag);
+    }
 }
@@ -1,18 +1,47 @@
+pub mod bootrom;
+pub mod browser;
 pub mod cartridge;
+pub mod cheat;
+pub mod clock;
 pub mod config;
 pub mod cpu;
 pub mod debug;
+pub mod diagnostics;
+pub mod disasm;
+pub mod dma;
 pub mod emulator;
+pub mod error;
+pub mod frontend;
+pub mod gamepad;
 pub mod graphics;
+pub mod history;
 pub mod joypad;
+#[cfg(feature = "native")]
+pub mod keymap;
+pub mod logging;
 pub mod mmu;
+pub mod movie;
+pub mod osd;
+pub mod pacing;
+pub mod profiler;
+pub mod recorder;
+pub mod romcheck;
+pub mod screenshot;
+pub mod script;
+pub mod serial;
+pub mod sound;
 pub mod spec;
+pub mod state;
+pub mod stats;
+pub mod storage;
+pub mod testrom;
 pub mod timer;
 pub mod util;
 
 pub use config::Config;
 pub use cpu::{Register, CPU};
 pub use emulator::Emulator;
+pub use error::Error;
 pub use graphics::gpu;
 pub use mmu::IOHandler;
 pub use spec::*;
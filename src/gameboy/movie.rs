@@ -0,0 +1,148 @@
+//! The input movie container.
+//!
+//! A movie is the pressed keys of every frame since the power on, together with what is needed
+//! to boot the machine into the same state:
+//! ```ignore
+//! Offset  Size  Description
+//! 0       4     Magic "NGCM"
+//! 4       2     Format version, little endian
+//! 6       1     Flags, bit 0: the ram is randomized with the seed
+//! 7       8     Seed of the ram randomization, little endian
+//! 15      4     Count of frames
+//! 19      ...   One byte per frame, bit 0-7 are Right, Left, Up, Down, A, B, Select, Start
+//! ```
+use std::fs;
+use std::path::Path;
+
+use super::state::{StateError, StateReader, StateWriter};
+
+const MAGIC: &[u8; 4] = b"NGCM";
+pub const MOVIE_VERSION: u16 = 1;
+
+const FLAG_RANDOM_RAM: u8 = 0b0000_0001;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    // The seed of the ram randomization, `None` means the ram is zeroed.
+    ram_seed: Option<u64>,
+    frames: Vec<u8>,
+}
+
+impl Movie {
+    pub fn new(ram_seed: Option<u64>) -> Self {
+        Self {
+            ram_seed,
+            frames: vec![],
+        }
+    }
+
+    pub fn get_ram_seed(&self) -> Option<u64> {
+        self.ram_seed
+    }
+
+    /// Append the pressed keys of the next frame.
+    pub fn push(&mut self, keys: u8) {
+        self.frames.push(keys);
+    }
+
+    /// Returns the pressed keys of the frame, `None` once the movie ends.
+    pub fn get_frame(&self, i: usize) -> Option<u8> {
+        self.frames.get(i).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.put_bytes(MAGIC);
+        w.put_u16(MOVIE_VERSION);
+        w.put_u8(if self.ram_seed.is_some() {
+            FLAG_RANDOM_RAM
+        } else {
+            0x00
+        });
+        w.put_u64(self.ram_seed.unwrap_or(0));
+        w.put_u32(self.frames.len() as u32);
+        w.put_bytes(&self.frames);
+        w.into_bytes()
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, StateError> {
+        let mut r = StateReader::new(buf);
+        if r.get_bytes(4).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.get_u16()?;
+        if version != MOVIE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let flags = r.get_u8()?;
+        let seed = r.get_u64()?;
+        let n = r.get_u32()? as usize;
+        let frames = r.get_bytes(n)?.to_vec();
+        Ok(Self {
+            ram_seed: if flags & FLAG_RANDOM_RAM != 0 {
+                Some(seed)
+            } else {
+                None
+            },
+            frames,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_movie_roundtrip() {
+        for seed in [None, Some(0), Some(0xdead_beef_0123_4567)].iter().copied() {
+            let mut movie = Movie::new(seed);
+            movie.push(0x00);
+            movie.push(0x81);
+            movie.push(0xff);
+
+            let decoded = Movie::from_bytes(&movie.to_bytes()).unwrap();
+            assert_eq!(decoded, movie);
+            assert_eq!(decoded.get_ram_seed(), seed);
+            assert_eq!(decoded.get_frame(1), Some(0x81));
+            assert_eq!(decoded.get_frame(3), None);
+        }
+    }
+
+    #[test]
+    fn test_movie_rejects_broken_file() {
+        let mut movie = Movie::new(Some(7));
+        movie.push(0x01);
+        let mut bytes = movie.to_bytes();
+        assert!(matches!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(StateError::Truncated)
+        ));
+        assert!(matches!(
+            Movie::from_bytes(b"NGCS\x01\x00"),
+            Err(StateError::BadMagic)
+        ));
+        bytes[4] = 0x02;
+        assert!(matches!(
+            Movie::from_bytes(&bytes),
+            Err(StateError::UnsupportedVersion(2))
+        ));
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::{draw_text, fill_rect, CHAR_W, LINE_H};
+use crate::gameboy::frontend::Frame;
+use crate::gameboy::{SCREEN_H, SCREEN_W};
+
+/// How long a message stays on the screen by default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+// The messages shown at once, the oldest one is dropped for a new one.
+const MAX_MESSAGES: usize = 4;
+// The longer text is cut at the screen border.
+const MAX_CHARS: usize = (SCREEN_W - 1) / CHAR_W;
+
+const PANEL: [u8; 3] = [0xff, 0xff, 0xff];
+const TEXT: [u8; 3] = [0x00, 0x00, 0x00];
+
+/// Where the messages are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Self::TopLeft),
+            "top-right" => Ok(Self::TopRight),
+            "bottom-left" => Ok(Self::BottomLeft),
+            "bottom-right" => Ok(Self::BottomRight),
+            _ => Err(format!("unknown corner {}", s)),
+        }
+    }
+}
+
+/// The messages of the emulator drawn over the frame, e.g. "State saved". Each one disappears
+/// after the timeout, the FPS counter and the status lines are drawn above them and stay.
+pub struct MessageOverlay {
+    corner: Corner,
+    timeout: Duration,
+    // The text and the time it's shown, the newest is the last.
+    messages: VecDeque<(String, Instant)>,
+    // The frames presented in the last second, `None` hides the FPS.
+    frames: Option<VecDeque<Instant>>,
+    // The line which stays while it's set, e.g. the rumble motor.
+    indicator: Option<String>,
+    // The lines which stay until they're replaced, e.g. the statistics.
+    status: Vec<String>,
+    // The lines replaced every frame, e.g. the I/O registers.
+    panel: Vec<String>,
+}
+
+impl MessageOverlay {
+    pub fn new(corner: Corner, timeout: Duration) -> Self {
+        Self {
+            corner,
+            timeout,
+            messages: VecDeque::with_capacity(MAX_MESSAGES),
+            frames: None,
+            indicator: None,
+            status: vec![],
+            panel: vec![],
+        }
+    }
+
+    pub fn get_corner(&self) -> Corner {
+        self.corner
+    }
+
+    pub fn set_corner(&mut self, corner: Corner) {
+        self.corner = corner;
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn is_fps_enabled(&self) -> bool {
+        self.frames.is_some()
+    }
+
+    pub fn set_fps_enabled(&mut self, enabled: bool) {
+        self.frames = if enabled { Some(VecDeque::new()) } else { None };
+    }
+
+    /// Show the line below the FPS until it's unset.
+    pub fn set_indicator(&mut self, text: Option<&str>) {
+        self.indicator = text.map(str::to_string);
+    }
+
+    /// Replace the status lines, an empty list hides them.
+    pub fn set_status(&mut self, lines: Vec<String>) {
+        self.status = lines;
+    }
+
+    /// Replace the panel lines below the status lines, an empty list hides them.
+    pub fn set_panel(&mut self, lines: Vec<String>) {
+        self.panel = lines;
+    }
+
+    /// Show the message from the time until the timeout.
+    pub fn show(&mut self, text: &str, now: Instant) {
+        if self.messages.len() >= MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((text.to_string(), now));
+    }
+
+    /// Record a frame is presented at the time.
+    pub fn tick(&mut self, now: Instant) {
+        if let Some(frames) = self.frames.as_mut() {
+            frames.push_back(now);
+            while let Some(t) = frames.front() {
+                if now.duration_since(*t) < Duration::from_secs(1) {
+                    break;
+                }
+                frames.pop_front();
+            }
+        }
+    }
+
+    /// Returns the frames presented in the last second.
+    pub fn get_fps(&self) -> usize {
+        self.frames.as_ref().map_or(0, |f| f.len())
+    }
+
+    /// Returns the lines to draw at the time from the top, the expired messages are dropped.
+    pub fn get_lines(&mut self, now: Instant) -> Vec<String> {
+        let timeout = self.timeout;
+        self.messages
+            .retain(|(_, t)| now.saturating_duration_since(*t) < timeout);
+        let mut lines = vec![];
+        if self.is_fps_enabled() {
+            lines.push(format!("FPS {}", self.get_fps()));
+        }
+        let texts = self
+            .indicator
+            .iter()
+            .chain(self.status.iter())
+            .chain(self.panel.iter())
+            .chain(self.messages.iter().map(|(t, _)| t));
+        for text in texts {
+            lines.push(text.chars().take(MAX_CHARS).collect());
+        }
+        lines
+    }
+
+    /// Draw the lines onto the frame, each one on a white panel.
+    pub fn draw(&mut self, frame: &mut Frame, now: Instant) {
+        let lines = self.get_lines(now);
+        for (i, line) in lines.iter().enumerate() {
+            let w = line.chars().count() * CHAR_W + 1;
+            let x = match self.corner {
+                Corner::TopLeft | Corner::BottomLeft => 0,
+                Corner::TopRight | Corner::BottomRight => SCREEN_W - w,
+            };
+            let y = match self.corner {
+                Corner::TopLeft | Corner::TopRight => i * LINE_H,
+                Corner::BottomLeft | Corner::BottomRight => {
+                    SCREEN_H - 1 - (lines.len() - i) * LINE_H
+                }
+            };
+            fill_rect(frame, x, y, w, LINE_H + 1, PANEL);
+            draw_text(frame, x + 1, y + 1, line, TEXT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_expire() {
+        let start = Instant::now();
+        let mut osd = MessageOverlay::new(Corner::TopLeft, Duration::from_secs(2));
+        osd.show("State saved", start);
+        osd.show("Fast-forward ON", start + Duration::from_secs(1));
+        assert_eq!(
+            osd.get_lines(start + Duration::from_millis(1500)),
+            vec!["State saved", "Fast-forward ON"]
+        );
+        assert_eq!(
+            osd.get_lines(start + Duration::from_millis(2500)),
+            vec!["Fast-forward ON"]
+        );
+        assert!(osd.get_lines(start + Duration::from_secs(3)).is_empty());
+
+        for i in 0..6 {
+            osd.show(&format!("{}", i), start);
+        }
+        assert_eq!(osd.get_lines(start), vec!["2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn test_fps() {
+        let start = Instant::now();
+        let mut osd = MessageOverlay::new(Corner::TopLeft, DEFAULT_TIMEOUT);
+        osd.set_fps_enabled(true);
+        for i in 0..90 {
+            osd.tick(start + Duration::from_millis(i * 1000 / 60));
+        }
+        assert_eq!(osd.get_fps(), 60);
+        assert_eq!(osd.get_lines(start), vec!["FPS 60"]);
+        osd.set_status(vec!["cpu 40%".to_string()]);
+        osd.set_panel(vec!["LY 90".to_string()]);
+        osd.show("State saved", start);
+        assert_eq!(
+            osd.get_lines(start),
+            vec!["FPS 60", "cpu 40%", "LY 90", "State saved"]
+        );
+        osd.set_indicator(Some("RUMBLE"));
+        assert_eq!(osd.get_lines(start)[..2], ["FPS 60", "RUMBLE"]);
+        osd.set_indicator(None);
+        assert_eq!(osd.get_lines(start).len(), 4);
+    }
+
+    #[test]
+    fn test_draw_in_the_corner() {
+        let now = Instant::now();
+        let mut frame = Frame::new([0x80; 3]);
+        let mut osd = MessageOverlay::new(Corner::BottomRight, DEFAULT_TIMEOUT);
+        osd.show(&"X".repeat(40), now);
+        osd.draw(&mut frame, now);
+        // The long text fills the line and stays inside the screen.
+        assert_eq!(frame.get_pixel(SCREEN_W - 1, SCREEN_H - 1), PANEL);
+        assert_eq!(frame.get_pixel(SCREEN_W - 1, SCREEN_H - 1 - LINE_H), PANEL);
+        assert_eq!(
+            frame.get_pixel(SCREEN_W - 1, SCREEN_H - 2 - LINE_H),
+            [0x80; 3]
+        );
+        assert_eq!(frame.get_pixel(0, SCREEN_H - 1), [0x80; 3]);
+    }
+}
@@ -0,0 +1,55 @@
+pub mod font;
+pub mod message;
+pub mod perf;
+
+use font::{get_glyph, GLYPH_H, GLYPH_W};
+
+use crate::gameboy::frontend::Frame;
+use crate::gameboy::{SCREEN_H, SCREEN_W};
+
+/// Horizontal advance of a char, include 1 pixel spacing.
+pub const CHAR_W: usize = GLYPH_W + 1;
+/// Vertical advance of a text line, include 1 pixel spacing.
+pub const LINE_H: usize = GLYPH_H + 1;
+
+/// Draw the text into the frame, the top left corner is (x, y). Pixels out of screen are clipped.
+pub fn draw_text(frame: &mut Frame, x: usize, y: usize, text: &str, color: [u8; 3]) {
+    for (i, c) in text.chars().enumerate() {
+        let glyph = get_glyph(c);
+        for (col, bits) in glyph.iter().enumerate() {
+            for row in 0..GLYPH_H {
+                if bits & (1 << row) == 0 {
+                    continue;
+                }
+                let px = x + i * CHAR_W + col;
+                let py = y + row;
+                if px < SCREEN_W && py < SCREEN_H {
+                    frame.set_pixel(px, py, color);
+                }
+            }
+        }
+    }
+}
+
+/// Fill a rectangle with the color, clipped at the screen border.
+pub fn fill_rect(frame: &mut Frame, x: usize, y: usize, w: usize, h: usize, color: [u8; 3]) {
+    for py in y..(y + h).min(SCREEN_H) {
+        for px in x..(x + w).min(SCREEN_W) {
+            frame.set_pixel(px, py, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_text_clipped() {
+        let mut frame = Frame::default();
+        draw_text(&mut frame, SCREEN_W - 2, SCREEN_H - 2, "I", [0, 0, 0]);
+        // The vertical bar of `I` starts at the 2nd column, it's the last visible column.
+        assert_eq!(frame.get_pixel(SCREEN_W - 1, SCREEN_H - 2), [0, 0, 0]);
+        assert_eq!(frame.get_pixel(SCREEN_W - 2, SCREEN_H - 2), [0xff; 3]);
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::{draw_text, fill_rect, LINE_H};
+use crate::gameboy::frontend::Frame;
+use crate::gameboy::{CLOCK_FREQUENCY, FRAME_CYCLES, SCREEN_H, SCREEN_W};
+
+/// How many frames are kept in the graph, one frame per pixel column.
+pub const HISTORY_LEN: usize = 120;
+const GRAPH_H: usize = 24;
+// The top of the graph, two emulated frames.
+const GRAPH_MAX_MS: f64 = 2.0 * FRAME_MS;
+const FRAME_MS: f64 = FRAME_CYCLES as f64 * 1000.0 / CLOCK_FREQUENCY as f64;
+
+const PANEL: [u8; 3] = [0xff, 0xff, 0xff];
+const BAR: [u8; 3] = [0x60, 0x60, 0x60];
+const SLOW_BAR: [u8; 3] = [0x00, 0x00, 0x00];
+const TARGET_LINE: [u8; 3] = [0xc0, 0xc0, 0xc0];
+
+/// The performance overlay, it records the host time of the last frames and plots them in the
+/// bottom right corner, together with the emulation speed in percent.
+#[derive(Default)]
+pub struct PerfOverlay {
+    frame_times: VecDeque<Duration>,
+    last_frame: Option<Instant>,
+}
+
+impl PerfOverlay {
+    pub fn new() -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(HISTORY_LEN),
+            last_frame: None,
+        }
+    }
+
+    /// Record a frame is presented at the time.
+    pub fn tick(&mut self, now: Instant) {
+        if let Some(last) = self.last_frame {
+            self.push(now.duration_since(last));
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// Record the host time spent on a frame.
+    pub fn push(&mut self, frame_time: Duration) {
+        if self.frame_times.len() >= HISTORY_LEN {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+    }
+
+    /// Returns the emulation speed of the recorded frames, 100 means the real hardware speed.
+    pub fn get_speed_percent(&self) -> f64 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        let total: Duration = self.frame_times.iter().sum();
+        let avg_ms = total.as_secs_f64() * 1000.0 / self.frame_times.len() as f64;
+        if avg_ms == 0.0 {
+            return 0.0;
+        }
+        FRAME_MS / avg_ms * 100.0
+    }
+
+    /// Draw the graph onto the frame. A frame slower than the hardware is drawn in black.
+    pub fn draw(&self, frame: &mut Frame) {
+        let left = SCREEN_W - HISTORY_LEN;
+        let bottom = SCREEN_H - 1;
+        let top = bottom - GRAPH_H;
+
+        let panel_h = GRAPH_H + LINE_H + 1;
+        fill_rect(frame, left, top - LINE_H, HISTORY_LEN, panel_h, PANEL);
+        let speed = format!("{:.0}%", self.get_speed_percent());
+        draw_text(frame, left + 1, top - LINE_H, &speed, SLOW_BAR);
+
+        let target = bottom - (FRAME_MS / GRAPH_MAX_MS * GRAPH_H as f64) as usize;
+        fill_rect(frame, left, target, HISTORY_LEN, 1, TARGET_LINE);
+
+        // The newest frame is at the right border.
+        let offset = HISTORY_LEN - self.frame_times.len();
+        for (i, t) in self.frame_times.iter().enumerate() {
+            let ms = t.as_secs_f64() * 1000.0;
+            let h = ((ms / GRAPH_MAX_MS).min(1.0) * GRAPH_H as f64).round() as usize;
+            let color = if ms > FRAME_MS * 1.05 { SLOW_BAR } else { BAR };
+            fill_rect(frame, left + offset + i, bottom + 1 - h, 1, h, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut perf = PerfOverlay::new();
+        for _ in 0..HISTORY_LEN * 2 {
+            perf.push(Duration::from_secs_f64(FRAME_MS / 2000.0));
+        }
+        assert_eq!(perf.frame_times.len(), HISTORY_LEN);
+        // Frames are twice as fast as the hardware.
+        assert_eq!(perf.get_speed_percent().round(), 200.0);
+    }
+}
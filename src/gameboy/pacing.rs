@@ -0,0 +1,269 @@
+//! The frame pacing of the speed simulation. The emulator runs a whole frame, then sleeps until
+//! the frame is due on the hardware clock, about 59.73 frames per second.
+//!
+//! The deadline moves by the exact time of every frame instead of restarting from the wake up,
+//! so the host oversleeping a little doesn't add up over the frames. If the host falls far
+//! behind, e.g. the window is dragged, the lost time is dropped instead of running fast to
+//! catch up.
+//!
+//! The audio sync paces by the samples queued for the audio device instead. The device plays
+//! them at the exact rate, so the emulation sleeps while the queue holds more than
+//! `AUDIO_LATENCY`. The tempo never drifts from the device clock and the queue doesn't run dry.
+use std::str::FromStr;
+#[cfg(feature = "native")]
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{CLOCK_FREQUENCY, FRAME_CYCLES};
+
+// How many frames the host may fall behind before the deadline restarts from now.
+const MAX_LAG_FRAMES: u32 = 4;
+/// The audio the audio sync keeps queued after the sleep, a frame is added on top of it.
+pub const AUDIO_LATENCY: Duration = Duration::from_millis(50);
+
+/// What the speed simulation paces the frames by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    // The hardware frame rate.
+    Video,
+    // The fill level of the audio queue, it falls back to the video without a queue.
+    Audio,
+    // As fast as possible.
+    Uncapped,
+}
+
+impl FromStr for SyncMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "video" => Ok(Self::Video),
+            "audio" => Ok(Self::Audio),
+            "uncapped" => Ok(Self::Uncapped),
+            _ => Err(format!("unknown sync mode {}", s)),
+        }
+    }
+}
+
+pub struct FramePacer {
+    mode: SyncMode,
+    // Run this many times of the real hardware speed, it's 1 unless fast forwarding.
+    speed_factor: u32,
+    // The percent of the speed left by the slow motion, it's 100 unless slowed down.
+    slow_motion: u32,
+    // The time the last frame is due, `None` starts from the next frame.
+    deadline: Option<Instant>,
+    // The totals since the start, for the statistics.
+    slept: Duration,
+    late_frames: u64,
+}
+
+impl FramePacer {
+    pub fn new(mode: SyncMode) -> Self {
+        Self {
+            mode,
+            speed_factor: 1,
+            slow_motion: 100,
+            deadline: None,
+            slept: Duration::from_secs(0),
+            late_frames: 0,
+        }
+    }
+
+    pub fn get_mode(&self) -> SyncMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SyncMode) {
+        self.mode = mode;
+        self.deadline = None;
+    }
+
+    pub fn get_speed_factor(&self) -> u32 {
+        self.speed_factor
+    }
+
+    /// Start the deadline from the next frame, e.g. the emulation was paused.
+    pub fn reset(&mut self) {
+        self.deadline = None;
+    }
+
+    /// Run `factor` times of the real hardware speed.
+    pub fn set_speed_factor(&mut self, factor: u32) {
+        self.speed_factor = factor.max(1);
+        // Don't sleep for the frames run faster before.
+        self.deadline = None;
+    }
+
+    pub fn get_slow_motion(&self) -> u32 {
+        self.slow_motion
+    }
+
+    /// Run at the percent of the speed, 1 to 100, e.g. 25 takes 4 times the frame time. It
+    /// slows down the fast forward too.
+    pub fn set_slow_motion(&mut self, percent: u32) {
+        self.slow_motion = percent.clamp(1, 100);
+        self.deadline = None;
+    }
+
+    /// Returns the host time of the cycles at the current speed.
+    pub fn get_frame_time(&self, cycles: u32) -> Duration {
+        let speed = f64::from(self.speed_factor) * f64::from(self.slow_motion) / 100.0;
+        Duration::from_secs_f64(f64::from(cycles) / f64::from(CLOCK_FREQUENCY) / speed)
+    }
+
+    /// Returns how long to sleep after the frame of the cycles finished at the time, the
+    /// deadline moves to the end of the frame.
+    pub fn next_sleep(&mut self, cycles: u32, now: Instant) -> Duration {
+        let deadline = self.deadline.unwrap_or(now) + self.get_frame_time(cycles);
+        if deadline >= now {
+            self.deadline = Some(deadline);
+            return deadline - now;
+        }
+        self.late_frames += 1;
+        let max_lag = self.get_frame_time(FRAME_CYCLES * MAX_LAG_FRAMES);
+        self.deadline = Some(if now - deadline > max_lag {
+            now
+        } else {
+            deadline
+        });
+        Duration::from_secs(0)
+    }
+
+    /// Returns how long to sleep for the queued audio to drain to `AUDIO_LATENCY`.
+    pub fn next_audio_sleep(&mut self, queued: Duration) -> Duration {
+        // The video deadline starts again once the audio stops pacing.
+        self.deadline = None;
+        if queued < AUDIO_LATENCY {
+            self.late_frames += 1;
+        }
+        queued.saturating_sub(AUDIO_LATENCY)
+    }
+
+    /// Sleep until the frame of the cycles is due. `queued` is the audio not played yet, the
+    /// audio sync only paces by it at the normal speed.
+    /// Without the `native` feature there's no clock to sleep by, the frames aren't paced.
+    pub fn wait(&mut self, cycles: u32, queued: Option<Duration>) {
+        if self.mode == SyncMode::Uncapped {
+            return;
+        }
+        #[cfg(feature = "native")]
+        {
+            let start = Instant::now();
+            let normal = self.speed_factor == 1 && self.slow_motion == 100;
+            let d = match (self.mode, queued) {
+                (SyncMode::Audio, Some(queued)) if normal => self.next_audio_sleep(queued),
+                _ => self.next_sleep(cycles, start),
+            };
+            if d > Duration::from_secs(0) {
+                thread::sleep(d);
+                self.slept += start.elapsed();
+            }
+        }
+        #[cfg(not(feature = "native"))]
+        let _ = (cycles, queued);
+    }
+
+    /// Returns the total time slept.
+    pub fn get_sleep_time(&self) -> Duration {
+        self.slept
+    }
+
+    /// Returns the count of the frames which finished after they're due.
+    pub fn get_late_frames(&self) -> u64 {
+        self.late_frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_does_not_drift() {
+        let start = Instant::now();
+        let frame = Duration::from_secs_f64(f64::from(FRAME_CYCLES) / f64::from(CLOCK_FREQUENCY));
+        let mut pacer = FramePacer::new(SyncMode::Video);
+        assert_eq!(pacer.next_sleep(FRAME_CYCLES, start), frame);
+        // The host wakes up 2ms late, the next frame is shorter to make up for it.
+        let woke = start + frame + Duration::from_millis(2);
+        assert_eq!(
+            pacer.next_sleep(FRAME_CYCLES, woke),
+            frame - Duration::from_millis(2)
+        );
+        assert_eq!(pacer.get_late_frames(), 0);
+
+        // A slow frame is counted, the next one catches up.
+        let slow = start + frame * 3 + Duration::from_millis(5);
+        assert_eq!(pacer.next_sleep(FRAME_CYCLES, slow), Duration::from_secs(0));
+        assert_eq!(pacer.get_late_frames(), 1);
+        assert_eq!(
+            pacer.next_sleep(FRAME_CYCLES, slow),
+            frame - Duration::from_millis(5)
+        );
+    }
+
+    #[test]
+    fn test_long_stall_is_dropped() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(SyncMode::Video);
+        pacer.next_sleep(FRAME_CYCLES, start);
+        let stall = start + Duration::from_secs(1);
+        assert_eq!(
+            pacer.next_sleep(FRAME_CYCLES, stall),
+            Duration::from_secs(0)
+        );
+        // The next frame takes the whole frame time again.
+        assert_eq!(
+            pacer.next_sleep(FRAME_CYCLES, stall),
+            pacer.get_frame_time(FRAME_CYCLES)
+        );
+
+        // Fast forward sleeps a quarter of the frame time.
+        pacer.set_speed_factor(4);
+        assert_eq!(
+            pacer.next_sleep(FRAME_CYCLES, stall),
+            pacer.get_frame_time(FRAME_CYCLES)
+        );
+        assert_eq!(
+            pacer.get_frame_time(FRAME_CYCLES * 4),
+            Duration::from_secs_f64(f64::from(FRAME_CYCLES) / f64::from(CLOCK_FREQUENCY))
+        );
+    }
+
+    #[test]
+    fn test_slow_motion() {
+        let start = Instant::now();
+        let frame = Duration::from_secs_f64(f64::from(FRAME_CYCLES) / f64::from(CLOCK_FREQUENCY));
+        let close = |a: Duration, b: Duration| (a.as_secs_f64() - b.as_secs_f64()).abs() < 1e-6;
+        let mut pacer = FramePacer::new(SyncMode::Video);
+        pacer.next_sleep(FRAME_CYCLES, start);
+        // The deadline starts again from the next frame at the new speed.
+        pacer.set_slow_motion(25);
+        let sleep = pacer.next_sleep(FRAME_CYCLES, start + frame);
+        assert!(close(sleep, frame * 4));
+        pacer.set_speed_factor(4);
+        assert!(close(pacer.get_frame_time(FRAME_CYCLES), frame));
+        pacer.set_slow_motion(0);
+        assert_eq!(pacer.get_slow_motion(), 1);
+        pacer.set_slow_motion(200);
+        assert_eq!(pacer.get_slow_motion(), 100);
+    }
+
+    #[test]
+    fn test_audio_sync() {
+        let mut pacer = FramePacer::new(SyncMode::Audio);
+        let frame = Duration::from_millis(16);
+        assert_eq!(pacer.next_audio_sleep(AUDIO_LATENCY + frame), frame);
+        assert_eq!(pacer.get_late_frames(), 0);
+        // The queue is running low, don't sleep to fill it up.
+        assert_eq!(
+            pacer.next_audio_sleep(AUDIO_LATENCY / 2),
+            Duration::from_secs(0)
+        );
+        assert_eq!(pacer.get_late_frames(), 1);
+
+        assert_eq!("audio".parse(), Ok(SyncMode::Audio));
+        assert!("vsync".parse::<SyncMode>().is_err());
+    }
+}
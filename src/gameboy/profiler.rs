@@ -0,0 +1,169 @@
+//! The memory profiler counts the reads and the writes of every 256-byte page and of every rom
+//! bank over a session, it shows which banks and routines a game actually runs. It's opt-in, the
+//! mmu counts nothing without it.
+//!
+//! The counts are exported as a CSV, and as a heatmap image of the pages: a grid of 16 x 16
+//! cells, the page 0x00 at the top left and 0xff at the bottom right. The reads are green, the
+//! writes are red, on a log scale of the busiest page.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::frontend::Frame;
+
+// The side of a page cell in the heatmap, 16 cells fill the height of the frame.
+const CELL: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AccessCount {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl AccessCount {
+    fn add(&mut self, write: bool) {
+        if write {
+            self.writes += 1;
+        } else {
+            self.reads += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryProfiler {
+    pages: Vec<AccessCount>,
+    banks: BTreeMap<usize, AccessCount>,
+    // The reads of the trace and the history are not the game's.
+    paused: bool,
+}
+
+impl Default for MemoryProfiler {
+    fn default() -> Self {
+        Self {
+            pages: vec![AccessCount::default(); 0x100],
+            banks: BTreeMap::new(),
+            paused: false,
+        }
+    }
+}
+
+impl MemoryProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the access, `bank` is the rom bank of the access to the rom.
+    pub fn record(&mut self, a: u16, write: bool, bank: Option<usize>) {
+        if self.paused {
+            return;
+        }
+        self.pages[usize::from(a >> 8)].add(write);
+        if let Some(bank) = bank {
+            self.banks.entry(bank).or_default().add(write);
+        }
+    }
+
+    /// Stop counting the accesses until it's resumed.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Returns the counts of the page, the memory from `page << 8` to `page << 8 | 0xff`.
+    pub fn get_page(&self, page: u8) -> AccessCount {
+        self.pages[usize::from(page)]
+    }
+
+    /// Returns the rom banks accessed and their counts, by the bank.
+    pub fn get_banks(&self) -> &BTreeMap<usize, AccessCount> {
+        &self.banks
+    }
+
+    /// Returns the CSV of the counts, the pages accessed and then the rom banks.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("region,index,reads,writes\n");
+        for (page, count) in self.pages.iter().enumerate() {
+            if count.reads + count.writes != 0 {
+                writeln!(csv, "page,0x{:02x},{},{}", page, count.reads, count.writes).unwrap();
+            }
+        }
+        for (bank, count) in self.banks.iter() {
+            writeln!(csv, "bank,{},{},{}", bank, count.reads, count.writes).unwrap();
+        }
+        csv
+    }
+
+    /// Draw the heatmap of the pages, the right of the frame is left black.
+    pub fn render_heatmap(&self) -> Frame {
+        let max = self
+            .pages
+            .iter()
+            .map(|c| c.reads.max(c.writes))
+            .max()
+            .unwrap_or(0);
+        // The log scale from 0 for no access to 0xff for the busiest page.
+        let level = |n: u64| -> u8 {
+            if n == 0 {
+                return 0;
+            }
+            let scale = ((n as f64).ln_1p() / (max as f64).ln_1p() * 0xdf as f64) as u8;
+            0x20 + scale
+        };
+        let mut frame = Frame::new([0x00; 3]);
+        for (page, count) in self.pages.iter().enumerate() {
+            let color = [level(count.writes), level(count.reads), 0x00];
+            let (x0, y0) = ((page & 0x0f) * CELL, (page >> 4) * CELL);
+            // The last line and row of a cell are the grid.
+            for y in y0..y0 + CELL - 1 {
+                for x in x0..x0 + CELL - 1 {
+                    frame.set_pixel(x, y, color);
+                }
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(profiler: &MemoryProfiler, page: u8) -> (u64, u64) {
+        let count = profiler.get_page(page);
+        (count.reads, count.writes)
+    }
+
+    #[test]
+    fn test_record() {
+        let mut profiler = MemoryProfiler::new();
+        profiler.record(0x4123, false, Some(3));
+        profiler.record(0x41ff, false, Some(3));
+        profiler.record(0xc000, true, None);
+        assert_eq!(counts(&profiler, 0x41), (2, 0));
+        assert_eq!(counts(&profiler, 0xc0), (0, 1));
+        assert_eq!(profiler.get_banks().len(), 1);
+        profiler.set_paused(true);
+        profiler.record(0xc000, true, None);
+        assert_eq!(counts(&profiler, 0xc0), (0, 1));
+        assert_eq!(
+            profiler.to_csv(),
+            "region,index,reads,writes\npage,0x41,2,0\npage,0xc0,0,1\nbank,3,2,0\n"
+        );
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let mut profiler = MemoryProfiler::new();
+        for _ in 0..100 {
+            profiler.record(0x0150, false, Some(0));
+        }
+        profiler.record(0xff80, true, None);
+        let frame = profiler.render_heatmap();
+        // The busiest page is the brightest, the accessed page is never black.
+        assert_eq!(frame.get_pixel(CELL, 0), [0x00, 0xff, 0x00]);
+        assert_eq!(frame.get_pixel(0, 15 * CELL), [0x00; 3]);
+        let hram = frame.get_pixel(15 * CELL, 15 * CELL);
+        assert!(hram[0] >= 0x20 && hram[1] == 0x00);
+        // The grid between the cells.
+        assert_eq!(frame.get_pixel(2 * CELL - 1, 0), [0x00; 3]);
+    }
+}
@@ -0,0 +1,438 @@
+//! Record the frames into an animated GIF or APNG file at the speed of the LCD, 59.7 frames per
+//! second. A frame equal to the previous one isn't written again, the previous one is shown
+//! longer instead, so a still screen costs nothing.
+//!
+//! The delays of both formats are too coarse for 1/59.7 second, each frame gets the ticks between
+//! its rounded start and end time, e.g. 2, 2, 1, 2 ... centiseconds in the GIF.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use log::error;
+
+use super::frontend::{Frame, VideoSink};
+use super::screenshot::{crc32, png_header, png_image_data, put_chunk, PNG_SIGNATURE};
+use super::{CLOCK_FREQUENCY, FRAME_CYCLES, SCREEN_H, SCREEN_W};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoFormat {
+    Gif,
+    Apng,
+}
+
+impl VideoFormat {
+    pub fn get_extension(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Apng => "png",
+        }
+    }
+}
+
+impl FromStr for VideoFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gif" => Ok(Self::Gif),
+            "apng" => Ok(Self::Apng),
+            _ => Err(format!("unknown video format {}", s)),
+        }
+    }
+}
+
+/// Returns the time of the frame in the ticks of `per_sec`, rounded.
+fn to_ticks(frames: u64, per_sec: u64) -> u64 {
+    let cycles = frames * u64::from(FRAME_CYCLES) * per_sec;
+    (cycles + u64::from(CLOCK_FREQUENCY) / 2) / u64::from(CLOCK_FREQUENCY)
+}
+
+/// The format specific part of the recorder.
+trait Encoder: Send {
+    /// Write the frame shown from the frame `start` to `end`.
+    fn write_frame(&mut self, frame: &Frame, start: u64, end: u64) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Pack the variable length LZW codes from the lowest bit.
+struct BitWriter {
+    data: Vec<u8>,
+    acc: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn put(&mut self, code: u16, width: u8) {
+        self.acc |= u32::from(code) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.data.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.data.push(self.acc as u8);
+        }
+        self.data
+    }
+}
+
+/// Compress the color indices with the GIF flavor of LZW.
+fn lzw_encode(indices: &[u8], min_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_size;
+    let end = clear + 1;
+    let mut out = BitWriter {
+        data: vec![],
+        acc: 0,
+        bits: 0,
+    };
+    let mut dict: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut width = min_size + 1;
+    out.put(clear, width);
+
+    let mut prefix = u16::from(indices[0]);
+    for &k in &indices[1..] {
+        if let Some(&code) = dict.get(&(prefix, k)) {
+            prefix = code;
+            continue;
+        }
+        out.put(prefix, width);
+        if next == 4096 {
+            // The table is full, start over.
+            out.put(clear, width);
+            dict.clear();
+            next = end + 1;
+            width = min_size + 1;
+        } else {
+            dict.insert((prefix, k), next);
+            // The codes are 12 bits at most.
+            if next == 1 << width && width < 12 {
+                width += 1;
+            }
+            next += 1;
+        }
+        prefix = u16::from(k);
+    }
+    out.put(prefix, width);
+    out.put(end, width);
+    out.finish()
+}
+
+struct GifEncoder {
+    file: BufWriter<File>,
+}
+
+impl GifEncoder {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"GIF89a")?;
+        file.write_all(&(SCREEN_W as u16).to_le_bytes())?;
+        file.write_all(&(SCREEN_H as u16).to_le_bytes())?;
+        // No global color table, the background color and the aspect ratio.
+        file.write_all(&[0x00, 0x00, 0x00])?;
+        // Loop forever.
+        file.write_all(&[0x21, 0xff, 0x0b])?;
+        file.write_all(b"NETSCAPE2.0")?;
+        file.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+        Ok(Self { file })
+    }
+}
+
+/// Returns the color table and the index of each pixel, the colors are reduced to RGB 332 if the
+/// frame has more than 256, e.g. the CGB games changing the palettes every line.
+fn index_colors(frame: &Frame) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let index = |mask: [u8; 3]| {
+        let mut table = vec![];
+        let mut map = HashMap::new();
+        let mut indices = Vec::with_capacity(SCREEN_W * SCREEN_H);
+        for p in frame.pixels() {
+            let c = [p[0] & mask[0], p[1] & mask[1], p[2] & mask[2]];
+            let i = *map.entry(c).or_insert_with(|| {
+                table.push(c);
+                table.len() - 1
+            });
+            indices.push(i);
+        }
+        (table, indices)
+    };
+    let (mut table, mut indices) = index([0xff, 0xff, 0xff]);
+    if table.len() > 256 {
+        let reduced = index([0xe0, 0xe0, 0xc0]);
+        table = reduced.0;
+        indices = reduced.1;
+    }
+    (table, indices.into_iter().map(|i| i as u8).collect())
+}
+
+impl Encoder for GifEncoder {
+    fn write_frame(&mut self, frame: &Frame, start: u64, end: u64) -> io::Result<()> {
+        let delay = (to_ticks(end, 100) - to_ticks(start, 100)).min(0xffff) as u16;
+        let (mut table, indices) = index_colors(frame);
+        // The table has 2^(n+1) colors, 4 at least for the LZW code size of 2.
+        let size_bits = (usize::BITS - (table.len().max(4) - 1).leading_zeros()) as u8;
+        table.resize(1 << size_bits, [0x00; 3]);
+
+        self.file.write_all(&[0x21, 0xf9, 0x04, 0x00])?;
+        self.file.write_all(&delay.to_le_bytes())?;
+        self.file.write_all(&[0x00, 0x00])?;
+
+        self.file.write_all(&[0x2c, 0x00, 0x00, 0x00, 0x00])?;
+        self.file.write_all(&(SCREEN_W as u16).to_le_bytes())?;
+        self.file.write_all(&(SCREEN_H as u16).to_le_bytes())?;
+        // The local color table follows.
+        self.file.write_all(&[0x80 | (size_bits - 1)])?;
+        for c in table.iter() {
+            self.file.write_all(c)?;
+        }
+        self.file.write_all(&[size_bits])?;
+        for block in lzw_encode(&indices, size_bits).chunks(255) {
+            self.file.write_all(&[block.len() as u8])?;
+            self.file.write_all(block)?;
+        }
+        self.file.write_all(&[0x00])
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.write_all(&[0x3b])?;
+        self.file.flush()
+    }
+}
+
+/// The frame count of acTL is filled when it's finished.
+struct ApngEncoder {
+    file: BufWriter<File>,
+    frames: u32,
+    // The sequence number of the fcTL and fdAT chunks.
+    sequence: u32,
+}
+
+// acTL follows the signature and IHDR.
+const ACTL_OFFSET: u64 = 8 + 25;
+
+impl ApngEncoder {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let mut head = PNG_SIGNATURE.to_vec();
+        put_chunk(&mut head, b"IHDR", &png_header());
+        // The frame count and play forever.
+        put_chunk(&mut head, b"acTL", &[0x00; 8]);
+        file.write_all(&head)?;
+        Ok(Self {
+            file,
+            frames: 0,
+            sequence: 0,
+        })
+    }
+
+    fn write_chunk(&mut self, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        let mut chunk = vec![];
+        put_chunk(&mut chunk, kind, data);
+        self.file.write_all(&chunk)
+    }
+}
+
+impl Encoder for ApngEncoder {
+    fn write_frame(&mut self, frame: &Frame, start: u64, end: u64) -> io::Result<()> {
+        let delay = (to_ticks(end, 1000) - to_ticks(start, 1000)).min(0xffff) as u16;
+        let mut control = self.sequence.to_be_bytes().to_vec();
+        control.extend_from_slice(&(SCREEN_W as u32).to_be_bytes());
+        control.extend_from_slice(&(SCREEN_H as u32).to_be_bytes());
+        // The offset, the delay in ms, no dispose and no blend.
+        control.extend_from_slice(&[0x00; 8]);
+        control.extend_from_slice(&delay.to_be_bytes());
+        control.extend_from_slice(&1000u16.to_be_bytes());
+        control.extend_from_slice(&[0x00, 0x00]);
+        self.write_chunk(b"fcTL", &control)?;
+        self.sequence += 1;
+
+        // The first frame is the default image too.
+        let data = png_image_data(frame);
+        if self.frames == 0 {
+            self.write_chunk(b"IDAT", &data)?;
+        } else {
+            let mut chunk = self.sequence.to_be_bytes().to_vec();
+            chunk.extend_from_slice(&data);
+            self.write_chunk(b"fdAT", &chunk)?;
+            self.sequence += 1;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_chunk(b"IEND", &[])?;
+        let mut actl = b"acTL".to_vec();
+        actl.extend_from_slice(&self.frames.to_be_bytes());
+        actl.extend_from_slice(&[0x00; 4]);
+        let crc = crc32(&actl);
+        self.file.seek(SeekFrom::Start(ACTL_OFFSET + 4))?;
+        self.file.write_all(&actl)?;
+        self.file.write_all(&crc.to_be_bytes())?;
+        self.file.flush()
+    }
+}
+
+/// The recording in progress, the last frame waits until a different one comes.
+pub struct Recorder {
+    encoder: Box<dyn Encoder>,
+    // The frame not written yet and the frame it's first shown.
+    pending: Option<(Frame, u64)>,
+    // The frames presented.
+    frames: u64,
+    // The frames written to the file, the repeated ones are not counted.
+    written: u32,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, format: VideoFormat) -> io::Result<Self> {
+        let encoder: Box<dyn Encoder> = match format {
+            VideoFormat::Gif => Box::new(GifEncoder::create(path.as_ref())?),
+            VideoFormat::Apng => Box::new(ApngEncoder::create(path.as_ref())?),
+        };
+        Ok(Self {
+            encoder,
+            pending: None,
+            frames: 0,
+            written: 0,
+        })
+    }
+
+    /// Add the frame, it's written once the next different frame comes.
+    pub fn push(&mut self, frame: &Frame) -> io::Result<()> {
+        let now = self.frames;
+        self.frames += 1;
+        match self.pending.as_ref() {
+            Some((last, _)) if last == frame => return Ok(()),
+            Some(_) => {
+                let (last, start) = self.pending.take().unwrap();
+                self.encoder.write_frame(&last, start, now)?;
+                self.written += 1;
+            }
+            None => {}
+        }
+        self.pending = Some((frame.clone(), now));
+        Ok(())
+    }
+
+    /// Returns the frames presented and the frames written.
+    pub fn get_counts(&self) -> (u64, u32) {
+        (self.frames, self.written)
+    }
+
+    /// Write the last frame and close the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some((last, start)) = self.pending.take() {
+            self.encoder.write_frame(&last, start, self.frames)?;
+        }
+        self.encoder.finish()
+    }
+}
+
+impl VideoSink for Recorder {
+    fn present(&mut self, frame: &Frame) {
+        if let Err(e) = self.push(frame) {
+            error!("Failed to record the frame: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_ticks() {
+        assert_eq!(to_ticks(0, 100), 0);
+        assert_eq!(to_ticks(1, 100), 2);
+        assert_eq!(to_ticks(3, 100), 5);
+        // 59.7 frames in a second.
+        assert_eq!(to_ticks(60, 1000), 1005);
+    }
+
+    #[test]
+    fn test_lzw() {
+        // The example of the GIF specification walkthrough, 2 colors and the code size 2.
+        let data = lzw_encode(&[1, 1, 1, 1, 1, 0, 0, 0, 0], 2);
+        // clear(4) 1 6 6 0 9 0 end(5), the code 8 is added with the second 6.
+        let mut bits = BitWriter {
+            data: vec![],
+            acc: 0,
+            bits: 0,
+        };
+        let codes = [4, 1, 6, 6, 0, 9, 0, 5];
+        let widths = [3, 3, 3, 3, 4, 4, 4, 4];
+        for (code, w) in codes.iter().zip(widths.iter()) {
+            bits.put(*code, *w);
+        }
+        assert_eq!(data, bits.finish());
+    }
+
+    #[test]
+    fn test_index_colors() {
+        let mut frame = Frame::new([0x00; 3]);
+        frame.set_pixel(0, 0, [0xff; 3]);
+        let (table, indices) = index_colors(&frame);
+        assert_eq!(table, vec![[0xff; 3], [0x00; 3]]);
+        assert_eq!(&indices[..2], &[0, 1]);
+
+        // Too many colors are reduced.
+        for i in 0..SCREEN_W * SCREEN_H {
+            frame.set_pixel(i % SCREEN_W, i / SCREEN_W, [i as u8, (i >> 8) as u8, 0x00]);
+        }
+        let (table, _) = index_colors(&frame);
+        assert!(table.len() <= 256);
+    }
+
+    fn record(format: VideoFormat, name: &str) -> (Vec<u8>, (u64, u32)) {
+        let path = env::temp_dir().join(format!("ngc224-{}-{}", std::process::id(), name));
+        let mut recorder = Recorder::create(&path, format).unwrap();
+        let (white, black) = (Frame::new([0xff; 3]), Frame::new([0x00; 3]));
+        for frame in [&white, &white, &white, &black, &white].iter() {
+            recorder.push(frame).unwrap();
+        }
+        let counts = recorder.get_counts();
+        recorder.finish().unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (data, counts)
+    }
+
+    #[test]
+    fn test_record_gif() {
+        let (gif, counts) = record(VideoFormat::Gif, "a.gif");
+        // The last frame is still pending.
+        assert_eq!(counts, (5, 2));
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(*gif.last().unwrap(), 0x3b);
+        let delays: Vec<u16> = gif
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == [0x21, 0xf9, 0x04, 0x00])
+            .map(|(i, _)| u16::from_le_bytes([gif[i + 4], gif[i + 5]]))
+            .collect();
+        // The 3 white frames are merged.
+        assert_eq!(delays, vec![5, 2, 1]);
+    }
+
+    #[test]
+    fn test_record_apng() {
+        let (png, _) = record(VideoFormat::Apng, "a.png");
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[37..41], b"acTL");
+        assert_eq!(png[41..45], [0, 0, 0, 3]);
+        let actl_crc = crc32(&png[37..49]);
+        assert_eq!(png[49..53], actl_crc.to_be_bytes());
+        let count = |kind: &[u8]| png.windows(4).filter(|w| *w == kind).count();
+        assert_eq!((count(b"fcTL"), count(b"IDAT"), count(b"fdAT")), (3, 1, 2));
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
@@ -0,0 +1,227 @@
+//! Check a directory of roms without the window, for curating a collection and smoke testing the
+//! mappers. The header of every rom is parsed and its mapper is built, then the game may run for
+//! some frames. Nothing is written beside the roms, the battery backed ram is not persisted.
+use std::any::Any;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use super::browser::is_rom_file;
+use super::cartridge::{load_cartridge_from_bytes, read_rom, CartridgeMeta};
+use super::config::Config;
+use super::emulator::Emulator;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The mapper is built and the frames ran.
+    Ok,
+    /// The rom can't be read, its header is broken or its mapper is not supported.
+    Rejected(String),
+    /// The game panicked the emulator or locked up the CPU in the frames.
+    Crashed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Rejected(e) => write!(f, "rejected: {}", e),
+            Self::Crashed(e) => write!(f, "crashed: {}", e),
+        }
+    }
+}
+
+/// The result of `check_rom`.
+#[derive(Debug, Clone)]
+pub struct RomCheck {
+    pub path: PathBuf,
+    /// The title and the type of the header, empty if there is no header.
+    pub title: String,
+    pub mapper: String,
+    pub outcome: Outcome,
+}
+
+/// Returns the roms in the directory and its subdirectories, sorted by the path.
+pub fn find_roms(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let mut roms = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // The links to a directory are not followed, they may loop.
+        if entry.file_type()?.is_dir() {
+            roms.extend(find_roms(&path)?);
+        } else if is_rom_file(&path) && path.is_file() {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+/// Check every rom in the directory and its subdirectories.
+pub fn check_dir(dir: impl AsRef<Path>, frames: u32, config: &Config) -> io::Result<Vec<RomCheck>> {
+    let roms = find_roms(dir)?;
+    Ok(roms.iter().map(|p| check_rom(p, frames, config)).collect())
+}
+
+/// Parse the header of the rom and build its mapper, then run the frames if `frames` isn't zero.
+/// The config must have no rom, its header check and MBC1 wiring apply to the rom.
+pub fn check_rom(path: impl AsRef<Path>, frames: u32, config: &Config) -> RomCheck {
+    let mut check = RomCheck {
+        path: path.as_ref().to_path_buf(),
+        title: String::new(),
+        mapper: String::new(),
+        outcome: Outcome::Ok,
+    };
+    let rom = match read_rom(&path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            check.outcome = Outcome::Rejected(e.to_string());
+            return check;
+        }
+    };
+    if let Ok(meta) = CartridgeMeta::new(&rom) {
+        check.title = meta.get_title();
+        check.mapper = format!("{:?}", meta.get_type());
+    }
+    check.outcome = run_rom(rom, frames, config);
+    check
+}
+
+fn run_rom(rom: Vec<u8>, frames: u32, config: &Config) -> Outcome {
+    if frames == 0 {
+        let check = config.get_header_check();
+        return match load_cartridge_from_bytes(rom, check, config.get_mbc1_wiring()) {
+            Ok(_) => Outcome::Ok,
+            Err(e) => Outcome::Rejected(e.to_string()),
+        };
+    }
+    let mut emulator = match Emulator::new(config.clone()) {
+        Ok(emulator) => emulator,
+        Err(e) => return Outcome::Rejected(e.to_string()),
+    };
+    if let Err(e) = emulator.load_rom_bytes(rom) {
+        return Outcome::Rejected(e.to_string());
+    }
+    emulator.set_speed_simulation(false);
+
+    // The panic is reported in the table, it's not printed.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        for _ in 0..frames {
+            emulator.step_frame();
+            if let Some(lock) = emulator.get_cpu_lock() {
+                return Err(lock);
+            }
+        }
+        Ok(())
+    }));
+    panic::set_hook(hook);
+    match result {
+        Ok(Ok(())) => Outcome::Ok,
+        Ok(Err(lock)) => Outcome::Crashed(format!("the cpu is locked up by {}", lock)),
+        Err(e) => Outcome::Crashed(panic_message(&*e)),
+    }
+}
+
+fn panic_message(e: &(dyn Any + Send)) -> String {
+    match e.downcast_ref::<&str>() {
+        Some(s) => s.to_string(),
+        None => e.downcast_ref::<String>().cloned().unwrap_or_default(),
+    }
+}
+
+/// Returns the table of the checks and the count of the results, the paths are shown relative to
+/// the directory.
+pub fn format_table(dir: impl AsRef<Path>, checks: &[RomCheck]) -> String {
+    let files: Vec<String> = checks
+        .iter()
+        .map(|c| {
+            let path = c.path.strip_prefix(&dir).unwrap_or(&c.path);
+            path.display().to_string()
+        })
+        .collect();
+    let fw = files.iter().map(|f| f.chars().count()).fold(4, usize::max);
+    let tw = checks.iter().map(|c| c.title.len()).fold(5, usize::max);
+    let mw = checks.iter().map(|c| c.mapper.len()).fold(6, usize::max);
+
+    let row = |file: &str, title: &str, mapper: &str| {
+        format!("{:3$}  {:4$}  {:5$}  ", file, title, mapper, fw, tw, mw)
+    };
+
+    let mut table = row("FILE", "TITLE", "MAPPER") + "RESULT\n";
+    for (file, c) in files.iter().zip(checks) {
+        writeln!(table, "{}{}", row(file, &c.title, &c.mapper), c.outcome).unwrap();
+    }
+    let count = |f: fn(&Outcome) -> bool| checks.iter().filter(|c| f(&c.outcome)).count();
+    write!(
+        table,
+        "{} roms, {} ok, {} rejected, {} crashed",
+        checks.len(),
+        count(|o| *o == Outcome::Ok),
+        count(|o| matches!(o, Outcome::Rejected(_))),
+        count(|o| matches!(o, Outcome::Crashed(_))),
+    )
+    .unwrap();
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(title: &[u8], r#type: u8, program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x0147] = r#type;
+        rom
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ngc224-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("more")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_check_dir() {
+        let dir = temp_dir("check-dir");
+        // JR -2 spins, 0xd3 is not an instruction.
+        fs::write(dir.join("a.gb"), rom(b"SPIN", 0x00, &[0x18, 0xfe])).unwrap();
+        fs::write(dir.join("more/b.gbc"), rom(b"LOCK", 0x01, &[0xd3])).unwrap();
+        fs::write(dir.join("c.gb"), rom(b"MBC7", 0x22, &[])).unwrap();
+        fs::write(dir.join("d.gb"), [0x00; 0x20]).unwrap();
+        fs::write(dir.join("readme.txt"), b"not a rom").unwrap();
+        let config = Config::default();
+
+        let checks = check_dir(&dir, 0, &config).unwrap();
+        let paths: Vec<PathBuf> = checks.iter().map(|c| c.path.clone()).collect();
+        let files = ["a.gb", "c.gb", "d.gb", "more/b.gbc"];
+        assert_eq!(paths, files.iter().map(|f| dir.join(f)).collect::<Vec<_>>());
+        assert_eq!(checks[0].outcome, Outcome::Ok);
+        assert_eq!(checks[0].mapper, "ROM_ONLY");
+        assert!(matches!(checks[1].outcome, Outcome::Rejected(_)));
+        assert_eq!(checks[2].title, "");
+        assert!(matches!(checks[2].outcome, Outcome::Rejected(_)));
+        assert_eq!(checks[3].outcome, Outcome::Ok);
+
+        let checks = check_dir(&dir, 2, &config).unwrap();
+        assert_eq!(checks[0].outcome, Outcome::Ok);
+        let lock = "crashed: the cpu is locked up by the unknown opcode d3 at 0100";
+        assert_eq!(checks[3].outcome.to_string(), lock);
+
+        let table = format_table(&dir, &checks);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "FILE        TITLE  MAPPER         RESULT");
+        assert_eq!(lines[1], "a.gb        SPIN   ROM_ONLY       ok");
+        assert!(lines[4].starts_with("more/b.gbc  LOCK   ROM_MBC1       crashed:"));
+        assert_eq!(lines[5], "4 roms, 1 ok, 2 rejected, 1 crashed");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,168 @@
+//! Save the frames as PNG images. The image data isn't compressed, it's kept in the stored
+//! deflate blocks so no zlib is needed, a frame is about 70 KiB.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::error;
+
+use super::frontend::{Frame, VideoSink};
+use super::{SCREEN_H, SCREEN_W};
+
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// The most bytes of a stored deflate block.
+const STORED_BLOCK_LEN: usize = 0xffff;
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for b in data {
+        crc ^= u32::from(*b);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for v in data {
+        a = (a + u32::from(*v)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Append the chunk, the CRC covers the type and the data.
+pub(crate) fn put_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap the data in a zlib stream of the stored blocks.
+pub(crate) fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks = data.chunks(STORED_BLOCK_LEN);
+    let count = blocks.len();
+    for (i, block) in blocks.enumerate() {
+        // BFINAL on the last block, BTYPE 00.
+        out.push(if i + 1 == count { 0x01 } else { 0x00 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// The IHDR of the 8-bit RGB image of the screen size.
+pub(crate) fn png_header() -> Vec<u8> {
+    let mut header = vec![];
+    header.extend_from_slice(&(SCREEN_W as u32).to_be_bytes());
+    header.extend_from_slice(&(SCREEN_H as u32).to_be_bytes());
+    // The bit depth, the RGB color type, the compression, the filter and no interlace.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    header
+}
+
+/// The zlib stream of the image data.
+pub(crate) fn png_image_data(frame: &Frame) -> Vec<u8> {
+    // Each row starts with the filter type, none here.
+    let mut raw = Vec::with_capacity((SCREEN_W * 3 + 1) * SCREEN_H);
+    for line in frame.as_rgba().chunks_exact(SCREEN_W * 4) {
+        raw.push(0x00);
+        for p in line.chunks_exact(4) {
+            raw.extend_from_slice(&p[..3]);
+        }
+    }
+    zlib_stored(&raw)
+}
+
+/// Encode the frame into a 8-bit RGB PNG image.
+pub fn encode_png(frame: &Frame) -> Vec<u8> {
+    let mut png = PNG_SIGNATURE.to_vec();
+    put_chunk(&mut png, b"IHDR", &png_header());
+    put_chunk(&mut png, b"IDAT", &png_image_data(frame));
+    put_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn save_png(path: impl AsRef<Path>, frame: &Frame) -> io::Result<()> {
+    fs::write(path, encode_png(frame))
+}
+
+/// Save every Nth frame into the directory as `frame-000000.png`, the number counts all the
+/// frames, so the dumps of two runs are compared file by file.
+pub struct FrameDump {
+    dir: PathBuf,
+    interval: u32,
+    // The frames presented.
+    frames: u64,
+    // The frames to skip before the next one is saved.
+    wait: u32,
+}
+
+impl FrameDump {
+    /// The directory is created if it doesn't exist.
+    pub fn create(dir: impl AsRef<Path>, interval: u32) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            interval: interval.max(1),
+            frames: 0,
+            wait: 0,
+        })
+    }
+}
+
+impl VideoSink for FrameDump {
+    fn present(&mut self, frame: &Frame) {
+        if self.wait == 0 {
+            self.wait = self.interval;
+            let path = self.dir.join(format!("frame-{:06}.png", self.frames));
+            if let Err(e) = save_png(&path, frame) {
+                error!("Can't dump the frame to {:?}: {}", path, e);
+            }
+        }
+        self.wait -= 1;
+        self.frames += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let mut frame = Frame::default();
+        frame.set_pixel(2, 1, [0x12, 0x34, 0x56]);
+        let png = encode_png(&frame);
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 160, 0, 0, 0, 144]);
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+
+        // The rows are stored as is after the zlib header and the block header.
+        let idat = 8 + 25 + 8 + 2 + 5;
+        let row = SCREEN_W * 3 + 1;
+        assert_eq!(png[idat + row], 0x00);
+        assert_eq!(png[idat + row + 1 + 2 * 3..][..3], [0x12, 0x34, 0x56]);
+    }
+}
@@ -0,0 +1,175 @@
+//! The hooks of the scripts: the callbacks run after every frame, after the writes to a memory
+//! range and before the instruction at an address, with the API reading and writing the memory
+//! and the registers. A HUD or an auto-splitter is a few hooks, a test script runs the frames
+//! headless and checks the memory.
+//!
+//! No script engine is embedded, the hooks are the Rust API of the library. A Lua or Rhai script
+//! is run by the program embedding the emulator: its callbacks are called from the closures and
+//! its memory functions call the `ScriptApi` passed to them.
+use super::cpu::{Register, CPU};
+use super::mmu::{IOHandler, Mmunit};
+
+/// What the hooks see of the machine.
+pub struct ScriptApi<'a> {
+    cpu: &'a mut CPU,
+    frame: u64,
+}
+
+impl<'a> ScriptApi<'a> {
+    /// Returns the frames finished since the power on.
+    pub fn get_frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn read_byte(&self, a: u16) -> u8 {
+        mmu_of(self.cpu).read_byte(a)
+    }
+
+    /// Write the memory like the game does, the write hooks don't see it.
+    pub fn write_byte(&mut self, a: u16, v: u8) {
+        self.cpu.get_bus_mut().write_byte(a, v);
+    }
+
+    pub fn get_reg(&self) -> Register {
+        self.cpu.get_reg_snapshot()
+    }
+
+    pub fn set_reg(&mut self, reg: Register) {
+        self.cpu.set_reg(reg);
+    }
+}
+
+pub type Hook = Box<dyn FnMut(&mut ScriptApi) + Send>;
+pub type WriteHook = Box<dyn FnMut(&mut ScriptApi, u16, u8) + Send>;
+
+/// The writes to the ranges of the write hooks, the mmu logs them and the hooks run after the
+/// instruction. It's cheap without any range.
+#[derive(Debug, Default)]
+pub struct WriteLog {
+    ranges: Vec<(u16, u16)>,
+    writes: Vec<(u16, u8)>,
+}
+
+impl WriteLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&mut self, a: u16, v: u8) {
+        if self.ranges.iter().any(|(s, e)| (*s..=*e).contains(&a)) {
+            self.writes.push((a, v));
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.writes)
+    }
+}
+
+/// The registered hooks, run by the emulator.
+#[derive(Default)]
+pub struct Hooks {
+    frame: Vec<Hook>,
+    pc: Vec<(u16, Hook)>,
+    write: Vec<(u16, u16, WriteHook)>,
+    frames: u64,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frame.is_empty() && self.pc.is_empty() && self.write.is_empty()
+    }
+
+    pub fn on_frame(&mut self, hook: Hook) {
+        self.frame.push(hook);
+    }
+
+    pub fn on_pc(&mut self, pc: u16, hook: Hook) {
+        self.pc.push((pc, hook));
+    }
+
+    /// The hook is called with the address and the value of every write in `start..=end`. The
+    /// mmu logs them in the log of `new_write_log`.
+    pub fn on_write(&mut self, start: u16, end: u16, hook: WriteHook) {
+        self.write.push((start, end, hook));
+    }
+
+    /// Returns the log of the write ranges for the mmu, it's set again once the mmu is rebuilt.
+    pub fn new_write_log(&self) -> WriteLog {
+        WriteLog {
+            ranges: self.write.iter().map(|(s, e, _)| (*s, *e)).collect(),
+            writes: vec![],
+        }
+    }
+
+    /// Run the hooks with the machine. The accesses of the hooks are not the game's, they don't
+    /// hit the watchpoints of the debugger, count in the memory profile or log their writes.
+    fn run(cpu: &mut CPU, frame: u64, f: impl FnOnce(&mut ScriptApi)) {
+        let hit = mmu_of(cpu).watchpoints.is_hit();
+        mmu_of(cpu).pause_profiler(true);
+        f(&mut ScriptApi { cpu, frame });
+        let mmu = cpu.get_bus_mut().as_mmu_mut().unwrap();
+        mmu.pause_profiler(false);
+        if !hit {
+            mmu.watchpoints.take_hit();
+        }
+        mmu.write_log.take();
+    }
+
+    /// Run the hooks of the PC, before the instruction there is executed.
+    pub fn run_pc(&mut self, cpu: &mut CPU) {
+        let pc = cpu.reg.get_PC();
+        if !self.pc.iter().any(|(p, _)| *p == pc) {
+            return;
+        }
+        let (hooks, frame) = (&mut self.pc, self.frames);
+        Self::run(cpu, frame, |api| {
+            for (_, hook) in hooks.iter_mut().filter(|(p, _)| *p == pc) {
+                hook(api);
+            }
+        });
+    }
+
+    /// Run the hooks of the writes the last instruction made.
+    pub fn run_write(&mut self, cpu: &mut CPU) {
+        let writes = match cpu.get_bus_mut().as_mmu_mut() {
+            Some(mmu) if !self.write.is_empty() => mmu.write_log.take(),
+            _ => return,
+        };
+        if writes.is_empty() {
+            return;
+        }
+        let (hooks, frame) = (&mut self.write, self.frames);
+        Self::run(cpu, frame, |api| {
+            for (a, v) in writes {
+                for (start, end, hook) in hooks.iter_mut() {
+                    if (*start..=*end).contains(&a) {
+                        hook(api, a, v);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Count the finished frame and run the hooks of it.
+    pub fn run_frame(&mut self, cpu: &mut CPU) {
+        self.frames += 1;
+        if self.frame.is_empty() {
+            return;
+        }
+        let (hooks, frame) = (&mut self.frame, self.frames);
+        Self::run(cpu, frame, |api| {
+            for hook in hooks.iter_mut() {
+                hook(api);
+            }
+        });
+    }
+}
+
+fn mmu_of(cpu: &CPU) -> &Mmunit {
+    cpu.get_bus().as_mmu().expect("the scripts run on the mmu")
+}
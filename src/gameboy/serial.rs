@@ -0,0 +1,137 @@
+// The serial port shifts a byte out through the link cable while the other Game Boy shifts its
+// byte in. No cable is plugged here, so the byte read back is always 0xff, but every byte sent is
+// kept, the test roms print their results through it.
+//
+// FF01 - SB - Serial transfer data (R/W)
+// FF02 - SC - Serial Transfer Control (R/W)
+//  Bit 7 - Transfer Start Flag (0=No transfer is in progress or requested, 1=Transfer in progress, or requested)
+//  Bit 0 - Shift Clock (0=External Clock, 1=Internal Clock 8192Hz)
+//
+// Without the other side nothing drives the external clock, so that transfer never ends.
+// See: https://gbdev.gg8.se/wiki/articles/Serial_Data_Transfer_(Link_Cable)
+use std::sync::Arc;
+
+use super::cpu::IntFlag;
+use super::cpu::IntReg;
+use super::state::{StateError, StateReader, StateWriter, Stateful};
+
+// The 8 bits are shifted with the internal clock of 8192Hz.
+const TRANSFER_CYCLES: u32 = 8 * 512;
+
+pub struct Serial {
+    intf: Arc<IntReg>,
+    sb: u8,
+    sc: u8,
+    // The cpu cycles left of the transfer with the internal clock.
+    remaining: u32,
+    // The bytes sent since the power on.
+    output: Vec<u8>,
+}
+
+impl Serial {
+    pub fn new(intf: Arc<IntReg>) -> Self {
+        Self {
+            intf,
+            sb: 0x00,
+            sc: 0x00,
+            remaining: 0,
+            output: vec![],
+        }
+    }
+
+    pub fn get(&self, a: u16) -> u8 {
+        match a {
+            0xff01 => self.sb,
+            // The bits 1-6 are unused.
+            0xff02 => self.sc | 0x7e,
+            _ => panic!("Unsupported address"),
+        }
+    }
+
+    pub fn set(&mut self, a: u16, v: u8) {
+        match a {
+            0xff01 => self.sb = v,
+            0xff02 => {
+                self.sc = v & 0x81;
+                if self.sc == 0x81 {
+                    self.output.push(self.sb);
+                    self.remaining = TRANSFER_CYCLES;
+                }
+            }
+            _ => panic!("Unsupported address"),
+        }
+    }
+
+    /// The serial clock follows the cpu clock like the timer.
+    pub fn next(&mut self, cycles: u32) {
+        if self.remaining == 0 {
+            return;
+        }
+        self.remaining = self.remaining.saturating_sub(cycles);
+        if self.remaining == 0 {
+            self.sb = 0xff;
+            self.sc &= 0x7f;
+            self.intf.req(IntFlag::Serial);
+        }
+    }
+
+    /// Returns the bytes sent through the port.
+    pub fn get_output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+}
+
+/// The bytes sent are not saved, they're kept for the reader on the host.
+impl Stateful for Serial {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u8(self.sb);
+        w.put_u8(self.sc);
+        w.put_u32(self.remaining);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.sb = r.get_u8()?;
+        self.sc = r.get_u8()?;
+        self.remaining = r.get_u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_clock_transfer() {
+        let intf = Arc::new(IntReg::new());
+        let mut serial = Serial::new(intf.clone());
+        serial.set(0xff01, b'P');
+        serial.set(0xff02, 0x81);
+        assert_eq!(serial.get_output(), b"P");
+        assert_eq!(serial.get(0xff02), 0xff);
+
+        serial.next(TRANSFER_CYCLES - 4);
+        assert_eq!(serial.get(0xff02), 0xff);
+        assert_eq!((intf.get() & 0x1f), 0x00);
+        serial.next(4);
+        assert_eq!(serial.get(0xff01), 0xff);
+        assert_eq!(serial.get(0xff02), 0x7f);
+        assert_eq!((intf.get() & 0x1f), 0x08);
+    }
+
+    #[test]
+    fn test_external_clock_never_ends() {
+        let intf = Arc::new(IntReg::new());
+        let mut serial = Serial::new(intf.clone());
+        serial.set(0xff01, 0x42);
+        serial.set(0xff02, 0x80);
+        serial.next(TRANSFER_CYCLES * 4);
+        assert!(serial.get_output().is_empty());
+        assert_eq!(serial.get(0xff02), 0xfe);
+        assert_eq!((intf.get() & 0x1f), 0x00);
+    }
+}
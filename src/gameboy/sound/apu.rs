@@ -0,0 +1,259 @@
+use super::noise::Noise;
+use super::square::Square;
+use super::wave::Wave;
+use crate::gameboy::mmu::IOHandler;
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+use crate::gameboy::CLOCK_FREQUENCY;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+// The frame sequencer runs at 512 Hz.
+const SEQUENCER_PERIOD: u32 = CLOCK_FREQUENCY / 512;
+
+/// The bits always read as 1 of FF10-FF2F, the write only bits included.
+#[rustfmt::skip]
+const READ_MASK: [u8; 0x20] = [
+    0x80, 0x3f, 0x00, 0xff, 0xbf, // NR10-NR14
+    0xff, 0x3f, 0x00, 0xff, 0xbf, // NR20-NR24
+    0x7f, 0xff, 0x9f, 0xff, 0xbf, // NR30-NR34
+    0xff, 0xff, 0x00, 0x00, 0xbf, // NR40-NR44
+    0x00, 0x00, 0x70,             // NR50-NR52
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+
+/// The audio processing unit, it owns the four sound channels and mixes them into stereo samples.
+///
+/// FF24 - NR50 - Channel control / ON-OFF / Volume (R/W)
+/// FF25 - NR51 - Selection of Sound output terminal (R/W)
+/// FF26 - NR52 - Sound on/off
+pub struct Apu {
+    power: bool,
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    nr50: u8,
+    nr51: u8,
+
+    sequencer_timer: u32,
+    sequencer_step: u8,
+
+    sample_rate: u32,
+    // Accumulates `cycles * sample_rate`, a sample is taken once it reaches the clock frequency.
+    sample_clock: u64,
+    // The samples not consumed by the audio output yet, at most one second is kept.
+    samples: Vec<[f32; 2]>,
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            power: true,
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            nr50: 0x00,
+            nr51: 0x00,
+            sequencer_timer: SEQUENCER_PERIOD,
+            sequencer_step: 0,
+            sample_rate,
+            sample_clock: 0,
+            samples: Vec::with_capacity(sample_rate as usize / 30),
+        }
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Take the generated samples, each one is the left and the right output in -1.0 to 1.0.
+    pub fn drain_samples(&mut self) -> Vec<[f32; 2]> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn next(&mut self, cycles: u32) {
+        if !self.power {
+            return;
+        }
+        self.square1.tick(cycles);
+        self.square2.tick(cycles);
+        self.wave.tick(cycles);
+        self.noise.tick(cycles);
+
+        let mut cycles_left = cycles;
+        while cycles_left >= self.sequencer_timer {
+            cycles_left -= self.sequencer_timer;
+            self.sequencer_timer = SEQUENCER_PERIOD;
+            self.step_sequencer();
+        }
+        self.sequencer_timer -= cycles_left;
+
+        self.sample_clock += u64::from(cycles) * u64::from(self.sample_rate);
+        while self.sample_clock >= u64::from(CLOCK_FREQUENCY) {
+            self.sample_clock -= u64::from(CLOCK_FREQUENCY);
+            if self.samples.len() < self.sample_rate as usize {
+                let sample = self.mix();
+                self.samples.push(sample);
+            }
+        }
+    }
+
+    /// Step  Length Ctr  Vol Env     Sweep
+    /// 0     Clock       -           -
+    /// 2     Clock       -           Clock
+    /// 4     Clock       -           -
+    /// 6     Clock       -           Clock
+    /// 7     -           Clock       -
+    fn step_sequencer(&mut self) {
+        if self.sequencer_step & 0x01 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.sequencer_step == 2 || self.sequencer_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.sequencer_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+    }
+
+    fn mix(&self) -> [f32; 2] {
+        let outputs = [
+            self.square1.output(),
+            self.square2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ];
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (i, v) in outputs.iter().enumerate() {
+            let v = f32::from(*v) / 15.0;
+            if self.nr51 & (0x10 << i) != 0 {
+                left += v;
+            }
+            if self.nr51 & (0x01 << i) != 0 {
+                right += v;
+            }
+        }
+        let left_volume = f32::from((self.nr50 >> 4) & 0x07) + 1.0;
+        let right_volume = f32::from(self.nr50 & 0x07) + 1.0;
+        [
+            left / 4.0 * left_volume / 8.0,
+            right / 4.0 * right_volume / 8.0,
+        ]
+    }
+
+    /// Bit 7 - All sound on/off
+    /// Bit 3 - Sound 4 ON flag (Read Only)
+    /// Bit 2 - Sound 3 ON flag (Read Only)
+    /// Bit 1 - Sound 2 ON flag (Read Only)
+    /// Bit 0 - Sound 1 ON flag (Read Only)
+    fn get_nr52(&self) -> u8 {
+        (self.power as u8) << 7
+            | (self.noise.is_enabled() as u8) << 3
+            | (self.wave.is_enabled() as u8) << 2
+            | (self.square2.is_enabled() as u8) << 1
+            | self.square1.is_enabled() as u8
+    }
+
+    /// Turning the power off clears all the registers, the wave ram is kept.
+    fn set_power(&mut self, power: bool) {
+        if self.power && !power {
+            let ram = self.wave_ram();
+            *self = Self {
+                power: false,
+                samples: std::mem::take(&mut self.samples),
+                ..Self::new(self.sample_rate)
+            };
+            for (i, v) in ram.iter().enumerate() {
+                self.wave.write_ram(i, *v);
+            }
+        } else if !self.power && power {
+            self.power = true;
+            self.sequencer_step = 0;
+        }
+    }
+
+    fn wave_ram(&self) -> [u8; 16] {
+        let mut ram = [0x00; 16];
+        for (i, v) in ram.iter_mut().enumerate() {
+            *v = self.wave.read_ram(i);
+        }
+        ram
+    }
+}
+
+impl IOHandler for Apu {
+    fn read_byte(&self, a: u16) -> u8 {
+        let v = match a {
+            0xff10..=0xff14 => self.square1.read(a - 0xff10),
+            0xff15..=0xff19 => self.square2.read(a - 0xff15),
+            0xff1a..=0xff1e => self.wave.read(a - 0xff1a),
+            0xff1f..=0xff23 => self.noise.read(a - 0xff1f),
+            0xff24 => self.nr50,
+            0xff25 => self.nr51,
+            0xff26 => self.get_nr52(),
+            0xff27..=0xff2f => 0x00,
+            0xff30..=0xff3f => return self.wave.read_ram(a as usize - 0xff30),
+            _ => unreachable!("APU should not handle the {:0x} address read operation", a),
+        };
+        v | READ_MASK[a as usize - 0xff10]
+    }
+
+    fn write_byte(&mut self, a: u16, v: u8) {
+        match a {
+            0xff26 => self.set_power(v & 0x80 != 0),
+            0xff30..=0xff3f => self.wave.write_ram(a as usize - 0xff30, v),
+            // The registers are read only while the power is off.
+            _ if !self.power => {}
+            0xff10..=0xff14 => self.square1.write(a - 0xff10, v),
+            0xff15..=0xff19 => self.square2.write(a - 0xff15, v),
+            0xff1a..=0xff1e => self.wave.write(a - 0xff1a, v),
+            0xff1f..=0xff23 => self.noise.write(a - 0xff1f, v),
+            0xff24 => self.nr50 = v,
+            0xff25 => self.nr51 = v,
+            0xff27..=0xff2f => {}
+            _ => panic!(
+                "APU should not handle the {:0x} address write operation, value is {:0x}",
+                a, v
+            ),
+        }
+    }
+}
+
+/// The samples waiting for the audio output are not saved.
+impl Stateful for Apu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_bool(self.power);
+        self.square1.save_state(w);
+        self.square2.save_state(w);
+        self.wave.save_state(w);
+        self.noise.save_state(w);
+        w.put_u8(self.nr50);
+        w.put_u8(self.nr51);
+        w.put_u32(self.sequencer_timer);
+        w.put_u8(self.sequencer_step);
+        w.put_u64(self.sample_clock);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.power = r.get_bool()?;
+        self.square1.load_state(r)?;
+        self.square2.load_state(r)?;
+        self.wave.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.nr50 = r.get_u8()?;
+        self.nr51 = r.get_u8()?;
+        self.sequencer_timer = r.get_u32()?.clamp(1, SEQUENCER_PERIOD);
+        self.sequencer_step = r.get_u8()? & 0x07;
+        // The sample rate may differ from the saving one, keep the fraction in range.
+        self.sample_clock = r.get_u64()? % u64::from(CLOCK_FREQUENCY);
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+pub mod apu;
+pub mod noise;
+pub mod sink;
+pub mod square;
+pub mod unit;
+pub mod wave;
+
+pub use apu::Apu;
+pub use sink::{AudioQueue, AudioSink, WavWriter};
@@ -0,0 +1,137 @@
+use super::unit::{Envelope, LengthCounter};
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// The noise channel, a linear feedback shift register outputs the pseudo random bits.
+///
+/// FF20 - NR41 - Channel 4 Sound Length (W)
+/// FF21 - NR42 - Channel 4 Volume Envelope (R/W)
+/// FF22 - NR43 - Channel 4 Polynomial Counter (R/W)
+///   Bit 7-4 - Shift Clock Frequency (s)
+///   Bit 3   - Counter Step/Width (0=15 bits, 1=7 bits)
+///   Bit 2-0 - Dividing Ratio of Frequencies (r)
+/// FF23 - NR44 - Channel 4 Counter/consecutive; Inital (R/W)
+#[derive(Debug)]
+pub struct Noise {
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    polynomial: u8,
+    lfsr: u16,
+    timer: u32,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+            polynomial: 0,
+            lfsr: 0x7fff,
+            timer: DIVISORS[0],
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The offset starts from the unused FF1F.
+    pub fn read(&self, offset: u16) -> u8 {
+        match offset {
+            0 | 1 => 0x00,
+            2 => self.envelope.get_reg(),
+            3 => self.polynomial,
+            _ => (self.length.is_enabled() as u8) << 6,
+        }
+    }
+
+    pub fn write(&mut self, offset: u16, v: u8) {
+        match offset {
+            0 => {}
+            1 => self.length.load(u16::from(v & 0x3f)),
+            2 => {
+                self.envelope.set_reg(v);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.polynomial = v,
+            _ => {
+                self.length.set_enabled(v & 0x40 != 0);
+                if v & 0x80 != 0 {
+                    self.enabled = self.envelope.is_dac_enabled();
+                    self.lfsr = 0x7fff;
+                    self.timer = self.get_period();
+                    self.length.trigger();
+                    self.envelope.trigger();
+                }
+            }
+        }
+    }
+
+    fn get_period(&self) -> u32 {
+        DIVISORS[(self.polynomial & 0x07) as usize] << (self.polynomial >> 4)
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.get_period();
+            let bit = (self.lfsr & 0x01) ^ ((self.lfsr >> 1) & 0x01);
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+            if self.polynomial & 0x08 != 0 {
+                self.lfsr = (self.lfsr & !0x40) | (bit << 6);
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Returns the volume 0-15, the channel outputs when the bit 0 of LFSR is zero.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 0x01 != 0 {
+            return 0;
+        }
+        self.envelope.get_volume()
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stateful for Noise {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_bool(self.enabled);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+        w.put_u8(self.polynomial);
+        w.put_u16(self.lfsr);
+        w.put_u32(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.get_bool()?;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)?;
+        self.polynomial = r.get_u8()?;
+        self.lfsr = r.get_u16()? & 0x7fff;
+        self.timer = r.get_u32()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use log::error;
+
+/// Where the samples produced by the APU go at the end of every frame.
+pub trait AudioSink: Send {
+    /// The samples are the stereo pairs in -1.0 to 1.0.
+    fn write(&mut self, samples: &[[f32; 2]]);
+
+    /// Returns the samples written but not played yet, `None` if the sink doesn't play them in
+    /// real time. The audio sync paces the emulation by it.
+    fn get_queued(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// The ring buffer between the emulator and the audio device. The emulator writes the samples
+/// of every frame, the callback of the device pops them from another thread through a clone.
+#[derive(Clone)]
+pub struct AudioQueue {
+    samples: Arc<Mutex<VecDeque<[f32; 2]>>>,
+    // The oldest samples are dropped beyond it, so a stalled device doesn't grow the buffer.
+    capacity: usize,
+}
+
+impl AudioQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Fill the buffer of the device, the missing samples are silence. Returns the samples
+    /// popped from the queue.
+    pub fn pop(&self, out: &mut [[f32; 2]]) -> usize {
+        let mut samples = self.samples.lock().unwrap();
+        let n = out.len().min(samples.len());
+        for (o, s) in out.iter_mut().zip(samples.drain(..n)) {
+            *o = s;
+        }
+        for o in out[n..].iter_mut() {
+            *o = [0.0; 2];
+        }
+        n
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl AudioSink for AudioQueue {
+    fn write(&mut self, samples: &[[f32; 2]]) {
+        let mut queue = self.samples.lock().unwrap();
+        queue.extend(samples);
+        let over = queue.len().saturating_sub(self.capacity);
+        queue.drain(..over);
+    }
+
+    fn get_queued(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+/// Dump the audio into a 16-bit stereo PCM wav file, the header sizes are filled when it's dropped.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    // The bytes of the sample data written.
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let block_align: u16 = 2 * 2;
+        file.write_all(b"RIFF")?;
+        file.write_all(&36u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM, 2 channels.
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * u32::from(block_align)).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { file, data_size: 0 })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + self.data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_size.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn write(&mut self, samples: &[[f32; 2]]) {
+        for sample in samples {
+            for v in sample {
+                let v = (v.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+                if let Err(e) = self.file.write_all(&v.to_le_bytes()) {
+                    error!("Failed to write the audio dump: {}", e);
+                    return;
+                }
+                self.data_size += 2;
+            }
+        }
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            error!("Failed to finish the audio dump: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_queue() {
+        let mut queue = AudioQueue::new(4);
+        let device = queue.clone();
+        queue.write(&[[0.1; 2], [0.2; 2], [0.3; 2]]);
+        assert_eq!(queue.get_queued(), Some(3));
+
+        let mut out = [[1.0; 2]; 2];
+        assert_eq!(device.pop(&mut out), 2);
+        assert_eq!(out, [[0.1; 2], [0.2; 2]]);
+        // The underrun is filled with silence.
+        let mut out = [[1.0; 2]; 2];
+        assert_eq!(device.pop(&mut out), 1);
+        assert_eq!(out, [[0.3; 2], [0.0; 2]]);
+        assert!(device.is_empty());
+
+        // The oldest samples are dropped beyond the capacity.
+        queue.write(&[[0.1; 2], [0.2; 2], [0.3; 2], [0.4; 2], [0.5; 2]]);
+        let mut out = [[1.0; 2]; 4];
+        assert_eq!(device.pop(&mut out), 4);
+        assert_eq!(out[0], [0.2; 2]);
+    }
+}
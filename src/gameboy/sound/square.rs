@@ -0,0 +1,220 @@
+use super::unit::{Envelope, LengthCounter};
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+
+/// The waveform of each duty, the bit 7 is the first step.
+///
+/// 00: 12.5% ( _-------_-------_------- )
+/// 01: 25%   ( __------__------__------ )
+/// 10: 50%   ( ____----____----____---- )
+/// 11: 75%   ( ______--______--______-- )
+const DUTY: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// The square channel, channel 1 has the frequency sweep and channel 2 doesn't.
+///
+/// FF10 - NR10 - Channel 1 Sweep register (R/W)
+/// FF11 - NR11 - Channel 1 Sound length/Wave pattern duty (R/W)
+/// FF12 - NR12 - Channel 1 Volume Envelope (R/W)
+/// FF13 - NR13 - Channel 1 Frequency lo (Write Only)
+/// FF14 - NR14 - Channel 1 Frequency hi (R/W)
+#[derive(Debug)]
+pub struct Square {
+    enabled: bool,
+    has_sweep: bool,
+    // NR10, the sweep period, the direction and the shift.
+    sweep_reg: u8,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    shadow_frequency: u16,
+
+    duty: u8,
+    // The current step of the duty waveform.
+    step: u8,
+    length: LengthCounter,
+    envelope: Envelope,
+    frequency: u16,
+    timer: u32,
+}
+
+impl Square {
+    pub fn new(has_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            has_sweep,
+            sweep_reg: 0,
+            sweep_timer: 0,
+            sweep_enabled: false,
+            shadow_frequency: 0,
+            duty: 0,
+            step: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+            frequency: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Read the register at the offset from NRx0, the write only bits are returned as they are.
+    pub fn read(&self, offset: u16) -> u8 {
+        match offset {
+            0 => self.sweep_reg,
+            1 => self.duty << 6,
+            2 => self.envelope.get_reg(),
+            3 => self.frequency as u8,
+            _ => (self.length.is_enabled() as u8) << 6,
+        }
+    }
+
+    pub fn write(&mut self, offset: u16, v: u8) {
+        match offset {
+            0 => self.sweep_reg = v & 0x7f,
+            1 => {
+                self.duty = v >> 6;
+                self.length.load(u16::from(v & 0x3f));
+            }
+            2 => {
+                self.envelope.set_reg(v);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x0700) | u16::from(v),
+            _ => {
+                self.frequency = (self.frequency & 0x00ff) | (u16::from(v & 0x07) << 8);
+                self.length.set_enabled(v & 0x40 != 0);
+                if v & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    fn get_period(&self) -> u32 {
+        (2048 - u32::from(self.frequency)) * 4
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.timer = self.get_period();
+        self.length.trigger();
+        self.envelope.trigger();
+
+        if self.has_sweep {
+            self.shadow_frequency = self.frequency;
+            self.sweep_timer = self.get_sweep_period();
+            let shift = self.sweep_reg & 0x07;
+            self.sweep_enabled = self.sweep_reg & 0x70 != 0 || shift != 0;
+            // The overflow check is done immediately if the shift is non-zero.
+            if shift != 0 && self.calc_sweep() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// The sweep timer treats the period 0 as 8.
+    fn get_sweep_period(&self) -> u8 {
+        match (self.sweep_reg >> 4) & 0x07 {
+            0 => 8,
+            n => n,
+        }
+    }
+
+    fn calc_sweep(&self) -> u16 {
+        let delta = self.shadow_frequency >> (self.sweep_reg & 0x07);
+        if self.sweep_reg & 0x08 != 0 {
+            self.shadow_frequency.wrapping_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.get_period();
+            self.step = (self.step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocked by the frame sequencer at 128 Hz.
+    pub fn clock_sweep(&mut self) {
+        if !self.has_sweep {
+            return;
+        }
+        if self.sweep_timer > 0 {
+            self.sweep_timer -= 1;
+        }
+        if self.sweep_timer != 0 {
+            return;
+        }
+        self.sweep_timer = self.get_sweep_period();
+        if !self.sweep_enabled || self.sweep_reg & 0x70 == 0 {
+            return;
+        }
+        let frequency = self.calc_sweep();
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if self.sweep_reg & 0x07 != 0 {
+            self.shadow_frequency = frequency;
+            self.frequency = frequency;
+            // Check the overflow again with the new frequency.
+            if self.calc_sweep() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Returns the volume 0-15 of the current step.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || DUTY[self.duty as usize] & (0x80 >> self.step) == 0 {
+            return 0;
+        }
+        self.envelope.get_volume()
+    }
+}
+
+impl Stateful for Square {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_bool(self.enabled);
+        w.put_u8(self.sweep_reg);
+        w.put_u8(self.sweep_timer);
+        w.put_bool(self.sweep_enabled);
+        w.put_u16(self.shadow_frequency);
+        w.put_u8(self.duty);
+        w.put_u8(self.step);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+        w.put_u16(self.frequency);
+        w.put_u32(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.get_bool()?;
+        self.sweep_reg = r.get_u8()? & 0x7f;
+        self.sweep_timer = r.get_u8()?;
+        self.sweep_enabled = r.get_bool()?;
+        self.shadow_frequency = r.get_u16()?;
+        self.duty = r.get_u8()? & 0x03;
+        self.step = r.get_u8()? & 0x07;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)?;
+        self.frequency = r.get_u16()? & 0x07ff;
+        self.timer = r.get_u32()?;
+        Ok(())
+    }
+}
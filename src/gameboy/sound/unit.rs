@@ -0,0 +1,137 @@
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+
+/// The length counter, it silences the channel once it counts down to zero.
+/// It's clocked by the frame sequencer at 256 Hz.
+#[derive(Debug)]
+pub struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    /// `max` is 64 for the square and the noise channel, 256 for the wave channel.
+    pub fn new(max: u16) -> Self {
+        Self {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    /// Load the length data written to NRx1, the counter is `max - length`.
+    pub fn load(&mut self, length: u16) {
+        self.counter = self.max - length;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// A zero counter is reloaded with the max length on trigger.
+    pub fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// Returns true if the counter reaches zero, the channel is disabled then.
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+/// The volume envelope, NRx2.
+///
+/// Bit 7-4 - Initial Volume of envelope (0-0Fh) (0=No Sound)
+/// Bit 3   - Envelope Direction (0=Decrease, 1=Increase)
+/// Bit 2-0 - Number of envelope sweep (n: 0-7) (If zero, stop envelope operation.)
+#[derive(Debug, Default)]
+pub struct Envelope {
+    reg: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn get_reg(&self) -> u8 {
+        self.reg
+    }
+
+    pub fn set_reg(&mut self, v: u8) {
+        self.reg = v;
+    }
+
+    /// The DAC is off if the upper 5 bits are all zero, the channel is disabled with it.
+    pub fn is_dac_enabled(&self) -> bool {
+        self.reg & 0xf8 != 0
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.volume
+    }
+
+    fn get_period(&self) -> u8 {
+        self.reg & 0x07
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.reg >> 4;
+        self.timer = self.get_period();
+    }
+
+    /// Clocked by the frame sequencer at 64 Hz.
+    pub fn clock(&mut self) {
+        let period = self.get_period();
+        if period == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = period;
+            if self.reg & 0x08 != 0 {
+                if self.volume < 15 {
+                    self.volume += 1;
+                }
+            } else if self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+impl Stateful for LengthCounter {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u16(self.counter);
+        w.put_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.counter = r.get_u16()?.min(self.max);
+        self.enabled = r.get_bool()?;
+        Ok(())
+    }
+}
+
+impl Stateful for Envelope {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_bytes(&[self.reg, self.volume, self.timer]);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.reg = r.get_u8()?;
+        self.volume = r.get_u8()? & 0x0f;
+        self.timer = r.get_u8()?;
+        Ok(())
+    }
+}
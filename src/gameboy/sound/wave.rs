@@ -0,0 +1,149 @@
+use super::unit::LengthCounter;
+use crate::gameboy::state::{StateError, StateReader, StateWriter, Stateful};
+
+/// The wave channel, it plays the 32 4-bit samples in the wave pattern ram.
+///
+/// FF1A - NR30 - Channel 3 Sound on/off (R/W)
+/// FF1B - NR31 - Channel 3 Sound Length (W)
+/// FF1C - NR32 - Channel 3 Select output level (R/W)
+/// FF1D - NR33 - Channel 3 Frequency's lower data (W)
+/// FF1E - NR34 - Channel 3 Frequency's higher data (R/W)
+/// FF30-FF3F - Wave Pattern RAM, the upper 4 bits are played first.
+#[derive(Debug)]
+pub struct Wave {
+    enabled: bool,
+    dac_enabled: bool,
+    length: LengthCounter,
+    // Bit 6-5 of NR32: 0 mute, 1 100%, 2 50%, 3 25%.
+    level: u8,
+    frequency: u16,
+    timer: u32,
+    // The index 0-31 of the playing sample.
+    position: u8,
+    ram: [u8; 16],
+}
+
+impl Wave {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            level: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            ram: [0x00; 16],
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn read(&self, offset: u16) -> u8 {
+        match offset {
+            0 => (self.dac_enabled as u8) << 7,
+            1 => 0x00,
+            2 => self.level << 5,
+            3 => self.frequency as u8,
+            _ => (self.length.is_enabled() as u8) << 6,
+        }
+    }
+
+    pub fn write(&mut self, offset: u16, v: u8) {
+        match offset {
+            0 => {
+                self.dac_enabled = v & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(u16::from(v)),
+            2 => self.level = (v >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x0700) | u16::from(v),
+            _ => {
+                self.frequency = (self.frequency & 0x00ff) | (u16::from(v & 0x07) << 8);
+                self.length.set_enabled(v & 0x40 != 0);
+                if v & 0x80 != 0 {
+                    self.enabled = self.dac_enabled;
+                    self.timer = self.get_period();
+                    self.position = 0;
+                    self.length.trigger();
+                }
+            }
+        }
+    }
+
+    pub fn read_ram(&self, i: usize) -> u8 {
+        self.ram[i]
+    }
+
+    pub fn write_ram(&mut self, i: usize, v: u8) {
+        self.ram[i] = v;
+    }
+
+    fn get_period(&self) -> u32 {
+        (2048 - u32::from(self.frequency)) * 2
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.get_period();
+            self.position = (self.position + 1) % 32;
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Returns the volume 0-15 of the playing sample.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.level == 0 {
+            return 0;
+        }
+        let b = self.ram[self.position as usize / 2];
+        let sample = if self.position & 0x01 == 0 {
+            b >> 4
+        } else {
+            b & 0x0f
+        };
+        sample >> (self.level - 1)
+    }
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stateful for Wave {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_bool(self.enabled);
+        w.put_bool(self.dac_enabled);
+        self.length.save_state(w);
+        w.put_u8(self.level);
+        w.put_u16(self.frequency);
+        w.put_u32(self.timer);
+        w.put_u8(self.position);
+        w.put_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.get_bool()?;
+        self.dac_enabled = r.get_bool()?;
+        self.length.load_state(r)?;
+        self.level = r.get_u8()? & 0x03;
+        self.frequency = r.get_u16()? & 0x07ff;
+        self.timer = r.get_u32()?;
+        self.position = r.get_u8()? & 0x1f;
+        r.fill(&mut self.ram)
+    }
+}
@@ -1,12 +1,15 @@
-use log::warn;
+use std::str::FromStr;
+
+use super::cartridge::CartridgePlatform;
 
 // Gameboy hardware specifications
 pub const SCREEN_W: usize = 160;
 pub const SCREEN_H: usize = 144;
 
 pub const CLOCK_FREQUENCY: u32 = 4_194_304;
+// An entire frame is 154 scanlines, 456 dots per scanline.
+pub const FRAME_CYCLES: u32 = 70224;
 pub const STEP_TIME: u32 = 16;
-pub const STEP_CYCLES: u32 = (STEP_TIME as f64 / (1000_f64 / CLOCK_FREQUENCY as f64)) as u32;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Term {
@@ -16,18 +19,76 @@ pub enum Term {
     SGB, // Super GameBoy
 }
 
-static mut NOW_TERM: Term = Term::GB;
+/// The hardware the games run on, a CGB enhanced game runs on the DMG too. The CPU starts with
+/// the A register of the hardware, and the GPU uses the palettes and the VRAM bank of the CGB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Follow the platform of the cartridge header.
+    Auto,
+    Dmg,
+    Cgb,
+}
+
+impl Mode {
+    pub fn get_term(self, platform: CartridgePlatform) -> Term {
+        match (self, platform) {
+            (Mode::Dmg, _) => Term::GB,
+            (Mode::Cgb, _) => Term::GBC,
+            (_, CartridgePlatform::GBC) => Term::GBC,
+            (_, CartridgePlatform::GBC_ONLY) => Term::GBC,
+            (_, CartridgePlatform::SGB) => Term::SGB,
+            _ => Term::GB,
+        }
+    }
+}
+
+/// How closely the timing of the hardware is followed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Accuracy {
+    /// The GPU registers change at the whole lines, it's enough for most games.
+    Fast,
+    /// Keep the quirks of the line boundaries, for the games polling LY.
+    Accurate,
+}
+
+impl FromStr for Accuracy {
+    type Err = String;
 
-pub fn get_global_term() -> Term {
-    unsafe { NOW_TERM }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Self::Fast),
+            "accurate" => Ok(Self::Accurate),
+            _ => Err(format!("unknown accuracy {}", s)),
+        }
+    }
 }
 
-pub fn set_global_term(t: Term) {
-    unsafe {
-        warn!(
-            "Change the Emulator from {:?} to {:?}, it will affect global",
-            NOW_TERM, t
-        );
-        NOW_TERM = t
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "dmg" => Ok(Self::Dmg),
+            "cgb" => Ok(Self::Cgb),
+            _ => Err(format!("unknown mode {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode() {
+        assert_eq!(Mode::Auto.get_term(CartridgePlatform::GBC_ONLY), Term::GBC);
+        assert_eq!(Mode::Auto.get_term(CartridgePlatform::SGB), Term::SGB);
+        assert_eq!(Mode::Dmg.get_term(CartridgePlatform::GBC), Term::GB);
+        assert_eq!(Mode::Cgb.get_term(CartridgePlatform::GB), Term::GBC);
+        assert_eq!("cgb".parse(), Ok(Mode::Cgb));
+        assert!("gba".parse::<Mode>().is_err());
+        assert_eq!("accurate".parse(), Ok(Accuracy::Accurate));
+        assert!("exact".parse::<Accuracy>().is_err());
     }
 }
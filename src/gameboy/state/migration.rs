@@ -0,0 +1,111 @@
+use super::{
+    Snapshot, StateError, CPU_SECTION, GPU_SECTION, MMU_SECTION, STATE_VERSION, TIMER_SECTION,
+};
+
+type Migration = fn(&mut Snapshot) -> Result<(), StateError>;
+
+/// `MIGRATIONS[n]` upgrades a snapshot from version `n + 1` to `n + 2`. Never edit a released
+/// step, append a new one instead.
+const MIGRATIONS: [Migration; STATE_VERSION as usize - 1] = [
+    v1_add_speed,
+    v2_add_stop,
+    v3_add_dma,
+    v4_add_hdma,
+    v5_add_window_line,
+    v6_timer_counter,
+    v7_add_lock,
+];
+
+/// Version 2 appends the CGB double speed and the prepared speed switch to the mmu.
+fn v1_add_speed(snapshot: &mut Snapshot) -> Result<(), StateError> {
+    let mut data = snapshot
+        .get_section(MMU_SECTION)
+        .ok_or(StateError::MissingSection(MMU_SECTION))?
+        .to_vec();
+    data.extend_from_slice(&[0x00, 0x00]);
+    snapshot.set_section(MMU_SECTION, data);
+    Ok(())
+}
+
+/// Version 3 appends the STOP mode and the pending HALT bug to the cpu.
+fn v2_add_stop(snapshot: &mut Snapshot) -> Result<(), StateError> {
+    let mut data = snapshot
+        .get_section(CPU_SECTION)
+        .ok_or(StateError::MissingSection(CPU_SECTION))?
+        .to_vec();
+    data.extend_from_slice(&[0x00, 0x00]);
+    snapshot.set_section(CPU_SECTION, data);
+    Ok(())
+}
+
+/// Version 4 appends the OAM DMA to the mmu, the transfer was instant before.
+fn v3_add_dma(snapshot: &mut Snapshot) -> Result<(), StateError> {
+    let mut data = snapshot
+        .get_section(MMU_SECTION)
+        .ok_or(StateError::MissingSection(MMU_SECTION))?
+        .to_vec();
+    data.extend_from_slice(&[0x00; 7]);
+    snapshot.set_section(MMU_SECTION, data);
+    Ok(())
+}
+
+/// Version 5 appends the CGB VRAM DMA to the mmu, no transfer was running before.
+fn v4_add_hdma(snapshot: &mut Snapshot) -> Result<(), StateError> {
+    let mut data = snapshot
+        .get_section(MMU_SECTION)
+        .ok_or(StateError::MissingSection(MMU_SECTION))?
+        .to_vec();
+    data.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x7f, 0x00, 0x00, 0x00, 0x00]);
+    snapshot.set_section(MMU_SECTION, data);
+    Ok(())
+}
+
+/// Version 6 appends the window line counter to the gpu, it restarts from the top of the window.
+/// The snapshots of the cpu and the mmu alone have no gpu to upgrade.
+fn v5_add_window_line(snapshot: &mut Snapshot) -> Result<(), StateError> {
+    if let Some(data) = snapshot.get_section(GPU_SECTION) {
+        let mut data = data.to_vec();
+        data.push(0x00);
+        snapshot.set_section(GPU_SECTION, data);
+    }
+    Ok(())
+}
+
+/// Version 7 keeps the internal 16-bit counter of the timer rather than DIV and the clocks, DIV
+/// and the counter bits below it are made of the DIV clock. No reload is pending.
+fn v6_timer_counter(snapshot: &mut Snapshot) -> Result<(), StateError> {
+    if let Some(data) = snapshot.get_section(TIMER_SECTION) {
+        if data.len() < 12 {
+            return Err(StateError::Truncated);
+        }
+        let div_cycles = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        let counter = u16::from(data[0]) << 8 | (div_cycles & 0xff) as u16;
+        let mut upgraded = counter.to_le_bytes().to_vec();
+        upgraded.extend_from_slice(&[data[1], data[2], data[3], 0x00]);
+        snapshot.set_section(TIMER_SECTION, upgraded);
+    }
+    Ok(())
+}
+
+/// Version 8 appends the lock-up by an unknown opcode to the cpu, the older builds panicked on it.
+fn v7_add_lock(snapshot: &mut Snapshot) -> Result<(), StateError> {
+    let mut data = snapshot
+        .get_section(CPU_SECTION)
+        .ok_or(StateError::MissingSection(CPU_SECTION))?
+        .to_vec();
+    data.extend_from_slice(&[0x00; 4]);
+    snapshot.set_section(CPU_SECTION, data);
+    Ok(())
+}
+
+/// Upgrade the snapshot to the current version step by step.
+pub fn migrate(snapshot: &mut Snapshot) -> Result<(), StateError> {
+    if snapshot.version == 0 || snapshot.version > STATE_VERSION {
+        return Err(StateError::UnsupportedVersion(snapshot.version));
+    }
+    while snapshot.version < STATE_VERSION {
+        MIGRATIONS[snapshot.version as usize - 1](snapshot)?;
+        snapshot.version += 1;
+    }
+    Ok(())
+}
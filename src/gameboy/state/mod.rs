@@ -0,0 +1,366 @@
+//! The save state container.
+//!
+//! A snapshot file is a versioned list of tagged sections, each component of the machine owns
+//! one section:
+//! ```ignore
+//! Offset  Size  Description
+//! 0       4     Magic "NGCS"
+//! 4       2     Format version, little endian
+//! 6       2     Count of sections
+//! 8       ...   Sections: 4 bytes tag, 4 bytes length, then the payload
+//! ```
+//! An older snapshot is upgraded by the migration layer before it is restored, so a state saved
+//! by a previous release always loads into the current one. A section added by a later release is
+//! optional, the component keeps its current state if the snapshot doesn't have it.
+//!
+//! The sections are the only form of the timer, the GPU and the MMU state, they don't derive
+//! serde: they share the interrupt register and own the cartridge trait object. The serde
+//! derives are on the values for the tools, the registers, the `CpuRecord` of the history and
+//! the cartridge info with its mapper and clock state.
+mod migration;
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub use migration::migrate;
+
+const MAGIC: &[u8; 4] = b"NGCS";
+
+/// The version of the snapshot layout written by this build. Bump it and append a migration once
+/// the payload of any section is changed.
+pub const STATE_VERSION: u16 = 8;
+
+pub const CPU_SECTION: [u8; 4] = *b"CPU ";
+pub const MMU_SECTION: [u8; 4] = *b"MMU ";
+pub const GPU_SECTION: [u8; 4] = *b"GPU ";
+pub const TIMER_SECTION: [u8; 4] = *b"TIMR";
+pub const JOYPAD_SECTION: [u8; 4] = *b"JOYP";
+pub const CARTRIDGE_SECTION: [u8; 4] = *b"CART";
+pub const APU_SECTION: [u8; 4] = *b"APU ";
+pub const SERIAL_SECTION: [u8; 4] = *b"SIO ";
+pub const BOOT_ROM_SECTION: [u8; 4] = *b"BOOT";
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(io::Error),
+    /// The file is not a snapshot, or not a movie.
+    BadMagic,
+    /// The snapshot is written by a newer release.
+    UnsupportedVersion(u16),
+    /// The data ends in the middle of a field.
+    Truncated,
+    MissingSection([u8; 4]),
+    /// The snapshot is taken from another game.
+    WrongCartridge,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::Io(e) => write!(f, "io error: {}", e),
+            StateError::BadMagic => write!(f, "unknown file format"),
+            StateError::UnsupportedVersion(v) => write!(
+                f,
+                "snapshot version {} is not supported, the latest is {}",
+                v, STATE_VERSION
+            ),
+            StateError::Truncated => write!(f, "snapshot is truncated"),
+            StateError::MissingSection(tag) => {
+                write!(f, "section {:?} is missing", String::from_utf8_lossy(tag))
+            }
+            StateError::WrongCartridge => write!(f, "snapshot is taken from another game"),
+        }
+    }
+}
+
+impl error::Error for StateError {}
+
+impl From<io::Error> for StateError {
+    fn from(e: io::Error) -> Self {
+        StateError::Io(e)
+    }
+}
+
+/// A component which could be saved into a snapshot section and restored from it.
+pub trait Stateful {
+    fn save_state(&self, w: &mut StateWriter);
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn put_bool(&mut self, v: bool) {
+        self.put_u8(v as u8);
+    }
+
+    pub fn put_u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn put_bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Returns the count of unread bytes.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn get_bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.remaining() < n {
+            return Err(StateError::Truncated);
+        }
+        let v = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(v)
+    }
+
+    /// Fill the whole slice from the reader.
+    pub fn fill(&mut self, dst: &mut [u8]) -> Result<(), StateError> {
+        dst.copy_from_slice(self.get_bytes(dst.len())?);
+        Ok(())
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.get_bytes(1)?[0])
+    }
+
+    pub fn get_bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.get_u8()? != 0)
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16, StateError> {
+        let b = self.get_bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, StateError> {
+        let b = self.get_bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, StateError> {
+        let mut b = [0x00; 8];
+        self.fill(&mut b)?;
+        Ok(u64::from_le_bytes(b))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub tag: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// A decoded snapshot file.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    version: u16,
+    sections: Vec<Section>,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot {
+    /// Returns an empty snapshot of the current version.
+    pub fn new() -> Self {
+        Self {
+            version: STATE_VERSION,
+            sections: vec![],
+        }
+    }
+
+    pub fn get_version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn get_sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn get_section(&self, tag: [u8; 4]) -> Option<&[u8]> {
+        self.sections
+            .iter()
+            .find(|s| s.tag == tag)
+            .map(|s| s.data.as_slice())
+    }
+
+    /// Insert or replace the raw payload of a section.
+    pub fn set_section(&mut self, tag: [u8; 4], data: Vec<u8>) {
+        match self.sections.iter_mut().find(|s| s.tag == tag) {
+            Some(s) => s.data = data,
+            None => self.sections.push(Section { tag, data }),
+        }
+    }
+
+    /// Save the component into the section.
+    pub fn put(&mut self, tag: [u8; 4], component: &dyn Stateful) {
+        let mut w = StateWriter::new();
+        component.save_state(&mut w);
+        self.set_section(tag, w.into_bytes());
+    }
+
+    /// Restore the component from the section.
+    pub fn restore(&self, tag: [u8; 4], component: &mut dyn Stateful) -> Result<(), StateError> {
+        let data = self
+            .get_section(tag)
+            .ok_or(StateError::MissingSection(tag))?;
+        component.load_state(&mut StateReader::new(data))
+    }
+
+    /// Restore the component if the section is found, returns whether it's restored.
+    pub fn restore_optional(
+        &self,
+        tag: [u8; 4],
+        component: &mut dyn Stateful,
+    ) -> Result<bool, StateError> {
+        match self.get_section(tag) {
+            Some(data) => component
+                .load_state(&mut StateReader::new(data))
+                .map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.put_bytes(MAGIC);
+        w.put_u16(self.version);
+        w.put_u16(self.sections.len() as u16);
+        for s in &self.sections {
+            w.put_bytes(&s.tag);
+            w.put_u32(s.data.len() as u32);
+            w.put_bytes(&s.data);
+        }
+        w.into_bytes()
+    }
+
+    /// Decode the snapshot as it is, without migration.
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, StateError> {
+        let mut r = StateReader::new(buf);
+        if r.get_bytes(4).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.get_u16()?;
+        let n = r.get_u16()?;
+        let mut sections = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            let mut tag = [0x00; 4];
+            r.fill(&mut tag)?;
+            let len = r.get_u32()? as usize;
+            let data = r.get_bytes(len)?.to_vec();
+            sections.push(Section { tag, data });
+        }
+        Ok(Self { version, sections })
+    }
+
+    /// Read a snapshot file, it's upgraded to the current version.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        let mut snapshot = Self::from_bytes(&fs::read(path)?)?;
+        migrate(&mut snapshot)?;
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Returns a human readable summary, used by the `state-info` command.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![
+            format!("version: {} (latest {})", self.version, STATE_VERSION),
+            format!("sections: {}", self.sections.len()),
+        ];
+        for s in &self.sections {
+            lines.push(format!(
+                "  {} {:>6} bytes",
+                String::from_utf8_lossy(&s.tag),
+                s.data.len()
+            ));
+        }
+        // The CPU layout never changed so far, decode it regardless of the version.
+        if let Some(cpu) = self.get_section(CPU_SECTION) {
+            let mut r = StateReader::new(cpu);
+            let regs: Result<Vec<u16>, StateError> = (0..6).map(|_| r.get_u16()).collect();
+            if let Ok(regs) = regs {
+                lines.push(format!(
+                    "cpu: AF={:04x} BC={:04x} DE={:04x} HL={:04x} SP={:04x} PC={:04x}",
+                    regs[0], regs[1], regs[2], regs[3], regs[4], regs[5]
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let mut snapshot = Snapshot::new();
+        snapshot.set_section(CPU_SECTION, vec![0x01, 0x02]);
+        snapshot.set_section(MMU_SECTION, vec![]);
+        snapshot.set_section(CPU_SECTION, vec![0x03]);
+
+        let decoded = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        assert_eq!(decoded.get_version(), STATE_VERSION);
+        assert_eq!(decoded.get_sections().len(), 2);
+        assert_eq!(decoded.get_section(CPU_SECTION), Some(&[0x03][..]));
+
+        let bytes = snapshot.to_bytes();
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(StateError::Truncated)
+        ));
+        assert!(matches!(
+            Snapshot::from_bytes(b"GBS"),
+            Err(StateError::BadMagic)
+        ));
+    }
+}
@@ -0,0 +1,219 @@
+//! The statistics of the emulation loop, they're collected every second to diagnose the
+//! pacing. The speed simulation sleeps in `FramePacer::wait` after every frame, the time it
+//! doesn't sleep is the time the host spends on the emulation.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::CLOCK_FREQUENCY;
+
+const PERIOD: Duration = Duration::from_secs(1);
+
+/// The window title, `{title}` is the title of the cartridge, `{fps}` and `{speed}` are the frames
+/// per second and the speed in percent of the last second.
+pub const DEFAULT_TITLE_FORMAT: &str = "{title} - {fps} fps {speed}%";
+
+/// Fill the title format in, the statistics are `--` in the first second.
+pub fn format_title(format: &str, title: &str, stats: Option<&FrameStats>) -> String {
+    let (fps, speed) = match stats {
+        Some(stats) => (
+            format!("{:.0}", stats.get_frames_per_sec()),
+            format!("{:.0}", stats.get_speed_percent()),
+        ),
+        None => ("--".to_string(), "--".to_string()),
+    };
+    format
+        .replace("{title}", title)
+        .replace("{fps}", &fps)
+        .replace("{speed}", &speed)
+}
+
+/// The statistics of a period, usually one second.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FrameStats {
+    pub frames: u32,
+    pub cycles: u64,
+    // The wall time of the period.
+    pub elapsed: Duration,
+    // The time slept by the speed simulation.
+    pub slept: Duration,
+    // The frames which ran too long to sleep at all.
+    pub late_frames: u64,
+}
+
+impl FrameStats {
+    pub fn get_frames_per_sec(&self) -> f64 {
+        f64::from(self.frames) / self.elapsed.as_secs_f64()
+    }
+
+    pub fn get_cycles_per_sec(&self) -> f64 {
+        self.cycles as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Returns the emulation speed, 100 means the real hardware speed.
+    pub fn get_speed_percent(&self) -> f64 {
+        self.get_cycles_per_sec() / f64::from(CLOCK_FREQUENCY) * 100.0
+    }
+
+    /// Returns the share of the wall time the emulation loop doesn't sleep, in percent.
+    pub fn get_cpu_percent(&self) -> f64 {
+        let busy = self.elapsed.saturating_sub(self.slept);
+        busy.as_secs_f64() / self.elapsed.as_secs_f64() * 100.0
+    }
+
+    /// Returns the short lines for the OSD.
+    pub fn get_lines(&self) -> Vec<String> {
+        vec![
+            format!(
+                "{:.0} fps {:.0}% speed",
+                self.get_frames_per_sec(),
+                self.get_speed_percent()
+            ),
+            format!(
+                "cpu {:.0}% late {}",
+                self.get_cpu_percent(),
+                self.late_frames
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} frames/sec, {:.0} cycles/sec ({:.1}% speed), {:.1}% cpu, {} late frames",
+            self.get_frames_per_sec(),
+            self.get_cycles_per_sec(),
+            self.get_speed_percent(),
+            self.get_cpu_percent(),
+            self.late_frames
+        )
+    }
+}
+
+/// Counts the frames and the cycles, and closes a period every second.
+pub struct StatsCollector {
+    start: Instant,
+    current: FrameStats,
+    // The counters of the pacer at the start of the period, they're totals since the power on.
+    slept: Duration,
+    late_frames: u64,
+    last: Option<FrameStats>,
+}
+
+impl StatsCollector {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            start: now,
+            current: FrameStats::default(),
+            slept: Duration::from_secs(0),
+            late_frames: 0,
+            last: None,
+        }
+    }
+
+    /// Record a finished frame with the totals of the pacer, returns the statistics of the period
+    /// once it's closed.
+    pub fn frame(
+        &mut self,
+        cycles: u64,
+        slept: Duration,
+        late_frames: u64,
+        now: Instant,
+    ) -> Option<FrameStats> {
+        self.current.frames += 1;
+        self.current.cycles += cycles;
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed < PERIOD {
+            return None;
+        }
+        let stats = FrameStats {
+            elapsed,
+            slept: slept - self.slept,
+            late_frames: late_frames - self.late_frames,
+            ..self.current
+        };
+        self.start = now;
+        self.current = FrameStats::default();
+        self.slept = slept;
+        self.late_frames = late_frames;
+        self.last = Some(stats);
+        self.last
+    }
+
+    /// Returns the statistics of the last closed period.
+    pub fn get_last(&self) -> Option<FrameStats> {
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect() {
+        let start = Instant::now();
+        let mut stats = StatsCollector::new(start);
+        let frame = Duration::from_secs(1) / 60;
+        let cycles = u64::from(CLOCK_FREQUENCY) / 60;
+        for i in 1..60 {
+            let slept = Duration::from_millis(10) * i;
+            assert_eq!(stats.frame(cycles, slept, 0, start + frame * i), None);
+        }
+        let last = stats
+            .frame(
+                cycles,
+                Duration::from_millis(600),
+                2,
+                start + Duration::from_secs(1),
+            )
+            .unwrap();
+        assert_eq!(last.frames, 60);
+        assert_eq!(last.cycles, cycles * 60);
+        assert_eq!(last.late_frames, 2);
+        assert!((last.get_frames_per_sec() - 60.0).abs() < 0.1);
+        assert!((last.get_speed_percent() - 100.0).abs() < 0.1);
+        assert!((last.get_cpu_percent() - 40.0).abs() < 0.1);
+        assert_eq!(stats.get_last(), Some(last));
+        assert_eq!(
+            last.get_lines(),
+            vec!["60 fps 100% speed", "cpu 40% late 2"]
+        );
+
+        // The next period only counts the sleep after the last one.
+        let next = stats
+            .frame(
+                cycles,
+                Duration::from_millis(1100),
+                2,
+                start + Duration::from_secs(2),
+            )
+            .unwrap();
+        assert_eq!(next.frames, 1);
+        assert_eq!(next.late_frames, 0);
+        assert!((next.get_cpu_percent() - 50.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_format_title() {
+        let stats = FrameStats {
+            frames: 59,
+            cycles: u64::from(CLOCK_FREQUENCY) * 2,
+            elapsed: Duration::from_secs(1),
+            ..FrameStats::default()
+        };
+        assert_eq!(
+            format_title(DEFAULT_TITLE_FORMAT, "TETRIS", Some(&stats)),
+            "TETRIS - 59 fps 200%"
+        );
+        assert_eq!(
+            format_title(DEFAULT_TITLE_FORMAT, "TETRIS", None),
+            "TETRIS - -- fps --%"
+        );
+        assert_eq!(
+            format_title("NGC-224 {title}", "ZELDA", None),
+            "NGC-224 ZELDA"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! The data directory keeps the files of the games apart from the roms, named by the game rather
+//! than the rom file, so a renamed or moved rom keeps its saves:
+//!
+//! ```text
+//! saves/TETRIS-0a.sav          the battery backed ram, and .rtc the clock
+//! states/TETRIS-0a.ngcs        the quick save state
+//! screenshots/TETRIS-0a-*.png  the screenshots, the videos and the bug reports
+//! games/TETRIS-0a.toml         the settings of the game over the config
+//! ```
+//!
+//! The name of a game is the title of the rom header and the header checksum, two revisions of a
+//! game have different checksums.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::cartridge::{CartridgeError, CartridgeMeta, Checksums};
+
+const DIRS: [&str; 4] = ["saves", "states", "screenshots", "games"];
+
+#[derive(Debug, Clone)]
+pub struct Storage {
+    root: PathBuf,
+}
+
+impl Storage {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the name of the game in the data directory, the title is kept to the letters and
+    /// the digits, e.g. `POKEMON_RED-91`.
+    pub fn get_game_key(rom: &[u8]) -> Result<String, CartridgeError> {
+        let title: String = CartridgeMeta::new(rom)?
+            .get_title()
+            .trim()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let title = match title.trim_matches('_') {
+            "" => "UNTITLED",
+            title => title,
+        };
+        Ok(format!("{}-{:02x}", title, Checksums::new(rom)?.header))
+    }
+
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Create the directories of the layout.
+    pub fn create(&self) -> io::Result<()> {
+        for dir in DIRS.iter() {
+            fs::create_dir_all(self.root.join(dir))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the save path of the cartridge, the files get the `.sav` and `.rtc` extensions.
+    pub fn get_save_path(&self, game: &str) -> PathBuf {
+        self.root.join("saves").join(game)
+    }
+
+    pub fn get_state_path(&self, game: &str) -> PathBuf {
+        self.root.join("states").join(format!("{}.ngcs", game))
+    }
+
+    /// Returns the path of a capture of the game, `name` is like `20240101-120000.png`.
+    pub fn get_capture_path(&self, game: &str, name: &str) -> PathBuf {
+        self.root
+            .join("screenshots")
+            .join(format!("{}-{}", game, name))
+    }
+
+    /// Returns the path of the settings of the game, it's in the format of the config file.
+    pub fn get_settings_path(&self, game: &str) -> PathBuf {
+        self.root.join("games").join(format!("{}.toml", game))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(title: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x014d] = 0x3c;
+        rom
+    }
+
+    #[test]
+    fn test_game_key() {
+        let key = Storage::get_game_key(&rom(b"POKEMON RED")).unwrap();
+        assert_eq!(key, "POKEMON_RED-3c");
+        let key = Storage::get_game_key(&rom(b"../..")).unwrap();
+        assert_eq!(key, "UNTITLED-3c");
+        assert!(Storage::get_game_key(&[0x00; 0x100]).is_err());
+    }
+
+    #[test]
+    fn test_layout() {
+        let storage = Storage::new("/data");
+        let save = storage.get_save_path("A-01").with_extension("sav");
+        assert_eq!(save, Path::new("/data/saves/A-01.sav"));
+        let state = storage.get_state_path("A-01");
+        assert_eq!(state, Path::new("/data/states/A-01.ngcs"));
+        let capture = storage.get_capture_path("A-01", "1.png");
+        assert_eq!(capture, Path::new("/data/screenshots/A-01-1.png"));
+        let settings = storage.get_settings_path("A-01");
+        assert_eq!(settings, Path::new("/data/games/A-01.toml"));
+    }
+}
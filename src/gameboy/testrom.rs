@@ -0,0 +1,127 @@
+//! Run the test roms without the window and read their verdict, so the blargg and the mooneye
+//! suites are checked like the unit tests.
+//!
+//! The blargg roms print the result text through the serial port, the newer ones also keep it in
+//! the cartridge ram: 0xa001-0xa003 hold the signature `de b0 61`, 0xa000 is 0x80 while the test
+//! runs and the result code after, the text follows from 0xa004. The mooneye roms send the
+//! Fibonacci numbers 3, 5, 8, 13, 21, 34 when they pass and six 0x42 when they fail.
+use std::path::Path;
+
+use super::config::Config;
+use super::emulator::Emulator;
+use super::error::Error;
+use super::mmu::IOHandler;
+use super::FRAME_CYCLES;
+
+const BLARGG_SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Passed,
+    Failed,
+    /// The rom didn't finish in the cycles given.
+    Timeout,
+}
+
+/// The result of `run_test_rom`.
+#[derive(Debug)]
+pub struct TestReport {
+    pub verdict: Verdict,
+    // The serial output, or the text in the ram if nothing is sent.
+    pub output: String,
+    pub cycles: u64,
+}
+
+/// Returns the verdict of the bytes sent through the serial port, `None` if it's not finished.
+pub fn check_serial(output: &[u8]) -> Option<Verdict> {
+    if output.ends_with(&MOONEYE_PASS) {
+        return Some(Verdict::Passed);
+    }
+    if output.ends_with(&MOONEYE_FAIL) {
+        return Some(Verdict::Failed);
+    }
+    let text = String::from_utf8_lossy(output);
+    if text.contains("Passed") {
+        Some(Verdict::Passed)
+    } else if text.contains("Failed") {
+        Some(Verdict::Failed)
+    } else {
+        None
+    }
+}
+
+/// Returns the verdict and the text kept in the cartridge ram, `None` if there is no signature
+/// or the test is still running.
+pub fn check_memory(bus: &dyn IOHandler) -> Option<(Verdict, String)> {
+    let signature = [
+        bus.read_byte(0xa001),
+        bus.read_byte(0xa002),
+        bus.read_byte(0xa003),
+    ];
+    if signature != BLARGG_SIGNATURE {
+        return None;
+    }
+    let verdict = match bus.read_byte(0xa000) {
+        0x80 => return None,
+        0x00 => Verdict::Passed,
+        _ => Verdict::Failed,
+    };
+    let text = (0xa004..0xc000)
+        .map(|a| bus.read_byte(a))
+        .take_while(|b| *b != 0x00)
+        .collect::<Vec<u8>>();
+    Some((verdict, String::from_utf8_lossy(&text).to_string()))
+}
+
+/// Run the emulator until the rom reports the verdict, it's checked after every frame worth of
+/// cycles.
+pub fn run_until_verdict(emulator: &mut Emulator, max_cycles: u64) -> TestReport {
+    emulator.set_speed_simulation(false);
+    let mut cycles = 0;
+    while cycles < max_cycles {
+        cycles += emulator.run_cycles(u64::from(FRAME_CYCLES));
+        let output = emulator.get_serial_output();
+        if let Some(verdict) = check_serial(&output) {
+            return TestReport {
+                verdict,
+                output: String::from_utf8_lossy(&output).to_string(),
+                cycles,
+            };
+        }
+        if let Some((verdict, output)) = check_memory(emulator.get_mmu()) {
+            return TestReport {
+                verdict,
+                output,
+                cycles,
+            };
+        }
+    }
+    TestReport {
+        verdict: Verdict::Timeout,
+        output: String::from_utf8_lossy(&emulator.get_serial_output()).to_string(),
+        cycles,
+    }
+}
+
+/// Load the rom and run it until the verdict, see `run_until_verdict`.
+pub fn run_test_rom(path: impl AsRef<Path>, max_cycles: u64) -> Result<TestReport, Error> {
+    let config = Config::new(path.as_ref().to_string_lossy().to_string());
+    let mut emulator = Emulator::new(config)?;
+    Ok(run_until_verdict(&mut emulator, max_cycles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_serial() {
+        assert_eq!(check_serial(b"cpu_instrs\n\n01:ok  "), None);
+        assert_eq!(check_serial(b"01:ok\n\nPassed\n"), Some(Verdict::Passed));
+        assert_eq!(check_serial(b"03:01\n\nFailed\n"), Some(Verdict::Failed));
+        assert_eq!(check_serial(&MOONEYE_PASS), Some(Verdict::Passed));
+        assert_eq!(check_serial(&MOONEYE_FAIL), Some(Verdict::Failed));
+    }
+}
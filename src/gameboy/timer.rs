@@ -5,38 +5,18 @@
 //
 // See: http://gbdev.gg8.se/wiki/articles/Timer_and_Divider_Registers
 // use super::clock::Clock;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use super::cpu::IntFlag;
 use super::cpu::IntReg;
-
-// Clock is outputed 1 cycle every N cycles.
-#[derive(Debug)]
-pub struct Clock {
-    pub period: u32,
-    pub n: u32,
-}
-
-impl Clock {
-    pub fn new(period: u32) -> Self {
-        Self { period, n: 0x00 }
-    }
-
-    pub fn next(&mut self, cycles: u32) -> u32 {
-        self.n += cycles;
-        let rs = self.n / self.period;
-        self.n = self.n % self.period;
-        rs
-    }
-}
+use super::state::{StateError, StateReader, StateWriter, Stateful};
 
 #[derive(Default)]
 struct TimerRegister {
-    // This register is incremented at rate of 16384Hz (~16779Hz on SGB). Writing any value to this register resets it
-    // to 00h.
+    // The internal counter is incremented every cpu cycle, the DIV register is its upper 8 bits, so it's incremented
+    // at rate of 16384Hz (~16779Hz on SGB). Writing any value to DIV resets the whole counter to 0000h.
     // Note: The divider is affected by CGB double speed mode, and will increment at 32768Hz in double speed.
-    div: u8,
+    counter: u16,
     // This timer is incremented by a clock frequency specified by the TAC register ($FF07). When the value overflows
     // (gets bigger than FFh) then it will be reset to the value specified in TMA (FF06), and an interrupt will be
     // requested, as described below.
@@ -52,77 +32,203 @@ struct TimerRegister {
     tac: u8,
 }
 
+// TIMA is reloaded one M-cycle after it overflows, it reads 00h in between.
+const RELOAD_DELAY: u8 = 4;
+
+/// Returns the bit of the internal counter selected by the TAC, TIMA is incremented when it falls.
+fn get_tima_bit(tac: u8) -> u16 {
+    match tac & 0x03 {
+        0x00 => 1 << 9,
+        0x01 => 1 << 3,
+        0x02 => 1 << 5,
+        _ => 1 << 7,
+    }
+}
+
 // Each time when the timer overflows (ie. when TIMA gets bigger than FFh), then an interrupt is requested by
 // setting Bit 2 in the IF Register (FF0F). When that interrupt is enabled, then the CPU will execute it by calling
 // the timer interrupt vector at 0050h.
+//
+// The TIMA input is the selected counter bit ANDed with the timer enable, so anything making it fall increments
+// TIMA: the counter itself, a DIV write resetting the counter while the bit is 1, or a TAC write disabling the
+// timer or selecting another bit.
+// See: https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
 pub struct Timer {
-    intf: Rc<RefCell<IntReg>>,
+    intf: Arc<IntReg>,
     reg: TimerRegister,
-    div_clock: Clock,
-    tma_clock: Clock,
+    // The cycles until TMA is loaded into the overflowed TIMA, 0 if no reload is pending.
+    reload: u8,
 }
 
 impl Timer {
-    pub fn new(intf: Rc<RefCell<IntReg>>) -> Self {
+    pub fn new(intf: Arc<IntReg>) -> Self {
         Timer {
             intf,
             reg: TimerRegister::default(),
-            div_clock: Clock::new(256),
-            tma_clock: Clock::new(1024),
+            reload: 0,
+        }
+    }
+
+    /// Returns the TIMA input, the falling edge increments TIMA.
+    fn get_input(&self) -> bool {
+        self.reg.tac & 0x04 != 0x00 && self.reg.counter & get_tima_bit(self.reg.tac) != 0
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, overflow) = self.reg.tima.overflowing_add(1);
+        self.reg.tima = tima;
+        if overflow {
+            self.reload = RELOAD_DELAY;
+        }
+    }
+
+    /// Set the counter and the TAC, TIMA is incremented if the input falls.
+    fn update(&mut self, counter: u16, tac: u8) {
+        let input = self.get_input();
+        self.reg.counter = counter;
+        self.reg.tac = tac;
+        if input && !self.get_input() {
+            self.increment_tima();
         }
     }
 
     pub fn get(&self, a: u16) -> u8 {
         match a {
-            0xff04 => self.reg.div,
+            0xff04 => (self.reg.counter >> 8) as u8,
             0xff05 => self.reg.tima,
             0xff06 => self.reg.tma,
-            0xff07 => self.reg.tac,
+            // The upper 5 bits of TAC are unused and always read as 1.
+            0xff07 => self.reg.tac | 0xf8,
             _ => panic!("Unsupported address"),
         }
     }
 
     pub fn set(&mut self, a: u16, v: u8) {
         match a {
-            0xff04 => {
-                self.reg.div = 0x00;
-                self.div_clock.n = 0x00;
+            0xff04 => self.update(0x0000, self.reg.tac),
+            0xff05 => {
+                // Writing TIMA in the reload delay cancels the reload and the interrupt.
+                self.reg.tima = v;
+                self.reload = 0;
             }
-            0xff05 => self.reg.tima = v,
             0xff06 => self.reg.tma = v,
-            0xff07 => {
-                if (self.reg.tac & 0x03) != (v & 0x03) {
-                    self.tma_clock.n = 0x00;
-                    self.tma_clock.period = match v & 0x03 {
-                        0x00 => 1024,
-                        0x01 => 16,
-                        0x02 => 64,
-                        0x03 => 256,
-                        _ => panic!(""),
-                    };
-                    self.reg.tima = self.reg.tma;
-                }
-                self.reg.tac = v;
-            }
+            0xff07 => self.update(self.reg.counter, v & 0x07),
             _ => panic!("Unsupported address"),
         }
     }
 
     pub fn next(&mut self, cycles: u32) {
-        // Increment div at rate of 16384Hz. Because the clock cycles is 4194304, so div increment every 256 cycles.
-        self.reg.div = self.reg.div.wrapping_add(self.div_clock.next(cycles) as u8);
-
-        // Increment tima at rate of Clock / freq
-        // Timer Enable
-        if (self.reg.tac & 0x04) != 0x00 {
-            let n = self.tma_clock.next(cycles);
-            for _ in 0..n {
-                self.reg.tima = self.reg.tima.wrapping_add(1);
-                if self.reg.tima == 0x00 {
+        // The selected bits are bit 3 at least, so stepping an M-cycle at once doesn't miss an edge.
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let n = remaining.min(4);
+            remaining -= n;
+            if self.reload != 0 {
+                self.reload = self.reload.saturating_sub(n as u8);
+                if self.reload == 0 {
                     self.reg.tima = self.reg.tma;
-                    self.intf.borrow_mut().req(IntFlag::Timer);
+                    self.intf.req(IntFlag::Timer);
                 }
             }
+            self.update(self.reg.counter.wrapping_add(n as u16), self.reg.tac);
         }
     }
 }
+
+impl Stateful for Timer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put_u16(self.reg.counter);
+        w.put_u8(self.reg.tima);
+        w.put_u8(self.reg.tma);
+        w.put_u8(self.reg.tac);
+        w.put_u8(self.reload);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.reg.counter = r.get_u16()?;
+        self.reg.tima = r.get_u8()?;
+        self.reg.tma = r.get_u8()?;
+        self.reg.tac = r.get_u8()?;
+        self.reload = r.get_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_timer() -> (Timer, Arc<IntReg>) {
+        let intf = Arc::new(IntReg::new());
+        (Timer::new(intf.clone()), intf)
+    }
+
+    #[test]
+    fn test_div_is_the_upper_byte() {
+        let (mut timer, _) = new_timer();
+        timer.next(255);
+        assert_eq!(timer.get(0xff04), 0x00);
+        timer.next(1);
+        assert_eq!(timer.get(0xff04), 0x01);
+        timer.next(256 * 0xff);
+        assert_eq!(timer.get(0xff04), 0x00);
+        timer.next(300);
+        timer.set(0xff04, 0x42);
+        assert_eq!(timer.get(0xff04), 0x00);
+        timer.next(255);
+        assert_eq!(timer.get(0xff04), 0x00);
+        assert_eq!(timer.get(0xff07), 0xf8);
+    }
+
+    #[test]
+    fn test_tima_falling_edge() {
+        let (mut timer, _) = new_timer();
+        // CPU Clock / 16, the bit 3.
+        timer.set(0xff07, 0x05);
+        timer.next(16 * 3);
+        assert_eq!(timer.get(0xff05), 0x03);
+
+        // The bit 3 is 1 after 8 cycles, resetting DIV makes it fall.
+        timer.next(8);
+        timer.set(0xff04, 0x00);
+        assert_eq!(timer.get(0xff05), 0x04);
+        timer.next(8);
+        timer.set(0xff04, 0x00);
+        assert_eq!(timer.get(0xff05), 0x05);
+        // The bit 3 is 0, no increment.
+        timer.next(4);
+        timer.set(0xff04, 0x00);
+        assert_eq!(timer.get(0xff05), 0x05);
+
+        // Disabling the timer while the bit is 1 makes it fall too.
+        timer.next(8);
+        timer.set(0xff07, 0x01);
+        assert_eq!(timer.get(0xff05), 0x06);
+        timer.next(64);
+        assert_eq!(timer.get(0xff05), 0x06);
+    }
+
+    #[test]
+    fn test_delayed_reload() {
+        let (mut timer, intf) = new_timer();
+        timer.set(0xff06, 0x80);
+        timer.set(0xff05, 0xff);
+        timer.set(0xff07, 0x05);
+        timer.next(16);
+        // TIMA reads 00 for an M-cycle before TMA is loaded.
+        assert_eq!(timer.get(0xff05), 0x00);
+        assert!(!intf.is_requested(IntFlag::Timer));
+        timer.next(4);
+        assert_eq!(timer.get(0xff05), 0x80);
+        assert!(intf.is_requested(IntFlag::Timer));
+
+        // Writing TIMA in the delay cancels the reload.
+        intf.ack(IntFlag::Timer);
+        timer.set(0xff05, 0xff);
+        timer.next(12);
+        timer.set(0xff05, 0x10);
+        timer.next(4);
+        assert_eq!(timer.get(0xff05), 0x10);
+        assert!(!intf.is_requested(IntFlag::Timer));
+    }
+}
@@ -20,6 +20,16 @@ pub fn set_bit(v: u8, b: u8) -> u8 {
     v | (1 << b)
 }
 
+/// The SplitMix64 generator, returns the next value and advances the state.
+/// It's tiny and fully determined by the seed, so a replay gets the same sequence.
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 pub mod gameboy;
+pub mod ngc224_ffi;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -0,0 +1,194 @@
+//! The C interface of the emulator core, for the frontends written in other languages. See
+//! `include/ngc224.h` for the declarations.
+//!
+//! The emulator is an opaque pointer created by `ngc224_create` and freed by `ngc224_destroy`.
+//! The frontend paces the frames by itself, so the speed simulation is off.
+//!
+//! The wasm32 build exports the same functions to JavaScript, see `web/` for the canvas demo.
+use std::os::raw::c_int;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use log::error;
+
+use crate::gameboy::frontend::InputSource;
+use crate::gameboy::joypad::{JoypadKey, KEYS};
+use crate::gameboy::{Config, Emulator};
+
+/// The emulator and the buttons held by the frontend.
+pub struct Ngc224 {
+    emulator: Emulator,
+    // The held buttons, in the layout of `Joypad::get_keys`.
+    keys: Arc<AtomicU8>,
+    // The RGB copy of the last frame, the emulator only keeps the RGBA one.
+    rgb: Vec<u8>,
+}
+
+struct Buttons(Arc<AtomicU8>);
+
+impl InputSource for Buttons {
+    fn is_held(&mut self, key: &JoypadKey) -> bool {
+        let i = KEYS.iter().position(|k| k == key).unwrap();
+        self.0.load(Ordering::Relaxed) & (1 << i) != 0
+    }
+}
+
+/// Returns the emulator with the empty cartridge, null if it can't be created.
+#[no_mangle]
+pub extern "C" fn ngc224_create() -> *mut Ngc224 {
+    let mut emulator = match Emulator::new(Config::default()) {
+        Ok(emulator) => emulator,
+        Err(e) => {
+            error!("Can't create the emulator: {}", e);
+            return ptr::null_mut();
+        }
+    };
+    emulator.set_speed_simulation(false);
+    let keys = Arc::new(AtomicU8::new(0x00));
+    emulator.set_input_source(Box::new(Buttons(keys.clone())));
+    Box::into_raw(Box::new(Ngc224 {
+        emulator,
+        keys,
+        rgb: vec![],
+    }))
+}
+
+/// # Safety
+///
+/// The pointer is returned by `ngc224_create` and not used again, null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn ngc224_destroy(ngc: *mut Ngc224) {
+    if !ngc.is_null() {
+        drop(Box::from_raw(ngc));
+    }
+}
+
+/// Insert the rom, the data is copied. Returns 0, or -1 if the rom can't be loaded and the
+/// running game is kept.
+///
+/// # Safety
+///
+/// The emulator is valid and `data` points to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ngc224_load_rom(ngc: *mut Ngc224, data: *const u8, len: usize) -> c_int {
+    if data.is_null() {
+        return -1;
+    }
+    let rom = slice::from_raw_parts(data, len).to_vec();
+    match (*ngc).emulator.load_rom_bytes(rom) {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("Can't load the rom: {}", e);
+            -1
+        }
+    }
+}
+
+/// Run a frame with the held buttons.
+///
+/// # Safety
+///
+/// The emulator is valid.
+#[no_mangle]
+pub unsafe extern "C" fn ngc224_step_frame(ngc: *mut Ngc224) {
+    (*ngc).emulator.step_frame();
+}
+
+/// Returns the last finished frame, 160x144 RGB pixels row by row, 3 bytes each. It's
+/// overwritten by the next frame.
+///
+/// # Safety
+///
+/// The emulator is valid, the pointer is only read until the next call on it.
+#[no_mangle]
+pub unsafe extern "C" fn ngc224_get_framebuffer(ngc: *mut Ngc224) -> *const u8 {
+    let ngc = &mut *ngc;
+    ngc.rgb.clear();
+    ngc.rgb.extend(ngc.emulator.get_frame().pixels().flatten());
+    ngc.rgb.as_ptr()
+}
+
+/// Returns the last finished frame like `ngc224_get_framebuffer`, but 4 bytes each in RGBA. It's
+/// not copied, it's the ImageData of a canvas as it is.
+///
+/// # Safety
+///
+/// The emulator is valid, the pointer is only read until the next call on it.
+#[no_mangle]
+pub unsafe extern "C" fn ngc224_get_frame_rgba(ngc: *mut Ngc224) -> *const u8 {
+    (*ngc).emulator.frame().as_ptr()
+}
+
+/// Returns the buffer of `len` bytes, for the caller without its own allocator to pass the rom,
+/// e.g. JavaScript writing into the wasm memory.
+#[no_mangle]
+pub extern "C" fn ngc224_alloc(len: usize) -> *mut u8 {
+    let mut buf = vec![0u8; len].into_boxed_slice();
+    let p = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    p
+}
+
+/// # Safety
+///
+/// The pointer and the length are the ones of `ngc224_alloc`.
+#[no_mangle]
+pub unsafe extern "C" fn ngc224_free(p: *mut u8, len: usize) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(p, len)));
+}
+
+/// Hold or release the button from the next frame. The buttons are 0 to 7 for Right, Left, Up,
+/// Down, A, B, Select and Start, the others are ignored.
+///
+/// # Safety
+///
+/// The emulator is valid.
+#[no_mangle]
+pub unsafe extern "C" fn ngc224_set_button(ngc: *mut Ngc224, button: c_int, pressed: bool) {
+    if !(0..8).contains(&button) {
+        return;
+    }
+    let keys = &(*ngc).keys;
+    let bit = 1 << button;
+    if pressed {
+        keys.fetch_or(bit, Ordering::Relaxed);
+    } else {
+        keys.fetch_and(!bit, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{SCREEN_H, SCREEN_W};
+
+    #[test]
+    fn test_ffi() {
+        unsafe {
+            let ngc = ngc224_create();
+            assert!(!ngc.is_null());
+            assert_eq!(ngc224_load_rom(ngc, [0x00; 0x100].as_ptr(), 0x100), -1);
+            assert_eq!(ngc224_load_rom(ngc, [0x00; 0x8000].as_ptr(), 0x8000), 0);
+
+            ngc224_set_button(ngc, 4, true);
+            ngc224_set_button(ngc, 7, true);
+            ngc224_set_button(ngc, 7, false);
+            ngc224_set_button(ngc, 8, true);
+            ngc224_step_frame(ngc);
+            assert_eq!((*ngc).emulator.get_mmu().joypad.get_keys(), 0x10);
+
+            let frame = slice::from_raw_parts(ngc224_get_framebuffer(ngc), SCREEN_W * SCREEN_H * 3);
+            assert_eq!(frame[..3], (*ngc).emulator.get_frame().get_pixel(0, 0));
+            let rgba = slice::from_raw_parts(ngc224_get_frame_rgba(ngc), SCREEN_W * SCREEN_H * 4);
+            assert_eq!(rgba[..3], frame[..3]);
+            assert_eq!(rgba[3], 0xff);
+
+            let rom = ngc224_alloc(0x8000);
+            assert_eq!(ngc224_load_rom(ngc, rom, 0x8000), 0);
+            ngc224_free(rom, 0x8000);
+            ngc224_destroy(ngc);
+        }
+    }
+}
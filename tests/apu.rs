@@ -0,0 +1,95 @@
+use NGC224::gameboy::sound::apu::{Apu, DEFAULT_SAMPLE_RATE};
+use NGC224::gameboy::IOHandler;
+use NGC224::gameboy::FRAME_CYCLES;
+
+const NR11: u16 = 0xff11;
+const NR12: u16 = 0xff12;
+const NR14: u16 = 0xff14;
+const NR50: u16 = 0xff24;
+const NR51: u16 = 0xff25;
+const NR52: u16 = 0xff26;
+
+fn run_cycles(apu: &mut Apu, cycles: u32) {
+    let mut n = 0;
+    while n < cycles {
+        apu.next(4);
+        n += 4;
+    }
+}
+
+#[test]
+fn test_read_masks() {
+    let apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    assert_eq!(apu.read_byte(0xff10), 0x80);
+    assert_eq!(apu.read_byte(NR11), 0x3f);
+    // The frequency registers are write only.
+    assert_eq!(apu.read_byte(0xff13), 0xff);
+    assert_eq!(apu.read_byte(NR14), 0xbf);
+    assert_eq!(apu.read_byte(0xff15), 0xff);
+    assert_eq!(apu.read_byte(0xff27), 0xff);
+    assert_eq!(apu.read_byte(NR52), 0xf0);
+}
+
+#[test]
+fn test_power_off_clears_registers() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    apu.write_byte(NR50, 0x77);
+    apu.write_byte(0xff30, 0x12);
+    apu.write_byte(NR52, 0x00);
+    assert_eq!(apu.read_byte(NR52), 0x70);
+    assert_eq!(apu.read_byte(NR50), 0x00);
+
+    // The registers ignore the writes until the power is back, the wave ram doesn't.
+    apu.write_byte(NR12, 0xf0);
+    assert_eq!(apu.read_byte(NR12), 0x00);
+    assert_eq!(apu.read_byte(0xff30), 0x12);
+
+    apu.write_byte(NR52, 0x80);
+    apu.write_byte(NR12, 0xf0);
+    assert_eq!(apu.read_byte(NR12), 0xf0);
+}
+
+#[test]
+fn test_trigger_and_length_expiry() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    // The DAC is off, the trigger doesn't enable the channel.
+    apu.write_byte(NR14, 0x80);
+    assert_eq!(apu.read_byte(NR52) & 0x01, 0x00);
+
+    apu.write_byte(NR12, 0xf0);
+    // The length 63 leaves one clock of the length counter.
+    apu.write_byte(NR11, 0x3f);
+    apu.write_byte(NR14, 0xc0);
+    assert_eq!(apu.read_byte(NR52) & 0x01, 0x01);
+
+    // The length counter is clocked at 256 Hz.
+    run_cycles(&mut apu, 4_194_304 / 256 * 2);
+    assert_eq!(apu.read_byte(NR52) & 0x01, 0x00);
+}
+
+#[test]
+fn test_samples_per_frame() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    run_cycles(&mut apu, FRAME_CYCLES);
+    let n = apu.drain_samples().len();
+    assert!((738..=739).contains(&n), "{} samples", n);
+    assert!(apu.drain_samples().is_empty());
+}
+
+#[test]
+fn test_square_output() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    apu.write_byte(NR50, 0x77);
+    // Channel 1 goes to the left only.
+    apu.write_byte(NR51, 0x10);
+    apu.write_byte(NR11, 0x80);
+    apu.write_byte(NR12, 0xf0);
+    apu.write_byte(0xff13, 0x00);
+    apu.write_byte(NR14, 0x87);
+    run_cycles(&mut apu, FRAME_CYCLES);
+
+    let samples = apu.drain_samples();
+    assert!(samples.iter().any(|s| s[0] > 0.0));
+    assert!(samples.iter().any(|s| s[0] == 0.0));
+    assert!(samples.iter().all(|s| s[1] == 0.0));
+}
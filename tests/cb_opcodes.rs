@@ -0,0 +1,109 @@
+mod common;
+
+use common::new_cpu;
+use NGC224::gameboy::cpu::opcode_set::get_cb_clock;
+use NGC224::gameboy::IOHandler;
+use NGC224::gameboy::Register;
+
+const ZERO: u8 = 0x80;
+const HALF_CARRY: u8 = 0x20;
+const CARRY: u8 = 0x10;
+
+// The operand values, each is run with the carry flag both cleared and set.
+const VALUES: [u8; 6] = [0x00, 0x01, 0x80, 0x81, 0x5a, 0xff];
+
+/// The expected result and flag register of the CB prefixed instruction, it's written apart
+/// from the cpu so both are checked against each other.
+fn expect(opcode: u8, v: u8, f: u8) -> (u8, u8) {
+    let bit = (opcode >> 3) & 0x07;
+    let carry_in = (f & CARRY) >> 4;
+    let shift = |r: u8, carry: bool| {
+        let mut f = if carry { CARRY } else { 0x00 };
+        if r == 0x00 {
+            f |= ZERO;
+        }
+        (r, f)
+    };
+    match opcode >> 3 {
+        0x00 => shift(v.rotate_left(1), v & 0x80 != 0),
+        0x01 => shift(v.rotate_right(1), v & 0x01 != 0),
+        0x02 => shift(v << 1 | carry_in, v & 0x80 != 0),
+        0x03 => shift(v >> 1 | carry_in << 7, v & 0x01 != 0),
+        0x04 => shift(v << 1, v & 0x80 != 0),
+        0x05 => shift(v >> 1 | (v & 0x80), v & 0x01 != 0),
+        0x06 => shift(v.rotate_left(4), false),
+        0x07 => shift(v >> 1, v & 0x01 != 0),
+        0x08..=0x0f => {
+            let z = if v & (1 << bit) == 0 { ZERO } else { 0x00 };
+            (v, z | HALF_CARRY | (f & CARRY))
+        }
+        0x10..=0x17 => (v & !(1 << bit), f),
+        _ => (v | (1 << bit), f),
+    }
+}
+
+/// Run `CB opcode` with the operand in its register, or at (HL) = 0xc000. The other registers
+/// hold values which no instruction produces from the operand, so a wrong target is caught.
+/// Returns the registers and the byte at (HL) after the instruction, and the used cycles.
+fn run(opcode: u8, v: u8, f: u8) -> (Register, u8, u32) {
+    let (mut cpu, bus) = new_cpu();
+    let mut regs = [0x33_u8; 8];
+    regs[(opcode & 0x07) as usize] = v;
+    let (h, l) = if opcode & 0x07 == 6 {
+        (0xc0, 0x00)
+    } else {
+        (regs[4], regs[5])
+    };
+    let pair = |hi: u8, lo: u8| u16::from(hi) << 8 | u16::from(lo);
+    cpu.set_reg(
+        Register::new()
+            .with_AF(pair(regs[7], f))
+            .with_BC(pair(regs[0], regs[1]))
+            .with_DE(pair(regs[2], regs[3]))
+            .with_HL(pair(h, l))
+            .with_PC(0x0100)
+            .with_SP(0xfffe),
+    );
+    bus.lock().unwrap().load(0x0100, &[0xcb, opcode]);
+    let mem = if opcode & 0x07 == 6 { v } else { 0x33 };
+    bus.lock().unwrap().load(0xc000, &[mem]);
+    let cycles = cpu.next();
+    let mem = bus.lock().unwrap().read_byte(0xc000);
+    (cpu.get_reg_snapshot(), mem, cycles)
+}
+
+fn operand(reg: &Register, mem: u8, col: u8) -> u8 {
+    match col {
+        0 => reg.get_B(),
+        1 => reg.get_C(),
+        2 => reg.get_D(),
+        3 => reg.get_E(),
+        4 => reg.get_H(),
+        5 => reg.get_L(),
+        6 => mem,
+        _ => reg.get_A(),
+    }
+}
+
+#[test]
+fn test_cb_opcodes() {
+    for opcode in 0..=0xff_u8 {
+        let col = opcode & 0x07;
+        for v in VALUES.iter() {
+            for f in [0x00, CARRY].iter() {
+                let (reg, mem, cycles) = run(opcode, *v, *f);
+                let (r, flags) = expect(opcode, *v, *f);
+                let case = format!("cb {:02x} on {:02x} f={:02x}", opcode, v, f);
+                assert_eq!(operand(&reg, mem, col), r, "{}", case);
+                assert_eq!(reg.get_AF() as u8, flags, "{}", case);
+                assert_eq!(reg.get_PC(), 0x0102, "{}", case);
+                assert_eq!(cycles, get_cb_clock(opcode), "{}", case);
+                // HL points to the operand of the (HL) column.
+                let hl = |c: u8| col == 6 && (c == 4 || c == 5);
+                for other in (0..8).filter(|c| *c != col && !hl(*c)) {
+                    assert_eq!(operand(&reg, mem, other), 0x33, "{} reg {}", case, other);
+                }
+            }
+        }
+    }
+}
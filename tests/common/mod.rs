@@ -0,0 +1,47 @@
+#![allow(dead_code)]
+
+pub mod vram;
+
+use std::sync::{Arc, Mutex};
+
+use NGC224::gameboy::IOHandler;
+use NGC224::gameboy::Register;
+use NGC224::gameboy::CPU;
+
+/// A flat 64KB memory without any mapping, the test writes the program and the io registers
+/// directly into it.
+pub struct TestBus {
+    data: Vec<u8>,
+}
+
+impl TestBus {
+    pub fn new() -> Self {
+        Self {
+            data: vec![0x00; 0x10000],
+        }
+    }
+
+    /// Copy the bytes into the memory, start from the address.
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) {
+        let start = addr as usize;
+        self.data[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+impl IOHandler for TestBus {
+    fn read_byte(&self, a: u16) -> u8 {
+        self.data[a as usize]
+    }
+
+    fn write_byte(&mut self, a: u16, v: u8) {
+        self.data[a as usize] = v;
+    }
+}
+
+/// Create a CPU on a `TestBus`, the PC is at 0x0100 and the SP is at 0xfffe.
+pub fn new_cpu() -> (CPU, Arc<Mutex<TestBus>>) {
+    let bus = Arc::new(Mutex::new(TestBus::new()));
+    let mut cpu = CPU::new(bus.clone());
+    cpu.set_reg(Register::new().with_PC(0x0100).with_SP(0xfffe));
+    (cpu, bus)
+}
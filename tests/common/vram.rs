@@ -0,0 +1,158 @@
+//! Build the VRAM and the OAM of a DMG scene, then render a frame of it. The palettes are the
+//! identity `0xe4` unless told otherwise and the shades are the gray ones, so a shade of the frame
+//! is the color number drawn there.
+use std::ops::Range;
+use std::sync::Arc;
+
+use NGC224::gameboy::cpu::IntReg;
+use NGC224::gameboy::gpu::GPU;
+use NGC224::gameboy::IOHandler;
+use NGC224::gameboy::{Term, FRAME_CYCLES};
+
+// LCD on, the tile data at 0x8000, the BG map at 0x9800, the sprites and the BG on.
+const LCDC: u8 = 0x93;
+
+/// A sprite at the screen position, the OAM holds it at X + 8 and Y + 16.
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    x: u8,
+    y: u8,
+    tile: u8,
+    attr: u8,
+}
+
+impl Sprite {
+    pub fn new(x: u8, y: u8, tile: u8) -> Self {
+        Self {
+            x: x.wrapping_add(8),
+            y: y.wrapping_add(16),
+            tile,
+            attr: 0x00,
+        }
+    }
+
+    /// Drawn only over the BG color 0.
+    pub fn behind_bg(mut self) -> Self {
+        self.attr |= 0x80;
+        self
+    }
+
+    pub fn yflip(mut self) -> Self {
+        self.attr |= 0x40;
+        self
+    }
+
+    pub fn xflip(mut self) -> Self {
+        self.attr |= 0x20;
+        self
+    }
+
+    pub fn obp1(mut self) -> Self {
+        self.attr |= 0x10;
+        self
+    }
+}
+
+pub struct VramBuilder {
+    gpu: GPU,
+    lcdc: u8,
+    sprites: u16,
+}
+
+impl VramBuilder {
+    pub fn new() -> Self {
+        let mut gpu = GPU::with_term(Arc::new(IntReg::new()), Term::GB);
+        for a in 0xff47..=0xff49 {
+            gpu.write_byte(a, 0xe4);
+        }
+        // The unused sprites are out of the screen.
+        for a in 0xfe00..0xfea0 {
+            gpu.write_byte(a, 0x00);
+        }
+        Self {
+            gpu,
+            lcdc: LCDC,
+            sprites: 0,
+        }
+    }
+
+    /// Write the tile, a row is the color numbers of its 8 pixels from the left, e.g. `"01233210"`.
+    pub fn tile(mut self, n: u8, rows: [&str; 8]) -> Self {
+        let addr = 0x8000 + u16::from(n) * 16;
+        for (y, row) in rows.iter().enumerate() {
+            let (mut lo, mut hi) = (0, 0);
+            for (x, c) in row.chars().enumerate() {
+                let color = c.to_digit(4).expect("a color number is 0 to 3") as u8;
+                lo |= (color & 0x01) << (7 - x);
+                hi |= (color >> 1) << (7 - x);
+            }
+            self.gpu.write_byte(addr + y as u16 * 2, lo);
+            self.gpu.write_byte(addr + y as u16 * 2 + 1, hi);
+        }
+        self
+    }
+
+    /// Fill the tile with a color.
+    pub fn solid_tile(self, n: u8, color: u8) -> Self {
+        let row = color.to_string().repeat(8);
+        self.tile(n, [row.as_str(); 8])
+    }
+
+    /// Put the tile on the BG map, at the tile column and row.
+    pub fn map(mut self, x: u16, y: u16, tile: u8) -> Self {
+        self.gpu.write_byte(0x9800 + y * 32 + x, tile);
+        self
+    }
+
+    /// Add the sprite after the ones added before it in the OAM.
+    pub fn sprite(mut self, sprite: Sprite) -> Self {
+        let addr = 0xfe00 + self.sprites * 4;
+        let oam = [sprite.y, sprite.x, sprite.tile, sprite.attr];
+        for (i, v) in oam.iter().enumerate() {
+            self.gpu.write_byte(addr + i as u16, *v);
+        }
+        self.sprites += 1;
+        self
+    }
+
+    /// The sprites are 8x16.
+    pub fn tall_sprites(mut self) -> Self {
+        self.lcdc |= 0x04;
+        self
+    }
+
+    pub fn palettes(mut self, bgp: u8, obp0: u8, obp1: u8) -> Self {
+        self.gpu.write_byte(0xff47, bgp);
+        self.gpu.write_byte(0xff48, obp0);
+        self.gpu.write_byte(0xff49, obp1);
+        self
+    }
+
+    /// Turn on the LCD and draw the first frame.
+    pub fn render(mut self) -> GPU {
+        self.gpu.write_byte(0xff40, self.lcdc);
+        let mut cycles = 0;
+        while cycles < FRAME_CYCLES {
+            self.gpu.next(4);
+            cycles += 4;
+        }
+        assert!(self.gpu.should_updated());
+        self.gpu
+    }
+}
+
+/// Returns the gray shade of the finished frame, 0 is white and 3 is black.
+pub fn shade(gpu: &GPU, x: usize, y: usize) -> u8 {
+    match gpu.get_frame().get_pixel(x, y) {
+        [0xff, 0xff, 0xff] => 0,
+        [0xc0, 0xc0, 0xc0] => 1,
+        [0x60, 0x60, 0x60] => 2,
+        [0x00, 0x00, 0x00] => 3,
+        rgb => panic!("{:?} at {},{} is not a gray shade", rgb, x, y),
+    }
+}
+
+/// Returns the shades of the pixels of the line, e.g. `"00113300"`.
+pub fn shades(gpu: &GPU, y: usize, xs: Range<usize>) -> String {
+    xs.map(|x| char::from(b'0' + shade(gpu, x, y))).collect()
+}
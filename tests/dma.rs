@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use NGC224::gameboy::cartridge::load_empty_cartridge;
+use NGC224::gameboy::cpu::IntReg;
+use NGC224::gameboy::gpu::GPU;
+use NGC224::gameboy::joypad::Joypad;
+use NGC224::gameboy::mmu::Mmunit;
+use NGC224::gameboy::sound::apu::{Apu, DEFAULT_SAMPLE_RATE};
+use NGC224::gameboy::state::{Snapshot, MMU_SECTION};
+use NGC224::gameboy::timer::Timer;
+use NGC224::gameboy::{IOHandler, Term};
+
+fn new_mmu() -> Mmunit {
+    new_mmu_with_term(Term::GB)
+}
+
+fn new_mmu_with_term(term: Term) -> Mmunit {
+    let intf = Arc::new(IntReg::new());
+    let gpu = GPU::with_term(intf.clone(), term);
+    Mmunit::new(
+        load_empty_cartridge(),
+        Apu::new(DEFAULT_SAMPLE_RATE),
+        gpu,
+        Joypad::new(intf.clone()),
+        Timer::new(intf.clone()),
+        intf,
+    )
+}
+
+/// Fill the work ram at 0xc000 with the transfer source and start the DMA.
+fn start_dma(mmu: &mut Mmunit) {
+    for i in 0..0xa0 {
+        mmu.write_byte(0xc000 + i, i as u8 + 1);
+    }
+    mmu.write_byte(0xff46, 0xc0);
+}
+
+fn read_oam(mmu: &Mmunit) -> Vec<u8> {
+    (0..0xa0)
+        .map(|i| mmu.gpu.borrow().read_byte(0xfe00 + i))
+        .collect()
+}
+
+#[test]
+fn test_dma_takes_160_cycles() {
+    let mut mmu = new_mmu();
+    start_dma(&mut mmu);
+    assert_eq!(mmu.read_byte(0xff46), 0xc0);
+    // The transfer starts one M-cycle after the write.
+    mmu.next(4);
+    assert!(mmu.is_dma_active());
+    assert_eq!(read_oam(&mmu)[0], 0x00);
+
+    mmu.next(4);
+    assert_eq!(&read_oam(&mmu)[..2], &[0x01, 0x00]);
+
+    for _ in 0..158 {
+        mmu.next(4);
+    }
+    assert!(mmu.is_dma_active());
+    mmu.next(4);
+    assert!(!mmu.is_dma_active());
+    let expect: Vec<u8> = (1..=0xa0).collect();
+    assert_eq!(read_oam(&mmu), expect);
+}
+
+#[test]
+fn test_dma_blocks_the_bus() {
+    let mut mmu = new_mmu();
+    start_dma(&mut mmu);
+    mmu.write_byte(0xff80, 0x42);
+    mmu.write_byte(0x8000, 0x24);
+    mmu.next(8);
+
+    // Only the high ram and the IO registers are reachable.
+    assert_eq!(mmu.read_byte(0xff80), 0x42);
+    assert_eq!(mmu.read_byte(0xff46), 0xc0);
+    assert_eq!(mmu.read_byte(0xfe00), 0xff);
+    // The work ram is on the bus of the DMA, it reads the byte being copied.
+    assert_eq!(mmu.read_byte(0xd123), 0x02);
+    assert_eq!(mmu.read_byte(0x0100), 0x02);
+    mmu.write_byte(0xc010, 0x99);
+    mmu.write_byte(0xfe10, 0x99);
+    // The video ram is on another bus.
+    assert_eq!(mmu.read_byte(0x8000), 0x24);
+
+    for _ in 0..0xa0 {
+        mmu.next(4);
+    }
+    assert_eq!(mmu.read_byte(0xc010), 0x11);
+    assert_eq!(mmu.read_byte(0xfe10), 0x11);
+}
+
+#[test]
+fn test_dma_is_saved() {
+    let mut mmu = new_mmu();
+    start_dma(&mut mmu);
+    mmu.next(4 * 0x51);
+
+    let mut snapshot = Snapshot::new();
+    snapshot.put(MMU_SECTION, &mmu);
+    let mut restored = new_mmu();
+    snapshot.restore(MMU_SECTION, &mut restored).unwrap();
+    assert!(restored.is_dma_active());
+    assert_eq!(restored.read_byte(0xff46), 0xc0);
+    for _ in 0..0x50 {
+        restored.next(4);
+    }
+    assert!(!restored.is_dma_active());
+    // The OAM is saved by the GPU, only the rest of the transfer is copied here.
+    let oam = read_oam(&restored);
+    assert_eq!(oam[0x4f], 0x00);
+    assert_eq!(oam[0x50], 0x51);
+    assert_eq!(oam[0x9f], 0xa0);
+}
+
+/// Fill the work ram at 0xc000 with the transfer source and set the destination to 0x8800.
+fn prepare_hdma(mmu: &mut Mmunit) {
+    for i in 0..0x40 {
+        mmu.write_byte(0xc000 + i, i as u8 + 1);
+    }
+    mmu.write_byte(0xff51, 0xc0);
+    mmu.write_byte(0xff52, 0x0f);
+    // The upper 3 bits are ignored.
+    mmu.write_byte(0xff53, 0xe8);
+    mmu.write_byte(0xff54, 0x00);
+}
+
+fn read_vram(mmu: &Mmunit, n: u16) -> Vec<u8> {
+    (0..n).map(|i| mmu.read_byte(0x8800 + i)).collect()
+}
+
+/// Step until the HDMA5 register changes.
+fn run_until_hdma5_changes(mmu: &mut Mmunit) -> u8 {
+    let v = mmu.read_byte(0xff55);
+    while mmu.read_byte(0xff55) == v {
+        mmu.next(4);
+    }
+    mmu.read_byte(0xff55)
+}
+
+#[test]
+fn test_general_purpose_dma() {
+    let mut mmu = new_mmu_with_term(Term::GBC);
+    prepare_hdma(&mut mmu);
+    mmu.write_byte(0xff55, 0x01);
+    let expect: Vec<u8> = (1..=0x20).collect();
+    assert_eq!(read_vram(&mmu, 0x21), [&expect[..], &[0x00]].concat());
+    assert_eq!(mmu.read_byte(0xff55), 0xff);
+    // The CPU is stopped while the blocks are copied.
+    assert_eq!(mmu.next(4), 4 + 64);
+    assert_eq!(mmu.next(4), 4);
+}
+
+#[test]
+fn test_hblank_dma() {
+    let mut mmu = new_mmu_with_term(Term::GBC);
+    prepare_hdma(&mut mmu);
+    mmu.write_byte(0xff55, 0x82);
+    assert_eq!(mmu.read_byte(0xff55), 0x02);
+    assert_eq!(read_vram(&mmu, 1), vec![0x00]);
+
+    assert_eq!(run_until_hdma5_changes(&mut mmu), 0x01);
+    let vram = read_vram(&mmu, 0x20);
+    assert_eq!(vram[0x0f], 0x10);
+    assert_eq!(vram[0x10], 0x00);
+
+    assert_eq!(run_until_hdma5_changes(&mut mmu), 0x00);
+    assert_eq!(run_until_hdma5_changes(&mut mmu), 0xff);
+    let expect: Vec<u8> = (1..=0x30).collect();
+    assert_eq!(read_vram(&mmu, 0x30), expect);
+}
+
+#[test]
+fn test_cancel_hblank_dma() {
+    let mut mmu = new_mmu_with_term(Term::GBC);
+    prepare_hdma(&mut mmu);
+    mmu.write_byte(0xff55, 0x82);
+    run_until_hdma5_changes(&mut mmu);
+    mmu.write_byte(0xff55, 0x00);
+    // The remaining length is kept.
+    assert_eq!(mmu.read_byte(0xff55), 0x81);
+    for _ in 0..1000 {
+        mmu.next(4);
+    }
+    assert_eq!(read_vram(&mmu, 0x11)[0x10], 0x00);
+
+    // The HDMA doesn't exist on the DMG.
+    let mut mmu = new_mmu();
+    prepare_hdma(&mut mmu);
+    mmu.write_byte(0xff55, 0x00);
+    assert_eq!(mmu.read_byte(0xff55), 0xff);
+    assert_eq!(read_vram(&mmu, 1), vec![0x00]);
+}
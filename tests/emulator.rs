@@ -0,0 +1,546 @@
+use std::env;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use NGC224::gameboy::cartridge::{CartridgeError, HeaderCheck};
+use NGC224::gameboy::emulator::Command;
+use NGC224::gameboy::frontend::{Frame, InputSource, RumbleSink, VideoSink};
+use NGC224::gameboy::joypad::JoypadKey;
+use NGC224::gameboy::pacing::SyncMode;
+use NGC224::gameboy::sound::AudioQueue;
+use NGC224::gameboy::state::Snapshot;
+use NGC224::gameboy::Config;
+use NGC224::gameboy::Emulator;
+use NGC224::gameboy::Error;
+use NGC224::gameboy::IOHandler;
+use NGC224::gameboy::Mode;
+use NGC224::gameboy::{FRAME_CYCLES, SCREEN_H, SCREEN_W};
+
+#[test]
+fn test_bench_runs_the_frames() {
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    let report = emulator.bench(2);
+    assert_eq!(report.frames, 2);
+    assert!(report.cycles >= 2 * u64::from(FRAME_CYCLES));
+    assert!(report.cycles < 3 * u64::from(FRAME_CYCLES));
+    assert!(report.instructions > 0);
+}
+
+#[test]
+fn test_restore_replays_the_same_frames() {
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.bench(3);
+    let saved = emulator.snapshot();
+    emulator.bench(2);
+    let expected = emulator.snapshot().to_bytes();
+
+    emulator.restore(&saved).unwrap();
+    assert_eq!(emulator.snapshot().to_bytes(), saved.to_bytes());
+    emulator.bench(2);
+    assert_eq!(emulator.snapshot().to_bytes(), expected);
+}
+
+#[test]
+fn test_restore_snapshot_without_new_sections() {
+    // The v1 snapshot only has the cpu and the mmu sections.
+    let snapshot = Snapshot::load("tests/fixtures/state/v1.ngcs").unwrap();
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.restore(&snapshot).unwrap();
+    assert_eq!(emulator.get_mmu().read_byte(0xc000), 0x42);
+}
+
+struct Frames(Arc<Mutex<u32>>);
+
+impl VideoSink for Frames {
+    fn present(&mut self, frame: &Frame) {
+        assert_eq!(frame.as_rgba().len(), SCREEN_W * SCREEN_H * 4);
+        *self.0.lock().unwrap() += 1;
+    }
+}
+
+struct HoldStart;
+
+impl InputSource for HoldStart {
+    fn is_held(&mut self, key: &JoypadKey) -> bool {
+        *key == JoypadKey::Start
+    }
+}
+
+#[test]
+fn test_step_frame_drives_the_frontend() {
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.set_speed_simulation(false);
+    let frames = Arc::new(Mutex::new(0));
+    emulator.set_video_sink(Box::new(Frames(frames.clone())));
+    emulator.set_input_source(Box::new(HoldStart));
+
+    emulator.step_frame();
+    emulator.step_frame();
+    assert_eq!(*frames.lock().unwrap(), 2);
+    // The keys are polled at the end of the frame.
+    assert_eq!(emulator.get_mmu().joypad.get_keys(), 0x80);
+}
+
+#[test]
+fn test_audio_sync_fills_the_queue() {
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.set_sync_mode(SyncMode::Audio);
+    let queue = AudioQueue::new(44100);
+    emulator.set_audio_sink(Box::new(queue.clone()));
+    // The queue is below the latency, so the frames run without sleeping.
+    emulator.step_frame();
+    emulator.step_frame();
+    assert!(queue.len() > 2 * 700 && queue.len() < 2 * 750);
+    let mut device = vec![[0.0; 2]; 1024];
+    assert_eq!(queue.pop(&mut device), 1024);
+}
+
+struct TurboA;
+
+impl InputSource for TurboA {
+    fn is_held(&mut self, key: &JoypadKey) -> bool {
+        *key == JoypadKey::B
+    }
+
+    fn is_turbo_held(&mut self, key: &JoypadKey) -> bool {
+        *key == JoypadKey::A
+    }
+}
+
+#[test]
+fn test_autofire() {
+    let mut config = Config::default();
+    config.set_autofire_rate(30);
+    config.set_autofire(vec![JoypadKey::B]);
+    let mut emulator = Emulator::new(config).unwrap();
+    emulator.set_speed_simulation(false);
+    emulator.set_input_source(Box::new(TurboA));
+
+    let mut keys = vec![];
+    for _ in 0..4 {
+        emulator.step_frame();
+        keys.push(emulator.get_mmu().joypad.get_keys());
+    }
+    // A and B alternate every frame.
+    assert_eq!(keys, vec![0x30, 0x00, 0x30, 0x00]);
+
+    assert!(!emulator.toggle_autofire());
+    emulator.step_frame();
+    emulator.step_frame();
+    assert_eq!(emulator.get_mmu().joypad.get_keys(), 0x30);
+}
+
+#[test]
+fn test_frame_dump_and_screenshot() {
+    let dir = env::temp_dir().join(format!("ngc224-frames-{}", std::process::id()));
+    let mut config = Config::default();
+    config.set_frame_dump(dir.to_string_lossy().to_string());
+    config.set_frame_dump_interval(2);
+    let mut emulator = Emulator::new(config).unwrap();
+    emulator.set_speed_simulation(false);
+    for _ in 0..5 {
+        emulator.step_frame();
+    }
+    let mut files: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        vec!["frame-000000.png", "frame-000002.png", "frame-000004.png"]
+    );
+
+    let rgba = emulator.frame();
+    assert_eq!(rgba.len(), SCREEN_W * SCREEN_H * 4);
+    let path = dir.join("shot.png");
+    emulator.screenshot(&path).unwrap();
+    let png = fs::read(&path).unwrap();
+    assert_eq!(&png[1..4], b"PNG");
+    assert_eq!(png, fs::read(dir.join("frame-000004.png")).unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cheats() {
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.set_speed_simulation(false);
+    let rom = emulator.get_mmu().read_byte(0x0a20);
+    emulator.add_cheat("3EA-20F", "").unwrap();
+    emulator.add_cheat("01420AC0", "").unwrap();
+    assert_eq!(emulator.get_mmu().read_byte(0x0a20), 0x3e);
+    assert_eq!(emulator.get_mmu().cartridge.read_byte(0x0a20), rom);
+
+    // The GameShark code is written at the VBlank.
+    emulator.get_mmu_mut().write_byte(0xc00a, 0x00);
+    emulator.step_frame();
+    assert_eq!(emulator.get_mmu().read_byte(0xc00a), 0x42);
+
+    assert_eq!(emulator.toggle_cheat("3EA-20F"), Some(false));
+    assert_eq!(emulator.get_mmu().read_byte(0x0a20), rom);
+    assert!(emulator.remove_cheat("01420AC0"));
+    assert!(emulator.add_cheat("zzz", "").is_err());
+}
+
+#[test]
+fn test_reset_keeps_the_cartridge() {
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.set_speed_simulation(false);
+    let power_on = emulator.snapshot().to_bytes();
+    emulator.add_cheat("01420AC0", "").unwrap();
+    emulator.step_frame();
+    assert_eq!(emulator.get_mmu().read_byte(0xc00a), 0x42);
+
+    emulator.pause();
+    assert!(emulator.is_paused());
+    emulator.reset();
+    emulator.resume();
+    assert!(!emulator.is_paused());
+    // The work ram starts over, the GameShark code is written again at the VBlank.
+    assert_eq!(emulator.get_mmu().read_byte(0xc00a), 0x00);
+    assert!(emulator.remove_cheat("01420AC0"));
+    assert_eq!(emulator.snapshot().to_bytes(), power_on);
+}
+
+#[test]
+fn test_mode_of_each_emulator() {
+    let mut config = Config::default();
+    config.set_mode(Mode::Cgb);
+    let cgb = Emulator::new(config).unwrap();
+    let mut config = Config::default();
+    config.set_mode(Mode::Dmg);
+    let dmg = Emulator::new(config).unwrap();
+    // The machine built later doesn't change the one running already.
+    assert!(cgb.get_mmu().gpu.borrow().is_cgb());
+    assert!(!dmg.get_mmu().gpu.borrow().is_cgb());
+}
+
+#[test]
+fn test_cpu_history() {
+    let mut config = Config::default();
+    config.set_cpu_history_size(16);
+    let mut emulator = Emulator::new(config).unwrap();
+    emulator.run_cycles(1000);
+    let history = emulator.get_cpu_history().unwrap();
+    assert_eq!(history.len(), 16);
+    let records: Vec<_> = history.iter().collect();
+    for w in records.windows(2) {
+        assert!(w[1].cycle >= w[0].cycle + u64::from(w[0].cycles));
+    }
+    assert_eq!(records[0].rom_bank, 1);
+    assert_eq!(history.to_csv().lines().count(), 17);
+
+    emulator.set_cpu_history_size(0);
+    assert!(emulator.get_cpu_history().is_none());
+}
+
+#[test]
+fn test_bad_rom_is_an_error() {
+    let path = env::temp_dir().join(format!("ngc224-bad-{}.gb", std::process::id()));
+    // The header ends at 0x150.
+    fs::write(&path, [0x00; 0x100]).unwrap();
+    let config = Config::new(path.to_string_lossy().to_string());
+    let err = Emulator::new(config).err().unwrap();
+    assert!(matches!(
+        err,
+        Error::Cartridge(CartridgeError::TooSmall(0x100))
+    ));
+
+    // The zero header has no logo, it only boots without the strict check.
+    fs::write(&path, [0x00; 0x8000]).unwrap();
+    let mut config = Config::new(path.to_string_lossy().to_string());
+    assert!(Emulator::new(config.clone()).is_ok());
+    config.set_header_check(HeaderCheck::Strict);
+    let err = Emulator::new(config).err().unwrap();
+    assert!(matches!(err, Error::Cartridge(CartridgeError::BadLogo)));
+    fs::write(&path, [0x00; 0x100]).unwrap();
+
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    assert!(emulator.load_rom(&path).is_err());
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        emulator.load_rom(&path),
+        Err(Error::Cartridge(CartridgeError::Io(_)))
+    ));
+}
+
+#[test]
+fn test_boot_rom() {
+    let dir = env::temp_dir();
+    let rom_path = dir.join(format!("ngc224-boot-{}.gb", std::process::id()));
+    let boot_path = dir.join(format!("ngc224-boot-{}.bin", std::process::id()));
+    let mut rom = vec![0x00; 0x8000];
+    // LD A,0x99; LD (0xc001),A; JR -2
+    rom[0x100..0x107].copy_from_slice(&[0x3e, 0x99, 0xea, 0x01, 0xc0, 0x18, 0xfe]);
+    fs::write(&rom_path, &rom).unwrap();
+    let mut boot = vec![0x00; 0x100];
+    // LD A,0x42; LD (0xc000),A
+    boot[..0x05].copy_from_slice(&[0x3e, 0x42, 0xea, 0x00, 0xc0]);
+    // LD A,0x01; LDH (0x50),A at the end, the cpu runs into the cartridge at 0x0100.
+    boot[0xfc..].copy_from_slice(&[0x3e, 0x01, 0xe0, 0x50]);
+    fs::write(&boot_path, &boot).unwrap();
+
+    let mut config = Config::new(rom_path.to_string_lossy().to_string());
+    config.set_boot_rom(boot_path.to_string_lossy().to_string());
+    let mut emulator = Emulator::new(config).unwrap();
+    fs::remove_file(&rom_path).unwrap();
+    fs::remove_file(&boot_path).unwrap();
+    assert!(emulator.get_mmu().is_boot_rom_mapped());
+    assert_eq!(emulator.get_mmu().read_byte(0x0000), 0x3e);
+    let power_on = emulator.snapshot();
+
+    emulator.run_cycles(2000);
+    let mmu = emulator.get_mmu();
+    assert!(!mmu.is_boot_rom_mapped());
+    assert_eq!(mmu.read_byte(0x0000), 0x00);
+    assert_eq!(mmu.read_byte(0xc000), 0x42);
+    assert_eq!(mmu.read_byte(0xc001), 0x99);
+
+    emulator.restore(&power_on).unwrap();
+    assert!(emulator.get_mmu().is_boot_rom_mapped());
+}
+
+struct Rumbles(Arc<Mutex<Vec<bool>>>);
+
+impl RumbleSink for Rumbles {
+    fn set_rumble(&mut self, enabled: bool) {
+        self.0.lock().unwrap().push(enabled);
+    }
+}
+
+#[test]
+fn test_rumble_sink() {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0147] = 0x1c; // MBC5+RUMBLE
+    // LD A,0x08; LD (0x4000),A; JR -2
+    rom[0x100..0x107].copy_from_slice(&[0x3e, 0x08, 0xea, 0x00, 0x40, 0x18, 0xfe]);
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.set_speed_simulation(false);
+    emulator.load_rom_bytes(rom).unwrap();
+    let rumbles = Arc::new(Mutex::new(vec![]));
+    emulator.set_rumble_sink(Box::new(Rumbles(rumbles.clone())));
+
+    emulator.step_frame();
+    emulator.step_frame();
+    assert!(emulator.is_rumbling());
+    assert_eq!(*rumbles.lock().unwrap(), [true]);
+    emulator.get_mmu_mut().write_byte(0x4000, 0x00);
+    emulator.step_frame();
+    assert!(!emulator.is_rumbling());
+    assert_eq!(*rumbles.lock().unwrap(), [true, false]);
+
+    // A pulse within the frame still rumbles.
+    emulator.get_mmu_mut().write_byte(0x4000, 0x08);
+    emulator.get_mmu_mut().write_byte(0x4000, 0x00);
+    emulator.step_frame();
+    emulator.step_frame();
+    assert_eq!(*rumbles.lock().unwrap(), [true, false, true, false]);
+}
+
+#[test]
+fn test_script_hooks() {
+    let mut rom = vec![0x00; 0x8000];
+    // LD A,0x00; INC A; LD (0xc000),A; JR -6
+    rom[0x100..0x108].copy_from_slice(&[0x3e, 0x00, 0x3c, 0xea, 0x00, 0xc0, 0x18, 0xfa]);
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.set_speed_simulation(false);
+    emulator.load_rom_bytes(rom).unwrap();
+    let frames = Arc::new(Mutex::new(vec![]));
+    let writes = Arc::new(Mutex::new(vec![]));
+    let hits = Arc::new(Mutex::new(0));
+
+    let f = frames.clone();
+    emulator.on_frame(move |api| {
+        let v = api.read_byte(0xc000);
+        f.lock().unwrap().push((api.get_frame(), v));
+    });
+    let w = writes.clone();
+    emulator.on_write(0xc000, 0xc100, move |_, a, v| {
+        w.lock().unwrap().push((a, v))
+    });
+    let h = hits.clone();
+    emulator.on_pc(0x0102, move |api| {
+        *h.lock().unwrap() += 1;
+        // The writes of the scripts don't call the write hooks.
+        api.write_byte(0xc100, api.get_reg().get_A());
+    });
+
+    emulator.run_cycles(100);
+    assert!(*hits.lock().unwrap() > 1);
+    // Every loop writes A once, the last one may not be written yet.
+    assert!(writes.lock().unwrap().len() + 1 >= *hits.lock().unwrap());
+    for (i, w) in writes.lock().unwrap().iter().enumerate() {
+        assert_eq!(*w, (0xc000, i as u8 + 1));
+    }
+    assert_ne!(emulator.get_mmu().read_byte(0xc100), 0x00);
+
+    emulator.step_frame();
+    emulator.step_frame();
+    let frames = frames.lock().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[1].0, 2);
+    assert_eq!(frames[1].1, emulator.get_mmu().read_byte(0xc000));
+
+    // The hooks are kept by the reset.
+    writes.lock().unwrap().clear();
+    emulator.reset();
+    emulator.run_cycles(100);
+    assert!(!writes.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_memory_profile() {
+    let mut rom = vec![0x00; 0x8000];
+    // LD A,0x00; INC A; LD (0xc000),A; JR -6
+    rom[0x100..0x108].copy_from_slice(&[0x3e, 0x00, 0x3c, 0xea, 0x00, 0xc0, 0x18, 0xfa]);
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.load_rom_bytes(rom.clone()).unwrap();
+    assert!(emulator.get_memory_profile().is_none());
+
+    let mut config = Config::default();
+    config.set_memory_profile("memory.csv".to_string());
+    let mut emulator = Emulator::new(config).unwrap();
+    emulator.set_speed_simulation(false);
+    emulator.load_rom_bytes(rom).unwrap();
+    emulator.run_cycles(1000);
+    let profile = emulator.get_memory_profile().unwrap();
+    let (code, wram) = (profile.get_page(0x01), profile.get_page(0xc0));
+    // A loop reads 6 bytes of code and writes once, the reads of the history are not counted.
+    let loops = (code.reads - 2) / 6;
+    assert!(loops > 10 && wram.writes >= loops && wram.writes <= loops + 1);
+    assert_eq!(wram.reads, 0);
+    assert_eq!(profile.get_banks()[&0].reads, code.reads);
+}
+
+#[test]
+fn test_cpu_profile() {
+    let mut rom = vec![0x00; 0x8000];
+    // CALL 0x0200; JR -5
+    rom[0x100..0x105].copy_from_slice(&[0xcd, 0x00, 0x02, 0x18, 0xfb]);
+    // LD B,0x10; DEC B; JR NZ,-3; RET
+    rom[0x200..0x206].copy_from_slice(&[0x06, 0x10, 0x05, 0x20, 0xfd, 0xc9]);
+    let mut config = Config::default();
+    config.set_cpu_profile(true);
+    let mut emulator = Emulator::new(config).unwrap();
+    emulator.set_speed_simulation(false);
+    emulator.load_rom_bytes(rom).unwrap();
+    let cycles = emulator.run_cycles(10000);
+
+    let profile = emulator.get_cpu_profile().unwrap();
+    assert_eq!(profile.get_total(), cycles);
+    let functions = profile.top_functions(2);
+    assert_eq!(functions[0].0, (0, 0x0200));
+    assert_eq!(functions[1].0, (0, 0x0100));
+    // A round of the main loop takes 36 cycles, a call of the function 276 cycles.
+    let loops = functions[1].1 / 36;
+    assert!(loops > 10 && functions[0].1 >= loops * 276);
+    assert_eq!(profile.top_instructions(1)[0].0, (0, 0x0203));
+}
+
+#[test]
+fn test_save_bug_report() {
+    let dir = env::temp_dir().join(format!("ngc224-crash-{}", std::process::id()));
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.load_rom_bytes(vec![0x00; 0x8000]).unwrap();
+    emulator.step_frame();
+    let reason = "unknown opcode 0xd3";
+    emulator.save_bug_report(&dir, reason).unwrap();
+
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("panic.txt"), "unknown opcode 0xd3\n");
+    assert!(read("cartridge.txt").contains("rom_size"));
+    assert!(read("history.csv").lines().count() > 1);
+    assert!(dir.join("frame.png").exists());
+    emulator.load_state(dir.join("state.ngcs")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_data_dir() {
+    let dir = env::temp_dir().join(format!("ngc224-data-{}", std::process::id()));
+    let rom_path = env::temp_dir().join(format!("ngc224-data-{}.gb", std::process::id()));
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0134..0x013b].copy_from_slice(b"NGCTEST");
+    rom[0x0143] = 0x80; // CGB
+    rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x0149] = 0x02;
+    rom[0x014d] = 0x5a;
+    fs::write(&rom_path, &rom).unwrap();
+    fs::create_dir_all(dir.join("games")).unwrap();
+    let settings = "mode = \"dmg\"\npalette = \"green\"\n";
+    fs::write(dir.join("games/NGCTEST-5a.toml"), settings).unwrap();
+
+    let mut config = Config::new(rom_path.to_string_lossy().to_string());
+    config.set_data_dir(Some(dir.to_string_lossy().to_string()));
+    let mut emulator = Emulator::new(config).unwrap();
+    let gpu = emulator.get_mmu().gpu.borrow();
+    assert_eq!(gpu.get_dmg_palette().get_name(), "green");
+    drop(gpu);
+    emulator.get_mmu_mut().write_byte(0x0000, 0x0a);
+    emulator.get_mmu_mut().write_byte(0xa000, 0x42);
+    drop(emulator);
+    // The save is named by the game, not beside the rom.
+    assert!(dir.join("saves/NGCTEST-5a.sav").exists());
+    assert!(!rom_path.with_extension("sav").exists());
+    assert!(dir.join("states").is_dir() && dir.join("screenshots").is_dir());
+
+    fs::write(dir.join("games/NGCTEST-5a.toml"), "scale = 3\n").unwrap();
+    let mut config = Config::new(rom_path.to_string_lossy().to_string());
+    config.set_data_dir(Some(dir.to_string_lossy().to_string()));
+    assert!(matches!(Emulator::new(config), Err(Error::Config(_))));
+    fs::remove_file(&rom_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_commands_from_another_thread() {
+    let path = env::temp_dir().join(format!("ngc224-commands-{}.ngcs", std::process::id()));
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.set_speed_simulation(false);
+    let commands = emulator.command_sender();
+    let runner = thread::spawn(move || {
+        emulator.serve_commands();
+        emulator
+    });
+    commands.send(Command::Press(JoypadKey::Start)).unwrap();
+    commands.send(Command::Press(JoypadKey::A)).unwrap();
+    commands.send(Command::Release(JoypadKey::A)).unwrap();
+    commands.send(Command::Pause).unwrap();
+    commands.send(Command::SaveState(path.clone())).unwrap();
+    // The paused emulator returns once the sender is dropped.
+    drop(commands);
+    let mut emulator = runner.join().unwrap();
+    assert!(emulator.is_paused());
+    assert!(Snapshot::load(&path).is_ok());
+
+    // The keys stay held after the channel is closed.
+    emulator.step_frame();
+    assert_eq!(emulator.get_mmu().joypad.get_keys(), 0x80);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_frame_step_while_paused() {
+    let mut emulator = Emulator::new(Config::default()).unwrap();
+    emulator.set_speed_simulation(false);
+    let frames = Arc::new(Mutex::new(0));
+    emulator.set_video_sink(Box::new(Frames(frames.clone())));
+    // The running game is paused by the first step.
+    emulator.frame_step();
+    assert!(emulator.is_paused());
+
+    let commands = emulator.command_sender();
+    let runner = thread::spawn(move || {
+        emulator.serve_commands();
+        emulator
+    });
+    commands.send(Command::FrameStep).unwrap();
+    commands.send(Command::FrameStep).unwrap();
+    commands.send(Command::SlowMotion(25)).unwrap();
+    drop(commands);
+    let emulator = runner.join().unwrap();
+    assert_eq!(*frames.lock().unwrap(), 2);
+    assert!(emulator.is_paused());
+    assert_eq!(emulator.get_slow_motion(), 25);
+}
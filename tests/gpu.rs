@@ -0,0 +1,619 @@
+use std::sync::Arc;
+
+use NGC224::gameboy::cpu::{IntFlag, IntReg};
+use NGC224::gameboy::debug::Poke;
+use NGC224::gameboy::gpu::{Renderer, GPU, VIEWER_H, VIEWER_W};
+use NGC224::gameboy::graphics::palette::PaletteSet;
+use NGC224::gameboy::graphics::tile::{Palette, PixelIndex};
+use NGC224::gameboy::state::{Snapshot, GPU_SECTION};
+use NGC224::gameboy::IOHandler;
+use NGC224::gameboy::{Accuracy, Term, FRAME_CYCLES};
+
+fn new_gpu() -> GPU {
+    let intf = Arc::new(IntReg::new());
+    GPU::new(intf)
+}
+
+fn run_frame(gpu: &mut GPU) {
+    let mut cycles = 0;
+    while cycles < FRAME_CYCLES {
+        gpu.next(4);
+        cycles += 4;
+    }
+}
+
+/// Step the GPU until the STAT mode is reached.
+fn run_until_mode(gpu: &mut GPU, mode: u8) {
+    while gpu.read_byte(0xff41) & 0x03 != mode {
+        gpu.next(4);
+    }
+}
+
+#[test]
+fn test_bg_indices_ignore_palette() {
+    let mut gpu = new_gpu();
+    // Tile 0: color 1 on the first line, color 2 on the second line.
+    gpu.write_byte(0x8000, 0xff);
+    gpu.write_byte(0x8003, 0xff);
+    // Every color number is mapped to black.
+    gpu.write_byte(0xff47, 0xff);
+    // LCD on, tile data at 0x8000, bg on.
+    gpu.write_byte(0xff40, 0x91);
+    run_frame(&mut gpu);
+
+    let indices = gpu.debug_frame_indices();
+    let bg = |color_num| PixelIndex {
+        color_num,
+        palette: Palette::BG,
+    };
+    assert_eq!(indices[0][0], bg(1));
+    assert_eq!(indices[1][17], bg(2));
+    assert_eq!(indices[2][5], bg(0));
+    assert_eq!(indices[8][0], bg(1));
+    assert_eq!(gpu.get_data().get_pixel(5, 2), [0x00; 3]);
+}
+
+#[test]
+fn test_dmg_palette() {
+    let mut gpu = new_gpu();
+    gpu.set_dmg_palette(PaletteSet::default().get("green").unwrap().clone());
+    // Tile 0: color 1 on the first line.
+    gpu.write_byte(0x8000, 0xff);
+    gpu.write_byte(0xff47, 0xe4);
+    gpu.write_byte(0xff40, 0x91);
+    run_frame(&mut gpu);
+
+    assert_eq!(gpu.get_data().get_pixel(0, 0), [0x8b, 0xac, 0x0f]);
+    assert_eq!(gpu.get_data().get_pixel(0, 1), [0x9b, 0xbc, 0x0f]);
+}
+
+#[test]
+fn test_sprite_indices() {
+    let mut gpu = new_gpu();
+    // Tile 1 is filled with color 3.
+    for i in 0x8010..0x8020 {
+        gpu.write_byte(i, 0xff);
+    }
+    // Sprite 0 at the top left corner with OBP1, sprite 1 next to it with OBP0.
+    for (i, v) in [16, 8, 1, 0x10, 16, 16, 1, 0x00].iter().enumerate() {
+        gpu.write_byte(0xfe00 + i as u16, *v);
+    }
+    // LCD on, tile data at 0x8000, sprite on, bg on.
+    gpu.write_byte(0xff40, 0x93);
+    run_frame(&mut gpu);
+
+    let indices = gpu.debug_frame_indices();
+    assert_eq!(
+        indices[3][3],
+        PixelIndex {
+            color_num: 3,
+            palette: Palette::OBP1
+        }
+    );
+    assert_eq!(indices[3][11].palette, Palette::OBP0);
+    assert_eq!(indices[3][20].palette, Palette::BG);
+    assert_eq!(indices[9][3].palette, Palette::BG);
+}
+
+/// Fill the tile 1 with color 3 and write the sprites, each one is Y, X and the attributes.
+fn write_sprites(gpu: &mut GPU, sprites: &[(u8, u8, u8)]) {
+    for i in 0x8010..0x8020 {
+        gpu.write_byte(i, 0xff);
+    }
+    for (i, (y, x, attr)) in sprites.iter().enumerate() {
+        let addr = 0xfe00 + i as u16 * 4;
+        for (j, v) in [*y, *x, 1, *attr].iter().enumerate() {
+            gpu.write_byte(addr + j as u16, *v);
+        }
+    }
+    gpu.write_byte(0xff40, 0x93);
+    run_frame(gpu);
+}
+
+#[test]
+fn test_sprite_limit_per_line() {
+    let mut gpu = new_gpu();
+    // A sprite out of the screen still counts, then 10 sprites side by side.
+    let mut sprites = vec![(16, 0, 0x00)];
+    sprites.extend((0..10).map(|i| (16, 8 + i * 8, 0x00)));
+    sprites.push((24, 8, 0x00));
+    write_sprites(&mut gpu, &sprites);
+
+    let indices = gpu.debug_frame_indices();
+    assert_eq!(indices[0][0].palette, Palette::OBP0);
+    assert_eq!(indices[0][71].palette, Palette::OBP0);
+    assert_eq!(indices[0][72].palette, Palette::BG);
+    // The line below has its own 10 sprites.
+    assert_eq!(indices[8][0].palette, Palette::OBP0);
+}
+
+#[test]
+fn test_sprite_priority() {
+    let mut gpu = new_gpu();
+    // The smaller X is drawn above, then the one earlier in the OAM.
+    write_sprites(
+        &mut gpu,
+        &[(16, 8, 0x00), (16, 12, 0x10), (32, 8, 0x10), (32, 8, 0x00)],
+    );
+    let indices = gpu.debug_frame_indices();
+    assert_eq!(indices[0][5].palette, Palette::OBP0);
+    assert_eq!(indices[0][9].palette, Palette::OBP1);
+    assert_eq!(indices[16][5].palette, Palette::OBP1);
+
+    // The CGB only follows the OAM order.
+    let mut gpu = new_cgb_gpu();
+    write_palette(&mut gpu, 0xff6a, 1, 3, 0x001f);
+    write_palette(&mut gpu, 0xff6a, 2, 3, 0x03e0);
+    write_sprites(&mut gpu, &[(16, 12, 0x01), (16, 8, 0x02)]);
+    assert_eq!(gpu.get_data().get_pixel(5, 0), [0xff, 0x00, 0x00]);
+    assert_eq!(gpu.get_data().get_pixel(3, 0), [0x00, 0xff, 0x00]);
+}
+
+#[test]
+fn test_palette_ram_locked_in_mode3() {
+    let mut gpu = new_gpu();
+    gpu.write_byte(0xff40, 0x91);
+    for (spec, data) in [(0xff68, 0xff69), (0xff6a, 0xff6b)].iter().copied() {
+        run_until_mode(&mut gpu, 0);
+        // Index 2 with auto increment.
+        gpu.write_byte(spec, 0x82);
+        gpu.write_byte(data, 0x12);
+        assert_eq!(gpu.read_byte(spec), 0xc3);
+
+        run_until_mode(&mut gpu, 3);
+        assert_eq!(gpu.read_byte(data), 0xff);
+        // The write is dropped, but the index still moves on.
+        gpu.write_byte(data, 0x34);
+        assert_eq!(gpu.read_byte(spec), 0xc4);
+
+        run_until_mode(&mut gpu, 0);
+        gpu.write_byte(spec, 0x02);
+        assert_eq!(gpu.read_byte(data), 0x12);
+        gpu.write_byte(spec, 0x03);
+        assert_eq!(gpu.read_byte(data), 0xff);
+        // Reading doesn't increment the index.
+        assert_eq!(gpu.read_byte(spec), 0x43);
+    }
+}
+
+#[test]
+fn test_palette_ram_unlocked_with_lcd_off() {
+    let mut gpu = new_gpu();
+    gpu.write_byte(0xff40, 0x00);
+    gpu.write_byte(0xff68, 0x00);
+    gpu.write_byte(0xff69, 0x7f);
+    assert_eq!(gpu.read_byte(0xff69), 0x7f);
+}
+
+#[test]
+fn test_rerender_shows_poke() {
+    let mut gpu = new_gpu();
+    gpu.write_byte(0xff47, 0xe4);
+    gpu.write_byte(0xff40, 0x91);
+    run_frame(&mut gpu);
+    assert_eq!(gpu.debug_frame_indices()[3][0].color_num, 0);
+
+    // Tile 0 row 3 turns into color 3, without running the next frame.
+    Poke::parse("tilepoke 0 row 3 0xff 0xff")
+        .unwrap()
+        .apply(&mut gpu);
+    assert!(gpu.should_updated());
+    assert_eq!(gpu.debug_frame_indices()[3][0].color_num, 3);
+    assert_eq!(gpu.debug_frame_indices()[4][0].color_num, 0);
+}
+
+#[test]
+fn test_tile_data_rewrite() {
+    let mut gpu = new_cgb_gpu();
+    // The tile 1 of the bank 1 is drawn at the second tile of the map.
+    gpu.write_byte(0x9801, 0x01);
+    gpu.write_byte(0xff4f, 0x01);
+    gpu.write_byte(0x9801, 0x08);
+    gpu.write_byte(0x8010, 0x80);
+    gpu.write_byte(0xff40, 0x91);
+    run_frame(&mut gpu);
+    assert_eq!(gpu.debug_frame_indices()[0][8].color_num, 1);
+    assert_eq!(gpu.debug_frame_indices()[0][9].color_num, 0);
+
+    // The rewritten row is drawn in the next frame.
+    gpu.write_byte(0x8011, 0x40);
+    run_frame(&mut gpu);
+    assert_eq!(gpu.debug_frame_indices()[0][8].color_num, 1);
+    assert_eq!(gpu.debug_frame_indices()[0][9].color_num, 2);
+
+    // The restored VRAM is drawn, not the one before the restore.
+    let mut snapshot = Snapshot::new();
+    snapshot.put(GPU_SECTION, &gpu);
+    let mut restored = new_cgb_gpu();
+    snapshot.restore(GPU_SECTION, &mut restored).unwrap();
+    assert_eq!(restored.debug_frame_indices()[0][9].color_num, 2);
+    assert_eq!(restored.read_byte(0x8011), 0x40);
+}
+
+fn new_cgb_gpu() -> GPU {
+    let intf = Arc::new(IntReg::new());
+    GPU::with_term(intf, Term::GBC)
+}
+
+/// Write the RGB555 color to the color palette memory through the specification register.
+fn write_palette(gpu: &mut GPU, spec: u16, palette: u8, color_num: u8, color: u16) {
+    gpu.write_byte(spec, palette * 8 + color_num * 2);
+    gpu.write_byte(spec + 1, color as u8);
+    gpu.write_byte(spec, palette * 8 + color_num * 2 + 1);
+    gpu.write_byte(spec + 1, (color >> 8) as u8);
+}
+
+#[test]
+fn test_vram_bank_is_cgb_only() {
+    let mut gpu = new_cgb_gpu();
+    gpu.write_byte(0xff4f, 0x01);
+    assert_eq!(gpu.read_byte(0xff4f), 0xff);
+    gpu.write_byte(0x8000, 0xaa);
+    gpu.write_byte(0xff4f, 0x00);
+    assert_eq!(gpu.read_byte(0xff4f), 0xfe);
+    assert_eq!(gpu.read_byte(0x8000), 0x00);
+
+    let mut gpu = new_gpu();
+    gpu.write_byte(0xff4f, 0x01);
+    gpu.write_byte(0x8000, 0xaa);
+    assert_eq!(gpu.read_byte(0xff4f), 0xff);
+    assert_eq!(gpu.read_byte(0x8000), 0xaa);
+}
+
+#[test]
+fn test_cgb_bg_attributes_and_colors() {
+    let mut gpu = new_cgb_gpu();
+    // Tile 0 in bank 0 is color 1 on the first line, tile 0 in bank 1 is color 2 there.
+    gpu.write_byte(0x8000, 0xff);
+    gpu.write_byte(0xff4f, 0x01);
+    gpu.write_byte(0x8001, 0xff);
+    // The second tile of the map uses the bank 1 and the palette 2.
+    gpu.write_byte(0x9801, 0x0a);
+    gpu.write_byte(0xff4f, 0x00);
+    write_palette(&mut gpu, 0xff68, 0, 1, 0x7c00);
+    write_palette(&mut gpu, 0xff68, 2, 2, 0x001f);
+    gpu.write_byte(0xff40, 0x91);
+    run_frame(&mut gpu);
+
+    assert_eq!(gpu.get_data().get_pixel(0, 0), [0x00, 0x00, 0xff]);
+    assert_eq!(gpu.get_data().get_pixel(8, 0), [0xff, 0x00, 0x00]);
+    assert_eq!(gpu.debug_frame_indices()[0][8].color_num, 2);
+    // The palette memory is white on power up.
+    assert_eq!(gpu.get_data().get_pixel(0, 1), [0xff; 3]);
+}
+
+#[test]
+fn test_cgb_sprite_palette() {
+    let mut gpu = new_cgb_gpu();
+    // Tile 1 in bank 1 is filled with color 3, the bank 0 one is empty.
+    gpu.write_byte(0xff4f, 0x01);
+    for i in 0x8010..0x8020 {
+        gpu.write_byte(i, 0xff);
+    }
+    gpu.write_byte(0xff4f, 0x00);
+    // Sprite 0 from bank 1 with the palette 5.
+    for (i, v) in [16, 8, 1, 0x0d].iter().enumerate() {
+        gpu.write_byte(0xfe00 + i as u16, *v);
+    }
+    write_palette(&mut gpu, 0xff6a, 5, 3, 0x03e0);
+    gpu.write_byte(0xff40, 0x93);
+    run_frame(&mut gpu);
+
+    assert_eq!(gpu.get_data().get_pixel(3, 3), [0x00, 0xff, 0x00]);
+    assert_eq!(gpu.debug_frame_indices()[3][3].palette, Palette::OBP0);
+}
+
+#[test]
+fn test_skip_rendering_keeps_the_frame() {
+    let mut gpu = new_gpu();
+    gpu.write_byte(0xff47, 0xff);
+    gpu.write_byte(0xff40, 0x91);
+    gpu.set_skip_rendering(true);
+    run_frame(&mut gpu);
+    // Still white from the power on.
+    assert_eq!(gpu.get_data().get_pixel(5, 2), [0xff; 3]);
+    assert!(gpu.should_updated());
+
+    gpu.set_skip_rendering(false);
+    run_frame(&mut gpu);
+    assert_eq!(gpu.get_data().get_pixel(5, 2), [0x00; 3]);
+}
+
+/// The tiles, the bg and the window maps with scrolling, and overlapping sprites.
+fn draw_scene(gpu: &mut GPU) {
+    for i in 0x8010..0x8020 {
+        gpu.write_byte(i, 0xff);
+    }
+    for i in 0..8 {
+        gpu.write_byte(0x8020 + i * 2, 0x55);
+        gpu.write_byte(0x8021 + i * 2, 0x0f);
+    }
+    for i in 0..0x400 {
+        gpu.write_byte(0x9800 + i, ((i % 32 + i / 32) * 7 % 3) as u8);
+        gpu.write_byte(0x9c00 + i, (i % 2) as u8 + 1);
+    }
+    let sprites = [
+        (20, 4, 0x00),
+        (24, 10, 0x10),
+        (24, 12, 0x80),
+        (60, 100, 0x20),
+        (70, 60, 0x40),
+    ];
+    for (i, (y, x, attr)) in sprites.iter().enumerate() {
+        let addr = 0xfe00 + i as u16 * 4;
+        for (j, v) in [*y, *x, 2, *attr].iter().enumerate() {
+            gpu.write_byte(addr + j as u16, *v);
+        }
+    }
+    gpu.write_byte(0xff42, 5);
+    gpu.write_byte(0xff43, 3);
+    gpu.write_byte(0xff4a, 50);
+    gpu.write_byte(0xff4b, 47);
+    gpu.write_byte(0xff47, 0xe4);
+    gpu.write_byte(0xff48, 0xd2);
+    gpu.write_byte(0xff49, 0x1b);
+    // LCD on, window map at 0x9c00, window on, tile data at 0x8000, sprite on, bg on.
+    gpu.write_byte(0xff40, 0xf3);
+    run_frame(gpu);
+}
+
+#[test]
+fn test_fifo_matches_scanline() {
+    let mut scanline = new_gpu();
+    draw_scene(&mut scanline);
+    let mut fifo = new_gpu();
+    fifo.set_renderer(Renderer::Fifo);
+    draw_scene(&mut fifo);
+    for (y, (a, b)) in scanline
+        .debug_frame_indices()
+        .iter()
+        .zip(fifo.debug_frame_indices().iter())
+        .enumerate()
+    {
+        assert_eq!(a[..], b[..], "line {}", y);
+    }
+    assert_eq!(scanline.get_data().as_rgba(), fifo.get_data().as_rgba());
+}
+
+/// Returns the dots of the mode 3 of the next line.
+fn measure_mode3(gpu: &mut GPU) -> u32 {
+    run_until_mode(gpu, 0);
+    run_until_mode(gpu, 2);
+    while gpu.read_byte(0xff41) & 0x03 == 2 {
+        gpu.next(1);
+    }
+    // The first dot is run on entering the mode 3.
+    let mut dots = 1;
+    while gpu.read_byte(0xff41) & 0x03 == 3 {
+        gpu.next(1);
+        dots += 1;
+    }
+    dots
+}
+
+#[test]
+fn test_fifo_mode3_length() {
+    let mut gpu = new_gpu();
+    gpu.set_renderer(Renderer::Fifo);
+    gpu.write_byte(0xff40, 0x91);
+    assert_eq!(measure_mode3(&mut gpu), 172);
+    // The fine scroll pixels are dropped.
+    gpu.write_byte(0xff43, 3);
+    assert_eq!(measure_mode3(&mut gpu), 175);
+}
+
+#[test]
+fn test_fifo_mid_line_scroll() {
+    for renderer in [Renderer::Scanline, Renderer::Fifo].iter() {
+        let mut gpu = new_gpu();
+        gpu.set_renderer(*renderer);
+        // The even tiles are filled with color 3.
+        for i in 0x8010..0x8020 {
+            gpu.write_byte(i, 0xff);
+        }
+        for i in 0..0x400 {
+            gpu.write_byte(0x9800 + i, (i % 2 == 0) as u8);
+        }
+        gpu.write_byte(0xff40, 0x91);
+        run_until_mode(&mut gpu, 3);
+        // Scroll by a tile in the middle of the first line.
+        for _ in 0..92 {
+            gpu.next(1);
+        }
+        gpu.write_byte(0xff43, 8);
+        run_until_mode(&mut gpu, 0);
+
+        let line = &gpu.debug_frame_indices()[0];
+        let colors = (line[4].color_num, line[150].color_num);
+        match renderer {
+            // The whole line is drawn with the last SCX.
+            Renderer::Scanline => assert_eq!(colors, (0, 0)),
+            Renderer::Fifo => assert_eq!(colors, (3, 0)),
+        }
+    }
+}
+
+/// Step the GPU until LY is reached, the line is not drawn yet.
+fn run_until_line(gpu: &mut GPU, ly: u8) {
+    while gpu.read_byte(0xff44) != ly {
+        gpu.next(4);
+    }
+}
+
+#[test]
+fn test_window_line_counter() {
+    for renderer in [Renderer::Scanline, Renderer::Fifo].iter() {
+        let mut gpu = new_gpu();
+        gpu.set_renderer(*renderer);
+        // The window rows of tiles are color 3 and 0, 1, 2, 3 in turn.
+        for i in 0x8010..0x8020 {
+            gpu.write_byte(i, 0xff);
+        }
+        for i in 0..8 {
+            gpu.write_byte(0x8020 + i * 2, 0x55);
+            gpu.write_byte(0x8021 + i * 2, 0x0f);
+        }
+        for i in 0..0x400 {
+            gpu.write_byte(0x9c00 + i, (i / 32 % 2) as u8 + 1);
+        }
+        gpu.write_byte(0xff4a, 0);
+        gpu.write_byte(0xff4b, 7);
+        gpu.write_byte(0xff40, 0xf1);
+        // Hide the window for the lines 8 - 15.
+        run_until_line(&mut gpu, 8);
+        gpu.write_byte(0xff40, 0xd1);
+        run_until_line(&mut gpu, 16);
+        gpu.write_byte(0xff40, 0xf1);
+        run_until_line(&mut gpu, 144);
+
+        let frame = gpu.debug_frame_indices();
+        let colors: Vec<u8> = [0, 8, 16, 23, 24]
+            .iter()
+            .map(|y| frame[*y][1].color_num)
+            .collect();
+        // The window goes on from its 8th line after it's shown again.
+        assert_eq!(colors, vec![3, 0, 1, 1, 3], "{:?}", renderer);
+    }
+}
+
+#[test]
+fn test_vram_viewer() {
+    let mut gpu = new_gpu();
+    // Tile 1 is filled with color 3, the BG map at 0x9c00 is all tile 1.
+    for i in 0x8010..0x8020 {
+        gpu.write_byte(i, 0xff);
+    }
+    for i in 0..0x400 {
+        gpu.write_byte(0x9c00 + i, 1);
+    }
+    // Sprite 0 shows tile 1 with OBP0.
+    for (i, v) in [16, 8, 1, 0x00].iter().enumerate() {
+        gpu.write_byte(0xfe00 + i as u16, *v);
+    }
+    gpu.write_byte(0xff47, 0xe4);
+    gpu.write_byte(0xff48, 0xe4);
+    gpu.write_byte(0xff42, 250);
+    gpu.write_byte(0xff43, 100);
+    gpu.write_byte(0xff4a, 0);
+    gpu.write_byte(0xff4b, 7);
+    // The bg on 0x9800, the window on 0x9c00.
+    gpu.write_byte(0xff40, 0xf1);
+
+    let view = gpu.render_vram();
+    assert_eq!(view.len(), VIEWER_W * VIEWER_H);
+    let at = |x: usize, y: usize| view[y * VIEWER_W + x];
+    let (white, black) = ([0xff; 3], [0x00; 3]);
+    // The tiles 0 and 1.
+    assert_eq!(at(7, 7), white);
+    assert_eq!(at(8, 0), black);
+    // The sprite in the first cell of the OAM.
+    assert_eq!(at(2, 200 + 2), black);
+    // The BG maps, the screen wraps around the bottom of 0x9800.
+    let (map0, map1) = (264, 528);
+    assert_eq!(at(map0 + 50, 50), white);
+    assert_eq!(at(map0 + 100, 250), [0xff, 0x00, 0x00]);
+    assert_eq!(at(map0 + 101, (250 + 143) % 256), [0xff, 0x00, 0x00]);
+    assert_eq!(at(map0 + 101, 251), white);
+    assert_eq!(at(map1 + 50, 50), black);
+    assert_eq!(at(map1, 50), [0x00, 0x80, 0xff]);
+    assert_eq!(at(map1 + 160, 50), black);
+}
+
+#[test]
+fn test_finished_frame_is_kept() {
+    let mut gpu = new_gpu();
+    gpu.write_byte(0xff47, 0xff);
+    gpu.write_byte(0xff40, 0x91);
+    run_frame(&mut gpu);
+    let frame = gpu.get_frame();
+    assert_eq!(frame.get_pixel(5, 2), [0x00; 3]);
+
+    // The next frame is drawn white, the handle still holds the black one.
+    gpu.write_byte(0xff47, 0x00);
+    run_until_mode(&mut gpu, 0);
+    run_until_mode(&mut gpu, 2);
+    assert_eq!(gpu.get_data().get_pixel(5, 0), [0xff; 3]);
+    assert_eq!(frame.get_pixel(5, 0), [0x00; 3]);
+    assert_eq!(gpu.get_frame().get_pixel(5, 0), [0x00; 3]);
+    run_frame(&mut gpu);
+    assert_eq!(gpu.get_frame().get_pixel(5, 0), [0xff; 3]);
+    assert_eq!(frame.get_pixel(5, 0), [0x00; 3]);
+}
+
+#[test]
+fn test_stat_interrupt_follows_the_enable_bits() {
+    let intf = Arc::new(IntReg::new());
+    let mut gpu = GPU::new(intf.clone());
+    gpu.write_byte(0xff40, 0x91);
+    // Only the HBlank source, the OAM scan is not an interrupt.
+    gpu.write_byte(0xff41, 0x08);
+    run_until_mode(&mut gpu, 3);
+    intf.set(0x00);
+    run_until_mode(&mut gpu, 0);
+    assert!(intf.is_requested(IntFlag::LCDStat));
+    intf.set(0x00);
+    run_until_mode(&mut gpu, 2);
+    assert!(!intf.is_requested(IntFlag::LCDStat));
+
+    // Only the OAM scan source.
+    gpu.write_byte(0xff41, 0x20);
+    run_until_mode(&mut gpu, 0);
+    intf.set(0x00);
+    run_until_mode(&mut gpu, 2);
+    assert!(intf.is_requested(IntFlag::LCDStat));
+}
+
+/// Step the GPU until LY reads as the line, it's the first dot of the line.
+fn run_until_ly(gpu: &mut GPU, ly: u8) {
+    while gpu.read_byte(0xff44) != ly {
+        gpu.next(4);
+    }
+}
+
+#[test]
+fn test_ly_153_reads_as_0() {
+    for accuracy in [Accuracy::Fast, Accuracy::Accurate].iter() {
+        let intf = Arc::new(IntReg::new());
+        let mut gpu = GPU::new(intf.clone());
+        gpu.set_accuracy(*accuracy);
+        gpu.write_byte(0xff40, 0x91);
+        // LYC=0 with the LYC interrupt on.
+        gpu.write_byte(0xff41, 0x40);
+        run_until_ly(&mut gpu, 153);
+        intf.set(0x00);
+        gpu.next(4);
+        let accurate = *accuracy == Accuracy::Accurate;
+        assert_eq!(gpu.read_byte(0xff44), if accurate { 0 } else { 153 });
+        assert_eq!(intf.is_requested(IntFlag::LCDStat), accurate);
+
+        // The line 0 doesn't match LYC=0 again.
+        intf.set(0x00);
+        gpu.next(452);
+        assert_eq!(gpu.read_byte(0xff44), 0);
+        gpu.next(4);
+        assert_eq!(intf.is_requested(IntFlag::LCDStat), !accurate);
+    }
+}
+
+#[test]
+fn test_vblank_starts_after_line_144() {
+    for accuracy in [Accuracy::Fast, Accuracy::Accurate].iter() {
+        let intf = Arc::new(IntReg::new());
+        let mut gpu = GPU::new(intf.clone());
+        gpu.set_accuracy(*accuracy);
+        gpu.write_byte(0xff40, 0x91);
+        // The mode 2 interrupt on.
+        gpu.write_byte(0xff41, 0x20);
+        run_until_ly(&mut gpu, 143);
+        run_until_mode(&mut gpu, 0);
+        intf.set(0x00);
+        run_until_ly(&mut gpu, 144);
+        let accurate = *accuracy == Accuracy::Accurate;
+        assert_eq!(intf.is_requested(IntFlag::VBlank), !accurate);
+        assert_eq!(intf.is_requested(IntFlag::LCDStat), accurate);
+        assert_eq!(gpu.read_byte(0xff41) & 0x03, if accurate { 0 } else { 1 });
+        gpu.next(4);
+        assert!(intf.is_requested(IntFlag::VBlank));
+        assert_eq!(gpu.read_byte(0xff41) & 0x03, 1);
+    }
+}
@@ -0,0 +1,386 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::new_cpu;
+use NGC224::gameboy::cartridge::load_empty_cartridge;
+use NGC224::gameboy::cpu::{IntFlag, IntReg};
+use NGC224::gameboy::gpu::GPU;
+use NGC224::gameboy::joypad::Joypad;
+use NGC224::gameboy::mmu::Mmunit;
+use NGC224::gameboy::sound::apu::{Apu, DEFAULT_SAMPLE_RATE};
+use NGC224::gameboy::timer::Timer;
+use NGC224::gameboy::{IOHandler, Term, CPU};
+
+const NOP: u8 = 0x00;
+const HALT: u8 = 0x76;
+const RETI: u8 = 0xd9;
+const DI: u8 = 0xf3;
+const EI: u8 = 0xfb;
+
+const IF: u16 = 0xff0f;
+const IE: u16 = 0xffff;
+
+const VBLANK: u8 = 0b0001;
+const TIMER: u8 = 0b0100;
+
+fn pop_word(cpu: &NGC224::gameboy::CPU) -> u16 {
+    cpu.read_word_from_memory(cpu.reg.get_SP())
+}
+
+#[test]
+fn test_ei_is_delayed_by_one_instruction() {
+    let (mut cpu, bus) = new_cpu();
+    cpu.disable_ime();
+    bus.lock().unwrap().load(0x100, &[EI, NOP, NOP]);
+    bus.lock().unwrap().write_byte(IE, VBLANK);
+    bus.lock().unwrap().write_byte(IF, VBLANK);
+
+    cpu.next();
+    assert!(!cpu.is_ime_enabled());
+    assert!(cpu.is_ime_pending());
+    // The instruction after EI is always executed.
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x102);
+    assert!(cpu.is_ime_enabled());
+
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x40);
+    assert_eq!(pop_word(&cpu), 0x102);
+    assert_eq!(bus.lock().unwrap().read_byte(IF), 0x00);
+}
+
+#[test]
+fn test_ei_followed_by_di() {
+    let (mut cpu, bus) = new_cpu();
+    cpu.disable_ime();
+    bus.lock().unwrap().load(0x100, &[EI, DI, NOP]);
+    bus.lock().unwrap().write_byte(IE, VBLANK);
+    bus.lock().unwrap().write_byte(IF, VBLANK);
+
+    for _ in 0..3 {
+        cpu.next();
+    }
+    assert_eq!(cpu.reg.get_PC(), 0x103);
+    assert!(!cpu.is_ime_enabled());
+    assert!(!cpu.is_ime_pending());
+}
+
+#[test]
+fn test_ei_inside_isr_allows_nesting() {
+    let (mut cpu, bus) = new_cpu();
+    bus.lock().unwrap().load(0x100, &[NOP]);
+    bus.lock().unwrap().load(0x50, &[EI, NOP, NOP]);
+    bus.lock().unwrap().write_byte(IE, VBLANK | TIMER);
+    bus.lock().unwrap().write_byte(IF, TIMER);
+
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x50);
+    assert!(!cpu.is_ime_enabled());
+
+    // A higher priority interrupt is requested in the timer handler.
+    bus.lock().unwrap().write_byte(IF, VBLANK);
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x52);
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x40);
+    assert_eq!(pop_word(&cpu), 0x52);
+    assert_eq!(cpu.reg.get_SP(), 0xfffa);
+}
+
+#[test]
+fn test_no_nesting_without_ei() {
+    let (mut cpu, bus) = new_cpu();
+    bus.lock().unwrap().load(0x50, &[NOP, NOP]);
+    bus.lock().unwrap().write_byte(IE, VBLANK | TIMER);
+    bus.lock().unwrap().write_byte(IF, TIMER);
+
+    cpu.next();
+    bus.lock().unwrap().write_byte(IF, VBLANK);
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x52);
+    assert_eq!(bus.lock().unwrap().read_byte(IF), VBLANK);
+}
+
+#[test]
+fn test_reti_with_pending_interrupt() {
+    let (mut cpu, bus) = new_cpu();
+    bus.lock().unwrap().load(0x100, &[NOP, NOP]);
+    bus.lock().unwrap().load(0x40, &[RETI]);
+    bus.lock().unwrap().write_byte(IE, VBLANK | TIMER);
+    bus.lock().unwrap().write_byte(IF, VBLANK | TIMER);
+
+    // VBlank has the higher priority.
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x40);
+    // RETI enables IME immediately, the timer is dispatched before returning to the main code.
+    cpu.next();
+    assert!(cpu.is_ime_enabled());
+    assert_eq!(cpu.reg.get_PC(), 0x100);
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x50);
+    assert_eq!(pop_word(&cpu), 0x100);
+    assert_eq!(cpu.reg.get_SP(), 0xfffc);
+}
+
+#[test]
+fn test_unused_if_bits_are_ignored() {
+    let (mut cpu, bus) = new_cpu();
+    bus.lock().unwrap().load(0x100, &[NOP]);
+    bus.lock().unwrap().write_byte(IE, 0xff);
+    bus.lock().unwrap().write_byte(IF, 0xe0);
+
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x101);
+
+    bus.lock().unwrap().write_byte(IF, 0xe0 | TIMER);
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x50);
+}
+
+#[test]
+fn test_if_written_by_game_code() {
+    let intf = Arc::new(IntReg::new());
+    let gpu = GPU::new(intf.clone());
+    let mut mmu = Mmunit::new(
+        load_empty_cartridge(),
+        Apu::new(DEFAULT_SAMPLE_RATE),
+        gpu,
+        Joypad::new(intf.clone()),
+        Timer::new(intf.clone()),
+        intf.clone(),
+    );
+
+    mmu.write_byte(IF, 0xff);
+    assert_eq!((intf.get() & 0x1f), 0x1f);
+    mmu.write_byte(IF, TIMER);
+    assert_eq!(mmu.read_byte(IF), 0xe0 | TIMER);
+
+    intf.req(IntFlag::VBlank);
+    intf.ack(IntFlag::Timer);
+    assert_eq!(mmu.read_byte(IF), 0xe0 | VBLANK);
+    mmu.ack_interrupt(IntFlag::VBlank);
+    assert_eq!(mmu.read_byte(IF), 0xe0);
+}
+
+#[test]
+fn test_dispatch_acks_only_its_request() {
+    let (mut cpu, bus) = new_cpu();
+    bus.lock().unwrap().load(0x100, &[NOP]);
+    bus.lock().unwrap().write_byte(IE, TIMER);
+    bus.lock().unwrap().write_byte(IF, VBLANK | TIMER);
+
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x50);
+    assert_eq!(bus.lock().unwrap().read_byte(IF), VBLANK);
+}
+
+#[test]
+fn test_halt_exits_into_isr() {
+    let (mut cpu, bus) = new_cpu();
+    bus.lock().unwrap().load(0x100, &[HALT, NOP]);
+    bus.lock().unwrap().write_byte(IE, TIMER);
+
+    cpu.next();
+    assert!(cpu.is_halt());
+    cpu.next();
+    assert!(cpu.is_halt());
+    assert_eq!(cpu.reg.get_PC(), 0x101);
+
+    bus.lock().unwrap().write_byte(IF, TIMER);
+    // The wake up takes a M-cycle, then the interrupt is dispatched.
+    assert_eq!(cpu.next(), 4);
+    assert!(!cpu.is_halt());
+    assert_eq!(cpu.reg.get_PC(), 0x101);
+    assert_eq!(cpu.next(), 20);
+    assert_eq!(cpu.reg.get_PC(), 0x50);
+    assert_eq!(pop_word(&cpu), 0x101);
+}
+
+#[test]
+fn test_halt_without_ime_resumes() {
+    let (mut cpu, bus) = new_cpu();
+    cpu.disable_ime();
+    bus.lock().unwrap().load(0x100, &[HALT, NOP]);
+    bus.lock().unwrap().write_byte(IE, TIMER);
+
+    cpu.next();
+    assert_eq!(cpu.next(), 4);
+    assert!(cpu.is_halt());
+    bus.lock().unwrap().write_byte(IF, TIMER);
+    // Wake up and continue without dispatching, the interrupt is still requested.
+    assert_eq!(cpu.next(), 4);
+    assert!(!cpu.is_halt());
+    assert_eq!(cpu.reg.get_PC(), 0x101);
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x102);
+    assert_eq!(bus.lock().unwrap().read_byte(IF), TIMER);
+}
+
+#[test]
+fn test_dispatch_takes_5_m_cycles() {
+    let (mut cpu, bus) = new_cpu();
+    bus.lock().unwrap().write_byte(IE, TIMER);
+    bus.lock().unwrap().write_byte(IF, TIMER);
+    assert_eq!(cpu.next(), 20);
+    assert_eq!(cpu.reg.get_PC(), 0x50);
+}
+
+#[test]
+fn test_ie_push_cancels_the_interrupt() {
+    // The upper byte of PC 0x0100 is pushed into IE, only VBlank is enabled after it.
+    let (mut cpu, bus) = new_cpu();
+    cpu.reg.set_SP(0x0000);
+    bus.lock().unwrap().write_byte(IE, TIMER);
+    bus.lock().unwrap().write_byte(IF, TIMER);
+    assert_eq!(cpu.next(), 20);
+    assert_eq!(cpu.reg.get_PC(), 0x0000);
+    assert_eq!(cpu.reg.get_SP(), 0xfffe);
+    assert_eq!(bus.lock().unwrap().read_byte(IE), 0x01);
+    assert_eq!(bus.lock().unwrap().read_byte(IF), TIMER);
+    assert!(!cpu.is_ime_enabled());
+
+    // Another requested interrupt is dispatched instead.
+    let (mut cpu, bus) = new_cpu();
+    cpu.reg.set_SP(0x0000);
+    bus.lock().unwrap().write_byte(IE, TIMER);
+    bus.lock().unwrap().write_byte(IF, VBLANK | TIMER);
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x40);
+    assert_eq!(bus.lock().unwrap().read_byte(IF), TIMER);
+}
+
+#[test]
+fn test_halt_bug() {
+    let (mut cpu, bus) = new_cpu();
+    cpu.disable_ime();
+    // HALT, INC A, NOP
+    bus.lock().unwrap().load(0x100, &[HALT, 0x3c, NOP]);
+    bus.lock().unwrap().write_byte(IE, TIMER);
+    bus.lock().unwrap().write_byte(IF, TIMER);
+
+    cpu.next();
+    assert!(!cpu.is_halt());
+    // The byte after HALT is read twice.
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x101);
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x102);
+    assert_eq!(cpu.reg.get_A(), 2);
+}
+
+#[test]
+fn test_stop_waits_for_joypad() {
+    let (mut cpu, bus) = new_cpu();
+    // STOP, INC A
+    bus.lock().unwrap().load(0x100, &[0x10, 0x00, 0x3c]);
+    bus.lock().unwrap().write_byte(0xff04, 0x12);
+    // The button keys are selected, none is pressed.
+    bus.lock().unwrap().write_byte(0xff00, 0xdf);
+
+    cpu.next();
+    assert!(cpu.is_stop());
+    assert_eq!(cpu.reg.get_PC(), 0x102);
+    assert_eq!(bus.lock().unwrap().read_byte(0xff04), 0x00);
+    assert_eq!(cpu.next(), 4);
+    assert_eq!(cpu.reg.get_PC(), 0x102);
+
+    // A is pressed.
+    bus.lock().unwrap().write_byte(0xff00, 0xde);
+    cpu.next();
+    assert!(!cpu.is_stop());
+    assert_eq!(cpu.reg.get_A(), 1);
+}
+
+/// Returns the CGB cpu on the mmu, running STOP, INC A from the work ram with the speed switch
+/// prepared.
+fn new_cgb_cpu_at_stop() -> CPU {
+    let intf = Arc::new(IntReg::new());
+    let gpu = GPU::with_term(intf.clone(), Term::GBC);
+    let mut mmu = Mmunit::new(
+        load_empty_cartridge(),
+        Apu::new(DEFAULT_SAMPLE_RATE),
+        gpu,
+        Joypad::new(intf.clone()),
+        Timer::new(intf.clone()),
+        intf,
+    );
+    for (i, v) in [0x10, 0x00, 0x3c].iter().enumerate() {
+        mmu.write_byte(0xc000 + i as u16, *v);
+    }
+    mmu.write_byte(0xff00, 0xdf);
+    mmu.write_byte(0xff4d, 0x01);
+    let mut cpu = CPU::with_term(mmu, Term::GBC);
+    cpu.reg.set_PC(0xc000);
+    cpu
+}
+
+#[test]
+fn test_stop_switches_speed() {
+    let mut cpu = new_cgb_cpu_at_stop();
+    let (pc, a) = (cpu.reg.get_PC(), cpu.reg.get_A());
+
+    cpu.next();
+    assert!(!cpu.is_stop());
+    assert_eq!(cpu.reg.get_PC(), pc + 2);
+    assert!(cpu.get_bus().as_mmu().unwrap().is_double_speed());
+    cpu.next();
+    assert_eq!(cpu.reg.get_A(), a.wrapping_add(1));
+}
+
+#[test]
+fn test_interrupt_before_stop_keeps_the_speed() {
+    let mut cpu = new_cgb_cpu_at_stop();
+    cpu.enable_ime();
+    cpu.get_bus_mut().write_byte(IE, VBLANK);
+    cpu.get_bus_mut().write_byte(IF, VBLANK);
+
+    // The interrupt is dispatched instead of the STOP, the switch waits for it.
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x40);
+    let mmu = cpu.get_bus().as_mmu().unwrap();
+    assert!(!mmu.is_double_speed());
+    assert_eq!(mmu.read_byte(0xff4d), 0x7f);
+}
+
+#[test]
+fn test_ei_followed_by_halt() {
+    let (mut cpu, bus) = new_cpu();
+    cpu.disable_ime();
+    bus.lock().unwrap().load(0x100, &[EI, HALT, NOP]);
+    bus.lock().unwrap().load(0x50, &[RETI]);
+    bus.lock().unwrap().write_byte(IE, TIMER);
+    bus.lock().unwrap().write_byte(IF, TIMER);
+
+    cpu.next();
+    cpu.next();
+    assert!(cpu.is_ime_enabled());
+    assert!(!cpu.is_halt());
+    // The interrupt returns to the HALT.
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x50);
+    assert_eq!(pop_word(&cpu), 0x101);
+    cpu.next();
+    cpu.next();
+    assert!(cpu.is_halt());
+    assert_eq!(cpu.reg.get_PC(), 0x102);
+}
+
+#[test]
+fn test_ei_twice() {
+    let (mut cpu, bus) = new_cpu();
+    cpu.disable_ime();
+    bus.lock().unwrap().load(0x100, &[EI, EI, NOP]);
+    bus.lock().unwrap().write_byte(IE, VBLANK);
+    bus.lock().unwrap().write_byte(IF, VBLANK);
+
+    cpu.next();
+    cpu.next();
+    // The second EI doesn't delay the first one.
+    assert!(cpu.is_ime_enabled());
+    cpu.next();
+    assert_eq!(cpu.reg.get_PC(), 0x40);
+    assert_eq!(pop_word(&cpu), 0x102);
+}
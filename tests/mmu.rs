@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use NGC224::gameboy::cartridge::load_empty_cartridge;
+use NGC224::gameboy::cpu::IntReg;
+use NGC224::gameboy::gpu::GPU;
+use NGC224::gameboy::joypad::Joypad;
+use NGC224::gameboy::mmu::Mmunit;
+use NGC224::gameboy::sound::apu::{Apu, DEFAULT_SAMPLE_RATE};
+use NGC224::gameboy::timer::Timer;
+use NGC224::gameboy::{IOHandler, Term};
+
+fn new_mmu(term: Term) -> Mmunit {
+    let intf = Arc::new(IntReg::new());
+    let gpu = GPU::with_term(intf.clone(), term);
+    Mmunit::new(
+        load_empty_cartridge(),
+        Apu::new(DEFAULT_SAMPLE_RATE),
+        gpu,
+        Joypad::new(intf.clone()),
+        Timer::new(intf.clone()),
+        intf,
+    )
+}
+
+/// Step the GPU until the STAT mode is reached.
+fn run_until_mode(mmu: &mut Mmunit, mode: u8) {
+    while mmu.read_byte(0xff41) & 0x03 != mode {
+        mmu.gpu.borrow_mut().next(4);
+    }
+}
+
+#[test]
+fn test_echo_ram_follows_the_bank() {
+    let mut mmu = new_mmu(Term::GBC);
+    mmu.write_byte(0xc123, 0x11);
+    mmu.write_byte(0xff70, 0x03);
+    mmu.write_byte(0xd123, 0x33);
+    assert_eq!(mmu.read_byte(0xe123), 0x11);
+    assert_eq!(mmu.read_byte(0xf123), 0x33);
+    mmu.write_byte(0xfdff, 0x44);
+    assert_eq!(mmu.read_byte(0xddff), 0x44);
+
+    mmu.write_byte(0xff70, 0x01);
+    assert_eq!(mmu.read_byte(0xf123), 0x00);
+    mmu.write_byte(0xff70, 0x03);
+    assert_eq!(mmu.read_byte(0xf123), 0x33);
+}
+
+#[test]
+fn test_svbk_is_cgb_only() {
+    let mut mmu = new_mmu(Term::GB);
+    mmu.write_byte(0xd000, 0x11);
+    mmu.write_byte(0xff70, 0x03);
+    assert_eq!(mmu.read_byte(0xff70), 0xff);
+    assert_eq!(mmu.read_byte(0xd000), 0x11);
+
+    let mut mmu = new_mmu(Term::GBC);
+    assert_eq!(mmu.read_byte(0xff70), 0xf9);
+    mmu.write_byte(0xff70, 0x03);
+    assert_eq!(mmu.read_byte(0xff70), 0xfb);
+}
+
+#[test]
+fn test_unmapped_io_reads_ff() {
+    let mmu = new_mmu(Term::GBC);
+    let unmapped = [
+        0xff03, 0xff08, 0xff0e, 0xff4c, 0xff4e, 0xff57, 0xff6c, 0xff7f,
+    ];
+    for a in unmapped.iter() {
+        assert_eq!(mmu.read_byte(*a), 0xff, "{:04x}", a);
+    }
+}
+
+#[test]
+fn test_prohibited_area() {
+    let mut mmu = new_mmu(Term::GB);
+    // The LCD is off, the OAM is free.
+    assert_eq!(mmu.read_byte(0xfea0), 0x00);
+    mmu.write_byte(0xfea0, 0x12);
+    assert_eq!(mmu.read_byte(0xfea0), 0x00);
+
+    mmu.write_byte(0xff40, 0x80);
+    run_until_mode(&mut mmu, 3);
+    assert_eq!(mmu.read_byte(0xfeff), 0xff);
+
+    let mut mmu = new_mmu(Term::GBC);
+    assert_eq!(mmu.read_byte(0xfea0), 0xaa);
+    assert_eq!(mmu.read_byte(0xfeb7), 0xbb);
+    assert_eq!(mmu.read_byte(0xfeff), 0xff);
+    mmu.write_byte(0xfec0, 0x12);
+    assert_eq!(mmu.read_byte(0xfec0), 0xcc);
+}
+
+fn fill_oam(mmu: &mut Mmunit) {
+    for i in 0..0xa0 {
+        mmu.write_byte(0xfe00 + i, i as u8);
+    }
+}
+
+fn read_oam_row(mmu: &Mmunit, row: u16) -> Vec<u8> {
+    (0..8)
+        .map(|i| mmu.gpu.borrow().read_byte(0xfe00 + row * 8 + i))
+        .collect()
+}
+
+#[test]
+fn test_oam_corruption() {
+    let mut mmu = new_mmu(Term::GB);
+    fill_oam(&mut mmu);
+    mmu.write_byte(0xff40, 0x80);
+    // The mode 2 is seen 4 dots in, the scan is at the row 2 after 4 more.
+    run_until_mode(&mut mmu, 0);
+    run_until_mode(&mut mmu, 2);
+    mmu.gpu.borrow_mut().next(4);
+    mmu.write_byte(0xfea0, 0x00);
+    // a = 0x1110, b = 0x0908, c = 0x0d0c: ((a ^ c) & (b ^ c)) ^ c
+    let v: u16 = ((0x1110 ^ 0x0d0c) & (0x0908 ^ 0x0d0c)) ^ 0x0d0c;
+    let mut row = v.to_le_bytes().to_vec();
+    row.extend_from_slice(&[0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f]);
+    assert_eq!(read_oam_row(&mmu, 2), row);
+    assert_eq!(read_oam_row(&mmu, 0), (0..8).collect::<Vec<u8>>());
+
+    // The CGB is not affected.
+    let mut mmu = new_mmu(Term::GBC);
+    fill_oam(&mut mmu);
+    mmu.write_byte(0xff40, 0x80);
+    run_until_mode(&mut mmu, 0);
+    run_until_mode(&mut mmu, 2);
+    mmu.gpu.borrow_mut().next(4);
+    assert_eq!(mmu.read_byte(0xfea0), 0xff);
+    assert_eq!(read_oam_row(&mmu, 2), (16..24).collect::<Vec<u8>>());
+}
@@ -1,9 +1,12 @@
 #![allow(non_snake_case)]
 
-use std::cell::RefCell;
+mod common;
+
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
+use common::new_cpu;
+use NGC224::gameboy::cpu::MemoryAccess;
 use NGC224::gameboy::IOHandler;
 use NGC224::gameboy::Register;
 use NGC224::gameboy::CPU;
@@ -46,1671 +49,2886 @@ impl IOHandler for FakeMemory<'_> {
 
 #[test]
 fn test_opcode_0X00() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 176, h: 1, l: 77, pc: 257, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 0);
-    mem.borrow_mut().fake_data(257, 195);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01b0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0101)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 0);
+    mem.lock().unwrap().fake_data(257, 195);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x00();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 176, h: 1, l: 77, pc: 257, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01b0)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0101)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0X01() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 192, l: 0, pc: 8062, sp: 57343 }",
-    );
-    mem.borrow_mut().fake_data(8062, 8192);
-    mem.borrow_mut().fake_data(8064, 54);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0xc000)
+        .with_PC(0x1f7e)
+        .with_SP(0xdfff);
+    mem.lock().unwrap().fake_data(8062, 8192);
+    mem.lock().unwrap().fake_data(8064, 54);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x01();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 160, h: 192, l: 0, pc: 8064, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x2000)
+            .with_DE(0x00d8)
+            .with_HL(0xc000)
+            .with_PC(0x1f80)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0X04() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 1, c: 104, d: 0, e: 0, f: 128, h: 69, l: 56, pc: 6419, sp: 57327 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(6419, 33);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0168)
+        .with_DE(0x0000)
+        .with_HL(0x4538)
+        .with_PC(0x1913)
+        .with_SP(0xdfef);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(6419, 33);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x04();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 2, c: 104, d: 0, e: 0, f: 0, h: 69, l: 56, pc: 6419, sp: 57327 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0000)
+            .with_BC(0x0268)
+            .with_DE(0x0000)
+            .with_HL(0x4538)
+            .with_PC(0x1913)
+            .with_SP(0xdfef)
     );
 }
 #[test]
 fn test_opcode_0X05() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 160, c: 0, d: 0, e: 216, f: 128, h: 195, l: 1, pc: 138, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(138, 32);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0xa000)
+        .with_DE(0x00d8)
+        .with_HL(0xc301)
+        .with_PC(0x008a)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(138, 32);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x05();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 159, c: 0, d: 0, e: 216, f: 96, h: 195, l: 1, pc: 138, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0060)
+            .with_BC(0x9f00)
+            .with_DE(0x00d8)
+            .with_HL(0xc301)
+            .with_PC(0x008a)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X06() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 0, d: 0, e: 216, f: 128, h: 195, l: 0, pc: 135, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(135, 160);
-    mem.borrow_mut().fake_data(136, 34);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0000)
+        .with_DE(0x00d8)
+        .with_HL(0xc300)
+        .with_PC(0x0087)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(135, 160);
+    mem.lock().unwrap().fake_data(136, 34);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x06();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 160, c: 0, d: 0, e: 216, f: 128, h: 195, l: 0, pc: 136, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0xa000)
+            .with_DE(0x00d8)
+            .with_HL(0xc300)
+            .with_PC(0x0088)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X09() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 2, b: 0, c: 0, d: 0, e: 4, f: 192, h: 192, l: 38, pc: 20747, sp: 57323 }",
-    );
-    mem.borrow_mut().fake_data(20747, 126);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x02c0)
+        .with_BC(0x0000)
+        .with_DE(0x0004)
+        .with_HL(0xc026)
+        .with_PC(0x510b)
+        .with_SP(0xdfeb);
+    mem.lock().unwrap().fake_data(20747, 126);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x09();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 2, b: 0, c: 0, d: 0, e: 4, f: 128, h: 192, l: 38, pc: 20747, sp: 57323 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0280)
+            .with_BC(0x0000)
+            .with_DE(0x0004)
+            .with_HL(0xc026)
+            .with_PC(0x510b)
+            .with_SP(0xdfeb)
     );
 }
 #[test]
 fn test_opcode_0X0B() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 160, h: 192, l: 1, pc: 8068, sp: 57343 }",
-    );
-    mem.borrow_mut().fake_data(8068, 120);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x2000)
+        .with_DE(0x00d8)
+        .with_HL(0xc001)
+        .with_PC(0x1f84)
+        .with_SP(0xdfff);
+    mem.lock().unwrap().fake_data(8068, 120);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x0B();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 31, c: 255, d: 0, e: 216, f: 160, h: 192, l: 1, pc: 8068, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x1fff)
+            .with_DE(0x00d8)
+            .with_HL(0xc001)
+            .with_PC(0x1f84)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0X0C() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 62, b: 10, c: 128, d: 0, e: 216, f: 192, h: 75, l: 252, pc: 19447, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(19447, 5);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x3ec0)
+        .with_BC(0x0a80)
+        .with_DE(0x00d8)
+        .with_HL(0x4bfc)
+        .with_PC(0x4bf7)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(19447, 5);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x0C();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 62, b: 10, c: 129, d: 0, e: 216, f: 0, h: 75, l: 252, pc: 19447, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x3e00)
+            .with_BC(0x0a81)
+            .with_DE(0x00d8)
+            .with_HL(0x4bfc)
+            .with_PC(0x4bf7)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X0D() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 9, d: 0, e: 0, f: 192, h: 96, l: 139, pc: 24710, sp: 57335 }",
-    );
-    mem.borrow_mut().fake_data(24710, 32);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00c0)
+        .with_BC(0x0009)
+        .with_DE(0x0000)
+        .with_HL(0x608b)
+        .with_PC(0x6086)
+        .with_SP(0xdff7);
+    mem.lock().unwrap().fake_data(24710, 32);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x0D();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 8, d: 0, e: 0, f: 64, h: 96, l: 139, pc: 24710, sp: 57335 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0040)
+            .with_BC(0x0008)
+            .with_DE(0x0000)
+            .with_HL(0x608b)
+            .with_PC(0x6086)
+            .with_SP(0xdff7)
     );
 }
 #[test]
 fn test_opcode_0X0E() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 0, d: 0, e: 216, f: 192, h: 195, l: 160, pc: 19438, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(19438, 128);
-    mem.borrow_mut().fake_data(19439, 6);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01c0)
+        .with_BC(0x0000)
+        .with_DE(0x00d8)
+        .with_HL(0xc3a0)
+        .with_PC(0x4bee)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(19438, 128);
+    mem.lock().unwrap().fake_data(19439, 6);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x0E();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 128, d: 0, e: 216, f: 192, h: 195, l: 160, pc: 19439, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01c0)
+            .with_BC(0x0080)
+            .with_DE(0x00d8)
+            .with_HL(0xc3a0)
+            .with_PC(0x4bef)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X11() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 127, b: 0, c: 138, d: 0, e: 216, f: 128, h: 152, l: 5, pc: 7414, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(7414, 1024);
-    mem.borrow_mut().fake_data(7416, 107);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x7f80)
+        .with_BC(0x008a)
+        .with_DE(0x00d8)
+        .with_HL(0x9805)
+        .with_PC(0x1cf6)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(7414, 1024);
+    mem.lock().unwrap().fake_data(7416, 107);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x11();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 127, b: 0, c: 138, d: 4, e: 0, f: 128, h: 152, l: 5, pc: 7416, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x7f80)
+            .with_BC(0x008a)
+            .with_DE(0x0400)
+            .with_HL(0x9805)
+            .with_PC(0x1cf8)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X12() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 128, c: 16, d: 136, e: 0, f: 32, h: 111, l: 233, pc: 24974, sp: 57331 }",
-    );
-    mem.borrow_mut().fake_data(24974, 19);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0020)
+        .with_BC(0x8010)
+        .with_DE(0x8800)
+        .with_HL(0x6fe9)
+        .with_PC(0x618e)
+        .with_SP(0xdff3);
+    mem.lock().unwrap().fake_data(24974, 19);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x12();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 128, c: 16, d: 136, e: 0, f: 32, h: 111, l: 233, pc: 24974, sp: 57331 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0020)
+            .with_BC(0x8010)
+            .with_DE(0x8800)
+            .with_HL(0x6fe9)
+            .with_PC(0x618e)
+            .with_SP(0xdff3)
     );
 }
 #[test]
 fn test_opcode_0X13() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 28, b: 0, c: 138, d: 127, e: 57, f: 0, h: 127, l: 57, pc: 32371, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32371, 26);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x1c00)
+        .with_BC(0x008a)
+        .with_DE(0x7f39)
+        .with_HL(0x7f39)
+        .with_PC(0x7e73)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32371, 26);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x13();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 28, b: 0, c: 138, d: 127, e: 58, f: 0, h: 127, l: 57, pc: 32371, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x1c00)
+            .with_BC(0x008a)
+            .with_DE(0x7f3a)
+            .with_HL(0x7f39)
+            .with_PC(0x7e73)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X15() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 127, b: 0, c: 138, d: 4, e: 0, f: 192, h: 153, l: 0, pc: 7422, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(7422, 32);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x7fc0)
+        .with_BC(0x008a)
+        .with_DE(0x0400)
+        .with_HL(0x9900)
+        .with_PC(0x1cfe)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(7422, 32);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x15();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 127, b: 0, c: 138, d: 3, e: 0, f: 64, h: 153, l: 0, pc: 7422, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x7f40)
+            .with_BC(0x008a)
+            .with_DE(0x0300)
+            .with_HL(0x9900)
+            .with_PC(0x1cfe)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X16() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 255, c: 138, d: 0, e: 0, f: 128, h: 160, l: 0, pc: 23148, sp: 57333 }",
-    );
-    mem.borrow_mut().fake_data(23148, 160);
-    mem.borrow_mut().fake_data(23149, 33);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0xff8a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x5a6c)
+        .with_SP(0xdff5);
+    mem.lock().unwrap().fake_data(23148, 160);
+    mem.lock().unwrap().fake_data(23149, 33);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x16();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 255, c: 138, d: 160, e: 0, f: 128, h: 160, l: 0, pc: 23149, sp: 57333 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0xff8a)
+            .with_DE(0xa000)
+            .with_HL(0xa000)
+            .with_PC(0x5a6d)
+            .with_SP(0xdff5)
     );
 }
 #[test]
 fn test_opcode_0X18() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 342, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(342, 2);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 0);
-    mem.borrow_mut().fake_data(345, 234);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0156)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(342, 2);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 0);
+    mem.lock().unwrap().fake_data(345, 234);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x18();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 345, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0159)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0X19() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 192, b: 0, c: 138, d: 0, e: 192, f: 0, h: 126, l: 121, pc: 32364, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32364, 84);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xc000)
+        .with_BC(0x008a)
+        .with_DE(0x00c0)
+        .with_HL(0x7e79)
+        .with_PC(0x7e6c)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32364, 84);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x19();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 192, b: 0, c: 138, d: 0, e: 192, f: 0, h: 127, l: 57, pc: 32364, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xc000)
+            .with_BC(0x008a)
+            .with_DE(0x00c0)
+            .with_HL(0x7f39)
+            .with_PC(0x7e6c)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X1A() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 192, b: 0, c: 138, d: 127, e: 57, f: 0, h: 127, l: 57, pc: 32367, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(32569, 28);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32367, 234);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xc000)
+        .with_BC(0x008a)
+        .with_DE(0x7f39)
+        .with_HL(0x7f39)
+        .with_PC(0x7e6f)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(32569, 28);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32367, 234);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x1A();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 28, b: 0, c: 138, d: 127, e: 57, f: 0, h: 127, l: 57, pc: 32367, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x1c00)
+            .with_BC(0x008a)
+            .with_DE(0x7f39)
+            .with_HL(0x7f39)
+            .with_PC(0x7e6f)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X1B() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 138, d: 27, e: 88, f: 128, h: 101, l: 8, pc: 24913, sp: 57329 }",
-    );
-    mem.borrow_mut().fake_data(24913, 122);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x008a)
+        .with_DE(0x1b58)
+        .with_HL(0x6508)
+        .with_PC(0x6151)
+        .with_SP(0xdff1);
+    mem.lock().unwrap().fake_data(24913, 122);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x1B();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 138, d: 27, e: 87, f: 128, h: 101, l: 8, pc: 24913, sp: 57329 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x008a)
+            .with_DE(0x1b57)
+            .with_HL(0x6508)
+            .with_PC(0x6151)
+            .with_SP(0xdff1)
     );
 }
 #[test]
 fn test_opcode_0X1D() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 127, b: 0, c: 138, d: 4, e: 0, f: 128, h: 152, l: 1, pc: 7419, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(7419, 32);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x7f80)
+        .with_BC(0x008a)
+        .with_DE(0x0400)
+        .with_HL(0x9801)
+        .with_PC(0x1cfb)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(7419, 32);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x1D();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 127, b: 0, c: 138, d: 4, e: 255, f: 96, h: 152, l: 1, pc: 7419, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x7f60)
+            .with_BC(0x008a)
+            .with_DE(0x04ff)
+            .with_HL(0x9801)
+            .with_PC(0x1cfb)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X1E() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 48, b: 16, c: 138, d: 62, e: 141, f: 128, h: 100, l: 248, pc: 24575, sp: 57331 }",
-    );
-    mem.borrow_mut().fake_data(24575, 8);
-    mem.borrow_mut().fake_data(24576, 42);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x3080)
+        .with_BC(0x108a)
+        .with_DE(0x3e8d)
+        .with_HL(0x64f8)
+        .with_PC(0x5fff)
+        .with_SP(0xdff3);
+    mem.lock().unwrap().fake_data(24575, 8);
+    mem.lock().unwrap().fake_data(24576, 42);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x1E();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 48, b: 16, c: 138, d: 62, e: 8, f: 128, h: 100, l: 248, pc: 24576, sp: 57331 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x3080)
+            .with_BC(0x108a)
+            .with_DE(0x3e08)
+            .with_HL(0x64f8)
+            .with_PC(0x6000)
+            .with_SP(0xdff3)
     );
 }
 #[test]
 fn test_opcode_0X20() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 112, h: 1, l: 77, pc: 112, sp: 65532 }",
-    );
-    mem.borrow_mut().fake_data(112, 250);
-    mem.borrow_mut().fake_data(107, 240);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0070)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0070)
+        .with_SP(0xfffc);
+    mem.lock().unwrap().fake_data(112, 250);
+    mem.lock().unwrap().fake_data(107, 240);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x20();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 112, h: 1, l: 77, pc: 107, sp: 65532 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0070)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x006b)
+            .with_SP(0xfffc)
     );
 }
 #[test]
 fn test_opcode_0X21() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 1, l: 77, pc: 8059, sp: 57343 }",
-    );
-    mem.borrow_mut().fake_data(8059, 49152);
-    mem.borrow_mut().fake_data(8061, 1);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x1f7b)
+        .with_SP(0xdfff);
+    mem.lock().unwrap().fake_data(8059, 49152);
+    mem.lock().unwrap().fake_data(8061, 1);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x21();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 192, l: 0, pc: 8061, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0xc000)
+            .with_PC(0x1f7d)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0X22() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 128, h: 128, l: 0, pc: 14052, sp: 57339 }",
-    );
-    mem.borrow_mut().fake_data(14052, 11);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x2000)
+        .with_DE(0x00d8)
+        .with_HL(0x8000)
+        .with_PC(0x36e4)
+        .with_SP(0xdffb);
+    mem.lock().unwrap().fake_data(14052, 11);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x22();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 128, h: 128, l: 1, pc: 14052, sp: 57339 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x2000)
+            .with_DE(0x00d8)
+            .with_HL(0x8001)
+            .with_PC(0x36e4)
+            .with_SP(0xdffb)
     );
 }
 #[test]
 fn test_opcode_0X23() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 160, h: 192, l: 0, pc: 8067, sp: 57343 }",
-    );
-    mem.borrow_mut().fake_data(8067, 11);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x2000)
+        .with_DE(0x00d8)
+        .with_HL(0xc000)
+        .with_PC(0x1f83)
+        .with_SP(0xdfff);
+    mem.lock().unwrap().fake_data(8067, 11);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x23();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 160, h: 192, l: 1, pc: 8067, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x2000)
+            .with_DE(0x00d8)
+            .with_HL(0xc001)
+            .with_PC(0x1f83)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0X24() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 5, c: 0, d: 0, e: 0, f: 176, h: 156, l: 0, pc: 7638, sp: 50240 }",
-    );
-    mem.borrow_mut().fake_data(7638, 5);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00b0)
+        .with_BC(0x0500)
+        .with_DE(0x0000)
+        .with_HL(0x9c00)
+        .with_PC(0x1dd6)
+        .with_SP(0xc440);
+    mem.lock().unwrap().fake_data(7638, 5);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x24();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 5, c: 0, d: 0, e: 0, f: 16, h: 157, l: 0, pc: 7638, sp: 50240 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0010)
+            .with_BC(0x0500)
+            .with_DE(0x0000)
+            .with_HL(0x9d00)
+            .with_PC(0x1dd6)
+            .with_SP(0xc440)
     );
 }
 #[test]
 fn test_opcode_0X26() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 255, b: 0, c: 138, d: 0, e: 216, f: 128, h: 76, l: 5, pc: 8127, sp: 57343 }",
-    );
-    mem.borrow_mut().fake_data(8127, 152);
-    mem.borrow_mut().fake_data(8128, 205);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xff80)
+        .with_BC(0x008a)
+        .with_DE(0x00d8)
+        .with_HL(0x4c05)
+        .with_PC(0x1fbf)
+        .with_SP(0xdfff);
+    mem.lock().unwrap().fake_data(8127, 152);
+    mem.lock().unwrap().fake_data(8128, 205);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x26();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 255, b: 0, c: 138, d: 0, e: 216, f: 128, h: 152, l: 5, pc: 8128, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xff80)
+            .with_BC(0x008a)
+            .with_DE(0x00d8)
+            .with_HL(0x9805)
+            .with_PC(0x1fc0)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0X28() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 80, h: 1, l: 77, pc: 339, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(339, 3);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 0);
-    mem.borrow_mut().fake_data(340, 175);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0150)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0153)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(339, 3);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 0);
+    mem.lock().unwrap().fake_data(340, 175);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x28();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 80, h: 1, l: 77, pc: 340, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0150)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0154)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0X29() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 12, b: 12, c: 0, d: 62, e: 141, f: 80, h: 0, l: 12, pc: 24051, sp: 57329 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(24051, 17);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0c50)
+        .with_BC(0x0c00)
+        .with_DE(0x3e8d)
+        .with_HL(0x000c)
+        .with_PC(0x5df3)
+        .with_SP(0xdff1);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(24051, 17);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x29();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 12, b: 12, c: 0, d: 62, e: 141, f: 0, h: 0, l: 24, pc: 24051, sp: 57329 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0c00)
+            .with_BC(0x0c00)
+            .with_DE(0x3e8d)
+            .with_HL(0x0018)
+            .with_PC(0x5df3)
+            .with_SP(0xdff1)
     );
 }
 #[test]
 fn test_opcode_0X2A() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 10, c: 128, d: 0, e: 216, f: 192, h: 75, l: 251, pc: 19445, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(19451, 62);
-    mem.borrow_mut().fake_data(19445, 226);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01c0)
+        .with_BC(0x0a80)
+        .with_DE(0x00d8)
+        .with_HL(0x4bfb)
+        .with_PC(0x4bf5)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(19451, 62);
+    mem.lock().unwrap().fake_data(19445, 226);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x2A();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 62, b: 10, c: 128, d: 0, e: 216, f: 192, h: 75, l: 252, pc: 19445, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x3ec0)
+            .with_BC(0x0a80)
+            .with_DE(0x00d8)
+            .with_HL(0x4bfc)
+            .with_PC(0x4bf5)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X2C() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 6, c: 0, d: 0, e: 0, f: 160, h: 156, l: 0, pc: 7585, sp: 50082 }",
-    );
-    mem.borrow_mut().fake_data(7585, 114);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01a0)
+        .with_BC(0x0600)
+        .with_DE(0x0000)
+        .with_HL(0x9c00)
+        .with_PC(0x1da1)
+        .with_SP(0xc3a2);
+    mem.lock().unwrap().fake_data(7585, 114);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x2C();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 6, c: 0, d: 0, e: 0, f: 0, h: 156, l: 1, pc: 7585, sp: 50082 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0100)
+            .with_BC(0x0600)
+            .with_DE(0x0000)
+            .with_HL(0x9c01)
+            .with_PC(0x1da1)
+            .with_SP(0xc3a2)
     );
 }
 #[test]
 fn test_opcode_0X2F() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 47, b: 2, c: 0, d: 25, e: 108, f: 160, h: 77, l: 238, pc: 370, sp: 57315 }",
-    );
-    mem.borrow_mut().fake_data(370, 230);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x2fa0)
+        .with_BC(0x0200)
+        .with_DE(0x196c)
+        .with_HL(0x4dee)
+        .with_PC(0x0172)
+        .with_SP(0xdfe3);
+    mem.lock().unwrap().fake_data(370, 230);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x2F();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 208, b: 2, c: 0, d: 25, e: 108, f: 224, h: 77, l: 238, pc: 370, sp: 57315 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xd0e0)
+            .with_BC(0x0200)
+            .with_DE(0x196c)
+            .with_HL(0x4dee)
+            .with_PC(0x0172)
+            .with_SP(0xdfe3)
     );
 }
 #[test]
 fn test_opcode_0X30() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 192, b: 0, c: 138, d: 0, e: 192, f: 0, h: 126, l: 121, pc: 32361, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(32361, 1);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32363, 25);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xc000)
+        .with_BC(0x008a)
+        .with_DE(0x00c0)
+        .with_HL(0x7e79)
+        .with_PC(0x7e69)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(32361, 1);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32363, 25);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x30();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 192, b: 0, c: 138, d: 0, e: 192, f: 0, h: 126, l: 121, pc: 32363, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xc000)
+            .with_BC(0x008a)
+            .with_DE(0x00c0)
+            .with_HL(0x7e79)
+            .with_PC(0x7e6b)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X31() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 1, l: 77, pc: 8056, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(8056, 57343);
-    mem.borrow_mut().fake_data(8058, 33);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x1f78)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(8056, 57343);
+    mem.lock().unwrap().fake_data(8058, 33);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x31();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 1, l: 77, pc: 8058, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x1f7a)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0X36() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 160, h: 192, l: 0, pc: 8065, sp: 57343 }",
-    );
-    mem.borrow_mut().fake_data(8065, 0);
-    mem.borrow_mut().fake_data(8066, 35);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x2000)
+        .with_DE(0x00d8)
+        .with_HL(0xc000)
+        .with_PC(0x1f81)
+        .with_SP(0xdfff);
+    mem.lock().unwrap().fake_data(8065, 0);
+    mem.lock().unwrap().fake_data(8066, 35);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x36();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 160, h: 192, l: 0, pc: 8066, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x2000)
+            .with_DE(0x00d8)
+            .with_HL(0xc000)
+            .with_PC(0x1f82)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0X37() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 128, h: 100, l: 248, pc: 24833, sp: 57335 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(24833, 201);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0x64f8)
+        .with_PC(0x6101)
+        .with_SP(0xdff7);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(24833, 201);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x37();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 144, h: 100, l: 248, pc: 24833, sp: 57335 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0090)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0x64f8)
+            .with_PC(0x6101)
+            .with_SP(0xdff7)
     );
 }
 #[test]
 fn test_opcode_0X3C() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 128, b: 20, c: 13, d: 0, e: 12, f: 192, h: 152, l: 1, pc: 24886, sp: 57333 }",
-    );
-    mem.borrow_mut().fake_data(24886, 5);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x80c0)
+        .with_BC(0x140d)
+        .with_DE(0x000c)
+        .with_HL(0x9801)
+        .with_PC(0x6136)
+        .with_SP(0xdff5);
+    mem.lock().unwrap().fake_data(24886, 5);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x3C();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 129, b: 20, c: 13, d: 0, e: 12, f: 0, h: 152, l: 1, pc: 24886, sp: 57333 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x8100)
+            .with_BC(0x140d)
+            .with_DE(0x000c)
+            .with_HL(0x9801)
+            .with_PC(0x6136)
+            .with_SP(0xdff5)
     );
 }
 #[test]
 fn test_opcode_0X3D() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 128, h: 160, l: 0, pc: 8225, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(8225, 195);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x2021)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(8225, 195);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x3D();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 255, b: 0, c: 138, d: 0, e: 0, f: 96, h: 160, l: 0, pc: 8225, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xff60)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x2021)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X3E() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 8049, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(8049, 128);
-    mem.borrow_mut().fake_data(8050, 224);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x1f71)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(8049, 128);
+    mem.lock().unwrap().fake_data(8050, 224);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x3E();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 128, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 8050, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x8080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x1f72)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0X42() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 255, c: 138, d: 160, e: 0, f: 128, h: 192, l: 6, pc: 23178, sp: 57331 }",
-    );
-    mem.borrow_mut().fake_data(23178, 34);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0xff8a)
+        .with_DE(0xa000)
+        .with_HL(0xc006)
+        .with_PC(0x5a8a)
+        .with_SP(0xdff3);
+    mem.lock().unwrap().fake_data(23178, 34);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x42();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 160, c: 138, d: 160, e: 0, f: 128, h: 192, l: 6, pc: 23178, sp: 57331 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0xa08a)
+            .with_DE(0xa000)
+            .with_HL(0xc006)
+            .with_PC(0x5a8a)
+            .with_SP(0xdff3)
     );
 }
 #[test]
 fn test_opcode_0X44() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 80, b: 0, c: 40, d: 69, e: 135, f: 192, h: 196, l: 143, pc: 6492, sp: 57327 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(6492, 77);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x50c0)
+        .with_BC(0x0028)
+        .with_DE(0x4587)
+        .with_HL(0xc48f)
+        .with_PC(0x195c)
+        .with_SP(0xdfef);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(6492, 77);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x44();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 80, b: 196, c: 40, d: 69, e: 135, f: 192, h: 196, l: 143, pc: 6492, sp: 57327 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x50c0)
+            .with_BC(0xc428)
+            .with_DE(0x4587)
+            .with_HL(0xc48f)
+            .with_PC(0x195c)
+            .with_SP(0xdfef)
     );
 }
 #[test]
 fn test_opcode_0X47() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 103, sp: 65532 }",
-    );
-    mem.borrow_mut().fake_data(103, 203);
-    mem.borrow_mut().fake_data(104, 135);
-    mem.borrow_mut().fake_data(105, 224);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0067)
+        .with_SP(0xfffc);
+    mem.lock().unwrap().fake_data(103, 203);
+    mem.lock().unwrap().fake_data(104, 135);
+    mem.lock().unwrap().fake_data(105, 224);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x47();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 103, sp: 65532 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0067)
+            .with_SP(0xfffc)
     );
 }
 #[test]
 fn test_opcode_0X4D() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 80, b: 196, c: 40, d: 69, e: 135, f: 192, h: 196, l: 143, pc: 6493, sp: 57327 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(6493, 225);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x50c0)
+        .with_BC(0xc428)
+        .with_DE(0x4587)
+        .with_HL(0xc48f)
+        .with_PC(0x195d)
+        .with_SP(0xdfef);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(6493, 225);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x4D();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 80, b: 196, c: 143, d: 69, e: 135, f: 192, h: 196, l: 143, pc: 6493, sp: 57327 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x50c0)
+            .with_BC(0xc48f)
+            .with_DE(0x4587)
+            .with_HL(0xc48f)
+            .with_PC(0x195d)
+            .with_SP(0xdfef)
     );
 }
 #[test]
 fn test_opcode_0X4F() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 12, c: 0, d: 62, e: 141, f: 0, h: 86, l: 130, pc: 16044, sp: 57327 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(16044, 201);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0000)
+        .with_BC(0x0c00)
+        .with_DE(0x3e8d)
+        .with_HL(0x5682)
+        .with_PC(0x3eac)
+        .with_SP(0xdfef);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(16044, 201);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x4F();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 12, c: 0, d: 62, e: 141, f: 0, h: 86, l: 130, pc: 16044, sp: 57327 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0000)
+            .with_BC(0x0c00)
+            .with_DE(0x3e8d)
+            .with_HL(0x5682)
+            .with_PC(0x3eac)
+            .with_SP(0xdfef)
     );
 }
 #[test]
 fn test_opcode_0X54() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 192, b: 0, c: 138, d: 0, e: 192, f: 0, h: 127, l: 57, pc: 32365, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32365, 93);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xc000)
+        .with_BC(0x008a)
+        .with_DE(0x00c0)
+        .with_HL(0x7f39)
+        .with_PC(0x7e6d)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32365, 93);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x54();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 192, b: 0, c: 138, d: 127, e: 192, f: 0, h: 127, l: 57, pc: 32365, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xc000)
+            .with_BC(0x008a)
+            .with_DE(0x7fc0)
+            .with_HL(0x7f39)
+            .with_PC(0x7e6d)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X57() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 128, h: 128, l: 0, pc: 14050, sp: 57339 }",
-    );
-    mem.borrow_mut().fake_data(14050, 122);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x2000)
+        .with_DE(0x00d8)
+        .with_HL(0x8000)
+        .with_PC(0x36e2)
+        .with_SP(0xdffb);
+    mem.lock().unwrap().fake_data(14050, 122);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x57();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 128, h: 128, l: 0, pc: 14050, sp: 57339 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x2000)
+            .with_DE(0x00d8)
+            .with_HL(0x8000)
+            .with_PC(0x36e2)
+            .with_SP(0xdffb)
     );
 }
 #[test]
 fn test_opcode_0X5D() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 192, b: 0, c: 138, d: 127, e: 192, f: 0, h: 127, l: 57, pc: 32366, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32366, 26);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xc000)
+        .with_BC(0x008a)
+        .with_DE(0x7fc0)
+        .with_HL(0x7f39)
+        .with_PC(0x7e6e)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32366, 26);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x5D();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 192, b: 0, c: 138, d: 127, e: 57, f: 0, h: 127, l: 57, pc: 32366, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xc000)
+            .with_BC(0x008a)
+            .with_DE(0x7f39)
+            .with_HL(0x7f39)
+            .with_PC(0x7e6e)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X5F() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 64, b: 0, c: 138, d: 0, e: 0, f: 192, h: 126, l: 121, pc: 32357, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32357, 135);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x40c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0x7e79)
+        .with_PC(0x7e65)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32357, 135);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x5F();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 64, b: 0, c: 138, d: 0, e: 64, f: 192, h: 126, l: 121, pc: 32357, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x40c0)
+            .with_BC(0x008a)
+            .with_DE(0x0040)
+            .with_HL(0x7e79)
+            .with_PC(0x7e65)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X66() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 40, b: 0, c: 9, d: 0, e: 0, f: 160, h: 96, l: 138, pc: 24702, sp: 57331 }",
-    );
-    mem.borrow_mut().fake_data(24714, 101);
-    mem.borrow_mut().fake_data(24702, 111);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x28a0)
+        .with_BC(0x0009)
+        .with_DE(0x0000)
+        .with_HL(0x608a)
+        .with_PC(0x607e)
+        .with_SP(0xdff3);
+    mem.lock().unwrap().fake_data(24714, 101);
+    mem.lock().unwrap().fake_data(24702, 111);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x66();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 40, b: 0, c: 9, d: 0, e: 0, f: 160, h: 101, l: 138, pc: 24702, sp: 57331 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x28a0)
+            .with_BC(0x0009)
+            .with_DE(0x0000)
+            .with_HL(0x658a)
+            .with_PC(0x607e)
+            .with_SP(0xdff3)
     );
 }
 #[test]
 fn test_opcode_0X67() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 96, b: 0, c: 138, d: 127, e: 59, f: 0, h: 127, l: 43, pc: 32376, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32376, 201);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x6000)
+        .with_BC(0x008a)
+        .with_DE(0x7f3b)
+        .with_HL(0x7f2b)
+        .with_PC(0x7e78)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32376, 201);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x67();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 96, b: 0, c: 138, d: 127, e: 59, f: 0, h: 96, l: 43, pc: 32376, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x6000)
+            .with_BC(0x008a)
+            .with_DE(0x7f3b)
+            .with_HL(0x602b)
+            .with_PC(0x7e78)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X6B() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 127, b: 0, c: 138, d: 4, e: 0, f: 128, h: 152, l: 5, pc: 7417, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(7417, 34);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x7f80)
+        .with_BC(0x008a)
+        .with_DE(0x0400)
+        .with_HL(0x9805)
+        .with_PC(0x1cf9)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(7417, 34);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x6B();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 127, b: 0, c: 138, d: 4, e: 0, f: 128, h: 152, l: 0, pc: 7417, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x7f80)
+            .with_BC(0x008a)
+            .with_DE(0x0400)
+            .with_HL(0x9800)
+            .with_PC(0x1cf9)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0X6F() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 43, b: 0, c: 138, d: 127, e: 58, f: 0, h: 127, l: 57, pc: 32373, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32373, 19);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x2b00)
+        .with_BC(0x008a)
+        .with_DE(0x7f3a)
+        .with_HL(0x7f39)
+        .with_PC(0x7e75)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32373, 19);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x6F();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 43, b: 0, c: 138, d: 127, e: 58, f: 0, h: 127, l: 43, pc: 32373, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x2b00)
+            .with_BC(0x008a)
+            .with_DE(0x7f3a)
+            .with_HL(0x7f2b)
+            .with_PC(0x7e75)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X71() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 192, h: 204, l: 84, pc: 32347, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32347, 33);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xcc54)
+        .with_PC(0x7e5b)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32347, 33);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x71();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 192, h: 204, l: 84, pc: 32347, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xcc54)
+            .with_PC(0x7e5b)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X72() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 6, c: 0, d: 0, e: 0, f: 0, h: 156, l: 1, pc: 7586, sp: 50082 }",
-    );
-    mem.borrow_mut().fake_data(7586, 44);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0100)
+        .with_BC(0x0600)
+        .with_DE(0x0000)
+        .with_HL(0x9c01)
+        .with_PC(0x1da2)
+        .with_SP(0xc3a2);
+    mem.lock().unwrap().fake_data(7586, 44);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x72();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 6, c: 0, d: 0, e: 0, f: 0, h: 156, l: 1, pc: 7586, sp: 50082 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0100)
+            .with_BC(0x0600)
+            .with_DE(0x0000)
+            .with_HL(0x9c01)
+            .with_PC(0x1da2)
+            .with_SP(0xc3a2)
     );
 }
 #[test]
 fn test_opcode_0X73() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 6, c: 0, d: 0, e: 0, f: 160, h: 156, l: 0, pc: 7584, sp: 50082 }",
-    );
-    mem.borrow_mut().fake_data(7584, 44);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01a0)
+        .with_BC(0x0600)
+        .with_DE(0x0000)
+        .with_HL(0x9c00)
+        .with_PC(0x1da0)
+        .with_SP(0xc3a2);
+    mem.lock().unwrap().fake_data(7584, 44);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x73();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 6, c: 0, d: 0, e: 0, f: 160, h: 156, l: 0, pc: 7584, sp: 50082 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01a0)
+            .with_BC(0x0600)
+            .with_DE(0x0000)
+            .with_HL(0x9c00)
+            .with_PC(0x1da0)
+            .with_SP(0xc3a2)
     );
 }
 #[test]
 fn test_opcode_0X76() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 3, d: 0, e: 0, f: 192, h: 197, l: 8, pc: 8372, sp: 57325 }",
-    );
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01c0)
+        .with_BC(0x0003)
+        .with_DE(0x0000)
+        .with_HL(0xc508)
+        .with_PC(0x20b4)
+        .with_SP(0xdfed);
 
-    let mut cpu = CPU::new(mem, false);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x76();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 3, d: 0, e: 0, f: 192, h: 197, l: 8, pc: 8372, sp: 57325 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01c0)
+            .with_BC(0x0003)
+            .with_DE(0x0000)
+            .with_HL(0xc508)
+            .with_PC(0x20b4)
+            .with_SP(0xdfed)
     );
     assert_eq!(cpu.is_halt(), true);
 }
 #[test]
 fn test_opcode_0X77() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 160, b: 40, c: 138, d: 0, e: 4, f: 192, h: 195, l: 0, pc: 152, sp: 57323 }",
-    );
-    mem.borrow_mut().fake_data(152, 25);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xa0c0)
+        .with_BC(0x288a)
+        .with_DE(0x0004)
+        .with_HL(0xc300)
+        .with_PC(0x0098)
+        .with_SP(0xdfeb);
+    mem.lock().unwrap().fake_data(152, 25);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x77();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 160, b: 40, c: 138, d: 0, e: 4, f: 192, h: 195, l: 0, pc: 152, sp: 57323 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xa0c0)
+            .with_BC(0x288a)
+            .with_DE(0x0004)
+            .with_HL(0xc300)
+            .with_PC(0x0098)
+            .with_SP(0xdfeb)
     );
 }
 #[test]
 fn test_opcode_0X78() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 1, l: 77, pc: 120, sp: 65532 }",
-    );
-    mem.borrow_mut().fake_data(120, 224);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0078)
+        .with_SP(0xfffc);
+    mem.lock().unwrap().fake_data(120, 224);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x78();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 1, l: 77, pc: 120, sp: 65532 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0078)
+            .with_SP(0xfffc)
     );
 }
 #[test]
 fn test_opcode_0X79() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 0, d: 0, e: 4, f: 160, h: 192, l: 38, pc: 20785, sp: 57323 }",
-    );
-    mem.borrow_mut().fake_data(20786, 12);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x0000)
+        .with_DE(0x0004)
+        .with_HL(0xc026)
+        .with_PC(0x5131)
+        .with_SP(0xdfeb);
+    mem.lock().unwrap().fake_data(20786, 12);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x79();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 0, d: 0, e: 4, f: 160, h: 192, l: 38, pc: 20785, sp: 57323 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x0000)
+            .with_DE(0x0004)
+            .with_HL(0xc026)
+            .with_PC(0x5131)
+            .with_SP(0xdfeb)
     );
 }
 #[test]
 fn test_opcode_0X7A() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 128, h: 128, l: 0, pc: 14051, sp: 57339 }",
-    );
-    mem.borrow_mut().fake_data(14051, 34);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x2000)
+        .with_DE(0x00d8)
+        .with_HL(0x8000)
+        .with_PC(0x36e3)
+        .with_SP(0xdffb);
+    mem.lock().unwrap().fake_data(14051, 34);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x7A();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 128, h: 128, l: 0, pc: 14051, sp: 57339 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x2000)
+            .with_DE(0x00d8)
+            .with_HL(0x8000)
+            .with_PC(0x36e3)
+            .with_SP(0xdffb)
     );
 }
 #[test]
 fn test_opcode_0X7B() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 192, h: 204, l: 82, pc: 32343, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32343, 34);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xcc52)
+        .with_PC(0x7e57)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32343, 34);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x7B();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 192, h: 204, l: 82, pc: 32343, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xcc52)
+            .with_PC(0x7e57)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X7C() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 19, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 32330, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32330, 234);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x13c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x7e4a)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32330, 234);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x7C();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 160, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 32330, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xa0c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x7e4a)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X7D() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 160, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 32334, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32334, 234);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xa0c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x7e4e)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32334, 234);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x7D();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 32334, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x7e4e)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X7E() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 138, d: 62, e: 141, f: 128, h: 100, l: 248, pc: 24556, sp: 57333 }",
-    );
-    mem.borrow_mut().fake_data(25848, 137);
-    mem.borrow_mut().fake_data(24556, 230);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x008a)
+        .with_DE(0x3e8d)
+        .with_HL(0x64f8)
+        .with_PC(0x5fec)
+        .with_SP(0xdff5);
+    mem.lock().unwrap().fake_data(25848, 137);
+    mem.lock().unwrap().fake_data(24556, 230);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x7E();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 137, b: 0, c: 138, d: 62, e: 141, f: 128, h: 100, l: 248, pc: 24556, sp: 57333 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x8980)
+            .with_BC(0x008a)
+            .with_DE(0x3e8d)
+            .with_HL(0x64f8)
+            .with_PC(0x5fec)
+            .with_SP(0xdff5)
     );
 }
 #[test]
 fn test_opcode_0X83() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 128, b: 0, c: 138, d: 0, e: 64, f: 0, h: 126, l: 121, pc: 32359, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32359, 95);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x8000)
+        .with_BC(0x008a)
+        .with_DE(0x0040)
+        .with_HL(0x7e79)
+        .with_PC(0x7e67)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32359, 95);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x83();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 192, b: 0, c: 138, d: 0, e: 64, f: 0, h: 126, l: 121, pc: 32359, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xc000)
+            .with_BC(0x008a)
+            .with_DE(0x0040)
+            .with_HL(0x7e79)
+            .with_PC(0x7e67)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X85() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 13, b: 6, c: 0, d: 0, e: 0, f: 0, h: 156, l: 19, pc: 7634, sp: 50100 }",
-    );
-    mem.borrow_mut().fake_data(7634, 111);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0d00)
+        .with_BC(0x0600)
+        .with_DE(0x0000)
+        .with_HL(0x9c13)
+        .with_PC(0x1dd2)
+        .with_SP(0xc3b4);
+    mem.lock().unwrap().fake_data(7634, 111);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x85();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 32, b: 6, c: 0, d: 0, e: 0, f: 32, h: 156, l: 19, pc: 7634, sp: 50100 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x2020)
+            .with_BC(0x0600)
+            .with_DE(0x0000)
+            .with_HL(0x9c13)
+            .with_PC(0x1dd2)
+            .with_SP(0xc3b4)
     );
 }
 #[test]
 fn test_opcode_0X87() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 64, b: 0, c: 138, d: 0, e: 64, f: 192, h: 126, l: 121, pc: 32358, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(32358, 131);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x40c0)
+        .with_BC(0x008a)
+        .with_DE(0x0040)
+        .with_HL(0x7e79)
+        .with_PC(0x7e66)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(32358, 131);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x87();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 128, b: 0, c: 138, d: 0, e: 64, f: 0, h: 126, l: 121, pc: 32358, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x8000)
+            .with_BC(0x008a)
+            .with_DE(0x0040)
+            .with_HL(0x7e79)
+            .with_PC(0x7e66)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0X88() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 130, c: 228, d: 0, e: 4, f: 192, h: 122, l: 143, pc: 31381, sp: 57311 }",
-    );
-    mem.borrow_mut().fake_data(31381, 224);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00c0)
+        .with_BC(0x82e4)
+        .with_DE(0x0004)
+        .with_HL(0x7a8f)
+        .with_PC(0x7a95)
+        .with_SP(0xdfdf);
+    mem.lock().unwrap().fake_data(31381, 224);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x88();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 130, b: 130, c: 228, d: 0, e: 4, f: 0, h: 122, l: 143, pc: 31381, sp: 57311 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x8200)
+            .with_BC(0x82e4)
+            .with_DE(0x0004)
+            .with_HL(0x7a8f)
+            .with_PC(0x7a95)
+            .with_SP(0xdfdf)
     );
 }
 #[test]
 fn test_opcode_0X98() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 130, c: 228, d: 0, e: 4, f: 0, h: 122, l: 143, pc: 31389, sp: 57311 }",
-    );
-    mem.borrow_mut().fake_data(31389, 224);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0000)
+        .with_BC(0x82e4)
+        .with_DE(0x0004)
+        .with_HL(0x7a8f)
+        .with_PC(0x7a9d)
+        .with_SP(0xdfdf);
+    mem.lock().unwrap().fake_data(31389, 224);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0x98();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 126, b: 130, c: 228, d: 0, e: 4, f: 112, h: 122, l: 143, pc: 31389, sp: 57311 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x7e70)
+            .with_BC(0x82e4)
+            .with_DE(0x0004)
+            .with_HL(0x7a8f)
+            .with_PC(0x7a9d)
+            .with_SP(0xdfdf)
     );
 }
 #[test]
 fn test_opcode_0XA7() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 255, c: 138, d: 0, e: 0, f: 96, h: 160, l: 0, pc: 9145, sp: 57335 }",
-    );
-    mem.borrow_mut().fake_data(9145, 40);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0060)
+        .with_BC(0xff8a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x23b9)
+        .with_SP(0xdff7);
+    mem.lock().unwrap().fake_data(9145, 40);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xA7();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 255, c: 138, d: 0, e: 0, f: 160, h: 160, l: 0, pc: 9145, sp: 57335 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0xff8a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x23b9)
+            .with_SP(0xdff7)
     );
 }
 #[test]
 fn test_opcode_0XAF() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 80, h: 1, l: 77, pc: 341, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 0);
-    mem.borrow_mut().fake_data(341, 24);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0150)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0155)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 0);
+    mem.lock().unwrap().fake_data(341, 24);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xAF();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 341, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0155)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0XB0() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 0, d: 25, e: 108, f: 160, h: 77, l: 238, pc: 403, sp: 57315 }",
-    );
-    mem.borrow_mut().fake_data(403, 224);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x0000)
+        .with_DE(0x196c)
+        .with_HL(0x4dee)
+        .with_PC(0x0193)
+        .with_SP(0xdfe3);
+    mem.lock().unwrap().fake_data(403, 224);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xB0();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 0, d: 25, e: 108, f: 128, h: 77, l: 238, pc: 403, sp: 57315 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0000)
+            .with_DE(0x196c)
+            .with_HL(0x4dee)
+            .with_PC(0x0193)
+            .with_SP(0xdfe3)
     );
 }
 #[test]
 fn test_opcode_0XB1() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 31, b: 31, c: 255, d: 0, e: 216, f: 160, h: 192, l: 1, pc: 8070, sp: 57343 }",
-    );
-    mem.borrow_mut().fake_data(8070, 32);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x1fa0)
+        .with_BC(0x1fff)
+        .with_DE(0x00d8)
+        .with_HL(0xc001)
+        .with_PC(0x1f86)
+        .with_SP(0xdfff);
+    mem.lock().unwrap().fake_data(8070, 32);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xB1();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 255, b: 31, c: 255, d: 0, e: 216, f: 0, h: 192, l: 1, pc: 8070, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xff00)
+            .with_BC(0x1fff)
+            .with_DE(0x00d8)
+            .with_HL(0xc001)
+            .with_PC(0x1f86)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0XB3() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 27, b: 0, c: 138, d: 27, e: 87, f: 128, h: 101, l: 8, pc: 24915, sp: 57329 }",
-    );
-    mem.borrow_mut().fake_data(24915, 32);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x1b80)
+        .with_BC(0x008a)
+        .with_DE(0x1b57)
+        .with_HL(0x6508)
+        .with_PC(0x6153)
+        .with_SP(0xdff1);
+    mem.lock().unwrap().fake_data(24915, 32);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xB3();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 95, b: 0, c: 138, d: 27, e: 87, f: 0, h: 101, l: 8, pc: 24915, sp: 57329 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x5f00)
+            .with_BC(0x008a)
+            .with_DE(0x1b57)
+            .with_HL(0x6508)
+            .with_PC(0x6153)
+            .with_SP(0xdff1)
     );
 }
 #[test]
 fn test_opcode_0XC0() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 255, b: 0, c: 138, d: 0, e: 0, f: 192, h: 101, l: 8, pc: 19224, sp: 57323 }",
-    );
-    mem.borrow_mut().fake_data(19224, 234);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xffc0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0x6508)
+        .with_PC(0x4b18)
+        .with_SP(0xdfeb);
+    mem.lock().unwrap().fake_data(19224, 234);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xC0();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 255, b: 0, c: 138, d: 0, e: 0, f: 192, h: 101, l: 8, pc: 19224, sp: 57323 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xffc0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0x6508)
+            .with_PC(0x4b18)
+            .with_SP(0xdfeb)
     );
 }
 #[test]
 fn test_opcode_0XC1() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 138, d: 24, e: 0, f: 192, h: 192, l: 206, pc: 9254, sp: 57335 }",
-    );
-    mem.borrow_mut().fake_data(57335, 138);
-    mem.borrow_mut().fake_data(9254, 209);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01c0)
+        .with_BC(0x008a)
+        .with_DE(0x1800)
+        .with_HL(0xc0ce)
+        .with_PC(0x2426)
+        .with_SP(0xdff7);
+    mem.lock().unwrap().fake_data(57335, 138);
+    mem.lock().unwrap().fake_data(9254, 209);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xC1();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 138, d: 24, e: 0, f: 192, h: 192, l: 206, pc: 9254, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01c0)
+            .with_BC(0x008a)
+            .with_DE(0x1800)
+            .with_HL(0xc0ce)
+            .with_PC(0x2426)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0XC3() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 176, h: 1, l: 77, pc: 258, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(258, 336);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 0);
-    mem.borrow_mut().fake_data(336, 254);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01b0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0102)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(258, 336);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 0);
+    mem.lock().unwrap().fake_data(336, 254);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xC3();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 176, h: 1, l: 77, pc: 336, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01b0)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0150)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0XC5() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 255, b: 0, c: 138, d: 0, e: 0, f: 96, h: 160, l: 0, pc: 9140, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(9140, 71);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xff60)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x23b4)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(9140, 71);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xC5();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 255, b: 0, c: 138, d: 0, e: 0, f: 96, h: 160, l: 0, pc: 9140, sp: 57335 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xff60)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x23b4)
+            .with_SP(0xdff7)
     );
 }
 #[test]
 fn test_opcode_0XC8() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 138, d: 62, e: 141, f: 32, h: 100, l: 248, pc: 24559, sp: 57333 }",
-    );
-    mem.borrow_mut().fake_data(24559, 71);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0120)
+        .with_BC(0x008a)
+        .with_DE(0x3e8d)
+        .with_HL(0x64f8)
+        .with_PC(0x5fef)
+        .with_SP(0xdff5);
+    mem.lock().unwrap().fake_data(24559, 71);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xC8();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 138, d: 62, e: 141, f: 32, h: 100, l: 248, pc: 24559, sp: 57333 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0120)
+            .with_BC(0x008a)
+            .with_DE(0x3e8d)
+            .with_HL(0x64f8)
+            .with_PC(0x5fef)
+            .with_SP(0xdff5)
     );
 }
 #[test]
 fn test_opcode_0XC9() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 1, l: 77, pc: 123, sp: 65532 }",
-    );
-    mem.borrow_mut().fake_data(65532, 8055);
-    mem.borrow_mut().fake_data(8055, 49);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x007b)
+        .with_SP(0xfffc);
+    mem.lock().unwrap().fake_data(65532, 8055);
+    mem.lock().unwrap().fake_data(8055, 49);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xC9();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 1, l: 77, pc: 8055, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x1f77)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0XCA() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 255, b: 255, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 22652, sp: 57333 }",
-    );
-    mem.borrow_mut().fake_data(22652, 23092);
-    mem.borrow_mut().fake_data(23092, 62);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xffc0)
+        .with_BC(0xff8a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x587c)
+        .with_SP(0xdff5);
+    mem.lock().unwrap().fake_data(22652, 23092);
+    mem.lock().unwrap().fake_data(23092, 62);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xCA();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 255, b: 255, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 23092, sp: 57333 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xffc0)
+            .with_BC(0xff8a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x5a34)
+            .with_SP(0xdff5)
     );
 }
 #[test]
 fn test_opcode_0XCC() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 2, c: 192, d: 0, e: 4, f: 32, h: 77, l: 238, pc: 8352, sp: 57325 }",
-    );
-    mem.borrow_mut().fake_data(8352, 351);
-    mem.borrow_mut().fake_data(8354, 250);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0120)
+        .with_BC(0x02c0)
+        .with_DE(0x0004)
+        .with_HL(0x4dee)
+        .with_PC(0x20a0)
+        .with_SP(0xdfed);
+    mem.lock().unwrap().fake_data(8352, 351);
+    mem.lock().unwrap().fake_data(8354, 250);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xCC();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 2, c: 192, d: 0, e: 4, f: 32, h: 77, l: 238, pc: 8354, sp: 57325 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0120)
+            .with_BC(0x02c0)
+            .with_DE(0x0004)
+            .with_HL(0x4dee)
+            .with_PC(0x20a2)
+            .with_SP(0xdfed)
     );
 }
 #[test]
 fn test_opcode_0XCD() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 128, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 8053, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(8053, 97);
-    mem.borrow_mut().fake_data(97, 175);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x8080)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x1f75)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(8053, 97);
+    mem.lock().unwrap().fake_data(97, 175);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xCD();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 128, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 97, sp: 65532 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x8080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0061)
+            .with_SP(0xfffc)
     );
 }
 #[test]
 fn test_opcode_0XD0() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 144, h: 100, l: 248, pc: 24627, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(24627, 62);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0090)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0x64f8)
+        .with_PC(0x6033)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(24627, 62);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xD0();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 138, d: 0, e: 0, f: 144, h: 100, l: 248, pc: 24627, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0090)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0x64f8)
+            .with_PC(0x6033)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0XD1() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 0, d: 0, e: 216, f: 128, h: 160, l: 0, pc: 14058, sp: 57339 }",
-    );
-    mem.borrow_mut().fake_data(57339, 216);
-    mem.borrow_mut().fake_data(14058, 201);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0000)
+        .with_DE(0x00d8)
+        .with_HL(0xa000)
+        .with_PC(0x36ea)
+        .with_SP(0xdffb);
+    mem.lock().unwrap().fake_data(57339, 216);
+    mem.lock().unwrap().fake_data(14058, 201);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xD1();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 0, d: 0, e: 216, f: 128, h: 160, l: 0, pc: 14058, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0000)
+            .with_DE(0x00d8)
+            .with_HL(0xa000)
+            .with_PC(0x36ea)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0XD5() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 128, h: 128, l: 0, pc: 14049, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(14049, 87);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x2000)
+        .with_DE(0x00d8)
+        .with_HL(0x8000)
+        .with_PC(0x36e1)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(14049, 87);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xD5();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 32, c: 0, d: 0, e: 216, f: 128, h: 128, l: 0, pc: 14049, sp: 57339 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x2000)
+            .with_DE(0x00d8)
+            .with_HL(0x8000)
+            .with_PC(0x36e1)
+            .with_SP(0xdffb)
     );
 }
 #[test]
 fn test_opcode_0XD6() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 32, b: 4, c: 32, d: 98, e: 136, f: 160, h: 150, l: 0, pc: 6274, sp: 57325 }",
-    );
-    mem.borrow_mut().fake_data(6274, 8);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(6275, 79);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x20a0)
+        .with_BC(0x0420)
+        .with_DE(0x6288)
+        .with_HL(0x9600)
+        .with_PC(0x1882)
+        .with_SP(0xdfed);
+    mem.lock().unwrap().fake_data(6274, 8);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(6275, 79);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xD6();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 24, b: 4, c: 32, d: 98, e: 136, f: 96, h: 150, l: 0, pc: 6275, sp: 57325 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x1860)
+            .with_BC(0x0420)
+            .with_DE(0x6288)
+            .with_HL(0x9600)
+            .with_PC(0x1883)
+            .with_SP(0xdfed)
     );
 }
 #[test]
 fn test_opcode_0XD9() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 101, l: 8, pc: 8368, sp: 57333 }",
-    );
-    mem.borrow_mut().fake_data(57333, 24743);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(24743, 205);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0x6508)
+        .with_PC(0x20b0)
+        .with_SP(0xdff5);
+    mem.lock().unwrap().fake_data(57333, 24743);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(24743, 205);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xD9();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 101, l: 8, pc: 24743, sp: 57335 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0x6508)
+            .with_PC(0x60a7)
+            .with_SP(0xdff7)
     );
 }
 #[test]
 fn test_opcode_0XE0() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 8023, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(8023, 15);
-    mem.borrow_mut().fake_data(8024, 224);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x1f57)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(8023, 15);
+    mem.lock().unwrap().fake_data(8024, 224);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xE0();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 8024, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x1f58)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0XE1() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 192, l: 206, pc: 9256, sp: 57339 }",
-    );
-    mem.borrow_mut().fake_data(57339, 40960);
-    mem.borrow_mut().fake_data(9256, 201);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xc0ce)
+        .with_PC(0x2428)
+        .with_SP(0xdffb);
+    mem.lock().unwrap().fake_data(57339, 40960);
+    mem.lock().unwrap().fake_data(9256, 201);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xE1();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 9256, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x2428)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0XE2() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 62, b: 10, c: 128, d: 0, e: 216, f: 192, h: 75, l: 252, pc: 19446, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(19446, 12);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x3ec0)
+        .with_BC(0x0a80)
+        .with_DE(0x00d8)
+        .with_HL(0x4bfc)
+        .with_PC(0x4bf6)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(19446, 12);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xE2();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 62, b: 10, c: 128, d: 0, e: 216, f: 192, h: 75, l: 252, pc: 19446, sp: 57341 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x3ec0)
+            .with_BC(0x0a80)
+            .with_DE(0x00d8)
+            .with_HL(0x4bfc)
+            .with_PC(0x4bf6)
+            .with_SP(0xdffd)
     );
 }
 #[test]
 fn test_opcode_0XE5() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 255, b: 0, c: 138, d: 0, e: 0, f: 96, h: 160, l: 0, pc: 9138, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(9138, 213);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xff60)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x23b2)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(9138, 213);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xE5();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 255, b: 0, c: 138, d: 0, e: 0, f: 96, h: 160, l: 0, pc: 9138, sp: 57339 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0xff60)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x23b2)
+            .with_SP(0xdffb)
     );
 }
 #[test]
 fn test_opcode_0XE6() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 128, b: 0, c: 19, d: 0, e: 216, f: 192, h: 1, l: 77, pc: 116, sp: 65532 }",
-    );
-    mem.borrow_mut().fake_data(116, 127);
-    mem.borrow_mut().fake_data(117, 224);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x80c0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0074)
+        .with_SP(0xfffc);
+    mem.lock().unwrap().fake_data(116, 127);
+    mem.lock().unwrap().fake_data(117, 224);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xE6();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 160, h: 1, l: 77, pc: 117, sp: 65532 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0075)
+            .with_SP(0xfffc)
     );
 }
 #[test]
 fn test_opcode_0XE9() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 28, b: 0, c: 138, d: 62, e: 141, f: 0, h: 96, l: 43, pc: 16013, sp: 57337 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(24619, 175);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x1c00)
+        .with_BC(0x008a)
+        .with_DE(0x3e8d)
+        .with_HL(0x602b)
+        .with_PC(0x3e8d)
+        .with_SP(0xdff9);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(24619, 175);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xE9();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 28, b: 0, c: 138, d: 62, e: 141, f: 0, h: 96, l: 43, pc: 24619, sp: 57337 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x1c00)
+            .with_BC(0x008a)
+            .with_DE(0x3e8d)
+            .with_HL(0x602b)
+            .with_PC(0x602b)
+            .with_SP(0xdff9)
     );
 }
 #[test]
 fn test_opcode_0XEA() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 346, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(346, 53018);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 0);
-    mem.borrow_mut().fake_data(348, 195);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x015a)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(346, 53018);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 0);
+    mem.lock().unwrap().fake_data(348, 195);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xEA();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 348, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x015c)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0XF0() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 101, sp: 65532 }",
-    );
-    mem.borrow_mut().fake_data(101, 255);
-    mem.borrow_mut().fake_data(65535, 0);
-    mem.borrow_mut().fake_data(102, 71);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0065)
+        .with_SP(0xfffc);
+    mem.lock().unwrap().fake_data(101, 255);
+    mem.lock().unwrap().fake_data(65535, 0);
+    mem.lock().unwrap().fake_data(102, 71);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xF0();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 102, sp: 65532 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0066)
+            .with_SP(0xfffc)
     );
 }
 #[test]
 fn test_opcode_0XF1() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 28, b: 0, c: 138, d: 0, e: 0, f: 32, h: 101, l: 8, pc: 8366, sp: 57331 }",
-    );
-    mem.borrow_mut().fake_data(57331, 448);
-    mem.borrow_mut().fake_data(8366, 217);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x1c20)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0x6508)
+        .with_PC(0x20ae)
+        .with_SP(0xdff3);
+    mem.lock().unwrap().fake_data(57331, 448);
+    mem.lock().unwrap().fake_data(8366, 217);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xF1();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 101, l: 8, pc: 8366, sp: 57333 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0x6508)
+            .with_PC(0x20ae)
+            .with_SP(0xdff5)
     );
 }
 #[test]
 fn test_opcode_0XF3() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 8021, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(8021, 175);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0080)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x1f55)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(8021, 175);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xF3();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 19, d: 0, e: 216, f: 128, h: 1, l: 77, pc: 8021, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0080)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x1f55)
+            .with_SP(0xfffe)
     );
 }
 #[test]
 fn test_opcode_0XF5() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 15990, sp: 57341 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(15990, 62);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x3e76)
+        .with_SP(0xdffd);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(15990, 62);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xF5();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 15990, sp: 57339 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x3e76)
+            .with_SP(0xdffb)
     );
 }
 #[test]
 fn test_opcode_0XF8() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 0, d: 25, e: 170, f: 32, h: 100, l: 216, pc: 7516, sp: 57309 }",
-    );
-    mem.borrow_mut().fake_data(7516, 0);
-    mem.borrow_mut().fake_data(7517, 124);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x0120)
+        .with_BC(0x0000)
+        .with_DE(0x19aa)
+        .with_HL(0x64d8)
+        .with_PC(0x1d5c)
+        .with_SP(0xdfdd);
+    mem.lock().unwrap().fake_data(7516, 0);
+    mem.lock().unwrap().fake_data(7517, 124);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xF8();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 0, d: 25, e: 170, f: 0, h: 223, l: 221, pc: 7517, sp: 57309 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0100)
+            .with_BC(0x0000)
+            .with_DE(0x19aa)
+            .with_HL(0xdfdd)
+            .with_PC(0x1d5d)
+            .with_SP(0xdfdd)
     );
 }
 #[test]
 fn test_opcode_0XF9() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 0, b: 0, c: 0, d: 25, e: 170, f: 160, h: 195, l: 160, pc: 7552, sp: 57309 }",
-    );
-    mem.borrow_mut().fake_data(7552, 240);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x00a0)
+        .with_BC(0x0000)
+        .with_DE(0x19aa)
+        .with_HL(0xc3a0)
+        .with_PC(0x1d80)
+        .with_SP(0xdfdd);
+    mem.lock().unwrap().fake_data(7552, 240);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xF9();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 0, c: 0, d: 25, e: 170, f: 160, h: 195, l: 160, pc: 7552, sp: 50080 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x00a0)
+            .with_BC(0x0000)
+            .with_DE(0x19aa)
+            .with_HL(0xc3a0)
+            .with_PC(0x1d80)
+            .with_SP(0xc3a0)
     );
 }
 #[test]
 fn test_opcode_0XFA() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 255, b: 255, c: 138, d: 0, e: 0, f: 96, h: 160, l: 0, pc: 9142, sp: 57335 }",
-    );
-    mem.borrow_mut().fake_data(9142, 49390);
-    mem.borrow_mut().fake_data(49390, 0);
-    mem.borrow_mut().fake_data(9144, 167);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0xff60)
+        .with_BC(0xff8a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x23b6)
+        .with_SP(0xdff7);
+    mem.lock().unwrap().fake_data(9142, 49390);
+    mem.lock().unwrap().fake_data(49390, 0);
+    mem.lock().unwrap().fake_data(9144, 167);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xFA();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 0, b: 255, c: 138, d: 0, e: 0, f: 96, h: 160, l: 0, pc: 9144, sp: 57335 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0060)
+            .with_BC(0xff8a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x23b8)
+            .with_SP(0xdff7)
     );
 }
 #[test]
 fn test_opcode_0XFB() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 8148, sp: 57343 }",
-    );
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 13);
-    mem.borrow_mut().fake_data(8148, 62);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01c0)
+        .with_BC(0x008a)
+        .with_DE(0x0000)
+        .with_HL(0xa000)
+        .with_PC(0x1fd4)
+        .with_SP(0xdfff);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 13);
+    mem.lock().unwrap().fake_data(8148, 62);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xFB();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 138, d: 0, e: 0, f: 192, h: 160, l: 0, pc: 8148, sp: 57343 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x01c0)
+            .with_BC(0x008a)
+            .with_DE(0x0000)
+            .with_HL(0xa000)
+            .with_PC(0x1fd4)
+            .with_SP(0xdfff)
     );
 }
 #[test]
 fn test_opcode_0XFE() {
-    let mem = Rc::new(RefCell::new(FakeMemory::new()));
-    let reg = Register::new_from_debug_string(
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 176, h: 1, l: 77, pc: 337, sp: 65534 }",
-    );
-    mem.borrow_mut().fake_data(337, 17);
-    mem.borrow_mut().fake_data(65295, 0);
-    mem.borrow_mut().fake_data(65535, 0);
-    mem.borrow_mut().fake_data(338, 40);
-    let mut cpu = CPU::new(mem, false);
+    let mem = Arc::new(Mutex::new(FakeMemory::new()));
+    let reg = Register::new()
+        .with_AF(0x01b0)
+        .with_BC(0x0013)
+        .with_DE(0x00d8)
+        .with_HL(0x014d)
+        .with_PC(0x0151)
+        .with_SP(0xfffe);
+    mem.lock().unwrap().fake_data(337, 17);
+    mem.lock().unwrap().fake_data(65295, 0);
+    mem.lock().unwrap().fake_data(65535, 0);
+    mem.lock().unwrap().fake_data(338, 40);
+    let mut cpu = CPU::new(mem);
     cpu.set_reg(reg);
     cpu.op_0xFE();
     assert_eq!(
-        format!("{:?}", cpu.get_reg_snapshot()).to_lowercase(),
-        "register { a: 1, b: 0, c: 19, d: 0, e: 216, f: 80, h: 1, l: 77, pc: 338, sp: 65534 }"
+        cpu.get_reg_snapshot(),
+        Register::new()
+            .with_AF(0x0150)
+            .with_BC(0x0013)
+            .with_DE(0x00d8)
+            .with_HL(0x014d)
+            .with_PC(0x0152)
+            .with_SP(0xfffe)
+    );
+}
+
+#[test]
+fn test_step() {
+    let (mut cpu, bus) = new_cpu();
+    let access = |addr, value, write| MemoryAccess { addr, value, write };
+    cpu.set_reg(
+        Register::new()
+            .with_BC(0x1234)
+            .with_PC(0x0100)
+            .with_SP(0xd000),
+    );
+    // PUSH BC; LD A,0x42; HALT
+    bus.lock().unwrap().load(0x0100, &[0xc5, 0x3e, 0x42, 0x76]);
+
+    let step = cpu.step();
+    assert_eq!((step.pc, step.opcode), (0x0100, 0xc5));
+    assert_eq!(step.mnemonic, "PUSH BC");
+    assert_eq!(step.cycles, 16);
+    assert_eq!(
+        step.memory_accesses,
+        vec![
+            access(0x0100, 0xc5, false),
+            access(0xcffe, 0x34, true),
+            access(0xcfff, 0x12, true),
+        ]
     );
+
+    let step = cpu.step();
+    assert_eq!(step.mnemonic, "LD A,0x42");
+    assert_eq!(step.memory_accesses.len(), 2);
+    assert_eq!(step.memory_accesses[1], access(0x0102, 0x42, false));
+    assert_eq!(cpu.step().mnemonic, "HALT");
+
+    // The halted CPU executes nothing until the V-Blank is pushed.
+    let step = cpu.step();
+    assert_eq!((step.mnemonic.as_str(), step.cycles), ("HALT", 4));
+    assert!(step.memory_accesses.is_empty());
+    bus.lock().unwrap().load(0xff0f, &[0x01]);
+    bus.lock().unwrap().load(0xffff, &[0x01]);
+    // It wakes up, then the interrupt is dispatched.
+    assert_eq!(cpu.step().mnemonic, "HALT");
+    let step = cpu.step();
+    assert_eq!(step.mnemonic, "INT");
+    assert_eq!(step.memory_accesses.len(), 2);
+    assert_eq!(cpu.reg.get_PC(), 0x0040);
+}
+
+#[test]
+fn test_addresses_wrap_around() {
+    let (mut cpu, bus) = new_cpu();
+    // POP BC; PUSH BC; LD (HL-),A
+    bus.lock().unwrap().load(0x0100, &[0xc1, 0xc5, 0x32]);
+    bus.lock().unwrap().load(0xffff, &[0x34]);
+    bus.lock().unwrap().load(0x0000, &[0x12]);
+    cpu.set_reg(
+        Register::new()
+            .with_AF(0x5600)
+            .with_PC(0x0100)
+            .with_SP(0xffff),
+    );
+    cpu.next();
+    assert_eq!((cpu.reg.get_BC(), cpu.reg.get_SP()), (0x1234, 0x0001));
+    cpu.next();
+    assert_eq!(cpu.reg.get_SP(), 0xffff);
+    assert_eq!(cpu.read_word_from_memory(0xffff), 0x1234);
+    cpu.next();
+    assert_eq!(cpu.reg.get_HL(), 0xffff);
+    assert_eq!(bus.lock().unwrap().read_byte(0x0000), 0x56);
+}
+
+#[test]
+fn test_conditional_branches() {
+    // The opcode, the flags failing its condition, the cycles not taken and taken.
+    let branches: [(u8, u8, u32, u32); 16] = [
+        (0x20, 0x80, 8, 12),
+        (0x28, 0x00, 8, 12),
+        (0x30, 0x10, 8, 12),
+        (0x38, 0x00, 8, 12),
+        (0xc0, 0x80, 8, 20),
+        (0xc8, 0x00, 8, 20),
+        (0xd0, 0x10, 8, 20),
+        (0xd8, 0x00, 8, 20),
+        (0xc2, 0x80, 12, 16),
+        (0xca, 0x00, 12, 16),
+        (0xd2, 0x10, 12, 16),
+        (0xda, 0x00, 12, 16),
+        (0xc4, 0x80, 12, 24),
+        (0xcc, 0x00, 12, 24),
+        (0xd4, 0x10, 12, 24),
+        (0xdc, 0x00, 12, 24),
+    ];
+    for (opcode, f, not_taken, taken) in branches.iter() {
+        for take in [false, true].iter() {
+            let (mut cpu, bus) = new_cpu();
+            // The flags of the taken branch are the other ones, the unrelated flags are set.
+            let f = if *take { !f & 0x90 } else { *f } | 0x60;
+            cpu.set_reg(
+                Register::new()
+                    .with_AF(u16::from(f))
+                    .with_PC(0x0100)
+                    .with_SP(0xd000),
+            );
+            // The operands are 0x10 0xc0: JR +16 or the address 0xc010.
+            bus.lock().unwrap().load(0x0100, &[*opcode, 0x10, 0xc0]);
+            // The return address on the stack.
+            bus.lock().unwrap().load(0xd000, &[0x34, 0x12]);
+            let cycles = cpu.next();
+            let reg = cpu.get_reg_snapshot();
+            let msg = format!("{:02x} taken: {}", opcode, take);
+
+            let (len, target, sp) = match opcode & 0xc7 {
+                0x00 => (2, 0x0112, 0xd000),
+                0xc0 => (1, 0x1234, 0xd002),
+                0xc2 => (3, 0xc010, 0xd000),
+                _ => (3, 0xc010, 0xcffe),
+            };
+            if *take {
+                assert_eq!(cycles, *taken, "{}", msg);
+                assert_eq!((reg.get_PC(), reg.get_SP()), (target, sp), "{}", msg);
+            } else {
+                assert_eq!(cycles, *not_taken, "{}", msg);
+                let pc = 0x0100 + len;
+                assert_eq!((reg.get_PC(), reg.get_SP()), (pc, 0xd000), "{}", msg);
+            }
+            if *take && opcode & 0xc7 == 0xc4 {
+                assert_eq!(cpu.read_word_from_memory(0xcffe), 0x0103, "{}", msg);
+            }
+            assert_eq!(reg.get_AF() as u8, f, "{}", msg);
+        }
+    }
+}
+
+#[test]
+fn test_stack_check() {
+    for enabled in [false, true].iter() {
+        let (mut cpu, bus) = new_cpu();
+        cpu.set_stack_check(*enabled);
+        // PUSH BC; PUSH BC; POP BC; PUSH BC
+        bus.lock().unwrap().load(0x0100, &[0xc5, 0xc5, 0xc1, 0xc5]);
+        cpu.set_reg(Register::new().with_PC(0x0100).with_SP(0xc002));
+        cpu.next();
+        assert_eq!(cpu.get_stack_warnings(), 0);
+        // Below the work ram, it's warned once.
+        cpu.next();
+        cpu.next();
+        assert_eq!(cpu.reg.get_SP(), 0xc000);
+        assert_eq!(cpu.get_stack_warnings(), u32::from(*enabled));
+        // Back to the work ram, the next crossing is warned again.
+        cpu.next();
+        assert_eq!(cpu.get_stack_warnings(), u32::from(*enabled) * 2);
+    }
+}
+
+#[test]
+fn test_unknown_opcode_locks_up() {
+    let (mut cpu, bus) = new_cpu();
+    // NOP; 0xd3
+    bus.lock().unwrap().load(0x0100, &[0x00, 0xd3, 0x00]);
+    cpu.set_reg(Register::new().with_PC(0x0100).with_SP(0xfffe));
+    cpu.enable_ime();
+    bus.lock().unwrap().write_byte(0xffff, 0x01);
+    cpu.next();
+    assert_eq!(cpu.get_lock(), None);
+    assert_eq!(cpu.next(), 4);
+    let lock = cpu.get_lock().unwrap();
+    assert_eq!((lock.pc, lock.opcode), (0x0101, 0xd3));
+    assert_eq!(lock.to_string(), "the unknown opcode d3 at 0101");
+    assert_eq!(cpu.get_instruction_count(), 1);
+    // Even an interrupt doesn't wake it up.
+    bus.lock().unwrap().write_byte(0xff0f, 0x01);
+    let step = cpu.step();
+    assert_eq!(step.mnemonic, "LOCKED");
+    assert_eq!(cpu.reg.get_PC(), 0x0102);
+    assert_eq!(cpu.get_instruction_count(), 1);
 }
@@ -0,0 +1,152 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use NGC224::gameboy::testrom::{run_test_rom, Verdict};
+use NGC224::gameboy::CLOCK_FREQUENCY;
+
+// The blargg roms report in about a minute, give them twice the time.
+const MAX_CYCLES: u64 = 120 * CLOCK_FREQUENCY as u64;
+
+/// Build a 32KB rom which jumps to the program at 0x0150, the data is placed at 0x0200.
+fn build_rom(cart_type: u8, ram_size: u8, program: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    // NOP; JP 0x0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    rom[0x134..0x13c].copy_from_slice(b"TESTROM\0");
+    rom[0x147] = cart_type;
+    rom[0x148] = 0x00;
+    rom[0x149] = ram_size;
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    rom[0x200..0x200 + data.len()].copy_from_slice(data);
+    rom
+}
+
+/// A program sending the zero terminated bytes at 0x0200 through the serial port, then it loops.
+fn serial_rom(message: &[u8]) -> Vec<u8> {
+    let program = [
+        0x21, 0x00, 0x02, // LD HL, 0x0200
+        0x2a, // loop: LD A, (HL+)
+        0xb7, // OR A
+        0x28, 0x0e, // JR Z, done
+        0xe0, 0x01, // LDH (0x01), A
+        0x3e, 0x81, // LD A, 0x81
+        0xe0, 0x02, // LDH (0x02), A
+        0xf0, 0x02, // wait: LDH A, (0x02)
+        0xcb, 0x7f, // BIT 7, A
+        0x20, 0xfa, // JR NZ, wait
+        0x18, 0xee, // JR loop
+        0x18, 0xfe, // done: JR done
+    ];
+    let mut data = message.to_vec();
+    data.push(0x00);
+    build_rom(0x00, 0x00, &program, &data)
+}
+
+fn write_rom(name: &str, rom: &[u8]) -> PathBuf {
+    let path = env::temp_dir().join(format!("ngc224-{}-{}.gb", name, std::process::id()));
+    fs::write(&path, rom).unwrap();
+    path
+}
+
+#[test]
+fn test_serial_passed() {
+    let rom = serial_rom(b"cpu_instrs\n\nPassed all tests\n");
+    let path = write_rom("serial-passed", &rom);
+    let report = run_test_rom(&path, MAX_CYCLES).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(report.verdict, Verdict::Passed);
+    assert!(report.output.starts_with("cpu_instrs\n\nPassed"));
+    // A byte takes 4096 cycles to shift out.
+    assert!(report.cycles < 40 * 4096 + 2 * 70224);
+}
+
+#[test]
+fn test_serial_mooneye_failed() {
+    let path = write_rom("serial-failed", &serial_rom(&[0x42; 6]));
+    let report = run_test_rom(&path, MAX_CYCLES).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(report.verdict, Verdict::Failed);
+}
+
+#[test]
+fn test_memory_signature() {
+    let program = [
+        0x3e, 0x0a, 0xea, 0x00, 0x00, // Enable the ram.
+        0x21, 0x01, 0xa0, // LD HL, 0xa001
+        0x36, 0xde, 0x23, 0x36, 0xb0, 0x23, 0x36, 0x61, 0x23, // The signature.
+        0x36, b'o', 0x23, 0x36, b'k', 0x23, 0x36, 0x00, // The text.
+        0x21, 0x00, 0xa0, 0x36, 0x01, // The result code 1.
+        0x18, 0xfe,
+    ];
+    // MBC1 with 8KB ram.
+    let path = write_rom("memory", &build_rom(0x02, 0x02, &program, &[]));
+    let report = run_test_rom(&path, MAX_CYCLES).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(report.verdict, Verdict::Failed);
+    assert_eq!(report.output, "ok");
+}
+
+#[test]
+fn test_timeout() {
+    let path = write_rom("timeout", &serial_rom(b"running"));
+    let report = run_test_rom(&path, 10 * 70224).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(report.verdict, Verdict::Timeout);
+    assert_eq!(report.output, "running");
+}
+
+/// Run the rom of the gb-test-roms submodule, it's skipped if the submodule is not checked out.
+fn check_test_rom(rom: &str) {
+    let path = Path::new("gb-test-roms").join(rom);
+    if !path.exists() {
+        eprintln!("{:?} is missing, run `git submodule update --init`", path);
+        return;
+    }
+    let report = run_test_rom(&path, MAX_CYCLES).unwrap();
+    assert_eq!(report.verdict, Verdict::Passed, "{}", report.output);
+}
+
+/// Run the built rom of the mooneye test suite, it's not a submodule, so it's skipped unless the
+/// roms are extracted into `mooneye-test-suite`.
+fn check_mooneye_rom(rom: &str) {
+    let path = Path::new("mooneye-test-suite").join(rom);
+    if !path.exists() {
+        eprintln!("{:?} is missing", path);
+        return;
+    }
+    let report = run_test_rom(&path, MAX_CYCLES).unwrap();
+    assert_eq!(report.verdict, Verdict::Passed, "{}", report.output);
+}
+
+// The roms take a while, run them with `cargo test --release --test rom_harness -- --ignored`.
+
+#[test]
+#[ignore]
+fn test_blargg_cpu_instrs() {
+    check_test_rom("cpu_instrs/cpu_instrs.gb");
+}
+
+#[test]
+#[ignore]
+fn test_blargg_instr_timing() {
+    check_test_rom("instr_timing/instr_timing.gb");
+}
+
+#[test]
+#[ignore]
+fn test_blargg_mem_timing() {
+    check_test_rom("mem_timing/mem_timing.gb");
+}
+
+#[test]
+#[ignore]
+fn test_mooneye_ie_push() {
+    check_mooneye_rom("acceptance/interrupts/ie_push.gb");
+}
+
+#[test]
+#[ignore]
+fn test_mooneye_intr_timing() {
+    check_mooneye_rom("acceptance/intr_timing.gb");
+}
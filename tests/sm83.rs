@@ -0,0 +1,321 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use common::new_cpu;
+use NGC224::gameboy::IOHandler;
+use NGC224::gameboy::Register;
+
+/// The JSON value, only what the test corpus uses is parsed.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_space();
+        if parser.pos != parser.text.len() {
+            return Err(format!("trailing bytes at {}", parser.pos));
+        }
+        Ok(value)
+    }
+
+    fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(map) => map.get(key).unwrap_or(&Json::Null),
+            _ => &Json::Null,
+        }
+    }
+
+    fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(values) => values,
+            _ => &[],
+        }
+    }
+
+    fn as_u16(&self) -> u16 {
+        match self {
+            Json::Number(n) => *n as u16,
+            Json::Bool(b) => u16::from(*b),
+            _ => 0,
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        self.as_u16() as u8
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Json::Str(s) => s,
+            _ => "",
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_space(&mut self) {
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_space();
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(format!("expect {:?} at {}", c as char, self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if !self.text[self.pos..].starts_with(word.as_bytes()) {
+            return Err(format!("unknown value at {}", self.pos));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(_) => self.number(),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut map = BTreeMap::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(map));
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(b':')?;
+            map.insert(key, self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                _ => break,
+            }
+        }
+        self.expect(b'}')?;
+        Ok(Json::Object(map))
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut values = vec![];
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                _ => break,
+            }
+        }
+        self.expect(b']')?;
+        Ok(Json::Array(values))
+    }
+
+    /// The escapes are kept as they are, the corpus only has plain names.
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let start = self.pos;
+        while self.pos < self.text.len() && self.text[self.pos] != b'"' {
+            if self.text[self.pos] == b'\\' {
+                self.pos += 1;
+            }
+            self.pos += 1;
+        }
+        let s = String::from_utf8_lossy(&self.text[start..self.pos.min(self.text.len())]);
+        self.expect(b'"')?;
+        Ok(s.into_owned())
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        let is_number = |c: u8| matches!(c, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9');
+        while self.pos < self.text.len() && is_number(self.text[self.pos]) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| format!("bad number at {}", start))
+    }
+}
+
+/// Read the registers of the state, its pc is where the opcode is.
+fn read_register(state: &Json) -> Register {
+    let pair = |hi: &str, lo: &str| u16::from(state.get(hi).as_u8()) << 8 | state.get(lo).as_u16();
+    Register::new()
+        .with_AF(pair("a", "f"))
+        .with_BC(pair("b", "c"))
+        .with_DE(pair("d", "e"))
+        .with_HL(pair("h", "l"))
+        // The corpus prefetches the opcode, its pc points after it.
+        .with_PC(state.get("pc").as_u16().wrapping_sub(1))
+        .with_SP(state.get("sp").as_u16())
+}
+
+/// Run a case of the corpus, which is the initial state, the expected state and the bus cycles.
+/// Returns what differs from the expected state.
+fn run_case(case: &Json) -> Result<(), String> {
+    let (initial, expected) = (case.get("initial"), case.get("final"));
+    let (mut cpu, bus) = new_cpu();
+    cpu.set_reg(read_register(initial));
+    if initial.get("ime").as_u8() != 0 {
+        cpu.enable_ime();
+    } else {
+        cpu.disable_ime();
+    }
+    let mut mem = bus.lock().unwrap();
+    mem.write_byte(0xffff, initial.get("ie").as_u8());
+    for cell in initial.get("ram").as_array() {
+        let cell = cell.as_array();
+        mem.write_byte(cell[0].as_u16(), cell[1].as_u8());
+    }
+    drop(mem);
+
+    let cycles = cpu.next();
+
+    let mut wrong = vec![];
+    let (reg, want) = (cpu.get_reg_snapshot(), read_register(expected));
+    if reg != want {
+        wrong.push(format!("{:?} != {:?}", reg, want));
+    }
+    let ime = expected.get("ime").as_u8() != 0;
+    if cpu.is_ime_enabled() != ime {
+        wrong.push(format!("ime {} != {}", cpu.is_ime_enabled(), ime));
+    }
+    for cell in expected.get("ram").as_array() {
+        let cell = cell.as_array();
+        let (addr, value) = (cell[0].as_u16(), cell[1].as_u8());
+        let got = bus.lock().unwrap().read_byte(addr);
+        if got != value {
+            wrong.push(format!("({:04x}) {:02x} != {:02x}", addr, got, value));
+        }
+    }
+    let m = case.get("cycles").as_array().len() as u32;
+    if cycles != m * 4 {
+        wrong.push(format!("cycles {} != {}", cycles, m * 4));
+    }
+    if wrong.is_empty() {
+        Ok(())
+    } else {
+        Err(wrong.join(", "))
+    }
+}
+
+/// Run every case of a file of the corpus, returns the first failure and how many failed.
+fn run_file(text: &str) -> Result<(), String> {
+    let cases = Json::parse(text)?;
+    let mut failed = 0;
+    let mut first = None;
+    for case in cases.as_array() {
+        if let Err(e) = run_case(case) {
+            failed += 1;
+            first.get_or_insert_with(|| format!("{}: {}", case.get("name").as_str(), e));
+        }
+    }
+    match first {
+        Some(first) => {
+            let total = cases.as_array().len();
+            Err(format!("{}/{} failed, {}", failed, total, first))
+        }
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_json() {
+    let value = Json::parse(r#"{"a": [1, -2.5, true, null], "b": {"c": "x y"}, "d": []}"#).unwrap();
+    assert_eq!(value.get("b").get("c").as_str(), "x y");
+    assert_eq!(value.get("a").as_array()[0].as_u16(), 1);
+    assert_eq!(value.get("a").as_array()[2], Json::Bool(true));
+    assert!(value.get("d").as_array().is_empty());
+    assert_eq!(value.get("e"), &Json::Null);
+    assert!(Json::parse("[1, 2").is_err());
+    assert!(Json::parse("{} x").is_err());
+}
+
+#[test]
+fn test_run_file() {
+    // LD B, A then the next opcode is prefetched.
+    let case = r#"[{
+        "name": "47 0000",
+        "initial": {"pc": 49153, "sp": 53248, "a": 18, "b": 0, "c": 0, "d": 0, "e": 0,
+            "f": 176, "h": 0, "l": 0, "ime": 0, "ie": 0, "ram": [[49152, 71], [49153, 0]]},
+        "final": {"pc": 49154, "sp": 53248, "a": 18, "b": 18, "c": 0, "d": 0, "e": 0,
+            "f": 176, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 71], [49153, 0]]},
+        "cycles": [[49153, 0, "r-m"]]
+    }]"#;
+    assert_eq!(run_file(case), Ok(()));
+    let case = case.replace(r#""b": 18"#, r#""b": 19"#);
+    let e = run_file(&case).unwrap_err();
+    assert!(e.starts_with("1/1 failed, 47 0000:"), "{}", e);
+}
+
+/// Run the per-opcode JSON tests of the sm83 corpus (github.com/SingleStepTests/sm83), it's
+/// not a submodule, so it's skipped unless the `v1` directory of the corpus is extracted into
+/// `sm83`. Every opcode is run and the failed ones are reported together.
+#[test]
+#[ignore]
+fn test_sm83_corpus() {
+    let dir = Path::new("sm83").join("v1");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            eprintln!("{:?} is missing", dir);
+            return;
+        }
+    };
+    let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    let mut wrong = vec![];
+    paths.retain(|p| p.extension().map_or(false, |e| e == "json"));
+    for path in paths.iter() {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let text = fs::read_to_string(path).unwrap();
+        if let Err(e) = run_file(&text) {
+            wrong.push(format!("{}: {}", name, e));
+        }
+    }
+    let report = format!("{} opcodes failed\n{}", wrong.len(), wrong.join("\n"));
+    assert!(wrong.is_empty(), "{}", report);
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::vram::{shade, shades, Sprite, VramBuilder};
+
+// The left half of the tile is color 1, the right half is transparent.
+const HALF: [&str; 8] = ["11110000"; 8];
+// The color number is the row, with a dot of 3 at the left of the top row.
+const ARROW: [&str; 8] = [
+    "30000000", "11111111", "22222222", "33333333", "00000000", "11111111", "22222222", "33333333",
+];
+
+#[test]
+fn test_color_0_is_transparent() {
+    let gpu = VramBuilder::new()
+        .solid_tile(0, 2)
+        .tile(1, HALF)
+        .sprite(Sprite::new(0, 0, 1))
+        .render();
+    assert_eq!(shades(&gpu, 0, 0..10), "1111222222");
+    assert_eq!(shades(&gpu, 7, 0..10), "1111222222");
+    assert_eq!(shades(&gpu, 8, 0..10), "2222222222");
+}
+
+#[test]
+fn test_obj_behind_bg() {
+    // The BG tile 1 is color 0 on the left and color 3 on the right.
+    let gpu = VramBuilder::new()
+        .tile(1, ["00003333"; 8])
+        .solid_tile(2, 1)
+        .map(0, 0, 1)
+        .sprite(Sprite::new(0, 0, 2).behind_bg())
+        .sprite(Sprite::new(0, 8, 2))
+        .render();
+    // Only the BG color 0 lets the sprite through.
+    assert_eq!(shades(&gpu, 0, 0..8), "11113333");
+    // The BG tile 0 is color 0 everywhere, the flag makes no difference there.
+    assert_eq!(shades(&gpu, 0, 8..12), "0000");
+    assert_eq!(shades(&gpu, 8, 0..8), "11111111");
+}
+
+#[test]
+fn test_obj_behind_bg_still_hides_lower_sprites() {
+    let gpu = VramBuilder::new()
+        .tile(1, ["00003333"; 8])
+        .solid_tile(2, 1)
+        .solid_tile(3, 2)
+        .map(0, 0, 1)
+        // The first in the OAM is above at the same X, it's behind the BG.
+        .sprite(Sprite::new(0, 0, 2).behind_bg())
+        .sprite(Sprite::new(0, 0, 3))
+        .render();
+    // The hidden pixels of the upper sprite show the BG, not the lower sprite.
+    assert_eq!(shades(&gpu, 0, 0..8), "11113333");
+}
+
+#[test]
+fn test_sprite_over_sprite() {
+    let gpu = VramBuilder::new()
+        .tile(1, HALF)
+        .solid_tile(2, 2)
+        .solid_tile(3, 3)
+        // The smaller X is above, whatever the OAM order.
+        .sprite(Sprite::new(4, 0, 3))
+        .sprite(Sprite::new(0, 0, 2))
+        // At the same X the first in the OAM is above, its color 0 shows the one below.
+        .sprite(Sprite::new(0, 8, 1))
+        .sprite(Sprite::new(0, 8, 3))
+        .render();
+    assert_eq!(shades(&gpu, 0, 0..12), "222222223333");
+    assert_eq!(shades(&gpu, 8, 0..8), "11113333");
+}
+
+#[test]
+fn test_flips() {
+    let gpu = VramBuilder::new()
+        .tile(1, ARROW)
+        .sprite(Sprite::new(0, 0, 1))
+        .sprite(Sprite::new(8, 0, 1).xflip())
+        .sprite(Sprite::new(16, 0, 1).yflip())
+        .sprite(Sprite::new(24, 0, 1).xflip().yflip())
+        .render();
+    let column = |x| (0..8).map(|y| shade(&gpu, x, y)).collect::<Vec<_>>();
+    assert_eq!(shades(&gpu, 0, 0..32), "30000000000000033333333333333333");
+    assert_eq!(column(0), [3, 1, 2, 3, 0, 1, 2, 3]);
+    assert_eq!(column(16), [3, 2, 1, 0, 3, 2, 1, 3]);
+    assert_eq!(shades(&gpu, 7, 16..32), "3000000000000003");
+    assert_eq!(column(31), [3, 2, 1, 0, 3, 2, 1, 3]);
+}
+
+#[test]
+fn test_tall_sprites() {
+    let gpu = VramBuilder::new()
+        .solid_tile(2, 1)
+        .solid_tile(3, 2)
+        .tall_sprites()
+        // The lower bit of the tile number is ignored, the tile 2 is the top half.
+        .sprite(Sprite::new(0, 0, 3))
+        .sprite(Sprite::new(8, 0, 2).yflip())
+        .sprite(Sprite::new(16, 0, 2).obp1())
+        .palettes(0xe4, 0xe4, 0x1b)
+        .render();
+    let column = |x| {
+        (0..17)
+            .map(|y| shade(&gpu, x, y).to_string())
+            .collect::<String>()
+    };
+    assert_eq!(column(0), "11111111222222220");
+    assert_eq!(column(8), "22222222111111110");
+    // OBP1 reverses the shades.
+    assert_eq!(column(16), "22222222111111110");
+}
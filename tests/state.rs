@@ -0,0 +1,170 @@
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+
+use common::new_cpu;
+use NGC224::gameboy::cartridge::load_empty_cartridge;
+use NGC224::gameboy::cpu::IntReg;
+use NGC224::gameboy::gpu::GPU;
+use NGC224::gameboy::joypad::Joypad;
+use NGC224::gameboy::mmu::Mmunit;
+use NGC224::gameboy::sound::apu::{Apu, DEFAULT_SAMPLE_RATE};
+use NGC224::gameboy::state::{
+    migrate, Snapshot, StateError, CPU_SECTION, MMU_SECTION, STATE_VERSION, TIMER_SECTION,
+};
+use NGC224::gameboy::timer::Timer;
+use NGC224::gameboy::{IOHandler, Register, Term};
+
+/// Snapshots written by the released versions, never regenerate them.
+const FIXTURES: [(&str, u16); 8] = [
+    ("tests/fixtures/state/v1.ngcs", 1),
+    ("tests/fixtures/state/v2.ngcs", 2),
+    ("tests/fixtures/state/v3.ngcs", 3),
+    ("tests/fixtures/state/v4.ngcs", 4),
+    ("tests/fixtures/state/v5.ngcs", 5),
+    ("tests/fixtures/state/v6.ngcs", 6),
+    ("tests/fixtures/state/v7.ngcs", 7),
+    ("tests/fixtures/state/v8.ngcs", 8),
+];
+
+fn new_mmu() -> Mmunit {
+    new_mmu_with_term(Term::GB)
+}
+
+fn new_mmu_with_term(term: Term) -> Mmunit {
+    let intf = Arc::new(IntReg::new());
+    let gpu = GPU::with_term(intf.clone(), term);
+    Mmunit::new(
+        load_empty_cartridge(),
+        Apu::new(DEFAULT_SAMPLE_RATE),
+        gpu,
+        Joypad::new(intf.clone()),
+        Timer::new(intf.clone()),
+        intf,
+    )
+}
+
+#[test]
+fn test_load_fixtures() {
+    for (path, version) in FIXTURES.iter() {
+        let raw = Snapshot::from_bytes(&fs::read(path).unwrap()).unwrap();
+        assert_eq!(raw.get_version(), *version, "{}", path);
+
+        let snapshot = Snapshot::load(path).unwrap();
+        assert_eq!(snapshot.get_version(), STATE_VERSION);
+
+        let (mut cpu, _) = new_cpu();
+        let mut mmu = new_mmu();
+        snapshot.restore(CPU_SECTION, &mut cpu).unwrap();
+        snapshot.restore(MMU_SECTION, &mut mmu).unwrap();
+
+        assert_eq!(
+            cpu.get_reg_snapshot(),
+            Register::new()
+                .with_AF(0x01b0)
+                .with_BC(0x0013)
+                .with_DE(0x00d8)
+                .with_HL(0x014d)
+                .with_PC(0x1fd4)
+                .with_SP(0xdfff),
+            "{}",
+            path
+        );
+        assert!(cpu.is_ime_enabled());
+        assert!(cpu.is_ime_pending());
+        assert!(!cpu.is_halt());
+        assert_eq!(mmu.read_byte(0xffff), 0x05);
+        assert_eq!(mmu.read_byte(0xff0f), 0xe1);
+        assert_eq!(mmu.read_byte(0xc000), 0x42);
+        assert_eq!(mmu.read_byte(0xdfff), 0x24);
+        assert_eq!(mmu.read_byte(0xff80), 0x99);
+    }
+}
+
+#[test]
+fn test_save_is_stable() {
+    // Restore then save again must write the same content, otherwise the layout is changed
+    // without bumping `STATE_VERSION`.
+    let (path, _) = FIXTURES[FIXTURES.len() - 1];
+    let origin = fs::read(path).unwrap();
+    let snapshot = Snapshot::load(path).unwrap();
+
+    let (mut cpu, _) = new_cpu();
+    let mut mmu = new_mmu();
+    snapshot.restore(CPU_SECTION, &mut cpu).unwrap();
+    snapshot.restore(MMU_SECTION, &mut mmu).unwrap();
+
+    let mut saved = Snapshot::new();
+    saved.put(CPU_SECTION, &cpu);
+    saved.put(MMU_SECTION, &mmu);
+    assert_eq!(saved.to_bytes(), origin);
+}
+
+#[test]
+fn test_reject_newer_version() {
+    let mut bytes = fs::read(FIXTURES[0].0).unwrap();
+    bytes[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+    let mut snapshot = Snapshot::from_bytes(&bytes).unwrap();
+    assert!(matches!(
+        migrate(&mut snapshot),
+        Err(StateError::UnsupportedVersion(v)) if v == STATE_VERSION + 1
+    ));
+}
+
+#[test]
+fn test_migrate_the_timer_counter() {
+    let mut snapshot = Snapshot::from_bytes(&fs::read(FIXTURES[5].0).unwrap()).unwrap();
+    // DIV 0x12, TIMA 0x34, TMA 0x56, TAC 0x05, the DIV clock at 0x80 and the TIMA clock.
+    let old = [0x12, 0x34, 0x56, 0x05, 0x80, 0, 0, 0, 0x08, 0, 0, 0];
+    snapshot.set_section(TIMER_SECTION, old.to_vec());
+    migrate(&mut snapshot).unwrap();
+
+    let intf = Arc::new(IntReg::new());
+    let mut timer = Timer::new(intf);
+    snapshot.restore(TIMER_SECTION, &mut timer).unwrap();
+    assert_eq!(timer.get(0xff04), 0x12);
+    assert_eq!(timer.get(0xff05), 0x34);
+    assert_eq!(timer.get(0xff06), 0x56);
+    assert_eq!(timer.get(0xff07), 0xfd);
+    // The counter is 0x1280, DIV is incremented after 128 cycles.
+    timer.next(127);
+    assert_eq!(timer.get(0xff04), 0x12);
+    timer.next(1);
+    assert_eq!(timer.get(0xff04), 0x13);
+}
+
+#[test]
+fn test_missing_section() {
+    let (mut cpu, _) = new_cpu();
+    assert!(matches!(
+        Snapshot::new().restore(CPU_SECTION, &mut cpu),
+        Err(StateError::MissingSection(CPU_SECTION))
+    ));
+}
+
+#[test]
+fn test_speed_switch_is_saved() {
+    let mut mmu = new_mmu_with_term(Term::GBC);
+    assert_eq!(mmu.read_byte(0xff4d), 0x7e);
+    assert!(!mmu.switch_speed());
+    mmu.write_byte(0xff4d, 0x01);
+    assert_eq!(mmu.read_byte(0xff4d), 0x7f);
+    assert!(mmu.switch_speed());
+    assert_eq!(mmu.read_byte(0xff4d), 0xfe);
+    // The GPU and the APU run at the normal speed.
+    assert_eq!(mmu.next(8), 4);
+
+    let mut snapshot = Snapshot::new();
+    snapshot.put(MMU_SECTION, &mmu);
+    let mut restored = new_mmu_with_term(Term::GBC);
+    snapshot.restore(MMU_SECTION, &mut restored).unwrap();
+    assert!(restored.is_double_speed());
+
+    // KEY1 doesn't exist on the DMG.
+    let mut mmu = new_mmu();
+    mmu.write_byte(0xff4d, 0x01);
+    assert_eq!(mmu.read_byte(0xff4d), 0xff);
+    assert!(!mmu.switch_speed());
+}
+
@@ -0,0 +1,104 @@
+mod common;
+
+use common::new_cpu;
+use NGC224::gameboy::cpu::opcode_set::get_cb_clock;
+use NGC224::gameboy::Register;
+
+/// The M-cycles of each instruction, the same table as blargg's instr_timing ROM. The
+/// conditional instructions are not taken, 0 is not checked (STOP, HALT and the undefined).
+#[rustfmt::skip]
+const TIMES: [u32; 256] = [
+    1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1, // 0
+    0, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1, // 1
+    2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1, // 2
+    2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1, // 3
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 4
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 5
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 6
+    2, 2, 2, 2, 2, 2, 0, 2, 1, 1, 1, 1, 1, 1, 2, 1, // 7
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 8
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 9
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // A
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // B
+    2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4, // C
+    2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4, // D
+    3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4, // E
+    3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4, // F
+];
+
+/// The conditional instructions: the opcode, the flag register with the branch not taken and
+/// the M-cycles when it's taken.
+const BRANCHES: [(u8, u8, u32); 16] = [
+    (0x20, 0x80, 3),
+    (0x28, 0x00, 3),
+    (0x30, 0x10, 3),
+    (0x38, 0x00, 3),
+    (0xc0, 0x80, 5),
+    (0xc2, 0x80, 4),
+    (0xc4, 0x80, 6),
+    (0xc8, 0x00, 5),
+    (0xca, 0x00, 4),
+    (0xcc, 0x00, 6),
+    (0xd0, 0x10, 5),
+    (0xd2, 0x10, 4),
+    (0xd4, 0x10, 6),
+    (0xd8, 0x00, 5),
+    (0xda, 0x00, 4),
+    (0xdc, 0x00, 6),
+];
+
+/// Execute the instruction and returns the used cycles. The operands are 0x00 0xc0, the stack
+/// and HL are in the work ram.
+fn run(program: &[u8], f: u8) -> u32 {
+    let (mut cpu, bus) = new_cpu();
+    cpu.set_reg(
+        Register::new()
+            .with_AF(u16::from(f))
+            .with_HL(0xc000)
+            .with_PC(0x0100)
+            .with_SP(0xd000),
+    );
+    bus.lock().unwrap().load(0x0100, program);
+    cpu.next()
+}
+
+#[test]
+fn test_instruction_timing() {
+    let mut wrong = vec![];
+    for (opcode, m) in TIMES.iter().enumerate() {
+        if *m == 0 || opcode == 0xcb {
+            continue;
+        }
+        let f = BRANCHES
+            .iter()
+            .find(|b| usize::from(b.0) == opcode)
+            .map_or(0x00, |b| b.1);
+        let cycles = run(&[opcode as u8, 0x00, 0xc0], f);
+        if cycles != m * 4 {
+            wrong.push(format!("{:02x}: {} != {}", opcode, cycles, m * 4));
+        }
+    }
+    assert!(wrong.is_empty(), "{:?}", wrong);
+}
+
+#[test]
+fn test_branch_taken_timing() {
+    for (opcode, f, m) in BRANCHES.iter() {
+        // Flip the flag to take the branch.
+        let f = if *f == 0x00 { 0x90 } else { 0x00 };
+        assert_eq!(run(&[*opcode, 0x00, 0xc0], f), m * 4, "{:02x}", opcode);
+    }
+}
+
+#[test]
+fn test_cb_timing() {
+    for opcode in 0..=0xff {
+        let m = match (opcode & 0x07, opcode >> 6) {
+            (6, 1) => 3,
+            (6, _) => 4,
+            _ => 2,
+        };
+        assert_eq!(run(&[0xcb, opcode], 0x00), m * 4, "cb {:02x}", opcode);
+        assert_eq!(get_cb_clock(opcode), m * 4);
+    }
+}
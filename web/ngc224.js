@@ -0,0 +1,47 @@
+// Runs the core built by `cargo build --lib --release --no-default-features --target
+// wasm32-unknown-unknown`, copy target/wasm32-unknown-unknown/release/NGC224.wasm here.
+const W = 160;
+const H = 144;
+// The buttons of ngc224_set_button.
+const BUTTONS = {
+  ArrowRight: 0, ArrowLeft: 1, ArrowUp: 2, ArrowDown: 3,
+  KeyZ: 4, KeyX: 5, Space: 6, Enter: 7,
+};
+
+(async () => {
+  const { instance } = await WebAssembly.instantiateStreaming(fetch("NGC224.wasm"), {});
+  const ngc = instance.exports;
+  const emu = ngc.ngc224_create();
+  const ctx = document.getElementById("screen").getContext("2d");
+  let running = false;
+
+  document.getElementById("rom").addEventListener("change", async (e) => {
+    const rom = new Uint8Array(await e.target.files[0].arrayBuffer());
+    const p = ngc.ngc224_alloc(rom.length);
+    new Uint8Array(ngc.memory.buffer, p, rom.length).set(rom);
+    running = ngc.ngc224_load_rom(emu, p, rom.length) === 0;
+    ngc.ngc224_free(p, rom.length);
+  });
+
+  const press = (pressed) => (e) => {
+    if (e.code in BUTTONS) {
+      ngc.ngc224_set_button(emu, BUTTONS[e.code], pressed);
+      e.preventDefault();
+    }
+  };
+  window.addEventListener("keydown", press(true));
+  window.addEventListener("keyup", press(false));
+
+  // The display runs at about 60 Hz, close to the 59.73 frames per second of the hardware.
+  const frame = () => {
+    if (running) {
+      ngc.ngc224_step_frame(emu);
+      const p = ngc.ngc224_get_frame_rgba(emu);
+      // The memory may grow, so the view is taken every frame.
+      const pixels = new Uint8ClampedArray(ngc.memory.buffer, p, W * H * 4);
+      ctx.putImageData(new ImageData(pixels, W, H), 0, 0);
+    }
+    requestAnimationFrame(frame);
+  };
+  requestAnimationFrame(frame);
+})();